@@ -55,17 +55,22 @@
 //!     -- -m http.server 8080
 //! ```
 
-use std::env;
+use std::{env, path::Path, time::Duration};
 
 use anyhow::Result;
 use clap::Parser;
+use encoding_rs::Encoding;
 use microsandbox_cli::{McrunArgs, McrunSubcommand};
 use microsandbox_core::{
-    config::{EnvPair, PathPair, PortPair},
-    runtime::MicroVmMonitor,
-    vm::{MicroVm, Rootfs},
+    config::{EnvPair, PathPair, PortPair, START_SCRIPT_NAME},
+    management::sandbox,
+    runtime::{ConfigRestartPolicy, ForwardOutput, MicroVmMonitor, DEFAULT_CONFIG_WATCH_DEBOUNCE},
+    vm::{LinuxRlimit, MicroVm, OverlayfsLayers, Rootfs},
+};
+use microsandbox_utils::{
+    log::{JsonLinesSink, LogRotation},
+    runtime::Supervisor,
 };
-use microsandbox_utils::runtime::Supervisor;
 
 //--------------------------------------------------------------------------------------------------
 // Functions: main
@@ -88,6 +93,7 @@ async fn main() -> Result<()> {
             env,
             mapped_dir,
             port_map,
+            rlimit,
             scope,
             ip,
             subnet,
@@ -105,6 +111,7 @@ async fn main() -> Result<()> {
             tracing::debug!("env: {:#?}", env);
             tracing::debug!("mapped_dir: {:#?}", mapped_dir);
             tracing::debug!("port_map: {:#?}", port_map);
+            tracing::debug!("rlimit: {:#?}", rlimit);
             tracing::debug!("scope: {:#?}", scope);
             tracing::debug!("ip: {:#?}", ip);
             tracing::debug!("subnet: {:#?}", subnet);
@@ -113,7 +120,7 @@ async fn main() -> Result<()> {
             // Check that only one of native_rootfs or overlayfs_layer is provided
             let rootfs = match (native_rootfs, overlayfs_layer.is_empty()) {
                 (Some(path), true) => Rootfs::Native(path),
-                (None, false) => Rootfs::Overlayfs(overlayfs_layer),
+                (None, false) => Rootfs::Overlayfs(OverlayfsLayers::from_stack(overlayfs_layer)?),
                 (Some(_), false) => {
                     anyhow::bail!("Cannot specify both native_rootfs and overlayfs_rootfs")
                 }
@@ -139,6 +146,10 @@ async fn main() -> Result<()> {
             // Parse environment variables
             let env: Vec<EnvPair> = env.iter().map(|s| s.parse()).collect::<Result<_, _>>()?;
 
+            // Parse resource limits
+            let rlimit: Vec<LinuxRlimit> =
+                rlimit.iter().map(|s| s.parse()).collect::<Result<_, _>>()?;
+
             // Create and configure MicroVM
             let mut builder = MicroVm::builder().rootfs(rootfs).exec_path(exec_path);
 
@@ -172,6 +183,11 @@ async fn main() -> Result<()> {
                 builder = builder.port_map(port_map);
             }
 
+            // Set resource limits if provided
+            if !rlimit.is_empty() {
+                builder = builder.rlimits(rlimit);
+            }
+
             // Set scope if provided
             if let Some(scope) = scope {
                 builder = builder.scope(scope.parse()?);
@@ -209,8 +225,23 @@ async fn main() -> Result<()> {
             sandbox_name,
             config_file,
             config_last_modified,
+            config_hash,
             log_level,
             forward_output,
+            forward_stdout,
+            forward_stderr,
+            output_encoding,
+            log_format,
+            log_sink,
+            log_max_size,
+            log_max_files,
+            log_compress,
+            stop_on_broken_pipe,
+            db_optional,
+            metrics_interval,
+            watch_config,
+            config_restart_policy,
+            oom_score_adj,
             native_rootfs,
             overlayfs_layer,
             num_vcpus,
@@ -220,6 +251,7 @@ async fn main() -> Result<()> {
             env,
             mapped_dir,
             port_map,
+            rlimit,
             scope,
             ip,
             subnet,
@@ -237,7 +269,9 @@ async fn main() -> Result<()> {
             // Get rootfs
             let rootfs = match (&native_rootfs, &overlayfs_layer.is_empty()) {
                 (Some(path), true) => Rootfs::Native(path.clone()),
-                (None, false) => Rootfs::Overlayfs(overlayfs_layer.clone()),
+                (None, false) => {
+                    Rootfs::Overlayfs(OverlayfsLayers::from_stack(overlayfs_layer.clone())?)
+                }
                 (Some(_), false) => {
                     anyhow::bail!("Cannot specify both native_rootfs and overlayfs_rootfs")
                 }
@@ -246,19 +280,104 @@ async fn main() -> Result<()> {
                 }
             };
 
+            // Pick where the output goes, the rotating log file unless told otherwise
+            let log_sink = match log_sink.as_deref() {
+                None | Some("file") => None,
+                Some("stdout-json") => Some(JsonLinesSink::stdout_factory()),
+                Some(other) => {
+                    anyhow::bail!("Unknown log sink: {} (expected file or stdout-json)", other)
+                }
+            };
+            let log_to_file = log_sink.is_none();
+
             // Create microvm monitor
-            let process_monitor = MicroVmMonitor::new(
+            let mut process_monitor = MicroVmMonitor::new(
                 supervisor_pid,
-                sandbox_db_path,
-                sandbox_name,
-                config_file,
+                &sandbox_db_path,
+                sandbox_name.clone(),
+                config_file.clone(),
                 config_last_modified,
                 log_dir.clone(),
                 rootfs.clone(),
-                forward_output,
+                ForwardOutput {
+                    stdout: forward_stdout.unwrap_or(forward_output),
+                    stderr: forward_stderr.unwrap_or(forward_output),
+                },
             )
             .await?;
 
+            // Keep the sandbox running without its database if asked to
+            process_monitor = process_monitor.with_db_optional(db_optional);
+
+            // Send output to the chosen sink instead of the log file
+            if let Some(factory) = log_sink {
+                process_monitor = process_monitor.with_log_sink(factory);
+            }
+
+            // Fail before starting anything if the log can't be written
+            if log_to_file {
+                let log_path = process_monitor.preview_log_path()?;
+                tracing::debug!("microvm log path: {}", log_path.display());
+            }
+
+            // Set what happens when the output consumer goes away
+            process_monitor = process_monitor.with_stop_on_broken_pipe(stop_on_broken_pipe);
+
+            // Set OOM score adjustment if provided
+            if let Some(oom_score_adj) = oom_score_adj {
+                process_monitor = process_monitor.with_oom_score_adj(oom_score_adj);
+            }
+
+            // Sample the MicroVM's resource use if asked to
+            if let Some(interval) = metrics_interval {
+                process_monitor =
+                    process_monitor.with_metrics_interval(Duration::from_secs(interval));
+            }
+
+            // Set config hash if provided
+            if let Some(config_hash) = config_hash {
+                process_monitor = process_monitor.with_config_hash(config_hash);
+            }
+
+            // Set output encoding if provided
+            if let Some(label) = output_encoding {
+                let encoding = Encoding::for_label(label.as_bytes())
+                    .ok_or_else(|| anyhow::anyhow!("Unknown output encoding: {}", label))?;
+                process_monitor = process_monitor.with_output_encoding(encoding);
+            }
+
+            // Watch the config file if asked to, restarting the sandbox when it changes
+            let project_dir = sandbox_db_path
+                .parent()
+                .and_then(Path::parent)
+                .map(Path::to_path_buf);
+            if watch_config {
+                let policy = match config_restart_policy {
+                    Some(policy) => policy.parse()?,
+                    None => ConfigRestartPolicy::default(),
+                };
+                let project_dir = project_dir.as_deref().ok_or_else(|| {
+                    anyhow::anyhow!("Cannot find the project of {}", sandbox_db_path.display())
+                })?;
+                process_monitor = process_monitor.with_config_watch(
+                    project_dir.join(&config_file),
+                    policy,
+                    DEFAULT_CONFIG_WATCH_DEBOUNCE,
+                );
+            }
+            let restart_request = process_monitor.restart_request();
+
+            // Set log format if provided
+            if let Some(format) = log_format {
+                process_monitor = process_monitor.with_log_format(format.parse()?);
+            }
+
+            // Set log rotation
+            let log_rotation = LogRotation::new(log_max_size)
+                .with_max_files(log_max_files)
+                .with_compression(log_compress);
+            process_monitor = process_monitor.with_log_rotation(log_rotation);
+
             // Compose child arguments
             let mut child_args = vec!["microvm".to_string(), format!("--exec-path={}", exec_path)];
 
@@ -310,6 +429,13 @@ async fn main() -> Result<()> {
                 }
             }
 
+            // Set resource limits if provided
+            if !rlimit.is_empty() {
+                for rlimit in rlimit {
+                    child_args.push(format!("--rlimit={}", rlimit));
+                }
+            }
+
             // Set scope if provided
             if let Some(scope) = scope {
                 child_args.push(format!("--scope={}", scope));
@@ -352,6 +478,26 @@ async fn main() -> Result<()> {
                 Supervisor::new(child_exe, child_args, child_envs, log_dir, process_monitor);
 
             supervisor.start().await?;
+
+            // Start the sandbox again once this supervisor has shut down, resolving the changed
+            // config the same way `msb up` does
+            if restart_request.is_requested() {
+                tracing::info!(
+                    "restarting sandbox {} with the changed config",
+                    sandbox_name
+                );
+                sandbox::run(
+                    &sandbox_name,
+                    Some(START_SCRIPT_NAME),
+                    project_dir.as_deref(),
+                    Some(&config_file),
+                    vec![],
+                    true,
+                    None,
+                    true,
+                )
+                .await?;
+            }
         }
     }
 
@@ -2,6 +2,7 @@ use std::path::PathBuf;
 
 use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
+use microsandbox_utils::DEFAULT_LOG_MAX_SIZE;
 
 use crate::styles;
 
@@ -19,6 +20,8 @@ pub struct McrunArgs {
 }
 
 /// Available subcommands for managing microvms
+// Parsed once at startup, so the size of the supervisor's arguments doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 pub enum McrunSubcommand {
     /// Run as microvm
@@ -63,6 +66,10 @@ pub enum McrunSubcommand {
         #[arg(long)]
         port_map: Vec<String>,
 
+        /// Resource limits (RESOURCE=SOFT:HARD format)
+        #[arg(long)]
+        rlimit: Vec<String>,
+
         /// Network communication scope
         #[arg(long)]
         scope: Option<String>,
@@ -102,6 +109,10 @@ pub enum McrunSubcommand {
         #[arg(long)]
         config_last_modified: DateTime<Utc>,
 
+        /// SHA-256 hash of the sandbox config file
+        #[arg(long)]
+        config_hash: Option<String>,
+
         /// Log level
         #[arg(long)]
         log_level: Option<u8>,
@@ -110,6 +121,69 @@ pub enum McrunSubcommand {
         #[arg(long, default_value = "true")]
         forward_output: bool,
 
+        /// Whether to forward the sandbox's stdout, or its TTY output, to stdout; defaults to
+        /// `--forward-output`
+        #[arg(long)]
+        forward_stdout: Option<bool>,
+
+        /// Whether to forward the sandbox's stderr to stderr; defaults to `--forward-output`
+        #[arg(long)]
+        forward_stderr: Option<bool>,
+
+        /// Encoding of the sandbox output (e.g. `latin1`), transcoded to UTF-8 when forwarded
+        #[arg(long)]
+        output_encoding: Option<String>,
+
+        /// Format of the sandbox output log: `raw` (default) or `jsonl`, which records the
+        /// stream, read time and sequence number of each chunk
+        #[arg(long)]
+        log_format: Option<String>,
+
+        /// Where the sandbox output is written: `file` (default), the rotating log file, or
+        /// `stdout-json`, JSON lines tagged with the sandbox name on the supervisor's stdout
+        #[arg(long)]
+        log_sink: Option<String>,
+
+        /// Size in bytes at which the sandbox output log is rotated
+        #[arg(long, default_value_t = DEFAULT_LOG_MAX_SIZE)]
+        log_max_size: u64,
+
+        /// Number of rotated sandbox output log segments to keep
+        #[arg(long, default_value = "1")]
+        log_max_files: usize,
+
+        /// Whether to gzip-compress rotated sandbox output log segments
+        #[arg(long, default_value = "false")]
+        log_compress: bool,
+
+        /// Whether to stop the sandbox when the consumer of its forwarded output goes away
+        #[arg(long, default_value = "false")]
+        stop_on_broken_pipe: bool,
+
+        /// Whether to keep running with metadata held in memory when the sandbox database
+        /// can't be written, instead of failing
+        #[arg(long, default_value = "false")]
+        db_optional: bool,
+
+        /// Interval in seconds at which the sandbox's CPU and memory use is recorded in the
+        /// sandbox database; not recorded if unset
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        metrics_interval: Option<u64>,
+
+        /// Whether to restart the sandbox with the new config when its config file changes
+        #[arg(long, default_value = "false")]
+        watch_config: bool,
+
+        /// Which config changes restart a sandbox run with `--watch-config`: `never`, which only
+        /// logs them, `on-change` (default), for changes to the sandbox's own definition, or
+        /// `always`, for any change
+        #[arg(long)]
+        config_restart_policy: Option<String>,
+
+        /// OOM score adjustment of the sandbox process (-1000 to 1000)
+        #[arg(long, allow_hyphen_values = true, value_parser = clap::value_parser!(i32).range(-1000..=1000))]
+        oom_score_adj: Option<i32>,
+
         // Sandbox specific arguments
         /// Native root filesystem path
         #[arg(long)]
@@ -147,6 +221,10 @@ pub enum McrunSubcommand {
         #[arg(long)]
         port_map: Vec<String>,
 
+        /// Resource limits (RESOURCE=SOFT:HARD format)
+        #[arg(long)]
+        rlimit: Vec<String>,
+
         /// Network communication scope
         #[arg(long)]
         scope: Option<String>,
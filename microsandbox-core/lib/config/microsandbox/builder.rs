@@ -6,6 +6,7 @@ use typed_path::Utf8UnixPathBuf;
 
 use crate::{
     config::{EnvPair, PathPair, PortPair, ReferenceOrPath},
+    vm::LinuxRlimit,
     MicrosandboxResult,
 };
 
@@ -52,6 +53,9 @@ pub struct MicrosandboxBuilder {
 /// - `imports`: The files to import
 /// - `exports`: The files to export
 /// - `scope`: The network scope for the sandbox
+/// - `ulimits`: The resource limits to apply in the guest
+/// - `hostname`: The hostname of the guest
+/// - `oom_score_adj`: The OOM score adjustment of the MicroVM process
 /// - `proxy`: The proxy to use
 pub struct SandboxBuilder<I> {
     version: Option<Version>,
@@ -71,6 +75,9 @@ pub struct SandboxBuilder<I> {
     imports: HashMap<String, Utf8UnixPathBuf>,
     exports: HashMap<String, Utf8UnixPathBuf>,
     scope: NetworkScope,
+    ulimits: Vec<LinuxRlimit>,
+    hostname: Option<String>,
+    oom_score_adj: Option<i32>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -153,6 +160,9 @@ impl<I> SandboxBuilder<I> {
             imports: self.imports,
             exports: self.exports,
             scope: self.scope,
+            ulimits: self.ulimits,
+            hostname: self.hostname,
+            oom_score_adj: self.oom_score_adj,
         }
     }
 
@@ -248,6 +258,24 @@ impl<I> SandboxBuilder<I> {
         self.scope = scope;
         self
     }
+
+    /// Sets the resource limits to apply in the guest
+    pub fn ulimits(mut self, ulimits: impl IntoIterator<Item = LinuxRlimit>) -> SandboxBuilder<I> {
+        self.ulimits = ulimits.into_iter().collect();
+        self
+    }
+
+    /// Sets the hostname of the guest
+    pub fn hostname(mut self, hostname: impl Into<String>) -> SandboxBuilder<I> {
+        self.hostname = Some(hostname.into());
+        self
+    }
+
+    /// Sets the OOM score adjustment of the MicroVM process
+    pub fn oom_score_adj(mut self, oom_score_adj: i32) -> SandboxBuilder<I> {
+        self.oom_score_adj = Some(oom_score_adj);
+        self
+    }
 }
 
 impl SandboxBuilder<ReferenceOrPath> {
@@ -270,6 +298,9 @@ impl SandboxBuilder<ReferenceOrPath> {
             imports: self.imports,
             exports: self.exports,
             scope: self.scope,
+            ulimits: self.ulimits,
+            hostname: self.hostname,
+            oom_score_adj: self.oom_score_adj,
         }
     }
 }
@@ -298,6 +329,9 @@ impl Default for SandboxBuilder<()> {
             imports: HashMap::new(),
             exports: HashMap::new(),
             scope: NetworkScope::default(),
+            ulimits: Vec::new(),
+            hostname: None,
+            oom_score_adj: None,
         }
     }
 }
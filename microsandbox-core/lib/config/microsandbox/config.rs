@@ -14,6 +14,7 @@ use typed_path::Utf8UnixPathBuf;
 
 use crate::{
     config::{EnvPair, PathPair, PortPair, ReferenceOrPath},
+    vm::LinuxRlimit,
     MicrosandboxError, MicrosandboxResult,
 };
 
@@ -304,6 +305,19 @@ pub struct Sandbox {
     /// The network scope for the sandbox.
     #[serde(default)]
     pub(crate) scope: NetworkScope,
+
+    /// The resource limits to apply in the guest, e.g. `RLIMIT_NOFILE=1024:4096`.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub(crate) ulimits: Vec<LinuxRlimit>,
+
+    /// The hostname of the guest.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub(crate) hostname: Option<String>,
+
+    /// The OOM score adjustment of the MicroVM process, from `-1000` (never kill) to `1000`
+    /// (kill first).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub(crate) oom_score_adj: Option<i32>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -360,6 +374,15 @@ impl Sandbox {
             return Err(MicrosandboxError::MissingStartOrExecOrShell);
         }
 
+        if let Some(oom_score_adj) = self.oom_score_adj {
+            if !(-1000..=1000).contains(&oom_score_adj) {
+                return Err(MicrosandboxError::InvalidArgument(format!(
+                    "oom_score_adj must be between -1000 and 1000, got {}",
+                    oom_score_adj
+                )));
+            }
+        }
+
         Ok(())
     }
 }
@@ -795,4 +818,33 @@ mod tests {
         "#;
         assert!(serde_yaml::from_str::<Microsandbox>(yaml).is_err());
     }
+
+    #[test]
+    fn test_microsandbox_config_oom_score_adj_range() {
+        let sandbox = |oom_score_adj| {
+            Sandbox::builder()
+                .image(ReferenceOrPath::Reference("alpine:latest".parse().unwrap()))
+                .shell("/bin/sh")
+                .oom_score_adj(oom_score_adj)
+                .build()
+        };
+
+        assert!(sandbox(-1000).validate().is_ok());
+        assert!(sandbox(1000).validate().is_ok());
+        assert!(sandbox(-1001).validate().is_err());
+        assert!(sandbox(1001).validate().is_err());
+
+        let yaml = r#"
+            sandboxes:
+              test:
+                image: "alpine:latest"
+                shell: "/bin/sh"
+                oom_score_adj: -500
+        "#;
+        let config: Microsandbox = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.sandboxes.get("test").unwrap().oom_score_adj,
+            Some(-500)
+        );
+    }
 }
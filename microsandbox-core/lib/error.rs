@@ -264,6 +264,22 @@ pub enum MicrosandboxError {
     #[error("cannot find sandbox: '{0}' in '{1}'")]
     SandboxNotFoundInConfig(String, PathBuf),
 
+    /// An error that occurred when an operation requires a running sandbox
+    #[error("sandbox is not running: '{0}'")]
+    SandboxNotRunning(String),
+
+    /// An error that occurred when an operation requires a stopped sandbox
+    #[error("sandbox is running: '{0}'")]
+    SandboxRunning(String),
+
+    /// An error that occurred when a snapshot was not found
+    #[error("snapshot '{0}' not found for sandbox '{1}'")]
+    SnapshotNotFound(String, String),
+
+    /// An error that occurred when a snapshot with the same name already exists
+    #[error("snapshot '{0}' already exists for sandbox '{1}'")]
+    SnapshotExists(String, String),
+
     /// An error that occurs when an invalid log level is used.
     #[error("invalid log level: {0}")]
     InvalidLogLevel(u8),
@@ -299,6 +315,10 @@ pub enum MicrosandboxError {
     /// An error that occurred when a command was not found.
     #[error("command not found: {0}")]
     CommandNotFound(String),
+
+    /// An error that occurred when a log can't be written at its path.
+    #[error("cannot write log at {0}: {1}")]
+    LogPathNotWritable(PathBuf, String),
 }
 
 /// An error that occurred when an invalid MicroVm configuration was used.
@@ -308,6 +328,18 @@ pub enum InvalidMicroVMConfigError {
     #[error("root path does not exist: {0}")]
     RootPathDoesNotExist(String),
 
+    /// An overlayfs rootfs has no lower layers.
+    #[error("overlayfs rootfs has no lower layers")]
+    NoLowerLayers,
+
+    /// The upper layer of an overlayfs rootfs is not writable.
+    #[error("overlayfs upper layer is not writable: {0}")]
+    UpperLayerNotWritable(String),
+
+    /// The upper layer of an overlayfs rootfs is also one of its lower layers.
+    #[error("overlayfs upper layer is also a lower layer: {0}")]
+    UpperLayerIsLowerLayer(String),
+
     /// A host path that should be mounted does not exist.
     #[error("host path does not exist: {0}")]
     HostPathDoesNotExist(String),
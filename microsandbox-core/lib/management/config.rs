@@ -3,7 +3,7 @@
 //! This module provides structures and utilities for modifying Microsandbox
 //! configuration.
 
-use microsandbox_utils::{DEFAULT_SHELL, MICROSANDBOX_CONFIG_FILENAME};
+use microsandbox_utils::{env, DEFAULT_SHELL, MICROSANDBOX_CONFIG_FILENAME, OCI_DB_FILENAME};
 use nondestructive::yaml;
 use sqlx::{Pool, Sqlite};
 use std::{
@@ -498,6 +498,27 @@ pub async fn resolve_config_paths(
     ))
 }
 
+/// Gets the environment variables set by a pulled image, as `KEY=value` strings.
+///
+/// Returns an empty list if the image hasn't been pulled or sets no environment.
+///
+/// ## Arguments
+///
+/// * `reference` - OCI image reference to look up
+pub async fn get_image_env(reference: &Reference) -> MicrosandboxResult<Vec<String>> {
+    let db_path = env::get_microsandbox_home_path().join(OCI_DB_FILENAME);
+    let oci_db = db::get_or_create_pool(&db_path, &db::OCI_DB_MIGRATOR).await?;
+
+    let Some(config) = db::get_image_config(&oci_db, &reference.to_string()).await? else {
+        return Ok(Vec::new());
+    };
+
+    match config.config_env_json {
+        Some(env_json) => Ok(serde_json::from_str(&env_json)?),
+        None => Ok(Vec::new()),
+    }
+}
+
 /// Applies defaults from an OCI image configuration to a sandbox configuration.
 ///
 /// This function enhances the sandbox configuration with defaults from the OCI image
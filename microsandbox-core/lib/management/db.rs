@@ -5,16 +5,24 @@
 //! migrations, and operations for storing and retrieving container images, layers,
 //! and sandbox configurations.
 
-use std::path::Path;
+use std::{future::Future, path::Path, str::FromStr, time::Duration};
 
 use chrono::{DateTime, NaiveDateTime, Utc};
+use microsandbox_utils::ExitStatus;
 use oci_spec::image::{ImageConfiguration, ImageIndex, ImageManifest, MediaType, Platform};
-use sqlx::{migrate::Migrator, sqlite::SqlitePoolOptions, Pool, Row, Sqlite};
+use sqlx::{
+    migrate::Migrator,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
+    Pool, Row, Sqlite,
+};
 use tokio::fs;
 
 use crate::{
-    models::{Config, Image, Index, Layer, Manifest, Sandbox},
-    runtime::SANDBOX_STATUS_RUNNING,
+    models::{
+        Config, Image, Index, Layer, Manifest, Sandbox, SandboxEvent, SandboxEventFilter,
+        SandboxEventKind, SandboxSnapshot,
+    },
+    runtime::{SANDBOX_STATUS_PAUSED, SANDBOX_STATUS_RUNNING},
     MicrosandboxResult,
 };
 
@@ -28,6 +36,99 @@ pub static SANDBOX_DB_MIGRATOR: Migrator = sqlx::migrate!("lib/migrations/sandbo
 /// Migrator for the OCI database
 pub static OCI_DB_MIGRATOR: Migrator = sqlx::migrate!("lib/migrations/oci");
 
+/// How many times a write that failed because the database was busy or locked is retried
+const DB_BUSY_RETRIES: u32 = 5;
+
+/// Backoff before the first retry of a busy write, doubled for each retry after it
+const DB_BUSY_BACKOFF: Duration = Duration::from_millis(20);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Settings of the connections in a database connection pool.
+///
+/// The defaults suit many processes sharing one database file, as the monitors of a project's
+/// sandboxes do: the write-ahead log lets readers and a writer work at the same time, and with
+/// `synchronous=NORMAL` a commit doesn't wait for the disk, at the risk of losing the last
+/// commits, but not of corrupting the database, if the machine loses power.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use microsandbox_core::management::db::{self, DbPoolOptions};
+/// use sqlx::sqlite::SqliteSynchronous;
+///
+/// # async fn example() -> microsandbox_core::MicrosandboxResult<()> {
+/// let options = DbPoolOptions::default()
+///     .with_synchronous(SqliteSynchronous::Full)
+///     .with_busy_timeout(Duration::from_secs(10));
+/// let pool = db::get_pool_with_options("sandbox.db", &options).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DbPoolOptions {
+    /// The journal mode of the database, write-ahead logging by default
+    pub journal_mode: SqliteJournalMode,
+
+    /// How hard SQLite works to make commits durable, `NORMAL` by default
+    pub synchronous: SqliteSynchronous,
+
+    /// How long a connection waits for another connection's lock on the database before
+    /// failing with `SQLITE_BUSY`, 5 seconds by default
+    pub busy_timeout: Duration,
+
+    /// The most connections the pool opens, 5 by default
+    pub max_connections: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl DbPoolOptions {
+    /// Sets the journal mode of the database
+    pub fn with_journal_mode(mut self, journal_mode: SqliteJournalMode) -> Self {
+        self.journal_mode = journal_mode;
+        self
+    }
+
+    /// Sets how hard SQLite works to make commits durable
+    pub fn with_synchronous(mut self, synchronous: SqliteSynchronous) -> Self {
+        self.synchronous = synchronous;
+        self
+    }
+
+    /// Sets how long a connection waits for another connection's lock on the database
+    pub fn with_busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.busy_timeout = busy_timeout;
+        self
+    }
+
+    /// Sets the most connections the pool opens
+    pub fn with_max_connections(mut self, max_connections: u32) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for DbPoolOptions {
+    fn default() -> Self {
+        Self {
+            journal_mode: SqliteJournalMode::Wal,
+            synchronous: SqliteSynchronous::Normal,
+            busy_timeout: Duration::from_secs(5),
+            max_connections: 5,
+        }
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -55,10 +156,7 @@ pub async fn initialize(
     }
 
     // Create database connection pool
-    let pool = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect(&format!("sqlite://{}?mode=rwc", db_path.display()))
-        .await?;
+    let pool = get_pool_with_options(db_path, &DbPoolOptions::default()).await?;
 
     // Run migrations
     migrator.run(&pool).await?;
@@ -68,14 +166,32 @@ pub async fn initialize(
 
 /// Creates and returns a connection pool for SQLite database operations.
 ///
-/// This function initializes a new SQLite connection pool with specified configuration parameters
-/// for managing database connections efficiently. The pool is configured with a maximum of 5
-/// concurrent connections.
+/// The pool is configured with the [default options](DbPoolOptions::default): write-ahead
+/// logging, `synchronous=NORMAL`, a maximum of 5 concurrent connections, each waiting up to 5
+/// seconds for locks held by other connections.
 pub async fn get_pool(db_path: impl AsRef<Path>) -> MicrosandboxResult<Pool<Sqlite>> {
+    get_pool_with_options(db_path, &DbPoolOptions::default()).await
+}
+
+/// Creates and returns a connection pool for SQLite database operations, configured with
+/// `options`.
+///
+/// The database file is created if it doesn't exist, but no migrations are run.
+pub async fn get_pool_with_options(
+    db_path: impl AsRef<Path>,
+    options: &DbPoolOptions,
+) -> MicrosandboxResult<Pool<Sqlite>> {
     let db_path = db_path.as_ref();
+    let connect_options =
+        SqliteConnectOptions::from_str(&format!("sqlite://{}", db_path.display()))?
+            .create_if_missing(true)
+            .journal_mode(options.journal_mode)
+            .synchronous(options.synchronous)
+            .busy_timeout(options.busy_timeout);
+
     let pool = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect(&format!("sqlite://{}?mode=rwc", db_path.display()))
+        .max_connections(options.max_connections)
+        .connect_with(connect_options)
         .await?;
 
     Ok(pool)
@@ -111,46 +227,58 @@ pub(crate) async fn save_or_update_sandbox(
     name: &str,
     config_file: &str,
     config_last_modified: &DateTime<Utc>,
+    config_hash: Option<&str>,
     status: &str,
     supervisor_pid: u32,
     microvm_pid: u32,
     rootfs_paths: &str,
+    log_path: Option<&str>,
 ) -> MicrosandboxResult<i64> {
     let sandbox = Sandbox {
         id: 0,
         name: name.to_string(),
         config_file: config_file.to_string(),
         config_last_modified: config_last_modified.clone(),
+        config_hash: config_hash.map(str::to_string),
         status: status.to_string(),
         supervisor_pid,
         microvm_pid,
         rootfs_paths: rootfs_paths.to_string(),
+        log_path: log_path.map(str::to_string),
         created_at: Utc::now(),
         modified_at: Utc::now(),
+        exit_status: None,
     };
 
     // Try to update first
-    let update_result = sqlx::query(
-        r#"
+    let update_result = retry_if_busy(|| {
+        sqlx::query(
+            r#"
         UPDATE sandboxes
         SET config_last_modified = ?,
+            config_hash = ?,
             status = ?,
             supervisor_pid = ?,
             microvm_pid = ?,
             rootfs_paths = ?,
+            log_path = ?,
+            exit_status = NULL,
             modified_at = CURRENT_TIMESTAMP
         WHERE name = ? AND config_file = ?
         RETURNING id
         "#,
-    )
-    .bind(&sandbox.config_last_modified.to_rfc3339())
-    .bind(&sandbox.status)
-    .bind(&sandbox.supervisor_pid)
-    .bind(&sandbox.microvm_pid)
-    .bind(&sandbox.rootfs_paths)
-    .bind(&sandbox.name)
-    .bind(&sandbox.config_file)
-    .fetch_optional(pool)
+        )
+        .bind(sandbox.config_last_modified.to_rfc3339())
+        .bind(&sandbox.config_hash)
+        .bind(&sandbox.status)
+        .bind(&sandbox.supervisor_pid)
+        .bind(&sandbox.microvm_pid)
+        .bind(&sandbox.rootfs_paths)
+        .bind(&sandbox.log_path)
+        .bind(&sandbox.name)
+        .bind(&sandbox.config_file)
+        .fetch_optional(pool)
+    })
     .await?;
 
     if let Some(record) = update_result {
@@ -159,24 +287,28 @@ pub(crate) async fn save_or_update_sandbox(
     } else {
         // If no record was updated, insert a new one
         tracing::debug!("creating new sandbox record");
-        let record = sqlx::query(
-            r#"
+        let record = retry_if_busy(|| {
+            sqlx::query(
+                r#"
             INSERT INTO sandboxes (
-                name, config_file, config_last_modified,
-                status, supervisor_pid, microvm_pid, rootfs_paths
+                name, config_file, config_last_modified, config_hash,
+                status, supervisor_pid, microvm_pid, rootfs_paths, log_path
             )
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING id
             "#,
-        )
-        .bind(sandbox.name)
-        .bind(sandbox.config_file)
-        .bind(sandbox.config_last_modified.to_rfc3339())
-        .bind(sandbox.status)
-        .bind(sandbox.supervisor_pid)
-        .bind(sandbox.microvm_pid)
-        .bind(sandbox.rootfs_paths)
-        .fetch_one(pool)
+            )
+            .bind(&sandbox.name)
+            .bind(&sandbox.config_file)
+            .bind(sandbox.config_last_modified.to_rfc3339())
+            .bind(&sandbox.config_hash)
+            .bind(&sandbox.status)
+            .bind(sandbox.supervisor_pid)
+            .bind(sandbox.microvm_pid)
+            .bind(&sandbox.rootfs_paths)
+            .bind(&sandbox.log_path)
+            .fetch_one(pool)
+        })
         .await?;
 
         Ok(record.get::<i64, _>("id"))
@@ -190,9 +322,9 @@ pub(crate) async fn get_sandbox(
 ) -> MicrosandboxResult<Option<Sandbox>> {
     let record = sqlx::query(
         r#"
-        SELECT id, name, config_file, config_last_modified, status,
-               supervisor_pid, microvm_pid, rootfs_paths,
-               created_at, modified_at
+        SELECT id, name, config_file, config_last_modified, config_hash, status,
+               supervisor_pid, microvm_pid, rootfs_paths, log_path,
+               created_at, modified_at, exit_status
         FROM sandboxes
         WHERE name = ? AND config_file = ?
         "#,
@@ -210,12 +342,15 @@ pub(crate) async fn get_sandbox(
             .get::<String, _>("config_last_modified")
             .parse::<DateTime<Utc>>()
             .unwrap(),
+        config_hash: row.get("config_hash"),
         status: row.get("status"),
         supervisor_pid: row.get("supervisor_pid"),
         microvm_pid: row.get("microvm_pid"),
         rootfs_paths: row.get("rootfs_paths"),
+        log_path: row.get("log_path"),
         created_at: parse_sqlite_datetime(&row.get::<String, _>("created_at")),
         modified_at: parse_sqlite_datetime(&row.get::<String, _>("modified_at")),
+        exit_status: parse_exit_status(row.get("exit_status")),
     }))
 }
 
@@ -226,40 +361,97 @@ pub(crate) async fn update_sandbox_status(
     config_file: &str,
     status: &str,
 ) -> MicrosandboxResult<()> {
-    sqlx::query(
-        r#"
+    retry_if_busy(|| {
+        sqlx::query(
+            r#"
         UPDATE sandboxes
         SET status = ?,
             modified_at = CURRENT_TIMESTAMP
         WHERE name = ? AND config_file = ?
         "#,
+        )
+        .bind(status)
+        .bind(name)
+        .bind(config_file)
+        .execute(pool)
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Records how a sandbox's microVM exited, counting it if the OOM killer killed it
+pub(crate) async fn update_sandbox_exit_status(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    config_file: &str,
+    exit_status: &ExitStatus,
+) -> MicrosandboxResult<()> {
+    let exit_status_json = serde_json::to_string(exit_status)?;
+    retry_if_busy(|| {
+        sqlx::query(
+            r#"
+        UPDATE sandboxes
+        SET exit_status = ?,
+            oom_kills = oom_kills + ?,
+            modified_at = CURRENT_TIMESTAMP
+        WHERE name = ? AND config_file = ?
+        "#,
+        )
+        .bind(&exit_status_json)
+        .bind(exit_status.oom as i64)
+        .bind(name)
+        .bind(config_file)
+        .execute(pool)
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Gets how many times a sandbox's microVM has been killed by the OOM killer, over all runs
+///
+/// Returns 0 for a sandbox that isn't in the database.
+pub(crate) async fn get_sandbox_oom_kills(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    config_file: &str,
+) -> MicrosandboxResult<u32> {
+    let record = sqlx::query(
+        r#"
+        SELECT oom_kills
+        FROM sandboxes
+        WHERE name = ? AND config_file = ?
+        "#,
     )
-    .bind(status)
     .bind(name)
     .bind(config_file)
-    .execute(pool)
+    .fetch_optional(pool)
     .await?;
 
-    Ok(())
+    Ok(record.map_or(0, |row| row.get::<i64, _>("oom_kills") as u32))
 }
 
-/// Gets all sandboxes associated with a specific config file
+/// Gets all live sandboxes associated with a specific config file
+///
+/// Paused sandboxes are included since their processes are still alive.
 pub(crate) async fn get_running_config_sandboxes(
     pool: &Pool<Sqlite>,
     config_file: &str,
 ) -> MicrosandboxResult<Vec<Sandbox>> {
     let records = sqlx::query(
         r#"
-        SELECT id, name, config_file, config_last_modified, status,
-               supervisor_pid, microvm_pid, rootfs_paths,
-               created_at, modified_at
+        SELECT id, name, config_file, config_last_modified, config_hash, status,
+               supervisor_pid, microvm_pid, rootfs_paths, log_path,
+               created_at, modified_at, exit_status
         FROM sandboxes
-        WHERE config_file = ? AND status = ?
+        WHERE config_file = ? AND status IN (?, ?)
         ORDER BY created_at DESC
         "#,
     )
     .bind(config_file)
     .bind(SANDBOX_STATUS_RUNNING)
+    .bind(SANDBOX_STATUS_PAUSED)
     .fetch_all(pool)
     .await?;
 
@@ -273,12 +465,52 @@ pub(crate) async fn get_running_config_sandboxes(
                 .get::<String, _>("config_last_modified")
                 .parse::<DateTime<Utc>>()
                 .unwrap(),
+            config_hash: row.get("config_hash"),
             status: row.get("status"),
             supervisor_pid: row.get("supervisor_pid"),
             microvm_pid: row.get("microvm_pid"),
             rootfs_paths: row.get("rootfs_paths"),
+            log_path: row.get("log_path"),
             created_at: parse_sqlite_datetime(&row.get::<String, _>("created_at")),
             modified_at: parse_sqlite_datetime(&row.get::<String, _>("modified_at")),
+            exit_status: parse_exit_status(row.get("exit_status")),
+        })
+        .collect())
+}
+
+/// Gets all sandboxes in the database, whatever their status
+pub(crate) async fn get_all_sandboxes(pool: &Pool<Sqlite>) -> MicrosandboxResult<Vec<Sandbox>> {
+    let records = sqlx::query(
+        r#"
+        SELECT id, name, config_file, config_last_modified, config_hash, status,
+               supervisor_pid, microvm_pid, rootfs_paths, log_path,
+               created_at, modified_at, exit_status
+        FROM sandboxes
+        ORDER BY config_file, name
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|row| Sandbox {
+            id: row.get("id"),
+            name: row.get("name"),
+            config_file: row.get("config_file"),
+            config_last_modified: row
+                .get::<String, _>("config_last_modified")
+                .parse::<DateTime<Utc>>()
+                .unwrap(),
+            config_hash: row.get("config_hash"),
+            status: row.get("status"),
+            supervisor_pid: row.get("supervisor_pid"),
+            microvm_pid: row.get("microvm_pid"),
+            rootfs_paths: row.get("rootfs_paths"),
+            log_path: row.get("log_path"),
+            created_at: parse_sqlite_datetime(&row.get::<String, _>("created_at")),
+            modified_at: parse_sqlite_datetime(&row.get::<String, _>("modified_at")),
+            exit_status: parse_exit_status(row.get("exit_status")),
         })
         .collect())
 }
@@ -303,6 +535,245 @@ pub(crate) async fn delete_sandbox(
     Ok(())
 }
 
+/// Records a sample of a sandbox's microVM resource usage.
+///
+/// The sample is keyed by the microVM's PID, so samples from different runs of the same
+/// sandbox can be told apart. Nothing is recorded if the sandbox isn't in the database.
+pub async fn insert_sandbox_metric(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    config_file: &str,
+    microvm_pid: u32,
+    cpu_usage_percent: f32,
+    memory_usage_bytes: u64,
+) -> MicrosandboxResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO sandbox_metrics (sandbox_id, microvm_pid, cpu_usage_percent, memory_usage_bytes)
+        SELECT id, ?, ?, ?
+        FROM sandboxes
+        WHERE name = ? AND config_file = ?
+        "#,
+    )
+    .bind(microvm_pid)
+    .bind(cpu_usage_percent)
+    .bind(memory_usage_bytes as i64)
+    .bind(name)
+    .bind(config_file)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Sandbox Events
+//--------------------------------------------------------------------------------------------------
+
+/// Records a transition in the lifecycle of a sandbox.
+pub async fn record_event(
+    pool: &Pool<Sqlite>,
+    sandbox_name: &str,
+    config_file: &str,
+    kind: SandboxEventKind,
+    detail: Option<&str>,
+) -> MicrosandboxResult<()> {
+    retry_if_busy(|| {
+        sqlx::query(
+            r#"
+        INSERT INTO sandbox_events (sandbox_name, config_file, event_kind, detail)
+        VALUES (?, ?, ?, ?)
+        "#,
+        )
+        .bind(sandbox_name)
+        .bind(config_file)
+        .bind(kind.as_str())
+        .bind(detail)
+        .execute(pool)
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Gets the sandbox lifecycle events matching `filter`, oldest first.
+///
+/// With a limit, the most recent matching events are returned.
+pub async fn query_events(
+    pool: &Pool<Sqlite>,
+    filter: &SandboxEventFilter,
+) -> MicrosandboxResult<Vec<SandboxEvent>> {
+    let records = sqlx::query(
+        r#"
+        SELECT id, timestamp, sandbox_name, config_file, event_kind, detail
+        FROM sandbox_events
+        WHERE (?1 IS NULL OR sandbox_name = ?1)
+          AND (?2 IS NULL OR config_file = ?2)
+          AND (?3 IS NULL OR event_kind = ?3)
+          AND (?4 IS NULL OR timestamp >= ?4)
+        ORDER BY id DESC
+        LIMIT ?5
+        "#,
+    )
+    .bind(filter.sandbox_name.as_deref())
+    .bind(filter.config_file.as_deref())
+    .bind(filter.kind.map(|kind| kind.as_str()))
+    .bind(
+        filter
+            .since
+            .map(|since| since.format("%Y-%m-%d %H:%M:%S").to_string()),
+    )
+    .bind(filter.limit.map_or(-1, i64::from))
+    .fetch_all(pool)
+    .await?;
+
+    // Events of kinds a newer version recorded are skipped
+    let mut events: Vec<SandboxEvent> = records
+        .into_iter()
+        .filter_map(|row| {
+            Some(SandboxEvent {
+                id: row.get("id"),
+                timestamp: parse_sqlite_datetime(&row.get::<String, _>("timestamp")),
+                sandbox_name: row.get("sandbox_name"),
+                config_file: row.get("config_file"),
+                kind: SandboxEventKind::parse(&row.get::<String, _>("event_kind"))?,
+                detail: row.get("detail"),
+            })
+        })
+        .collect();
+    events.reverse();
+
+    Ok(events)
+}
+
+/// Deletes all but the `max_events` most recent sandbox lifecycle events.
+///
+/// Returns how many events were deleted.
+pub async fn prune_events(pool: &Pool<Sqlite>, max_events: u32) -> MicrosandboxResult<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM sandbox_events
+        WHERE id NOT IN (
+            SELECT id FROM sandbox_events ORDER BY id DESC LIMIT ?
+        )
+        "#,
+    )
+    .bind(max_events)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Sandbox Snapshots
+//--------------------------------------------------------------------------------------------------
+
+/// Records a snapshot of a sandbox and returns it.
+pub(crate) async fn save_snapshot(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    sandbox_name: &str,
+    config_file: &str,
+    size_bytes: u64,
+) -> MicrosandboxResult<SandboxSnapshot> {
+    // Insert in a transaction. An `INSERT ... RETURNING` that fails on a taken name can leave
+    // its statement unreset, which keeps the pooled connection's WAL read snapshot open, so
+    // later reads through that connection miss newer writes. Dropping the failed transaction
+    // rolls back and ends the snapshot
+    let mut tx = pool.begin().await?;
+    let record = sqlx::query(
+        r#"
+        INSERT INTO sandbox_snapshots (name, sandbox_name, config_file, size_bytes)
+        VALUES (?, ?, ?, ?)
+        RETURNING id, created_at
+        "#,
+    )
+    .bind(name)
+    .bind(sandbox_name)
+    .bind(config_file)
+    .bind(size_bytes as i64)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(SandboxSnapshot {
+        id: record.get("id"),
+        name: name.to_string(),
+        sandbox_name: sandbox_name.to_string(),
+        config_file: config_file.to_string(),
+        size_bytes,
+        created_at: parse_sqlite_datetime(&record.get::<String, _>("created_at")),
+    })
+}
+
+/// Gets a snapshot of a sandbox by name.
+pub(crate) async fn get_snapshot(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    sandbox_name: &str,
+    config_file: &str,
+) -> MicrosandboxResult<Option<SandboxSnapshot>> {
+    let record = sqlx::query(
+        r#"
+        SELECT id, name, sandbox_name, config_file, size_bytes, created_at
+        FROM sandbox_snapshots
+        WHERE name = ? AND sandbox_name = ? AND config_file = ?
+        "#,
+    )
+    .bind(name)
+    .bind(sandbox_name)
+    .bind(config_file)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|row| snapshot_from_row(&row)))
+}
+
+/// Gets the snapshots of a sandbox, oldest first.
+pub(crate) async fn get_snapshots(
+    pool: &Pool<Sqlite>,
+    sandbox_name: &str,
+    config_file: &str,
+) -> MicrosandboxResult<Vec<SandboxSnapshot>> {
+    let records = sqlx::query(
+        r#"
+        SELECT id, name, sandbox_name, config_file, size_bytes, created_at
+        FROM sandbox_snapshots
+        WHERE sandbox_name = ? AND config_file = ?
+        ORDER BY id
+        "#,
+    )
+    .bind(sandbox_name)
+    .bind(config_file)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records.iter().map(snapshot_from_row).collect())
+}
+
+/// Deletes one snapshot of a sandbox, or all of them if `name` is `None`.
+pub(crate) async fn delete_snapshots(
+    pool: &Pool<Sqlite>,
+    name: Option<&str>,
+    sandbox_name: &str,
+    config_file: &str,
+) -> MicrosandboxResult<()> {
+    sqlx::query(
+        r#"
+        DELETE FROM sandbox_snapshots
+        WHERE (?1 IS NULL OR name = ?1) AND sandbox_name = ?2 AND config_file = ?3
+        "#,
+    )
+    .bind(name)
+    .bind(sandbox_name)
+    .bind(config_file)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: Images
 //--------------------------------------------------------------------------------------------------
@@ -894,6 +1365,7 @@ pub(crate) async fn get_image_layer_digests(
 mod tests {
     use super::*;
     use sqlx::Row;
+    use std::time::Duration;
     use tempfile::tempdir;
 
     #[tokio::test]
@@ -930,6 +1402,108 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_insert_sandbox_metric() -> MicrosandboxResult<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("test_sandbox.db");
+        initialize(&db_path, &SANDBOX_DB_MIGRATOR).await?;
+        let pool = get_pool(&db_path).await?;
+
+        let id = save_or_update_sandbox(
+            &pool,
+            "app",
+            "Sandboxfile",
+            &Utc::now(),
+            None,
+            SANDBOX_STATUS_RUNNING,
+            1,
+            42,
+            "native:/rootfs",
+            Some("/logs/app.log"),
+        )
+        .await?;
+
+        let sandbox = get_sandbox(&pool, "app", "Sandboxfile").await?.unwrap();
+        assert_eq!(sandbox.log_path.as_deref(), Some("/logs/app.log"));
+
+        insert_sandbox_metric(&pool, "app", "Sandboxfile", 42, 12.5, 64 << 20).await?;
+        insert_sandbox_metric(&pool, "missing", "Sandboxfile", 43, 1.0, 1).await?;
+
+        let rows = sqlx::query(
+            "SELECT sandbox_id, microvm_pid, cpu_usage_percent, memory_usage_bytes FROM sandbox_metrics",
+        )
+        .fetch_all(&pool)
+        .await?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get::<i64, _>("sandbox_id"), id);
+        assert_eq!(rows[0].get::<i64, _>("microvm_pid"), 42);
+        assert_eq!(rows[0].get::<f64, _>("cpu_usage_percent"), 12.5);
+        assert_eq!(rows[0].get::<i64, _>("memory_usage_bytes"), 64 << 20);
+
+        // Metrics go with their sandbox
+        delete_sandbox(&pool, "app", "Sandboxfile").await?;
+        let remaining: i64 = sqlx::query("SELECT COUNT(*) AS count FROM sandbox_metrics")
+            .fetch_one(&pool)
+            .await?
+            .get("count");
+        assert_eq!(remaining, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_exit_status_counts_oom_kills() -> MicrosandboxResult<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("test_sandbox.db");
+        initialize(&db_path, &SANDBOX_DB_MIGRATOR).await?;
+        let pool = get_pool(&db_path).await?;
+        assert_eq!(get_sandbox_oom_kills(&pool, "app", "Sandboxfile").await?, 0);
+
+        let config_last_modified = Utc::now();
+        let start = |microvm_pid| {
+            save_or_update_sandbox(
+                &pool,
+                "app",
+                "Sandboxfile",
+                &config_last_modified,
+                None,
+                SANDBOX_STATUS_RUNNING,
+                1,
+                microvm_pid,
+                "native:/rootfs",
+                None,
+            )
+        };
+        let exit_status = ExitStatus {
+            code: None,
+            signal: Some(9),
+            oom: true,
+            duration: Duration::from_secs(5),
+            peak_rss_bytes: Some(512 << 20),
+            cpu_time: Some(Duration::from_millis(1500)),
+        };
+
+        // The count carries over restarts, while the exit status is of the last run
+        start(42).await?;
+        update_sandbox_exit_status(&pool, "app", "Sandboxfile", &exit_status).await?;
+        start(43).await?;
+        update_sandbox_exit_status(&pool, "app", "Sandboxfile", &exit_status).await?;
+        start(44).await?;
+        let clean_exit = ExitStatus {
+            code: Some(0),
+            signal: None,
+            oom: false,
+            ..exit_status
+        };
+        update_sandbox_exit_status(&pool, "app", "Sandboxfile", &clean_exit).await?;
+
+        assert_eq!(get_sandbox_oom_kills(&pool, "app", "Sandboxfile").await?, 2);
+        let sandbox = get_sandbox(&pool, "app", "Sandboxfile").await?.unwrap();
+        assert_eq!(sandbox.exit_status, Some(clean_exit));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_init_oci_db() -> MicrosandboxResult<()> {
         // Create temporary directory
@@ -975,6 +1549,231 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_sandbox_events() -> MicrosandboxResult<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("test_sandbox.db");
+        initialize(&db_path, &SANDBOX_DB_MIGRATOR).await?;
+        let pool = get_pool(&db_path).await?;
+
+        record_event(&pool, "app", "Sandboxfile", SandboxEventKind::Start, None).await?;
+        record_event(
+            &pool,
+            "app",
+            "Sandboxfile",
+            SandboxEventKind::Crash,
+            Some("exited with code 1"),
+        )
+        .await?;
+        record_event(&pool, "app", "Sandboxfile", SandboxEventKind::Restart, None).await?;
+        record_event(&pool, "db", "Sandboxfile", SandboxEventKind::Start, None).await?;
+
+        // Oldest first, filtered by sandbox
+        let filter = SandboxEventFilter {
+            sandbox_name: Some("app".to_string()),
+            ..Default::default()
+        };
+        let kinds: Vec<_> = query_events(&pool, &filter)
+            .await?
+            .into_iter()
+            .map(|event| event.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                SandboxEventKind::Start,
+                SandboxEventKind::Crash,
+                SandboxEventKind::Restart
+            ]
+        );
+
+        let crashes = query_events(
+            &pool,
+            &SandboxEventFilter {
+                kind: Some(SandboxEventKind::Crash),
+                ..Default::default()
+            },
+        )
+        .await?;
+        assert_eq!(crashes.len(), 1);
+        assert_eq!(crashes[0].detail.as_deref(), Some("exited with code 1"));
+
+        // A limit keeps the most recent events
+        let latest = query_events(
+            &pool,
+            &SandboxEventFilter {
+                limit: Some(2),
+                ..Default::default()
+            },
+        )
+        .await?;
+        assert_eq!(
+            latest
+                .iter()
+                .map(|event| event.sandbox_name.as_str())
+                .collect::<Vec<_>>(),
+            ["app", "db"]
+        );
+
+        let future = SandboxEventFilter {
+            since: Some(Utc::now() + chrono::Duration::hours(1)),
+            ..Default::default()
+        };
+        assert!(query_events(&pool, &future).await?.is_empty());
+
+        // Pruning keeps the newest events
+        assert_eq!(prune_events(&pool, 1).await?, 3);
+        let remaining = query_events(&pool, &SandboxEventFilter::default()).await?;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].sandbox_name, "db");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_snapshots() -> MicrosandboxResult<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("test_sandbox.db");
+        initialize(&db_path, &SANDBOX_DB_MIGRATOR).await?;
+        let pool = get_pool(&db_path).await?;
+
+        let saved = save_snapshot(&pool, "prepared", "app", "Sandboxfile", 1024).await?;
+        save_snapshot(&pool, "later", "app", "Sandboxfile", 2048).await?;
+        save_snapshot(&pool, "prepared", "db", "Sandboxfile", 512).await?;
+
+        // Names are unique per sandbox
+        assert!(save_snapshot(&pool, "prepared", "app", "Sandboxfile", 1)
+            .await
+            .is_err());
+
+        let fetched = get_snapshot(&pool, "prepared", "app", "Sandboxfile").await?;
+        assert_eq!(fetched, Some(saved));
+        assert!(get_snapshot(&pool, "prepared", "app", "other.yaml")
+            .await?
+            .is_none());
+
+        let names: Vec<_> = get_snapshots(&pool, "app", "Sandboxfile")
+            .await?
+            .into_iter()
+            .map(|snapshot| snapshot.name)
+            .collect();
+        assert_eq!(names, vec!["prepared", "later"]);
+
+        delete_snapshots(&pool, Some("prepared"), "app", "Sandboxfile").await?;
+        assert_eq!(get_snapshots(&pool, "app", "Sandboxfile").await?.len(), 1);
+        delete_snapshots(&pool, None, "app", "Sandboxfile").await?;
+        assert!(get_snapshots(&pool, "app", "Sandboxfile").await?.is_empty());
+        assert_eq!(get_snapshots(&pool, "db", "Sandboxfile").await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_writers_do_not_deadlock() -> MicrosandboxResult<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("test_sandbox.db");
+        initialize(&db_path, &SANDBOX_DB_MIGRATOR).await?;
+
+        let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+            .fetch_one(&get_pool(&db_path).await?)
+            .await?;
+        assert_eq!(journal_mode, "wal");
+
+        // Separate pools stand in for the monitors of two sandboxes
+        let writers = ["app", "db"].map(|name| {
+            let db_path = db_path.clone();
+            tokio::spawn(async move {
+                let pool = get_pool(&db_path).await?;
+                for i in 0..50 {
+                    let status = if i % 2 == 0 { "RUNNING" } else { "STOPPED" };
+                    save_or_update_sandbox(
+                        &pool,
+                        name,
+                        "Sandboxfile",
+                        &Utc::now(),
+                        None,
+                        status,
+                        1,
+                        2,
+                        "",
+                        None,
+                    )
+                    .await?;
+                    record_event(&pool, name, "Sandboxfile", SandboxEventKind::Start, None).await?;
+                }
+                MicrosandboxResult::Ok(())
+            })
+        });
+
+        for writer in writers {
+            tokio::time::timeout(Duration::from_secs(30), writer)
+                .await
+                .expect("writer deadlocked")
+                .unwrap()?;
+        }
+
+        let pool = get_pool(&db_path).await?;
+        let events = query_events(&pool, &SandboxEventFilter::default()).await?;
+        assert_eq!(events.len(), 100);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_writes_retry_while_database_is_locked() -> MicrosandboxResult<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("test_sandbox.db");
+        initialize(&db_path, &SANDBOX_DB_MIGRATOR).await?;
+        let pool = get_pool(&db_path).await?;
+        save_or_update_sandbox(
+            &pool,
+            "app",
+            "Sandboxfile",
+            &Utc::now(),
+            None,
+            "RUNNING",
+            1,
+            2,
+            "",
+            None,
+        )
+        .await?;
+
+        // A pool that fails at once on a lock, like one whose busy timeout has run out
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", db_path.display()))?
+            .busy_timeout(Duration::ZERO);
+        let impatient = SqlitePoolOptions::new().connect_with(options).await?;
+
+        // Hold the write lock from another connection
+        let mut holder = pool.acquire().await?;
+        sqlx::query("BEGIN IMMEDIATE").execute(&mut *holder).await?;
+
+        let error = sqlx::query("UPDATE sandboxes SET status = 'STOPPED'")
+            .execute(&impatient)
+            .await
+            .unwrap_err();
+        assert!(is_busy_error(&error));
+
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            sqlx::query("COMMIT").execute(&mut *holder).await
+        });
+        update_sandbox_status(&impatient, "app", "Sandboxfile", "STOPPED").await?;
+        release.await.unwrap()?;
+
+        let sandbox = get_sandbox(&pool, "app", "Sandboxfile").await?.unwrap();
+        assert_eq!(sandbox.status, "STOPPED");
+
+        // Other errors aren't retried
+        let error = sqlx::query("UPDATE missing SET x = 1")
+            .execute(&impatient)
+            .await
+            .unwrap_err();
+        assert!(!is_busy_error(&error));
+
+        Ok(())
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -988,8 +1787,67 @@ fn parse_sqlite_datetime(s: &str) -> DateTime<Utc> {
     DateTime::from_naive_utc_and_offset(naive_dt, Utc)
 }
 
+/// Builds a snapshot from a row of the `sandbox_snapshots` table.
+fn snapshot_from_row(row: &sqlx::sqlite::SqliteRow) -> SandboxSnapshot {
+    SandboxSnapshot {
+        id: row.get("id"),
+        name: row.get("name"),
+        sandbox_name: row.get("sandbox_name"),
+        config_file: row.get("config_file"),
+        size_bytes: row.get::<i64, _>("size_bytes") as u64,
+        created_at: parse_sqlite_datetime(&row.get::<String, _>("created_at")),
+    }
+}
+
+/// Parses the JSON exit status stored for a sandbox, ignoring values that can't be read.
+fn parse_exit_status(value: Option<String>) -> Option<ExitStatus> {
+    serde_json::from_str(&null_to_none(value)?).ok()
+}
+
 /// Sometimes the json columns in the database can have literal "null" values.
 /// This function converts those to None.
 fn null_to_none(value: Option<String>) -> Option<String> {
     value.filter(|v| v != "null")
 }
+
+/// Runs a database operation, retrying it with a growing backoff while it fails because
+/// another connection holds a lock on the database.
+///
+/// Monitors of many sandboxes write to the same database file, so under load a write can
+/// still find the database locked after waiting out the busy timeout. Any other error is
+/// returned right away, as is the busy error once the retries run out.
+async fn retry_if_busy<T, F, Fut>(mut operation: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut backoff = DB_BUSY_BACKOFF;
+    let mut retries = 0;
+    loop {
+        match operation().await {
+            Err(e) if is_busy_error(&e) && retries < DB_BUSY_RETRIES => {
+                retries += 1;
+                tracing::debug!(retries, ?backoff, "database is busy, retrying: {}", e);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether an error is SQLite reporting the database as busy or locked, i.e. `SQLITE_BUSY`
+/// or `SQLITE_LOCKED`, including their extended result codes.
+fn is_busy_error(error: &sqlx::Error) -> bool {
+    const SQLITE_BUSY: i32 = 5;
+    const SQLITE_LOCKED: i32 = 6;
+
+    let sqlx::Error::Database(e) = error else {
+        return false;
+    };
+
+    // Extended result codes keep the primary code in their low byte
+    e.code()
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED))
+}
@@ -0,0 +1,237 @@
+//! Filesystem diffing for Microsandbox.
+//!
+//! A sandbox's writable overlay layer already captures every change its code makes on disk. This
+//! module records a manifest of that layer at a point in time and compares manifests to report
+//! which guest paths were added, modified, or deleted in between.
+
+use std::{collections::BTreeMap, path::Path, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::{
+    management::rootfs::{OPAQUE_WHITEOUT_MARKER, WHITEOUT_PREFIX},
+    MicrosandboxResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The kind of change made to a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsChangeKind {
+    /// The path was created
+    Added,
+
+    /// The file's contents or metadata changed
+    Modified,
+
+    /// The path was removed
+    Deleted,
+}
+
+/// A change to a path inside the sandbox
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FsChange {
+    /// The path as seen by the guest, e.g. `/tmp/out.txt`
+    pub path: String,
+
+    /// The kind of change
+    pub kind: FsChangeKind,
+}
+
+/// A point-in-time listing of a writable layer, keyed by guest path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsManifest {
+    entries: BTreeMap<String, FsEntry>,
+}
+
+/// A single entry in a manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FsEntry {
+    /// A directory present in the layer
+    Dir,
+
+    /// A file (or symlink) present in the layer
+    File { size: u64, modified: SystemTime },
+
+    /// A whiteout hiding the path from the lower layers
+    Deleted,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsManifest {
+    /// Records the entries of a writable layer.
+    ///
+    /// Whiteout files are recorded as deletions of the paths they hide, and opaque directory
+    /// markers are skipped.
+    pub async fn scan(layer_path: impl AsRef<Path>) -> MicrosandboxResult<Self> {
+        let layer_path = layer_path.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || Self::scan_blocking(&layer_path)).await?
+    }
+
+    fn scan_blocking(layer_path: &Path) -> MicrosandboxResult<Self> {
+        let mut entries = BTreeMap::new();
+
+        for entry in WalkDir::new(layer_path).min_depth(1).follow_links(false) {
+            let entry = entry?;
+            let relative = entry
+                .path()
+                .strip_prefix(layer_path)
+                .unwrap_or(entry.path());
+            let file_name = entry.file_name().to_string_lossy();
+
+            if file_name == OPAQUE_WHITEOUT_MARKER {
+                continue;
+            }
+
+            if let Some(hidden) = file_name.strip_prefix(WHITEOUT_PREFIX) {
+                let path = relative.with_file_name(hidden);
+                entries.insert(guest_path(&path), FsEntry::Deleted);
+                continue;
+            }
+
+            let fs_entry = if entry.file_type().is_dir() {
+                FsEntry::Dir
+            } else {
+                let metadata = entry.metadata()?;
+                FsEntry::File {
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                }
+            };
+            entries.insert(guest_path(relative), fs_entry);
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Lists the changes between this earlier manifest and a later one, ordered by path.
+    ///
+    /// Files from the image that are changed for the first time show up as added, since the
+    /// writable layer only gets a copy of them on first write.
+    pub fn diff(&self, later: &FsManifest) -> Vec<FsChange> {
+        let mut changes = Vec::new();
+
+        for (path, after) in &later.entries {
+            let kind = match (self.entries.get(path), after) {
+                (Some(FsEntry::Deleted), FsEntry::Deleted) => continue,
+                (_, FsEntry::Deleted) => FsChangeKind::Deleted,
+                (None | Some(FsEntry::Deleted), _) => FsChangeKind::Added,
+                (Some(FsEntry::Dir), FsEntry::Dir) => continue,
+                (Some(before), after) if before == after => continue,
+                (Some(_), _) => FsChangeKind::Modified,
+            };
+            changes.push(FsChange {
+                path: path.clone(),
+                kind,
+            });
+        }
+
+        // Paths created in the writable layer and removed again leave no whiteout behind
+        for (path, before) in &self.entries {
+            if *before != FsEntry::Deleted && !later.entries.contains_key(path) {
+                changes.push(FsChange {
+                    path: path.clone(),
+                    kind: FsChangeKind::Deleted,
+                });
+            }
+        }
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        changes
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Converts a path relative to the layer root into an absolute guest path
+fn guest_path(relative: &Path) -> String {
+    format!("/{}", relative.to_string_lossy())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+    use tokio::fs;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scan_records_files_and_whiteouts() -> anyhow::Result<()> {
+        let layer = TempDir::new()?;
+        fs::create_dir_all(layer.path().join("etc")).await?;
+        fs::write(layer.path().join("etc/app.conf"), "x=1").await?;
+        fs::write(layer.path().join("etc/.wh.old.conf"), "").await?;
+        fs::write(layer.path().join("etc").join(OPAQUE_WHITEOUT_MARKER), "").await?;
+
+        let manifest = FsManifest::scan(layer.path()).await?;
+
+        assert_eq!(manifest.entries.get("/etc"), Some(&FsEntry::Dir));
+        assert!(matches!(
+            manifest.entries.get("/etc/app.conf"),
+            Some(FsEntry::File { size: 3, .. })
+        ));
+        assert_eq!(
+            manifest.entries.get("/etc/old.conf"),
+            Some(&FsEntry::Deleted)
+        );
+        assert_eq!(manifest.entries.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_reports_added_modified_and_deleted() {
+        let t0 = SystemTime::UNIX_EPOCH;
+        let t1 = t0 + Duration::from_secs(1);
+        let file = |size, modified| FsEntry::File { size, modified };
+
+        let before = FsManifest {
+            entries: BTreeMap::from([
+                ("/tmp".to_string(), FsEntry::Dir),
+                ("/tmp/kept".to_string(), file(1, t0)),
+                ("/tmp/edited".to_string(), file(1, t0)),
+                ("/tmp/scratch".to_string(), file(1, t0)),
+                ("/etc/gone".to_string(), FsEntry::Deleted),
+            ]),
+        };
+        let after = FsManifest {
+            entries: BTreeMap::from([
+                ("/tmp".to_string(), FsEntry::Dir),
+                ("/tmp/kept".to_string(), file(1, t0)),
+                ("/tmp/edited".to_string(), file(2, t1)),
+                ("/tmp/new".to_string(), file(1, t1)),
+                ("/etc/gone".to_string(), FsEntry::Deleted),
+                ("/etc/hosts".to_string(), FsEntry::Deleted),
+            ]),
+        };
+
+        let change = |path: &str, kind| FsChange {
+            path: path.to_string(),
+            kind,
+        };
+        assert_eq!(
+            before.diff(&after),
+            vec![
+                change("/etc/hosts", FsChangeKind::Deleted),
+                change("/tmp/edited", FsChangeKind::Modified),
+                change("/tmp/new", FsChangeKind::Added),
+                change("/tmp/scratch", FsChangeKind::Deleted),
+            ]
+        );
+    }
+}
@@ -276,6 +276,26 @@ pub async fn pull_group_from_sandboxes_registry(_group: &Reference) -> Microsand
     ));
 }
 
+/// Checks whether an image has been pulled, with all its layers extracted.
+///
+/// ## Arguments
+///
+/// * `image` - The reference to the image to check
+///
+/// ## Errors
+///
+/// Returns an error if the image database can't be opened.
+pub async fn is_pulled(image: &Reference) -> MicrosandboxResult<bool> {
+    let microsandbox_home_path = env::get_microsandbox_home_path();
+    let db_path = microsandbox_home_path.join(OCI_DB_FILENAME);
+    if !db_path.exists() {
+        return Ok(false);
+    }
+
+    let pool = db::get_or_create_pool(&db_path, &OCI_DB_MIGRATOR).await?;
+    check_image_layers(&pool, image, microsandbox_home_path.join(LAYERS_SUBDIR)).await
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
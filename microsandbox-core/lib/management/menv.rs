@@ -11,7 +11,7 @@ use crate::{MicrosandboxError, MicrosandboxResult};
 use microsandbox_utils::term;
 use microsandbox_utils::{
     DEFAULT_CONFIG, LOG_SUBDIR, MICROSANDBOX_CONFIG_FILENAME, MICROSANDBOX_ENV_DIR, PATCH_SUBDIR,
-    RW_SUBDIR, SANDBOX_DB_FILENAME,
+    RW_SUBDIR, SANDBOX_DB_FILENAME, SNAPSHOTS_SUBDIR,
 };
 use std::path::{Path, PathBuf};
 use tokio::{fs, io::AsyncWriteExt};
@@ -215,6 +215,7 @@ pub async fn clean(
     // Clean up sandbox-specific directories
     let rw_path = menv_path.join(RW_SUBDIR).join(&namespaced_name);
     let patch_path = menv_path.join(PATCH_SUBDIR).join(&namespaced_name);
+    let snapshots_path = menv_path.join(SNAPSHOTS_SUBDIR).join(&namespaced_name);
 
     // Remove sandbox directories if they exist
     if rw_path.exists() {
@@ -230,6 +231,14 @@ pub async fn clean(
         );
     }
 
+    if snapshots_path.exists() {
+        fs::remove_dir_all(&snapshots_path).await?;
+        tracing::info!(
+            "Removed sandbox snapshots directory at {}",
+            snapshots_path.display()
+        );
+    }
+
     // Remove log file if it exists
     let log_file = menv_path
         .join(LOG_SUBDIR)
@@ -246,6 +255,7 @@ pub async fn clean(
     if db_path.exists() {
         let pool = db::get_or_create_pool(&db_path, &db::SANDBOX_DB_MIGRATOR).await?;
         db::delete_sandbox(&pool, sandbox_name, config_file).await?;
+        db::delete_snapshots(&pool, None, sandbox_name, config_file).await?;
         tracing::info!("Removed sandbox {} from database", sandbox_name);
     }
 
@@ -0,0 +1,478 @@
+//! Metrics export for Microsandbox.
+//!
+//! This module renders sandbox counts and per-sandbox resource metrics in the Prometheus text
+//! exposition format, so operators can scrape them from a `/metrics` endpoint without a separate
+//! exporter.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::Path,
+};
+
+use chrono::Utc;
+use microsandbox_utils::{MICROSANDBOX_ENV_DIR, SANDBOX_DB_FILENAME};
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    management::orchestra::{self, SandboxStatus},
+    models::{SandboxEventFilter, SandboxEventKind},
+    runtime::{
+        SANDBOX_STATUS_CRASHED, SANDBOX_STATUS_PAUSED, SANDBOX_STATUS_RUNNING,
+        SANDBOX_STATUS_STOPPED,
+    },
+    MicrosandboxResult,
+};
+
+use super::db;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The statuses counted by `microsandbox_sandboxes`, reported even when no sandbox has them
+const COUNTED_STATUSES: [&str; 4] = [
+    SANDBOX_STATUS_RUNNING,
+    SANDBOX_STATUS_PAUSED,
+    SANDBOX_STATUS_STOPPED,
+    SANDBOX_STATUS_CRASHED,
+];
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A point-in-time sample of a sandbox's metrics
+#[derive(Debug, Clone)]
+struct SandboxSample {
+    /// The namespace the sandbox belongs to
+    namespace: String,
+
+    /// The name of the sandbox
+    sandbox: String,
+
+    /// The config file that defines the sandbox
+    config_file: String,
+
+    /// The status recorded in the database, e.g. `RUNNING`
+    status: String,
+
+    /// CPU usage percentage
+    cpu_usage: Option<f32>,
+
+    /// Memory usage in bytes
+    memory_usage: Option<u64>,
+
+    /// Disk usage of the RW layer in bytes
+    disk_usage: Option<u64>,
+
+    /// Seconds since the sandbox last changed status
+    uptime: Option<f64>,
+
+    /// CPU time used by the microVM in its current or last run, in seconds
+    cpu_seconds: Option<f64>,
+
+    /// Peak resident memory of the microVM in its current or last run, in bytes
+    peak_rss: Option<u64>,
+
+    /// Crashes recorded in the sandbox's event log
+    crashes: u64,
+
+    /// Times the OOM killer has killed the sandbox's microVM
+    oom_kills: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Renders the current metrics of every sandbox in the database in Prometheus text format.
+///
+/// Each series is labelled with `namespace` and `config_file`, and per-sandbox series also with
+/// `sandbox`. Live resource metrics are only reported for running or paused sandboxes; CPU time
+/// and peak memory cover the current run of a live sandbox and the last run of a stopped one.
+///
+/// ## Arguments
+///
+/// * `pool` - Connection pool to the namespace's sandbox database
+/// * `namespace` - The namespace the database belongs to
+pub async fn render_prometheus(pool: &Pool<Sqlite>, namespace: &str) -> MicrosandboxResult<String> {
+    Ok(render(&collect_samples(pool, namespace).await?))
+}
+
+/// Renders the current metrics of the sandboxes in every namespace in Prometheus text format.
+///
+/// Every directory in `namespaces_dir` is a namespace, named after the directory. Namespaces
+/// whose sandboxes have never been started have no sandbox database yet, and are skipped.
+///
+/// ## Arguments
+///
+/// * `namespaces_dir` - The parent directory containing namespace directories
+pub async fn render_prometheus_namespaces(namespaces_dir: &Path) -> MicrosandboxResult<String> {
+    let mut namespace_dirs = Vec::new();
+    if namespaces_dir.exists() {
+        let mut entries = tokio::fs::read_dir(namespaces_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if let (true, Some(namespace)) = (path.is_dir(), entry.file_name().to_str()) {
+                namespace_dirs.push((namespace.to_string(), path));
+            }
+        }
+    }
+    namespace_dirs.sort();
+
+    let mut samples = Vec::new();
+    for (namespace, namespace_dir) in namespace_dirs {
+        let db_path = namespace_dir
+            .join(MICROSANDBOX_ENV_DIR)
+            .join(SANDBOX_DB_FILENAME);
+        if !db_path.exists() {
+            continue;
+        }
+
+        let pool = db::get_or_create_pool(&db_path, &db::SANDBOX_DB_MIGRATOR).await?;
+        samples.extend(collect_samples(&pool, &namespace).await?);
+    }
+
+    Ok(render(&samples))
+}
+
+/// Samples the metrics of every sandbox in a namespace's database
+async fn collect_samples(
+    pool: &Pool<Sqlite>,
+    namespace: &str,
+) -> MicrosandboxResult<Vec<SandboxSample>> {
+    let now = Utc::now();
+
+    let crash_filter = SandboxEventFilter {
+        kind: Some(SandboxEventKind::Crash),
+        ..Default::default()
+    };
+    let mut crashes = HashMap::<_, u64>::new();
+    for event in db::query_events(pool, &crash_filter).await? {
+        *crashes
+            .entry((event.sandbox_name, event.config_file))
+            .or_default() += 1;
+    }
+
+    let mut samples = Vec::new();
+    for sandbox in db::get_all_sandboxes(pool).await? {
+        let live =
+            sandbox.status == SANDBOX_STATUS_RUNNING || sandbox.status == SANDBOX_STATUS_PAUSED;
+
+        let mut status = SandboxStatus {
+            name: sandbox.name.clone(),
+            running: live,
+            paused: sandbox.status == SANDBOX_STATUS_PAUSED,
+            crashed: sandbox.status == SANDBOX_STATUS_CRASHED,
+            supervisor_pid: None,
+            microvm_pid: None,
+            cpu_usage: None,
+            memory_usage: None,
+            disk_usage: None,
+            rootfs_paths: None,
+            db_stale: false,
+        };
+
+        if live {
+            orchestra::sample_resource_usage(&sandbox, &mut status).await;
+        }
+        let (peak_rss, cpu_time) = orchestra::sample_run_usage(&sandbox, live).await;
+        let oom_kills =
+            db::get_sandbox_oom_kills(pool, &sandbox.name, &sandbox.config_file).await?;
+        let crashes = crashes
+            .get(&(sandbox.name.clone(), sandbox.config_file.clone()))
+            .copied()
+            .unwrap_or(0);
+
+        samples.push(SandboxSample {
+            namespace: namespace.to_string(),
+            sandbox: sandbox.name,
+            config_file: sandbox.config_file,
+            status: sandbox.status,
+            cpu_usage: status.cpu_usage,
+            memory_usage: status.memory_usage.map(|mib| mib * 1024 * 1024),
+            disk_usage: status.disk_usage,
+            uptime: live.then(|| (now - sandbox.modified_at).num_milliseconds() as f64 / 1000.0),
+            cpu_seconds: cpu_time.map(|time| time.as_secs_f64()),
+            peak_rss,
+            crashes,
+            oom_kills,
+        });
+    }
+
+    Ok(samples)
+}
+
+/// Formats samples in the Prometheus text exposition format
+fn render(samples: &[SandboxSample]) -> String {
+    let mut out = String::new();
+
+    write_counts(&mut out, samples);
+
+    write_family(
+        &mut out,
+        "microsandbox_sandbox_status",
+        "Status of the sandbox, as a label; the value is always 1",
+        "gauge",
+        samples,
+        |_| Some(1.0),
+        |s| Some(("status", s.status.as_str())),
+    );
+    write_family(
+        &mut out,
+        "microsandbox_sandbox_cpu_usage_percent",
+        "CPU usage of the sandbox's microVM process in percent",
+        "gauge",
+        samples,
+        |s| s.cpu_usage.map(f64::from),
+        |_| None,
+    );
+    write_family(
+        &mut out,
+        "microsandbox_sandbox_memory_usage_bytes",
+        "Resident memory of the sandbox's microVM process in bytes",
+        "gauge",
+        samples,
+        |s| s.memory_usage.map(|v| v as f64),
+        |_| None,
+    );
+    write_family(
+        &mut out,
+        "microsandbox_sandbox_disk_usage_bytes",
+        "Disk usage of the sandbox's writable layer in bytes",
+        "gauge",
+        samples,
+        |s| s.disk_usage.map(|v| v as f64),
+        |_| None,
+    );
+    write_family(
+        &mut out,
+        "microsandbox_sandbox_uptime_seconds",
+        "Seconds since the sandbox was started or last changed status",
+        "gauge",
+        samples,
+        |s| s.uptime,
+        |_| None,
+    );
+    write_family(
+        &mut out,
+        "microsandbox_sandbox_cpu_seconds_total",
+        "CPU time used by the sandbox's microVM in its current or last run, in seconds",
+        "counter",
+        samples,
+        |s| s.cpu_seconds,
+        |_| None,
+    );
+    write_family(
+        &mut out,
+        "microsandbox_sandbox_peak_rss_bytes",
+        "Peak resident memory of the sandbox's microVM in its current or last run, in bytes",
+        "gauge",
+        samples,
+        |s| s.peak_rss.map(|v| v as f64),
+        |_| None,
+    );
+    write_family(
+        &mut out,
+        "microsandbox_sandbox_crashes_total",
+        "Crashes of the sandbox recorded in its event log",
+        "counter",
+        samples,
+        |s| Some(s.crashes as f64),
+        |_| None,
+    );
+    write_family(
+        &mut out,
+        "microsandbox_sandbox_oom_kills_total",
+        "Times the OOM killer has killed the sandbox's microVM",
+        "counter",
+        samples,
+        |s| Some(f64::from(s.oom_kills)),
+        |_| None,
+    );
+
+    out
+}
+
+/// Writes the number of sandboxes with each status, per namespace and config file
+fn write_counts(out: &mut String, samples: &[SandboxSample]) {
+    let name = "microsandbox_sandboxes";
+    let _ = writeln!(out, "# HELP {} Number of sandboxes with each status", name);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+
+    let mut counts = BTreeMap::<_, BTreeMap<&str, u64>>::new();
+    for sample in samples {
+        let statuses = counts
+            .entry((sample.namespace.as_str(), sample.config_file.as_str()))
+            .or_insert_with(|| COUNTED_STATUSES.iter().map(|status| (*status, 0)).collect());
+        *statuses.entry(sample.status.as_str()).or_default() += 1;
+    }
+
+    for ((namespace, config_file), statuses) in counts {
+        for (status, count) in statuses {
+            let _ = writeln!(
+                out,
+                "{}{{namespace=\"{}\",config_file=\"{}\",status=\"{}\"}} {}",
+                name,
+                escape_label_value(namespace),
+                escape_label_value(config_file),
+                escape_label_value(status),
+                count
+            );
+        }
+    }
+}
+
+/// Writes one metric family of the given type, skipping samples without a value
+fn write_family<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    kind: &str,
+    samples: &'a [SandboxSample],
+    value: impl Fn(&'a SandboxSample) -> Option<f64>,
+    extra_label: impl Fn(&'a SandboxSample) -> Option<(&'static str, &'a str)>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+
+    for sample in samples {
+        let Some(value) = value(sample) else {
+            continue;
+        };
+
+        let _ = write!(
+            out,
+            "{}{{namespace=\"{}\",sandbox=\"{}\",config_file=\"{}\"",
+            name,
+            escape_label_value(&sample.namespace),
+            escape_label_value(&sample.sandbox),
+            escape_label_value(&sample.config_file),
+        );
+        if let Some((label, label_value)) = extra_label(sample) {
+            let _ = write!(out, ",{}=\"{}\"", label, escape_label_value(label_value));
+        }
+        let _ = writeln!(out, "}} {}", value);
+    }
+}
+
+/// Escapes a label value as required by the Prometheus text format
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(sandbox: &str, status: &str) -> SandboxSample {
+        SandboxSample {
+            namespace: "default".to_string(),
+            sandbox: sandbox.to_string(),
+            config_file: "microsandbox.yaml".to_string(),
+            status: status.to_string(),
+            cpu_usage: None,
+            memory_usage: None,
+            disk_usage: None,
+            uptime: None,
+            cpu_seconds: None,
+            peak_rss: None,
+            crashes: 0,
+            oom_kills: 0,
+        }
+    }
+
+    #[test]
+    fn test_render_live_and_stopped_sandboxes() {
+        let mut running = sample("app", "RUNNING");
+        running.cpu_usage = Some(12.5);
+        running.memory_usage = Some(64 * 1024 * 1024);
+        running.disk_usage = Some(4096);
+        running.uptime = Some(30.0);
+        let stopped = sample("worker", "STOPPED");
+
+        let text = render(&[running, stopped]);
+
+        assert!(text.contains("# TYPE microsandbox_sandbox_cpu_usage_percent gauge\n"));
+        assert!(text.contains(
+            "microsandbox_sandbox_status{namespace=\"default\",sandbox=\"app\",config_file=\"microsandbox.yaml\",status=\"RUNNING\"} 1\n"
+        ));
+        assert!(text.contains(
+            "microsandbox_sandbox_status{namespace=\"default\",sandbox=\"worker\",config_file=\"microsandbox.yaml\",status=\"STOPPED\"} 1\n"
+        ));
+        assert!(text.contains(
+            "microsandbox_sandbox_cpu_usage_percent{namespace=\"default\",sandbox=\"app\",config_file=\"microsandbox.yaml\"} 12.5\n"
+        ));
+        assert!(text.contains(
+            "microsandbox_sandbox_memory_usage_bytes{namespace=\"default\",sandbox=\"app\",config_file=\"microsandbox.yaml\"} 67108864\n"
+        ));
+        assert!(text.contains(
+            "microsandbox_sandbox_uptime_seconds{namespace=\"default\",sandbox=\"app\",config_file=\"microsandbox.yaml\"} 30\n"
+        ));
+
+        // Stopped sandboxes don't report live resource usage
+        assert!(!text.contains(
+            "microsandbox_sandbox_cpu_usage_percent{namespace=\"default\",sandbox=\"worker\""
+        ));
+    }
+
+    #[test]
+    fn test_render_counts_and_run_totals() {
+        let mut running = sample("app", "RUNNING");
+        running.cpu_seconds = Some(1.5);
+        running.peak_rss = Some(2048);
+        let mut crashed = sample("worker", "CRASHED");
+        crashed.crashes = 3;
+        crashed.oom_kills = 1;
+        let mut other = sample("db", "STOPPED");
+        other.namespace = "team-b".to_string();
+
+        let text = render(&[running, crashed, other]);
+
+        assert!(text.contains("# TYPE microsandbox_sandbox_crashes_total counter\n"));
+        for (namespace, status, count) in [
+            ("default", "RUNNING", 1),
+            ("default", "CRASHED", 1),
+            ("default", "STOPPED", 0),
+            ("default", "PAUSED", 0),
+            ("team-b", "STOPPED", 1),
+            ("team-b", "RUNNING", 0),
+        ] {
+            assert!(text.contains(&format!(
+                "microsandbox_sandboxes{{namespace=\"{}\",config_file=\"microsandbox.yaml\",status=\"{}\"}} {}\n",
+                namespace, status, count
+            )));
+        }
+        assert!(text.contains(
+            "microsandbox_sandbox_cpu_seconds_total{namespace=\"default\",sandbox=\"app\",config_file=\"microsandbox.yaml\"} 1.5\n"
+        ));
+        assert!(text.contains(
+            "microsandbox_sandbox_peak_rss_bytes{namespace=\"default\",sandbox=\"app\",config_file=\"microsandbox.yaml\"} 2048\n"
+        ));
+        assert!(text.contains(
+            "microsandbox_sandbox_crashes_total{namespace=\"default\",sandbox=\"worker\",config_file=\"microsandbox.yaml\"} 3\n"
+        ));
+        assert!(text.contains(
+            "microsandbox_sandbox_oom_kills_total{namespace=\"default\",sandbox=\"worker\",config_file=\"microsandbox.yaml\"} 1\n"
+        ));
+
+        // Each family is only described once, however many namespaces there are
+        assert_eq!(
+            text.matches("# TYPE microsandbox_sandboxes gauge").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_escape_label_value() {
+        assert_eq!(escape_label_value("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -7,10 +7,13 @@
 //!
 //! Key components:
 //! - `db`: Database management for storing container and sandbox metadata
+//! - `fsdiff`: Filesystem diffing of a sandbox's writable layer
 //! - `image`: Container image handling and registry operations
 //! - `menv`: Microsandbox environment management
+//! - `metrics`: Prometheus metrics export for sandboxes
 //! - `rootfs`: Root filesystem operations for containers
 //! - `sandbox`: Sandbox creation and management
+//! - `snapshot`: Snapshots of a sandbox's writable layer
 //! - `orchestra`: Orchestra management for sandboxes
 //! - `home`: Home directory management
 //! - `toolchain`: Toolchain management
@@ -21,10 +24,13 @@
 
 pub mod config;
 pub mod db;
+pub mod fsdiff;
 pub mod home;
 pub mod image;
 pub mod menv;
+pub mod metrics;
 pub mod orchestra;
 pub mod rootfs;
 pub mod sandbox;
+pub mod snapshot;
 pub mod toolchain;
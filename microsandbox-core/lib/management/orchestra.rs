@@ -9,9 +9,14 @@
 //! - `up`: Start up all sandboxes defined in configuration
 //! - `down`: Gracefully shut down all running sandboxes
 //! - `apply`: Reconcile running sandboxes with configuration
+//! - `pause`/`resume`: Suspend and continue running sandboxes
 
 use crate::{
     config::{Microsandbox, START_SCRIPT_NAME},
+    runtime::{
+        MicroVmMonitor, SANDBOX_STATUS_CRASHED, SANDBOX_STATUS_PAUSED, SANDBOX_STATUS_RUNNING,
+    },
+    vm::Rootfs,
     MicrosandboxError, MicrosandboxResult,
 };
 
@@ -19,12 +24,13 @@ use crate::{
 use console::style;
 #[cfg(feature = "cli")]
 use microsandbox_utils::term;
-use microsandbox_utils::{MICROSANDBOX_ENV_DIR, SANDBOX_DB_FILENAME};
+use microsandbox_utils::{ExitStatus, LOG_SUBDIR, MICROSANDBOX_ENV_DIR, SANDBOX_DB_FILENAME};
 use nix::{
     sys::signal::{self, Signal},
     unistd::Pid,
 };
 use once_cell::sync::Lazy;
+use sqlx::{Pool, Sqlite};
 #[cfg(feature = "cli")]
 use std::io::{self, IsTerminal};
 use std::{
@@ -69,6 +75,12 @@ pub struct SandboxStatus {
     /// Whether the sandbox is running
     pub running: bool,
 
+    /// Whether the running sandbox is paused
+    pub paused: bool,
+
+    /// Whether the sandbox's MicroVM last exited abnormally without being stopped
+    pub crashed: bool,
+
     /// The PID of the supervisor process
     pub supervisor_pid: Option<u32>,
 
@@ -86,6 +98,55 @@ pub struct SandboxStatus {
 
     /// Rootfs paths
     pub rootfs_paths: Option<String>,
+
+    /// Whether the database may be out of date for this sandbox, because its monitor runs in
+    /// db-optional mode and failed to write to it
+    pub db_stale: bool,
+}
+
+/// Health of a sandbox, as recorded by its supervisor
+#[derive(Debug, Clone)]
+pub struct SandboxHealth {
+    /// The name of the sandbox
+    pub name: String,
+
+    /// The recorded status of the sandbox, one of the `SANDBOX_STATUS_*` constants, or `None`
+    /// if it has never been started
+    pub status: Option<String>,
+
+    /// How long the MicroVM has been running, if it still is
+    pub uptime: Option<Duration>,
+
+    /// The PID of the last supervisor process of the sandbox
+    pub supervisor_pid: Option<u32>,
+
+    /// The PID of the last microVM process of the sandbox
+    pub microvm_pid: Option<u32>,
+
+    /// Whether the supervisor process is alive
+    pub supervisor_alive: bool,
+
+    /// How the microVM last exited, if it has exited since it was started
+    pub exit_status: Option<ExitStatus>,
+}
+
+/// Resource use of a sandbox's MicroVM, to tell how close it came to its limits
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxStats {
+    /// The name of the sandbox
+    pub name: String,
+
+    /// Whether the figures are of a MicroVM that is still running, rather than of its last run
+    pub live: bool,
+
+    /// The largest resident set size the MicroVM reached in its current or last run, in bytes
+    pub peak_rss_bytes: Option<u64>,
+
+    /// The CPU time the MicroVM used in its current or last run
+    pub cpu_time: Option<Duration>,
+
+    /// How many times the OOM killer has killed the sandbox's MicroVM, over all its runs
+    pub oom_kills: u32,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -255,6 +316,12 @@ pub async fn apply(
     for sandbox in running_sandboxes {
         if !config_sandboxes.contains_key(&sandbox.name) {
             tracing::info!("stopping sandbox: {}", sandbox.name);
+
+            // A suspended microVM can't handle the shutdown, so continue it first
+            if sandbox.status == SANDBOX_STATUS_PAUSED {
+                let _ = signal::kill(Pid::from_raw(sandbox.microvm_pid as i32), Signal::SIGCONT);
+            }
+
             if let Err(e) = signal::kill(
                 Pid::from_raw(sandbox.supervisor_pid as i32),
                 Signal::SIGTERM,
@@ -579,6 +646,12 @@ pub async fn down(
             && config_sandboxes.contains_key(&sandbox.name)
         {
             tracing::info!("stopping sandbox: {}", sandbox.name);
+
+            // A suspended microVM can't handle the shutdown, so continue it first
+            if sandbox.status == SANDBOX_STATUS_PAUSED {
+                let _ = signal::kill(Pid::from_raw(sandbox.microvm_pid as i32), Signal::SIGCONT);
+            }
+
             if let Err(e) = signal::kill(
                 Pid::from_raw(sandbox.supervisor_pid as i32),
                 Signal::SIGTERM,
@@ -596,6 +669,70 @@ pub async fn down(
     Ok(())
 }
 
+/// Pauses running sandboxes.
+///
+/// The microVM process of each sandbox is suspended with `SIGSTOP`, so it consumes no CPU while
+/// keeping its memory and state intact. The sandbox is recorded as `PAUSED` in the database until
+/// it is resumed with [`resume`]. Sandboxes that are already paused are left untouched.
+///
+/// ## Arguments
+///
+/// * `sandbox_names` - List of sandbox names to pause
+/// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
+/// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
+///
+/// ## Returns
+///
+/// Returns `MicrosandboxResult<()>` indicating success or failure. Possible failures include:
+/// - Config file not found or invalid
+/// - Database errors
+/// - A requested sandbox is not running
+///
+/// ## Example
+///
+/// ```no_run
+/// use microsandbox_core::management::orchestra;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     orchestra::pause(vec!["sandbox1".to_string()], None, None).await?;
+///     orchestra::resume(vec!["sandbox1".to_string()], None, None).await?;
+///     Ok(())
+/// }
+/// ```
+pub async fn pause(
+    sandbox_names: Vec<String>,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<()> {
+    set_sandboxes_paused(sandbox_names, project_dir, config_file, true).await
+}
+
+/// Resumes paused sandboxes.
+///
+/// The microVM process of each sandbox is continued with `SIGCONT` and the sandbox is recorded as
+/// `RUNNING` again. Sandboxes that are not paused are left untouched.
+///
+/// ## Arguments
+///
+/// * `sandbox_names` - List of sandbox names to resume
+/// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
+/// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
+///
+/// ## Returns
+///
+/// Returns `MicrosandboxResult<()>` indicating success or failure. Possible failures include:
+/// - Config file not found or invalid
+/// - Database errors
+/// - A requested sandbox is not running
+pub async fn resume(
+    sandbox_names: Vec<String>,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<()> {
+    set_sandboxes_paused(sandbox_names, project_dir, config_file, false).await
+}
+
 /// Gets status information about specified sandboxes.
 ///
 /// This function retrieves the current status and resource usage of the specified sandboxes:
@@ -704,66 +841,33 @@ pub async fn status(
             let mut sandbox_status = SandboxStatus {
                 name: sandbox_name.clone(),
                 running: running_sandbox_map.contains_key(sandbox_name),
+                paused: running_sandbox_map
+                    .get(sandbox_name)
+                    .is_some_and(|s| s.status == SANDBOX_STATUS_PAUSED),
+                crashed: false,
                 supervisor_pid: None,
                 microvm_pid: None,
                 cpu_usage: None,
                 memory_usage: None,
                 disk_usage: None,
                 rootfs_paths: None,
+                db_stale: MicroVmMonitor::db_stale_marker_path(
+                    &menv_path.join(LOG_SUBDIR),
+                    &config_file,
+                    sandbox_name,
+                )
+                .exists(),
             };
 
-            // If the sandbox is running, get additional stats
+            // If the sandbox is running, get additional stats, otherwise check how it ended
             if sandbox_status.running {
                 if let Some(sandbox) = running_sandbox_map.get(sandbox_name) {
-                    sandbox_status.supervisor_pid = Some(sandbox.supervisor_pid);
-                    sandbox_status.microvm_pid = Some(sandbox.microvm_pid);
-                    sandbox_status.rootfs_paths = Some(sandbox.rootfs_paths.clone());
-
-                    // Get CPU and memory usage for the microVM process
-                    if let Ok(mut process) = psutil::process::Process::new(sandbox.microvm_pid) {
-                        // CPU usage
-                        if let Ok(cpu_percent) = process.cpu_percent() {
-                            sandbox_status.cpu_usage = Some(cpu_percent);
-                        }
-
-                        // Memory usage
-                        if let Ok(memory_info) = process.memory_info() {
-                            // Convert bytes to MiB
-                            sandbox_status.memory_usage = Some(memory_info.rss() / (1024 * 1024));
-                        }
-                    }
-
-                    // Get disk usage of the RW layer if it's an overlayfs
-                    if sandbox.rootfs_paths.starts_with("overlayfs:") {
-                        let paths: Vec<&str> = sandbox.rootfs_paths.split(':').collect();
-                        if paths.len() > 1 {
-                            // The last path should be the RW layer
-                            let rw_path = paths.last().unwrap();
-                            if let Ok(metadata) = tokio::fs::metadata(rw_path).await {
-                                // For a directory, we need to calculate the total size
-                                if metadata.is_dir() {
-                                    if let Ok(size) = get_directory_size(rw_path).await {
-                                        sandbox_status.disk_usage = Some(size);
-                                    }
-                                } else {
-                                    sandbox_status.disk_usage = Some(metadata.len());
-                                }
-                            }
-                        }
-                    } else if sandbox.rootfs_paths.starts_with("native:") {
-                        // For native rootfs, get the size of the rootfs
-                        let path = sandbox.rootfs_paths.strip_prefix("native:").unwrap();
-                        if let Ok(metadata) = tokio::fs::metadata(path).await {
-                            if metadata.is_dir() {
-                                if let Ok(size) = get_directory_size(path).await {
-                                    sandbox_status.disk_usage = Some(size);
-                                }
-                            } else {
-                                sandbox_status.disk_usage = Some(metadata.len());
-                            }
-                        }
-                    }
+                    sample_resource_usage(sandbox, &mut sandbox_status).await;
                 }
+            } else {
+                sandbox_status.crashed = db::get_sandbox(&pool, sandbox_name, &config_file)
+                    .await?
+                    .is_some_and(|s| s.status == SANDBOX_STATUS_CRASHED);
             }
 
             statuses.push(sandbox_status);
@@ -773,6 +877,143 @@ pub async fn status(
     Ok(statuses)
 }
 
+/// Check the health of a sandbox, whether or not it is running
+///
+/// Unlike [`status`], this reports the last-known process IDs of a sandbox that is no longer
+/// running, and checks that its supervisor process is still alive, so a caller can tell a
+/// sandbox that is starting or running from one whose supervisor died without updating the
+/// database.
+///
+/// ## Arguments
+///
+/// * `sandbox_name` - The name of the sandbox to check
+/// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
+/// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
+///
+/// ## Returns
+///
+/// Returns the sandbox's health. Fails with [`MicrosandboxError::SandboxNotFoundInConfig`] if
+/// the sandbox isn't defined in the configuration.
+pub async fn health(
+    sandbox_name: &str,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<SandboxHealth> {
+    let (config, canonical_project_dir, config_file) =
+        config::load_config(project_dir, config_file).await?;
+    validate_sandbox_names(
+        &[sandbox_name.to_string()],
+        &config,
+        &canonical_project_dir,
+        &config_file,
+    )?;
+
+    // Ensure menv files exist
+    let menv_path = canonical_project_dir.join(MICROSANDBOX_ENV_DIR);
+    menv::ensure_menv_files(&menv_path).await?;
+
+    let db_path = menv_path.join(SANDBOX_DB_FILENAME);
+    let pool = db::get_or_create_pool(&db_path, &db::SANDBOX_DB_MIGRATOR).await?;
+    sandbox_health(&pool, sandbox_name, &config_file).await
+}
+
+/// Get the resource use of a sandbox's MicroVM, whether or not it is running
+///
+/// For a running MicroVM, the peak memory and CPU time so far are read from its process.
+/// Once it has exited, they are those its supervisor recorded for the whole run. A sandbox
+/// that has never been started reports no figures.
+///
+/// ## Arguments
+///
+/// * `sandbox_name` - The name of the sandbox to check
+/// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
+/// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
+///
+/// ## Returns
+///
+/// Returns the sandbox's stats. Fails with [`MicrosandboxError::SandboxNotFoundInConfig`] if
+/// the sandbox isn't defined in the configuration.
+pub async fn stats(
+    sandbox_name: &str,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<SandboxStats> {
+    let (config, canonical_project_dir, config_file) =
+        config::load_config(project_dir, config_file).await?;
+    validate_sandbox_names(
+        &[sandbox_name.to_string()],
+        &config,
+        &canonical_project_dir,
+        &config_file,
+    )?;
+
+    // Ensure menv files exist
+    let menv_path = canonical_project_dir.join(MICROSANDBOX_ENV_DIR);
+    menv::ensure_menv_files(&menv_path).await?;
+
+    let db_path = menv_path.join(SANDBOX_DB_FILENAME);
+    let pool = db::get_or_create_pool(&db_path, &db::SANDBOX_DB_MIGRATOR).await?;
+    let oom_kills = db::get_sandbox_oom_kills(&pool, sandbox_name, &config_file).await?;
+    let Some(sandbox) = db::get_sandbox(&pool, sandbox_name, &config_file).await? else {
+        return Ok(SandboxStats {
+            name: sandbox_name.to_string(),
+            live: false,
+            peak_rss_bytes: None,
+            cpu_time: None,
+            oom_kills,
+        });
+    };
+
+    let live = sandbox.status == SANDBOX_STATUS_RUNNING || sandbox.status == SANDBOX_STATUS_PAUSED;
+    let (peak_rss_bytes, cpu_time) = sample_run_usage(&sandbox, live).await;
+
+    Ok(SandboxStats {
+        name: sandbox.name,
+        live,
+        peak_rss_bytes,
+        cpu_time,
+        oom_kills,
+    })
+}
+
+/// List the sandboxes defined in the configuration, with their health
+///
+/// Every sandbox in the configuration is listed, whether or not it has ever been started, so
+/// callers can find sandboxes that are defined but not running as well as running ones.
+///
+/// ## Arguments
+///
+/// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
+/// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
+///
+/// ## Returns
+///
+/// Returns the health of each sandbox, ordered by name.
+pub async fn list(
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<Vec<SandboxHealth>> {
+    let (config, canonical_project_dir, config_file) =
+        config::load_config(project_dir, config_file).await?;
+
+    // Ensure menv files exist
+    let menv_path = canonical_project_dir.join(MICROSANDBOX_ENV_DIR);
+    menv::ensure_menv_files(&menv_path).await?;
+
+    let db_path = menv_path.join(SANDBOX_DB_FILENAME);
+    let pool = db::get_or_create_pool(&db_path, &db::SANDBOX_DB_MIGRATOR).await?;
+
+    let mut names: Vec<&String> = config.get_sandboxes().keys().collect();
+    names.sort();
+
+    let mut sandboxes = Vec::with_capacity(names.len());
+    for name in names {
+        sandboxes.push(sandbox_health(&pool, name, &config_file).await?);
+    }
+
+    Ok(sandboxes)
+}
+
 /// Show the status of the sandboxes
 ///
 /// ## Arguments
@@ -1514,8 +1755,12 @@ fn format_status_columns(
     String,
     String,
 ) {
-    let status_text = if status.running {
+    let status_text = if status.paused {
+        style("PAUSED".to_string()).yellow()
+    } else if status.running {
         style("RUNNING".to_string()).green()
+    } else if status.crashed {
+        style("CRASHED".to_string()).red().bold()
     } else {
         style("STOPPED".to_string()).red()
     };
@@ -1559,6 +1804,109 @@ fn format_status_columns(
     (status_text, pids, cpu, memory, disk)
 }
 
+/// Suspends or continues the microVMs of the given sandboxes and records the new status
+async fn set_sandboxes_paused(
+    sandbox_names: Vec<String>,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+    paused: bool,
+) -> MicrosandboxResult<()> {
+    let (config, canonical_project_dir, config_file) =
+        config::load_config(project_dir, config_file).await?;
+
+    validate_sandbox_names(
+        &sandbox_names,
+        &config,
+        &canonical_project_dir,
+        &config_file,
+    )?;
+
+    let menv_path = canonical_project_dir.join(MICROSANDBOX_ENV_DIR);
+    menv::ensure_menv_files(&menv_path).await?;
+
+    let db_path = menv_path.join(SANDBOX_DB_FILENAME);
+    let pool = db::get_or_create_pool(&db_path, &db::SANDBOX_DB_MIGRATOR).await?;
+    let running_sandboxes = db::get_running_config_sandboxes(&pool, &config_file).await?;
+
+    let (signal, from_status, to_status) = if paused {
+        (
+            Signal::SIGSTOP,
+            SANDBOX_STATUS_RUNNING,
+            SANDBOX_STATUS_PAUSED,
+        )
+    } else {
+        (
+            Signal::SIGCONT,
+            SANDBOX_STATUS_PAUSED,
+            SANDBOX_STATUS_RUNNING,
+        )
+    };
+
+    for name in &sandbox_names {
+        let sandbox = running_sandboxes
+            .iter()
+            .find(|s| &s.name == name)
+            .ok_or_else(|| MicrosandboxError::SandboxNotRunning(name.clone()))?;
+
+        if sandbox.status != from_status {
+            continue;
+        }
+
+        tracing::info!(
+            "{} sandbox: {}",
+            if paused { "pausing" } else { "resuming" },
+            name
+        );
+        signal::kill(Pid::from_raw(sandbox.microvm_pid as i32), signal)?;
+        db::update_sandbox_status(&pool, name, &config_file, to_status).await?;
+    }
+
+    Ok(())
+}
+
+/// Read the health of one sandbox from the database, checking its supervisor is alive
+async fn sandbox_health(
+    pool: &Pool<Sqlite>,
+    sandbox_name: &str,
+    config_file: &str,
+) -> MicrosandboxResult<SandboxHealth> {
+    let Some(sandbox) = db::get_sandbox(pool, sandbox_name, config_file).await? else {
+        return Ok(SandboxHealth {
+            name: sandbox_name.to_string(),
+            status: None,
+            uptime: None,
+            supervisor_pid: None,
+            microvm_pid: None,
+            supervisor_alive: false,
+            exit_status: None,
+        });
+    };
+
+    let supervisor_alive = signal::kill(Pid::from_raw(sandbox.supervisor_pid as i32), None).is_ok();
+
+    // The MicroVM's uptime is the age of its process, if it is still running
+    let live = sandbox.status == SANDBOX_STATUS_RUNNING || sandbox.status == SANDBOX_STATUS_PAUSED;
+    let uptime = live
+        .then(|| psutil::process::Process::new(sandbox.microvm_pid).ok())
+        .flatten()
+        .and_then(|process| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()?
+                .checked_sub(process.create_time())
+        });
+
+    Ok(SandboxHealth {
+        name: sandbox.name,
+        status: Some(sandbox.status),
+        uptime,
+        supervisor_pid: Some(sandbox.supervisor_pid),
+        microvm_pid: Some(sandbox.microvm_pid),
+        supervisor_alive,
+        exit_status: sandbox.exit_status,
+    })
+}
+
 /// Validate that all requested sandbox names exist in the configuration
 fn validate_sandbox_names(
     sandbox_names: &[String],
@@ -1584,6 +1932,90 @@ fn validate_sandbox_names(
     Ok(())
 }
 
+/// Reads the peak resident set size of a running process from `/proc/<pid>/status`, in bytes
+///
+/// Returns `None` on systems without `/proc` or once the process is gone.
+async fn read_peak_rss(pid: u32) -> Option<u64> {
+    let status = tokio::fs::read_to_string(format!("/proc/{}/status", pid))
+        .await
+        .ok()?;
+    parse_peak_rss(&status)
+}
+
+/// Extracts the peak resident set size, `VmHWM`, from the contents of `/proc/<pid>/status`
+fn parse_peak_rss(status: &str) -> Option<u64> {
+    let kib: u64 = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// Gets the peak resident set size and CPU time of a sandbox's current run if it is live, or of
+/// its last run otherwise
+pub(crate) async fn sample_run_usage(
+    sandbox: &crate::models::Sandbox,
+    live: bool,
+) -> (Option<u64>, Option<Duration>) {
+    if live {
+        let cpu_time = psutil::process::Process::new(sandbox.microvm_pid)
+            .and_then(|process| process.cpu_times())
+            .ok()
+            .map(|times| times.user() + times.system());
+        (read_peak_rss(sandbox.microvm_pid).await, cpu_time)
+    } else {
+        sandbox.exit_status.as_ref().map_or((None, None), |status| {
+            (status.peak_rss_bytes, status.cpu_time)
+        })
+    }
+}
+
+/// Fills in the process and disk usage of a live sandbox
+pub(crate) async fn sample_resource_usage(
+    sandbox: &crate::models::Sandbox,
+    sandbox_status: &mut SandboxStatus,
+) {
+    sandbox_status.supervisor_pid = Some(sandbox.supervisor_pid);
+    sandbox_status.microvm_pid = Some(sandbox.microvm_pid);
+    sandbox_status.rootfs_paths = Some(sandbox.rootfs_paths.clone());
+
+    // Get CPU and memory usage for the microVM process
+    if let Ok(mut process) = psutil::process::Process::new(sandbox.microvm_pid) {
+        // CPU usage
+        if let Ok(cpu_percent) = process.cpu_percent() {
+            sandbox_status.cpu_usage = Some(cpu_percent);
+        }
+
+        // Memory usage
+        if let Ok(memory_info) = process.memory_info() {
+            // Convert bytes to MiB
+            sandbox_status.memory_usage = Some(memory_info.rss() / (1024 * 1024));
+        }
+    }
+
+    // Get disk usage of the RW layer if it's an overlayfs, or of the whole rootfs if it's native
+    let path = match sandbox.rootfs_paths.parse::<Rootfs>() {
+        Ok(Rootfs::Overlayfs(layers)) => Some(layers.get_upper().clone()),
+        Ok(Rootfs::Native(path)) => Some(path),
+        Err(_) => None,
+    };
+    if let Some(path) = path {
+        if let Ok(metadata) = tokio::fs::metadata(&path).await {
+            if metadata.is_dir() {
+                if let Ok(size) = get_directory_size(&path.to_string_lossy()).await {
+                    sandbox_status.disk_usage = Some(size);
+                }
+            } else {
+                sandbox_status.disk_usage = Some(metadata.len());
+            }
+        }
+    }
+}
+
 /// Recursively calculate the size of a directory, but cache the result for a short period so that
 /// callers (status refresh every ~2 s) don't hammer the filesystem.
 async fn get_directory_size(path: &str) -> MicrosandboxResult<u64> {
@@ -225,7 +225,7 @@ pub async fn patch_with_virtiofs_mounts(
 /// - Cannot create directories in the rootfs
 /// - Cannot read or write the hosts file
 /// - Cannot set permissions on the hosts file
-async fn _patch_with_hostnames(
+async fn patch_with_hostnames(
     root_path: &Path,
     hostname_mappings: &[(std::net::Ipv4Addr, String)],
 ) -> MicrosandboxResult<()> {
@@ -274,6 +274,52 @@ async fn _patch_with_hostnames(
     Ok(())
 }
 
+/// Sets the guest hostname by writing /etc/hostname and mapping the name in /etc/hosts.
+///
+/// The files are written to the top layer. If a lower layer already has an /etc/hosts file,
+/// it is copied up first so its entries are kept.
+///
+/// ## Arguments
+/// * `root_paths` - List of root paths, ordered from bottom to top layer (the same layers
+///   passed to [`patch_with_default_dns_settings`])
+/// * `hostname` - The hostname to give the guest
+///
+/// ## Errors
+/// Returns an error if:
+/// - Cannot create directories in the rootfs
+/// - Cannot read or write the hostname or hosts files
+pub async fn patch_with_hostname(root_paths: &[PathBuf], hostname: &str) -> MicrosandboxResult<()> {
+    let Some(top_layer) = root_paths.last() else {
+        return Ok(());
+    };
+
+    // Write /etc/hostname
+    let hostname_path = top_layer.join("etc/hostname");
+    if let Some(parent) = hostname_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&hostname_path, format!("{}\n", hostname)).await?;
+    fs::set_permissions(&hostname_path, Permissions::from_mode(0o644)).await?;
+
+    // Copy up the nearest lower /etc/hosts so its entries aren't shadowed
+    let top_hosts_path = top_layer.join("etc/hosts");
+    if !top_hosts_path.exists() {
+        for root_path in root_paths.iter().rev().skip(1) {
+            let hosts_path = root_path.join("etc/hosts");
+            if hosts_path.exists() {
+                fs::copy(&hosts_path, &top_hosts_path).await?;
+                break;
+            }
+        }
+    }
+
+    patch_with_hostnames(
+        top_layer,
+        &[(std::net::Ipv4Addr::new(127, 0, 1, 1), hostname.to_string())],
+    )
+    .await
+}
+
 /// Updates the /etc/resolv.conf file in the guest rootfs to add default DNS servers if none exist.
 /// Creates the file if it doesn't exist.
 ///
@@ -669,7 +715,7 @@ mod tests {
         ];
 
         // Update hosts file
-        _patch_with_hostnames(root_path, &hostname_mappings).await?;
+        patch_with_hostnames(root_path, &hostname_mappings).await?;
 
         // Verify hosts file was created with correct content
         let hosts_path = root_path.join("etc/hosts");
@@ -697,7 +743,7 @@ mod tests {
         ];
 
         // Update hosts file again
-        _patch_with_hostnames(root_path, &new_mappings).await?;
+        patch_with_hostnames(root_path, &new_mappings).await?;
 
         // Verify updated content
         let updated_content = fs::read_to_string(&hosts_path).await?;
@@ -720,6 +766,37 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_patch_with_hostname() -> anyhow::Result<()> {
+        // Create a lower layer with an existing hosts file and an empty top layer
+        let lower_dir = TempDir::new()?;
+        let top_dir = TempDir::new()?;
+        fs::create_dir_all(lower_dir.path().join("etc")).await?;
+        fs::write(
+            lower_dir.path().join("etc/hosts"),
+            "127.0.0.1\tlocalhost\n10.0.0.5\tdb\n",
+        )
+        .await?;
+
+        let root_paths = vec![lower_dir.path().to_path_buf(), top_dir.path().to_path_buf()];
+        patch_with_hostname(&root_paths, "worker-1").await?;
+
+        // The hostname is written to the top layer
+        let hostname = fs::read_to_string(top_dir.path().join("etc/hostname")).await?;
+        assert_eq!(hostname, "worker-1\n");
+
+        // The lower hosts entries are kept and the hostname is mapped
+        let hosts = fs::read_to_string(top_dir.path().join("etc/hosts")).await?;
+        assert!(hosts.contains("10.0.0.5\tdb"));
+        assert!(hosts.contains("127.0.1.1\tworker-1"));
+
+        // The lower layer is left untouched
+        let lower_hosts = fs::read_to_string(lower_dir.path().join("etc/hosts")).await?;
+        assert!(!lower_hosts.contains("worker-1"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_copy_dir_complex_permissions() -> anyhow::Result<()> {
         // Skip this test in CI environments
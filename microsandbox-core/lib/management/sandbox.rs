@@ -9,12 +9,13 @@ use std::{
     process::Stdio,
 };
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use microsandbox_utils::{
     env, DEFAULT_MSBRUN_EXE_PATH, DEFAULT_SHELL, EXTRACTED_LAYER_SUFFIX, LAYERS_SUBDIR, LOG_SUBDIR,
     MICROSANDBOX_CONFIG_FILENAME, MICROSANDBOX_ENV_DIR, MSBRUN_EXE_ENV_VAR, OCI_DB_FILENAME,
     PATCH_SUBDIR, RW_SUBDIR, SANDBOX_DB_FILENAME, SANDBOX_DIR, SCRIPTS_DIR, SHELL_SCRIPT_NAME,
 };
+use sha2::{Digest, Sha256};
 use sqlx::{Pool, Sqlite};
 use tempfile;
 use tokio::{fs, process::Command};
@@ -26,7 +27,7 @@ use crate::{
     },
     management::{config, db, image, menv, rootfs},
     oci::Reference,
-    vm::Rootfs,
+    vm::{OverlayfsLayers, Rootfs},
     MicrosandboxError, MicrosandboxResult,
 };
 
@@ -36,6 +37,10 @@ use crate::{
 
 const TEMPORARY_SANDBOX_NAME: &str = "tmp";
 
+/// How far apart config modification times can be and still be treated as ambiguous, to allow
+/// for clock skew and coarse timestamps on networked filesystems.
+const CONFIG_MTIME_TOLERANCE: TimeDelta = TimeDelta::seconds(2);
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -191,6 +196,9 @@ pub async fn prepare_run(
     // Get the config last modified timestamp
     let config_last_modified: DateTime<Utc> = fs::metadata(&config_path).await?.modified()?.into();
 
+    // Get the config content hash, used when the timestamp is ambiguous
+    let config_hash = hex::encode(Sha256::digest(fs::read(&config_path).await?));
+
     let rootfs = match sandbox_config.get_image().clone() {
         ReferenceOrPath::Path(root_path) => {
             setup_native_rootfs(
@@ -199,6 +207,7 @@ pub async fn prepare_run(
                 &sandbox_config,
                 &config_file,
                 &config_last_modified,
+                &config_hash,
                 &sandbox_pool,
             )
             .await?
@@ -211,6 +220,7 @@ pub async fn prepare_run(
                 &menv_path,
                 &config_file,
                 &config_last_modified,
+                &config_hash,
                 &sandbox_pool,
                 use_image_defaults,
             )
@@ -245,6 +255,8 @@ pub async fn prepare_run(
         .arg(&config_file)
         .arg("--config-last-modified")
         .arg(&config_last_modified.to_rfc3339())
+        .arg("--config-hash")
+        .arg(&config_hash)
         .arg("--sandbox-db-path")
         .arg(&sandbox_db_path)
         .arg("--scope")
@@ -262,6 +274,11 @@ pub async fn prepare_run(
         command.arg("--memory-mib").arg(memory.to_string());
     }
 
+    // OOM score adjustment
+    if let Some(oom_score_adj) = sandbox_config.get_oom_score_adj() {
+        command.arg(format!("--oom-score-adj={}", oom_score_adj));
+    }
+
     // Workdir
     if let Some(workdir) = sandbox_config.get_workdir() {
         command.arg("--workdir-path").arg(workdir);
@@ -272,11 +289,27 @@ pub async fn prepare_run(
         command.arg("--env").arg(env.to_string());
     }
 
+    // Expose the configured hostname unless the config already sets HOSTNAME
+    if let Some(hostname) = sandbox_config.get_hostname() {
+        if !sandbox_config
+            .get_envs()
+            .iter()
+            .any(|env| env.get_name() == "HOSTNAME")
+        {
+            command.arg("--env").arg(format!("HOSTNAME={}", hostname));
+        }
+    }
+
     // Ports
     for port in sandbox_config.get_ports() {
         command.arg("--port-map").arg(port.to_string());
     }
 
+    // Resource limits
+    for ulimit in sandbox_config.get_ulimits() {
+        command.arg("--rlimit").arg(ulimit.to_string());
+    }
+
     // Volumes
     for volume in sandbox_config.get_volumes() {
         match volume {
@@ -310,8 +343,8 @@ pub async fn prepare_run(
         Rootfs::Native(path) => {
             command.arg("--native-rootfs").arg(path);
         }
-        Rootfs::Overlayfs(paths) => {
-            for path in paths {
+        Rootfs::Overlayfs(layers) => {
+            for path in layers.stack() {
                 command.arg("--overlayfs-layer").arg(path);
             }
         }
@@ -539,6 +572,7 @@ async fn setup_image_rootfs(
     menv_path: &Path,
     config_file: &str,
     config_last_modified: &DateTime<Utc>,
+    config_hash: &str,
     sandbox_pool: &Pool<Sqlite>,
     use_image_defaults: bool,
 ) -> MicrosandboxResult<Rootfs> {
@@ -600,6 +634,7 @@ async fn setup_image_rootfs(
         sandbox_name,
         config_file,
         config_last_modified,
+        config_hash,
     )
     .await?;
 
@@ -629,6 +664,11 @@ async fn setup_image_rootfs(
         all_layers.push(patch_dir.clone());
         rootfs::patch_with_default_dns_settings(&all_layers).await?;
 
+        // Patch with the configured hostname
+        if let Some(hostname) = sandbox_config.get_hostname() {
+            rootfs::patch_with_hostname(&all_layers, hostname).await?;
+        }
+
         // Patch with volume mounts if there are any volumes defined
         let volumes = &sandbox_config.get_volumes();
         if !volumes.is_empty() {
@@ -642,11 +682,13 @@ async fn setup_image_rootfs(
         tracing::info!("skipping sandbox patch - config unchanged");
     }
 
-    // Add the scripts and rootfs directories to the layer paths
+    // Stack the scripts directory on the image layers, under the writable rootfs directory
     layer_paths.push(patch_dir);
-    layer_paths.push(top_rw_path);
 
-    Ok(Rootfs::Overlayfs(layer_paths))
+    Ok(Rootfs::Overlayfs(OverlayfsLayers::new(
+        layer_paths,
+        top_rw_path,
+    )?))
 }
 
 async fn setup_native_rootfs(
@@ -655,6 +697,7 @@ async fn setup_native_rootfs(
     sandbox_config: &Sandbox,
     config_file: &str,
     config_last_modified: &DateTime<Utc>,
+    config_hash: &str,
     sandbox_pool: &Pool<Sqlite>,
 ) -> MicrosandboxResult<Rootfs> {
     // Create the scripts directory
@@ -667,6 +710,7 @@ async fn setup_native_rootfs(
         sandbox_name,
         config_file,
         config_last_modified,
+        config_hash,
     )
     .await?;
 
@@ -688,6 +732,11 @@ async fn setup_native_rootfs(
         // Patch with default DNS settings - for native rootfs, just pass the single root path
         rootfs::patch_with_default_dns_settings(&[root_path.to_path_buf()]).await?;
 
+        // Patch with the configured hostname
+        if let Some(hostname) = sandbox_config.get_hostname() {
+            rootfs::patch_with_hostname(&[root_path.to_path_buf()], hostname).await?;
+        }
+
         // Patch with volume mounts if there are any volumes defined
         let volumes = &sandbox_config.get_volumes();
         if !volumes.is_empty() {
@@ -705,26 +754,51 @@ async fn setup_native_rootfs(
     Ok(Rootfs::Native(root_path.to_path_buf()))
 }
 
-/// Checks if a sandbox's configuration has changed by comparing the current config's last modified
-/// timestamp with the stored timestamp in the database. Returns true if the sandbox doesn't exist
-/// or if the config has been modified since the last run.
+/// Checks if a sandbox's configuration has changed since the sandbox was last run. Returns true if
+/// the sandbox doesn't exist or if the config is stale (see [`is_config_stale`]).
 async fn has_sandbox_config_changed(
     sandbox_pool: &Pool<Sqlite>,
     sandbox_name: &str,
     config_file: &str,
     config_last_modified: &DateTime<Utc>,
+    config_hash: &str,
 ) -> MicrosandboxResult<bool> {
     // Check if sandbox exists and config hasn't changed
     let sandbox = db::get_sandbox(sandbox_pool, sandbox_name, config_file).await?;
     Ok(match sandbox {
-        Some(sandbox) => {
-            // Compare timestamps to see if config has changed
-            sandbox.config_last_modified != *config_last_modified
-        }
+        Some(sandbox) => is_config_stale(
+            &sandbox.config_last_modified,
+            sandbox.config_hash.as_deref(),
+            config_last_modified,
+            config_hash,
+            &Utc::now(),
+        ),
         None => true, // No existing sandbox, need to patch
     })
 }
 
+/// Decides whether a config differs from the one a sandbox was last run with.
+///
+/// Modification times normally decide, but they are ambiguous when they are within
+/// [`CONFIG_MTIME_TOLERANCE`] of each other (coarse or jittery timestamps) or when the current
+/// one is in the future (clock skew). In those cases the content hashes are compared instead, if
+/// a hash was stored.
+fn is_config_stale(
+    stored_modified: &DateTime<Utc>,
+    stored_hash: Option<&str>,
+    current_modified: &DateTime<Utc>,
+    current_hash: &str,
+    now: &DateTime<Utc>,
+) -> bool {
+    let ambiguous = (*current_modified - *stored_modified).abs() <= CONFIG_MTIME_TOLERANCE
+        || *current_modified > *now + CONFIG_MTIME_TOLERANCE;
+
+    match stored_hash {
+        Some(stored_hash) if ambiguous => stored_hash != current_hash,
+        _ => stored_modified != current_modified,
+    }
+}
+
 /// Determines the execution command and arguments for a sandbox based on the provided configuration.
 ///
 /// The function follows this priority order:
@@ -796,3 +870,98 @@ pub fn determine_exec_path_and_args(
         },
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_750_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_config_stale_with_equal_mtimes() {
+        let now = time(100);
+
+        // Same mtime and same content is not stale
+        assert!(!is_config_stale(
+            &time(0),
+            Some("abc"),
+            &time(0),
+            "abc",
+            &now
+        ));
+
+        // Same mtime but edited content is stale
+        assert!(is_config_stale(
+            &time(0),
+            Some("abc"),
+            &time(0),
+            "def",
+            &now
+        ));
+
+        // Same mtime without a stored hash falls back to the timestamp
+        assert!(!is_config_stale(&time(0), None, &time(0), "def", &now));
+    }
+
+    #[test]
+    fn test_config_stale_with_future_mtime() {
+        let now = time(100);
+
+        // A future mtime from clock skew with unchanged content is not stale
+        assert!(!is_config_stale(
+            &time(0),
+            Some("abc"),
+            &time(3600),
+            "abc",
+            &now
+        ));
+
+        // A future mtime with changed content is stale
+        assert!(is_config_stale(
+            &time(0),
+            Some("abc"),
+            &time(3600),
+            "def",
+            &now
+        ));
+    }
+
+    #[test]
+    fn test_config_stale_with_jittery_mtime() {
+        let now = time(100);
+
+        // Jitter within the tolerance is resolved by the hash
+        assert!(!is_config_stale(
+            &time(0),
+            Some("abc"),
+            &time(1),
+            "abc",
+            &now
+        ));
+        assert!(!is_config_stale(
+            &time(1),
+            Some("abc"),
+            &time(0),
+            "abc",
+            &now
+        ));
+
+        // A clear mtime change is stale even with unchanged content
+        assert!(is_config_stale(
+            &time(0),
+            Some("abc"),
+            &time(50),
+            "abc",
+            &now
+        ));
+
+        // Without a stored hash, any mtime change is stale
+        assert!(is_config_stale(&time(0), None, &time(1), "abc", &now));
+    }
+}
@@ -0,0 +1,473 @@
+//! Sandbox snapshots for Microsandbox.
+//!
+//! A sandbox's writable overlay layer captures every change made on top of its image, so a copy
+//! of that layer is enough to save the sandbox's filesystem state. This module takes such copies,
+//! records them in the sandbox database, and restores a sandbox's layer from one.
+//!
+//! A running sandbox is paused while its layer is copied, so the snapshot doesn't catch a write
+//! halfway through, and resumed afterwards. Restoring replaces the layer the sandbox boots from,
+//! so it is only allowed while the sandbox is stopped; starting the sandbox afterwards picks up
+//! the restored state. A snapshot can be restored any number of times, which makes it a cheap way
+//! to reset a prepared environment without running its setup again.
+
+use std::path::{Path, PathBuf};
+
+use microsandbox_utils::{MICROSANDBOX_ENV_DIR, RW_SUBDIR, SANDBOX_DB_FILENAME, SNAPSHOTS_SUBDIR};
+use sqlx::{Pool, Sqlite};
+use tokio::{fs, process::Command};
+use walkdir::WalkDir;
+
+use crate::{
+    models::SandboxSnapshot,
+    runtime::{SANDBOX_STATUS_PAUSED, SANDBOX_STATUS_RUNNING},
+    MicrosandboxError, MicrosandboxResult,
+};
+
+use super::{config, db, menv, orchestra};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum length of a snapshot name
+const MAX_SNAPSHOT_NAME_LEN: usize = 63;
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Takes a snapshot of a sandbox's writable layer.
+///
+/// The sandbox must have been started at least once, so it has a writable layer. If it is
+/// running, it is paused while the layer is copied and resumed afterwards; a sandbox that is
+/// already paused is left paused.
+///
+/// ## Arguments
+///
+/// * `sandbox_name` - Name of the sandbox to snapshot
+/// * `snapshot_name` - Name of the snapshot, unique among the sandbox's snapshots
+/// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
+/// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
+///
+/// ## Example
+///
+/// ```no_run
+/// use microsandbox_core::management::snapshot;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let taken = snapshot::create("sandbox1", "prepared", None, None).await?;
+///     println!("Snapshot {} is {} bytes", taken.name, taken.size_bytes);
+///     Ok(())
+/// }
+/// ```
+pub async fn create(
+    sandbox_name: &str,
+    snapshot_name: &str,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<SandboxSnapshot> {
+    validate_snapshot_name(snapshot_name)?;
+    let (menv_path, config_file, pool) =
+        open_sandbox_db(sandbox_name, project_dir, config_file).await?;
+
+    let rw_path = rw_layer_path(&menv_path, &config_file, sandbox_name);
+    if !rw_path.exists() {
+        return Err(MicrosandboxError::PathNotFound(format!(
+            "sandbox '{}' has no writable layer; it may not have been started",
+            sandbox_name
+        )));
+    }
+
+    let snapshot_path = snapshot_path(&menv_path, &config_file, sandbox_name, snapshot_name);
+    if snapshot_path.exists()
+        || db::get_snapshot(&pool, snapshot_name, sandbox_name, &config_file)
+            .await?
+            .is_some()
+    {
+        return Err(MicrosandboxError::SnapshotExists(
+            snapshot_name.to_string(),
+            sandbox_name.to_string(),
+        ));
+    }
+    if let Some(parent) = snapshot_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    // Quiesce a running sandbox while its layer is copied
+    let pause = sandbox_status(&pool, sandbox_name, &config_file)
+        .await?
+        .as_deref()
+        == Some(SANDBOX_STATUS_RUNNING);
+    if pause {
+        orchestra::pause(
+            vec![sandbox_name.to_string()],
+            project_dir,
+            Some(&config_file),
+        )
+        .await?;
+    }
+
+    let copied = copy_layer(&rw_path, &snapshot_path).await;
+
+    if pause {
+        orchestra::resume(
+            vec![sandbox_name.to_string()],
+            project_dir,
+            Some(&config_file),
+        )
+        .await?;
+    }
+    copied?;
+
+    let size_bytes = layer_size(&snapshot_path);
+    match db::save_snapshot(&pool, snapshot_name, sandbox_name, &config_file, size_bytes).await {
+        Ok(snapshot) => {
+            tracing::info!(
+                "took snapshot {} of sandbox {} ({} bytes)",
+                snapshot_name,
+                sandbox_name,
+                size_bytes
+            );
+            Ok(snapshot)
+        }
+        Err(e) => {
+            let _ = fs::remove_dir_all(&snapshot_path).await;
+            Err(e)
+        }
+    }
+}
+
+/// Restores a sandbox's writable layer from one of its snapshots.
+///
+/// Changes made since the snapshot was taken are discarded. The sandbox must be stopped, since
+/// the layer can't be swapped out from under a running microVM; start it again to use the
+/// restored state. The snapshot itself is kept, so it can be restored again.
+///
+/// ## Arguments
+///
+/// * `sandbox_name` - Name of the sandbox to restore
+/// * `snapshot_name` - Name of the snapshot to restore it from
+/// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
+/// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
+pub async fn restore(
+    sandbox_name: &str,
+    snapshot_name: &str,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<SandboxSnapshot> {
+    let (menv_path, config_file, pool) =
+        open_sandbox_db(sandbox_name, project_dir, config_file).await?;
+
+    let snapshot = db::get_snapshot(&pool, snapshot_name, sandbox_name, &config_file)
+        .await?
+        .ok_or_else(|| {
+            MicrosandboxError::SnapshotNotFound(snapshot_name.to_string(), sandbox_name.to_string())
+        })?;
+    let snapshot_path = snapshot_path(&menv_path, &config_file, sandbox_name, snapshot_name);
+    if !snapshot_path.exists() {
+        return Err(MicrosandboxError::PathNotFound(format!(
+            "snapshot '{}' of sandbox '{}' is missing its files at {}",
+            snapshot_name,
+            sandbox_name,
+            snapshot_path.display()
+        )));
+    }
+
+    if matches!(
+        sandbox_status(&pool, sandbox_name, &config_file)
+            .await?
+            .as_deref(),
+        Some(SANDBOX_STATUS_RUNNING | SANDBOX_STATUS_PAUSED)
+    ) {
+        return Err(MicrosandboxError::SandboxRunning(sandbox_name.to_string()));
+    }
+
+    // Copy next to the current layer first, so a failed copy leaves it untouched
+    let rw_path = rw_layer_path(&menv_path, &config_file, sandbox_name);
+    let staged_path = rw_path.with_file_name(format!(".{}.restore", sandbox_name));
+    if let Some(parent) = rw_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    copy_layer(&snapshot_path, &staged_path).await?;
+
+    if rw_path.exists() {
+        fs::remove_dir_all(&rw_path).await?;
+    }
+    fs::rename(&staged_path, &rw_path).await?;
+
+    tracing::info!(
+        "restored sandbox {} from snapshot {}",
+        sandbox_name,
+        snapshot_name
+    );
+
+    Ok(snapshot)
+}
+
+/// Lists the snapshots of a sandbox, oldest first.
+///
+/// ## Arguments
+///
+/// * `sandbox_name` - Name of the sandbox whose snapshots to list
+/// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
+/// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
+pub async fn list(
+    sandbox_name: &str,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<Vec<SandboxSnapshot>> {
+    let (_, config_file, pool) = open_sandbox_db(sandbox_name, project_dir, config_file).await?;
+    db::get_snapshots(&pool, sandbox_name, &config_file).await
+}
+
+/// Deletes a snapshot of a sandbox.
+///
+/// ## Arguments
+///
+/// * `sandbox_name` - Name of the sandbox the snapshot was taken of
+/// * `snapshot_name` - Name of the snapshot to delete
+/// * `project_dir` - Optional path to the project directory. If None, defaults to current directory
+/// * `config_file` - Optional path to the Microsandbox config file. If None, uses default filename
+pub async fn delete(
+    sandbox_name: &str,
+    snapshot_name: &str,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<()> {
+    let (menv_path, config_file, pool) =
+        open_sandbox_db(sandbox_name, project_dir, config_file).await?;
+
+    if db::get_snapshot(&pool, snapshot_name, sandbox_name, &config_file)
+        .await?
+        .is_none()
+    {
+        return Err(MicrosandboxError::SnapshotNotFound(
+            snapshot_name.to_string(),
+            sandbox_name.to_string(),
+        ));
+    }
+
+    let snapshot_path = snapshot_path(&menv_path, &config_file, sandbox_name, snapshot_name);
+    if snapshot_path.exists() {
+        fs::remove_dir_all(&snapshot_path).await?;
+    }
+    db::delete_snapshots(&pool, Some(snapshot_name), sandbox_name, &config_file).await
+}
+
+/// Copies a writable layer to a new path, replacing anything already there.
+///
+/// Ownership, modes and the extended attributes that hold the guest's file stats are kept. A
+/// partial copy is removed if the copy fails.
+pub async fn copy_layer(source: &Path, target: &Path) -> MicrosandboxResult<()> {
+    if target.exists() {
+        fs::remove_dir_all(target).await?;
+    }
+
+    let output = Command::new("cp")
+        .arg("-a")
+        .arg(source)
+        .arg(target)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let _ = fs::remove_dir_all(target).await;
+        return Err(MicrosandboxError::Io(std::io::Error::other(format!(
+            "failed to copy layer {} to {}: {}",
+            source.display(),
+            target.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))));
+    }
+
+    Ok(())
+}
+
+/// Gets the path a snapshot of a sandbox is stored at.
+pub fn snapshot_path(
+    menv_path: &Path,
+    config_file: &str,
+    sandbox_name: &str,
+    snapshot_name: &str,
+) -> PathBuf {
+    menv_path
+        .join(SNAPSHOTS_SUBDIR)
+        .join(config_file)
+        .join(sandbox_name)
+        .join(snapshot_name)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Loads the config, checks it defines the sandbox, and opens the project's sandbox database.
+///
+/// Returns the path of the project's menv directory, the config file name, and the database pool.
+async fn open_sandbox_db(
+    sandbox_name: &str,
+    project_dir: Option<&Path>,
+    config_file: Option<&str>,
+) -> MicrosandboxResult<(PathBuf, String, Pool<Sqlite>)> {
+    let (config, canonical_project_dir, config_file) =
+        config::load_config(project_dir, config_file).await?;
+
+    if !config.get_sandboxes().contains_key(sandbox_name) {
+        return Err(MicrosandboxError::SandboxNotFoundInConfig(
+            sandbox_name.to_string(),
+            canonical_project_dir.join(&config_file),
+        ));
+    }
+
+    let menv_path = canonical_project_dir.join(MICROSANDBOX_ENV_DIR);
+    menv::ensure_menv_files(&menv_path).await?;
+
+    let db_path = menv_path.join(SANDBOX_DB_FILENAME);
+    let pool = db::get_or_create_pool(&db_path, &db::SANDBOX_DB_MIGRATOR).await?;
+
+    Ok((menv_path, config_file, pool))
+}
+
+/// Gets the recorded status of a sandbox, if it has ever been started.
+async fn sandbox_status(
+    pool: &Pool<Sqlite>,
+    sandbox_name: &str,
+    config_file: &str,
+) -> MicrosandboxResult<Option<String>> {
+    Ok(db::get_sandbox(pool, sandbox_name, config_file)
+        .await?
+        .map(|sandbox| sandbox.status))
+}
+
+/// Gets the path of a sandbox's writable layer.
+fn rw_layer_path(menv_path: &Path, config_file: &str, sandbox_name: &str) -> PathBuf {
+    menv_path
+        .join(RW_SUBDIR)
+        .join(config_file)
+        .join(sandbox_name)
+}
+
+/// Adds up the sizes of the files in a layer.
+fn layer_size(layer_path: &Path) -> u64 {
+    WalkDir::new(layer_path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Checks that a snapshot name is safe to use as a directory name.
+fn validate_snapshot_name(name: &str) -> MicrosandboxResult<()> {
+    if name.is_empty() || name.len() > MAX_SNAPSHOT_NAME_LEN {
+        return Err(MicrosandboxError::InvalidArgument(format!(
+            "snapshot name must be between 1 and {} characters",
+            MAX_SNAPSHOT_NAME_LEN
+        )));
+    }
+
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(MicrosandboxError::InvalidArgument(format!(
+            "invalid snapshot name '{}': only alphanumeric characters, hyphens, or underscores are allowed",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Creates a project defining `app`, with a writable layer holding `state.txt`
+    async fn project_with_layer(contents: &str) -> anyhow::Result<(tempfile::TempDir, PathBuf)> {
+        let project = tempdir()?;
+        fs::write(
+            project.path().join("Sandboxfile"),
+            "sandboxes:\n  app:\n    image: alpine\n",
+        )
+        .await?;
+
+        let rw_path = project
+            .path()
+            .join(MICROSANDBOX_ENV_DIR)
+            .join(RW_SUBDIR)
+            .join("Sandboxfile")
+            .join("app");
+        fs::create_dir_all(&rw_path).await?;
+        fs::write(rw_path.join("state.txt"), contents).await?;
+
+        Ok((project, rw_path))
+    }
+
+    #[tokio::test]
+    async fn test_restore_brings_back_the_snapshotted_layer() -> anyhow::Result<()> {
+        let (project, rw_path) = project_with_layer("prepared").await?;
+        let project_dir = Some(project.path());
+
+        let taken = create("app", "prepared", project_dir, Some("Sandboxfile")).await?;
+        assert_eq!(taken.size_bytes, "prepared".len() as u64);
+
+        // Names are unique per sandbox
+        assert!(matches!(
+            create("app", "prepared", project_dir, Some("Sandboxfile")).await,
+            Err(MicrosandboxError::SnapshotExists(..))
+        ));
+
+        // Changes after the snapshot are discarded by a restore, as often as it's restored
+        for _ in 0..2 {
+            fs::write(rw_path.join("state.txt"), "modified").await?;
+            fs::write(rw_path.join("scratch.txt"), "scratch").await?;
+
+            restore("app", "prepared", project_dir, Some("Sandboxfile")).await?;
+            assert_eq!(
+                fs::read_to_string(rw_path.join("state.txt")).await?,
+                "prepared"
+            );
+            assert!(!rw_path.join("scratch.txt").exists());
+        }
+
+        let names: Vec<_> = list("app", project_dir, Some("Sandboxfile"))
+            .await?
+            .into_iter()
+            .map(|snapshot| snapshot.name)
+            .collect();
+        assert_eq!(names, vec!["prepared"]);
+
+        delete("app", "prepared", project_dir, Some("Sandboxfile")).await?;
+        assert!(matches!(
+            restore("app", "prepared", project_dir, Some("Sandboxfile")).await,
+            Err(MicrosandboxError::SnapshotNotFound(..))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_rejects_unknown_sandboxes_and_bad_names() -> anyhow::Result<()> {
+        let (project, _) = project_with_layer("prepared").await?;
+        let project_dir = Some(project.path());
+
+        assert!(matches!(
+            create("web", "prepared", project_dir, Some("Sandboxfile")).await,
+            Err(MicrosandboxError::SandboxNotFoundInConfig(..))
+        ));
+        assert!(matches!(
+            create("app", "../escape", project_dir, Some("Sandboxfile")).await,
+            Err(MicrosandboxError::InvalidArgument(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+-- Drop config hash column
+ALTER TABLE sandboxes DROP COLUMN config_hash;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+-- Add content hash of the config file, used when mtimes are ambiguous
+ALTER TABLE sandboxes ADD COLUMN config_hash TEXT;
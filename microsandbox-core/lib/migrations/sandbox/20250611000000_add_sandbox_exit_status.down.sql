@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+-- Drop exit status column
+ALTER TABLE sandboxes DROP COLUMN exit_status;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+-- Add how the sandbox last exited, stored as JSON
+ALTER TABLE sandboxes ADD COLUMN exit_status TEXT;
@@ -0,0 +1,22 @@
+-- Add down migration script here
+
+-- Recreate sandbox_metrics without the microVM PID
+CREATE TABLE sandbox_metrics_old (
+    id INTEGER PRIMARY KEY,
+    sandbox_id INTEGER NOT NULL,
+    timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+    cpu_usage_percent REAL,
+    memory_usage_bytes INTEGER,
+    disk_usage_bytes INTEGER,
+    FOREIGN KEY(sandbox_id) REFERENCES sandbox(id)
+);
+
+INSERT INTO sandbox_metrics_old (id, sandbox_id, timestamp, cpu_usage_percent, memory_usage_bytes, disk_usage_bytes)
+SELECT id, sandbox_id, timestamp, cpu_usage_percent, memory_usage_bytes, disk_usage_bytes
+FROM sandbox_metrics;
+
+DROP TABLE sandbox_metrics;
+ALTER TABLE sandbox_metrics_old RENAME TO sandbox_metrics;
+
+-- Create index
+CREATE INDEX IF NOT EXISTS idx_sandbox_metrics_sandbox_id_timestamp ON sandbox_metrics(sandbox_id, timestamp);
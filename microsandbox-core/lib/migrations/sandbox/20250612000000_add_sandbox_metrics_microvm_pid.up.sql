@@ -0,0 +1,25 @@
+-- Add up migration script here
+
+-- Recreate sandbox_metrics with the PID of the microVM each metric was sampled from, and a
+-- foreign key to the sandboxes table (it referenced a nonexistent `sandbox` table)
+CREATE TABLE sandbox_metrics_new (
+    id INTEGER PRIMARY KEY,
+    sandbox_id INTEGER NOT NULL,
+    microvm_pid INTEGER,
+    timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+    cpu_usage_percent REAL,
+    memory_usage_bytes INTEGER,
+    disk_usage_bytes INTEGER,
+    FOREIGN KEY(sandbox_id) REFERENCES sandboxes(id) ON DELETE CASCADE
+);
+
+INSERT INTO sandbox_metrics_new (id, sandbox_id, timestamp, cpu_usage_percent, memory_usage_bytes, disk_usage_bytes)
+SELECT id, sandbox_id, timestamp, cpu_usage_percent, memory_usage_bytes, disk_usage_bytes
+FROM sandbox_metrics;
+
+DROP TABLE sandbox_metrics;
+ALTER TABLE sandbox_metrics_new RENAME TO sandbox_metrics;
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_sandbox_metrics_sandbox_id_timestamp ON sandbox_metrics(sandbox_id, timestamp);
+CREATE INDEX IF NOT EXISTS idx_sandbox_metrics_microvm_pid_timestamp ON sandbox_metrics(microvm_pid, timestamp);
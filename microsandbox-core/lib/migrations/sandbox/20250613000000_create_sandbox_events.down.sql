@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+-- Drop index first
+DROP INDEX IF EXISTS idx_sandbox_events_sandbox;
+
+-- Drop sandbox_events table
+DROP TABLE IF EXISTS sandbox_events;
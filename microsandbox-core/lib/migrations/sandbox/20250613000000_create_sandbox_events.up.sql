@@ -0,0 +1,15 @@
+-- Add up migration script here
+
+-- Create sandbox_events table. Events are keyed by name and config file rather than by
+-- sandbox id, so the history of a sandbox outlives its row in the sandboxes table
+CREATE TABLE IF NOT EXISTS sandbox_events (
+    id INTEGER PRIMARY KEY,
+    timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+    sandbox_name TEXT NOT NULL,
+    config_file TEXT NOT NULL,
+    event_kind TEXT NOT NULL,
+    detail TEXT
+);
+
+-- Create index
+CREATE INDEX IF NOT EXISTS idx_sandbox_events_sandbox ON sandbox_events(sandbox_name, config_file, id);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+-- Drop OOM kills column
+ALTER TABLE sandboxes DROP COLUMN oom_kills;
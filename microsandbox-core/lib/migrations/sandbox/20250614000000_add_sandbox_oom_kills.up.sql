@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+-- Add how many times the sandbox's microVM has been killed by the OOM killer, over all runs
+ALTER TABLE sandboxes ADD COLUMN oom_kills INTEGER NOT NULL DEFAULT 0;
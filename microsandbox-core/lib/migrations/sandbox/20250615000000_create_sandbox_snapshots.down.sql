@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+-- Drop sandbox_snapshots table
+DROP TABLE IF EXISTS sandbox_snapshots;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+
+-- Create sandbox_snapshots table. Like events, snapshots are keyed by sandbox name and config
+-- file, since a stopped sandbox may have no row in the sandboxes table
+CREATE TABLE IF NOT EXISTS sandbox_snapshots (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    sandbox_name TEXT NOT NULL,
+    config_file TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (sandbox_name, config_file, name)
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+-- Drop log path column
+ALTER TABLE sandboxes DROP COLUMN log_path;
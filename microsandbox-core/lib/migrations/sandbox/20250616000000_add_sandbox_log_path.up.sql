@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+-- Add the path of the log file the sandbox's microVM output is written to, if any
+ALTER TABLE sandboxes ADD COLUMN log_path TEXT;
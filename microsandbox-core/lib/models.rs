@@ -1,6 +1,7 @@
 //! Database models for Microsandbox.
 
 use chrono::{DateTime, Utc};
+use microsandbox_utils::ExitStatus;
 
 //--------------------------------------------------------------------------------------------------
 // Types: Sandbox
@@ -21,6 +22,9 @@ pub struct Sandbox {
     /// The last modified date and time of the Microsandbox configuration file.
     pub config_last_modified: DateTime<Utc>,
 
+    /// The SHA-256 hash of the Microsandbox configuration file, if recorded.
+    pub config_hash: Option<String>,
+
     /// The status of the sandbox.
     pub status: String,
 
@@ -33,11 +37,97 @@ pub struct Sandbox {
     /// The paths to the root filesystems for the sandbox.
     pub rootfs_paths: String,
 
+    /// The log file the sandbox's microVM output is written to, if it's written to a file.
+    pub log_path: Option<String>,
+
     /// When the sandbox was created
     pub created_at: DateTime<Utc>,
 
     /// When the sandbox was last modified
     pub modified_at: DateTime<Utc>,
+
+    /// How the sandbox's microVM last exited, if it has exited since it was started.
+    pub exit_status: Option<ExitStatus>,
+}
+
+/// The kind of a sandbox lifecycle event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SandboxEventKind {
+    /// The sandbox's microVM was started.
+    Start,
+
+    /// The sandbox's microVM was started again without having stopped cleanly, e.g. after
+    /// a crash.
+    Restart,
+
+    /// The sandbox's microVM was stopped, or exited successfully on its own.
+    Stop,
+
+    /// The sandbox's microVM exited on its own with a failure.
+    Crash,
+}
+
+/// A recorded transition in the lifecycle of a sandbox.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SandboxEvent {
+    /// The unique identifier for the event, increasing in the order events were recorded.
+    pub id: i64,
+
+    /// When the event was recorded.
+    pub timestamp: DateTime<Utc>,
+
+    /// The name of the sandbox.
+    pub sandbox_name: String,
+
+    /// The Microsandbox configuration filename that defines the sandbox.
+    pub config_file: String,
+
+    /// What happened.
+    pub kind: SandboxEventKind,
+
+    /// More about what happened, e.g. how the microVM exited.
+    pub detail: Option<String>,
+}
+
+/// Which sandbox lifecycle events to query. Unset fields match every event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SandboxEventFilter {
+    /// Only events of the sandbox with this name.
+    pub sandbox_name: Option<String>,
+
+    /// Only events of sandboxes defined in this configuration file.
+    pub config_file: Option<String>,
+
+    /// Only events of this kind.
+    pub kind: Option<SandboxEventKind>,
+
+    /// Only events recorded at or after this time.
+    pub since: Option<DateTime<Utc>>,
+
+    /// At most this many events, the most recent ones.
+    pub limit: Option<u32>,
+}
+
+/// A saved copy of a sandbox's writable layer that the sandbox can be restored to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SandboxSnapshot {
+    /// The unique identifier for the snapshot.
+    pub id: i64,
+
+    /// The name of the snapshot, unique among the sandbox's snapshots.
+    pub name: String,
+
+    /// The name of the sandbox the snapshot was taken of.
+    pub sandbox_name: String,
+
+    /// The Microsandbox configuration filename that defines the sandbox.
+    pub config_file: String,
+
+    /// The total size of the files in the snapshot.
+    pub size_bytes: u64,
+
+    /// When the snapshot was taken.
+    pub created_at: DateTime<Utc>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -213,3 +303,30 @@ pub struct Layer {
     /// Time when the record was last modified
     pub modified_at: DateTime<Utc>,
 }
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl SandboxEventKind {
+    /// Returns how the kind is stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SandboxEventKind::Start => "start",
+            SandboxEventKind::Restart => "restart",
+            SandboxEventKind::Stop => "stop",
+            SandboxEventKind::Crash => "crash",
+        }
+    }
+
+    /// Parses a kind as stored in the database.
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "start" => Some(SandboxEventKind::Start),
+            "restart" => Some(SandboxEventKind::Restart),
+            "stop" => Some(SandboxEventKind::Stop),
+            "crash" => Some(SandboxEventKind::Crash),
+            _ => None,
+        }
+    }
+}
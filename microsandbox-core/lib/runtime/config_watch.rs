@@ -0,0 +1,326 @@
+//! Watching a sandbox's config file for changes.
+//!
+//! A monitor with config watching enabled restarts its sandbox when the config file changes, so
+//! edits to a sandbox's definition take effect without stopping and starting it by hand. The
+//! watcher only asks for the restart: it stops the sandbox through the supervisor's usual
+//! `SIGTERM` handling and flags a [`RestartRequest`], and whoever launched the supervisor starts
+//! the sandbox again once it has shut down, with the config resolved afresh.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
+use notify::{RecursiveMode, Watcher};
+use serde_yaml::Value;
+use tokio::sync::mpsc;
+
+use crate::{MicrosandboxError, MicrosandboxResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How long the config file has to go without changing before a change is acted on
+///
+/// Editors often write a file in several steps, and a save can be followed by another within
+/// moments, so changes are only looked at once the file has settled.
+pub const DEFAULT_CONFIG_WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Which changes to a watched config file restart the sandbox
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConfigRestartPolicy {
+    /// Never restart; changes to the sandbox's definition are only logged
+    Never,
+
+    /// Restart when the sandbox's own definition changes, ignoring edits to other sandboxes,
+    /// comments and formatting
+    #[default]
+    OnChange,
+
+    /// Restart on any change to the config's contents, e.g. for sandboxes that depend on
+    /// other parts of the config
+    Always,
+}
+
+/// Shared flag recording that a config change asked for the sandbox to be restarted
+#[derive(Debug, Clone, Default)]
+pub struct RestartRequest(Arc<AtomicBool>);
+
+/// Watches a config file and stops the supervisor when the sandbox should restart
+#[derive(Clone)]
+pub(crate) struct ConfigWatcher {
+    /// Path of the config file
+    config_path: PathBuf,
+
+    /// Name of the sandbox whose definition is compared
+    sandbox_name: String,
+
+    /// Which changes restart the sandbox
+    policy: ConfigRestartPolicy,
+
+    /// How long the file has to settle before a change is acted on
+    debounce: Duration,
+
+    /// Flag set when a restart is asked for
+    restart: RestartRequest,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RestartRequest {
+    /// Whether a config change asked for the sandbox to be restarted
+    pub fn is_requested(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Records that the sandbox should be restarted
+    fn request(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ConfigWatcher {
+    /// Creates a watcher for the config file at `config_path`
+    pub(crate) fn new(
+        config_path: PathBuf,
+        sandbox_name: String,
+        policy: ConfigRestartPolicy,
+        debounce: Duration,
+        restart: RestartRequest,
+    ) -> Self {
+        Self {
+            config_path,
+            sandbox_name,
+            policy,
+            debounce,
+            restart,
+        }
+    }
+
+    /// Watches the config file until a change restarts the sandbox
+    ///
+    /// The file's directory is watched rather than the file itself, so editors that save by
+    /// replacing the file don't end the watch. A config that fails to parse is skipped until the
+    /// next change, leaving the sandbox running.
+    pub(crate) async fn run(self) -> MicrosandboxResult<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let file_name = self.config_path.file_name().map(ToOwned::to_owned);
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = event {
+                    if event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == file_name.as_deref())
+                    {
+                        let _ = tx.send(());
+                    }
+                }
+            })
+            .map_err(watch_error)?;
+        let watch_dir = self
+            .config_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        watcher
+            .watch(watch_dir, RecursiveMode::NonRecursive)
+            .map_err(watch_error)?;
+
+        let mut current = read_config(&self.config_path).await?;
+
+        while rx.recv().await.is_some() {
+            // Wait for the file to settle
+            while let Ok(Some(())) = tokio::time::timeout(self.debounce, rx.recv()).await {}
+
+            let updated = match read_config(&self.config_path).await {
+                Ok(updated) => updated,
+                Err(e) => {
+                    tracing::warn!(error = %e, "ignoring config change that can't be read");
+                    continue;
+                }
+            };
+
+            if self.should_restart(&current, &updated) {
+                tracing::info!(
+                    config = %self.config_path.display(),
+                    "config changed, restarting sandbox {}",
+                    self.sandbox_name
+                );
+                self.restart.request();
+                signal::kill(Pid::this(), Signal::SIGTERM)?;
+                return Ok(());
+            }
+
+            current = updated;
+        }
+
+        Ok(())
+    }
+
+    /// Decides whether a change from `before` to `after` restarts the sandbox
+    fn should_restart(&self, before: &Value, after: &Value) -> bool {
+        let definition = sandbox_definition(after, &self.sandbox_name);
+        let changed = sandbox_definition(before, &self.sandbox_name) != definition;
+
+        // A sandbox removed from the config is left for `apply` to stop
+        if definition.is_none() {
+            if changed {
+                tracing::warn!(
+                    "sandbox {} was removed from the config, not restarting it",
+                    self.sandbox_name
+                );
+            }
+            return false;
+        }
+
+        match self.policy {
+            ConfigRestartPolicy::Never => {
+                if changed {
+                    tracing::info!(
+                        "definition of sandbox {} changed, not restarting it",
+                        self.sandbox_name
+                    );
+                }
+                false
+            }
+            ConfigRestartPolicy::OnChange => changed,
+            ConfigRestartPolicy::Always => before != after,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FromStr for ConfigRestartPolicy {
+    type Err = MicrosandboxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Self::Never),
+            "on-change" => Ok(Self::OnChange),
+            "always" => Ok(Self::Always),
+            _ => Err(MicrosandboxError::InvalidArgument(format!(
+                "invalid config restart policy '{}': expected never, on-change or always",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ConfigRestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Never => write!(f, "never"),
+            Self::OnChange => write!(f, "on-change"),
+            Self::Always => write!(f, "always"),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Reads and parses a config file
+async fn read_config(config_path: &Path) -> MicrosandboxResult<Value> {
+    let contents = tokio::fs::read_to_string(config_path).await?;
+    Ok(serde_yaml::from_str(&contents)?)
+}
+
+/// Gets the definition of a sandbox from a parsed config
+fn sandbox_definition<'a>(config: &'a Value, sandbox_name: &str) -> Option<&'a Value> {
+    config.get("sandboxes")?.get(sandbox_name)
+}
+
+/// Converts an error setting up the watch
+fn watch_error(error: notify::Error) -> MicrosandboxError {
+    MicrosandboxError::custom(anyhow::anyhow!("failed to watch config file: {}", error))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watcher(policy: ConfigRestartPolicy) -> ConfigWatcher {
+        ConfigWatcher::new(
+            PathBuf::from("Sandboxfile"),
+            "app".to_string(),
+            policy,
+            DEFAULT_CONFIG_WATCH_DEBOUNCE,
+            RestartRequest::default(),
+        )
+    }
+
+    fn config(yaml: &str) -> Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_only_changes_to_the_sandbox_restart_it_by_default() {
+        let before = config(
+            "sandboxes:\n  app:\n    image: python\n    memory: 512\n  db:\n    image: postgres\n",
+        );
+        let reformatted = config(
+            "# a comment\nsandboxes:\n  db: { image: postgres }\n  app: { memory: 512, image: python }\n",
+        );
+        let other_sandbox = config(
+            "sandboxes:\n  app:\n    image: python\n    memory: 512\n  db:\n    image: mysql\n",
+        );
+        let own_definition = config(
+            "sandboxes:\n  app:\n    image: python\n    memory: 1024\n  db:\n    image: postgres\n",
+        );
+        let removed = config("sandboxes:\n  db:\n    image: postgres\n");
+
+        let on_change = watcher(ConfigRestartPolicy::OnChange);
+        assert!(!on_change.should_restart(&before, &reformatted));
+        assert!(!on_change.should_restart(&before, &other_sandbox));
+        assert!(on_change.should_restart(&before, &own_definition));
+        assert!(!on_change.should_restart(&before, &removed));
+
+        let always = watcher(ConfigRestartPolicy::Always);
+        assert!(!always.should_restart(&before, &reformatted));
+        assert!(always.should_restart(&before, &other_sandbox));
+
+        let never = watcher(ConfigRestartPolicy::Never);
+        assert!(!never.should_restart(&before, &own_definition));
+    }
+
+    #[test]
+    fn test_restart_policy_round_trips_through_its_name() {
+        for policy in [
+            ConfigRestartPolicy::Never,
+            ConfigRestartPolicy::OnChange,
+            ConfigRestartPolicy::Always,
+        ] {
+            assert_eq!(
+                policy.to_string().parse::<ConfigRestartPolicy>().unwrap(),
+                policy
+            );
+        }
+        assert!("sometimes".parse::<ConfigRestartPolicy>().is_err());
+    }
+}
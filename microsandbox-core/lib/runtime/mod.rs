@@ -1,9 +1,11 @@
 //! Runtime components for the Microsandbox runtime.
 
+mod config_watch;
 mod monitor;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub use config_watch::{ConfigRestartPolicy, RestartRequest, DEFAULT_CONFIG_WATCH_DEBOUNCE};
 pub use monitor::*;
@@ -1,19 +1,43 @@
 use std::{
-    io::{Read, Write},
-    os::fd::BorrowedFd,
+    borrow::Cow,
+    future::Future,
+    io::{self, Read, Write},
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SecondsFormat, Utc};
+use encoding_rs::{Decoder, Encoding, UTF_8};
 use microsandbox_utils::{
-    ChildIo, MicrosandboxUtilsError, MicrosandboxUtilsResult, ProcessMonitor, RotatingLog,
-    LOG_SUFFIX,
+    log::{
+        LogLockPolicy, LogRotation, LogSink, LogSinkFactory, LogWriteAhead, OutputRing, RingPolicy,
+    },
+    term, ChildIo, ExitStatus, MicrosandboxUtilsError, MicrosandboxUtilsResult, ProcessMonitor,
+    RotatingLog, StdinRouter, DEFAULT_LOG_WRITE_AHEAD_SIZE, LOG_SUFFIX,
 };
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::{self, AccessFlags, Pid},
+};
+use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Sqlite};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::{io::AsyncReadExt, task::JoinHandle};
+use tracing::{Instrument, Span};
+
+use crate::{
+    management::db,
+    models::{SandboxEventFilter, SandboxEventKind},
+    vm::Rootfs,
+    MicrosandboxError, MicrosandboxResult,
+};
 
-use crate::{management::db, vm::Rootfs, MicrosandboxResult};
+use super::config_watch::{ConfigRestartPolicy, ConfigWatcher, RestartRequest};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -25,14 +49,58 @@ pub const SANDBOX_STATUS_RUNNING: &str = "RUNNING";
 /// The status of a sandbox when it is stopped
 pub const SANDBOX_STATUS_STOPPED: &str = "STOPPED";
 
+/// The status of a sandbox when its microVM is suspended
+pub const SANDBOX_STATUS_PAUSED: &str = "PAUSED";
+
+/// The status of a sandbox whose microVM exited abnormally without being stopped
+pub const SANDBOX_STATUS_CRASHED: &str = "CRASHED";
+
+/// Extension of the file marking a sandbox whose database state may be stale
+const DB_STALE_SUFFIX: &str = "db-stale";
+
+/// How long a running MicroVM may go without output before it is reported as silent
+pub const DEFAULT_SILENCE_THRESHOLD: Duration = Duration::from_secs(60);
+
+/// How long a stopping MicroVM has to exit after `SIGTERM` before it is sent `SIGKILL`
+pub const DEFAULT_STOP_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Size in bytes of the buffer the MicroVM's output is read into
+///
+/// Large enough to drain a full Linux pipe in one read.
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Time between checks for a stopping MicroVM having exited
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How many lifecycle events the sandbox database keeps, across all sandboxes
+const MAX_SANDBOX_EVENTS: u32 = 10_000;
+
+/// Shortest time between checks for a silent MicroVM
+const SILENCE_CHECK_MIN: Duration = Duration::from_millis(100);
+
+/// Longest time between checks for a silent MicroVM
+const SILENCE_CHECK_MAX: Duration = Duration::from_secs(5);
+
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
 
 /// A process monitor for MicroVMs
 pub struct MicroVmMonitor {
-    /// The database for tracking sandbox metrics and metadata
-    sandbox_db: Pool<Sqlite>,
+    /// The path of the database for tracking sandbox metrics and metadata
+    sandbox_db_path: PathBuf,
+
+    /// The database for tracking sandbox metrics and metadata, once it has been opened
+    sandbox_db: Option<Pool<Sqlite>>,
+
+    /// Whether database failures degrade to warnings instead of failing the sandbox
+    db_optional: bool,
+
+    /// The sandbox's metadata, kept in memory so it survives failed database writes
+    metadata: SandboxMetadata,
+
+    /// Whether the database may be out of date because a write to it failed
+    db_stale: bool,
 
     /// The name of the sandbox
     sandbox_name: String,
@@ -43,8 +111,8 @@ pub struct MicroVmMonitor {
     /// The last modified timestamp of the config file
     config_last_modified: DateTime<Utc>,
 
-    /// The supervisor PID
-    supervisor_pid: u32,
+    /// The content hash of the config file
+    config_hash: Option<String>,
 
     /// The MicroVM log path
     log_path: Option<PathBuf>,
@@ -58,8 +126,373 @@ pub struct MicroVmMonitor {
     /// original terminal settings for STDIN (set in TTY mode)
     original_term: Option<nix::sys::termios::Termios>,
 
-    /// Whether to forward output to stdout/stderr
-    forward_output: bool,
+    /// Which of the MicroVM's output streams are forwarded to stdout/stderr
+    forward_output: ForwardOutput,
+
+    /// Encoding of the MicroVM's output, transcoded to UTF-8 when forwarded
+    output_encoding: Option<&'static Encoding>,
+
+    /// Whether to stop the MicroVM when the forwarded output's consumer goes away
+    stop_on_broken_pipe: bool,
+
+    /// OOM score adjustment applied to the MicroVM process once it starts
+    oom_score_adj: Option<i32>,
+
+    /// Router that the MicroVM's stdin is registered with, instead of reading stdin directly
+    stdin_router: Option<StdinRouter>,
+
+    /// Size in bytes of the buffer that absorbs output while the log rotates
+    log_write_ahead_size: usize,
+
+    /// Size in bytes of the buffer each of the MicroVM's output streams is read into
+    read_buffer_size: usize,
+
+    /// Format that output is written to the log in
+    log_format: OutputLogFormat,
+
+    /// What to do when another monitor is already writing the log
+    log_lock_policy: LogLockPolicy,
+
+    /// When the log rotates and how many rotated segments are kept
+    log_rotation: LogRotation,
+
+    /// Creates the sink that output is written to, instead of the rotating log file
+    log_sink: Option<LogSinkFactory>,
+
+    /// In-memory buffer of the most recent output, if enabled
+    recent_output: Option<Arc<OutputRing>>,
+
+    /// Span that the monitor's diagnostics are recorded in
+    span: Span,
+
+    /// Tasks copying the MicroVM's output to the log
+    output_tasks: Vec<JoinHandle<()>>,
+
+    /// How long the MicroVM may go without output before it is reported as silent
+    silence_threshold: Duration,
+
+    /// When the running MicroVM last produced output
+    activity: Option<Arc<OutputActivity>>,
+
+    /// Task reporting when the MicroVM goes silent and when its output resumes
+    silence_watcher: Option<JoinHandle<()>>,
+
+    /// How often the MicroVM's CPU and memory use is sampled into the database, if at all
+    metrics_interval: Option<Duration>,
+
+    /// Task sampling the MicroVM's CPU and memory use
+    metrics_sampler: Option<JoinHandle<()>>,
+
+    /// Watcher that restarts the sandbox when its config file changes, if enabled
+    config_watcher: Option<ConfigWatcher>,
+
+    /// Task running the config watcher
+    config_watch_task: Option<JoinHandle<()>>,
+
+    /// Set when a config change asks for the sandbox to be restarted
+    restart_request: RestartRequest,
+
+    /// PID of the running MicroVM, until it is stopped or reaped
+    microvm_pid: Option<u32>,
+
+    /// Whether the MicroVM was reattached to, rather than spawned by this process
+    microvm_reattached: bool,
+
+    /// How long the MicroVM has to exit after `SIGTERM` before it is sent `SIGKILL`
+    stop_grace_period: Duration,
+
+    /// Whether the monitor stopped the MicroVM's current run, rather than it exiting on its own
+    stop_requested: bool,
+}
+
+/// Metadata the monitor records about its sandbox
+///
+/// The monitor writes it to the sandbox database as the sandbox starts, stops and exits, and
+/// keeps it in memory as well, so it stays available when the database can't be written.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SandboxMetadata {
+    /// Status of the sandbox, one of the `SANDBOX_STATUS_*` constants
+    pub status: String,
+
+    /// PID of the supervisor process
+    pub supervisor_pid: u32,
+
+    /// PID of the MicroVM process, once it has started
+    pub microvm_pid: Option<u32>,
+
+    /// Root filesystem paths, in the form stored in the database
+    pub rootfs_paths: Option<String>,
+
+    /// Log file the MicroVM's output is written to, unless it goes to another log sink
+    pub log_path: Option<PathBuf>,
+
+    /// How the MicroVM exited, once it has
+    pub exit_status: Option<ExitStatus>,
+}
+
+/// The state of a monitor that can be handed to a new supervisor process
+///
+/// Exported with [`MicroVmMonitor::export_state`] and restored with
+/// [`MicroVmMonitor::from_state`], so the supervisor binary can be upgraded without stopping
+/// its sandbox. Only what can cross a process boundary is kept:
+///
+/// - The database connection, stdin router and tracing span belong to the old process; the
+///   new monitor opens its own database connection and takes a new span.
+/// - The MicroVM's output pipes or pseudo-TTY are live file descriptors, which must be passed
+///   to the new process separately (e.g. with `SCM_RIGHTS` over a unix socket) and handed to
+///   [`MicroVmMonitor::reattach`].
+/// - The contents of the in-memory output buffer are lost; only its policy is kept.
+/// - A log sink factory is code, so a restored monitor writes to the rotating log file unless
+///   it is given the factory again.
+/// - The terminal settings saved before stdin was switched to raw mode can't be carried over.
+///   [`terminal_raw`](Self::terminal_raw) records that the terminal is in raw mode, but a
+///   restored monitor sees the raw settings as the original ones, so the caller has to restore
+///   the terminal itself once the sandbox stops.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonitorState {
+    /// The name of the sandbox
+    pub sandbox_name: String,
+
+    /// The config file for the sandbox
+    pub config_file: String,
+
+    /// The last modified timestamp of the config file
+    pub config_last_modified: DateTime<Utc>,
+
+    /// The content hash of the config file
+    pub config_hash: Option<String>,
+
+    /// The metadata recorded about the sandbox, including the supervisor and MicroVM PIDs
+    pub metadata: SandboxMetadata,
+
+    /// Whether the database may be out of date because a write to it failed
+    pub db_stale: bool,
+
+    /// Whether database failures degrade to warnings instead of failing the sandbox
+    pub db_optional: bool,
+
+    /// The root filesystem
+    pub rootfs: Rootfs,
+
+    /// The log directory
+    pub log_dir: PathBuf,
+
+    /// The MicroVM log path, if the MicroVM is running
+    pub log_path: Option<PathBuf>,
+
+    /// Whether stdin was switched to raw mode for a pseudo-TTY
+    pub terminal_raw: bool,
+
+    /// Which of the MicroVM's output streams are forwarded to stdout/stderr
+    pub forward_output: ForwardOutput,
+
+    /// Name of the encoding of the MicroVM's output, if not UTF-8
+    pub output_encoding: Option<String>,
+
+    /// Whether to stop the MicroVM when the forwarded output's consumer goes away
+    pub stop_on_broken_pipe: bool,
+
+    /// OOM score adjustment applied to the MicroVM process
+    pub oom_score_adj: Option<i32>,
+
+    /// Size in bytes of the buffer that absorbs output while the log rotates
+    pub log_write_ahead_size: usize,
+
+    /// Size in bytes of the buffer each of the MicroVM's output streams is read into
+    #[serde(default = "default_read_buffer_size")]
+    pub read_buffer_size: usize,
+
+    /// Format that output is written to the log in
+    pub log_format: OutputLogFormat,
+
+    /// What to do when another monitor is already writing the log
+    pub log_lock_policy: LogLockPolicy,
+
+    /// When the log rotates and how many rotated segments are kept
+    pub log_rotation: LogRotation,
+
+    /// Retention policy of the in-memory output buffer, if enabled
+    pub recent_output_policy: Option<RingPolicy>,
+
+    /// How long the MicroVM may go without output before it is reported as silent
+    pub silence_threshold: Duration,
+
+    /// How often the MicroVM's CPU and memory use is sampled into the database, if at all
+    pub metrics_interval: Option<Duration>,
+
+    /// How long the MicroVM has to exit after `SIGTERM` before it is sent `SIGKILL`
+    pub stop_grace_period: Duration,
+}
+
+/// A write of the sandbox's metadata to the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DbWrite {
+    /// The whole sandbox record
+    Record,
+
+    /// The sandbox's status
+    Status,
+
+    /// How the MicroVM exited
+    ExitStatus,
+}
+
+/// The parts of a monitor that are written to the sandbox database
+struct DbRecord<'a> {
+    sandbox_name: &'a str,
+    config_file: &'a str,
+    config_last_modified: &'a DateTime<Utc>,
+    config_hash: Option<&'a str>,
+    metadata: &'a SandboxMetadata,
+}
+
+/// How the MicroVM went down when its monitor was stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOutcome {
+    /// There was no running MicroVM, or it had already exited
+    NotRunning,
+
+    /// The MicroVM exited within the grace period after `SIGTERM`
+    Graceful,
+
+    /// The MicroVM was still running after the grace period and was sent `SIGKILL`
+    Killed,
+}
+
+/// Health of a MicroVM, as seen by its monitor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonitorHealth {
+    /// The MicroVM hasn't been started, or has been stopped
+    NotRunning,
+
+    /// The MicroVM is running and produced output within the silence threshold
+    Active {
+        /// Time since the last output, or since the start if there was none yet
+        idle: Duration,
+    },
+
+    /// The MicroVM is running but hasn't produced output for longer than the silence threshold
+    ///
+    /// This doesn't mean it is hung: plenty of guests work silently for long stretches.
+    Silent {
+        /// Time since the last output, or since the start if there was none yet
+        idle: Duration,
+    },
+
+    /// The MicroVM process has exited
+    Exited,
+}
+
+/// Samples the CPU and memory use of a process from `/proc`
+struct ProcessSampler {
+    pid: u32,
+
+    /// Start time of the process in clock ticks since boot, to tell when its PID is reused
+    start_time: u64,
+
+    /// CPU time the process had used at the previous sample, in clock ticks
+    cpu_ticks: u64,
+
+    /// When the previous sample was taken
+    sampled_at: Instant,
+}
+
+/// A sample of a process's CPU and memory use
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ProcessSample {
+    /// CPU use since the previous sample, as a percentage of one CPU
+    cpu_usage_percent: f32,
+
+    /// Resident set size in bytes
+    memory_usage_bytes: u64,
+}
+
+/// The fields of `/proc/<pid>/stat` the sampler uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ProcStat {
+    /// Process state, e.g. `R` for running or `Z` for zombie
+    state: char,
+
+    /// User and system CPU time, in clock ticks
+    cpu_ticks: u64,
+
+    /// Start time of the process, in clock ticks since boot
+    start_time: u64,
+}
+
+/// Tracks when a MicroVM last produced output on any of its streams
+#[derive(Debug)]
+struct OutputActivity {
+    /// PID of the MicroVM process
+    pid: u32,
+
+    /// When the MicroVM started
+    started_at: Instant,
+
+    /// Milliseconds from the start to the latest output
+    last_output_ms: AtomicU64,
+}
+
+/// Which of the MicroVM's output streams are forwarded to the supervisor's own stdout and
+/// stderr
+///
+/// Forwarded output is written to the log either way. A MicroVM on a pseudo-TTY has a single
+/// stream combining stdout and stderr, which is forwarded to stdout if `stdout` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(from = "ForwardOutputRepr")]
+pub struct ForwardOutput {
+    /// Whether the MicroVM's stdout, or its pseudo-TTY output, is forwarded to stdout
+    pub stdout: bool,
+
+    /// Whether the MicroVM's stderr is forwarded to stderr
+    pub stderr: bool,
+}
+
+/// How [`ForwardOutput`] is serialized, also accepting the single flag of older monitors
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ForwardOutputRepr {
+    Both(bool),
+    Streams { stdout: bool, stderr: bool },
+}
+
+/// Format of the MicroVM's output log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputLogFormat {
+    /// The raw output bytes, with stdout and stderr interleaved as they are read
+    #[default]
+    Raw,
+
+    /// One JSON object per line for each chunk of output, recording the stream it came from,
+    /// when it was read, and a sequence number
+    ///
+    /// Each chunk is stamped as soon as it is read, so consumers can reconstruct the order of
+    /// each stream even where the merged file interleaves the streams oddly. Stdout and stderr
+    /// are separate pipes read independently, so the sequence across streams only reflects the
+    /// order the chunks were read in; the OS doesn't guarantee it matches the order the guest
+    /// wrote them in.
+    JsonLines,
+}
+
+/// Encodes chunks of output for the log in the configured format
+#[derive(Clone)]
+struct OutputLogEncoder {
+    format: OutputLogFormat,
+
+    /// Name of the stream the chunks are read from
+    stream: &'static str,
+
+    /// Sequence counter shared by all the MicroVM's streams
+    seq: Arc<AtomicU64>,
+}
+
+/// Transcodes chunks of forwarded output to UTF-8, keeping partial characters between chunks
+///
+/// Output that is already UTF-8 is passed through byte for byte, so the forwarded copy matches
+/// the log even for binary output or characters split across reads.
+struct OutputDecoder {
+    decoder: Option<Decoder>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -68,6 +501,15 @@ pub struct MicroVmMonitor {
 
 impl MicroVmMonitor {
     /// Create a new MicroVM monitor
+    ///
+    /// The sandbox database at `sandbox_db_path` is opened when the monitor first writes to
+    /// it, as the MicroVM starts.
+    ///
+    /// Starting the monitor sets `SIGPIPE` to `SIG_IGN` for the whole process. Otherwise writing
+    /// forwarded output to a parent pipe whose reader has gone away would kill the supervisor
+    /// instead of failing with `BrokenPipe`, which the monitor handles by no longer forwarding.
+    /// Processes spawned through `std::process::Command` get the default disposition back, so
+    /// the MicroVM itself is unaffected.
     pub async fn new(
         supervisor_pid: u32,
         sandbox_db_path: impl AsRef<Path>,
@@ -76,91 +518,982 @@ impl MicroVmMonitor {
         config_last_modified: DateTime<Utc>,
         log_dir: impl Into<PathBuf>,
         rootfs: Rootfs,
-        forward_output: bool,
+        forward_output: ForwardOutput,
     ) -> MicrosandboxResult<Self> {
+        let span = tracing::info_span!("microvm_monitor", sandbox = %sandbox_name);
+
         Ok(Self {
-            supervisor_pid,
-            sandbox_db: db::get_pool(sandbox_db_path.as_ref()).await?,
+            sandbox_db_path: sandbox_db_path.as_ref().to_path_buf(),
+            sandbox_db: None,
+            db_optional: false,
+            db_stale: false,
+            metadata: SandboxMetadata {
+                supervisor_pid,
+                ..Default::default()
+            },
             sandbox_name,
             config_file,
             config_last_modified,
+            config_hash: None,
             log_path: None,
             log_dir: log_dir.into(),
             rootfs,
             original_term: None,
             forward_output,
+            output_encoding: None,
+            stop_on_broken_pipe: false,
+            oom_score_adj: None,
+            stdin_router: None,
+            log_write_ahead_size: DEFAULT_LOG_WRITE_AHEAD_SIZE,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            log_format: OutputLogFormat::default(),
+            log_lock_policy: LogLockPolicy::default(),
+            log_rotation: LogRotation::default(),
+            log_sink: None,
+            recent_output: None,
+            span,
+            output_tasks: Vec::new(),
+            silence_threshold: DEFAULT_SILENCE_THRESHOLD,
+            activity: None,
+            silence_watcher: None,
+            metrics_interval: None,
+            metrics_sampler: None,
+            config_watcher: None,
+            config_watch_task: None,
+            restart_request: RestartRequest::default(),
+            microvm_pid: None,
+            microvm_reattached: false,
+            stop_grace_period: DEFAULT_STOP_GRACE_PERIOD,
+            stop_requested: false,
         })
     }
 
+    /// Set the encoding of the MicroVM's output
+    ///
+    /// Forwarded output is transcoded from this encoding to UTF-8 before it is printed. The log
+    /// file always keeps the raw bytes. Defaults to UTF-8.
+    pub fn with_output_encoding(mut self, encoding: &'static Encoding) -> Self {
+        self.output_encoding = Some(encoding);
+        self
+    }
+
+    /// Set whether to stop the MicroVM when the forwarded output's consumer goes away
+    ///
+    /// When the parent's stdout or stderr is a pipe that gets closed (e.g. `| head`), the
+    /// monitor always stops forwarding to it and keeps logging to file. With this set, it also
+    /// sends `SIGTERM` to the MicroVM. Defaults to `false`.
+    pub fn with_stop_on_broken_pipe(mut self, stop: bool) -> Self {
+        self.stop_on_broken_pipe = stop;
+        self
+    }
+
+    /// Set the OOM score adjustment of the MicroVM process
+    ///
+    /// The value, from `-1000` (never kill) to `1000` (kill first), is written to
+    /// `/proc/<pid>/oom_score_adj` once the MicroVM starts, so the kernel's OOM killer picks
+    /// best-effort sandboxes before critical ones. Lowering the score below its current value
+    /// needs `CAP_SYS_RESOURCE`; if that fails the MicroVM keeps running with the default score.
+    pub fn with_oom_score_adj(mut self, oom_score_adj: i32) -> Self {
+        self.oom_score_adj = Some(oom_score_adj);
+        self
+    }
+
+    /// Set the size of the buffer that absorbs output while the log rotates
+    ///
+    /// Output is written to the log through an in-memory buffer of this many bytes, so the
+    /// MicroVM's output keeps being read while the log file is being rotated. Once the buffer
+    /// is full, reading waits for the log to catch up. Defaults to
+    /// [`DEFAULT_LOG_WRITE_AHEAD_SIZE`].
+    pub fn with_log_write_ahead_size(mut self, size: usize) -> Self {
+        self.log_write_ahead_size = size;
+        self
+    }
+
+    /// Set the size of the buffer each of the MicroVM's output streams is read into
+    ///
+    /// Larger buffers take more output per read, so a MicroVM producing a lot of output
+    /// costs fewer syscalls and log writes. A size of zero is treated as one byte. Defaults to
+    /// [`DEFAULT_READ_BUFFER_SIZE`].
+    pub fn with_read_buffer_size(mut self, size: usize) -> Self {
+        self.read_buffer_size = size.max(1);
+        self
+    }
+
+    /// Set the format that output is written to the log in
+    ///
+    /// Defaults to [`OutputLogFormat::Raw`]. Use [`OutputLogFormat::JsonLines`] when the order
+    /// of output within each stream matters, e.g. to debug output that depends on it.
+    pub fn with_log_format(mut self, format: OutputLogFormat) -> Self {
+        self.log_format = format;
+        self
+    }
+
+    /// Set what to do when another monitor is already writing the sandbox's log
+    ///
+    /// The monitor holds a lock on its log while it runs, so a second monitor for the same
+    /// sandbox, e.g. one that reattached, can't interleave its writes with the first. Defaults to
+    /// [`LogLockPolicy::Refuse`], which fails to start instead. Use [`LogLockPolicy::Wait`] to
+    /// take the log over once the other monitor exits.
+    pub fn with_log_lock_policy(mut self, policy: LogLockPolicy) -> Self {
+        self.log_lock_policy = policy;
+        self
+    }
+
+    /// Set when the sandbox's log rotates and which rotated segments are kept
+    ///
+    /// Caps the disk space a chatty sandbox's log takes: at most `max_files` rotated segments
+    /// of `max_size` bytes each are kept next to the active log, optionally gzip-compressed,
+    /// and older ones are deleted with a warning. Defaults to rotating at
+    /// [`DEFAULT_LOG_MAX_SIZE`](microsandbox_utils::DEFAULT_LOG_MAX_SIZE) and keeping one
+    /// uncompressed segment.
+    pub fn with_log_rotation(mut self, rotation: LogRotation) -> Self {
+        self.log_rotation = rotation;
+        self
+    }
+
+    /// Write output to the sinks `factory` creates instead of the rotating log file
+    ///
+    /// A sink is created each time the MicroVM starts, in place of the log file under
+    /// `log_dir`; e.g. a [`JsonLinesSink`](microsandbox_utils::log::JsonLinesSink) shipping it
+    /// on stdout. The [log format](Self::with_log_format) still applies to what the sink is
+    /// given, so a sink that adds its own framing wants the default raw format. Rotation and
+    /// locking options only apply to the log file.
+    pub fn with_log_sink(mut self, factory: LogSinkFactory) -> Self {
+        self.log_sink = Some(factory);
+        self
+    }
+
+    /// Record the monitor's diagnostics in `span`
+    ///
+    /// Lets a subscriber route a single sandbox's events to a separate destination. Defaults to
+    /// a `microvm_monitor` span carrying the sandbox name, recorded by the global subscriber.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    /// Route stdin to the MicroVM through a shared router
+    ///
+    /// Instead of copying the parent's stdin straight to the MicroVM, the monitor registers the
+    /// MicroVM's stdin with `router` under the sandbox name, and the MicroVM only receives input
+    /// while it is the router's active target. This lets a host running several interactive
+    /// sandboxes switch which one gets keystrokes.
+    pub fn with_stdin_router(mut self, router: StdinRouter) -> Self {
+        self.stdin_router = Some(router);
+        self
+    }
+
+    /// Set the content hash of the config file
+    ///
+    /// The hash is stored alongside `config_last_modified` and used to tell whether the config
+    /// changed when its modification time is ambiguous.
+    pub fn with_config_hash(mut self, config_hash: impl Into<String>) -> Self {
+        self.config_hash = Some(config_hash.into());
+        self
+    }
+
+    /// Keep the last `size` bytes of output in memory
+    ///
+    /// The buffered output can be read with [`recent_output`](Self::recent_output). Off by
+    /// default. Must be called before the monitor starts.
+    pub fn with_recent_output_size(mut self, size: usize) -> Self {
+        self.recent_output = Some(Arc::new(OutputRing::new(size)));
+        self
+    }
+
+    /// Set what output the in-memory output buffer retains
+    ///
+    /// Replaces the byte limit set with [`with_recent_output_size`](Self::with_recent_output_size),
+    /// enabling the buffer if it was off, so [`recent_output`](Self::recent_output) can be
+    /// bounded by lines and age as well. Must be called before the monitor starts.
+    pub fn with_recent_output_policy(mut self, policy: RingPolicy) -> Self {
+        self.recent_output = Some(Arc::new(OutputRing::with_policy(policy)));
+        self
+    }
+
+    /// Set how long the MicroVM may go without output before it is reported as silent
+    ///
+    /// Once the running MicroVM has produced no output for this long, [`health`](Self::health)
+    /// reports [`MonitorHealth::Silent`] and a warning is recorded in the monitor's span; an
+    /// info event follows when output resumes. Defaults to [`DEFAULT_SILENCE_THRESHOLD`].
+    pub fn with_silence_threshold(mut self, threshold: Duration) -> Self {
+        self.silence_threshold = threshold;
+        self
+    }
+
+    /// Restart the sandbox when its config file changes
+    ///
+    /// While the MicroVM runs, the config file at `config_path` is watched, and once it has gone
+    /// `debounce` without changing, its new contents are compared with the old according to
+    /// `policy`. A change that calls for a restart stops the sandbox by sending the supervisor
+    /// `SIGTERM` and sets the [`restart_request`](Self::restart_request), so whoever launched
+    /// the supervisor can start the sandbox again with the new config once it has shut down.
+    pub fn with_config_watch(
+        mut self,
+        config_path: impl Into<PathBuf>,
+        policy: ConfigRestartPolicy,
+        debounce: Duration,
+    ) -> Self {
+        self.config_watcher = Some(ConfigWatcher::new(
+            config_path.into(),
+            self.sandbox_name.clone(),
+            policy,
+            debounce,
+            self.restart_request.clone(),
+        ));
+        self
+    }
+
+    /// Returns the flag set when a config change asks for the sandbox to be restarted
+    ///
+    /// The flag is shared, so it can be kept after the monitor is handed to the supervisor.
+    pub fn restart_request(&self) -> RestartRequest {
+        self.restart_request.clone()
+    }
+
+    /// Sample the MicroVM's resource use every `interval`
+    ///
+    /// The MicroVM process's CPU and memory use is read from `/proc/<pid>/stat` and
+    /// `/proc/<pid>/statm` that often while it runs, and recorded in the `sandbox_metrics` table
+    /// keyed by its PID. Sampling stops when the process exits, and doesn't run at all without a
+    /// database or on systems without `/proc`. Off by default.
+    pub fn with_metrics_interval(mut self, interval: Duration) -> Self {
+        self.metrics_interval = Some(interval);
+        self
+    }
+
+    /// Keep the sandbox running when its database can't be opened or written
+    ///
+    /// With this set, e.g. on a full or read-only filesystem, database failures don't stop the
+    /// sandbox from starting: they are logged as warnings, the metadata is kept in memory (see
+    /// [`metadata`](Self::metadata)), and the sandbox is marked as having stale database state
+    /// until a later write succeeds. The mark is a file at
+    /// [`db_stale_marker_path`](Self::db_stale_marker_path) holding the in-memory metadata as
+    /// JSON, so reconcilers reading the database know not to trust it. Off by default, when
+    /// the first failed write fails the sandbox.
+    pub fn with_db_optional(mut self, db_optional: bool) -> Self {
+        self.db_optional = db_optional;
+        self
+    }
+
+    /// Set how long the MicroVM has to exit when the monitor is stopped
+    ///
+    /// Stopping the monitor sends `SIGTERM` to the MicroVM and, if it is still running after
+    /// this long, `SIGKILL`. Defaults to [`DEFAULT_STOP_GRACE_PERIOD`].
+    pub fn with_stop_grace_period(mut self, grace_period: Duration) -> Self {
+        self.stop_grace_period = grace_period;
+        self
+    }
+
+    /// Export the monitor's state, to hand the sandbox to a new supervisor process
+    ///
+    /// See [`MonitorState`] for what is left out. The old process should stop writing the log,
+    /// by dropping the monitor, before the new one reattaches; otherwise the new monitor has to
+    /// be set to wait for the log's lock with [`LogLockPolicy::Wait`].
+    pub fn export_state(&self) -> MonitorState {
+        MonitorState {
+            sandbox_name: self.sandbox_name.clone(),
+            config_file: self.config_file.clone(),
+            config_last_modified: self.config_last_modified,
+            config_hash: self.config_hash.clone(),
+            metadata: self.metadata.clone(),
+            db_stale: self.db_stale,
+            db_optional: self.db_optional,
+            rootfs: self.rootfs.clone(),
+            log_dir: self.log_dir.clone(),
+            log_path: self.log_path.clone(),
+            terminal_raw: self.original_term.is_some(),
+            forward_output: self.forward_output,
+            output_encoding: self.output_encoding.map(|e| e.name().to_string()),
+            stop_on_broken_pipe: self.stop_on_broken_pipe,
+            oom_score_adj: self.oom_score_adj,
+            log_write_ahead_size: self.log_write_ahead_size,
+            read_buffer_size: self.read_buffer_size,
+            log_format: self.log_format,
+            log_lock_policy: self.log_lock_policy,
+            log_rotation: self.log_rotation,
+            recent_output_policy: self.recent_output.as_ref().map(|ring| ring.policy()),
+            silence_threshold: self.silence_threshold,
+            metrics_interval: self.metrics_interval,
+            stop_grace_period: self.stop_grace_period,
+        }
+    }
+
+    /// Create a monitor from state exported by another supervisor process
+    ///
+    /// The monitor is configured as the exported one was and keeps its metadata, with
+    /// `supervisor_pid` replaced by the new supervisor's. Call [`reattach`](Self::reattach)
+    /// with the MicroVM's output file descriptors to resume monitoring it.
+    pub async fn from_state(
+        state: MonitorState,
+        supervisor_pid: u32,
+        sandbox_db_path: impl AsRef<Path>,
+    ) -> MicrosandboxResult<Self> {
+        let mut monitor = Self::new(
+            supervisor_pid,
+            sandbox_db_path,
+            state.sandbox_name,
+            state.config_file,
+            state.config_last_modified,
+            state.log_dir,
+            state.rootfs,
+            state.forward_output,
+        )
+        .await?
+        .with_db_optional(state.db_optional)
+        .with_stop_on_broken_pipe(state.stop_on_broken_pipe)
+        .with_log_write_ahead_size(state.log_write_ahead_size)
+        .with_read_buffer_size(state.read_buffer_size)
+        .with_log_format(state.log_format)
+        .with_log_lock_policy(state.log_lock_policy)
+        .with_log_rotation(state.log_rotation)
+        .with_silence_threshold(state.silence_threshold)
+        .with_stop_grace_period(state.stop_grace_period);
+
+        if let Some(config_hash) = state.config_hash {
+            monitor = monitor.with_config_hash(config_hash);
+        }
+        if let Some(oom_score_adj) = state.oom_score_adj {
+            monitor = monitor.with_oom_score_adj(oom_score_adj);
+        }
+        if let Some(interval) = state.metrics_interval {
+            monitor = monitor.with_metrics_interval(interval);
+        }
+        if let Some(policy) = state.recent_output_policy {
+            monitor = monitor.with_recent_output_policy(policy);
+        }
+        if let Some(label) = state.output_encoding {
+            let encoding = Encoding::for_label(label.as_bytes()).ok_or_else(|| {
+                MicrosandboxError::InvalidArgument(format!("unknown output encoding: {}", label))
+            })?;
+            monitor = monitor.with_output_encoding(encoding);
+        }
+
+        monitor.metadata = SandboxMetadata {
+            supervisor_pid,
+            ..state.metadata
+        };
+        monitor.db_stale |= state.db_stale;
+        monitor.log_path = state.log_path;
+
+        Ok(monitor)
+    }
+
+    /// Resume monitoring the MicroVM of a monitor created with [`from_state`](Self::from_state)
+    ///
+    /// `child_io` wraps the MicroVM's output file descriptors passed over from the old
+    /// process. The log is reopened for appending and the sandbox's record is rewritten with
+    /// the new supervisor's PID.
+    pub async fn reattach(&mut self, child_io: ChildIo) -> MicrosandboxResult<()> {
+        let microvm_pid = self.metadata.microvm_pid.ok_or_else(|| {
+            MicrosandboxError::InvalidArgument(format!(
+                "sandbox {} has no running microvm to reattach to",
+                self.sandbox_name
+            ))
+        })?;
+
+        self.start(microvm_pid, child_io).await?;
+        self.microvm_reattached = true;
+        Ok(())
+    }
+
+    /// Shut down the running MicroVM
+    ///
+    /// Sends `SIGTERM` and waits up to the grace period for the MicroVM to exit, then sends
+    /// `SIGKILL`. A MicroVM that has exited but not been reaped yet counts as exited; reaping
+    /// it is left to the supervisor. Called by [`stop`](ProcessMonitor::stop), which records
+    /// the outcome in the monitor's span.
+    pub async fn shutdown_microvm(&mut self) -> StopOutcome {
+        let Some(pid) = self.microvm_pid.take() else {
+            return StopOutcome::NotRunning;
+        };
+        let is_child = !self.microvm_reattached;
+        if microvm_exited(pid, is_child) {
+            return StopOutcome::NotRunning;
+        }
+
+        let span = &self.span;
+        if let Err(e) = signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM) {
+            tracing::warn!(parent: span, microvm_pid = pid, error = %e, "failed to send SIGTERM to microvm");
+        }
+
+        let deadline = Instant::now() + self.stop_grace_period;
+        while Instant::now() < deadline {
+            if microvm_exited(pid, is_child) {
+                return StopOutcome::Graceful;
+            }
+            tokio::time::sleep(STOP_POLL_INTERVAL.min(deadline - Instant::now())).await;
+        }
+        if microvm_exited(pid, is_child) {
+            return StopOutcome::Graceful;
+        }
+
+        if let Err(e) = signal::kill(Pid::from_raw(pid as i32), Signal::SIGKILL) {
+            tracing::warn!(parent: span, microvm_pid = pid, error = %e, "failed to send SIGKILL to microvm");
+        }
+        StopOutcome::Killed
+    }
+
+    /// Get the health of the MicroVM
+    ///
+    /// Tells a MicroVM that is alive but quiet ([`MonitorHealth::Silent`]) apart from one that
+    /// is producing output and from one that has exited.
+    pub fn health(&self) -> MonitorHealth {
+        match &self.activity {
+            Some(activity) => activity.health(self.silence_threshold),
+            None => MonitorHealth::NotRunning,
+        }
+    }
+
+    /// Get up to the last `max_bytes` bytes of output
+    ///
+    /// Returns an empty buffer if the in-memory output buffer is disabled.
+    pub fn recent_output(&self, max_bytes: usize) -> Vec<u8> {
+        self.recent_output
+            .as_ref()
+            .map(|ring| ring.tail(max_bytes))
+            .unwrap_or_default()
+    }
+
+    /// Get the log file the MicroVM's output is being written to
+    ///
+    /// `None` until the MicroVM has started, once it has been stopped, and when its output
+    /// goes to a log sink other than the rotating log file. The path is also recorded in the
+    /// sandbox's row in the database, where it is kept after the sandbox stops, for tools
+    /// outside this process.
+    pub fn log_path(&self) -> Option<&Path> {
+        self.log_path.as_deref()
+    }
+
+    /// Get the metadata the monitor has recorded about the sandbox
+    ///
+    /// Always up to date, even when writing it to the database failed.
+    pub fn metadata(&self) -> &SandboxMetadata {
+        &self.metadata
+    }
+
+    /// Check whether the sandbox database may be out of date for this sandbox
+    ///
+    /// Only ever `true` for a monitor set up with [`with_db_optional`](Self::with_db_optional),
+    /// after a database write failed and until one succeeds again.
+    pub fn is_db_stale(&self) -> bool {
+        self.db_stale
+    }
+
+    /// Returns the path of the file marking a sandbox whose database state may be stale
+    ///
+    /// The file sits next to the sandbox's log and exists only while the database may be out
+    /// of date. It holds the sandbox's [`SandboxMetadata`] as JSON.
+    pub fn db_stale_marker_path(log_dir: &Path, config_file: &str, sandbox_name: &str) -> PathBuf {
+        Self::log_path_for(log_dir, config_file, sandbox_name).with_extension(DB_STALE_SUFFIX)
+    }
+
+    /// Writes the sandbox's metadata to the database
+    ///
+    /// In db-optional mode a failed write is logged, the sandbox is marked as having stale
+    /// database state, and `Ok` is returned. Once the database is stale, every write saves
+    /// the whole record, so the first write that succeeds brings it up to date.
+    async fn persist(&mut self, write: DbWrite) -> MicrosandboxUtilsResult<()> {
+        let result = match self.open_db().await {
+            Ok(pool) => {
+                let record = DbRecord {
+                    sandbox_name: &self.sandbox_name,
+                    config_file: &self.config_file,
+                    config_last_modified: &self.config_last_modified,
+                    config_hash: self.config_hash.as_deref(),
+                    metadata: &self.metadata,
+                };
+                record.write(&pool, write, self.db_stale).await
+            }
+            Err(e) => Err(e),
+        };
+
+        let marker =
+            Self::db_stale_marker_path(&self.log_dir, &self.config_file, &self.sandbox_name);
+        match result {
+            Ok(()) => {
+                if self.db_stale {
+                    self.db_stale = false;
+                    if let Err(e) = tokio::fs::remove_file(&marker).await {
+                        if e.kind() != io::ErrorKind::NotFound {
+                            tracing::warn!(parent: &self.span, error = %e, "failed to remove stale database marker");
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Err(e) if self.db_optional => {
+                tracing::warn!(parent: &self.span, error = %e, ?write, "failed to write sandbox metadata to the database, keeping it in memory");
+                self.db_stale = true;
+                let contents = serde_json::to_vec(&self.metadata).unwrap_or_default();
+                if let Err(e) = tokio::fs::write(&marker, contents).await {
+                    tracing::warn!(parent: &self.span, error = %e, "failed to write stale database marker");
+                }
+                Ok(())
+            }
+            Err(e) => Err(MicrosandboxUtilsError::custom(e)),
+        }
+    }
+
+    /// Returns the sandbox database, opening it if it isn't open yet
+    ///
+    /// A database that can't be opened is tried again on the next call, so a db-optional
+    /// monitor starts using it once it becomes available.
+    async fn open_db(&mut self) -> MicrosandboxResult<Pool<Sqlite>> {
+        if let Some(pool) = &self.sandbox_db {
+            return Ok(pool.clone());
+        }
+
+        let pool = db::get_pool(&self.sandbox_db_path).await?;
+        self.sandbox_db = Some(pool.clone());
+        Ok(pool)
+    }
+
     fn restore_terminal_settings(&mut self) {
         if let Some(original_term) = self.original_term.take() {
-            if let Err(e) = nix::sys::termios::tcsetattr(
-                unsafe { BorrowedFd::borrow_raw(libc::STDIN_FILENO) },
-                nix::sys::termios::SetArg::TCSANOW,
-                &original_term,
-            ) {
-                tracing::warn!(error = %e, "failed to restore terminal settings in restore_terminal_settings");
+            term::disarm_terminal_restore();
+            if let Err(e) = restore_terminal(&original_term) {
+                tracing::warn!(parent: &self.span, error = %e, "failed to restore terminal settings in restore_terminal_settings");
             }
         }
     }
 
-    /// Generate a hierarchical log path with the format: <log_dir>/<config_file>/<sandbox_name>.<LOG_SUFFIX>
+    /// Returns the path the MicroVM's log will be written to, without starting anything
+    ///
+    /// Checks that the log's directory, or the nearest ancestor that exists yet, is writable,
+    /// so permission problems surface before the MicroVM is started rather than after.
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`MicrosandboxError::LogPathNotWritable`] if the log can't be created there.
+    pub fn preview_log_path(&self) -> MicrosandboxResult<PathBuf> {
+        let log_path = self.generate_log_path();
+        check_log_path_writable(&log_path)?;
+        Ok(log_path)
+    }
+
+    /// Returns the hierarchical log path for a sandbox, in the format
+    /// `<log_dir>/<config_file>/<sandbox_name>.<LOG_SUFFIX>`
+    ///
     /// This creates a directory structure that namespaces logs by config file and sandbox name.
-    fn generate_log_path(&self) -> PathBuf {
+    pub fn log_path_for(log_dir: &Path, config_file: &str, sandbox_name: &str) -> PathBuf {
         // Create a directory for the config file
-        let config_dir = self.log_dir.join(&self.config_file);
+        let config_dir = log_dir.join(config_file);
         // Place the log file inside that directory with the sandbox name
-        config_dir.join(format!("{}.{}", self.sandbox_name, LOG_SUFFIX))
+        config_dir.join(format!("{}.{}", sandbox_name, LOG_SUFFIX))
+    }
+
+    /// Generate the hierarchical log path for this sandbox
+    fn generate_log_path(&self) -> PathBuf {
+        Self::log_path_for(&self.log_dir, &self.config_file, &self.sandbox_name)
+    }
+
+    /// Records a lifecycle event of the sandbox in the database
+    ///
+    /// Events are an audit trail rather than state the sandbox depends on, so a failed write
+    /// is only logged.
+    async fn record_event(&mut self, kind: SandboxEventKind, detail: Option<&str>) {
+        let Ok(pool) = self.open_db().await else {
+            return;
+        };
+
+        if let Err(e) =
+            db::record_event(&pool, &self.sandbox_name, &self.config_file, kind, detail).await
+        {
+            tracing::warn!(parent: &self.span, error = %e, kind = kind.as_str(), "failed to record sandbox event");
+        }
+    }
+
+    /// Records the start of a MicroVM run, and prunes old events
+    ///
+    /// A start is recorded as a restart if the sandbox's previous run never recorded how it
+    /// ended, or ended in a crash, so a flapping sandbox shows up as such in the history.
+    async fn record_start_event(&mut self) {
+        let Ok(pool) = self.open_db().await else {
+            return;
+        };
+
+        let filter = SandboxEventFilter {
+            sandbox_name: Some(self.sandbox_name.clone()),
+            config_file: Some(self.config_file.clone()),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let kind = match db::query_events(&pool, &filter).await {
+            Ok(events) => match events.last().map(|event| event.kind) {
+                Some(
+                    SandboxEventKind::Start | SandboxEventKind::Restart | SandboxEventKind::Crash,
+                ) => SandboxEventKind::Restart,
+                Some(SandboxEventKind::Stop) | None => SandboxEventKind::Start,
+            },
+            Err(e) => {
+                tracing::warn!(parent: &self.span, error = %e, "failed to read sandbox events");
+                SandboxEventKind::Start
+            }
+        };
+        self.record_event(kind, None).await;
+
+        if let Err(e) = db::prune_events(&pool, MAX_SANDBOX_EVENTS).await {
+            tracing::warn!(parent: &self.span, error = %e, "failed to prune sandbox events");
+        }
+    }
+}
+
+impl DbRecord<'_> {
+    /// Writes one change of the sandbox's metadata, or the whole record if `full` is set
+    async fn write(
+        &self,
+        pool: &Pool<Sqlite>,
+        write: DbWrite,
+        full: bool,
+    ) -> MicrosandboxResult<()> {
+        if write == DbWrite::Record || full {
+            db::save_or_update_sandbox(
+                pool,
+                self.sandbox_name,
+                self.config_file,
+                self.config_last_modified,
+                self.config_hash,
+                &self.metadata.status,
+                self.metadata.supervisor_pid,
+                self.metadata.microvm_pid.unwrap_or_default(),
+                self.metadata.rootfs_paths.as_deref().unwrap_or_default(),
+                self.metadata
+                    .log_path
+                    .as_deref()
+                    .map(|path| path.to_string_lossy())
+                    .as_deref(),
+            )
+            .await?;
+        } else if write == DbWrite::Status {
+            db::update_sandbox_status(
+                pool,
+                self.sandbox_name,
+                self.config_file,
+                &self.metadata.status,
+            )
+            .await?;
+        }
+
+        if let (DbWrite::ExitStatus, Some(exit_status)) = (write, &self.metadata.exit_status) {
+            db::update_sandbox_exit_status(pool, self.sandbox_name, self.config_file, exit_status)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl OutputActivity {
+    /// Start tracking the MicroVM process `pid`, which just started
+    fn new(pid: u32) -> Self {
+        Self {
+            pid,
+            started_at: Instant::now(),
+            last_output_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Record that the MicroVM just produced output
+    fn record(&self) {
+        let elapsed = self.started_at.elapsed().as_millis() as u64;
+        self.last_output_ms.fetch_max(elapsed, Ordering::Relaxed);
+    }
+
+    /// Time since the last output, or since the start if there was none yet
+    fn idle(&self) -> Duration {
+        let last_output = Duration::from_millis(self.last_output_ms.load(Ordering::Relaxed));
+        self.started_at.elapsed().saturating_sub(last_output)
+    }
+
+    /// Classify the MicroVM's health against the silence threshold
+    fn health(&self, silence_threshold: Duration) -> MonitorHealth {
+        if signal::kill(Pid::from_raw(self.pid as i32), None).is_err() {
+            return MonitorHealth::Exited;
+        }
+
+        let idle = self.idle();
+        if idle > silence_threshold {
+            MonitorHealth::Silent { idle }
+        } else {
+            MonitorHealth::Active { idle }
+        }
+    }
+
+    /// Report when the MicroVM goes silent and when its output resumes, until it exits
+    async fn watch(self: Arc<Self>, silence_threshold: Duration) {
+        let interval = (silence_threshold / 4).clamp(SILENCE_CHECK_MIN, SILENCE_CHECK_MAX);
+        let mut silent = false;
+        loop {
+            tokio::time::sleep(interval).await;
+            match self.health(silence_threshold) {
+                MonitorHealth::Silent { idle } if !silent => {
+                    silent = true;
+                    tracing::warn!(
+                        microvm_pid = self.pid,
+                        idle_secs = idle.as_secs(),
+                        "microvm is alive but has produced no output for {}s",
+                        idle.as_secs()
+                    );
+                }
+                MonitorHealth::Active { .. } if silent => {
+                    silent = false;
+                    tracing::info!(microvm_pid = self.pid, "microvm output resumed");
+                }
+                MonitorHealth::Exited | MonitorHealth::NotRunning => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl ForwardOutput {
+    /// Forward both streams
+    pub const ALL: Self = Self {
+        stdout: true,
+        stderr: true,
+    };
+
+    /// Forward neither stream, only writing output to the log
+    pub const NONE: Self = Self {
+        stdout: false,
+        stderr: false,
+    };
+}
+
+impl OutputLogEncoder {
+    /// Create an encoder for one stream, sharing the sequence counter with the others
+    fn new(format: OutputLogFormat, stream: &'static str, seq: Arc<AtomicU64>) -> Self {
+        Self {
+            format,
+            stream,
+            seq,
+        }
+    }
+
+    /// Encode a chunk of output that was just read
+    fn encode<'a>(&self, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.format {
+            OutputLogFormat::Raw => Cow::Borrowed(bytes),
+            OutputLogFormat::JsonLines => {
+                let record = serde_json::json!({
+                    "seq": self.seq.fetch_add(1, Ordering::SeqCst),
+                    "ts": Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true),
+                    "stream": self.stream,
+                    "data": String::from_utf8_lossy(bytes),
+                });
+                let mut line = record.to_string().into_bytes();
+                line.push(b'\n');
+                Cow::Owned(line)
+            }
+        }
+    }
+}
+
+impl ProcessSampler {
+    /// Start sampling a process, or return `None` if it can't be read from `/proc`
+    async fn new(pid: u32) -> Option<Self> {
+        let stat = read_proc_stat(pid).await?;
+        Some(Self {
+            pid,
+            start_time: stat.start_time,
+            cpu_ticks: stat.cpu_ticks,
+            sampled_at: Instant::now(),
+        })
+    }
+
+    /// Take a sample, or return `None` once the process has exited
+    ///
+    /// A process whose `/proc` entry is gone, is a zombie, or whose PID now belongs to another
+    /// process counts as exited, so stale entries are never sampled.
+    async fn sample(&mut self) -> Option<ProcessSample> {
+        let stat = read_proc_stat(self.pid).await?;
+        if stat.start_time != self.start_time || matches!(stat.state, 'Z' | 'X') {
+            return None;
+        }
+
+        let statm = tokio::fs::read_to_string(format!("/proc/{}/statm", self.pid))
+            .await
+            .ok()?;
+        let resident_pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.sampled_at).as_secs_f64();
+        let cpu_seconds =
+            stat.cpu_ticks.saturating_sub(self.cpu_ticks) as f64 / clock_ticks_per_second();
+        self.cpu_ticks = stat.cpu_ticks;
+        self.sampled_at = now;
+
+        Some(ProcessSample {
+            cpu_usage_percent: if elapsed > 0.0 {
+                (cpu_seconds / elapsed * 100.0) as f32
+            } else {
+                0.0
+            },
+            memory_usage_bytes: resident_pages * page_size(),
+        })
+    }
+}
+
+impl ProcStat {
+    /// Parse the contents of `/proc/<pid>/stat`
+    ///
+    /// The command name is wrapped in parentheses and may itself contain spaces and
+    /// parentheses, so fields are counted from the last closing parenthesis.
+    fn parse(contents: &str) -> Option<Self> {
+        let (_, rest) = contents.rsplit_once(')')?;
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        let utime: u64 = fields.get(11)?.parse().ok()?;
+        let stime: u64 = fields.get(12)?.parse().ok()?;
+
+        Some(Self {
+            state: fields.first()?.chars().next()?,
+            cpu_ticks: utime + stime,
+            start_time: fields.get(19)?.parse().ok()?,
+        })
+    }
+}
+
+impl OutputDecoder {
+    /// Create a decoder for the given encoding, passing UTF-8 through as is
+    fn new(encoding: Option<&'static Encoding>) -> Self {
+        let decoder = encoding
+            .filter(|e| *e != UTF_8)
+            .map(|e| e.new_decoder_without_bom_handling());
+        Self { decoder }
+    }
+
+    /// Decode the next chunk of output
+    fn decode<'a>(&mut self, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        let Some(decoder) = self.decoder.as_mut() else {
+            return Cow::Borrowed(bytes);
+        };
+
+        let capacity = decoder
+            .max_utf8_buffer_length(bytes.len())
+            .unwrap_or(bytes.len() * 3);
+        let mut output = String::with_capacity(capacity);
+        let _ = decoder.decode_to_string(bytes, &mut output, false);
+        Cow::Owned(output.into_bytes())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<bool> for ForwardOutput {
+    fn from(forward: bool) -> Self {
+        Self {
+            stdout: forward,
+            stderr: forward,
+        }
+    }
+}
+
+impl From<ForwardOutputRepr> for ForwardOutput {
+    fn from(repr: ForwardOutputRepr) -> Self {
+        match repr {
+            ForwardOutputRepr::Both(forward) => forward.into(),
+            ForwardOutputRepr::Streams { stdout, stderr } => Self { stdout, stderr },
+        }
+    }
+}
+
+impl FromStr for OutputLogFormat {
+    type Err = MicrosandboxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "jsonl" | "json-lines" => Ok(Self::JsonLines),
+            _ => Err(MicrosandboxError::InvalidArgument(format!(
+                "unknown output log format: {} (expected raw or jsonl)",
+                s
+            ))),
+        }
     }
 }
 
-//--------------------------------------------------------------------------------------------------
-// Trait Implementations
-//--------------------------------------------------------------------------------------------------
-
 #[async_trait]
 impl ProcessMonitor for MicroVmMonitor {
     async fn start(&mut self, pid: u32, child_io: ChildIo) -> MicrosandboxUtilsResult<()> {
-        // Generate the log path with directory-level separation
-        let log_path = self.generate_log_path();
+        ignore_sigpipe(&self.span);
+        self.microvm_reattached = false;
 
-        // Ensure the parent directory exists
-        if let Some(parent) = log_path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
+        let (log_sink, log_path) = match &self.log_sink {
+            Some(factory) => (factory(&self.sandbox_name)?, None),
+            None => {
+                // Generate the log path with directory-level separation
+                let log_path = self.generate_log_path();
+
+                // Ensure the parent directory exists
+                if let Some(parent) = log_path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+
+                let log = RotatingLog::with_rotation(
+                    &log_path,
+                    self.log_rotation,
+                    Some(self.log_lock_policy),
+                )
+                .await?;
+                (Box::new(log) as Box<dyn LogSink>, Some(log_path))
+            }
+        };
 
-        let microvm_log =
-            std::sync::Arc::new(tokio::sync::Mutex::new(RotatingLog::new(&log_path).await?));
+        let (microvm_log, log_writer) = LogWriteAhead::new(log_sink, self.log_write_ahead_size);
+        self.output_tasks
+            .push(spawn_in_span(&self.span, log_writer.run()));
         let microvm_pid = pid;
+        let log_seq = Arc::new(AtomicU64::new(0));
+        let activity = Arc::new(OutputActivity::new(microvm_pid));
+        self.activity = Some(activity.clone());
+        self.silence_watcher = Some(spawn_in_span(
+            &self.span,
+            activity.clone().watch(self.silence_threshold),
+        ));
 
-        self.log_path = Some(log_path);
+        self.metadata.log_path = log_path.clone();
+        self.log_path = log_path;
 
         // Get rootfs paths
-        let rootfs_paths = match &self.rootfs {
-            Rootfs::Native(path) => format!("native:{}", path.to_string_lossy().into_owned()),
-            Rootfs::Overlayfs(paths) => format!(
-                "overlayfs:{}",
-                paths
-                    .iter()
-                    .map(|p| p.to_string_lossy().into_owned())
-                    .collect::<Vec<String>>()
-                    .join(":")
-            ),
-        };
+        let rootfs_paths = self.rootfs.to_string();
 
         // Insert sandbox entry into database
-        db::save_or_update_sandbox(
-            &self.sandbox_db,
-            &self.sandbox_name,
-            &self.config_file,
-            &self.config_last_modified,
-            SANDBOX_STATUS_RUNNING,
-            self.supervisor_pid,
-            microvm_pid,
-            &rootfs_paths,
-        )
-        .await
-        .map_err(MicrosandboxUtilsError::custom)?;
+        self.metadata.status = SANDBOX_STATUS_RUNNING.to_string();
+        self.metadata.microvm_pid = Some(microvm_pid);
+        self.microvm_pid = Some(microvm_pid);
+        self.metadata.rootfs_paths = Some(rootfs_paths);
+        self.metadata.exit_status = None;
+        self.persist(DbWrite::Record).await?;
+        self.stop_requested = false;
+        self.record_start_event().await;
+
+        if let Some(oom_score_adj) = self.oom_score_adj {
+            set_oom_score_adj(microvm_pid, oom_score_adj).await;
+        }
+
+        if let (Some(interval), Some(pool)) = (self.metrics_interval, &self.sandbox_db) {
+            self.metrics_sampler = Some(spawn_in_span(
+                &self.span,
+                sample_metrics(
+                    pool.clone(),
+                    self.sandbox_name.clone(),
+                    self.config_file.clone(),
+                    microvm_pid,
+                    interval,
+                ),
+            ));
+        }
+
+        if let Some(watcher) = self.config_watcher.clone() {
+            self.config_watch_task = Some(spawn_in_span(&self.span, async move {
+                if let Err(e) = watcher.run().await {
+                    tracing::warn!(error = %e, "stopped watching the config file");
+                }
+            }));
+        }
 
         match child_io {
             ChildIo::Piped {
@@ -171,73 +1504,101 @@ impl ProcessMonitor for MicroVmMonitor {
                 // Handle stdout logging
                 if let Some(mut stdout) = stdout {
                     let log = microvm_log.clone();
-                    let forward_output = self.forward_output;
-                    tokio::spawn(async move {
-                        let mut buf = [0u8; 8192]; // NOTE(appcypher): Using 8192 as buffer size because ChatGPT recommended it lol
+                    let encoder = OutputLogEncoder::new(self.log_format, "stdout", log_seq.clone());
+                    let activity = activity.clone();
+                    let mut forward_output = self.forward_output.stdout;
+                    let stop_on_broken_pipe = self.stop_on_broken_pipe;
+                    let recent_output = self.recent_output.clone();
+                    let mut decoder = OutputDecoder::new(self.output_encoding);
+                    let read_buffer_size = self.read_buffer_size;
+                    self.output_tasks.push(spawn_in_span(&self.span, async move {
+                        let mut buf = vec![0u8; read_buffer_size];
                         while let Ok(n) = stdout.read(&mut buf).await {
                             if n == 0 {
                                 break;
                             }
-                            // Write to log file
-                            let mut log_guard = log.lock().await;
-                            if let Err(e) = log_guard.write_all(&buf[..n]).await {
+                            activity.record();
+
+                            // Write to log file, buffering ahead while the log rotates
+                            if let Err(e) = log.write(encoder.stream, &encoder.encode(&buf[..n])).await {
                                 tracing::error!(microvm_pid = microvm_pid, error = %e, "failed to write to microvm stdout log");
                             }
-                            if let Err(e) = log_guard.flush().await {
-                                tracing::error!(microvm_pid = microvm_pid, error = %e, "failed to flush microvm stdout log");
+
+                            // Keep the chunk for fast tail queries
+                            if let Some(ring) = &recent_output {
+                                ring.push(&buf[..n]);
                             }
 
                             // Also forward to parent's stdout if enabled
                             if forward_output {
-                                print!("{}", String::from_utf8_lossy(&buf[..n]));
-                                // Flush stdout in case data is buffered
-                                if let Err(e) = std::io::stdout().flush() {
-                                    tracing::warn!(error = %e, "failed to flush parent stdout");
-                                }
+                                forward_output = forward_to_parent(
+                                    &mut std::io::stdout(),
+                                    &decoder.decode(&buf[..n]),
+                                    "stdout",
+                                    microvm_pid,
+                                    stop_on_broken_pipe,
+                                );
                             }
                         }
-                    });
+                    }));
                 }
 
                 // Handle stderr logging
                 if let Some(mut stderr) = stderr {
                     let log = microvm_log.clone();
-                    let forward_output = self.forward_output;
-                    tokio::spawn(async move {
-                        let mut buf = [0u8; 8192]; // NOTE(appcypher): Using 8192 as buffer size because ChatGPT recommended it lol
+                    let encoder = OutputLogEncoder::new(self.log_format, "stderr", log_seq.clone());
+                    let activity = activity.clone();
+                    let mut forward_output = self.forward_output.stderr;
+                    let stop_on_broken_pipe = self.stop_on_broken_pipe;
+                    let recent_output = self.recent_output.clone();
+                    let mut decoder = OutputDecoder::new(self.output_encoding);
+                    let read_buffer_size = self.read_buffer_size;
+                    self.output_tasks.push(spawn_in_span(&self.span, async move {
+                        let mut buf = vec![0u8; read_buffer_size];
                         while let Ok(n) = stderr.read(&mut buf).await {
                             if n == 0 {
                                 break;
                             }
-                            // Write to log file
-                            let mut log_guard = log.lock().await;
-                            if let Err(e) = log_guard.write_all(&buf[..n]).await {
+                            activity.record();
+
+                            // Write to log file, buffering ahead while the log rotates
+                            if let Err(e) = log.write(encoder.stream, &encoder.encode(&buf[..n])).await {
                                 tracing::error!(microvm_pid = microvm_pid, error = %e, "failed to write to microvm stderr log");
                             }
-                            if let Err(e) = log_guard.flush().await {
-                                tracing::error!(microvm_pid = microvm_pid, error = %e, "failed to flush microvm stderr log");
+
+                            // Keep the chunk for fast tail queries
+                            if let Some(ring) = &recent_output {
+                                ring.push(&buf[..n]);
                             }
 
                             // Also forward to parent's stderr if enabled
                             if forward_output {
-                                eprint!("{}", String::from_utf8_lossy(&buf[..n]));
-                                // Flush stderr in case data is buffered
-                                if let Err(e) = std::io::stderr().flush() {
-                                    tracing::warn!(error = %e, "failed to flush parent stderr");
-                                }
+                                forward_output = forward_to_parent(
+                                    &mut std::io::stderr(),
+                                    &decoder.decode(&buf[..n]),
+                                    "stderr",
+                                    microvm_pid,
+                                    stop_on_broken_pipe,
+                                );
                             }
                         }
-                    });
+                    }));
                 }
 
                 // Handle stdin streaming from parent to child
                 if let Some(mut child_stdin) = stdin {
-                    tokio::spawn(async move {
-                        let mut parent_stdin = tokio::io::stdin();
-                        if let Err(e) = tokio::io::copy(&mut parent_stdin, &mut child_stdin).await {
-                            tracing::warn!(error = %e, "failed to copy parent stdin to child stdin");
-                        }
-                    });
+                    if let Some(router) = &self.stdin_router {
+                        router.register(&self.sandbox_name, child_stdin).await;
+                    } else {
+                        spawn_in_span(&self.span, async move {
+                            let mut parent_stdin = tokio::io::stdin();
+                            if let Err(e) =
+                                tokio::io::copy(&mut parent_stdin, &mut child_stdin).await
+                            {
+                                tracing::warn!(error = %e, "failed to copy parent stdin to child stdin");
+                            }
+                        });
+                    }
                 }
             }
             ChildIo::TTY {
@@ -245,24 +1606,21 @@ impl ProcessMonitor for MicroVmMonitor {
                 mut master_write,
             } => {
                 // Handle TTY I/O
-                // Put terminal in raw mode
-                let term = nix::sys::termios::tcgetattr(unsafe {
-                    BorrowedFd::borrow_raw(libc::STDIN_FILENO)
-                })?;
-                self.original_term = Some(term.clone());
-                let mut raw_term = term.clone();
-                nix::sys::termios::cfmakeraw(&mut raw_term);
-                nix::sys::termios::tcsetattr(
-                    unsafe { BorrowedFd::borrow_raw(libc::STDIN_FILENO) },
-                    nix::sys::termios::SetArg::TCSANOW,
-                    &raw_term,
-                )?;
+                // Put terminal in raw mode, if there is one whose mode can be changed
+                self.original_term = enter_raw_mode(&self.span)?;
 
                 // Spawn async task to read from the master
                 let log = microvm_log.clone();
-                let forward_output = self.forward_output;
-                tokio::spawn(async move {
-                    let mut buf = [0u8; 1024];
+                let encoder = OutputLogEncoder::new(self.log_format, "tty", log_seq);
+                let activity = activity.clone();
+                // Stdout and stderr are combined on the pseudo-TTY, which follows the stdout flag
+                let mut forward_output = self.forward_output.stdout;
+                let stop_on_broken_pipe = self.stop_on_broken_pipe;
+                let recent_output = self.recent_output.clone();
+                let mut decoder = OutputDecoder::new(self.output_encoding);
+                let read_buffer_size = self.read_buffer_size;
+                self.output_tasks.push(spawn_in_span(&self.span, async move {
+                    let mut buf = vec![0u8; read_buffer_size];
                     loop {
                         let mut read_guard = match master_read.readable().await {
                             Ok(guard) => guard,
@@ -275,20 +1633,27 @@ impl ProcessMonitor for MicroVmMonitor {
                         match read_guard.try_io(|inner| inner.get_ref().read(&mut buf)) {
                             Ok(Ok(0)) => break, // EOF reached.
                             Ok(Ok(n)) => {
-                                // Write to log file
-                                let mut log_guard = log.lock().await;
-                                if let Err(e) = log_guard.write_all(&buf[..n]).await {
+                                activity.record();
+
+                                // Write to log file, buffering ahead while the log rotates
+                                if let Err(e) = log.write(encoder.stream, &encoder.encode(&buf[..n])).await {
                                     tracing::error!(microvm_pid = microvm_pid, error = %e, "failed to write to microvm tty log");
                                 }
-                                if let Err(e) = log_guard.flush().await {
-                                    tracing::error!(microvm_pid = microvm_pid, error = %e, "failed to flush microvm tty log");
+
+                                // Keep the chunk for fast tail queries
+                                if let Some(ring) = &recent_output {
+                                    ring.push(&buf[..n]);
                                 }
 
                                 // Print the output from the child process if enabled
                                 if forward_output {
-                                    print!("{}", String::from_utf8_lossy(&buf[..n]));
-                                    // flush stdout in case data is buffered
-                                    std::io::stdout().flush().ok();
+                                    forward_output = forward_to_parent(
+                                        &mut std::io::stdout(),
+                                        &decoder.decode(&buf[..n]),
+                                        "stdout",
+                                        microvm_pid,
+                                        stop_on_broken_pipe,
+                                    );
                                 }
                             }
                             Ok(Err(e)) => {
@@ -298,38 +1663,122 @@ impl ProcessMonitor for MicroVmMonitor {
                             Err(_) => continue,
                         }
                     }
-                });
+                }));
 
-                // Spawn async task to copy parent's stdin to the master
-                tokio::spawn(async move {
-                    let mut stdin = tokio::io::stdin();
-                    if let Err(e) = tokio::io::copy(&mut stdin, &mut master_write).await {
-                        tracing::warn!(error = %e, "error copying stdin to master fd");
-                    }
-                });
+                if let Some(router) = &self.stdin_router {
+                    router.register(&self.sandbox_name, master_write).await;
+                } else {
+                    // Spawn async task to copy parent's stdin to the master
+                    spawn_in_span(&self.span, async move {
+                        let mut stdin = tokio::io::stdin();
+                        if let Err(e) = tokio::io::copy(&mut stdin, &mut master_write).await {
+                            tracing::warn!(error = %e, "error copying stdin to master fd");
+                        }
+                    });
+                }
             }
         }
 
         Ok(())
     }
 
+    fn on_reaped(&mut self) {
+        // The PID may already belong to another process, which must not be signalled
+        self.microvm_pid = None;
+    }
+
     async fn stop(&mut self) -> MicrosandboxUtilsResult<()> {
+        // Shut down the MicroVM if it is still running
+        let microvm_pid = self.microvm_pid;
+        // A MicroVM that already exited on its own is recorded once its exit status is known
+        match self.shutdown_microvm().await {
+            StopOutcome::NotRunning => {}
+            StopOutcome::Graceful => {
+                tracing::info!(parent: &self.span, microvm_pid, "microvm exited after SIGTERM");
+                self.record_event(SandboxEventKind::Stop, Some("exited after SIGTERM"))
+                    .await;
+                self.stop_requested = true;
+            }
+            StopOutcome::Killed => {
+                tracing::warn!(
+                    parent: &self.span,
+                    microvm_pid,
+                    grace_ms = self.stop_grace_period.as_millis() as u64,
+                    "microvm did not exit within the grace period, sent SIGKILL"
+                );
+                self.record_event(
+                    SandboxEventKind::Stop,
+                    Some("killed with SIGKILL after the stop grace period"),
+                )
+                .await;
+                self.stop_requested = true;
+            }
+        }
+
         // Restore terminal settings if they were modified
         self.restore_terminal_settings();
 
+        if let Some(router) = &self.stdin_router {
+            router.unregister(&self.sandbox_name).await;
+        }
+
         // Update sandbox status to stopped
-        db::update_sandbox_status(
-            &self.sandbox_db,
-            &self.sandbox_name,
-            &self.config_file,
-            SANDBOX_STATUS_STOPPED,
-        )
-        .await
-        .map_err(MicrosandboxUtilsError::custom)?;
+        self.metadata.status = SANDBOX_STATUS_STOPPED.to_string();
+        self.persist(DbWrite::Status).await?;
 
         // Reset the log path
         self.log_path = None;
 
+        // Stop watching for silence
+        self.activity = None;
+        if let Some(watcher) = self.silence_watcher.take() {
+            watcher.abort();
+        }
+
+        // Stop sampling metrics and watching the config
+        if let Some(sampler) = self.metrics_sampler.take() {
+            sampler.abort();
+        }
+        if let Some(task) = self.config_watch_task.take() {
+            task.abort();
+        }
+
+        Ok(())
+    }
+
+    async fn drain(&mut self) -> MicrosandboxUtilsResult<()> {
+        // Wait for the output tasks to reach EOF; every chunk is flushed as it is logged
+        let span = &self.span;
+        for task in self.output_tasks.drain(..) {
+            if let Err(e) = task.await {
+                tracing::warn!(parent: span, error = %e, "microvm output task failed while draining");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn on_exit(&mut self, status: &ExitStatus) -> MicrosandboxUtilsResult<()> {
+        self.metadata.exit_status = Some(*status);
+        self.persist(DbWrite::ExitStatus).await?;
+
+        // A stop the monitor asked for was already recorded, however the MicroVM went down
+        if self.stop_requested {
+            return Ok(());
+        }
+
+        let detail = describe_exit(status);
+        if status.success() {
+            self.record_event(SandboxEventKind::Stop, Some(&detail))
+                .await;
+        } else {
+            tracing::error!(parent: &self.span, %detail, "microvm crashed");
+            self.metadata.status = SANDBOX_STATUS_CRASHED.to_string();
+            self.persist(DbWrite::Status).await?;
+            self.record_event(SandboxEventKind::Crash, Some(&detail))
+                .await;
+        }
+
         Ok(())
     }
 }
@@ -337,5 +1786,828 @@ impl ProcessMonitor for MicroVmMonitor {
 impl Drop for MicroVmMonitor {
     fn drop(&mut self) {
         self.restore_terminal_settings();
+        if let Some(watcher) = self.silence_watcher.take() {
+            watcher.abort();
+        }
+        if let Some(sampler) = self.metrics_sampler.take() {
+            sampler.abort();
+        }
+        if let Some(task) = self.config_watch_task.take() {
+            task.abort();
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Puts stdin in raw mode for a pseudo-TTY, returning its original settings
+///
+/// Returns `None` without touching the terminal if stdin isn't a terminal, e.g. when the
+/// supervisor runs in the background or under a test harness. The MicroVM's pseudo-TTY is
+/// still read and written; only the local echo and line editing are left as they were.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn enter_raw_mode(span: &Span) -> MicrosandboxUtilsResult<Option<nix::sys::termios::Termios>> {
+    use std::io::IsTerminal;
+
+    if !io::stdin().is_terminal() {
+        tracing::debug!(parent: span, "stdin is not a terminal, leaving its mode unchanged");
+        return Ok(None);
+    }
+
+    let stdin = io::stdin();
+    let original = nix::sys::termios::tcgetattr(&stdin)?;
+    term::arm_terminal_restore(&original);
+    let mut raw_term = original.clone();
+    nix::sys::termios::cfmakeraw(&mut raw_term);
+    if let Err(e) =
+        nix::sys::termios::tcsetattr(&stdin, nix::sys::termios::SetArg::TCSANOW, &raw_term)
+    {
+        term::disarm_terminal_restore();
+        return Err(e.into());
+    }
+
+    Ok(Some(original))
+}
+
+/// Leaves stdin as it is on platforms without termios raw mode
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn enter_raw_mode(span: &Span) -> MicrosandboxUtilsResult<Option<nix::sys::termios::Termios>> {
+    tracing::debug!(parent: span, "raw mode is not supported on this platform, leaving stdin unchanged");
+    Ok(None)
+}
+
+/// Restores stdin's terminal settings saved by [`enter_raw_mode`]
+fn restore_terminal(original: &nix::sys::termios::Termios) -> nix::Result<()> {
+    nix::sys::termios::tcsetattr(io::stdin(), nix::sys::termios::SetArg::TCSANOW, original)
+}
+
+/// Returns the read buffer size of monitor state exported before it was configurable
+fn default_read_buffer_size() -> usize {
+    DEFAULT_READ_BUFFER_SIZE
+}
+
+/// Describes how a MicroVM exited, for its lifecycle events
+fn describe_exit(status: &ExitStatus) -> String {
+    let how = match (status.code, status.signal) {
+        _ if status.oom => "killed by the OOM killer".to_string(),
+        (Some(code), _) => format!("exited with code {}", code),
+        (None, Some(signal)) => format!("killed by signal {}", signal),
+        (None, None) => "exited".to_string(),
+    };
+    format!("{} after {:.1}s", how, status.duration.as_secs_f64())
+}
+
+/// Writes forwarded output to the parent's stdout or stderr
+///
+/// Returns whether forwarding should continue. Once the consumer has gone away (broken pipe),
+/// this logs it a single time, optionally stops the MicroVM, and returns `false` so later output
+/// is only written to the log.
+fn forward_to_parent(
+    out: &mut impl Write,
+    bytes: &[u8],
+    stream: &str,
+    microvm_pid: u32,
+    stop_on_broken_pipe: bool,
+) -> bool {
+    match out.write_all(bytes).and_then(|_| out.flush()) {
+        Ok(()) => true,
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+            tracing::warn!(
+                stream,
+                "parent {} closed, no longer forwarding output",
+                stream
+            );
+            if stop_on_broken_pipe {
+                tracing::info!(
+                    microvm_pid,
+                    "stopping microvm since its output consumer went away"
+                );
+                if let Err(e) = signal::kill(Pid::from_raw(microvm_pid as i32), Signal::SIGTERM) {
+                    tracing::warn!(microvm_pid, error = %e, "failed to stop microvm");
+                }
+            }
+            false
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to forward output to parent {}", stream);
+            true
+        }
+    }
+}
+
+/// Checks whether the MicroVM process has exited
+///
+/// An exited child stays a zombie until the supervisor reaps it, so on Linux it is checked
+/// with `waitid(WNOWAIT)`, which leaves it for the supervisor to reap. A MicroVM that isn't a
+/// child of this process, such as one reattached after a supervisor upgrade, and any MicroVM on
+/// other platforms, is checked for still existing instead.
+///
+/// A child that `waitid` no longer knows has already been reaped, and its PID may belong to
+/// another process by now, so it counts as exited without checking the PID.
+fn microvm_exited(pid: u32, is_child: bool) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        use nix::sys::wait::{self, Id, WaitPidFlag, WaitStatus};
+
+        match wait::waitid(
+            Id::Pid(Pid::from_raw(pid as i32)),
+            WaitPidFlag::WEXITED | WaitPidFlag::WNOHANG | WaitPidFlag::WNOWAIT,
+        ) {
+            Ok(WaitStatus::StillAlive) => return false,
+            Ok(_) => return true,
+            Err(nix::errno::Errno::ECHILD) if is_child => return true,
+            Err(_) => {}
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = is_child;
+
+    signal::kill(Pid::from_raw(pid as i32), None).is_err()
+}
+
+/// Ignores `SIGPIPE`, so writes to a closed pipe fail with `BrokenPipe` instead of killing
+/// the process
+fn ignore_sigpipe(span: &Span) {
+    // SAFETY: SIG_IGN installs no handler code, so no async-signal-safety rules apply
+    if let Err(e) = unsafe { signal::signal(Signal::SIGPIPE, signal::SigHandler::SigIgn) } {
+        tracing::warn!(parent: span, error = %e, "failed to ignore SIGPIPE");
+    }
+}
+
+/// Samples a MicroVM's CPU and memory use every `interval` and records it in the sandbox
+/// database, until the process exits
+async fn sample_metrics(
+    pool: Pool<Sqlite>,
+    sandbox_name: String,
+    config_file: String,
+    microvm_pid: u32,
+    interval: Duration,
+) {
+    let Some(mut sampler) = ProcessSampler::new(microvm_pid).await else {
+        tracing::debug!(
+            microvm_pid,
+            "microvm process can't be read from /proc, not sampling metrics"
+        );
+        return;
+    };
+
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    // The first tick completes immediately, before there is any CPU time to measure
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let Some(sample) = sampler.sample().await else {
+            tracing::debug!(
+                microvm_pid,
+                "microvm process exited, no longer sampling metrics"
+            );
+            return;
+        };
+
+        if let Err(e) = db::insert_sandbox_metric(
+            &pool,
+            &sandbox_name,
+            &config_file,
+            microvm_pid,
+            sample.cpu_usage_percent,
+            sample.memory_usage_bytes,
+        )
+        .await
+        {
+            tracing::warn!(microvm_pid, error = %e, "failed to record microvm metrics");
+        }
+    }
+}
+
+/// Reads the fields the sampler uses from `/proc/<pid>/stat`
+async fn read_proc_stat(pid: u32) -> Option<ProcStat> {
+    let contents = tokio::fs::read_to_string(format!("/proc/{}/stat", pid))
+        .await
+        .ok()?;
+    ProcStat::parse(&contents)
+}
+
+/// Returns the number of clock ticks per second that `/proc` reports CPU time in
+fn clock_ticks_per_second() -> f64 {
+    // SAFETY: sysconf has no preconditions
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as f64,
+        _ => 100.0,
+    }
+}
+
+/// Returns the size of a memory page in bytes
+fn page_size() -> u64 {
+    // SAFETY: sysconf has no preconditions
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as u64,
+        _ => 4096,
+    }
+}
+
+/// Checks that a log file can be created at `log_path`
+///
+/// Directories that don't exist yet will be created on start, so the check applies to the
+/// nearest ancestor of the log that exists.
+fn check_log_path_writable(log_path: &Path) -> MicrosandboxResult<()> {
+    let not_writable =
+        |reason: String| MicrosandboxError::LogPathNotWritable(log_path.to_path_buf(), reason);
+
+    let Some(mut dir) = log_path.parent() else {
+        return Err(not_writable("path has no parent directory".to_string()));
+    };
+    while !dir.exists() {
+        dir = dir
+            .parent()
+            .ok_or_else(|| not_writable("no ancestor directory exists".to_string()))?;
+    }
+
+    if !dir.is_dir() {
+        return Err(not_writable(format!(
+            "{} is not a directory",
+            dir.display()
+        )));
+    }
+
+    unistd::access(dir, AccessFlags::W_OK | AccessFlags::X_OK).map_err(|e| {
+        not_writable(format!(
+            "directory {} is not writable: {}",
+            dir.display(),
+            e
+        ))
+    })?;
+
+    if log_path.is_dir() {
+        return Err(not_writable("path is a directory".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Writes the OOM score adjustment of a process, logging instead of failing if it can't
+async fn set_oom_score_adj(pid: u32, oom_score_adj: i32) {
+    let path = format!("/proc/{}/oom_score_adj", pid);
+    match tokio::fs::write(&path, oom_score_adj.to_string()).await {
+        Ok(()) => tracing::info!(microvm_pid = pid, oom_score_adj, "set microvm oom score adjustment"),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => tracing::warn!(
+            microvm_pid = pid,
+            oom_score_adj,
+            "not permitted to set microvm oom score adjustment; lowering it requires CAP_SYS_RESOURCE"
+        ),
+        Err(e) => tracing::warn!(
+            microvm_pid = pid,
+            oom_score_adj,
+            error = %e,
+            "failed to set microvm oom score adjustment"
+        ),
+    }
+}
+
+/// Spawns a task whose diagnostics are recorded in the given span
+fn spawn_in_span<F>(span: &Span, future: F) -> JoinHandle<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(future.instrument(span.clone()))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+
+    use encoding_rs::WINDOWS_1252;
+
+    use crate::vm::OverlayfsLayers;
+
+    use super::*;
+
+    #[test]
+    fn test_output_decoder_transcodes_latin1() {
+        let mut decoder = OutputDecoder::new(Some(WINDOWS_1252));
+        assert_eq!(
+            decoder.decode(b"caf\xe9 \xfcber \xa3"),
+            "café über £".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_output_decoder_carries_partial_characters_across_chunks() {
+        let mut decoder = OutputDecoder::new(Encoding::for_label(b"utf-16le"));
+        assert_eq!(decoder.decode(&[0x68, 0x00, 0xe9]), "h".as_bytes());
+        assert_eq!(decoder.decode(&[0x00]), "é".as_bytes());
+    }
+
+    #[test]
+    fn test_output_decoder_passes_utf8_through() {
+        let mut decoder = OutputDecoder::new(None);
+        assert_eq!(decoder.decode("café".as_bytes()), "café".as_bytes());
+
+        // Binary output and characters split across chunks come through unchanged
+        let bytes = "é".as_bytes();
+        assert_eq!(decoder.decode(&bytes[..1]), &bytes[..1]);
+        assert_eq!(decoder.decode(&bytes[1..]), &bytes[1..]);
+        assert_eq!(decoder.decode(b"\x00\xff\xfe"), &b"\x00\xff\xfe"[..]);
+    }
+
+    #[tokio::test]
+    async fn test_output_activity_reports_silence() {
+        let activity = OutputActivity::new(std::process::id());
+        let threshold = Duration::from_millis(50);
+        assert!(matches!(
+            activity.health(threshold),
+            MonitorHealth::Active { .. }
+        ));
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(matches!(
+            activity.health(threshold),
+            MonitorHealth::Silent { idle } if idle >= threshold
+        ));
+
+        activity.record();
+        assert!(matches!(
+            activity.health(threshold),
+            MonitorHealth::Active { idle } if idle < threshold
+        ));
+
+        // A process that no longer exists has exited, however recent its output
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert_eq!(
+            OutputActivity::new(pid).health(threshold),
+            MonitorHealth::Exited
+        );
+    }
+
+    #[test]
+    fn test_output_log_encoder_sequences_chunks_across_streams() {
+        let seq = Arc::new(AtomicU64::new(0));
+        let stdout = OutputLogEncoder::new(OutputLogFormat::JsonLines, "stdout", seq.clone());
+        let stderr = OutputLogEncoder::new(OutputLogFormat::JsonLines, "stderr", seq);
+
+        let records: Vec<serde_json::Value> = [stdout.encode(b"a\n"), stderr.encode(b"b\n")]
+            .iter()
+            .map(|line| {
+                assert!(line.ends_with(b"\n"));
+                serde_json::from_slice(line).unwrap()
+            })
+            .collect();
+        assert_eq!(records[0]["seq"], 0);
+        assert_eq!(records[0]["stream"], "stdout");
+        assert_eq!(records[0]["data"], "a\n");
+        assert_eq!(records[1]["seq"], 1);
+        assert_eq!(records[1]["stream"], "stderr");
+        assert!(records[1]["ts"].is_string());
+
+        let raw = OutputLogEncoder::new(OutputLogFormat::Raw, "stdout", Default::default());
+        assert_eq!(&*raw.encode(b"a\n"), b"a\n");
+    }
+
+    #[test]
+    fn test_proc_stat_parse_handles_parentheses_in_command_name() {
+        let contents = "4242 (msb (worker) 1) S 1 4242 4242 0 -1 4194560 1200 0 0 0 \
+                        250 50 0 0 20 0 3 0 987654 104857600 2048 18446744073709551615";
+        assert_eq!(
+            ProcStat::parse(contents),
+            Some(ProcStat {
+                state: 'S',
+                cpu_ticks: 300,
+                start_time: 987654,
+            })
+        );
+        assert_eq!(ProcStat::parse("4242 (truncated) S 1"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_process_sampler_samples_live_process_and_stops_after_exit() -> anyhow::Result<()>
+    {
+        let mut sampler = ProcessSampler::new(std::process::id()).await.unwrap();
+        let sample = sampler.sample().await.unwrap();
+        assert!(sample.memory_usage_bytes > 0);
+        assert!(sample.cpu_usage_percent >= 0.0);
+
+        let mut child = std::process::Command::new("true").spawn()?;
+        let mut sampler = ProcessSampler::new(child.id()).await;
+        child.wait()?;
+        if let Some(sampler) = sampler.as_mut() {
+            assert_eq!(sampler.sample().await, None);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_monitor_state_survives_a_process_swap() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db_path = dir.path().join("missing").join("sandbox.db");
+        let (lower, upper) = (dir.path().join("lower"), dir.path().join("upper"));
+        std::fs::create_dir_all(&lower)?;
+        std::fs::create_dir_all(&upper)?;
+        let mut monitor = MicroVmMonitor::new(
+            100,
+            &db_path,
+            "app".to_string(),
+            "sandbox.yaml".to_string(),
+            Utc::now(),
+            dir.path(),
+            Rootfs::Overlayfs(OverlayfsLayers::from_stack(vec![lower, upper])?),
+            ForwardOutput {
+                stdout: false,
+                stderr: true,
+            },
+        )
+        .await?
+        .with_db_optional(true)
+        .with_recent_output_size(4096)
+        .with_output_encoding(WINDOWS_1252)
+        .with_log_format(OutputLogFormat::JsonLines)
+        .with_log_rotation(LogRotation::new(1 << 20).with_max_files(3))
+        .with_read_buffer_size(256 * 1024)
+        .with_metrics_interval(Duration::from_secs(5))
+        .with_config_hash("abc123");
+        monitor.metadata.status = SANDBOX_STATUS_RUNNING.to_string();
+        monitor.metadata.microvm_pid = Some(4242);
+
+        // The state crosses the process boundary as JSON
+        let exported = monitor.export_state();
+        let json = serde_json::to_string(&exported)?;
+        let restored =
+            MicroVmMonitor::from_state(serde_json::from_str(&json)?, 200, &db_path).await?;
+
+        let state = restored.export_state();
+        assert_eq!(state.metadata.supervisor_pid, 200);
+        assert_eq!(state.metadata.microvm_pid, Some(4242));
+        assert_eq!(state.output_encoding.as_deref(), Some("windows-1252"));
+        assert_eq!(
+            MonitorState {
+                metadata: exported.metadata.clone(),
+                ..state
+            },
+            exported
+        );
+
+        // State exported before the read buffer size was configurable gets the default
+        let mut older = serde_json::to_value(&exported)?;
+        older.as_object_mut().unwrap().remove("read_buffer_size");
+        assert_eq!(
+            serde_json::from_value::<MonitorState>(older)?.read_buffer_size,
+            DEFAULT_READ_BUFFER_SIZE
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_forward_output_reads_the_flag_of_older_monitors() -> anyhow::Result<()> {
+        let forward_output = ForwardOutput {
+            stdout: false,
+            stderr: true,
+        };
+        let json = serde_json::to_string(&forward_output)?;
+        assert_eq!(
+            serde_json::from_str::<ForwardOutput>(&json)?,
+            forward_output
+        );
+
+        assert_eq!(
+            serde_json::from_str::<ForwardOutput>("true")?,
+            ForwardOutput::ALL
+        );
+        assert_eq!(
+            serde_json::from_str::<ForwardOutput>("false")?,
+            ForwardOutput::NONE
+        );
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_stop_escalates_to_sigkill_after_grace_period() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db_path = dir.path().join("missing").join("sandbox.db");
+        let mut monitor = MicroVmMonitor::new(
+            std::process::id(),
+            &db_path,
+            "app".to_string(),
+            "sandbox.yaml".to_string(),
+            Utc::now(),
+            dir.path(),
+            Rootfs::Native(dir.path().to_path_buf()),
+            ForwardOutput::NONE,
+        )
+        .await?
+        .with_stop_grace_period(Duration::from_millis(200));
+        assert_eq!(monitor.shutdown_microvm().await, StopOutcome::NotRunning);
+
+        // Exits on SIGTERM
+        let mut child = std::process::Command::new("sleep").arg("30").spawn()?;
+        monitor.microvm_pid = Some(child.id());
+        assert_eq!(monitor.shutdown_microvm().await, StopOutcome::Graceful);
+        assert_eq!(child.wait()?.signal(), Some(libc::SIGTERM));
+
+        // Ignores SIGTERM, once the shell has exec'd into sleep
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "trap '' TERM; exec sleep 30"])
+            .spawn()?;
+        let comm = format!("/proc/{}/comm", child.id());
+        while std::fs::read_to_string(&comm)?.trim() != "sleep" {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        monitor.microvm_pid = Some(child.id());
+        assert_eq!(monitor.shutdown_microvm().await, StopOutcome::Killed);
+        assert_eq!(child.wait()?.signal(), Some(libc::SIGKILL));
+
+        // An exited but unreaped MicroVM isn't signalled
+        let mut child = std::process::Command::new("true").spawn()?;
+        while !microvm_exited(child.id(), true) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        monitor.microvm_pid = Some(child.id());
+        assert_eq!(monitor.shutdown_microvm().await, StopOutcome::NotRunning);
+        assert!(child.wait()?.success());
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_stop_leaves_the_pid_of_a_reaped_microvm_alone() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut monitor = MicroVmMonitor::new(
+            std::process::id(),
+            dir.path().join("sandbox.db"),
+            "app".to_string(),
+            "sandbox.yaml".to_string(),
+            Utc::now(),
+            dir.path(),
+            Rootfs::Native(dir.path().to_path_buf()),
+            ForwardOutput::NONE,
+        )
+        .await?;
+
+        // A reaped child is gone, even with its PID checked before anything else takes it
+        let mut child = std::process::Command::new("true").spawn()?;
+        child.wait()?;
+        monitor.microvm_pid = Some(child.id());
+        assert_eq!(monitor.shutdown_microvm().await, StopOutcome::NotRunning);
+
+        // A process that took over the PID of a reaped MicroVM isn't signalled
+        let mut reused = std::process::Command::new("sleep").arg("30").spawn()?;
+        monitor.microvm_pid = Some(reused.id());
+        monitor.on_reaped();
+        assert_eq!(monitor.shutdown_microvm().await, StopOutcome::NotRunning);
+        assert!(reused.try_wait()?.is_none());
+
+        reused.kill()?;
+        reused.wait()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_sigpipe_turns_broken_pipe_writes_into_errors() -> anyhow::Result<()> {
+        ignore_sigpipe(&Span::none());
+
+        // Reading the disposition back changes nothing for the tests running alongside
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::sigaction(libc::SIGPIPE, std::ptr::null(), &mut action) },
+            0
+        );
+        assert_eq!(action.sa_sigaction, libc::SIG_IGN);
+
+        // With the default disposition, this write would kill the test process
+        let (reader, writer) = unistd::pipe()?;
+        drop(reader);
+        let mut writer = std::fs::File::from(writer);
+        let err = writer.write_all(b"output").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        Ok(())
+    }
+
+    #[test]
+    fn test_enter_raw_mode_leaves_a_non_terminal_stdin_alone() -> anyhow::Result<()> {
+        use std::io::IsTerminal;
+
+        // Only meaningful where the test harness doesn't hand stdin over from a terminal
+        if io::stdin().is_terminal() {
+            return Ok(());
+        }
+        assert!(enter_raw_mode(&Span::none())?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_log_path_writable() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        // Missing directories are fine as long as an existing ancestor is writable
+        let log_path = MicroVmMonitor::log_path_for(dir.path(), "sandbox.yaml", "app");
+        assert_eq!(
+            log_path,
+            dir.path()
+                .join("sandbox.yaml")
+                .join(format!("app.{}", LOG_SUFFIX))
+        );
+        check_log_path_writable(&log_path)?;
+
+        // A file standing where a directory should be is reported
+        std::fs::write(dir.path().join("sandbox.yaml"), "")?;
+        assert!(matches!(
+            check_log_path_writable(&log_path),
+            Err(MicrosandboxError::LogPathNotWritable(..))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_db_optional_keeps_metadata_when_database_is_unavailable() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db_path = dir.path().join("missing").join("sandbox.db");
+        let (dir_path, db_path) = (dir.path(), &db_path);
+        let new_monitor = |db_optional| async move {
+            anyhow::Ok(
+                MicroVmMonitor::new(
+                    1,
+                    db_path,
+                    "app".to_string(),
+                    "sandbox.yaml".to_string(),
+                    Utc::now(),
+                    dir_path,
+                    Rootfs::Native(dir_path.to_path_buf()),
+                    ForwardOutput::NONE,
+                )
+                .await?
+                .with_db_optional(db_optional),
+            )
+        };
+        std::fs::create_dir_all(dir.path().join("sandbox.yaml"))?;
+
+        // Without db-optional mode the missing database fails the first write
+        let mut monitor = new_monitor(false).await?;
+        assert!(monitor.persist(DbWrite::Record).await.is_err());
+        assert!(!monitor.is_db_stale());
+
+        let mut monitor = new_monitor(true).await?;
+        monitor.metadata.status = SANDBOX_STATUS_RUNNING.to_string();
+        monitor.persist(DbWrite::Record).await?;
+        assert!(monitor.is_db_stale());
+
+        let marker = MicroVmMonitor::db_stale_marker_path(dir.path(), "sandbox.yaml", "app");
+        let recorded: serde_json::Value = serde_json::from_slice(&std::fs::read(marker)?)?;
+        assert_eq!(recorded["status"], SANDBOX_STATUS_RUNNING);
+        assert_eq!(recorded["supervisor_pid"], 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_events_are_recorded() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db_path = dir.path().join("sandbox.db");
+        db::initialize(&db_path, &db::SANDBOX_DB_MIGRATOR).await?;
+        let mut monitor = MicroVmMonitor::new(
+            1,
+            &db_path,
+            "app".to_string(),
+            "sandbox.yaml".to_string(),
+            Utc::now(),
+            dir.path(),
+            Rootfs::Native(dir.path().to_path_buf()),
+            ForwardOutput::NONE,
+        )
+        .await?;
+        let crashed = ExitStatus {
+            code: Some(1),
+            signal: None,
+            oom: false,
+            duration: Duration::from_secs(3),
+            peak_rss_bytes: None,
+            cpu_time: None,
+        };
+
+        // A start after a crash is a restart, and an exit the monitor asked for is not a crash
+        monitor.record_start_event().await;
+        monitor.on_exit(&crashed).await?;
+        assert_eq!(monitor.metadata.status, SANDBOX_STATUS_CRASHED);
+        monitor.record_start_event().await;
+        monitor.metadata.status = SANDBOX_STATUS_STOPPED.to_string();
+        monitor.stop_requested = true;
+        monitor.on_exit(&crashed).await?;
+        assert_eq!(monitor.metadata.status, SANDBOX_STATUS_STOPPED);
+
+        let pool = db::get_pool(&db_path).await?;
+        let events = db::query_events(&pool, &SandboxEventFilter::default()).await?;
+        let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                SandboxEventKind::Start,
+                SandboxEventKind::Crash,
+                SandboxEventKind::Restart
+            ]
+        );
+        assert_eq!(
+            events[1].detail.as_deref(),
+            Some("exited with code 1 after 3.0s")
+        );
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_exit_is_recorded_as_a_crash_unless_the_monitor_stopped_it() -> anyhow::Result<()>
+    {
+        let dir = tempfile::tempdir()?;
+        let db_path = dir.path().join("sandbox.db");
+        db::initialize(&db_path, &db::SANDBOX_DB_MIGRATOR).await?;
+        let pool = db::get_pool(&db_path).await?;
+        let recorded_status = || async {
+            db::get_sandbox(&pool, "app", "sandbox.yaml")
+                .await
+                .unwrap()
+                .unwrap()
+                .status
+        };
+        let mut monitor = MicroVmMonitor::new(
+            std::process::id(),
+            &db_path,
+            "app".to_string(),
+            "sandbox.yaml".to_string(),
+            Utc::now(),
+            dir.path(),
+            Rootfs::Native(dir.path().to_path_buf()),
+            ForwardOutput::NONE,
+        )
+        .await?
+        .with_stop_grace_period(Duration::from_secs(5));
+        let failed = ExitStatus {
+            code: Some(1),
+            signal: None,
+            oom: false,
+            duration: Duration::from_secs(3),
+            peak_rss_bytes: None,
+            cpu_time: None,
+        };
+
+        // A MicroVM exiting with an error while nobody asked it to stop has crashed
+        monitor.metadata.status = SANDBOX_STATUS_RUNNING.to_string();
+        monitor.persist(DbWrite::Record).await?;
+        monitor.on_exit(&failed).await?;
+        assert_eq!(recorded_status().await, SANDBOX_STATUS_CRASHED);
+
+        // One the monitor stopped is recorded as stopped, even though SIGTERM ended it
+        let mut child = std::process::Command::new("sleep").arg("30").spawn()?;
+        monitor.microvm_pid = Some(child.id());
+        monitor.metadata.status = SANDBOX_STATUS_RUNNING.to_string();
+        monitor.persist(DbWrite::Status).await?;
+        monitor.stop().await?;
+        assert_eq!(child.wait()?.signal(), Some(libc::SIGTERM));
+        monitor
+            .on_exit(&ExitStatus {
+                code: None,
+                signal: Some(libc::SIGTERM),
+                ..failed
+            })
+            .await?;
+        assert_eq!(recorded_status().await, SANDBOX_STATUS_STOPPED);
+        Ok(())
+    }
+
+    #[test]
+    fn test_forward_to_parent_stops_on_broken_pipe() {
+        /// Writer whose reader has gone away
+        struct ClosedPipe;
+
+        impl Write for ClosedPipe {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut out = Vec::new();
+        assert!(forward_to_parent(&mut out, b"hello", "stdout", 0, false));
+        assert_eq!(out, b"hello");
+
+        assert!(!forward_to_parent(
+            &mut ClosedPipe,
+            b"hello",
+            "stdout",
+            0,
+            false
+        ));
     }
 }
@@ -147,24 +147,32 @@ impl<R, M> MicroVmConfigBuilder<R, M> {
     /// ## Examples
     ///
     /// ```rust
-    /// use microsandbox_core::vm::{MicroVmConfigBuilder, Rootfs};
+    /// use microsandbox_core::vm::{MicroVmConfigBuilder, OverlayfsLayers, Rootfs};
     /// use std::path::PathBuf;
     ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let temp_dir = tempfile::tempdir()?;
+    /// # std::env::set_current_dir(temp_dir.path())?;
+    /// # std::fs::create_dir_all("layer1")?;
+    /// # std::fs::create_dir_all("upper")?;
     /// let config = MicroVmConfigBuilder::default()
     ///     // Option 1: Direct passthrough of a directory
     ///     .rootfs(Rootfs::Native(PathBuf::from("/path/to/rootfs")));
     ///
     /// let config = MicroVmConfigBuilder::default()
-    ///     // Option 2: Overlayfs with multiple layers
-    ///     .rootfs(Rootfs::Overlayfs(vec![
-    ///         PathBuf::from("/path/to/layer1"),
-    ///         PathBuf::from("/path/to/layer2")
-    ///     ]));
+    ///     // Option 2: Overlayfs with read-only layers under a writable one
+    ///     .rootfs(Rootfs::Overlayfs(OverlayfsLayers::new(
+    ///         vec![PathBuf::from("layer1")],
+    ///         PathBuf::from("upper"),
+    ///     )?));
+    /// # Ok(())
+    /// # }
     /// ```
     ///
     /// ## Notes
     /// - For Passthrough: The directory must exist and contain a valid root filesystem structure
-    /// - For Overlayfs: The layers are stacked in order, with later layers taking precedence
+    /// - For Overlayfs: The lower layers are stacked in order, with later layers taking
+    ///   precedence, and writes go to the upper layer
     /// - Common choices include Alpine Linux or Ubuntu root filesystems
     pub fn rootfs(self, rootfs: Rootfs) -> MicroVmConfigBuilder<Rootfs, M> {
         MicroVmConfigBuilder {
@@ -620,27 +628,32 @@ impl<R, M> MicroVmBuilder<R, M> {
     /// ## Examples
     ///
     /// ```rust
-    /// use microsandbox_core::vm::{MicroVmBuilder, Rootfs};
+    /// use microsandbox_core::vm::{MicroVmBuilder, OverlayfsLayers, Rootfs};
     /// use std::path::PathBuf;
     ///
     /// # fn main() -> anyhow::Result<()> {
+    /// # let temp_dir = tempfile::tempdir()?;
+    /// # std::env::set_current_dir(temp_dir.path())?;
+    /// # std::fs::create_dir_all("layer1")?;
+    /// # std::fs::create_dir_all("upper")?;
     /// // Option 1: Direct passthrough
     /// let vm = MicroVmBuilder::default()
     ///     .rootfs(Rootfs::Native(PathBuf::from("/path/to/rootfs")));
     ///
-    /// // Option 2: Overlayfs with layers
+    /// // Option 2: Overlayfs with read-only layers under a writable one
     /// let vm = MicroVmBuilder::default()
-    ///     .rootfs(Rootfs::Overlayfs(vec![
-    ///         PathBuf::from("/path/to/layer1"),
-    ///         PathBuf::from("/path/to/layer2")
-    ///     ]));
+    ///     .rootfs(Rootfs::Overlayfs(OverlayfsLayers::new(
+    ///         vec![PathBuf::from("layer1")],
+    ///         PathBuf::from("upper"),
+    ///     )?));
     /// # Ok(())
     /// # }
     /// ```
     ///
     /// ## Notes
     /// - For Passthrough: The directory must exist and contain a valid root filesystem structure
-    /// - For Overlayfs: The layers are stacked in order, with later layers taking precedence
+    /// - For Overlayfs: The lower layers are stacked in order, with later layers taking
+    ///   precedence, and writes go to the upper layer
     /// - Common choices include Alpine Linux or Ubuntu root filesystems
     /// - This is a required field - the build will fail if not set
     pub fn rootfs(self, rootfs: Rootfs) -> MicroVmBuilder<Rootfs, M> {
@@ -1121,10 +1134,15 @@ mod tests {
     use std::path::PathBuf;
 
     use super::*;
+    use crate::vm::OverlayfsLayers;
 
     #[test]
     fn test_microvm_builder() -> anyhow::Result<()> {
-        let rootfs = Rootfs::Overlayfs(vec![PathBuf::from("/tmp")]);
+        let temp_dir = tempfile::tempdir()?;
+        let rootfs = Rootfs::Overlayfs(OverlayfsLayers::new(
+            vec![PathBuf::from("/tmp")],
+            temp_dir.path().to_path_buf(),
+        )?);
         let workdir_path = "/workdir";
         let exec_path = "/bin/example";
 
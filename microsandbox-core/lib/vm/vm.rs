@@ -1,8 +1,16 @@
-use std::{ffi::CString, net::Ipv4Addr, path::PathBuf, ptr};
+use std::{
+    ffi::CString,
+    fmt,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    ptr,
+    str::FromStr,
+};
 
 use getset::Getters;
 use ipnetwork::Ipv4Network;
 use microsandbox_utils::SupportedPathType;
+use serde::{Deserialize, Serialize};
 use typed_path::Utf8UnixPathBuf;
 
 use crate::{
@@ -65,24 +73,58 @@ pub struct MicroVm {
 /// ## Variants
 ///
 /// * `Native(PathBuf)` - A native rootfs using a single path.
-/// * `Overlayfs(Vec<PathBuf>)` - An overlayfs rootfs using a list of paths.
+/// * `Overlayfs(OverlayfsLayers)` - An overlayfs rootfs using read-only lower layers and a
+///   writable upper layer.
+///
+/// A rootfs is recorded in the sandbox database in its [`Display`](fmt::Display) form, which
+/// parses back with [`FromStr`].
 ///
 /// ## Examples
 ///
 /// ```rust
-/// use microsandbox_core::vm::Rootfs;
+/// use microsandbox_core::vm::{OverlayfsLayers, Rootfs};
 /// use std::path::PathBuf;
+/// # fn main() -> anyhow::Result<()> {
+/// # let temp_dir = tempfile::tempdir()?;
+/// # let lower = temp_dir.path().join("lower");
+/// # let upper = temp_dir.path().join("upper");
+/// # std::fs::create_dir_all(&lower)?;
+/// # std::fs::create_dir_all(&upper)?;
 ///
 /// let native_root = Rootfs::Native(PathBuf::from("/path/to/root"));
-/// let overlayfs_root = Rootfs::Overlayfs(vec![PathBuf::from("/path/to/root1"), PathBuf::from("/path/to/root2")]);
+/// let overlayfs_root = Rootfs::Overlayfs(OverlayfsLayers::new(vec![lower], upper)?);
+///
+/// assert_eq!(native_root.to_string().parse::<Rootfs>()?, native_root);
+/// assert_eq!(overlayfs_root.to_string().parse::<Rootfs>()?, overlayfs_root);
+/// # Ok(())
+/// # }
 /// ```
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Rootfs {
     /// A rootfs using underlying native filesystem.
     Native(PathBuf),
 
-    /// An overlayfs rootfs using a list of paths.
-    Overlayfs(Vec<PathBuf>),
+    /// An overlayfs rootfs using read-only lower layers and a writable upper layer.
+    Overlayfs(OverlayfsLayers),
+}
+
+/// The layers of an overlayfs rootfs.
+///
+/// The lower layers are read-only and stacked in order, the first one at the bottom. Writes go
+/// to the upper layer, on top of them. libkrun keeps the overlay's bookkeeping in the upper
+/// layer itself, so unlike a kernel overlayfs mount there is no separate work directory.
+///
+/// [`OverlayfsLayers::new`] checks that the layers exist and that the upper one is writable,
+/// since layers passed in the wrong order make the MicroVM write into what should be a shared,
+/// read-only layer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct OverlayfsLayers {
+    /// The read-only layers, from the bottom up.
+    lower: Vec<PathBuf>,
+
+    /// The writable layer on top.
+    upper: PathBuf,
 }
 
 /// Configuration for a MicroVm instance.
@@ -326,10 +368,10 @@ impl MicroVm {
                     assert!(status >= 0, "failed to set rootfs: {}", status);
                 }
             }
-            Rootfs::Overlayfs(paths) => {
-                tracing::debug!("setting overlayfs rootfs: {:?}", paths);
-                let c_paths: Vec<_> = paths
-                    .iter()
+            Rootfs::Overlayfs(layers) => {
+                tracing::debug!("setting overlayfs rootfs: {:?}", layers);
+                let c_paths: Vec<_> = layers
+                    .stack()
                     .map(|p| CString::new(p.to_str().unwrap().as_bytes()).unwrap())
                     .collect();
                 let c_paths_ptrs = utils::to_null_terminated_c_array(&c_paths);
@@ -574,8 +616,8 @@ impl MicroVmConfig {
                     ));
                 }
             }
-            Rootfs::Overlayfs(paths) => {
-                for path in paths {
+            Rootfs::Overlayfs(layers) => {
+                for path in layers.stack() {
                     if !path.exists() {
                         return Err(MicrosandboxError::InvalidMicroVMConfig(
                             InvalidMicroVMConfigError::RootPathDoesNotExist(
@@ -666,6 +708,66 @@ impl MicroVmConfig {
     }
 }
 
+impl OverlayfsLayers {
+    /// Creates the layers of an overlayfs rootfs.
+    ///
+    /// ## Arguments
+    /// * `lower` - The read-only layers, from the bottom up
+    /// * `upper` - The writable layer on top
+    ///
+    /// ## Errors
+    /// Returns an error if there are no lower layers, if a layer is not an existing directory,
+    /// if the upper layer is not writable, or if it is also one of the lower layers.
+    pub fn new(lower: Vec<PathBuf>, upper: PathBuf) -> MicrosandboxResult<Self> {
+        if lower.is_empty() {
+            return Err(MicrosandboxError::InvalidMicroVMConfig(
+                InvalidMicroVMConfigError::NoLowerLayers,
+            ));
+        }
+
+        for path in lower.iter().chain([&upper]) {
+            if !path.is_dir() {
+                return Err(MicrosandboxError::InvalidMicroVMConfig(
+                    InvalidMicroVMConfigError::RootPathDoesNotExist(path.to_string_lossy().into()),
+                ));
+            }
+        }
+
+        if lower.contains(&upper) {
+            return Err(MicrosandboxError::InvalidMicroVMConfig(
+                InvalidMicroVMConfigError::UpperLayerIsLowerLayer(upper.to_string_lossy().into()),
+            ));
+        }
+
+        if nix::unistd::access(&upper, nix::unistd::AccessFlags::W_OK).is_err() {
+            return Err(MicrosandboxError::InvalidMicroVMConfig(
+                InvalidMicroVMConfigError::UpperLayerNotWritable(upper.to_string_lossy().into()),
+            ));
+        }
+
+        Ok(Self { lower, upper })
+    }
+
+    /// Creates the layers of an overlayfs rootfs from a stack of layers, the last of which is
+    /// the writable upper layer.
+    ///
+    /// ## Errors
+    /// Returns the errors of [`OverlayfsLayers::new`], taking an empty stack as having no lower
+    /// layers.
+    pub fn from_stack(mut layers: Vec<PathBuf>) -> MicrosandboxResult<Self> {
+        let upper = layers.pop().ok_or(MicrosandboxError::InvalidMicroVMConfig(
+            InvalidMicroVMConfigError::NoLowerLayers,
+        ))?;
+
+        Self::new(layers, upper)
+    }
+
+    /// Returns every layer from the bottom up, ending with the upper layer.
+    pub fn stack(&self) -> impl Iterator<Item = &Path> {
+        self.lower.iter().chain([&self.upper]).map(PathBuf::as_path)
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Trait Implementations
 //--------------------------------------------------------------------------------------------------
@@ -676,6 +778,46 @@ impl Drop for MicroVm {
     }
 }
 
+impl fmt::Display for Rootfs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rootfs::Native(path) => write!(f, "native:{}", escape_rootfs_path(path)),
+            Rootfs::Overlayfs(layers) => {
+                write!(f, "overlayfs")?;
+                for path in layers.stack() {
+                    write!(f, ":{}", escape_rootfs_path(path))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for Rootfs {
+    type Err = MicrosandboxError;
+
+    /// Parses a rootfs from its [`Display`](fmt::Display) form, without checking that its paths
+    /// exist.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MicrosandboxError::InvalidArgument(format!("invalid rootfs: {}", s));
+
+        let mut fields = split_rootfs_paths(s).into_iter();
+        let kind = fields.next().ok_or_else(invalid)?;
+        let mut paths: Vec<PathBuf> = fields.map(PathBuf::from).collect();
+        match kind.as_str() {
+            "native" if paths.len() == 1 => Ok(Rootfs::Native(paths.remove(0))),
+            "overlayfs" if paths.len() >= 2 => {
+                let upper = paths.pop().ok_or_else(invalid)?;
+                Ok(Rootfs::Overlayfs(OverlayfsLayers {
+                    lower: paths,
+                    upper,
+                }))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
 impl TryFrom<u8> for LogLevel {
     type Error = MicrosandboxError;
 
@@ -692,6 +834,31 @@ impl TryFrom<u8> for LogLevel {
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Escapes the separators in a rootfs path, so paths containing `:` stay unambiguous.
+fn escape_rootfs_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace(':', "\\:")
+}
+
+/// Splits the display form of a rootfs on its unescaped `:` separators, unescaping each field.
+fn split_rootfs_paths(s: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => fields.last_mut().unwrap().extend(chars.next()),
+            ':' => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -718,6 +885,67 @@ mod tests {
         assert_eq!(config.num_vcpus, DEFAULT_NUM_VCPUS);
     }
 
+    #[test]
+    fn test_overlayfs_layers_validation() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let lower = temp_dir.path().join("lower");
+        let upper = temp_dir.path().join("upper");
+        std::fs::create_dir_all(&lower)?;
+        std::fs::create_dir_all(&upper)?;
+
+        let layers = OverlayfsLayers::from_stack(vec![lower.clone(), upper.clone()])?;
+        assert_eq!(layers.get_lower(), &vec![lower.clone()]);
+        assert_eq!(layers.get_upper(), &upper);
+
+        assert!(matches!(
+            OverlayfsLayers::new(vec![], upper.clone()),
+            Err(MicrosandboxError::InvalidMicroVMConfig(
+                InvalidMicroVMConfigError::NoLowerLayers
+            ))
+        ));
+        assert!(matches!(
+            OverlayfsLayers::new(vec![temp_dir.path().join("missing")], upper.clone()),
+            Err(MicrosandboxError::InvalidMicroVMConfig(
+                InvalidMicroVMConfigError::RootPathDoesNotExist(_)
+            ))
+        ));
+        assert!(matches!(
+            OverlayfsLayers::new(vec![lower.clone(), upper.clone()], upper),
+            Err(MicrosandboxError::InvalidMicroVMConfig(
+                InvalidMicroVMConfigError::UpperLayerIsLowerLayer(_)
+            ))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rootfs_display_round_trips() -> anyhow::Result<()> {
+        let overlayfs = Rootfs::Overlayfs(OverlayfsLayers {
+            lower: vec![PathBuf::from("/layers/a:b"), PathBuf::from("/layers/c\\d")],
+            upper: PathBuf::from("/sandboxes/rw"),
+        });
+        assert_eq!(
+            overlayfs.to_string(),
+            "overlayfs:/layers/a\\:b:/layers/c\\\\d:/sandboxes/rw"
+        );
+        assert_eq!(overlayfs.to_string().parse::<Rootfs>()?, overlayfs);
+
+        let native = Rootfs::Native(PathBuf::from("/root"));
+        assert_eq!(native.to_string().parse::<Rootfs>()?, native);
+
+        // Rows written before paths were escaped parse the same way
+        assert_eq!(
+            "overlayfs:/a:/b:/rw".parse::<Rootfs>()?,
+            Rootfs::Overlayfs(OverlayfsLayers {
+                lower: vec![PathBuf::from("/a"), PathBuf::from("/b")],
+                upper: PathBuf::from("/rw"),
+            })
+        );
+        assert!("overlayfs:/rw".parse::<Rootfs>().is_err());
+        assert!("ext4:/rw".parse::<Rootfs>().is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_microvm_config_validation_success() {
         let temp_dir = TempDir::new().unwrap();
@@ -57,6 +57,8 @@
 //! JSON-RPC. In a real application, you might want to implement additional
 //! error handling and more sophisticated request/response processing.
 
+use std::collections::HashMap;
+
 use anyhow::Result;
 use reqwest::Client;
 use serde_json::{json, Value};
@@ -173,6 +175,8 @@ for i in range(1, 6):
         code: python_code.to_string(),
         language: "python".to_string(),
         timeout: Some(30), // Add a 30 second timeout
+        env: HashMap::new(),
+        cwd: None,
     };
 
     // Send sandbox.repl.run request with the typed parameters
@@ -230,6 +234,8 @@ for (let i = 0; i < 5; i++) {
         code: js_code.to_string(),
         language: "nodejs".to_string(),
         timeout: Some(30), // Add a 30 second timeout
+        env: HashMap::new(),
+        cwd: None,
     };
 
     // Send sandbox.repl.run request
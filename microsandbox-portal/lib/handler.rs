@@ -1,21 +1,57 @@
 //! Request handlers for the microsandbox portal JSON-RPC server.
 
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use std::{collections::BTreeMap, convert::Infallible, path::Path, time::Duration};
+
+use axum::{
+    body::{Body, BodyDataStream},
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::StreamExt;
+use microsandbox_utils::redact_env;
 use serde_json::{json, Value};
-use tracing::debug;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
 
 use crate::{
     error::PortalError,
     payload::{
-        JsonRpcError, JsonRpcRequest, JsonRpcResponse, SandboxCommandRunParams,
+        JsonRpcError, JsonRpcRequest, JsonRpcResponse, ProcessInputFrame, SandboxCommandRunParams,
+        SandboxFsListParams, SandboxFsReadParams, SandboxFsWatchParams, SandboxFsWriteParams,
+        SandboxProcessSpawnParams, SandboxReplFlushParams, SandboxReplPartialParams,
         SandboxReplRunParams, JSONRPC_VERSION,
     },
-    portal::command::create_command_executor,
+    portal::{
+        command::{create_command_executor, CommandHandle, CommandLine},
+        fs::{append_file, list_dir, read_file, write_file},
+        process::run_process,
+        repl::{start_engines, EngineHandle, EvalContext, Language, Line, Stream},
+    },
     state::SharedState,
 };
 
-#[cfg(any(feature = "python", feature = "nodejs"))]
-use crate::portal::repl::{start_engines, Language};
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Content type of streamed responses, which are newline-delimited JSON frames
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Number of frames buffered between a streaming method and its response
+const STREAM_CHANNEL_CAPACITY: usize = 64;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Splits a streamed request body into its newline-delimited frames
+struct FrameReader {
+    body: BodyDataStream,
+    pending: Vec<u8>,
+}
 
 //--------------------------------------------------------------------------------------------------
 // Functions
@@ -27,7 +63,11 @@ pub async fn json_rpc_handler(
     req: Json<JsonRpcRequest>,
 ) -> Result<impl IntoResponse, PortalError> {
     let request = req.0;
-    debug!(?request, "Received JSON-RPC request");
+    debug!(
+        method = %request.method,
+        params = %redact_env(&request.params),
+        "Received JSON-RPC request"
+    );
 
     // Check for required JSON-RPC fields
     if request.jsonrpc != JSONRPC_VERSION {
@@ -59,6 +99,32 @@ pub async fn json_rpc_handler(
                 }
             }
         }
+        "sandbox.repl.flush" => {
+            // Call the sandbox_repl_flush_impl function
+            match sandbox_repl_flush_impl(state, request.params).await {
+                Ok(result) => {
+                    // Create JSON-RPC response with success
+                    Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id))))
+                }
+                Err(e) => {
+                    // Use our helper function to create the error response
+                    Ok(create_error_response(e, id))
+                }
+            }
+        }
+        "sandbox.repl.partial" => {
+            // Call the sandbox_repl_partial_impl function
+            match sandbox_repl_partial_impl(state, request.params).await {
+                Ok(result) => {
+                    // Create JSON-RPC response with success
+                    Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id))))
+                }
+                Err(e) => {
+                    // Use our helper function to create the error response
+                    Ok(create_error_response(e, id))
+                }
+            }
+        }
         "sandbox.command.run" => {
             // Call the sandbox_command_run_impl function
             match sandbox_command_run_impl(state, request.params).await {
@@ -72,6 +138,71 @@ pub async fn json_rpc_handler(
                 }
             }
         }
+        "sandbox.env" => {
+            // Call the sandbox_env_impl function
+            match sandbox_env_impl(state, request.params).await {
+                Ok(result) => {
+                    // Create JSON-RPC response with success
+                    Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id))))
+                }
+                Err(e) => {
+                    // Use our helper function to create the error response
+                    Ok(create_error_response(e, id))
+                }
+            }
+        }
+        "sandbox.fs.write" => {
+            // Call the sandbox_fs_write_impl function
+            match sandbox_fs_write_impl(state, request.params).await {
+                Ok(result) => {
+                    // Create JSON-RPC response with success
+                    Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id))))
+                }
+                Err(e) => {
+                    // Use our helper function to create the error response
+                    Ok(create_error_response(e, id))
+                }
+            }
+        }
+        "sandbox.fs.read" => {
+            // Call the sandbox_fs_read_impl function
+            match sandbox_fs_read_impl(state, request.params).await {
+                Ok(result) => {
+                    // Create JSON-RPC response with success
+                    Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id))))
+                }
+                Err(e) => {
+                    // Use our helper function to create the error response
+                    Ok(create_error_response(e, id))
+                }
+            }
+        }
+        "sandbox.fs.list" => {
+            // Call the sandbox_fs_list_impl function
+            match sandbox_fs_list_impl(state, request.params).await {
+                Ok(result) => {
+                    // Create JSON-RPC response with success
+                    Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id))))
+                }
+                Err(e) => {
+                    // Use our helper function to create the error response
+                    Ok(create_error_response(e, id))
+                }
+            }
+        }
+        "sandbox.fs.watch" => {
+            // Call the sandbox_fs_watch_impl function
+            match sandbox_fs_watch_impl(state, request.params).await {
+                Ok(result) => {
+                    // Create JSON-RPC response with success
+                    Ok((StatusCode::OK, Json(JsonRpcResponse::success(result, id))))
+                }
+                Err(e) => {
+                    // Use our helper function to create the error response
+                    Ok(create_error_response(e, id))
+                }
+            }
+        }
         _ => {
             let error = PortalError::MethodNotFound(format!("Method not found: {}", method));
             Ok(create_error_response(error, id))
@@ -79,50 +210,299 @@ pub async fn json_rpc_handler(
     }
 }
 
+/// Handles JSON-RPC requests whose result is streamed
+///
+/// The response body is a series of newline-delimited JSON frames, sent as the method
+/// produces them and ending with a final frame. A request that fails before it starts
+/// streaming gets a single frame carrying the error.
+pub async fn json_rpc_stream_handler(
+    State(state): State<SharedState>,
+    req: Json<JsonRpcRequest>,
+) -> Response {
+    let request = req.0;
+    debug!(
+        method = %request.method,
+        params = %redact_env(&request.params),
+        "Received streaming JSON-RPC request"
+    );
+
+    let frames = match request.method.as_str() {
+        "sandbox.repl.stream" => sandbox_repl_stream_impl(state, request.params).await,
+        "sandbox.command.stream" => sandbox_command_stream_impl(state, request.params).await,
+        method => Err(PortalError::MethodNotFound(format!(
+            "Method not found: {}",
+            method
+        ))),
+    };
+
+    let frames = frames.unwrap_or_else(|e| {
+        let (frame_tx, frame_rx) = mpsc::channel(1);
+        let _ = frame_tx.try_send(error_frame(e));
+        frame_rx
+    });
+    frame_response(frames)
+}
+
+/// Handles JSON-RPC requests that stream input to the method while it streams its result back
+///
+/// The request body starts with the JSON-RPC request on its own line, followed by
+/// newline-delimited input frames. The response is streamed like that of
+/// [`json_rpc_stream_handler`].
+pub async fn json_rpc_duplex_handler(body: Body) -> Response {
+    let mut input = FrameReader::new(body.into_data_stream());
+
+    let frames = match input.next_frame().await {
+        Some(line) => match serde_json::from_slice::<JsonRpcRequest>(&line) {
+            Ok(request) if request.jsonrpc != JSONRPC_VERSION => Err(PortalError::JsonRpc(
+                "Invalid or missing jsonrpc version field".to_string(),
+            )),
+            Ok(request) => {
+                debug!(
+                    method = %request.method,
+                    params = %redact_env(&request.params),
+                    "Received duplex JSON-RPC request"
+                );
+                match request.method.as_str() {
+                    "sandbox.process.spawn" => sandbox_process_spawn_impl(request.params, input),
+                    method => Err(PortalError::MethodNotFound(format!(
+                        "Method not found: {}",
+                        method
+                    ))),
+                }
+            }
+            Err(e) => Err(PortalError::Parse(format!(
+                "Invalid JSON-RPC request: {}",
+                e
+            ))),
+        },
+        None => Err(PortalError::JsonRpc("Missing JSON-RPC request".to_string())),
+    };
+
+    let frames = frames.unwrap_or_else(|e| {
+        let (frame_tx, frame_rx) = mpsc::channel(1);
+        let _ = frame_tx.try_send(error_frame(e));
+        frame_rx
+    });
+    frame_response(frames)
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: Implementations
 //--------------------------------------------------------------------------------------------------
 
+/// Maps a guest file system error to a portal error, blaming the caller for bad paths
+fn fs_error(operation: &str, path: &str, e: std::io::Error) -> PortalError {
+    match e.kind() {
+        std::io::ErrorKind::InvalidInput | std::io::ErrorKind::NotFound => {
+            PortalError::JsonRpc(format!("Failed to {} {}: {}", operation, path, e))
+        }
+        _ => PortalError::Internal(format!("Failed to {} {}: {}", operation, path, e)),
+    }
+}
+
 /// Implementation for sandbox run method
-async fn sandbox_run_impl(_state: SharedState, params: Value) -> Result<Value, PortalError> {
-    debug!(?params, "Sandbox run method called");
+async fn sandbox_run_impl(state: SharedState, params: Value) -> Result<Value, PortalError> {
+    debug!(params = %redact_env(&params), "Sandbox run method called");
 
     // Deserialize parameters using the structured type
     let params: SandboxReplRunParams = serde_json::from_value(params)
         .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+    let (engine_handle, language, code) = prepare_repl_run(&state, &params).await?;
 
-    // Convert language string to Language enum
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    let language;
+    debug!("Language: {}", params.language);
 
-    match params.language.to_lowercase().as_str() {
-        #[cfg(feature = "python")]
-        "python" => language = Language::Python,
-        #[cfg(feature = "nodejs")]
-        "node" | "nodejs" | "javascript" => language = Language::Node,
-        _ => {
-            // Check if we're being asked for a language that is supported but not enabled via features
-            let error_msg = match params.language.to_lowercase().as_str() {
-                "python" => {
-                    "Python language support is not enabled. Recompile with --features python"
-                        .to_string()
-                }
-                "node" | "nodejs" | "javascript" => {
-                    "Node.js language support is not enabled. Recompile with --features nodejs"
-                        .to_string()
+    // Use a temporary identifier for evaluation
+    let temp_id = uuid::Uuid::new_v4().to_string();
+
+    // Execute the code in REPL
+    let evaluation = engine_handle
+        .eval_with_usage(code, language, temp_id, params.timeout)
+        .await
+        .map_err(|e| PortalError::Internal(format!("REPL execution failed: {}", e)))?;
+
+    debug!(
+        timed_out = evaluation.timed_out,
+        "REPL execution produced {} output lines",
+        evaluation.lines.len()
+    );
+
+    // Convert the lines to a format suitable for JSON
+    let output_lines: Vec<Value> = evaluation
+        .lines
+        .iter()
+        .map(|line| {
+            json!({
+                "stream": match line.stream {
+                    crate::portal::repl::Stream::Stdout => "stdout",
+                    crate::portal::repl::Stream::Stderr => "stderr",
+                },
+                "text": line.text,
+            })
+        })
+        .collect();
+
+    // Construct the result JSON object with explicit String conversions. An execution
+    // interrupted by its timeout still returns the output it produced
+    let result = json!({
+        "status": if evaluation.timed_out { "error" } else { "success" }.to_string(),
+        "language": params.language.to_string(),
+        "output": output_lines,
+        "resource_usage": evaluation.usage,
+        "timed_out": evaluation.timed_out,
+    });
+
+    debug!("Returning result with output: {}", result);
+
+    Ok(result)
+}
+
+/// Implementation for sandbox repl stream method
+///
+/// Runs code like `sandbox.repl.run`, but sends each line of output as a frame as soon as
+/// the REPL produces it. Lines are sent as base64-encoded bytes, newline included, and the
+/// final frame tells whether the execution was interrupted by its timeout.
+async fn sandbox_repl_stream_impl(
+    state: SharedState,
+    params: Value,
+) -> Result<mpsc::Receiver<Value>, PortalError> {
+    debug!(params = %redact_env(&params), "Sandbox repl stream method called");
+
+    // Deserialize parameters using the structured type
+    let params: SandboxReplRunParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+    let (engine_handle, language, code) = prepare_repl_run(&state, &params).await?;
+
+    let (frame_tx, frame_rx) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+    tokio::spawn(async move {
+        let (line_tx, mut line_rx) = mpsc::channel::<Line>(STREAM_CHANNEL_CAPACITY);
+        let forward = async {
+            while let Some(line) = line_rx.recv().await {
+                let frame = json!({
+                    "stream": stream_name(line.stream),
+                    "data": BASE64.encode(format!("{}\n", line.text)),
+                });
+                let _ = frame_tx.send(frame).await;
+            }
+        };
+
+        let temp_id = uuid::Uuid::new_v4().to_string();
+        let (result, ()) = tokio::join!(
+            engine_handle.eval_streaming(code, language, temp_id, params.timeout, line_tx),
+            forward
+        );
+
+        let last = match result {
+            Ok(timed_out) => json!({ "done": true, "timed_out": timed_out }),
+            Err(e) => error_frame(PortalError::Internal(format!(
+                "REPL execution failed: {}",
+                e
+            ))),
+        };
+        let _ = frame_tx.send(last).await;
+    });
+
+    Ok(frame_rx)
+}
+
+/// Implementation for sandbox process spawn method
+///
+/// Starts a process and writes the stdin frames that follow the request to it, closing its
+/// stdin at the EOF frame or the end of the body. Each line of output is sent as a frame as
+/// soon as the process writes it, and the final frame carries its exit code.
+fn sandbox_process_spawn_impl(
+    params: Value,
+    mut input: FrameReader,
+) -> Result<mpsc::Receiver<Value>, PortalError> {
+    debug!(?params, "Sandbox process spawn method called");
+
+    // Deserialize parameters using the structured type
+    let params: SandboxProcessSpawnParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+
+    // Pass the stdin frames on until the caller has no more input
+    let (stdin_tx, stdin_rx) = mpsc::channel::<String>(STREAM_CHANNEL_CAPACITY);
+    tokio::spawn(async move {
+        while let Some(frame) = input.next_frame().await {
+            let frame: ProcessInputFrame = match serde_json::from_slice(&frame) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    warn!("Closing stdin after an invalid input frame: {}", e);
+                    break;
                 }
-                _ => format!("Unsupported language: {}", params.language),
             };
-            return Err(PortalError::JsonRpc(error_msg));
+
+            if let Some(data) = frame.stdin {
+                if stdin_tx.send(data).await.is_err() {
+                    break;
+                }
+            }
+
+            if frame.eof {
+                break;
+            }
         }
+    });
+
+    let (frame_tx, frame_rx) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+    tokio::spawn(async move {
+        let (line_tx, mut line_rx) = mpsc::channel::<CommandLine>(STREAM_CHANNEL_CAPACITY);
+        let output_tx = frame_tx.clone();
+
+        // Stop reading output once the caller is gone, which kills the process
+        let forward = async move {
+            while let Some(line) = line_rx.recv().await {
+                let frame = json!({ "stream": stream_name(line.stream), "text": line.text });
+                if output_tx.send(frame).await.is_err() {
+                    break;
+                }
+            }
+        };
+
+        let (result, ()) = tokio::join!(
+            run_process(&params.command, &params.args, stdin_rx, line_tx),
+            forward
+        );
+
+        let last = match result {
+            Ok(exit_code) => json!({ "exit_code": exit_code }),
+            Err(e) => error_frame(PortalError::Internal(format!("Process failed: {}", e))),
+        };
+        let _ = frame_tx.send(last).await;
+    });
+
+    Ok(frame_rx)
+}
+
+/// Checks the params of a REPL execution and gets it ready to run
+///
+/// Returns the REPL engines, starting them on first use, with the language to run the code
+/// in and the code wrapped in the environment it asks for.
+async fn prepare_repl_run(
+    state: &SharedState,
+    params: &SandboxReplRunParams,
+) -> Result<(EngineHandle, Language, String), PortalError> {
+    // Check the environment the code asks for before touching the REPL
+    let context = EvalContext {
+        env: params.env.clone(),
+        cwd: params.cwd.clone(),
     };
+    context.validate().map_err(PortalError::JsonRpc)?;
+    if let Some(cwd) = &context.cwd {
+        if !Path::new(cwd).is_dir() {
+            return Err(PortalError::JsonRpc(format!(
+                "Working directory {} does not exist",
+                cwd
+            )));
+        }
+    }
+
+    let language = repl_language(&params.language)?;
 
     // Get or initialize engine handle
     // With tokio::sync::Mutex, we can safely .await while holding the lock
-    #[cfg(any(feature = "python", feature = "nodejs"))]
     let engine_handle = {
         // Get the current engine handle if it exists
-        let mut lock = _state.engine_handle.lock().await;
+        let mut lock = state.engine_handle.lock().await;
 
         if let Some(ref handle) = *lock {
             handle.clone()
@@ -139,25 +519,109 @@ async fn sandbox_run_impl(_state: SharedState, params: Value) -> Result<Value, P
         }
     };
 
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    debug!("Language: {}", params.language);
+    let code = wrap_code(&context, &params.code, language);
+    Ok((engine_handle, language, code))
+}
 
-    // Use a temporary identifier for evaluation
+/// Wraps code so it runs in the environment it asks for, in the REPL of its language
+fn wrap_code(context: &EvalContext, code: &str, language: Language) -> String {
     #[cfg(any(feature = "python", feature = "nodejs"))]
-    let temp_id = uuid::Uuid::new_v4().to_string();
+    {
+        context.wrap(code, language)
+    }
 
-    // Execute the code in REPL
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    let lines = engine_handle
-        .eval(&params.code, language, &temp_id, params.timeout)
-        .await
-        .map_err(|e| PortalError::Internal(format!("REPL execution failed: {}", e)))?;
+    #[cfg(not(any(feature = "python", feature = "nodejs")))]
+    {
+        let _ = (context, code);
+        match language {}
+    }
+}
 
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    debug!("REPL execution produced {} output lines", lines.len());
+/// Converts the language of a REPL request into the REPL's language, if it is enabled
+fn repl_language(name: &str) -> Result<Language, PortalError> {
+    match name.to_lowercase().as_str() {
+        #[cfg(feature = "python")]
+        "python" => Ok(Language::Python),
+        #[cfg(feature = "nodejs")]
+        "node" | "nodejs" | "javascript" => Ok(Language::Node),
+        _ => {
+            // Check if we're being asked for a language that is supported but not enabled via features
+            let error_msg = match name.to_lowercase().as_str() {
+                "python" => {
+                    "Python language support is not enabled. Recompile with --features python"
+                        .to_string()
+                }
+                "node" | "nodejs" | "javascript" => {
+                    "Node.js language support is not enabled. Recompile with --features nodejs"
+                        .to_string()
+                }
+                _ => format!("Unsupported language: {}", name),
+            };
+            Err(PortalError::JsonRpc(error_msg))
+        }
+    }
+}
+
+/// Implementation for sandbox repl flush method
+///
+/// Asks the REPL engine to emit any partial output line it is holding as output of the
+/// running evaluation. Does nothing if the engines haven't been started yet.
+async fn sandbox_repl_flush_impl(_state: SharedState, params: Value) -> Result<Value, PortalError> {
+    debug!(?params, "Sandbox repl flush method called");
+
+    // Deserialize parameters using the structured type
+    let params: SandboxReplFlushParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
 
-    // Convert the lines to a format suitable for JSON
     #[cfg(any(feature = "python", feature = "nodejs"))]
+    {
+        let language = match params.language.to_lowercase().as_str() {
+            #[cfg(feature = "python")]
+            "python" => Language::Python,
+            #[cfg(feature = "nodejs")]
+            "node" | "nodejs" | "javascript" => Language::Node,
+            _ => {
+                return Err(PortalError::JsonRpc(format!(
+                    "Unsupported language: {}",
+                    params.language
+                )))
+            }
+        };
+
+        if let Some(handle) = _state.engine_handle.lock().await.as_ref() {
+            handle.flush(language);
+        }
+
+        Ok(json!({ "status": "success" }))
+    }
+
+    #[cfg(not(any(feature = "python", feature = "nodejs")))]
+    Err(PortalError::JsonRpc(format!(
+        "{} language support is not enabled",
+        params.language
+    )))
+}
+
+/// Implementation for sandbox repl partial method
+///
+/// Returns the output the most recent REPL evaluation has produced so far, so a client that
+/// gave up waiting on `sandbox.repl.run` can still read it. Returns no output if the engines
+/// haven't been started yet.
+async fn sandbox_repl_partial_impl(
+    state: SharedState,
+    params: Value,
+) -> Result<Value, PortalError> {
+    debug!(?params, "Sandbox repl partial method called");
+
+    // Deserialize parameters using the structured type
+    let params: SandboxReplPartialParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+
+    let lines = match state.engine_handle.lock().await.as_ref() {
+        Some(handle) => handle.partial_output(),
+        None => Vec::new(),
+    };
+
     let output_lines: Vec<Value> = lines
         .iter()
         .map(|line| {
@@ -171,19 +635,107 @@ async fn sandbox_run_impl(_state: SharedState, params: Value) -> Result<Value, P
         })
         .collect();
 
-    // Construct the result JSON object with explicit String conversions
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    let result = json!({
-        "status": "success".to_string(),
-        "language": params.language.to_string(),
+    Ok(json!({
+        "status": "partial",
+        "language": params.language.unwrap_or_default(),
         "output": output_lines,
-    });
+    }))
+}
 
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    debug!("Returning result with output: {}", result);
+/// Implementation for sandbox env method
+///
+/// Returns the environment variables of the portal process, which are the effective
+/// environment of the guest.
+async fn sandbox_env_impl(_state: SharedState, params: Value) -> Result<Value, PortalError> {
+    debug!(?params, "Sandbox env method called");
 
-    #[cfg(any(feature = "python", feature = "nodejs"))]
-    Ok(result)
+    let env: BTreeMap<String, String> = std::env::vars().collect();
+    Ok(json!({ "env": env }))
+}
+
+/// Implementation for sandbox fs write method
+async fn sandbox_fs_write_impl(_state: SharedState, params: Value) -> Result<Value, PortalError> {
+    // Deserialize parameters using the structured type
+    let params: SandboxFsWriteParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+    debug!(path = %params.path, mode = ?params.mode, "Sandbox fs write method called");
+
+    let contents = BASE64
+        .decode(&params.content)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid file content: {}", e)))?;
+
+    let path = Path::new(&params.path);
+    let result = if params.append {
+        append_file(path, &contents).await
+    } else {
+        write_file(path, &contents, params.mode).await
+    };
+    result.map_err(|e| fs_error("write", &params.path, e))?;
+
+    Ok(json!({
+        "path": params.path,
+        "size": contents.len(),
+    }))
+}
+
+/// Implementation for sandbox fs read method
+async fn sandbox_fs_read_impl(_state: SharedState, params: Value) -> Result<Value, PortalError> {
+    // Deserialize parameters using the structured type
+    let params: SandboxFsReadParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+    debug!(path = %params.path, offset = params.offset, length = ?params.length, "Sandbox fs read method called");
+
+    let (contents, size) = read_file(Path::new(&params.path), params.offset, params.length)
+        .await
+        .map_err(|e| fs_error("read", &params.path, e))?;
+
+    Ok(json!({
+        "path": params.path,
+        "content": BASE64.encode(&contents),
+        "offset": params.offset,
+        "size": size,
+    }))
+}
+
+/// Implementation for sandbox fs list method
+async fn sandbox_fs_list_impl(_state: SharedState, params: Value) -> Result<Value, PortalError> {
+    // Deserialize parameters using the structured type
+    let params: SandboxFsListParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+    debug!(path = %params.path, "Sandbox fs list method called");
+
+    let entries = list_dir(Path::new(&params.path))
+        .await
+        .map_err(|e| fs_error("list", &params.path, e))?;
+
+    Ok(json!({
+        "path": params.path,
+        "entries": entries,
+    }))
+}
+
+/// Implementation for sandbox fs watch method
+///
+/// Starts a watch when called without a cursor, and otherwise waits up to the requested
+/// timeout for events recorded after the cursor.
+async fn sandbox_fs_watch_impl(state: SharedState, params: Value) -> Result<Value, PortalError> {
+    // Deserialize parameters using the structured type
+    let params: SandboxFsWatchParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+    debug!(path = %params.path, cursor = ?params.cursor, "Sandbox fs watch method called");
+
+    let poll = state
+        .fs_watches
+        .poll(
+            Path::new(&params.path),
+            &params.patterns,
+            params.cursor.as_deref(),
+            Duration::from_secs(params.timeout),
+        )
+        .await
+        .map_err(|e| fs_error("watch", &params.path, e))?;
+
+    Ok(json!(poll))
 }
 
 /// Implementation for sandbox command run method
@@ -194,23 +746,7 @@ async fn sandbox_command_run_impl(state: SharedState, params: Value) -> Result<V
     let params: SandboxCommandRunParams = serde_json::from_value(params)
         .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
 
-    // Get or initialize command executor handle
-    let cmd_handle = {
-        // Get the current command handle if it exists
-        let mut lock = state.command_handle.lock().await;
-
-        if let Some(ref handle) = *lock {
-            handle.clone()
-        } else {
-            // Otherwise initialize a new command executor
-            let handle = create_command_executor();
-
-            // Store the new handle in the shared state
-            *lock = Some(handle.clone());
-
-            handle
-        }
-    };
+    let cmd_handle = command_handle(&state).await;
 
     // Execute the command
     let (exit_code, output_lines) = cmd_handle
@@ -246,15 +782,123 @@ async fn sandbox_command_run_impl(state: SharedState, params: Value) -> Result<V
     Ok(result)
 }
 
+/// Implementation for sandbox command stream method
+///
+/// Runs a command like `sandbox.command.run`, but sends each line of output as a frame as
+/// soon as the command writes it. The final frame carries the command's exit code.
+async fn sandbox_command_stream_impl(
+    state: SharedState,
+    params: Value,
+) -> Result<mpsc::Receiver<Value>, PortalError> {
+    debug!(?params, "Sandbox command stream method called");
+
+    // Deserialize parameters using the structured type
+    let params: SandboxCommandRunParams = serde_json::from_value(params)
+        .map_err(|e| PortalError::JsonRpc(format!("Invalid parameters: {}", e)))?;
+    let cmd_handle = command_handle(&state).await;
+
+    let (frame_tx, frame_rx) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+    tokio::spawn(async move {
+        let (line_tx, mut line_rx) = mpsc::channel::<CommandLine>(STREAM_CHANNEL_CAPACITY);
+        let forward = async {
+            while let Some(line) = line_rx.recv().await {
+                let frame = json!({ "stream": stream_name(line.stream), "text": line.text });
+                let _ = frame_tx.send(frame).await;
+            }
+        };
+
+        let (result, ()) = tokio::join!(
+            cmd_handle.execute_streaming(params.command, params.args, params.timeout, line_tx),
+            forward
+        );
+
+        let last = match result {
+            Ok(exit_code) => json!({ "exit_code": exit_code }),
+            Err(e) => error_frame(PortalError::Internal(format!(
+                "Command execution failed: {}",
+                e
+            ))),
+        };
+        let _ = frame_tx.send(last).await;
+    });
+
+    Ok(frame_rx)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FrameReader {
+    /// Creates a reader of the frames of a request body
+    fn new(body: BodyDataStream) -> Self {
+        Self {
+            body,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Reads the next non-empty frame, without its trailing newline
+    ///
+    /// Returns `None` at the end of the body, or if reading it fails. Text after the last
+    /// newline is returned as a final frame.
+    async fn next_frame(&mut self) -> Option<Vec<u8>> {
+        loop {
+            while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+                let frame: Vec<u8> = self.pending.drain(..=end).collect();
+                let frame = &frame[..end];
+                if !frame.iter().all(u8::is_ascii_whitespace) {
+                    return Some(frame.to_vec());
+                }
+            }
+
+            match self.body.next().await {
+                Some(Ok(chunk)) => self.pending.extend_from_slice(&chunk),
+                _ => {
+                    let frame = std::mem::take(&mut self.pending);
+                    return (!frame.iter().all(u8::is_ascii_whitespace)).then_some(frame);
+                }
+            }
+        }
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
+/// Gets the command executor, starting it on first use
+async fn command_handle(state: &SharedState) -> CommandHandle {
+    // Get the current command handle if it exists
+    let mut lock = state.command_handle.lock().await;
+
+    if let Some(ref handle) = *lock {
+        handle.clone()
+    } else {
+        // Otherwise initialize a new command executor
+        let handle = create_command_executor();
+
+        // Store the new handle in the shared state
+        *lock = Some(handle.clone());
+
+        handle
+    }
+}
+
 /// Helper function to create a JSON-RPC error response from a PortalError
 fn create_error_response(
     error: PortalError,
     id: Option<Value>,
 ) -> (StatusCode, Json<JsonRpcResponse>) {
+    // Return the properly formatted error response
+    (
+        StatusCode::BAD_REQUEST,
+        Json(JsonRpcResponse::error(json_rpc_error(error), id)),
+    )
+}
+
+/// Converts a PortalError into a JSON-RPC error
+fn json_rpc_error(error: PortalError) -> JsonRpcError {
     // Determine appropriate JSON-RPC error code
     let code = match &error {
         PortalError::JsonRpc(_) => -32600,        // Invalid Request
@@ -263,15 +907,142 @@ fn create_error_response(
         PortalError::Internal(_) => -32603,       // Internal error
     };
 
-    let json_rpc_error = JsonRpcError {
+    JsonRpcError {
         code,
         message: error.to_string(),
         data: None,
-    };
+    }
+}
+
+/// Creates the frame of a streamed response that reports an error and ends the stream
+fn error_frame(error: PortalError) -> Value {
+    json!({ "error": json_rpc_error(error) })
+}
+
+/// Gets the name of an output stream, as sent in responses
+fn stream_name(stream: Stream) -> &'static str {
+    match stream {
+        Stream::Stdout => "stdout",
+        Stream::Stderr => "stderr",
+    }
+}
+
+/// Creates a streamed response that sends each frame as a line of JSON as it arrives
+fn frame_response(frames: mpsc::Receiver<Value>) -> Response {
+    let body = futures::stream::unfold(frames, |mut frames| async move {
+        let frame = frames.recv().await?;
+        let mut line = frame.to_string().into_bytes();
+        line.push(b'\n');
+        Some((Ok::<_, Infallible>(line), frames))
+    });
 
-    // Return the properly formatted error response
     (
-        StatusCode::BAD_REQUEST,
-        Json(JsonRpcResponse::error(json_rpc_error, id)),
+        [(header::CONTENT_TYPE, NDJSON_CONTENT_TYPE)],
+        Body::from_stream(body),
     )
+        .into_response()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends a request to the streaming handler and reads back the frames it streams
+    async fn stream_frames(method: &str, params: Value) -> Vec<Value> {
+        let request = JsonRpcRequest {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            method: method.to_string(),
+            params,
+            id: Some(json!(1)),
+        };
+        let response = json_rpc_stream_handler(State(SharedState::default()), Json(request)).await;
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            NDJSON_CONTENT_TYPE
+        );
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.ends_with('\n'));
+        body.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    /// Parses a frame read from a streamed response
+    fn frame_value(frame: Option<Vec<u8>>) -> Value {
+        serde_json::from_slice(&frame.expect("stream ended early")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stream_reports_unknown_language_as_error_frame() {
+        let frames = stream_frames(
+            "sandbox.repl.stream",
+            json!({ "code": "print(1)", "language": "cobol" }),
+        )
+        .await;
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0]["error"]["code"], -32600);
+    }
+
+    #[tokio::test]
+    async fn test_stream_reports_unknown_method_as_error_frame() {
+        let frames = stream_frames("sandbox.repl.run", json!({})).await;
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0]["error"]["code"], -32601);
+    }
+
+    #[tokio::test]
+    async fn test_command_stream_sends_lines_then_exit_code() {
+        let frames = stream_frames(
+            "sandbox.command.stream",
+            json!({ "command": "sh", "args": ["-c", "echo one; echo two >&2; exit 3"] }),
+        )
+        .await;
+
+        let (last, lines) = frames.split_last().unwrap();
+        assert_eq!(last, &json!({ "exit_code": 3 }));
+        assert_eq!(lines.len(), 2);
+        assert!(lines.contains(&json!({ "stream": "stdout", "text": "one" })));
+        assert!(lines.contains(&json!({ "stream": "stderr", "text": "two" })));
+    }
+
+    #[tokio::test]
+    async fn test_process_spawn_streams_input_and_output() {
+        let (body_tx, body_rx) = mpsc::channel::<&'static str>(8);
+        let body = futures::stream::unfold(body_rx, |mut rx| async move {
+            let chunk = rx.recv().await?;
+            Some((Ok::<_, Infallible>(chunk), rx))
+        });
+        body_tx
+            .send("{\"jsonrpc\":\"2.0\",\"method\":\"sandbox.process.spawn\",\"params\":{\"command\":\"cat\"},\"id\":1}\n")
+            .await
+            .unwrap();
+
+        let response = json_rpc_duplex_handler(Body::from_stream(body)).await;
+        let mut output = FrameReader::new(response.into_body().into_data_stream());
+
+        // Input split across chunks still arrives as whole frames
+        body_tx.send("{\"stdin\":\"hel").await.unwrap();
+        body_tx.send("lo\\n\"}\n").await.unwrap();
+        assert_eq!(
+            frame_value(output.next_frame().await),
+            json!({ "stream": "stdout", "text": "hello" })
+        );
+
+        // The EOF frame closes stdin, after which cat exits
+        body_tx.send("{\"eof\":true}\n").await.unwrap();
+        assert_eq!(
+            frame_value(output.next_frame().await),
+            json!({ "exit_code": 0 })
+        );
+    }
 }
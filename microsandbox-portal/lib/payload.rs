@@ -1,5 +1,7 @@
 //! JSON-RPC payload structures for microsandbox portal.
 
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -80,6 +82,27 @@ pub struct SandboxReplRunParams {
 
     /// Optional timeout in seconds after which execution will be cancelled
     pub timeout: Option<u64>,
+
+    /// Environment variables set while the code runs
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+
+    /// Working directory the code runs in, instead of the REPL's own
+    pub cwd: Option<String>,
+}
+
+/// Request parameters for flushing buffered REPL output
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxReplFlushParams {
+    /// Programming language of the REPL to flush
+    pub language: String,
+}
+
+/// Request parameters for reading the output of the most recent REPL evaluation
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxReplPartialParams {
+    /// Programming language of the evaluation, if known
+    pub language: Option<String>,
 }
 
 /// Request parameters for executing a shell command
@@ -96,6 +119,85 @@ pub struct SandboxCommandRunParams {
     pub timeout: Option<u64>,
 }
 
+/// Request parameters for starting a long-running process with its stdin left open
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxProcessSpawnParams {
+    /// Command to execute
+    pub command: String,
+
+    /// Optional arguments for the command
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// A frame of input sent to a process after the `sandbox.process.spawn` request
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProcessInputFrame {
+    /// Text to write to the process's stdin
+    pub stdin: Option<String>,
+
+    /// Whether this is the last frame, after which the process's stdin is closed
+    #[serde(default)]
+    pub eof: bool,
+}
+
+/// Request parameters for writing a file in the guest
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxFsWriteParams {
+    /// Absolute path of the file in the guest
+    pub path: String,
+
+    /// Base64-encoded contents of the file
+    pub content: String,
+
+    /// Permission bits of the file, e.g. `0o644`
+    pub mode: Option<u32>,
+
+    /// Append to the existing file instead of replacing it, for the later chunks of a large
+    /// upload
+    #[serde(default)]
+    pub append: bool,
+}
+
+/// Request parameters for reading a file in the guest
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxFsReadParams {
+    /// Absolute path of the file in the guest
+    pub path: String,
+
+    /// Byte offset to start reading at
+    #[serde(default)]
+    pub offset: u64,
+
+    /// Maximum number of bytes to read; the rest of the file if not set
+    pub length: Option<u64>,
+}
+
+/// Request parameters for listing a directory in the guest
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxFsListParams {
+    /// Absolute path of the directory in the guest
+    pub path: String,
+}
+
+/// Request parameters for polling a file system watch in the guest
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxFsWatchParams {
+    /// Absolute path of the watched directory in the guest
+    pub path: String,
+
+    /// Glob patterns a changed path must match to be reported; empty matches everything
+    #[serde(default)]
+    pub patterns: Vec<String>,
+
+    /// Cursor returned by the previous poll; starts a new watch if not set
+    pub cursor: Option<String>,
+
+    /// How long to wait for events, in seconds
+    #[serde(default)]
+    pub timeout: u64,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Methods
 //--------------------------------------------------------------------------------------------------
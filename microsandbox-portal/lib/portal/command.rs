@@ -166,7 +166,7 @@ impl CommandHandle {
         Self { cmd_sender }
     }
 
-    /// Executes a command and streams the output
+    /// Executes a command and collects its output
     ///
     /// # Parameters
     ///
@@ -183,6 +183,44 @@ impl CommandHandle {
         args: Vec<String>,
         timeout: Option<u64>,
     ) -> Result<(i32, Vec<CommandLine>), CommandError> {
+        let (line_tx, mut line_rx) = mpsc::channel::<CommandLine>(100);
+
+        // Collect all output lines while the command runs
+        let collect = async {
+            let mut lines = Vec::new();
+            while let Some(line) = line_rx.recv().await {
+                lines.push(line);
+            }
+            lines
+        };
+
+        let (result, lines) = tokio::join!(
+            self.execute_streaming(command, args, timeout, line_tx),
+            collect
+        );
+
+        Ok((result?, lines))
+    }
+
+    /// Executes a command, sending each line of its output to `lines` as soon as it is read
+    ///
+    /// # Parameters
+    ///
+    /// * `command` - The command to execute
+    /// * `args` - Arguments to pass to the command
+    /// * `timeout` - Optional timeout in seconds after which execution will be cancelled
+    /// * `lines` - Receives the output lines, and is dropped once the last one is sent
+    ///
+    /// # Returns
+    ///
+    /// The exit code of the command
+    pub async fn execute_streaming<S: Into<String>>(
+        &self,
+        command: S,
+        args: Vec<String>,
+        timeout: Option<u64>,
+        lines: Sender<CommandLine>,
+    ) -> Result<i32, CommandError> {
         let command = command.into();
 
         // Generate a unique execution ID
@@ -190,7 +228,6 @@ impl CommandHandle {
 
         // Channels for communication
         let (resp_tx, mut resp_rx) = mpsc::channel::<CommandResp>(100);
-        let (line_tx, mut line_rx) = mpsc::channel::<CommandLine>(100);
         let (done_tx, done_rx) = oneshot::channel::<Result<i32, CommandError>>();
 
         // Send the command execution request
@@ -206,56 +243,25 @@ impl CommandHandle {
             .await
             .map_err(|_| CommandError::Unavailable("Command executor not available".to_string()))?;
 
-        // Process responses in a separate task
-        let process_handle = tokio::spawn(async move {
-            let mut exit_code = 0;
-
-            while let Some(resp) = resp_rx.recv().await {
-                match resp {
-                    CommandResp::Line {
-                        id: _,
-                        stream,
-                        text,
-                    } => {
-                        let _ = line_tx.send(CommandLine { stream, text }).await;
-                    }
-                    CommandResp::Done {
-                        id: _,
-                        exit_code: code,
-                    } => {
-                        exit_code = code;
-                        break;
-                    }
-                    CommandResp::Error { id: _, message } => {
-                        let _ = line_tx
-                            .send(CommandLine {
-                                stream: Stream::Stderr,
-                                text: format!("Error: {}", message),
-                            })
-                            .await;
-                        break;
-                    }
-                }
+        // Forward output until the executor drops its sender. The completion can arrive before
+        // the last lines are read, so reading stops at the end of the channel, not at `Done`.
+        // Errors are reported through the result.
+        while let Some(resp) = resp_rx.recv().await {
+            if let CommandResp::Line {
+                id: _,
+                stream,
+                text,
+            } = resp
+            {
+                let _ = lines.send(CommandLine { stream, text }).await;
             }
-
-            exit_code
-        });
-
-        // Collect all output lines
-        let mut lines = Vec::new();
-        while let Some(line) = line_rx.recv().await {
-            lines.push(line);
         }
-
-        // Wait for processing to complete
-        let _exit_code = process_handle.await.unwrap_or(1);
+        drop(lines);
 
         // Wait for execution completion
-        let result = done_rx
+        done_rx
             .await
-            .map_err(|_| CommandError::ExecutionError("Command execution failed".to_string()))??;
-
-        Ok((result, lines))
+            .map_err(|_| CommandError::ExecutionError("Command execution failed".to_string()))?
     }
 }
 
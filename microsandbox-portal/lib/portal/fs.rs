@@ -1 +1,259 @@
-//! TODO: Implement file system operations for the microsandbox portal.
+//! File system operations for the microsandbox portal.
+//!
+//! This module reads, writes and lists files in the guest on behalf of the host, so sandboxes
+//! can be provisioned and inspected without going through a shell. Paths are always absolute
+//! paths in the guest; missing parent directories are created on write.
+
+use std::{
+    io,
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path},
+};
+
+use serde::Serialize;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An entry of a directory listing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirEntry {
+    /// Name of the entry within its directory
+    pub name: String,
+
+    /// Size of the entry in bytes
+    pub size: u64,
+
+    /// Permission bits of the entry, e.g. `0o644`
+    pub mode: u32,
+
+    /// Whether the entry is a directory
+    pub is_dir: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Writes `contents` to the file at `path`, replacing any existing file.
+///
+/// The file is written to a temporary file next to it and renamed into place, so a reader
+/// never sees it half-written. With a `mode`, the file's permission bits are set to it;
+/// otherwise they follow the process umask.
+///
+/// ## Errors
+///
+/// Will return an error if:
+/// * `path` is not absolute or contains `..` components
+/// * The parent directories cannot be created
+/// * The file cannot be written or renamed into place
+pub async fn write_file(path: &Path, contents: &[u8], mode: Option<u32>) -> io::Result<()> {
+    validate_path(path)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(".msb-tmp-{}", std::process::id()));
+
+    let result = async {
+        fs::write(&tmp_path, contents).await?;
+        if let Some(mode) = mode {
+            fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(mode)).await?;
+        }
+        fs::rename(&tmp_path, path).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path).await;
+    }
+    result
+}
+
+/// Appends `contents` to the existing file at `path`.
+///
+/// Used for the later chunks of a large upload, whose first chunk went through
+/// [`write_file`].
+///
+/// ## Errors
+///
+/// Will return an error if:
+/// * `path` is not absolute or contains `..` components
+/// * The file doesn't exist or cannot be written
+pub async fn append_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    validate_path(path)?;
+
+    let mut file = fs::OpenOptions::new().append(true).open(path).await?;
+    file.write_all(contents).await?;
+    file.flush().await
+}
+
+/// Reads up to `length` bytes of the file at `path`, starting at `offset`.
+///
+/// Returns the bytes read and the total size of the file, so callers can read a large file
+/// in chunks. Without a `length`, the rest of the file is read.
+///
+/// ## Errors
+///
+/// Will return an error if:
+/// * `path` is not absolute or contains `..` components
+/// * The file doesn't exist or cannot be read
+pub async fn read_file(
+    path: &Path,
+    offset: u64,
+    length: Option<u64>,
+) -> io::Result<(Vec<u8>, u64)> {
+    validate_path(path)?;
+
+    let mut file = fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+    let remaining = size.saturating_sub(offset);
+    let length = length.map_or(remaining, |length| length.min(remaining));
+
+    file.seek(io::SeekFrom::Start(offset)).await?;
+    let mut contents = Vec::with_capacity(length as usize);
+    file.take(length).read_to_end(&mut contents).await?;
+
+    Ok((contents, size))
+}
+
+/// Lists the entries of the directory at `path`, sorted by name.
+///
+/// Symlinks are reported as themselves rather than what they point to.
+///
+/// ## Errors
+///
+/// Will return an error if:
+/// * `path` is not absolute or contains `..` components
+/// * The directory doesn't exist or cannot be read
+pub async fn list_dir(path: &Path) -> io::Result<Vec<DirEntry>> {
+    validate_path(path)?;
+
+    let mut entries = Vec::new();
+    let mut dir = fs::read_dir(path).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let metadata = fs::symlink_metadata(entry.path()).await?;
+        entries.push(DirEntry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            size: metadata.len(),
+            mode: metadata.permissions().mode() & 0o7777,
+            is_dir: metadata.is_dir(),
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(entries)
+}
+
+/// Checks that `path` is absolute and doesn't climb out of a directory with `..`
+fn validate_path(path: &Path) -> io::Result<()> {
+    if !path.is_absolute() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("path must be absolute: {}", path.display()),
+        ));
+    }
+
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("path must not contain '..': {}", path.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_file_creates_parents_and_sets_mode() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("etc/app/config.toml");
+
+        write_file(&path, b"port = 8080\n", Some(0o600)).await?;
+        assert_eq!(std::fs::read(&path)?, b"port = 8080\n");
+        assert_eq!(
+            std::fs::metadata(&path)?.permissions().mode() & 0o777,
+            0o600
+        );
+
+        // Existing files are replaced
+        write_file(&path, b"port = 9090\n", None).await?;
+        assert_eq!(std::fs::read(&path)?, b"port = 9090\n");
+
+        let err = write_file(Path::new("relative/file"), b"", None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = write_file(&dir.path().join("../escape"), b"", None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_file_in_chunks() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.bin");
+
+        write_file(&path, b"hello ", None).await?;
+        append_file(&path, b"world").await?;
+
+        let (contents, size) = read_file(&path, 0, None).await?;
+        assert_eq!(contents, b"hello world");
+        assert_eq!(size, 11);
+
+        let (contents, _) = read_file(&path, 6, Some(3)).await?;
+        assert_eq!(contents, b"wor");
+        let (contents, _) = read_file(&path, 9, Some(100)).await?;
+        assert_eq!(contents, b"ld");
+        let (contents, _) = read_file(&path, 100, Some(4)).await?;
+        assert!(contents.is_empty());
+
+        let err = read_file(&dir.path().join("../data.bin"), 0, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_dir_reports_size_and_mode() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        write_file(&dir.path().join("b.txt"), b"abc", Some(0o640)).await?;
+        std::fs::create_dir(dir.path().join("a"))?;
+
+        let entries = list_dir(dir.path()).await?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "a");
+        assert!(entries[0].is_dir);
+        assert_eq!(
+            entries[1],
+            DirEntry {
+                name: "b.txt".to_string(),
+                size: 3,
+                mode: 0o640,
+                is_dir: false,
+            }
+        );
+
+        Ok(())
+    }
+}
@@ -10,7 +10,9 @@
 //!
 //! - `repl`: Provides multi-language REPL engines for interactive code execution
 //! - `command`: Handles sandboxed execution of system commands
+//! - `process`: Runs long-running processes with input streamed to their stdin
 //! - `fs`: Manages secure file system operations
+//! - `watch`: Watches guest directories for file system changes
 //!
 //! # Architecture
 //!
@@ -75,4 +77,6 @@
 
 pub mod command;
 pub mod fs;
+pub mod process;
 pub mod repl;
+pub mod watch;
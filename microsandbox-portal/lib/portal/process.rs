@@ -0,0 +1,167 @@
+//! Long-running processes for the microsandbox portal.
+//!
+//! Unlike [`command`](super::command), which runs a command to completion with no input, this
+//! runs a process whose stdin stays open while it runs, for servers, interactive CLIs and other
+//! processes driven programmatically. Input is written to the process as it arrives, and its
+//! stdin is closed once the caller has no more, so a process reading its input to the end can
+//! finish cleanly. The process is killed if the caller stops reading its output.
+
+use std::process::Stdio;
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::Command,
+    sync::mpsc,
+};
+
+use crate::portal::{
+    command::{CommandError, CommandLine},
+    repl::Stream,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Runs a process, writing what arrives on `input` to its stdin and sending each line of its
+/// output to `output` as soon as it is read.
+///
+/// The process's stdin is closed when `input` is closed. If `output` is closed before the
+/// process exits, the process is killed.
+///
+/// ## Errors
+///
+/// Will return an error if:
+/// * The process cannot be spawned
+/// * Waiting for the process fails
+/// * `output` is closed before the process exits
+pub async fn run_process(
+    command: &str,
+    args: &[String],
+    mut input: mpsc::Receiver<String>,
+    output: mpsc::Sender<CommandLine>,
+) -> Result<i32, CommandError> {
+    let mut process = Command::new(command)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| CommandError::SpawnError(format!("Failed to spawn {}: {}", command, e)))?;
+
+    let (Some(mut stdin), Some(stdout), Some(stderr)) = (
+        process.stdin.take(),
+        process.stdout.take(),
+        process.stderr.take(),
+    ) else {
+        return Err(CommandError::ExecutionError(
+            "Failed to capture the process's standard streams".to_string(),
+        ));
+    };
+
+    let stdout_handle = tokio::spawn(forward_lines(stdout, Stream::Stdout, output.clone()));
+    let stderr_handle = tokio::spawn(forward_lines(stderr, Stream::Stderr, output.clone()));
+
+    // Dropping stdin once the input ends closes it, so the process sees the end of its input
+    let stdin_handle = tokio::spawn(async move {
+        while let Some(data) = input.recv().await {
+            if stdin.write_all(data.as_bytes()).await.is_err() || stdin.flush().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let result = tokio::select! {
+        status = process.wait() => status
+            .map(|status| status.code().unwrap_or(1))
+            .map_err(|e| CommandError::ExecutionError(format!("Failed to wait for process: {}", e))),
+        _ = output.closed() => {
+            let _ = process.kill().await;
+            Err(CommandError::ExecutionError(
+                "Output connection closed before the process exited".to_string(),
+            ))
+        }
+    };
+
+    // Input sent after the process exited has nowhere to go
+    stdin_handle.abort();
+
+    // Wait for output handlers to send what the process wrote before exiting
+    let _ = stdout_handle.await;
+    let _ = stderr_handle.await;
+
+    result
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Sends each line read from `reader` to `output`, until the end of the stream
+async fn forward_lines(
+    reader: impl AsyncRead + Unpin,
+    stream: Stream,
+    output: mpsc::Sender<CommandLine>,
+) {
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(text)) = lines.next_line().await {
+        if output.send(CommandLine { stream, text }).await.is_err() {
+            break;
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_process_echoes_input_and_exits_when_stdin_closes() {
+        let (input_tx, input_rx) = mpsc::channel(8);
+        let (output_tx, mut output_rx) = mpsc::channel(8);
+        let process =
+            tokio::spawn(async move { run_process("cat", &[], input_rx, output_tx).await });
+
+        input_tx.send("hello\n".to_string()).await.unwrap();
+        let line = output_rx.recv().await.unwrap();
+        assert_eq!((line.stream, line.text.as_str()), (Stream::Stdout, "hello"));
+
+        input_tx.send("again\n".to_string()).await.unwrap();
+        assert_eq!(output_rx.recv().await.unwrap().text, "again");
+
+        // Closing the input closes stdin, after which cat exits
+        drop(input_tx);
+        assert_eq!(process.await.unwrap().unwrap(), 0);
+        assert!(output_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_run_process_kills_the_process_when_output_closes() {
+        let (_input_tx, input_rx) = mpsc::channel(8);
+        let (output_tx, output_rx) = mpsc::channel(8);
+        let process = tokio::spawn(async move {
+            run_process("sleep", &["60".to_string()], input_rx, output_tx).await
+        });
+
+        drop(output_rx);
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), process)
+            .await
+            .expect("process was not killed")
+            .unwrap();
+        assert!(matches!(result, Err(CommandError::ExecutionError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_run_process_reports_spawn_failures() {
+        let (_input_tx, input_rx) = mpsc::channel(8);
+        let (output_tx, _output_rx) = mpsc::channel(8);
+
+        let result = run_process("/nonexistent/command", &[], input_rx, output_tx).await;
+        assert!(matches!(result, Err(CommandError::SpawnError(_))));
+    }
+}
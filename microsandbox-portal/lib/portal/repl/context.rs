@@ -0,0 +1,203 @@
+//! Environment variables and working directory of a REPL evaluation.
+//!
+//! The REPL engines are long-lived interpreters shared by every evaluation, so an evaluation's
+//! environment can't be set on the process. Instead the code is wrapped in a prologue that
+//! saves the interpreter's environment and working directory and applies the evaluation's,
+//! and an epilogue that puts the saved ones back, so the settings only hold while the code
+//! runs.
+
+use std::collections::HashMap;
+
+#[cfg(any(feature = "python", feature = "nodejs"))]
+use super::Language;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Environment variables and working directory that code is evaluated with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EvalContext {
+    /// Environment variables set while the code runs, on top of the interpreter's
+    pub env: HashMap<String, String>,
+
+    /// Working directory the code runs in, instead of the interpreter's
+    pub cwd: Option<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl EvalContext {
+    /// Checks that the variables can be set, returning why not if they can't
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in &self.env {
+            if name.is_empty() || name.contains(['=', '\0']) {
+                return Err(format!("Invalid environment variable name: {:?}", name));
+            }
+            if value.contains('\0') {
+                return Err(format!(
+                    "Value of environment variable {} contains a NUL byte",
+                    name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the code runs with the interpreter's own environment and working directory
+    pub fn is_empty(&self) -> bool {
+        self.env.is_empty() && self.cwd.is_none()
+    }
+
+    /// Wraps code to run in this context in the REPL of `language`
+    #[cfg(any(feature = "python", feature = "nodejs"))]
+    pub fn wrap(&self, code: &str, language: Language) -> String {
+        match language {
+            #[cfg(feature = "python")]
+            Language::Python => self.wrap_python(code),
+            #[cfg(feature = "nodejs")]
+            Language::Node => self.wrap_node(code),
+        }
+    }
+
+    /// Wraps code to run in this context in the Python REPL
+    ///
+    /// The prologue and epilogue are single lines, and the epilogue follows a blank line so
+    /// that it isn't read as part of a block the code ends with. The interactive interpreter
+    /// runs each statement on its own, so the epilogue runs even if the code raises.
+    pub fn wrap_python(&self, code: &str) -> String {
+        if self.is_empty() {
+            return code.to_string();
+        }
+
+        let mut prologue = String::from(
+            "import os as _msb_os; _msb_saved = (dict(_msb_os.environ), _msb_os.getcwd())",
+        );
+        if !self.env.is_empty() {
+            prologue.push_str(&format!("; _msb_os.environ.update({})", self.env_literal()));
+        }
+        if let Some(cwd) = &self.cwd {
+            prologue.push_str(&format!("; _msb_os.chdir({})", string_literal(cwd)));
+        }
+
+        let epilogue = "_msb_os.environ.clear(); _msb_os.environ.update(_msb_saved[0]); \
+                        _msb_os.chdir(_msb_saved[1]); del _msb_saved, _msb_os";
+
+        format!(
+            "{}\n{}\n\n{}\n",
+            prologue,
+            code.trim_end_matches('\n'),
+            epilogue
+        )
+    }
+
+    /// Wraps code to run in this context in the Node.js REPL
+    pub fn wrap_node(&self, code: &str) -> String {
+        if self.is_empty() {
+            return code.to_string();
+        }
+
+        let mut prologue = String::from(
+            "globalThis.__msbSaved = { env: { ...process.env }, cwd: process.cwd() };",
+        );
+        if !self.env.is_empty() {
+            prologue.push_str(&format!(
+                " Object.assign(process.env, {});",
+                self.env_literal()
+            ));
+        }
+        if let Some(cwd) = &self.cwd {
+            prologue.push_str(&format!(" process.chdir({});", string_literal(cwd)));
+        }
+
+        let epilogue = "(({ env, cwd }) => { \
+                        for (const name of Object.keys(process.env)) { if (!(name in env)) delete process.env[name]; } \
+                        Object.assign(process.env, env); process.chdir(cwd); \
+                        })(globalThis.__msbSaved); delete globalThis.__msbSaved;";
+
+        format!(
+            "{}\n{}\n{}\n",
+            prologue,
+            code.trim_end_matches('\n'),
+            epilogue
+        )
+    }
+
+    /// The environment variables as an object literal, valid in both Python and JavaScript
+    fn env_literal(&self) -> String {
+        // JSON strings only use escapes that Python and JavaScript string literals share
+        serde_json::to_string(&self.env).unwrap_or_else(|_| "{}".to_string())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Quotes a string as a literal, valid in both Python and JavaScript
+fn string_literal(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> EvalContext {
+        EvalContext {
+            env: HashMap::from([("GREETING".to_string(), "it's \"quoted\"\n".to_string())]),
+            cwd: Some("/srv/app".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_wrap_python_applies_and_restores_the_context() {
+        let wrapped = context().wrap_python("for i in range(2):\n    print(i)\n");
+        let lines: Vec<_> = wrapped.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "import os as _msb_os; _msb_saved = (dict(_msb_os.environ), _msb_os.getcwd()); \
+             _msb_os.environ.update({\"GREETING\":\"it's \\\"quoted\\\"\\n\"}); \
+             _msb_os.chdir(\"/srv/app\")"
+        );
+        assert_eq!(lines[1..3], ["for i in range(2):", "    print(i)"]);
+
+        // A blank line ends the loop before the context is restored
+        assert_eq!(lines[3], "");
+        assert!(lines[4].starts_with("_msb_os.environ.clear()"));
+    }
+
+    #[test]
+    fn test_wrap_node_applies_and_restores_the_context() {
+        let wrapped = context().wrap_node("console.log(process.env.GREETING)");
+        let lines: Vec<_> = wrapped.lines().collect();
+
+        assert!(lines[0].contains("process.chdir(\"/srv/app\");"));
+        assert_eq!(lines[1], "console.log(process.env.GREETING)");
+        assert!(lines[2].ends_with("delete globalThis.__msbSaved;"));
+    }
+
+    #[test]
+    fn test_empty_context_leaves_code_alone() {
+        let context = EvalContext::default();
+        assert_eq!(context.wrap_python("print(1)"), "print(1)");
+        assert_eq!(context.wrap_node("1 + 1"), "1 + 1");
+    }
+
+    #[test]
+    fn test_validate_rejects_unsettable_variables() {
+        let mut context = context();
+        assert!(context.validate().is_ok());
+
+        context.env.insert("A=B".to_string(), "x".to_string());
+        assert!(context.validate().is_err());
+    }
+}
@@ -54,7 +54,13 @@ use super::nodejs;
 #[cfg(feature = "python")]
 use super::python;
 
-use super::types::{Cmd, EngineError, EngineHandle, Language, Line, Resp, Stream};
+use super::{
+    types::{
+        Cmd, EngineError, EngineHandle, EnginePids, Evaluation, FlushRequests, Language, Line,
+        Resp, Stream,
+    },
+    usage::UsageSampler,
+};
 
 #[cfg(any(feature = "python", feature = "nodejs"))]
 use super::types::Engine;
@@ -108,11 +114,53 @@ impl EngineHandle {
         execution_id: S,
         timeout: Option<u64>,
     ) -> Result<Vec<Line>, EngineError> {
+        let (lines, _timed_out) = self
+            .eval_lines(code, language, execution_id, timeout)
+            .await?;
+        Ok(lines)
+    }
+
+    /// Evaluates code, returning its output and whether it was interrupted by its timeout
+    async fn eval_lines<S: Into<String>>(
+        &self,
+        code: S,
+        language: Language,
+        execution_id: S,
+        timeout: Option<u64>,
+    ) -> Result<(Vec<Line>, bool), EngineError> {
+        let (line_tx, mut line_rx) = mpsc::channel::<Line>(100);
+        let collect = async {
+            let mut lines = Vec::new();
+            while let Some(line) = line_rx.recv().await {
+                lines.push(line);
+            }
+            lines
+        };
+
+        let (timed_out, lines) = tokio::join!(
+            self.eval_streaming(code, language, execution_id, timeout, line_tx),
+            collect
+        );
+        Ok((lines, timed_out?))
+    }
+
+    /// Evaluates code, sending each line of output to `lines` as soon as it is produced
+    ///
+    /// Returns whether the evaluation was interrupted by its timeout, once it has finished.
+    /// The evaluation runs to the end even if `lines` is closed early, and its output stays
+    /// readable with [`partial_output`](Self::partial_output) either way.
+    pub async fn eval_streaming<S: Into<String>>(
+        &self,
+        code: S,
+        language: Language,
+        execution_id: S,
+        timeout: Option<u64>,
+        lines: mpsc::Sender<Line>,
+    ) -> Result<bool, EngineError> {
         let code = code.into();
         let execution_id = execution_id.into();
         // Create channel for receiving results
         let (resp_tx, mut resp_rx) = mpsc::channel::<Resp>(100);
-        let (line_tx, mut line_rx) = mpsc::channel::<Line>(100);
 
         // Send evaluation command to reactor using the provided execution_id
         self.cmd_sender
@@ -126,43 +174,123 @@ impl EngineHandle {
             .await
             .map_err(|_| EngineError::Unavailable("Reactor thread not available".to_string()))?;
 
-        // Process responses in a separate task
-        let process_handle = tokio::spawn(async move {
-            while let Some(resp) = resp_rx.recv().await {
-                match resp {
-                    Resp::Line {
-                        id: _,
-                        stream,
-                        text,
-                    } => {
-                        let _ = line_tx.send(Line { stream, text }).await;
-                    }
-                    Resp::Done { id: _ } => {
-                        break;
-                    }
-                    Resp::Error { id: _, message } => {
-                        let _ = line_tx
-                            .send(Line {
-                                stream: Stream::Stderr,
-                                text: format!("Error: {}", message),
-                            })
-                            .await;
-                        break;
+        // Forward the lines, keeping a copy readable while the evaluation runs
+        self.partial_output.lock().unwrap().clear();
+        let mut timed_out = false;
+        while let Some(resp) = resp_rx.recv().await {
+            let line = match resp {
+                Resp::Line {
+                    id: _,
+                    stream,
+                    text,
+                } => Line { stream, text },
+                Resp::Done { id: _ } => break,
+                Resp::Error { id: _, message } => {
+                    self.emit_line(
+                        &lines,
+                        Line {
+                            stream: Stream::Stderr,
+                            text: format!("Error: {}", message),
+                        },
+                    )
+                    .await;
+                    break;
+                }
+                Resp::TimedOut {
+                    id: _,
+                    timeout_secs,
+                } => {
+                    // Keep reading: the interrupted interpreter may still report output
+                    timed_out = true;
+                    Line {
+                        stream: Stream::Stderr,
+                        text: format!("Error: Execution timed out after {} seconds", timeout_secs),
                     }
                 }
-            }
-        });
+            };
+            self.emit_line(&lines, line).await;
+        }
 
-        // Collect all lines
-        let mut lines = Vec::new();
-        while let Some(line) = line_rx.recv().await {
-            lines.push(line);
+        Ok(timed_out)
+    }
+
+    /// Records a line of the running evaluation's output and sends it to `lines`
+    async fn emit_line(&self, lines: &mpsc::Sender<Line>, line: Line) {
+        self.partial_output.lock().unwrap().push(line.clone());
+        let _ = lines.send(line).await;
+    }
+
+    /// Evaluates code like [`eval`](Self::eval), also measuring the resources it used
+    ///
+    /// The usage is measured on the language's interpreter process and is `None` when it
+    /// can't be read, e.g. when `/proc` isn't available. An evaluation that runs past its
+    /// timeout is interrupted and reported as timed out, with the output it produced.
+    pub async fn eval_with_usage<S: Into<String>>(
+        &self,
+        code: S,
+        language: Language,
+        execution_id: S,
+        timeout: Option<u64>,
+    ) -> Result<Evaluation, EngineError> {
+        let sampler = self.engine_pid(language).map(UsageSampler::start);
+        let (lines, timed_out) = self
+            .eval_lines(code, language, execution_id, timeout)
+            .await?;
+        Ok(Evaluation {
+            lines,
+            usage: sampler.and_then(UsageSampler::finish),
+            timed_out,
+        })
+    }
+
+    /// Returns the process ID of the interpreter for a language, if it is running
+    fn engine_pid(&self, language: Language) -> Option<u32> {
+        #[cfg(any(feature = "python", feature = "nodejs"))]
+        {
+            let pid = match language {
+                #[cfg(feature = "python")]
+                Language::Python => &self.engine_pids.python,
+                #[cfg(feature = "nodejs")]
+                Language::Node => &self.engine_pids.nodejs,
+            };
+            match pid.load(std::sync::atomic::Ordering::SeqCst) {
+                0 => None,
+                pid => Some(pid),
+            }
         }
 
-        // Wait for processing to complete
-        let _ = process_handle.await;
+        #[cfg(not(any(feature = "python", feature = "nodejs")))]
+        match language {}
+    }
 
-        Ok(lines)
+    /// Asks the engine for a language to emit its buffered partial output
+    ///
+    /// Output is read from the engine's process line by line, so text without a trailing
+    /// newline (e.g. a prompt) is normally held back until the line completes or the
+    /// evaluation ends. A flush emits that text as output of the running evaluation right
+    /// away, within the engine's polling interval. It has no effect when no evaluation is
+    /// running.
+    ///
+    /// This only flushes what the engine has already received; output still buffered by
+    /// the guest process itself (e.g. a block-buffered child's stdout) is not affected.
+    #[cfg(any(feature = "python", feature = "nodejs"))]
+    pub fn flush(&self, language: Language) {
+        let flag = match language {
+            #[cfg(feature = "python")]
+            Language::Python => &self.flush_requests.python,
+            #[cfg(feature = "nodejs")]
+            Language::Node => &self.flush_requests.nodejs,
+        };
+        flag.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Returns the output produced so far by the most recent evaluation
+    ///
+    /// While an evaluation runs this is the output received up to now; once it finishes,
+    /// it is the evaluation's full output, kept until the next evaluation starts. Lets a
+    /// client whose request timed out still see what the code printed before the deadline.
+    pub fn partial_output(&self) -> Vec<Line> {
+        self.partial_output.lock().unwrap().clone()
     }
 
     /// Shuts down all engines and the reactor
@@ -201,12 +329,19 @@ impl EngineHandle {
 /// Returns an `EngineError` if any of the engines fail to initialize.
 pub async fn start_engines() -> Result<EngineHandle, EngineError> {
     let (cmd_tx, mut _cmd_rx) = mpsc::channel::<Cmd>(100);
+    let flush_requests = FlushRequests::default();
+    let engine_pids = EnginePids::default();
 
     // Spawn reactor task
+    #[cfg(any(feature = "python", feature = "nodejs"))]
+    let _flush_requests = flush_requests.clone();
+    #[cfg(any(feature = "python", feature = "nodejs"))]
+    let _engine_pids = engine_pids.clone();
+
     #[cfg(any(feature = "python", feature = "nodejs"))]
     tokio::spawn(async move {
         // Initialize engines asynchronously
-        let mut engines = initialize_engines()
+        let mut engines = initialize_engines(_flush_requests, _engine_pids)
             .await
             .expect("Failed to initialize engines");
 
@@ -263,7 +398,12 @@ pub async fn start_engines() -> Result<EngineHandle, EngineError> {
         }
     });
 
-    Ok(EngineHandle { cmd_sender: cmd_tx })
+    Ok(EngineHandle {
+        cmd_sender: cmd_tx,
+        flush_requests,
+        partial_output: Default::default(),
+        engine_pids,
+    })
 }
 
 /// Initialize all engines
@@ -279,11 +419,14 @@ pub async fn start_engines() -> Result<EngineHandle, EngineError> {
 ///
 /// Returns an `EngineError` if any of the engines fail to initialize.
 #[cfg(any(feature = "python", feature = "nodejs"))]
-async fn initialize_engines() -> Result<Engines, EngineError> {
+async fn initialize_engines(
+    flush_requests: FlushRequests,
+    engine_pids: EnginePids,
+) -> Result<Engines, EngineError> {
     #[cfg(feature = "python")]
-    let mut python_engine = python::create_engine()?;
+    let mut python_engine = python::create_engine(flush_requests.python, engine_pids.python)?;
     #[cfg(feature = "nodejs")]
-    let mut nodejs_engine = nodejs::create_engine()?;
+    let mut nodejs_engine = nodejs::create_engine(flush_requests.nodejs, engine_pids.nodejs)?;
 
     // Initialize each engine asynchronously
     #[cfg(feature = "python")]
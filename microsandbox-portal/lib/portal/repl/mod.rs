@@ -57,8 +57,12 @@ pub mod python;
 #[cfg(feature = "nodejs")]
 pub mod nodejs;
 
+pub mod context;
 pub mod engine;
 pub mod types;
+pub mod usage;
 
+pub use context::EvalContext;
 pub use engine::*;
 pub use types::*;
+pub use usage::ResourceUsage;
@@ -11,9 +11,12 @@
 
 use async_trait::async_trait;
 use rand::{distr::Alphanumeric, Rng};
-use std::sync::{Arc, Mutex};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc, Mutex,
+};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncReadExt, AsyncWriteExt},
     process::Command,
     sync::{
         mpsc::{self, Sender},
@@ -22,7 +25,7 @@ use tokio::{
     time::{sleep, timeout as tokio_timeout, Duration},
 };
 
-use super::types::{Engine, EngineError, Resp, Stream};
+use super::types::{interrupt_until, Engine, EngineError, LineBuffer, Resp, Stream};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -32,6 +35,8 @@ use super::types::{Engine, EngineError, Resp, Stream};
 pub struct NodeEngine {
     process_control_tx: Option<Sender<ProcessControl>>,
     eval_tx: Option<Sender<EvalRequest>>,
+    flush_requested: Arc<AtomicBool>,
+    pid: Arc<AtomicU32>,
 }
 
 /// Commands for controlling the Node.js process
@@ -61,10 +66,12 @@ struct ExecutionStatus {
 //--------------------------------------------------------------------------------------------------
 
 impl NodeEngine {
-    fn new() -> Self {
+    fn new(flush_requested: Arc<AtomicBool>, pid: Arc<AtomicU32>) -> Self {
         NodeEngine {
             process_control_tx: None,
             eval_tx: None,
+            flush_requested,
+            pid,
         }
     }
 }
@@ -83,14 +90,26 @@ impl Engine for NodeEngine {
         self.process_control_tx = Some(process_control_tx);
         self.eval_tx = Some(eval_tx);
 
+        let flush_requested = Arc::clone(&self.flush_requested);
+        let engine_pid = Arc::clone(&self.pid);
+
         // Start the Node.js process manager in a separate task
         tokio::spawn(async move {
             // Start Node.js process with custom REPL
-            // Custom REPL starts with no prompt, no terminal features, and ignores undefined
+            // Custom REPL starts with no prompt, no terminal features, and ignores undefined.
+            // SIGINT breaks out of the running evaluation; between evaluations it is ignored
+            // rather than killing the interpreter. The idle handler is detached while code is
+            // evaluated, because the REPL only breaks out of evaluations when nothing else
+            // handles SIGINT
             let mut process = match Command::new("node")
                 .args(&[
                     "-e",
-                    "const r=require('repl').start({prompt:'',terminal:false,ignoreUndefined:true,useGlobal:true});r._prompt='';r.displayPrompt=()=>{}",
+                    concat!(
+                        "const idle=()=>{};process.on('SIGINT',idle);",
+                        "const r=require('repl').start({prompt:'',terminal:false,ignoreUndefined:true,useGlobal:true,breakEvalOnSigint:true});",
+                        "const e=r.eval;r.eval=(c,x,f,cb)=>{process.off('SIGINT',idle);e.call(r,c,x,f,(...a)=>{process.on('SIGINT',idle);cb(...a)})};",
+                        "r._prompt='';r.displayPrompt=()=>{}",
+                    ),
                 ])
                 .stdin(std::process::Stdio::piped())
                 .stdout(std::process::Stdio::piped())
@@ -104,6 +123,10 @@ impl Engine for NodeEngine {
                 }
             };
 
+            // Publish the process ID so evaluations can be measured
+            let process_pid = process.id();
+            engine_pid.store(process_pid.unwrap_or(0), Ordering::SeqCst);
+
             // Get stdin handle
             let mut stdin = match process.stdin.take() {
                 Some(s) => s,
@@ -134,63 +157,64 @@ impl Engine for NodeEngine {
             let execution_status = Arc::new(Mutex::new(None::<ExecutionStatus>));
 
             // Start stdout handler in a separate task
-            let stdout_reader = BufReader::new(stdout);
+            let stdout_buffer = Arc::new(Mutex::new(LineBuffer::default()));
+            let stdout_reader_buffer = Arc::clone(&stdout_buffer);
             let (stdout_done_tx, mut stdout_done_rx) = mpsc::channel::<()>(1);
             let stdout_exec_status = Arc::clone(&execution_status);
 
             tokio::task::spawn_blocking(move || {
-                let mut lines_future = stdout_reader.lines();
+                let mut stdout = stdout;
+                let mut chunk = [0u8; 4096];
                 let runtime = tokio::runtime::Handle::current();
 
                 loop {
-                    // Use the runtime to execute the async call in the blocking thread
-                    let line_result = runtime.block_on(lines_future.next_line());
-
-                    match line_result {
-                        Ok(Some(line)) => {
-                            // Skip Node.js REPL response tags '>' and '..'
-                            if !line.trim().is_empty()
-                                && !line.starts_with('>')
-                                && !line.starts_with("..")
+                    // Read whatever is available rather than whole lines, so that a partial
+                    // line waits in the shared buffer where a flush can pick it up
+                    let lines = match runtime.block_on(stdout.read(&mut chunk)) {
+                        Ok(0) => break, // EOF
+                        Ok(n) => stdout_reader_buffer.lock().unwrap().push(&chunk[..n]),
+                        Err(_) => break, // Error reading
+                    };
+
+                    for line in lines {
+                        // Skip Node.js REPL response tags '>' and '..'
+                        if !line.trim().is_empty()
+                            && !line.starts_with('>')
+                            && !line.starts_with("..")
+                        {
+                            // Check if this is an end-of-execution marker line
+                            let mut should_send = true;
+
                             {
-                                // Check if this is an end-of-execution marker line
-                                let mut should_send = true;
-
-                                {
-                                    let mut status_guard = stdout_exec_status.lock().unwrap();
-                                    if let Some(status) = status_guard.as_mut() {
-                                        // Check if this line is our end-of-execution marker
-                                        if line.trim() == status.eoe_marker {
-                                            should_send = false;
-                                            status.completed = true;
-
-                                            // Signal completion
-                                            let id = status.id.clone();
-                                            let sender = status.sender.clone();
-                                            runtime.block_on(async {
-                                                let _ = sender.send(Resp::Done { id }).await;
-                                            });
-                                        }
+                                let mut status_guard = stdout_exec_status.lock().unwrap();
+                                if let Some(status) = status_guard.as_mut() {
+                                    // Check if this line is our end-of-execution marker
+                                    if line.trim() == status.eoe_marker {
+                                        should_send = false;
+                                        status.completed = true;
+
+                                        // Signal completion
+                                        let id = status.id.clone();
+                                        let sender = status.sender.clone();
+                                        runtime.block_on(async {
+                                            let _ = sender.send(Resp::Done { id }).await;
+                                        });
                                     }
                                 }
+                            }
 
-                                // Send line if it's not an EOE marker
-                                if should_send {
-                                    if let Some(status) =
-                                        stdout_exec_status.lock().unwrap().as_ref()
-                                    {
-                                        // Use block_on to send the message
-                                        let _ = runtime.block_on(status.sender.send(Resp::Line {
-                                            id: status.id.clone(),
-                                            stream: Stream::Stdout,
-                                            text: line,
-                                        }));
-                                    }
+                            // Send line if it's not an EOE marker
+                            if should_send {
+                                if let Some(status) = stdout_exec_status.lock().unwrap().as_ref() {
+                                    // Use block_on to send the message
+                                    let _ = runtime.block_on(status.sender.send(Resp::Line {
+                                        id: status.id.clone(),
+                                        stream: Stream::Stdout,
+                                        text: line,
+                                    }));
                                 }
                             }
                         }
-                        Ok(None) => break, // EOF
-                        Err(_) => break,   // Error reading
                     }
                 }
 
@@ -199,31 +223,34 @@ impl Engine for NodeEngine {
             });
 
             // Start stderr handler in a separate task
-            let stderr_reader = BufReader::new(stderr);
+            let stderr_buffer = Arc::new(Mutex::new(LineBuffer::default()));
+            let stderr_reader_buffer = Arc::clone(&stderr_buffer);
             let (stderr_done_tx, mut stderr_done_rx) = mpsc::channel::<()>(1);
             let stderr_exec_status = Arc::clone(&execution_status);
 
             tokio::task::spawn_blocking(move || {
-                let mut lines_future = stderr_reader.lines();
+                let mut stderr = stderr;
+                let mut chunk = [0u8; 4096];
                 let runtime = tokio::runtime::Handle::current();
 
                 loop {
-                    // Use the runtime to execute the async call in the blocking thread
-                    let line_result = runtime.block_on(lines_future.next_line());
-
-                    match line_result {
-                        Ok(Some(line)) => {
-                            if let Some(status) = stderr_exec_status.lock().unwrap().as_ref() {
-                                // Use block_on to send the message
-                                let _ = runtime.block_on(status.sender.send(Resp::Line {
-                                    id: status.id.clone(),
-                                    stream: Stream::Stderr,
-                                    text: line,
-                                }));
-                            }
+                    // Read whatever is available rather than whole lines, so that a partial
+                    // line waits in the shared buffer where a flush can pick it up
+                    let lines = match runtime.block_on(stderr.read(&mut chunk)) {
+                        Ok(0) => break, // EOF
+                        Ok(n) => stderr_reader_buffer.lock().unwrap().push(&chunk[..n]),
+                        Err(_) => break, // Error reading
+                    };
+
+                    for line in lines {
+                        if let Some(status) = stderr_exec_status.lock().unwrap().as_ref() {
+                            // Use block_on to send the message
+                            let _ = runtime.block_on(status.sender.send(Resp::Line {
+                                id: status.id.clone(),
+                                stream: Stream::Stderr,
+                                text: line,
+                            }));
                         }
-                        Ok(None) => break, // EOF
-                        Err(_) => break,   // Error reading
                     }
                 }
 
@@ -252,6 +279,9 @@ impl Engine for NodeEngine {
                             .map(char::from)
                             .collect::<String>());
 
+                        // Drop flush requests made while no execution was running
+                        flush_requested.store(false, Ordering::SeqCst);
+
                         // Set as current execution
                         {
                             let mut status_guard = execution_status.lock().unwrap();
@@ -299,6 +329,9 @@ impl Engine for NodeEngine {
                                     if let Some(status) = exec_status.lock().unwrap().as_ref() {
                                         completed = status.completed;
                                     }
+                                    if flush_requested.swap(false, Ordering::SeqCst) {
+                                        flush_partial_output(&exec_status, &stdout_buffer, &stderr_buffer).await;
+                                    }
                                     sleep(Duration::from_millis(50)).await;
                                 }
                             };
@@ -307,12 +340,16 @@ impl Engine for NodeEngine {
                             match timeout {
                                 Some(timeout_secs) => {
                                     let timeout_duration = Duration::from_secs(timeout_secs);
-                                    if let Err(_) = tokio_timeout(timeout_duration, wait_future).await {
-                                        // Timeout occurred
-                                        let _ = resp_tx.send(Resp::Error {
+                                    tokio::pin!(wait_future);
+                                    if tokio_timeout(timeout_duration, wait_future.as_mut()).await.is_err() {
+                                        // Timeout occurred: interrupt the code until the
+                                        // interpreter reaches the end of it, keeping what it
+                                        // reports on the way
+                                        let _ = resp_tx.send(Resp::TimedOut {
                                             id: id.clone(),
-                                            message: format!("Execution timed out after {} seconds", timeout_secs),
+                                            timeout_secs,
                                         }).await;
+                                        interrupt_until(process_pid, wait_future).await;
                                         return Err(EngineError::Timeout(timeout_secs));
                                     }
                                 },
@@ -346,6 +383,7 @@ impl Engine for NodeEngine {
             }
 
             // Cleanup: kill the process
+            engine_pid.store(0, Ordering::SeqCst);
             let _ = process.kill().await;
             let _ = process.wait().await;
         });
@@ -407,6 +445,44 @@ impl Engine for NodeEngine {
 //--------------------------------------------------------------------------------------------------
 
 /// Create a new Node.js engine instance
-pub fn create_engine() -> Result<Box<dyn Engine>, EngineError> {
-    Ok(Box::new(NodeEngine::new()))
+///
+/// Partial output lines are emitted early whenever `flush_requested` is set, and the
+/// interpreter's process ID is kept in `pid` while it runs.
+pub fn create_engine(
+    flush_requested: Arc<AtomicBool>,
+    pid: Arc<AtomicU32>,
+) -> Result<Box<dyn Engine>, EngineError> {
+    Ok(Box::new(NodeEngine::new(flush_requested, pid)))
+}
+
+/// Emits the partial lines held in the output buffers as output of the current execution
+async fn flush_partial_output(
+    execution_status: &Mutex<Option<ExecutionStatus>>,
+    stdout_buffer: &Mutex<LineBuffer>,
+    stderr_buffer: &Mutex<LineBuffer>,
+) {
+    let current = execution_status
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|status| (status.id.clone(), status.sender.clone()));
+    let Some((id, sender)) = current else {
+        return;
+    };
+
+    for (stream, buffer) in [
+        (Stream::Stdout, stdout_buffer),
+        (Stream::Stderr, stderr_buffer),
+    ] {
+        let partial = buffer.lock().unwrap().take_partial();
+        if let Some(text) = partial {
+            let _ = sender
+                .send(Resp::Line {
+                    id: id.clone(),
+                    stream,
+                    text,
+                })
+                .await;
+        }
+    }
 }
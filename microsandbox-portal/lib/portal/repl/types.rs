@@ -27,9 +27,35 @@
 //! The design accounts for concurrent use by leveraging thread-safe primitives and
 //! message passing through channels to communicate between components.
 
+#[cfg(any(feature = "python", feature = "nodejs"))]
+use std::sync::atomic::{AtomicBool, AtomicU32};
+use std::sync::{Arc, Mutex};
+#[cfg(any(feature = "python", feature = "nodejs"))]
+use std::{future::Future, time::Duration};
+
+#[cfg(any(feature = "python", feature = "nodejs"))]
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
 use thiserror::Error;
 use tokio::sync::mpsc::Sender;
 
+use super::usage::ResourceUsage;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How long an engine keeps interrupting an evaluation that timed out before giving up on
+/// the interpreter returning to its prompt
+#[cfg(any(feature = "python", feature = "nodejs"))]
+const INTERRUPT_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// How often an evaluation that timed out is interrupted until it is done
+#[cfg(any(feature = "python", feature = "nodejs"))]
+const INTERRUPT_INTERVAL: Duration = Duration::from_millis(100);
+
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
@@ -74,6 +100,41 @@ pub struct Line {
 #[derive(Clone)]
 pub struct EngineHandle {
     pub(crate) cmd_sender: Sender<Cmd>,
+    pub(crate) flush_requests: FlushRequests,
+    pub(crate) partial_output: Arc<Mutex<Vec<Line>>>,
+    pub(crate) engine_pids: EnginePids,
+}
+
+/// Per-language flags asking an engine to emit its buffered partial output
+///
+/// These bypass the reactor's command channel, which is busy for as long as an
+/// evaluation runs, so a flush can take effect in the middle of one.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FlushRequests {
+    #[cfg(feature = "python")]
+    pub(crate) python: Arc<AtomicBool>,
+    #[cfg(feature = "nodejs")]
+    pub(crate) nodejs: Arc<AtomicBool>,
+}
+
+/// Per-language process IDs of the running interpreters, `0` while none is running
+///
+/// Used to measure the resources each evaluation uses.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EnginePids {
+    #[cfg(feature = "python")]
+    pub(crate) python: Arc<AtomicU32>,
+    #[cfg(feature = "nodejs")]
+    pub(crate) nodejs: Arc<AtomicU32>,
+}
+
+/// Splits a process's output into lines as it is read
+///
+/// Bytes after the last newline are held until the rest of the line arrives, or until
+/// they are taken early by a flush.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
 }
 
 /// Error types that can occur during engine operations
@@ -150,6 +211,32 @@ pub enum Resp {
         /// Error message
         message: String,
     },
+
+    /// Evaluation ran past its timeout and was interrupted
+    ///
+    /// Output the interpreter produces while it is being interrupted, such as a traceback,
+    /// may still follow before the evaluation is done.
+    TimedOut {
+        /// Unique identifier for the evaluation
+        id: String,
+
+        /// Timeout of the evaluation in seconds
+        timeout_secs: u64,
+    },
+}
+
+/// Output of an evaluation, as returned by
+/// [`EngineHandle::eval_with_usage`](super::engine::EngineHandle::eval_with_usage)
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    /// Lines of output, in the order they were produced
+    pub lines: Vec<Line>,
+
+    /// Resources the interpreter used during the evaluation, if they could be measured
+    pub usage: Option<ResourceUsage>,
+
+    /// Whether the evaluation was interrupted by its timeout
+    pub timed_out: bool,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -194,6 +281,88 @@ pub trait Engine: Send + 'static {
     async fn shutdown(&mut self);
 }
 
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl LineBuffer {
+    /// Adds bytes read from the process and returns the lines they complete
+    ///
+    /// Lines are returned without their `\n` or `\r\n` terminator.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut lines = Vec::new();
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = line.strip_suffix(b"\n").unwrap_or(&line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            lines.push(String::from_utf8_lossy(line).into_owned());
+        }
+        lines
+    }
+
+    /// Takes the partial line held so far, if there is one
+    ///
+    /// A character whose bytes haven't all arrived yet is held back with the rest of the line,
+    /// rather than being taken as a replacement character.
+    pub fn take_partial(&mut self) -> Option<String> {
+        let end = complete_utf8_len(&self.pending);
+        if end == 0 {
+            return None;
+        }
+
+        let partial: Vec<u8> = self.pending.drain(..end).collect();
+        Some(String::from_utf8_lossy(&partial).into_owned())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Interrupts the evaluation running in an interpreter until `done` completes, as pressing
+/// Ctrl-C repeatedly would
+///
+/// Python raises `KeyboardInterrupt` in the running statement; Node.js stops synchronous
+/// code. The interpreters read code statement by statement, so the statements after the
+/// interrupted one would still run; interrupting until the evaluation is done stops them
+/// too. Gives up after [`INTERRUPT_GRACE_PERIOD`].
+#[cfg(any(feature = "python", feature = "nodejs"))]
+pub(crate) async fn interrupt_until(pid: Option<u32>, done: impl Future<Output = ()>) {
+    let Some(pid) = pid else {
+        return;
+    };
+
+    let interrupted = async {
+        tokio::pin!(done);
+        loop {
+            let _ = signal::kill(Pid::from_raw(pid as i32), Signal::SIGINT);
+            if tokio::time::timeout(INTERRUPT_INTERVAL, &mut done)
+                .await
+                .is_ok()
+            {
+                break;
+            }
+        }
+    };
+    let _ = tokio::time::timeout(INTERRUPT_GRACE_PERIOD, interrupted).await;
+}
+
+/// Gets the length of the longest prefix of `bytes` that doesn't end inside a UTF-8 sequence
+fn complete_utf8_len(bytes: &[u8]) -> usize {
+    let mut start = 0;
+    loop {
+        match std::str::from_utf8(&bytes[start..]) {
+            Ok(_) => return bytes.len(),
+            Err(e) => match e.error_len() {
+                Some(invalid) => start += e.valid_up_to() + invalid,
+                None => return start + e.valid_up_to(),
+            },
+        }
+    }
+}
+
 // -------------------------------------------------------------------------------------------------
 // Trait Implementations
 // -------------------------------------------------------------------------------------------------
@@ -203,6 +372,49 @@ impl std::fmt::Debug for EngineHandle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("EngineHandle")
             .field("cmd_sender", &"<channel>")
+            .field("flush_requests", &self.flush_requests)
+            .field("partial_output", &self.partial_output)
+            .field("engine_pids", &self.engine_pids)
             .finish()
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_buffer_flushes_partial_line_before_newline() {
+        let mut buffer = LineBuffer::default();
+
+        // A prompt without a trailing newline is held back...
+        assert_eq!(buffer.push(b"starting\r\nName: "), vec!["starting"]);
+
+        // ...until a flush takes it, before the rest of the output arrives
+        assert_eq!(buffer.take_partial().as_deref(), Some("Name: "));
+        assert_eq!(buffer.take_partial(), None);
+
+        assert_eq!(buffer.push(b"done\n"), vec!["done"]);
+        assert_eq!(buffer.take_partial(), None);
+    }
+
+    #[test]
+    fn test_line_buffer_holds_back_a_split_character_on_flush() {
+        let mut buffer = LineBuffer::default();
+
+        // "é" is two bytes; a flush between them takes only what precedes it
+        assert!(buffer.push(b"caf\xc3").is_empty());
+        assert_eq!(buffer.take_partial().as_deref(), Some("caf"));
+        assert_eq!(buffer.take_partial(), None);
+
+        assert_eq!(buffer.push(b"\xa9\n"), vec!["\u{e9}"]);
+
+        // Invalid bytes that no later byte can complete are still taken
+        assert!(buffer.push(b"\xffok").is_empty());
+        assert_eq!(buffer.take_partial().as_deref(), Some("\u{fffd}ok"));
+    }
+}
@@ -0,0 +1,142 @@
+//! Resource accounting for code evaluations.
+//!
+//! Each language engine runs its code in a long-lived interpreter process, so the resources an
+//! evaluation used are measured as the change in that process's counters across the
+//! evaluation. CPU time is read from `/proc/<pid>/stat`, and the peak resident set size is
+//! taken from `VmHWM` in `/proc/<pid>/status` after resetting it through
+//! `/proc/<pid>/clear_refs` when the evaluation starts.
+//!
+//! Measurement is best effort: when `/proc` can't be read, no usage is reported.
+
+use std::time::Instant;
+
+use serde::Serialize;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Clock ticks per second used by the times in `/proc/<pid>/stat`
+///
+/// The kernel always reports these times in `USER_HZ`, which is 100 on every Linux platform.
+const USER_HZ: u64 = 100;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Resources used by a single code evaluation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ResourceUsage {
+    /// CPU time spent by the interpreter and the children it waited on, in milliseconds
+    pub cpu_ms: u64,
+
+    /// Peak resident set size of the interpreter during the evaluation, in bytes
+    pub peak_rss: u64,
+
+    /// Wall-clock time the evaluation took, in milliseconds
+    pub wall_ms: u64,
+}
+
+/// Measures the resources an interpreter process uses between `start` and `finish`
+#[derive(Debug)]
+pub(crate) struct UsageSampler {
+    pid: u32,
+    started_at: Instant,
+    start_cpu_ticks: Option<u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl UsageSampler {
+    /// Starts measuring the process `pid`, resetting its peak RSS
+    pub(crate) fn start(pid: u32) -> Self {
+        // Writing 5 resets the VmHWM high water mark to the current RSS
+        let _ = std::fs::write(format!("/proc/{}/clear_refs", pid), "5");
+
+        Self {
+            pid,
+            started_at: Instant::now(),
+            start_cpu_ticks: read_cpu_ticks(pid),
+        }
+    }
+
+    /// Stops measuring and returns the usage, or `None` if the process couldn't be read
+    pub(crate) fn finish(self) -> Option<ResourceUsage> {
+        let wall_ms = self.started_at.elapsed().as_millis() as u64;
+        let end_cpu_ticks = read_cpu_ticks(self.pid)?;
+        let peak_rss = read_peak_rss(self.pid)?;
+
+        Some(ResourceUsage {
+            cpu_ms: end_cpu_ticks.saturating_sub(self.start_cpu_ticks?) * 1000 / USER_HZ,
+            peak_rss,
+            wall_ms,
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Reads the CPU time of a process and its waited-on children, in clock ticks
+fn read_cpu_ticks(pid: u32) -> Option<u64> {
+    parse_cpu_ticks(&std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
+}
+
+/// Reads the peak resident set size of a process, in bytes
+fn read_peak_rss(pid: u32) -> Option<u64> {
+    parse_peak_rss(&std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?)
+}
+
+/// Sums utime, stime, cutime and cstime from the contents of `/proc/<pid>/stat`
+fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    // The command name may contain spaces and parentheses, so fields are counted from the
+    // last closing parenthesis, which is followed by the state field (field 3)
+    let fields: Vec<&str> = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .collect();
+
+    // utime, stime, cutime and cstime are fields 14 to 17
+    fields.get(11..15)?.iter().try_fold(0u64, |total, field| {
+        Some(total + field.parse::<u64>().ok()?)
+    })
+}
+
+/// Reads `VmHWM` from the contents of `/proc/<pid>/status`, converting it to bytes
+fn parse_peak_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_usage_fields() {
+        let stat = "4242 (python3 (repl)) S 1 4242 4242 0 -1 4194304 1200 0 0 0 \
+                    150 30 12 8 20 0 1 0 5000 30000000 2000 18446744073709551615";
+        assert_eq!(parse_cpu_ticks(stat), Some(200));
+        assert_eq!(parse_cpu_ticks("4242 (python3) S 1"), None);
+
+        let status =
+            "Name:\tpython3\nVmPeak:\t  30000 kB\nVmHWM:\t    8192 kB\nVmRSS:\t    4096 kB\n";
+        assert_eq!(parse_peak_rss(status), Some(8192 * 1024));
+        assert_eq!(parse_peak_rss("Name:\tpython3\n"), None);
+    }
+}
@@ -0,0 +1,465 @@
+//! File system watches for the microsandbox portal.
+//!
+//! A watch reports the files created, modified and deleted under a directory in the guest.
+//! It is backed by inotify, through the `notify` crate, and read by long polling: each poll
+//! returns the events recorded since the previous one, along with a cursor to pass to the
+//! next poll. Events stay buffered until a poll with a later cursor acknowledges them, so a
+//! poll whose response is lost can be repeated without losing events.
+//!
+//! Watches that aren't polled for a while are dropped, since a client that went away never
+//! tells the portal it is done.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use serde::Serialize;
+use tokio::sync::Notify;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum number of unacknowledged events kept per watch; the oldest are dropped beyond it
+const MAX_WATCH_EVENTS: usize = 10_000;
+
+/// Longest a single poll waits for events
+const MAX_POLL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a watch is kept without being polled
+const WATCH_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Kind of change reported by a watch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsEventKind {
+    /// A file or directory was created, or moved into the watched directory
+    Create,
+
+    /// A file's contents or metadata changed
+    Modify,
+
+    /// A file or directory was deleted, or moved out of the watched directory
+    Delete,
+}
+
+/// A change to a file under a watched directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FsEvent {
+    /// Absolute path of the changed file in the guest
+    pub path: String,
+
+    /// Kind of change
+    pub kind: FsEventKind,
+}
+
+/// The events returned by a poll of a watch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WatchPoll {
+    /// Events recorded since the poll's cursor, oldest first
+    pub events: Vec<FsEvent>,
+
+    /// Cursor to pass to the next poll
+    pub cursor: String,
+}
+
+/// The file system watches of the portal, by ID
+#[derive(Debug, Default)]
+pub struct FsWatches {
+    watches: Mutex<HashMap<String, Watch>>,
+}
+
+/// A running watch
+struct Watch {
+    /// Keeps the inotify watch alive for as long as the watch exists
+    _watcher: RecommendedWatcher,
+
+    /// State shared with the watcher's event handler
+    shared: Arc<WatchShared>,
+
+    /// When the watch was last polled
+    last_polled: Instant,
+}
+
+/// State of a watch shared with its event handler
+struct WatchShared {
+    /// Watched directory
+    root: PathBuf,
+
+    /// Glob patterns a changed path must match to be reported; empty matches everything
+    patterns: Vec<glob::Pattern>,
+
+    /// Unacknowledged events
+    buffer: Mutex<EventBuffer>,
+
+    /// Woken when events are recorded
+    changed: Notify,
+}
+
+/// Unacknowledged events of a watch, each numbered in the order it was recorded
+#[derive(Default)]
+struct EventBuffer {
+    events: VecDeque<(u64, FsEvent)>,
+    next_seq: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsWatches {
+    /// Polls a watch of `path` for events
+    ///
+    /// Without a cursor, starts a new watch and returns right away with no events and the
+    /// watch's first cursor. With a cursor, drops the events it acknowledges and returns the
+    /// ones recorded after it, waiting up to `timeout` for at least one.
+    ///
+    /// `patterns` are glob patterns matched against the changed path relative to `path`, and
+    /// against its file name; an empty list matches everything. They are only read when the
+    /// watch starts.
+    ///
+    /// ## Errors
+    ///
+    /// Will return an error if:
+    /// * The cursor is malformed, or its watch doesn't exist or has expired
+    /// * A pattern isn't a valid glob pattern
+    /// * `path` doesn't exist or can't be watched
+    pub async fn poll(
+        &self,
+        path: &Path,
+        patterns: &[String],
+        cursor: Option<&str>,
+        timeout: Duration,
+    ) -> io::Result<WatchPoll> {
+        self.expire_idle();
+
+        let Some(cursor) = cursor else {
+            let id = self.start(path, patterns)?;
+            return Ok(WatchPoll {
+                events: Vec::new(),
+                cursor: format!("{}:0", id),
+            });
+        };
+
+        let (id, seq) = parse_cursor(cursor)?;
+        let shared = self.touch(id)?;
+
+        let deadline = tokio::time::Instant::now() + timeout.min(MAX_POLL_TIMEOUT);
+        loop {
+            // Register for wakeups before checking, so an event recorded in between isn't missed
+            let changed = shared.changed.notified();
+
+            let (events, next_seq) = shared.take_after(seq);
+            if !events.is_empty() || tokio::time::Instant::now() >= deadline {
+                self.touch(id)?;
+                return Ok(WatchPoll {
+                    events,
+                    cursor: format!("{}:{}", id, next_seq),
+                });
+            }
+
+            let _ = tokio::time::timeout_at(deadline, changed).await;
+        }
+    }
+
+    /// Starts watching `path` and returns the new watch's ID
+    fn start(&self, path: &Path, patterns: &[String]) -> io::Result<String> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                glob::Pattern::new(pattern).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid pattern {}: {}", pattern, e),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let shared = Arc::new(WatchShared {
+            root: path.to_path_buf(),
+            patterns,
+            buffer: Mutex::new(EventBuffer::default()),
+            changed: Notify::new(),
+        });
+
+        let handler_shared = Arc::clone(&shared);
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<_>| {
+            if let Ok(event) = result {
+                handler_shared.record(event);
+            }
+        })
+        .map_err(notify_error)?;
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .map_err(notify_error)?;
+
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        self.watches.lock().unwrap().insert(
+            id.clone(),
+            Watch {
+                _watcher: watcher,
+                shared,
+                last_polled: Instant::now(),
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Marks a watch as polled now and returns its shared state
+    fn touch(&self, id: &str) -> io::Result<Arc<WatchShared>> {
+        let mut watches = self.watches.lock().unwrap();
+        let watch = watches.get_mut(id).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("watch {} doesn't exist or has expired", id),
+            )
+        })?;
+        watch.last_polled = Instant::now();
+        Ok(Arc::clone(&watch.shared))
+    }
+
+    /// Drops the watches that haven't been polled for too long
+    fn expire_idle(&self) {
+        self.watches
+            .lock()
+            .unwrap()
+            .retain(|_, watch| watch.last_polled.elapsed() < WATCH_IDLE_TIMEOUT);
+    }
+}
+
+impl WatchShared {
+    /// Records the changes carried by an inotify event
+    fn record(&self, event: notify::Event) {
+        let kinds: Vec<FsEventKind> = match event.kind {
+            EventKind::Create(_) => vec![FsEventKind::Create],
+            EventKind::Remove(_) => vec![FsEventKind::Delete],
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => vec![FsEventKind::Delete],
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => vec![FsEventKind::Create],
+            // Carries the old path and then the new one
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                vec![FsEventKind::Delete, FsEventKind::Create]
+            }
+            EventKind::Modify(_) => vec![FsEventKind::Modify],
+            _ => return,
+        };
+
+        let mut recorded = false;
+        let mut buffer = self.buffer.lock().unwrap();
+        for (i, path) in event.paths.iter().enumerate() {
+            if !self.matches(path) {
+                continue;
+            }
+
+            let kind = kinds.get(i).or(kinds.last()).copied().unwrap();
+            let seq = buffer.next_seq;
+            buffer.next_seq += 1;
+            buffer.events.push_back((
+                seq,
+                FsEvent {
+                    path: path.to_string_lossy().into_owned(),
+                    kind,
+                },
+            ));
+            if buffer.events.len() > MAX_WATCH_EVENTS {
+                buffer.events.pop_front();
+            }
+            recorded = true;
+        }
+        drop(buffer);
+
+        if recorded {
+            self.changed.notify_waiters();
+        }
+    }
+
+    /// Checks whether a changed path matches the watch's patterns
+    fn matches(&self, path: &Path) -> bool {
+        if self.patterns.is_empty() {
+            return true;
+        }
+
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let name = path.file_name().map(Path::new);
+        self.patterns.iter().any(|pattern| {
+            pattern.matches_path(relative) || name.is_some_and(|name| pattern.matches_path(name))
+        })
+    }
+
+    /// Drops the events before `seq` and returns the rest, with the sequence number that
+    /// follows them
+    fn take_after(&self, seq: u64) -> (Vec<FsEvent>, u64) {
+        let mut buffer = self.buffer.lock().unwrap();
+        while buffer.events.front().is_some_and(|(s, _)| *s < seq) {
+            buffer.events.pop_front();
+        }
+
+        let events = buffer.events.iter().map(|(_, e)| e.clone()).collect();
+        (events, buffer.next_seq.max(seq))
+    }
+}
+
+impl std::fmt::Debug for Watch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Watch")
+            .field("root", &self.shared.root)
+            .field("last_polled", &self.last_polled)
+            .finish()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Splits a cursor into its watch ID and sequence number
+fn parse_cursor(cursor: &str) -> io::Result<(&str, u64)> {
+    cursor
+        .split_once(':')
+        .and_then(|(id, seq)| Some((id, seq.parse().ok()?)))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid watch cursor {}", cursor),
+            )
+        })
+}
+
+/// Converts an error of the `notify` crate into an I/O error
+fn notify_error(error: notify::Error) -> io::Error {
+    match error.kind {
+        notify::ErrorKind::Io(e) => e,
+        notify::ErrorKind::PathNotFound => {
+            io::Error::new(io::ErrorKind::NotFound, "path does not exist")
+        }
+        _ => io::Error::other(error.to_string()),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Polls until at least `count` events have arrived, acknowledging each batch
+    async fn poll_events(
+        watches: &FsWatches,
+        dir: &Path,
+        cursor: &mut String,
+        count: usize,
+    ) -> Vec<FsEvent> {
+        let mut events = Vec::new();
+        while events.len() < count {
+            let poll = watches
+                .poll(dir, &[], Some(cursor), Duration::from_secs(5))
+                .await
+                .unwrap();
+            assert!(!poll.events.is_empty(), "watch timed out without events");
+            events.extend(poll.events);
+            *cursor = poll.cursor;
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn test_watch_reports_creates_and_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let watches = FsWatches::default();
+        let mut cursor = watches
+            .poll(dir.path(), &[], None, Duration::from_secs(5))
+            .await
+            .unwrap()
+            .cursor;
+
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, b"one").unwrap();
+        let events = poll_events(&watches, dir.path(), &mut cursor, 1).await;
+        assert_eq!(events[0].path, file.to_string_lossy());
+        assert_eq!(events[0].kind, FsEventKind::Create);
+
+        std::fs::remove_file(&file).unwrap();
+        let events = poll_events(&watches, dir.path(), &mut cursor, 1).await;
+        assert!(events
+            .iter()
+            .any(|e| e.kind == FsEventKind::Delete && e.path == file.to_string_lossy()));
+    }
+
+    #[tokio::test]
+    async fn test_watch_filters_by_pattern_and_keeps_unacknowledged_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let watches = FsWatches::default();
+        let cursor = watches
+            .poll(
+                dir.path(),
+                &["*.py".to_string()],
+                None,
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap()
+            .cursor;
+
+        std::fs::write(dir.path().join("skip.txt"), b"").unwrap();
+        std::fs::write(dir.path().join("keep.py"), b"").unwrap();
+        // Let every event of the writes arrive before polling
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let first = watches
+            .poll(dir.path(), &[], Some(&cursor), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(first.events.iter().all(|e| e.path.ends_with("keep.py")));
+        assert!(!first.events.is_empty());
+
+        // Repeating a poll with the same cursor returns the same events
+        let repeated = watches
+            .poll(dir.path(), &[], Some(&cursor), Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert_eq!(repeated.events, first.events);
+
+        // The next cursor acknowledges them
+        let next = watches
+            .poll(
+                dir.path(),
+                &[],
+                Some(&first.cursor),
+                Duration::from_millis(10),
+            )
+            .await
+            .unwrap();
+        assert!(next.events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watch_rejects_unknown_cursors_and_missing_paths() {
+        let watches = FsWatches::default();
+        let error = watches
+            .poll(Path::new("/"), &[], Some("missing:0"), Duration::ZERO)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let error = watches
+            .poll(Path::new("/definitely/not/here"), &[], None, Duration::ZERO)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
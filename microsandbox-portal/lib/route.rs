@@ -16,9 +16,13 @@ use crate::{handler, state::SharedState};
 
 /// Create a new router with the given state
 pub fn create_router(state: SharedState) -> Router {
-    // Create JSON-RPC routes - a single endpoint that handles all RPC methods
+    // Create JSON-RPC routes - a single endpoint that handles all RPC methods, one for the
+    // methods whose results are streamed, and one for those that also stream their input
     // Using an adapter function to properly handle the state parameter
-    let rpc_api = Router::new().route("/", post(handler::json_rpc_handler));
+    let rpc_api = Router::new()
+        .route("/", post(handler::json_rpc_handler))
+        .route("/stream", post(handler::json_rpc_stream_handler))
+        .route("/duplex", post(handler::json_rpc_duplex_handler));
 
     // Combine all routes with tracing middleware
     Router::new()
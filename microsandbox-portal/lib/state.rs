@@ -3,7 +3,7 @@
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::portal::{command::CommandHandle, repl::EngineHandle};
+use crate::portal::{command::CommandHandle, repl::EngineHandle, watch::FsWatches};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -20,6 +20,9 @@ pub struct SharedState {
 
     /// Command handle for command execution
     pub command_handle: Arc<Mutex<Option<CommandHandle>>>,
+
+    /// File system watches polled with `sandbox.fs.watch`
+    pub fs_watches: Arc<FsWatches>,
 }
 
 impl Default for SharedState {
@@ -28,6 +31,7 @@ impl Default for SharedState {
             ready: Arc::new(Mutex::new(false)),
             engine_handle: Arc::new(Mutex::new(None)),
             command_handle: Arc::new(Mutex::new(None)),
+            fs_watches: Arc::new(FsWatches::default()),
         }
     }
 }
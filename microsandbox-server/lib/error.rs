@@ -86,6 +86,16 @@ pub enum ServerError {
     /// Error returned when an unexpected internal error occurs
     #[error("Internal server error: {0}")]
     InternalError(String),
+
+    /// Error returned when a phase of a sandbox start exceeds its time limit
+    #[error("Sandbox start timed out in the {phase} phase: {message}")]
+    StartPhaseTimeout {
+        /// The phase that timed out: `pull`, `boot` or `ready`
+        phase: &'static str,
+
+        /// What was being waited for
+        message: String,
+    },
 }
 
 /// Error code structure to be sent to frontend
@@ -336,6 +346,14 @@ impl IntoResponse for ServerError {
                     Some(ErrorCode::InvalidOrExpiredConfirmationToken as u32),
                 ),
             },
+            ServerError::StartPhaseTimeout { phase, message } => (
+                StatusCode::GATEWAY_TIMEOUT,
+                format!(
+                    "Sandbox start timed out in the {} phase: {}",
+                    phase, message
+                ),
+                None,
+            ),
             ServerError::InternalError(details) => {
                 error!(details = ?details, "Internal error");
                 (
@@ -11,15 +11,30 @@
 //! - Response generation and error handling
 
 use axum::{
-    body::Body,
+    body::{Body, Bytes},
     debug_handler,
     extract::{Path, State},
-    http::{Request, StatusCode},
+    http::{header, HeaderMap, Request, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use microsandbox_core::management::{menv, orchestra};
-use microsandbox_utils::{DEFAULT_CONFIG, DEFAULT_PORTAL_GUEST_PORT, MICROSANDBOX_CONFIG_FILENAME};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::StreamExt;
+use microsandbox_core::{
+    config::{Microsandbox, ReferenceOrPath},
+    management::{
+        config, fsdiff::FsManifest, image, menv, metrics as core_metrics, orchestra, snapshot,
+    },
+    oci::Reference,
+    runtime::MicroVmMonitor,
+    vm::LinuxRLimitResource,
+    MicrosandboxError,
+};
+use microsandbox_utils::{
+    log::LogIndex, redact_env, DEFAULT_CONFIG, DEFAULT_MEMORY_MIB, DEFAULT_NUM_VCPUS,
+    DEFAULT_PORTAL_GUEST_PORT, LOG_SUBDIR, MICROSANDBOX_CONFIG_FILENAME, MICROSANDBOX_ENV_DIR,
+    RW_SUBDIR,
+};
 use reqwest;
 use serde_json::{self, json};
 use serde_yaml;
@@ -34,14 +49,118 @@ use crate::{
     error::ServerError,
     mcp, middleware,
     payload::{
-        JsonRpcError, JsonRpcRequest, JsonRpcResponse, JsonRpcResponseOrNotification,
-        RegularMessageResponse, SandboxMetricsGetParams, SandboxStartParams, SandboxStopParams,
-        JSONRPC_VERSION,
+        ConfigDiagnostic, ImagePullParams, ImagePullResponse, JsonRpcError, JsonRpcRequest,
+        JsonRpcResponse, JsonRpcResponseOrNotification, LanguageInfo, RegularMessageResponse,
+        SandboxCloneParams, SandboxDescriptor, SandboxFsDiffParams, SandboxFsDiffResponse,
+        SandboxFsSnapshotParams, SandboxFsSnapshotResponse, SandboxHealthParams,
+        SandboxHealthResponse, SandboxListParams, SandboxListResponse, SandboxLogsParams,
+        SandboxLogsResponse, SandboxMetricsGetParams, SandboxPauseParams, SandboxRestoreParams,
+        SandboxSnapshotParams, SandboxSnapshotResponse, SandboxStartParams, SandboxStartResponse,
+        SandboxStatsParams, SandboxStatsResponse, SandboxStopParams, SandboxUlimit,
+        SandboxValidateResponse, ServerInfoResponse, ServerLanguagesResponse,
+        ServerNamespacesResponse, CHECK_CONFIG, CHECK_IMAGE, CHECK_NAME, CHECK_RESOURCES,
+        JSONRPC_VERSION, WARNING_START_TIMEOUT, WARNING_START_UNVERIFIED,
     },
     state::AppState,
     SandboxStatus, SandboxStatusResponse, ServerResult,
 };
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Languages the server can run code in: name, image, package manager, and the image's
+/// environment variable holding the language version
+const SUPPORTED_LANGUAGES: [(&str, &str, &str, &str); 2] = [
+    ("python", "microsandbox/python", "pip", "PYTHON_VERSION"),
+    ("javascript", "microsandbox/node", "npm", "NODE_VERSION"),
+];
+
+/// JSON-RPC error code for a sandbox start phase that exceeded its time limit
+const START_PHASE_TIMEOUT_CODE: i32 = -32001;
+
+/// JSON-RPC error code for an execution refused because the sandbox is running its maximum
+/// number of concurrent executions
+const EXECUTION_LIMIT_CODE: i32 = -32002;
+
+/// JSON-RPC error code for a sandbox that isn't defined in its namespace
+const SANDBOX_NOT_FOUND_CODE: i32 = -32003;
+
+/// JSON-RPC methods whose result the portal streams back as newline-delimited JSON frames
+const STREAMING_METHODS: [&str; 2] = ["sandbox.repl.stream", "sandbox.command.stream"];
+
+/// Content type of a streamed JSON-RPC result
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Maximum size of the JSON-RPC request line that starts a duplex request body (1MiB)
+const MAX_DUPLEX_REQUEST_SIZE: usize = 1024 * 1024;
+
+/// Time between checks that a starting sandbox's portal accepts connections
+const PORTAL_READY_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// JSON-RPC methods the server handles, as reported by `server.info`
+const SUPPORTED_METHODS: [&str; 31] = [
+    "sandbox.start",
+    "sandbox.validate",
+    "sandbox.stop",
+    "sandbox.status",
+    "sandbox.stats",
+    "sandbox.list",
+    "sandbox.pause",
+    "sandbox.resume",
+    "sandbox.clone",
+    "sandbox.metrics.get",
+    "sandbox.fs.snapshot",
+    "sandbox.fs.diff",
+    "sandbox.snapshot.create",
+    "sandbox.snapshot.restore",
+    "sandbox.logs",
+    "sandbox.repl.run",
+    "sandbox.repl.stream",
+    "sandbox.repl.flush",
+    "sandbox.repl.partial",
+    "sandbox.command.run",
+    "sandbox.command.stream",
+    "sandbox.process.spawn",
+    "sandbox.env",
+    "sandbox.fs.write",
+    "sandbox.fs.read",
+    "sandbox.fs.list",
+    "sandbox.fs.watch",
+    "server.languages",
+    "server.namespaces",
+    "server.info",
+    "image.pull",
+];
+
+/// Optional sandbox configuration the server can apply, as reported by `server.info`
+///
+/// `security_profile` is left out: the MicroVM backend can't drop capabilities or apply a
+/// seccomp profile in the guest, so sandbox configs carrying one are rejected.
+const SUPPORTED_CAPABILITIES: [&str; 4] = [
+    "ulimits",
+    "hostname",
+    "oom_score_adj",
+    "max_concurrent_executions",
+];
+
+/// Maximum number of log bytes read for one `sandbox.logs` request (1MiB)
+const MAX_LOGS_RESPONSE_SIZE: usize = 1024 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A chunk of output in a sandbox log written in the `jsonl` format
+#[derive(Debug, serde::Deserialize)]
+struct LogRecord {
+    /// Stream the chunk was read from
+    stream: String,
+
+    /// The output
+    data: String,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: REST API Handlers
 //--------------------------------------------------------------------------------------------------
@@ -56,6 +175,20 @@ pub async fn health() -> ServerResult<impl IntoResponse> {
     ))
 }
 
+/// Handler for Prometheus metrics of the sandboxes in every namespace
+pub async fn metrics(State(state): State<AppState>) -> ServerResult<impl IntoResponse> {
+    let namespaces_dir = state.get_config().get_namespace_dir();
+    let text = core_metrics::render_prometheus_namespaces(namespaces_dir)
+        .await
+        .map_err(|e| ServerError::InternalError(format!("Failed to render metrics: {}", e)))?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        text,
+    ))
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions: JSON-RPC Handlers
 //--------------------------------------------------------------------------------------------------
@@ -66,7 +199,12 @@ pub async fn mcp_handler(
     State(state): State<AppState>,
     Json(request): Json<JsonRpcRequest>,
 ) -> ServerResult<impl IntoResponse> {
-    debug!(?request, "Received MCP request");
+    debug!(
+        method = %request.method,
+        params = %redact_env(&request.params),
+        id = ?request.id,
+        "Received MCP request"
+    );
     // Check for required JSON-RPC fields
     if request.jsonrpc != JSONRPC_VERSION {
         let error = JsonRpcError {
@@ -101,12 +239,166 @@ pub async fn mcp_handler(
 }
 
 /// Main JSON-RPC handler that dispatches to the appropriate method
+///
+/// The body is either a single request or a batch: a non-empty array of requests, answered
+/// with an array of responses. The calls in a batch run one after another in the order given,
+/// and their responses are in the same order. A call that fails gets an error response in its
+/// place without affecting the others.
+///
+/// Methods in [`STREAMING_METHODS`] answer with newline-delimited JSON frames instead of a
+/// JSON-RPC response, so they can't be part of a batch.
 #[debug_handler]
 pub async fn json_rpc_handler(
     State(state): State<AppState>,
-    Json(request): Json<JsonRpcRequest>,
-) -> ServerResult<impl IntoResponse> {
-    debug!(?request, "Received JSON-RPC request");
+    Json(body): Json<serde_json::Value>,
+) -> ServerResult<Response> {
+    let serde_json::Value::Array(calls) = body else {
+        let request: JsonRpcRequest = serde_json::from_value(body).map_err(|e| {
+            ServerError::ValidationError(crate::error::ValidationError::InvalidInput(format!(
+                "Invalid JSON-RPC request: {}",
+                e
+            )))
+        })?;
+        if STREAMING_METHODS.contains(&request.method.as_str()) {
+            return Ok(forward_stream_to_portal(state, request).await);
+        }
+        return Ok(dispatch_rpc_request(state, request).await?.into_response());
+    };
+
+    if calls.is_empty() {
+        let error = JsonRpcError {
+            code: -32600,
+            message: "Empty JSON-RPC batch".to_string(),
+            data: None,
+        };
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            Json(JsonRpcResponse::error(error, None)),
+        )
+            .into_response());
+    }
+
+    let mut responses = Vec::with_capacity(calls.len());
+    for call in calls {
+        let id = call.get("id").cloned();
+        let request: JsonRpcRequest = match serde_json::from_value(call) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = JsonRpcError {
+                    code: -32600,
+                    message: format!("Invalid JSON-RPC request: {}", e),
+                    data: None,
+                };
+                responses.push(JsonRpcResponse::error(error, id));
+                continue;
+            }
+        };
+
+        if STREAMING_METHODS.contains(&request.method.as_str()) {
+            let error = JsonRpcError {
+                code: -32600,
+                message: format!(
+                    "{} streams its result and can't be called in a batch",
+                    request.method
+                ),
+                data: None,
+            };
+            responses.push(JsonRpcResponse::error(error, id));
+            continue;
+        }
+
+        let response = match dispatch_rpc_request(state.clone(), request).await {
+            Ok((_, Json(response))) => response,
+            Err(e) => JsonRpcResponse::error(call_error(&e), id),
+        };
+        responses.push(response);
+    }
+
+    Ok((StatusCode::OK, Json(responses)).into_response())
+}
+
+/// Handler for JSON-RPC methods that stream input to the sandbox while streaming their result
+/// back
+///
+/// The body starts with the JSON-RPC request on its own line, followed by newline-delimited
+/// input frames that are passed on to the portal as they arrive. Only the request line is
+/// authenticated, since the rest of the body isn't there yet. The response is streamed like
+/// those of [`STREAMING_METHODS`].
+pub async fn json_rpc_duplex_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Body,
+) -> ServerResult<Response> {
+    let mut body = body.into_data_stream();
+    let mut head = Vec::new();
+    let line_end = loop {
+        if let Some(end) = head.iter().position(|&b| b == b'\n') {
+            break end;
+        }
+        if head.len() > MAX_DUPLEX_REQUEST_SIZE {
+            return Err(ServerError::ValidationError(
+                crate::error::ValidationError::InvalidInput(format!(
+                    "JSON-RPC request line is larger than {} bytes",
+                    MAX_DUPLEX_REQUEST_SIZE
+                )),
+            ));
+        }
+
+        match body.next().await {
+            Some(Ok(chunk)) => head.extend_from_slice(&chunk),
+            Some(Err(e)) => {
+                return Err(ServerError::InternalError(format!(
+                    "Failed to read request body: {}",
+                    e
+                )))
+            }
+            None => break head.len(),
+        }
+    };
+
+    middleware::authorize_request_line(&state, &headers, &head[..line_end])?;
+    let request: JsonRpcRequest = serde_json::from_slice(&head[..line_end]).map_err(|e| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(format!(
+            "Invalid JSON-RPC request: {}",
+            e
+        )))
+    })?;
+
+    debug!(
+        method = %request.method,
+        params = %redact_env(&request.params),
+        id = ?request.id,
+        "Received duplex JSON-RPC request"
+    );
+
+    if request.method != "sandbox.process.spawn" {
+        return Ok(frame_stream_response(Err(JsonRpcError {
+            code: -32601,
+            message: format!("Method not found: {}", request.method),
+            data: None,
+        })));
+    }
+
+    // Pass the body on as it was sent, request line first
+    let head = futures::stream::once(async move { Ok(Bytes::from(head)) });
+    let input = reqwest::Body::wrap_stream(head.chain(body));
+
+    Ok(frame_stream_response(
+        open_portal_stream(state, &request, "duplex", input).await,
+    ))
+}
+
+/// Dispatches a single JSON-RPC request to the handler of its method
+async fn dispatch_rpc_request(
+    state: AppState,
+    request: JsonRpcRequest,
+) -> ServerResult<(StatusCode, Json<JsonRpcResponse>)> {
+    debug!(
+        method = %request.method,
+        params = %redact_env(&request.params),
+        id = ?request.id,
+        "Received JSON-RPC request"
+    );
 
     // Check for required JSON-RPC fields
     if request.jsonrpc != JSONRPC_VERSION {
@@ -136,7 +428,22 @@ pub async fn json_rpc_handler(
                 })?;
 
             // Call the sandbox_up_impl function
-            let result = sandbox_start_impl(state, start_params).await?;
+            let result = match sandbox_start_impl(state, start_params).await {
+                Ok(result) => result,
+                // Report the phase as error data, so clients can tell the phases apart
+                Err(ServerError::StartPhaseTimeout { phase, message }) => {
+                    let error = JsonRpcError {
+                        code: START_PHASE_TIMEOUT_CODE,
+                        message: format!(
+                            "Sandbox start timed out in the {} phase: {}",
+                            phase, message
+                        ),
+                        data: Some(json!({ "phase": phase })),
+                    };
+                    return Ok((StatusCode::OK, Json(JsonRpcResponse::error(error, id))));
+                }
+                Err(e) => return Err(e),
+            };
 
             // Create JSON-RPC response with success
             Ok((
@@ -144,6 +451,21 @@ pub async fn json_rpc_handler(
                 Json(JsonRpcResponse::success(json!(result), id)),
             ))
         }
+        "sandbox.validate" => {
+            let validate_params: SandboxStartParams =
+                serde_json::from_value(request.params.clone()).map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.validate: {}", e),
+                    ))
+                })?;
+
+            let result = sandbox_validate_impl(state, validate_params).await?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
         "sandbox.stop" => {
             // Parse the params into a SandboxStopRequest
             let stop_params: SandboxStopParams = serde_json::from_value(request.params.clone())
@@ -162,6 +484,44 @@ pub async fn json_rpc_handler(
                 Json(JsonRpcResponse::success(json!(result), id)),
             ))
         }
+        "sandbox.pause" | "sandbox.resume" => {
+            // Parse the params into a SandboxPauseParams
+            let pause_params: SandboxPauseParams =
+                serde_json::from_value(request.params.clone()).map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for {}: {}", method, e),
+                    ))
+                })?;
+
+            let result = if method == "sandbox.pause" {
+                sandbox_pause_impl(state, pause_params).await?
+            } else {
+                sandbox_resume_impl(state, pause_params).await?
+            };
+
+            // Create JSON-RPC response with success
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+        "sandbox.clone" => {
+            // Parse the params into a SandboxCloneParams
+            let clone_params: SandboxCloneParams =
+                serde_json::from_value(request.params.clone()).map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.clone: {}", e),
+                    ))
+                })?;
+
+            let result = sandbox_clone_impl(state, clone_params).await?;
+
+            // Create JSON-RPC response with success
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
         "sandbox.metrics.get" => {
             // Parse the params into a SandboxMetricsGetRequest
             let metrics_params: SandboxMetricsGetParams =
@@ -180,9 +540,244 @@ pub async fn json_rpc_handler(
                 Json(JsonRpcResponse::success(json!(result), id)),
             ))
         }
+        "sandbox.fs.snapshot" => {
+            let snapshot_params: SandboxFsSnapshotParams =
+                serde_json::from_value(request.params.clone()).map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.fs.snapshot: {}", e),
+                    ))
+                })?;
 
-        // Portal-forwarded methods
+            let result = sandbox_fs_snapshot_impl(state, snapshot_params).await?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+        "sandbox.fs.diff" => {
+            let diff_params: SandboxFsDiffParams =
+                serde_json::from_value(request.params.clone()).map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.fs.diff: {}", e),
+                    ))
+                })?;
+
+            let result = sandbox_fs_diff_impl(state, diff_params).await?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+        "sandbox.snapshot.create" => {
+            let snapshot_params: SandboxSnapshotParams =
+                serde_json::from_value(request.params.clone()).map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.snapshot.create: {}", e),
+                    ))
+                })?;
+
+            let result = sandbox_snapshot_create_impl(state, snapshot_params).await?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+        "sandbox.snapshot.restore" => {
+            let restore_params: SandboxRestoreParams =
+                serde_json::from_value(request.params.clone()).map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.snapshot.restore: {}", e),
+                    ))
+                })?;
+
+            let result = sandbox_snapshot_restore_impl(state, restore_params).await?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+        "sandbox.status" => {
+            let health_params: SandboxHealthParams = serde_json::from_value(request.params.clone())
+                .map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.status: {}", e),
+                    ))
+                })?;
+            let (namespace, sandbox) = (
+                health_params.namespace.clone(),
+                health_params.sandbox.clone(),
+            );
+
+            let result = match sandbox_health_impl(state, health_params).await {
+                Ok(result) => result,
+                // A missing sandbox is reported as error data, apart from an unreachable server
+                Err(ServerError::NotFound(message)) => {
+                    let error = JsonRpcError {
+                        code: SANDBOX_NOT_FOUND_CODE,
+                        message,
+                        data: Some(json!({ "namespace": namespace, "sandbox": sandbox })),
+                    };
+                    return Ok((StatusCode::OK, Json(JsonRpcResponse::error(error, id))));
+                }
+                Err(e) => return Err(e),
+            };
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+
+        "sandbox.stats" => {
+            let stats_params: SandboxStatsParams =
+                serde_json::from_value(request.params.clone()).map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.stats: {}", e),
+                    ))
+                })?;
+            let (namespace, sandbox) =
+                (stats_params.namespace.clone(), stats_params.sandbox.clone());
+
+            let result = match sandbox_stats_impl(state, stats_params).await {
+                Ok(result) => result,
+                Err(ServerError::NotFound(message)) => {
+                    let error = JsonRpcError {
+                        code: SANDBOX_NOT_FOUND_CODE,
+                        message,
+                        data: Some(json!({ "namespace": namespace, "sandbox": sandbox })),
+                    };
+                    return Ok((StatusCode::OK, Json(JsonRpcResponse::error(error, id))));
+                }
+                Err(e) => return Err(e),
+            };
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+
+        "sandbox.list" => {
+            let list_params: SandboxListParams = serde_json::from_value(request.params.clone())
+                .map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.list: {}", e),
+                    ))
+                })?;
+
+            let result = sandbox_list_impl(state, list_params).await?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+
+        "sandbox.logs" => {
+            let logs_params: SandboxLogsParams = serde_json::from_value(request.params.clone())
+                .map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for sandbox.logs: {}", e),
+                    ))
+                })?;
+
+            let result = sandbox_logs_impl(state, logs_params).await?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+
+        "server.languages" => {
+            let result = server_languages_impl().await;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+
+        "server.namespaces" => {
+            let namespace = request.params.get("namespace").and_then(|v| v.as_str());
+            let result = server_namespaces_impl(state, namespace).await?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+
+        "server.info" => Ok((
+            StatusCode::OK,
+            Json(JsonRpcResponse::success(json!(server_info_impl()), id)),
+        )),
+
+        "image.pull" => {
+            let pull_params: ImagePullParams = serde_json::from_value(request.params.clone())
+                .map_err(|e| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!("Invalid params for image.pull: {}", e),
+                    ))
+                })?;
+
+            let result = image_pull_impl(pull_params).await?;
+
+            Ok((
+                StatusCode::OK,
+                Json(JsonRpcResponse::success(json!(result), id)),
+            ))
+        }
+
+        "sandbox.process.spawn" => {
+            let error = JsonRpcError {
+                code: -32600,
+                message: "sandbox.process.spawn needs a duplex request to /api/v1/rpc/duplex"
+                    .to_string(),
+                data: None,
+            };
+            Ok((
+                StatusCode::BAD_REQUEST,
+                Json(JsonRpcResponse::error(error, id)),
+            ))
+        }
+
+        // Portal-forwarded executions, which take one of the sandbox's execution slots
         "sandbox.repl.run" | "sandbox.command.run" => {
+            let param = |key: &str| {
+                request
+                    .params
+                    .get(key)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let (namespace, sandbox) = (param("namespace"), param("sandbox"));
+
+            // Hold the slot until the portal has answered
+            let _permit = match state.try_acquire_execution(&namespace, &sandbox).await {
+                Ok(permit) => permit,
+                Err(max) => {
+                    let error = execution_limit_error(&namespace, &sandbox, max);
+                    return Ok((StatusCode::OK, Json(JsonRpcResponse::error(error, id))));
+                }
+            };
+
+            forward_rpc_to_portal(state, request).await
+        }
+
+        // Portal-forwarded methods
+        "sandbox.repl.flush"
+        | "sandbox.repl.partial"
+        | "sandbox.env"
+        | "sandbox.fs.write"
+        | "sandbox.fs.read"
+        | "sandbox.fs.list"
+        | "sandbox.fs.watch" => {
             // Forward these RPC methods to the portal
             match forward_rpc_to_portal(state, request).await {
                 Ok((status, json_response)) => Ok((status, json_response)),
@@ -204,46 +799,40 @@ pub async fn json_rpc_handler(
     }
 }
 
+/// Converts a server error into its JSON-RPC error, for results that can't report it as the
+/// HTTP response: calls in a batch and streamed results
+fn call_error(error: &ServerError) -> JsonRpcError {
+    let code = match error {
+        ServerError::ValidationError(_) => -32602,
+        _ => -32603,
+    };
+
+    JsonRpcError {
+        code,
+        message: error.to_string(),
+        data: None,
+    }
+}
+
+/// Builds the error for an execution refused because the sandbox is running its maximum number
+/// of concurrent executions
+fn execution_limit_error(namespace: &str, sandbox: &str, max: usize) -> JsonRpcError {
+    JsonRpcError {
+        code: EXECUTION_LIMIT_CODE,
+        message: format!(
+            "Sandbox {}/{} is already running its maximum of {} concurrent executions",
+            namespace, sandbox, max
+        ),
+        data: Some(json!({ "max_concurrent_executions": max })),
+    }
+}
+
 /// Forwards the JSON-RPC request to the portal service
 pub async fn forward_rpc_to_portal(
     state: AppState,
     request: JsonRpcRequest,
 ) -> ServerResult<(StatusCode, Json<JsonRpcResponse>)> {
-    // Extract sandbox information from request context or method parameters
-    // The method will have the format "sandbox.repl.run" etc.
-    // The method params will have a sandbox_name and namespace parameter
-
-    // Extract the sandbox and namespace from the parameters
-    let (sandbox_name, namespace) = if let Some(params) = request.params.as_object() {
-        // Get sandbox name
-        let sandbox = params
-            .get("sandbox")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| {
-                ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
-                    "Missing required 'sandbox' parameter for portal request".to_string(),
-                ))
-            })?;
-
-        // Get namespace
-        let namespace = params
-            .get("namespace")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| {
-                ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
-                    "Missing required 'namespace' parameter for portal request".to_string(),
-                ))
-            })?;
-
-        (sandbox, namespace)
-    } else {
-        return Err(ServerError::ValidationError(
-            crate::error::ValidationError::InvalidInput(
-                "Request parameters must be an object containing 'sandbox' and 'namespace'"
-                    .to_string(),
-            ),
-        ));
-    };
+    let (namespace, sandbox_name) = portal_request_target(&request)?;
 
     // Get the portal URL specifically for this sandbox
     let portal_url = state
@@ -257,7 +846,166 @@ pub async fn forward_rpc_to_portal(
 
     // Create an HTTP client
     let client = reqwest::Client::new();
+    connect_to_portal(&client, &portal_url).await?;
+
+    // Forward the request to the portal now that we've verified connectivity
+    let response = client
+        .post(&portal_rpc_url)
+        .json(&request)
+        .send()
+        .await
+        .map_err(|e| {
+            ServerError::InternalError(format!("Failed to forward RPC to portal: {}", e))
+        })?;
+
+    // Check if the request was successful
+    if !response.status().is_success() {
+        return Err(portal_status_error(response).await);
+    }
+
+    // Parse the JSON-RPC response from the portal
+    let portal_response: JsonRpcResponse = response.json().await.map_err(|e| {
+        ServerError::InternalError(format!("Failed to parse portal response: {}", e))
+    })?;
+
+    // Return the portal's response directly
+    Ok((StatusCode::OK, Json(portal_response)))
+}
 
+/// Forwards a JSON-RPC request whose result the portal streams back as newline-delimited JSON
+/// frames, passing the frames on as they arrive
+///
+/// The stream takes one of the sandbox's execution slots and holds it until the stream ends. A
+/// request that fails before the portal starts streaming is answered with a single
+/// `{"error": ...}` frame.
+async fn forward_stream_to_portal(state: AppState, request: JsonRpcRequest) -> Response {
+    debug!(
+        method = %request.method,
+        params = %redact_env(&request.params),
+        id = ?request.id,
+        "Received streaming JSON-RPC request"
+    );
+
+    let body = match serde_json::to_vec(&request) {
+        Ok(body) => open_portal_stream(state, &request, "stream", body.into()).await,
+        Err(e) => Err(call_error(&ServerError::InternalError(format!(
+            "Failed to encode request: {}",
+            e
+        )))),
+    };
+
+    frame_stream_response(body)
+}
+
+/// Opens the portal's stream of frames for a JSON-RPC request, sending `body` to the portal's
+/// `endpoint` under `/api/v1/rpc`
+async fn open_portal_stream(
+    state: AppState,
+    request: &JsonRpcRequest,
+    endpoint: &str,
+    body: reqwest::Body,
+) -> Result<Body, JsonRpcError> {
+    if request.jsonrpc != JSONRPC_VERSION {
+        return Err(JsonRpcError {
+            code: -32600,
+            message: "Invalid or missing jsonrpc version field".to_string(),
+            data: None,
+        });
+    }
+
+    let (namespace, sandbox_name) = portal_request_target(request).map_err(|e| call_error(&e))?;
+    let permit = state
+        .try_acquire_execution(namespace, sandbox_name)
+        .await
+        .map_err(|max| execution_limit_error(namespace, sandbox_name, max))?;
+
+    let portal_url = state
+        .get_portal_url_for_sandbox(namespace, sandbox_name)
+        .await
+        .map_err(|e| call_error(&e))?;
+    let portal_stream_url = format!("{}/api/v1/rpc/{}", portal_url, endpoint);
+
+    debug!("Forwarding streaming RPC to portal: {}", portal_stream_url);
+
+    let client = reqwest::Client::new();
+    connect_to_portal(&client, &portal_url)
+        .await
+        .map_err(|e| call_error(&e))?;
+
+    let response = client
+        .post(&portal_stream_url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| {
+            call_error(&ServerError::InternalError(format!(
+                "Failed to forward RPC to portal: {}",
+                e
+            )))
+        })?;
+
+    if !response.status().is_success() {
+        return Err(call_error(&portal_status_error(response).await));
+    }
+
+    // Hold the execution slot until the portal ends the stream
+    let frames = response.bytes_stream().map(move |chunk| {
+        let _ = &permit;
+        chunk
+    });
+
+    Ok(Body::from_stream(frames))
+}
+
+/// Creates the response to a streaming JSON-RPC request from the stream of frames, or from the
+/// error that kept it from starting
+fn frame_stream_response(body: Result<Body, JsonRpcError>) -> Response {
+    let body = body.unwrap_or_else(|error| {
+        let mut frame = json!({ "error": error }).to_string();
+        frame.push('\n');
+        Body::from(frame)
+    });
+
+    ([(header::CONTENT_TYPE, NDJSON_CONTENT_TYPE)], body).into_response()
+}
+
+/// Gets the namespace and sandbox a portal-forwarded request is for from its params
+fn portal_request_target(request: &JsonRpcRequest) -> ServerResult<(&str, &str)> {
+    let Some(params) = request.params.as_object() else {
+        return Err(ServerError::ValidationError(
+            crate::error::ValidationError::InvalidInput(
+                "Request parameters must be an object containing 'sandbox' and 'namespace'"
+                    .to_string(),
+            ),
+        ));
+    };
+
+    // Get sandbox name
+    let sandbox = params
+        .get("sandbox")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| {
+            ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                "Missing required 'sandbox' parameter for portal request".to_string(),
+            ))
+        })?;
+
+    // Get namespace
+    let namespace = params
+        .get("namespace")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| {
+            ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                "Missing required 'namespace' parameter for portal request".to_string(),
+            ))
+        })?;
+
+    Ok((namespace, sandbox))
+}
+
+/// Waits until the portal at `portal_url` accepts connections
+async fn connect_to_portal(client: &reqwest::Client, portal_url: &str) -> ServerResult<()> {
     // Configure connection retry parameters
     const MAX_RETRIES: u32 = 10_000;
     const TIMEOUT_MS: u64 = 50;
@@ -270,7 +1018,7 @@ pub async fn forward_rpc_to_portal(
     while retry_count < MAX_RETRIES {
         // Check if portal is available with a HEAD request
         match client
-            .head(&portal_url)
+            .head(portal_url)
             .timeout(Duration::from_millis(TIMEOUT_MS))
             .send()
             .await
@@ -282,7 +1030,7 @@ pub async fn forward_rpc_to_portal(
                     retry_count,
                     response.status()
                 );
-                break;
+                return Ok(());
             }
             Err(e) => {
                 // Track the error for potential reporting but keep retrying
@@ -295,61 +1043,97 @@ pub async fn forward_rpc_to_portal(
         retry_count += 1;
     }
 
-    // If we've hit the max retries and still can't connect, report the error
-    if retry_count >= MAX_RETRIES {
-        let error_msg = if let Some(e) = last_error {
-            format!(
-                "Failed to connect to portal after {} retries: {}",
-                MAX_RETRIES, e
-            )
-        } else {
-            format!("Failed to connect to portal after {} retries", MAX_RETRIES)
-        };
-        return Err(ServerError::InternalError(error_msg));
-    }
+    // We've hit the max retries and still can't connect
+    let error_msg = if let Some(e) = last_error {
+        format!(
+            "Failed to connect to portal after {} retries: {}",
+            MAX_RETRIES, e
+        )
+    } else {
+        format!("Failed to connect to portal after {} retries", MAX_RETRIES)
+    };
+    Err(ServerError::InternalError(error_msg))
+}
 
-    // Forward the request to the portal now that we've verified connectivity
-    let response = client
-        .post(&portal_rpc_url)
-        .json(&request)
-        .send()
+/// Builds the error for a portal response with an unsuccessful status
+async fn portal_status_error(response: reqwest::Response) -> ServerError {
+    let status = response.status();
+    let error_text = response
+        .text()
         .await
-        .map_err(|e| {
-            ServerError::InternalError(format!("Failed to forward RPC to portal: {}", e))
-        })?;
+        .unwrap_or_else(|_| "Unknown error".to_string());
 
-    // Check if the request was successful
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
+    ServerError::InternalError(format!(
+        "Portal returned error status {}: {}",
+        status, error_text
+    ))
+}
 
-        return Err(ServerError::InternalError(format!(
-            "Portal returned error status {}: {}",
-            status, error_text
-        )));
-    }
+/// Implementation for starting a sandbox
+///
+/// Starts of the same sandbox run one at a time. A start with the idempotency key of the
+/// sandbox's last successful start returns the running sandbox instead of starting it again,
+/// so a client can retry a start whose response it never got, even while the first attempt
+/// is still starting the sandbox. Keys are only kept in memory, so when the server doesn't
+/// know the sandbox's last key, as after a restart, any start with a key returns the sandbox
+/// if it is running.
+pub async fn sandbox_start_impl(
+    state: AppState,
+    params: SandboxStartParams,
+) -> ServerResult<SandboxStartResponse> {
+    // Validate sandbox name and namespace
+    validate_sandbox_name(&params.sandbox)?;
+    validate_namespace(&params.namespace)?;
 
-    // Parse the JSON-RPC response from the portal
-    let portal_response: JsonRpcResponse = response.json().await.map_err(|e| {
-        ServerError::InternalError(format!("Failed to parse portal response: {}", e))
-    })?;
+    let mut last_key = state.lock_start(&params.namespace, &params.sandbox).await;
+    let idempotency_key = params.idempotency_key.clone();
+    if idempotency_key.is_some() && (last_key.is_none() || *last_key == idempotency_key) {
+        let namespace_dir = state
+            .get_config()
+            .get_namespace_dir()
+            .join(&params.namespace);
+        if namespace_dir.join(MICROSANDBOX_CONFIG_FILENAME).exists() {
+            let statuses = orchestra::status(
+                vec![params.sandbox.clone()],
+                Some(&namespace_dir),
+                Some(MICROSANDBOX_CONFIG_FILENAME),
+            )
+            .await
+            .map_err(|e| {
+                ServerError::InternalError(format!("Failed to get sandbox status: {}", e))
+            })?;
+
+            if let Some(status) = statuses
+                .iter()
+                .find(|s| s.name == params.sandbox && s.running)
+            {
+                debug!(
+                    "Sandbox {} already running for idempotency key {:?}",
+                    params.sandbox, idempotency_key
+                );
+                return Ok(SandboxStartResponse {
+                    paused: status.paused,
+                    ..SandboxStartResponse::new(format!(
+                        "Sandbox {} is already running",
+                        params.sandbox
+                    ))
+                });
+            }
+        }
+    }
 
-    // Return the portal's response directly
-    Ok((StatusCode::OK, Json(portal_response)))
+    let started = start_sandbox_locked(state, params).await;
+    if started.is_ok() {
+        *last_key = idempotency_key;
+    }
+    started
 }
 
-/// Implementation for starting a sandbox
-pub async fn sandbox_start_impl(
+/// Starts a sandbox, with the sandbox's start lock held
+async fn start_sandbox_locked(
     state: AppState,
     params: SandboxStartParams,
-) -> ServerResult<String> {
-    // Validate sandbox name and namespace
-    validate_sandbox_name(&params.sandbox)?;
-    validate_namespace(&params.namespace)?;
-
+) -> ServerResult<SandboxStartResponse> {
     let namespace_dir = state
         .get_config()
         .get_namespace_dir()
@@ -584,6 +1368,57 @@ pub async fn sandbox_start_impl(
                 );
             }
 
+            if config.security.is_some() {
+                return Err(ServerError::ValidationError(
+                    crate::error::ValidationError::InvalidInput(
+                        "security profiles are not supported by the sandbox backend".to_string(),
+                    ),
+                ));
+            }
+
+            if let Some(hostname) = &config.hostname {
+                validate_hostname(hostname)?;
+                sandbox_map.insert(
+                    serde_yaml::Value::String("hostname".to_string()),
+                    serde_yaml::Value::String(hostname.clone()),
+                );
+            }
+
+            if config.max_concurrent_executions == Some(0) {
+                return Err(ServerError::ValidationError(
+                    crate::error::ValidationError::InvalidInput(
+                        "max_concurrent_executions must be at least 1".to_string(),
+                    ),
+                ));
+            }
+
+            if let Some(oom_score_adj) = config.oom_score_adj {
+                if !(-1000..=1000).contains(&oom_score_adj) {
+                    return Err(ServerError::ValidationError(
+                        crate::error::ValidationError::InvalidInput(format!(
+                            "oom_score_adj must be between -1000 and 1000, got {}",
+                            oom_score_adj
+                        )),
+                    ));
+                }
+                sandbox_map.insert(
+                    serde_yaml::Value::String("oom_score_adj".to_string()),
+                    serde_yaml::Value::Number(oom_score_adj.into()),
+                );
+            }
+
+            if !config.ulimits.is_empty() {
+                let ulimits_array = config
+                    .ulimits
+                    .iter()
+                    .map(|u| validate_ulimit(u).map(serde_yaml::Value::String))
+                    .collect::<ServerResult<Vec<_>>>()?;
+                sandbox_map.insert(
+                    serde_yaml::Value::String("ulimits".to_string()),
+                    serde_yaml::Value::Sequence(ulimits_array),
+                );
+            }
+
             // Replace or add the sandbox in the config
             sandboxes_map.insert(
                 serde_yaml::Value::String(sandbox.clone()),
@@ -650,17 +1485,78 @@ pub async fn sandbox_start_impl(
         .await
         .map_err(|e| ServerError::InternalError(format!("Failed to write config file: {}", e)))?;
 
+    // Pull the image up front when pulling has its own time limit, so that a slow pull
+    // isn't counted against the boot
+    let image_name = params.config.as_ref().and_then(|c| c.image.as_ref());
+    if let (Some(limit), Some(image_name)) =
+        (phase_limit("pull", params.timeouts.pull)?, image_name)
+    {
+        let reference = image_name.parse::<Reference>().map_err(|e| {
+            ServerError::ValidationError(crate::error::ValidationError::InvalidInput(format!(
+                "Invalid image '{}': {}",
+                image_name, e
+            )))
+        })?;
+        within_phase(
+            "pull",
+            limit,
+            format!("pulling image {}", image_name),
+            image::pull(reference, true, None),
+        )
+        .await?
+        .map_err(|e| {
+            ServerError::InternalError(format!("Failed to pull image {}: {}", image_name, e))
+        })?;
+    }
+
+    // Executions are limited from this start on, replacing the limit of any earlier start
+    let max_concurrent_executions = params
+        .config
+        .as_ref()
+        .and_then(|c| c.max_concurrent_executions);
+    state
+        .set_execution_limit(&params.namespace, sandbox, max_concurrent_executions)
+        .await;
+
+    let boot_limit = phase_limit("boot", params.timeouts.boot)?;
+    let ready_limit = phase_limit("ready", params.timeouts.ready)?;
+
     // Start the sandbox
-    orchestra::up(
-        vec![sandbox.clone()],
-        Some(&namespace_dir),
-        Some(config_file),
-        true,
-    )
-    .await
-    .map_err(|e| {
-        ServerError::InternalError(format!("Failed to start sandbox {}: {}", params.sandbox, e))
-    })?;
+    let up = async {
+        orchestra::up(
+            vec![sandbox.clone()],
+            Some(&namespace_dir),
+            Some(config_file),
+            true,
+        )
+        .await
+        .map_err(|e| {
+            ServerError::InternalError(format!("Failed to start sandbox {}: {}", params.sandbox, e))
+        })
+    };
+
+    // With explicit limits, the boot and ready phases must complete or the start fails
+    if boot_limit.is_some() || ready_limit.is_some() {
+        let boot = async {
+            up.await?;
+            poll_sandbox_until_running(&params.sandbox, &namespace_dir, config_file).await
+        };
+        match boot_limit {
+            Some(limit) => {
+                within_phase("boot", limit, format!("booting {}", sandbox), boot).await??
+            }
+            None => boot.await?,
+        }
+        if let Some(limit) = ready_limit {
+            wait_for_portal_ready(&state, &params.namespace, sandbox, limit).await?;
+        }
+        return Ok(SandboxStartResponse::new(format!(
+            "Sandbox {} started successfully",
+            params.sandbox
+        )));
+    }
+
+    up.await?;
 
     // Determine if this is a first-time image pull based on config
     let potentially_first_time_pull = if let Some(config) = &params.config {
@@ -688,28 +1584,104 @@ pub async fn sandbox_start_impl(
         Ok(result) => match result {
             Ok(_) => {
                 debug!("Sandbox {} is now running", sandbox);
-                Ok(format!("Sandbox {} started successfully", params.sandbox))
+                Ok(SandboxStartResponse::new(format!(
+                    "Sandbox {} started successfully",
+                    params.sandbox
+                )))
             }
             Err(e) => {
                 // The sandbox was started but polling failed for some reason
                 warn!("Failed to verify sandbox {} is running: {}", sandbox, e);
-                Ok(format!(
-                    "Sandbox {} was started, but couldn't verify it's running: {}",
-                    params.sandbox, e
+                Ok(SandboxStartResponse::with_warning(
+                    format!(
+                        "Sandbox {} was started, but couldn't verify it's running: {}",
+                        params.sandbox, e
+                    ),
+                    WARNING_START_UNVERIFIED,
                 ))
             }
         },
         Err(_) => {
             // Timeout occurred, but we still return success since the sandbox might still be starting
             warn!("Timeout waiting for sandbox {} to start", sandbox);
-            Ok(format!(
-                "Sandbox {} was started, but timed out waiting for it to be fully running. It may still be initializing.",
-                params.sandbox
+            Ok(SandboxStartResponse::with_warning(
+                format!(
+                    "Sandbox {} was started, but timed out waiting for it to be fully running. It may still be initializing.",
+                    params.sandbox
+                ),
+                WARNING_START_TIMEOUT,
             ))
         }
     }
 }
 
+/// Converts a phase time limit from seconds, rejecting limits that aren't positive
+fn phase_limit(phase: &str, seconds: Option<f64>) -> ServerResult<Option<Duration>> {
+    seconds
+        .map(|seconds| {
+            Duration::try_from_secs_f64(seconds)
+                .ok()
+                .filter(|limit| !limit.is_zero())
+                .ok_or_else(|| {
+                    ServerError::ValidationError(crate::error::ValidationError::InvalidInput(
+                        format!(
+                            "The {} timeout must be a positive number of seconds, got {}",
+                            phase, seconds
+                        ),
+                    ))
+                })
+        })
+        .transpose()
+}
+
+/// Runs a phase of a sandbox start, failing with the phase's name if it exceeds `limit`
+async fn within_phase<T>(
+    phase: &'static str,
+    limit: Duration,
+    waiting_for: String,
+    future: impl std::future::Future<Output = T>,
+) -> ServerResult<T> {
+    timeout(limit, future)
+        .await
+        .map_err(|_| ServerError::StartPhaseTimeout {
+            phase,
+            message: format!("gave up {} after {:?}", waiting_for, limit),
+        })
+}
+
+/// Waits until the portal of a starting sandbox accepts connections
+async fn wait_for_portal_ready(
+    state: &AppState,
+    namespace: &str,
+    sandbox: &str,
+    limit: Duration,
+) -> ServerResult<()> {
+    let portal_url = state.get_portal_url_for_sandbox(namespace, sandbox).await?;
+    let client = reqwest::Client::new();
+
+    within_phase(
+        "ready",
+        limit,
+        format!(
+            "waiting for the portal of {} to accept connections",
+            sandbox
+        ),
+        async {
+            // Any HTTP response means the portal is up
+            while client
+                .head(&portal_url)
+                .timeout(PORTAL_READY_POLL_INTERVAL)
+                .send()
+                .await
+                .is_err()
+            {
+                sleep(PORTAL_READY_POLL_INTERVAL).await;
+            }
+        },
+    )
+    .await
+}
+
 /// Polls the sandbox until it's verified to be running
 async fn poll_sandbox_until_running(
     sandbox_name: &str,
@@ -752,6 +1724,106 @@ async fn poll_sandbox_until_running(
     )))
 }
 
+/// Implementation for validating a sandbox configuration without starting the sandbox
+///
+/// Runs the checks a start would run, with the same params, and reports the outcome of each
+/// instead of stopping at the first failure. Nothing is written to the namespace.
+pub async fn sandbox_validate_impl(
+    state: AppState,
+    params: SandboxStartParams,
+) -> ServerResult<SandboxValidateResponse> {
+    let mut diagnostics = Vec::new();
+
+    // Check the names first, the config path is built from them
+    match validate_sandbox_name(&params.sandbox).and_then(|_| validate_namespace(&params.namespace))
+    {
+        Ok(()) => diagnostics.push(ConfigDiagnostic::pass(
+            CHECK_NAME,
+            "Sandbox and namespace names are valid",
+        )),
+        Err(e) => {
+            diagnostics.push(ConfigDiagnostic::fail(CHECK_NAME, e.to_string()));
+            return Ok(SandboxValidateResponse::new(diagnostics));
+        }
+    }
+
+    // Parse the existing config, which a start keeps the other sandboxes of
+    let config_path = state
+        .get_config()
+        .get_namespace_dir()
+        .join(&params.namespace)
+        .join(MICROSANDBOX_CONFIG_FILENAME);
+    let existing = if config_path.exists() {
+        let parsed = match tokio_fs::read_to_string(&config_path).await {
+            Ok(content) => {
+                serde_yaml::from_str::<Microsandbox>(&content).map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        match parsed {
+            Ok(existing) => Some(existing),
+            Err(e) => {
+                diagnostics.push(ConfigDiagnostic::fail(
+                    CHECK_CONFIG,
+                    format!("Failed to parse existing configuration: {}", e),
+                ));
+                return Ok(SandboxValidateResponse::new(diagnostics));
+            }
+        }
+    } else {
+        None
+    };
+
+    // Settings from the request replace the sandbox's existing ones
+    let requested = params.config.as_ref().filter(|c| c.image.is_some());
+    let existing_sandbox = existing
+        .as_ref()
+        .and_then(|c| c.get_sandbox(&params.sandbox));
+    let (image, memory, cpus) = match (requested, existing_sandbox) {
+        (Some(config), _) => {
+            diagnostics.push(ConfigDiagnostic::pass(
+                CHECK_CONFIG,
+                "Configuration provided in the request",
+            ));
+            (
+                config.image.clone().unwrap_or_default(),
+                config.memory,
+                config.cpus,
+            )
+        }
+        (None, Some(sandbox)) => {
+            if let Err(e) = sandbox.validate() {
+                diagnostics.push(ConfigDiagnostic::fail(CHECK_CONFIG, e.to_string()));
+                return Ok(SandboxValidateResponse::new(diagnostics));
+            }
+            diagnostics.push(ConfigDiagnostic::pass(
+                CHECK_CONFIG,
+                "Existing configuration is valid",
+            ));
+            (
+                sandbox.get_image().to_string(),
+                *sandbox.get_memory(),
+                *sandbox.get_cpus(),
+            )
+        }
+        (None, None) => {
+            diagnostics.push(ConfigDiagnostic::fail(
+                CHECK_CONFIG,
+                format!(
+                    "No configuration provided and no existing configuration found for sandbox '{}'",
+                    params.sandbox
+                ),
+            ));
+            return Ok(SandboxValidateResponse::new(diagnostics));
+        }
+    };
+
+    diagnostics.push(check_image(&image).await);
+    diagnostics.push(check_resources(memory, cpus));
+
+    Ok(SandboxValidateResponse::new(diagnostics))
+}
+
 /// Implementation for stopping a sandbox
 pub async fn sandbox_stop_impl(state: AppState, params: SandboxStopParams) -> ServerResult<String> {
     // Validate sandbox name and namespace
@@ -776,40 +1848,507 @@ pub async fn sandbox_stop_impl(state: AppState, params: SandboxStopParams) -> Se
         ));
     }
 
-    // Verify that the config file exists
-    let config_path = namespace_dir.join(config_file);
-    if !config_path.exists() {
-        return Err(ServerError::ValidationError(
-            crate::error::ValidationError::InvalidInput(format!(
-                "Configuration file not found for namespace '{}'",
-                params.namespace
-            )),
-        ));
-    }
+    // Verify that the config file exists
+    let config_path = namespace_dir.join(config_file);
+    if !config_path.exists() {
+        return Err(ServerError::ValidationError(
+            crate::error::ValidationError::InvalidInput(format!(
+                "Configuration file not found for namespace '{}'",
+                params.namespace
+            )),
+        ));
+    }
+
+    // Stop the sandbox using orchestra::down
+    orchestra::down(
+        vec![sandbox.clone()],
+        Some(&namespace_dir),
+        Some(config_file),
+    )
+    .await
+    .map_err(|e| {
+        ServerError::InternalError(format!("Failed to stop sandbox {}: {}", params.sandbox, e))
+    })?;
+
+    // Release the assigned port
+    {
+        let mut port_manager = state.get_port_manager().write().await;
+        port_manager.release_port(&sandbox_key).await.map_err(|e| {
+            ServerError::InternalError(format!("Failed to release portal port: {}", e))
+        })?;
+    }
+
+    debug!("Released portal port for sandbox {}", sandbox_key);
+
+    state
+        .set_execution_limit(&params.namespace, sandbox, None)
+        .await;
+    state.remove_start_lock(&params.namespace, sandbox).await;
+
+    // Return success message
+    Ok(format!("Sandbox {} stopped successfully", params.sandbox))
+}
+
+/// Implementation for pausing a sandbox
+pub async fn sandbox_pause_impl(
+    state: AppState,
+    params: SandboxPauseParams,
+) -> ServerResult<String> {
+    let namespace_dir = get_sandbox_namespace_dir(&state, &params.namespace, &params.sandbox)?;
+
+    orchestra::pause(
+        vec![params.sandbox.clone()],
+        Some(&namespace_dir),
+        Some(MICROSANDBOX_CONFIG_FILENAME),
+    )
+    .await
+    .map_err(|e| {
+        ServerError::InternalError(format!("Failed to pause sandbox {}: {}", params.sandbox, e))
+    })?;
+
+    Ok(format!("Sandbox {} paused successfully", params.sandbox))
+}
+
+/// Implementation for resuming a paused sandbox
+pub async fn sandbox_resume_impl(
+    state: AppState,
+    params: SandboxPauseParams,
+) -> ServerResult<String> {
+    let namespace_dir = get_sandbox_namespace_dir(&state, &params.namespace, &params.sandbox)?;
+
+    orchestra::resume(
+        vec![params.sandbox.clone()],
+        Some(&namespace_dir),
+        Some(MICROSANDBOX_CONFIG_FILENAME),
+    )
+    .await
+    .map_err(|e| {
+        ServerError::InternalError(format!(
+            "Failed to resume sandbox {}: {}",
+            params.sandbox, e
+        ))
+    })?;
+
+    Ok(format!("Sandbox {} resumed successfully", params.sandbox))
+}
+
+/// Implementation for cloning a sandbox into a new one and starting the clone
+///
+/// The clone gets a copy of the source's configuration and of its writable layer, so it
+/// starts from the source's current filesystem on top of the same image layers. A running
+/// source is paused while its writable layer is copied, so the copy is consistent, and is
+/// resumed afterwards whether or not the copy succeeded.
+///
+/// The source's port mappings aren't copied, since their host ports are taken by the source;
+/// the clone gets only its own portal port. If the clone can't be registered or started, its
+/// copied layer and configuration entry are removed.
+pub async fn sandbox_clone_impl(
+    state: AppState,
+    params: SandboxCloneParams,
+) -> ServerResult<String> {
+    let namespace_dir = get_sandbox_namespace_dir(&state, &params.namespace, &params.sandbox)?;
+    validate_sandbox_name(&params.new_name)?;
+
+    let config_path = namespace_dir.join(MICROSANDBOX_CONFIG_FILENAME);
+    let config_content = tokio_fs::read_to_string(&config_path)
+        .await
+        .map_err(|e| ServerError::InternalError(format!("Failed to read config file: {}", e)))?;
+    let mut config_yaml: serde_yaml::Value = serde_yaml::from_str(&config_content)
+        .map_err(|e| ServerError::InternalError(format!("Failed to parse config file: {}", e)))?;
+
+    let sandboxes_map = config_yaml
+        .get_mut("sandboxes")
+        .and_then(|sandboxes| sandboxes.as_mapping_mut())
+        .ok_or_else(|| {
+            ServerError::ValidationError(crate::error::ValidationError::InvalidInput(format!(
+                "Sandbox '{}' not found in existing configuration",
+                params.sandbox
+            )))
+        })?;
+    let source_config = sandboxes_map
+        .get(params.sandbox.as_str())
+        .cloned()
+        .ok_or_else(|| {
+            ServerError::ValidationError(crate::error::ValidationError::InvalidInput(format!(
+                "Sandbox '{}' not found in existing configuration",
+                params.sandbox
+            )))
+        })?;
+    if sandboxes_map.contains_key(params.new_name.as_str()) {
+        return Err(ServerError::ValidationError(
+            crate::error::ValidationError::InvalidInput(format!(
+                "Sandbox '{}' already exists in namespace '{}'",
+                params.new_name, params.namespace
+            )),
+        ));
+    }
+
+    let rw_dir = namespace_dir
+        .join(MICROSANDBOX_ENV_DIR)
+        .join(RW_SUBDIR)
+        .join(MICROSANDBOX_CONFIG_FILENAME);
+    let source_rw_path = rw_dir.join(&params.sandbox);
+    let clone_rw_path = rw_dir.join(&params.new_name);
+    if !source_rw_path.exists() {
+        return Err(ServerError::ValidationError(
+            crate::error::ValidationError::InvalidInput(format!(
+                "Sandbox {}/{} has no writable layer; it may not have been started",
+                params.namespace, params.sandbox
+            )),
+        ));
+    }
+
+    // Quiesce the source while its writable layer is copied
+    let statuses = orchestra::status(
+        vec![params.sandbox.clone()],
+        Some(&namespace_dir),
+        Some(MICROSANDBOX_CONFIG_FILENAME),
+    )
+    .await
+    .map_err(|e| ServerError::InternalError(format!("Failed to get sandbox status: {}", e)))?;
+    let pause_source = statuses
+        .iter()
+        .any(|s| s.name == params.sandbox && s.running && !s.paused);
+
+    let source_params = SandboxPauseParams {
+        sandbox: params.sandbox.clone(),
+        namespace: params.namespace.clone(),
+    };
+    if pause_source {
+        sandbox_pause_impl(state.clone(), source_params).await?;
+    }
+
+    let copied = snapshot::copy_layer(&source_rw_path, &clone_rw_path)
+        .await
+        .map_err(|e| {
+            ServerError::InternalError(format!(
+                "Failed to copy writable layer {}: {}",
+                source_rw_path.display(),
+                e
+            ))
+        });
+
+    if pause_source {
+        let source_params = SandboxPauseParams {
+            sandbox: params.sandbox.clone(),
+            namespace: params.namespace.clone(),
+        };
+        sandbox_resume_impl(state.clone(), source_params).await?;
+    }
+    copied?;
+
+    debug!(
+        "Copied writable layer of sandbox {} to {}",
+        params.sandbox, params.new_name
+    );
+
+    // Register the clone with the source's configuration, without the host ports the source
+    // already holds, then start it
+    let mut clone_config = source_config;
+    if let Some(clone_mapping) = clone_config.as_mapping_mut() {
+        clone_mapping.remove("ports");
+    }
+    sandboxes_map.insert(
+        serde_yaml::Value::String(params.new_name.clone()),
+        clone_config,
+    );
+    let registered = match serde_yaml::to_string(&config_yaml) {
+        Ok(updated_config) => tokio_fs::write(&config_path, updated_config)
+            .await
+            .map_err(|e| ServerError::InternalError(format!("Failed to write config file: {}", e))),
+        Err(e) => Err(ServerError::InternalError(format!(
+            "Failed to serialize config: {}",
+            e
+        ))),
+    };
+    if let Err(e) = registered {
+        discard_clone(&clone_rw_path, None, &params.new_name).await;
+        return Err(e);
+    }
+
+    let started = sandbox_start_impl(
+        state,
+        SandboxStartParams {
+            sandbox: params.new_name.clone(),
+            namespace: params.namespace.clone(),
+            config: None,
+            idempotency_key: None,
+            timeouts: Default::default(),
+        },
+    )
+    .await;
+    if let Err(e) = started {
+        discard_clone(&clone_rw_path, Some(&config_path), &params.new_name).await;
+        return Err(e);
+    }
+
+    Ok(format!(
+        "Sandbox {} cloned to {} successfully",
+        params.sandbox, params.new_name
+    ))
+}
+
+/// Remove what a failed clone left behind: its copied writable layer and, if the clone was
+/// registered in `config_path`, its configuration entry
+///
+/// Failures are logged rather than returned, so they don't hide the error that failed the clone.
+async fn discard_clone(
+    clone_rw_path: &std::path::Path,
+    config_path: Option<&std::path::Path>,
+    new_name: &str,
+) {
+    if let Err(e) = tokio_fs::remove_dir_all(clone_rw_path).await {
+        warn!(
+            "Failed to remove writable layer {} of failed clone: {}",
+            clone_rw_path.display(),
+            e
+        );
+    }
+
+    let Some(config_path) = config_path else {
+        return;
+    };
+    let unregistered = async {
+        let config_content = tokio_fs::read_to_string(config_path).await?;
+        let mut config_yaml: serde_yaml::Value =
+            serde_yaml::from_str(&config_content).map_err(std::io::Error::other)?;
+        if let Some(sandboxes_map) = config_yaml
+            .get_mut("sandboxes")
+            .and_then(|sandboxes| sandboxes.as_mapping_mut())
+        {
+            sandboxes_map.remove(new_name);
+        }
+        let updated_config = serde_yaml::to_string(&config_yaml).map_err(std::io::Error::other)?;
+        tokio_fs::write(config_path, updated_config).await
+    };
+    if let Err(e) = unregistered.await {
+        warn!(
+            "Failed to remove failed clone {} from config: {}",
+            new_name, e
+        );
+    }
+}
+
+/// Implementation for recording a snapshot marker of a sandbox's filesystem
+pub async fn sandbox_fs_snapshot_impl(
+    state: AppState,
+    params: SandboxFsSnapshotParams,
+) -> ServerResult<SandboxFsSnapshotResponse> {
+    let manifest = scan_sandbox_rw_layer(&state, &params.namespace, &params.sandbox).await?;
+    let snapshot_id = state
+        .save_fs_snapshot(&params.namespace, &params.sandbox, manifest)
+        .await;
+
+    Ok(SandboxFsSnapshotResponse { snapshot_id })
+}
+
+/// Implementation for diffing a sandbox's filesystem against a snapshot marker
+pub async fn sandbox_fs_diff_impl(
+    state: AppState,
+    params: SandboxFsDiffParams,
+) -> ServerResult<SandboxFsDiffResponse> {
+    let manifest = scan_sandbox_rw_layer(&state, &params.namespace, &params.sandbox).await?;
+    let since = state
+        .get_fs_snapshot(&params.since, &params.namespace, &params.sandbox)
+        .await?;
+
+    Ok(SandboxFsDiffResponse {
+        changes: since.diff(&manifest),
+    })
+}
+
+/// Implementation for taking a snapshot of a sandbox's writable layer
+///
+/// A running sandbox is paused while its layer is copied and resumed afterwards.
+pub async fn sandbox_snapshot_create_impl(
+    state: AppState,
+    params: SandboxSnapshotParams,
+) -> ServerResult<SandboxSnapshotResponse> {
+    let namespace_dir = get_sandbox_namespace_dir(&state, &params.namespace, &params.sandbox)?;
+
+    let taken = snapshot::create(
+        &params.sandbox,
+        &params.name,
+        Some(&namespace_dir),
+        Some(MICROSANDBOX_CONFIG_FILENAME),
+    )
+    .await
+    .map_err(|e| snapshot_error(&params.namespace, &params.sandbox, e))?;
+
+    Ok(SandboxSnapshotResponse {
+        name: taken.name,
+        size_bytes: taken.size_bytes,
+        created_at: taken.created_at.to_rfc3339(),
+    })
+}
+
+/// Implementation for restoring a sandbox's writable layer from a snapshot
+///
+/// The sandbox must be stopped; starting it afterwards boots from the restored layer.
+pub async fn sandbox_snapshot_restore_impl(
+    state: AppState,
+    params: SandboxRestoreParams,
+) -> ServerResult<SandboxSnapshotResponse> {
+    let namespace_dir = get_sandbox_namespace_dir(&state, &params.namespace, &params.sandbox)?;
 
-    // Stop the sandbox using orchestra::down
-    orchestra::down(
-        vec![sandbox.clone()],
+    let restored = snapshot::restore(
+        &params.sandbox,
+        &params.snapshot,
         Some(&namespace_dir),
-        Some(config_file),
+        Some(MICROSANDBOX_CONFIG_FILENAME),
     )
     .await
-    .map_err(|e| {
-        ServerError::InternalError(format!("Failed to stop sandbox {}: {}", params.sandbox, e))
+    .map_err(|e| snapshot_error(&params.namespace, &params.sandbox, e))?;
+
+    Ok(SandboxSnapshotResponse {
+        name: restored.name,
+        size_bytes: restored.size_bytes,
+        created_at: restored.created_at.to_rfc3339(),
+    })
+}
+
+/// Implementation for reading a sandbox's captured output
+///
+/// Output is read from the active log file only; output rotated out of it is reported as
+/// truncated rather than read back from the rotated segments. Filtering by stream needs the
+/// sandbox's log to be in the `jsonl` format, since raw logs don't record which stream the
+/// output came from.
+pub async fn sandbox_logs_impl(
+    state: AppState,
+    params: SandboxLogsParams,
+) -> ServerResult<SandboxLogsResponse> {
+    let invalid = |message: String| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(message))
+    };
+
+    let stream = match params.stream.as_deref() {
+        None | Some("both") => None,
+        Some(stream @ ("stdout" | "stderr")) => Some(stream),
+        Some(other) => {
+            return Err(invalid(format!(
+                "Invalid stream '{}': expected stdout, stderr or both",
+                other
+            )))
+        }
+    };
+
+    let namespace_dir = get_sandbox_namespace_dir(&state, &params.namespace, &params.sandbox)?;
+    let log_path = MicroVmMonitor::log_path_for(
+        &namespace_dir.join(MICROSANDBOX_ENV_DIR).join(LOG_SUBDIR),
+        MICROSANDBOX_CONFIG_FILENAME,
+        &params.sandbox,
+    );
+
+    if !log_path.exists() {
+        return Err(invalid(format!(
+            "Sandbox {}/{} has no log; it may not have been started",
+            params.namespace, params.sandbox
+        )));
+    }
+
+    let index = LogIndex::load(&log_path).await.map_err(|e| {
+        ServerError::InternalError(format!("Failed to load index of sandbox log: {}", e))
     })?;
+    let contents = tokio_fs::read(&log_path)
+        .await
+        .map_err(|e| ServerError::InternalError(format!("Failed to read sandbox log: {}", e)))?;
+
+    // Offsets count bytes of the whole log stream, of which the active file is the tail
+    let active_start = index.active_start();
+    let requested = params.offset.unwrap_or(0);
+    let skip = requested
+        .saturating_sub(active_start)
+        .min(contents.len() as u64) as usize;
+    let mut truncated = requested < active_start;
+
+    // Tailing reads back from the end of the log, otherwise read forward from the offset
+    let (mut start, mut end) = if params.tail.is_some() {
+        let start = skip.max(contents.len().saturating_sub(MAX_LOGS_RESPONSE_SIZE));
+        truncated |= start > skip;
+        (start, contents.len())
+    } else {
+        (skip, contents.len().min(skip + MAX_LOGS_RESPONSE_SIZE))
+    };
 
-    // Release the assigned port
-    {
-        let mut port_manager = state.get_port_manager().write().await;
-        port_manager.release_port(&sandbox_key).await.map_err(|e| {
-            ServerError::InternalError(format!("Failed to release portal port: {}", e))
-        })?;
+    let json_lines = contents
+        .split(|b| *b == b'\n')
+        .next()
+        .and_then(|line| serde_json::from_slice::<LogRecord>(line).ok())
+        .is_some();
+
+    let output = if json_lines {
+        // Only return whole records, so the next read starts at the beginning of one
+        if start > 0 && contents[start - 1] != b'\n' {
+            start = find_line_end(&contents, start, end).unwrap_or(end);
+        }
+        end = contents[start..end]
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(start, |i| start + i + 1);
+
+        let mut output = Vec::new();
+        for line in contents[start..end].split(|b| *b == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            let record: LogRecord = serde_json::from_slice(line).map_err(|e| {
+                ServerError::InternalError(format!("Malformed record in sandbox log: {}", e))
+            })?;
+            if stream.is_none_or(|stream| stream == record.stream) {
+                output.extend_from_slice(record.data.as_bytes());
+            }
+        }
+        output
+    } else if stream.is_some() {
+        return Err(invalid(format!(
+            "The log of sandbox {}/{} is in the raw format, which doesn't record which stream \
+             output came from; start the sandbox with the jsonl log format to filter by stream",
+            params.namespace, params.sandbox
+        )));
+    } else {
+        contents[start..end].to_vec()
+    };
+
+    let output = match params.tail {
+        Some(lines) => tail_lines(&output, lines).to_vec(),
+        None => output,
+    };
+
+    Ok(SandboxLogsResponse {
+        content: BASE64.encode(output),
+        next_offset: active_start + end as u64,
+        truncated,
+    })
+}
+
+/// Implementation for reporting the server's version and the JSON-RPC methods it handles
+pub fn server_info_impl() -> ServerInfoResponse {
+    ServerInfoResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        methods: SUPPORTED_METHODS.iter().map(|m| m.to_string()).collect(),
+        capabilities: SUPPORTED_CAPABILITIES
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
     }
+}
 
-    debug!("Released portal port for sandbox {}", sandbox_key);
+/// Implementation for listing the languages the server supports
+///
+/// Versions are read from the environment of the language images, which set e.g.
+/// `PYTHON_VERSION`, and are left out for images that haven't been pulled yet.
+pub async fn server_languages_impl() -> ServerLanguagesResponse {
+    let mut languages = Vec::new();
+    for (name, image, package_manager, version_env) in SUPPORTED_LANGUAGES {
+        languages.push(LanguageInfo {
+            name: name.to_string(),
+            image: image.to_string(),
+            version: image_env_value(image, version_env).await,
+            package_manager: package_manager.to_string(),
+        });
+    }
 
-    // Return success message
-    Ok(format!("Sandbox {} stopped successfully", params.sandbox))
+    ServerLanguagesResponse { languages }
 }
 
 /// Implementation for sandbox metrics
@@ -873,13 +2412,19 @@ pub async fn sandbox_get_metrics_impl(
                 Ok(statuses) => {
                     for status in statuses {
                         // Convert from orchestra::SandboxStatus to our SandboxStatus
+                        let max_concurrent_executions =
+                            state.get_execution_limit(&namespace, &status.name).await;
                         all_statuses.push(SandboxStatus {
                             namespace: namespace.clone(),
                             name: status.name,
                             running: status.running,
+                            paused: status.paused,
+                            crashed: status.crashed,
                             cpu_usage: status.cpu_usage,
                             memory_usage: status.memory_usage,
                             disk_usage: status.disk_usage,
+                            db_stale: status.db_stale,
+                            max_concurrent_executions,
                         });
                     }
                 }
@@ -914,13 +2459,20 @@ pub async fn sandbox_get_metrics_impl(
             Ok(statuses) => {
                 for status in statuses {
                     // Convert from orchestra::SandboxStatus to our SandboxStatus
+                    let max_concurrent_executions = state
+                        .get_execution_limit(&params.namespace, &status.name)
+                        .await;
                     all_statuses.push(SandboxStatus {
                         namespace: params.namespace.clone(),
                         name: status.name,
                         running: status.running,
+                        paused: status.paused,
+                        crashed: status.crashed,
                         cpu_usage: status.cpu_usage,
                         memory_usage: status.memory_usage,
                         disk_usage: status.disk_usage,
+                        db_stale: status.db_stale,
+                        max_concurrent_executions,
                     });
                 }
             }
@@ -987,6 +2539,504 @@ pub async fn proxy_fallback() -> ServerResult<impl IntoResponse> {
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
+/// Looks up a variable in the environment of a pulled image
+async fn image_env_value(image: &str, key: &str) -> Option<String> {
+    let reference = image.parse::<Reference>().ok()?;
+    let envs = match config::get_image_env(&reference).await {
+        Ok(envs) => envs,
+        Err(e) => {
+            warn!("failed to read environment of image {}: {}", image, e);
+            return None;
+        }
+    };
+
+    envs.into_iter().find_map(|env| {
+        let (name, value) = env.split_once('=')?;
+        (name == key).then(|| value.to_string())
+    })
+}
+
+/// Validates a sandbox and namespace and returns the namespace directory
+fn get_sandbox_namespace_dir(
+    state: &AppState,
+    namespace: &str,
+    sandbox: &str,
+) -> ServerResult<PathBuf> {
+    validate_sandbox_name(sandbox)?;
+    validate_namespace(namespace)?;
+
+    let namespace_dir = state.get_config().get_namespace_dir().join(namespace);
+
+    if !namespace_dir.join(MICROSANDBOX_CONFIG_FILENAME).exists() {
+        return Err(ServerError::ValidationError(
+            crate::error::ValidationError::InvalidInput(format!(
+                "Configuration file not found for namespace '{}'",
+                namespace
+            )),
+        ));
+    }
+
+    Ok(namespace_dir)
+}
+
+/// Implementation for checking a sandbox's health
+///
+/// Works whether or not the sandbox is running. A sandbox that isn't defined in its namespace
+/// is reported as [`ServerError::NotFound`].
+pub async fn sandbox_health_impl(
+    state: AppState,
+    params: SandboxHealthParams,
+) -> ServerResult<SandboxHealthResponse> {
+    validate_sandbox_name(&params.sandbox)?;
+    validate_namespace(&params.namespace)?;
+
+    let namespace_dir = state
+        .get_config()
+        .get_namespace_dir()
+        .join(&params.namespace);
+    if !namespace_dir.join(MICROSANDBOX_CONFIG_FILENAME).exists() {
+        return Err(ServerError::NotFound(format!(
+            "Sandbox {}/{} not found: namespace has no configuration",
+            params.namespace, params.sandbox
+        )));
+    }
+
+    let health = match orchestra::health(
+        &params.sandbox,
+        Some(&namespace_dir),
+        Some(MICROSANDBOX_CONFIG_FILENAME),
+    )
+    .await
+    {
+        Ok(health) => health,
+        Err(MicrosandboxError::SandboxNotFoundInConfig(_, _)) => {
+            return Err(ServerError::NotFound(format!(
+                "Sandbox {}/{} not found",
+                params.namespace, params.sandbox
+            )))
+        }
+        Err(e) => {
+            return Err(ServerError::InternalError(format!(
+                "Failed to check sandbox health: {}",
+                e
+            )))
+        }
+    };
+
+    Ok(SandboxHealthResponse {
+        status: health.status,
+        uptime_secs: health.uptime.map(|uptime| uptime.as_secs_f64()),
+        supervisor_pid: health.supervisor_pid,
+        microvm_pid: health.microvm_pid,
+        supervisor_alive: health.supervisor_alive,
+    })
+}
+
+/// Implementation for getting the resource use of a sandbox's MicroVM
+pub async fn sandbox_stats_impl(
+    state: AppState,
+    params: SandboxStatsParams,
+) -> ServerResult<SandboxStatsResponse> {
+    validate_sandbox_name(&params.sandbox)?;
+    validate_namespace(&params.namespace)?;
+
+    let namespace_dir = state
+        .get_config()
+        .get_namespace_dir()
+        .join(&params.namespace);
+    if !namespace_dir.join(MICROSANDBOX_CONFIG_FILENAME).exists() {
+        return Err(ServerError::NotFound(format!(
+            "Sandbox {}/{} not found: namespace has no configuration",
+            params.namespace, params.sandbox
+        )));
+    }
+
+    let stats = match orchestra::stats(
+        &params.sandbox,
+        Some(&namespace_dir),
+        Some(MICROSANDBOX_CONFIG_FILENAME),
+    )
+    .await
+    {
+        Ok(stats) => stats,
+        Err(MicrosandboxError::SandboxNotFoundInConfig(_, _)) => {
+            return Err(ServerError::NotFound(format!(
+                "Sandbox {}/{} not found",
+                params.namespace, params.sandbox
+            )))
+        }
+        Err(e) => {
+            return Err(ServerError::InternalError(format!(
+                "Failed to get sandbox stats: {}",
+                e
+            )))
+        }
+    };
+
+    Ok(SandboxStatsResponse {
+        live: stats.live,
+        peak_rss: stats.peak_rss_bytes,
+        cpu_seconds: stats.cpu_time.map(|cpu_time| cpu_time.as_secs_f64()),
+        oom_kills: stats.oom_kills,
+    })
+}
+
+/// Implementation for listing the namespaces on the server
+///
+/// A namespace is a directory in the server's namespaces directory, whether or not it
+/// defines any sandboxes yet. Every namespace is listed when `namespace` is missing or "*",
+/// which the auth middleware only lets through for tokens covering all namespaces. Otherwise
+/// only `namespace` itself is listed, if it exists, so a namespace-scoped token can't learn
+/// the names of the others.
+pub async fn server_namespaces_impl(
+    state: AppState,
+    namespace: Option<&str>,
+) -> ServerResult<ServerNamespacesResponse> {
+    let namespaces = match namespace {
+        None | Some("*") => list_namespaces(&state).await?,
+        Some(namespace) => {
+            validate_namespace(namespace)?;
+            let namespace_dir = state.get_config().get_namespace_dir().join(namespace);
+            if namespace_dir.is_dir() {
+                vec![namespace.to_string()]
+            } else {
+                Vec::new()
+            }
+        }
+    };
+
+    Ok(ServerNamespacesResponse { namespaces })
+}
+
+/// Lists every namespace directory in the server's namespaces directory, in order
+async fn list_namespaces(state: &AppState) -> ServerResult<Vec<String>> {
+    let namespaces_dir = state.get_config().get_namespace_dir();
+    if !namespaces_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = tokio::fs::read_dir(&namespaces_dir).await.map_err(|e| {
+        ServerError::InternalError(format!("Failed to read namespaces directory: {}", e))
+    })?;
+
+    let mut namespaces = Vec::new();
+    while let Some(entry) = entries.next_entry().await.map_err(|e| {
+        ServerError::InternalError(format!("Failed to read namespace directory entry: {}", e))
+    })? {
+        if !entry.path().is_dir() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            namespaces.push(name.to_string());
+        }
+    }
+    namespaces.sort();
+
+    Ok(namespaces)
+}
+
+/// Implementation for pulling an image ahead of the sandboxes that use it
+///
+/// Completes once the image and all its layers are stored, so that starting a sandbox with
+/// it doesn't pull. An image that is already pulled is left alone.
+pub async fn image_pull_impl(params: ImagePullParams) -> ServerResult<ImagePullResponse> {
+    let reference = params.reference.parse::<Reference>().map_err(|e| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(format!(
+            "Invalid image '{}': {}",
+            params.reference, e
+        )))
+    })?;
+
+    let already_present = image::is_pulled(&reference).await.map_err(|e| {
+        ServerError::InternalError(format!("Failed to look up image {}: {}", reference, e))
+    })?;
+    if !already_present {
+        image::pull(reference.clone(), true, None)
+            .await
+            .map_err(|e| {
+                ServerError::InternalError(format!("Failed to pull image {}: {}", reference, e))
+            })?;
+    }
+
+    Ok(ImagePullResponse {
+        reference: reference.to_string(),
+        already_present,
+    })
+}
+
+/// Implementation for listing the sandboxes defined on the server
+///
+/// Lists every sandbox defined in the namespace's configuration, or in every namespace's
+/// when the namespace is missing or "*", ordered by namespace then name. Namespaces without
+/// a configuration have no sandboxes.
+pub async fn sandbox_list_impl(
+    state: AppState,
+    params: SandboxListParams,
+) -> ServerResult<SandboxListResponse> {
+    let namespaces = match params.namespace.as_deref() {
+        None | Some("*") => list_namespaces(&state).await?,
+        Some(namespace) => {
+            validate_namespace(namespace)?;
+            vec![namespace.to_string()]
+        }
+    };
+
+    let namespaces_dir = state.get_config().get_namespace_dir();
+    let mut sandboxes = Vec::new();
+    for namespace in namespaces {
+        let namespace_dir = namespaces_dir.join(&namespace);
+        let config_file = namespace_dir.join(MICROSANDBOX_CONFIG_FILENAME);
+        if !config_file.exists() {
+            continue;
+        }
+
+        let healths = orchestra::list(Some(&namespace_dir), Some(MICROSANDBOX_CONFIG_FILENAME))
+            .await
+            .map_err(|e| {
+                ServerError::InternalError(format!(
+                    "Failed to list sandboxes in namespace {}: {}",
+                    namespace, e
+                ))
+            })?;
+
+        sandboxes.extend(healths.into_iter().map(|health| SandboxDescriptor {
+            name: health.name,
+            namespace: namespace.clone(),
+            status: health.status,
+            config_file: config_file.display().to_string(),
+            uptime_secs: health.uptime.map(|uptime| uptime.as_secs_f64()),
+        }));
+    }
+
+    Ok(SandboxListResponse { sandboxes })
+}
+
+/// Returns the position just past the first newline in `contents[start..end]`
+fn find_line_end(contents: &[u8], start: usize, end: usize) -> Option<usize> {
+    contents[start..end]
+        .iter()
+        .position(|b| *b == b'\n')
+        .map(|i| start + i + 1)
+}
+
+/// Returns the last `lines` lines of `output`, not counting a trailing newline as a line break
+fn tail_lines(output: &[u8], lines: usize) -> &[u8] {
+    if lines == 0 {
+        return &[];
+    }
+
+    let body = output.strip_suffix(b"\n").unwrap_or(output);
+    let start = body
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, b)| **b == b'\n')
+        .nth(lines - 1)
+        .map_or(0, |(i, _)| i + 1);
+    &output[start..]
+}
+
+/// Scans the writable overlay layer of a sandbox into a manifest
+async fn scan_sandbox_rw_layer(
+    state: &AppState,
+    namespace: &str,
+    sandbox: &str,
+) -> ServerResult<FsManifest> {
+    let namespace_dir = get_sandbox_namespace_dir(state, namespace, sandbox)?;
+    let rw_path = namespace_dir
+        .join(MICROSANDBOX_ENV_DIR)
+        .join(RW_SUBDIR)
+        .join(MICROSANDBOX_CONFIG_FILENAME)
+        .join(sandbox);
+
+    if !rw_path.exists() {
+        return Err(ServerError::ValidationError(
+            crate::error::ValidationError::InvalidInput(format!(
+                "Sandbox {}/{} has no writable layer; it may not have been started",
+                namespace, sandbox
+            )),
+        ));
+    }
+
+    FsManifest::scan(&rw_path).await.map_err(|e| {
+        ServerError::InternalError(format!(
+            "Failed to scan filesystem of sandbox {}: {}",
+            sandbox, e
+        ))
+    })
+}
+
+/// Maps an error from taking or restoring a snapshot to the server error reported for it
+fn snapshot_error(namespace: &str, sandbox: &str, error: MicrosandboxError) -> ServerError {
+    let invalid = |message: String| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(message))
+    };
+
+    match error {
+        MicrosandboxError::SandboxNotFoundInConfig(_, _) => {
+            ServerError::NotFound(format!("Sandbox {}/{} not found", namespace, sandbox))
+        }
+        MicrosandboxError::SnapshotNotFound(snapshot, _) => ServerError::NotFound(format!(
+            "Snapshot '{}' of sandbox {}/{} not found",
+            snapshot, namespace, sandbox
+        )),
+        MicrosandboxError::SandboxRunning(_) => invalid(format!(
+            "Sandbox {}/{} is running; stop it before restoring a snapshot",
+            namespace, sandbox
+        )),
+        e @ (MicrosandboxError::SnapshotExists(_, _)
+        | MicrosandboxError::InvalidArgument(_)
+        | MicrosandboxError::PathNotFound(_)) => invalid(e.to_string()),
+        e => ServerError::InternalError(format!("Failed to snapshot sandbox {}: {}", sandbox, e)),
+    }
+}
+
+/// Validates a ulimit and converts it to the `RESOURCE=SOFT:HARD` form used in the config
+fn validate_ulimit(ulimit: &SandboxUlimit) -> ServerResult<String> {
+    let name = ulimit.name.to_uppercase();
+    let name = if name.starts_with("RLIMIT_") {
+        name
+    } else {
+        format!("RLIMIT_{}", name)
+    };
+
+    let resource: LinuxRLimitResource = name.parse().map_err(|_| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(format!(
+            "Unsupported ulimit '{}'",
+            ulimit.name
+        )))
+    })?;
+
+    if ulimit.soft > ulimit.hard {
+        return Err(ServerError::ValidationError(
+            crate::error::ValidationError::InvalidInput(format!(
+                "Soft limit for ulimit '{}' cannot exceed its hard limit",
+                ulimit.name
+            )),
+        ));
+    }
+
+    Ok(format!("{}={}:{}", resource, ulimit.soft, ulimit.hard))
+}
+
+/// Validates a hostname against RFC 1123
+fn validate_hostname(hostname: &str) -> ServerResult<()> {
+    let invalid = |reason: &str| {
+        ServerError::ValidationError(crate::error::ValidationError::InvalidInput(format!(
+            "Invalid hostname '{}': {}",
+            hostname, reason
+        )))
+    };
+
+    if hostname.is_empty() || hostname.len() > 253 {
+        return Err(invalid("must be between 1 and 253 characters"));
+    }
+
+    for label in hostname.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid("each label must be between 1 and 63 characters"));
+        }
+
+        if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(invalid(
+                "labels can only contain alphanumeric characters or hyphens",
+            ));
+        }
+
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(invalid("labels cannot start or end with a hyphen"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a sandbox's image is a local rootfs that exists, or a valid image reference
+async fn check_image(image: &str) -> ConfigDiagnostic {
+    let image = match image.parse::<ReferenceOrPath>() {
+        Ok(image) => image,
+        Err(e) => {
+            return ConfigDiagnostic::fail(CHECK_IMAGE, format!("Invalid image '{}': {}", image, e))
+        }
+    };
+
+    match &image {
+        ReferenceOrPath::Path(path) if path.is_dir() => {
+            ConfigDiagnostic::pass(CHECK_IMAGE, format!("Rootfs found at {}", path.display()))
+        }
+        ReferenceOrPath::Path(path) => ConfigDiagnostic::fail(
+            CHECK_IMAGE,
+            format!("Rootfs directory {} does not exist", path.display()),
+        ),
+        ReferenceOrPath::Reference(reference) => match image::is_pulled(reference).await {
+            Ok(true) => {
+                ConfigDiagnostic::pass(CHECK_IMAGE, format!("Image {} found locally", reference))
+            }
+            Ok(false) => ConfigDiagnostic::pass(
+                CHECK_IMAGE,
+                format!(
+                    "Image {} is not pulled yet, it will be pulled when the sandbox starts",
+                    reference
+                ),
+            ),
+            Err(e) => ConfigDiagnostic::fail(
+                CHECK_IMAGE,
+                format!("Failed to look up image {}: {}", reference, e),
+            ),
+        },
+    }
+}
+
+/// Checks that a sandbox's memory and CPUs fit the host
+fn check_resources(memory: Option<u32>, cpus: Option<u8>) -> ConfigDiagnostic {
+    let memory = memory.unwrap_or(DEFAULT_MEMORY_MIB);
+    let cpus = cpus.unwrap_or(DEFAULT_NUM_VCPUS);
+
+    if memory == 0 || cpus == 0 {
+        return ConfigDiagnostic::fail(CHECK_RESOURCES, "Memory and CPUs must be greater than 0");
+    }
+
+    if let Ok(host_cpus) = std::thread::available_parallelism() {
+        if cpus as usize > host_cpus.get() {
+            return ConfigDiagnostic::fail(
+                CHECK_RESOURCES,
+                format!("{} CPUs requested, but the host has {}", cpus, host_cpus),
+            );
+        }
+    }
+
+    if let Some(host_memory) = host_memory_mib() {
+        if memory as u64 > host_memory {
+            return ConfigDiagnostic::fail(
+                CHECK_RESOURCES,
+                format!(
+                    "{} MiB of memory requested, but the host has {} MiB",
+                    memory, host_memory
+                ),
+            );
+        }
+    }
+
+    ConfigDiagnostic::pass(
+        CHECK_RESOURCES,
+        format!("{} MiB of memory and {} CPUs fit the host", memory, cpus),
+    )
+}
+
+/// Returns the host's total memory in MiB, if it can be read
+fn host_memory_mib() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let kib = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib / 1024)
+}
+
 /// Validates a sandbox name
 fn validate_sandbox_name(name: &str) -> ServerResult<()> {
     // Check name length
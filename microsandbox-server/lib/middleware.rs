@@ -14,7 +14,7 @@
 use axum::{
     body::{to_bytes, Body},
     extract::State,
-    http::{HeaderMap, Request, StatusCode, Uri},
+    http::{HeaderMap, Method, Request, StatusCode, Uri},
     middleware::Next,
     response::IntoResponse,
 };
@@ -110,6 +110,16 @@ pub async fn auth_middleware(
         return Ok(next.run(req).await);
     }
 
+    // Requests without a JSON-RPC body, like metrics scrapes, cover every namespace
+    if req.method() == Method::GET {
+        return Err(ServerError::AuthorizationError(
+            crate::error::AuthorizationError::AccessDenied(format!(
+                "Token only has access to namespace '{}', this endpoint needs access to all namespaces",
+                claims.namespace
+            )),
+        ));
+    }
+
     // For namespace-specific tokens, we need to ensure the token has access to the requested namespace
     // We need to read the request body to extract the namespace
     let (parts, body) = req.into_parts();
@@ -119,15 +129,18 @@ pub async fn auth_middleware(
         .await
         .map_err(|e| ServerError::InternalError(format!("Failed to read request body: {}", e)))?;
 
-    // Parse the JSON-RPC request and extract the namespace
-    let namespace_from_request = extract_namespace_from_json_rpc(&bytes)?;
+    // Parse the JSON-RPC request, or each request of a batch, and extract the namespaces
+    let namespaces_from_request = extract_namespaces_from_json_rpc(&bytes)?;
 
-    // Validate that the token has access to the requested namespace
-    if claims.namespace != namespace_from_request {
+    // Validate that the token has access to every requested namespace
+    if let Some(namespace) = namespaces_from_request
+        .iter()
+        .find(|namespace| **namespace != claims.namespace)
+    {
         return Err(ServerError::AuthorizationError(
             crate::error::AuthorizationError::AccessDenied(format!(
                 "Token does not have access to namespace '{}'",
-                namespace_from_request
+                namespace
             )),
         ));
     }
@@ -140,6 +153,43 @@ pub async fn auth_middleware(
     Ok(next.run(req).await)
 }
 
+/// Authenticates a duplex JSON-RPC request by its headers and the request on its first line
+///
+/// The rest of a duplex body streams in while the call runs, so unlike [`auth_middleware`],
+/// which buffers the whole body to find the namespaces it asks for, this only looks at the
+/// JSON-RPC request that starts it.
+pub fn authorize_request_line(
+    state: &AppState,
+    headers: &HeaderMap,
+    request_line: &[u8],
+) -> Result<(), ServerError> {
+    // Skip auth in dev mode if configured
+    if *state.get_config().get_dev_mode() {
+        return Ok(());
+    }
+
+    let api_key = extract_api_key_from_headers(headers)?;
+    let claims = validate_token(&api_key, state)?;
+    if claims.namespace == "*" {
+        return Ok(());
+    }
+
+    // Validate that the token has access to the requested namespace
+    if let Some(namespace) = extract_namespaces_from_json_rpc(request_line)?
+        .iter()
+        .find(|namespace| **namespace != claims.namespace)
+    {
+        return Err(ServerError::AuthorizationError(
+            crate::error::AuthorizationError::AccessDenied(format!(
+                "Token does not have access to namespace '{}'",
+                namespace
+            )),
+        ));
+    }
+
+    Ok(())
+}
+
 /// Smart authentication middleware for MCP requests that handles protocol vs tool methods differently
 /// Protocol methods (initialize, tools/list, prompts/list, prompts/get) don't require namespace validation
 /// Tool methods (tools/call) require namespace validation
@@ -191,7 +241,7 @@ pub async fn mcp_smart_auth_middleware(
 
     if requires_namespace_validation {
         // Extract namespace from params for tool execution methods
-        let namespace_from_request = extract_namespace_from_json_rpc(&bytes)?;
+        let namespace_from_request = extract_namespace(&json_value)?;
 
         // Validate that the token has access to the requested namespace
         if claims.namespace != namespace_from_request {
@@ -216,8 +266,8 @@ pub async fn mcp_smart_auth_middleware(
 // Helper Functions
 //--------------------------------------------------------------------------------------------------
 
-/// Extract the namespace from a JSON-RPC request body
-fn extract_namespace_from_json_rpc(bytes: &[u8]) -> Result<String, ServerError> {
+/// Extract the namespaces from a JSON-RPC request body, one per request of a batch
+fn extract_namespaces_from_json_rpc(bytes: &[u8]) -> Result<Vec<String>, ServerError> {
     // Parse the request body as JSON
     let json_value: Value = serde_json::from_slice(bytes).map_err(|e| {
         ServerError::ValidationError(ValidationError::InvalidInput(format!(
@@ -226,6 +276,14 @@ fn extract_namespace_from_json_rpc(bytes: &[u8]) -> Result<String, ServerError>
         )))
     })?;
 
+    match &json_value {
+        Value::Array(requests) => requests.iter().map(extract_namespace).collect(),
+        request => Ok(vec![extract_namespace(request)?]),
+    }
+}
+
+/// Extract the namespace from the params of a single JSON-RPC request
+fn extract_namespace(json_value: &Value) -> Result<String, ServerError> {
     // Extract the method for logging purposes
     let method = json_value
         .get("method")
@@ -12,6 +12,7 @@
 //! - Success message formatting for sandbox operations
 //! - Detailed error information handling
 
+use microsandbox_core::management::fsdiff::FsChange;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -22,6 +23,25 @@ use serde_json::Value;
 /// JSON-RPC version - always "2.0"
 pub const JSONRPC_VERSION: &str = "2.0";
 
+/// Warning code for a sandbox that was started but not seen running before the server gave up
+/// waiting for it
+pub const WARNING_START_TIMEOUT: &str = "start_timeout";
+
+/// Warning code for a sandbox that was started but couldn't be checked for running
+pub const WARNING_START_UNVERIFIED: &str = "start_unverified";
+
+/// Validation check of the sandbox and namespace names
+pub const CHECK_NAME: &str = "name";
+
+/// Validation check that the sandbox configuration parses and is complete
+pub const CHECK_CONFIG: &str = "config";
+
+/// Validation check that the sandbox image can be found
+pub const CHECK_IMAGE: &str = "image";
+
+/// Validation check that the sandbox's memory and CPUs fit the host
+pub const CHECK_RESOURCES: &str = "resources";
+
 //--------------------------------------------------------------------------------------------------
 // Types: JSON-RPC Payloads
 //--------------------------------------------------------------------------------------------------
@@ -127,6 +147,30 @@ pub struct SandboxStartParams {
 
     /// Optional sandbox configuration
     pub config: Option<SandboxConfig>,
+
+    /// Optional idempotency key - a repeated start with the key of the sandbox's last
+    /// successful start returns the running sandbox
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+
+    /// Optional time limits for each phase of the start
+    #[serde(default)]
+    pub timeouts: SandboxStartTimeouts,
+}
+
+/// Time limits for the phases of a sandbox start, in seconds
+///
+/// A phase without a limit keeps the server's default behavior.
+#[derive(Debug, Default, Deserialize)]
+pub struct SandboxStartTimeouts {
+    /// Time to pull the sandbox's image
+    pub pull: Option<f64>,
+
+    /// Time for the sandbox's microVM to be running
+    pub boot: Option<f64>,
+
+    /// Time for the portal in the sandbox to accept connections
+    pub ready: Option<f64>,
 }
 
 /// Request payload for stopping a sandbox
@@ -139,6 +183,128 @@ pub struct SandboxStopParams {
     pub namespace: String,
 }
 
+/// Request payload for pausing or resuming a sandbox
+#[derive(Debug, Deserialize)]
+pub struct SandboxPauseParams {
+    /// Sandbox name
+    pub sandbox: String,
+
+    /// Optional namespace
+    pub namespace: String,
+}
+
+/// Request payload for cloning a sandbox into a new one
+#[derive(Debug, Deserialize)]
+pub struct SandboxCloneParams {
+    /// Name of the sandbox to clone
+    pub sandbox: String,
+
+    /// Namespace of both sandboxes
+    pub namespace: String,
+
+    /// Name of the new sandbox
+    pub new_name: String,
+}
+
+/// Request payload for recording a filesystem snapshot marker
+#[derive(Debug, Deserialize)]
+pub struct SandboxFsSnapshotParams {
+    /// Sandbox name
+    pub sandbox: String,
+
+    /// Namespace
+    pub namespace: String,
+}
+
+/// Request payload for diffing a sandbox's filesystem against a snapshot marker
+#[derive(Debug, Deserialize)]
+pub struct SandboxFsDiffParams {
+    /// Sandbox name
+    pub sandbox: String,
+
+    /// Namespace
+    pub namespace: String,
+
+    /// ID of the snapshot marker to diff against
+    pub since: String,
+}
+
+/// Request payload for taking a snapshot of a sandbox's writable layer
+#[derive(Debug, Deserialize)]
+pub struct SandboxSnapshotParams {
+    /// Sandbox name
+    pub sandbox: String,
+
+    /// Namespace
+    pub namespace: String,
+
+    /// Name of the snapshot, unique among the sandbox's snapshots
+    pub name: String,
+}
+
+/// Request payload for restoring a sandbox's writable layer from a snapshot
+#[derive(Debug, Deserialize)]
+pub struct SandboxRestoreParams {
+    /// Sandbox name
+    pub sandbox: String,
+
+    /// Namespace
+    pub namespace: String,
+
+    /// Name of the snapshot to restore
+    pub snapshot: String,
+}
+
+/// Request payload for reading a sandbox's captured output
+#[derive(Debug, Deserialize)]
+pub struct SandboxLogsParams {
+    /// Sandbox name
+    pub sandbox: String,
+
+    /// Namespace
+    pub namespace: String,
+
+    /// Only return the last this many lines
+    #[serde(default)]
+    pub tail: Option<usize>,
+
+    /// Offset in the log stream to start reading at, e.g. the `next_offset` of an earlier
+    /// response
+    #[serde(default)]
+    pub offset: Option<u64>,
+
+    /// Only return output written to this stream, `stdout` or `stderr`; `both` by default
+    #[serde(default)]
+    pub stream: Option<String>,
+}
+
+/// Request payload for checking a sandbox's health
+#[derive(Debug, Deserialize)]
+pub struct SandboxHealthParams {
+    /// Sandbox name
+    pub sandbox: String,
+
+    /// Namespace
+    pub namespace: String,
+}
+
+/// Request payload for getting a sandbox's resource use
+#[derive(Debug, Deserialize)]
+pub struct SandboxStatsParams {
+    /// Sandbox name
+    pub sandbox: String,
+
+    /// Namespace
+    pub namespace: String,
+}
+
+/// Request payload for listing sandboxes
+#[derive(Debug, Deserialize)]
+pub struct SandboxListParams {
+    /// Namespace to list - all namespaces if not provided or "*"
+    pub namespace: Option<String>,
+}
+
 /// Request payload for getting sandbox metrics
 #[derive(Debug, Deserialize)]
 pub struct SandboxMetricsGetParams {
@@ -159,7 +325,7 @@ pub struct SandboxConfig {
     /// The amount of memory in MiB to use
     pub memory: Option<u32>,
 
-    /// The number of vCPUs to use
+    /// The number of vCPUs to use, which can only be whole vCPUs
     pub cpus: Option<u8>,
 
     /// The volumes to mount
@@ -190,11 +356,47 @@ pub struct SandboxConfig {
 
     /// The exec command to run
     pub exec: Option<String>,
+
+    /// The resource limits to apply in the guest
+    #[serde(default)]
+    pub ulimits: Vec<SandboxUlimit>,
+
+    /// The hostname of the guest
+    pub hostname: Option<String>,
+
+    /// The OOM score adjustment of the sandbox process (-1000 to 1000)
+    pub oom_score_adj: Option<i32>,
+
+    /// Capabilities to drop and seccomp profile to apply in the guest
+    pub security: Option<Value>,
+
+    /// The maximum number of code and command executions the sandbox runs at once
+    pub max_concurrent_executions: Option<usize>,
     // SECURITY: Needs networking namespacing to be implemented
     // /// The network scope for the sandbox
     // pub scope: Option<String>,
 }
 
+/// A resource limit to apply in the guest
+#[derive(Debug, Deserialize)]
+pub struct SandboxUlimit {
+    /// The limit name, e.g. `nofile` or `RLIMIT_NOFILE`
+    pub name: String,
+
+    /// The soft limit
+    pub soft: u64,
+
+    /// The hard limit
+    pub hard: u64,
+}
+
+/// Request payload for pulling an image
+#[derive(Debug, Deserialize)]
+pub struct ImagePullParams {
+    /// Reference of the image to pull, e.g. `microsandbox/python`
+    pub reference: String,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Types: Portal-mirrored RPC Payloads
 //--------------------------------------------------------------------------------------------------
@@ -207,6 +409,13 @@ pub struct SandboxReplRunParams {
 
     /// Programming language to use for execution
     pub language: String,
+
+    /// Environment variables set while the code runs
+    #[serde(default)]
+    pub env: std::collections::HashMap<String, String>,
+
+    /// Working directory the code runs in, instead of the REPL's own
+    pub cwd: Option<String>,
 }
 
 /// Request parameters for retrieving output from a previous REPL execution
@@ -299,6 +508,61 @@ impl JsonRpcResponseOrNotification {
     }
 }
 
+impl SandboxStartResponse {
+    /// A response for a start that went without problems
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            warnings: Vec::new(),
+            paused: false,
+        }
+    }
+
+    /// A response for a start that went through with a problem that isn't fatal
+    pub fn with_warning(message: impl Into<String>, code: &str) -> Self {
+        let message = message.into();
+        Self {
+            warnings: vec![StartWarning {
+                code: code.to_string(),
+                message: message.clone(),
+                fatal: false,
+            }],
+            message,
+            paused: false,
+        }
+    }
+}
+
+impl SandboxValidateResponse {
+    /// A response for the given check outcomes
+    pub fn new(diagnostics: Vec<ConfigDiagnostic>) -> Self {
+        Self {
+            valid: diagnostics.iter().all(|d| d.ok),
+            diagnostics,
+        }
+    }
+}
+
+impl ConfigDiagnostic {
+    /// A passed check
+    pub fn pass(check: &str, message: impl Into<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            ok: true,
+            message: message.into(),
+        }
+    }
+
+    /// A failed check
+    pub fn fail(check: &str, message: impl Into<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            ok: false,
+            message: message.into(),
+        }
+    }
+}
+
 impl JsonRpcResponse {
     /// Create a new successful JSON-RPC response
     pub fn success(result: Value, id: Option<Value>) -> Self {
@@ -332,6 +596,56 @@ pub struct RegularMessageResponse {
     pub message: String,
 }
 
+/// Sandbox start response
+#[derive(Debug, Serialize)]
+pub struct SandboxStartResponse {
+    /// Message describing how the start went
+    pub message: String,
+
+    /// Problems that didn't stop the sandbox from starting, but that the caller may want to
+    /// react to
+    pub warnings: Vec<StartWarning>,
+
+    /// Whether the sandbox is paused, which a sandbox found already running may be
+    pub paused: bool,
+}
+
+/// A warning about a sandbox start
+#[derive(Debug, Serialize)]
+pub struct StartWarning {
+    /// Machine-readable warning code, one of the `WARNING_START_*` constants
+    pub code: String,
+
+    /// Human-readable warning message
+    pub message: String,
+
+    /// Whether the sandbox is unusable because of the problem
+    pub fatal: bool,
+}
+
+/// Sandbox configuration validation response
+#[derive(Debug, Serialize)]
+pub struct SandboxValidateResponse {
+    /// Whether the sandbox would pass every check at start
+    pub valid: bool,
+
+    /// Outcome of each check, in the order they ran
+    pub diagnostics: Vec<ConfigDiagnostic>,
+}
+
+/// Outcome of one sandbox configuration check
+#[derive(Debug, Serialize)]
+pub struct ConfigDiagnostic {
+    /// Check that ran, one of the `CHECK_*` constants
+    pub check: String,
+
+    /// Whether the check passed
+    pub ok: bool,
+
+    /// Human-readable description of the outcome
+    pub message: String,
+}
+
 /// System status response
 #[derive(Debug, Serialize)]
 pub struct SystemStatusResponse {}
@@ -343,6 +657,161 @@ pub struct SandboxStatusResponse {
     pub sandboxes: Vec<SandboxStatus>,
 }
 
+/// A language the server can run code in
+#[derive(Debug, Serialize)]
+pub struct LanguageInfo {
+    /// Language name, as passed to `sandbox.repl.run`
+    pub name: String,
+
+    /// Image providing the language's REPL
+    pub image: String,
+
+    /// Version of the language in the image, if the image has been pulled
+    pub version: Option<String>,
+
+    /// Package manager available in the image
+    pub package_manager: String,
+}
+
+/// Supported languages response
+#[derive(Debug, Serialize)]
+pub struct ServerLanguagesResponse {
+    /// Languages the server supports
+    pub languages: Vec<LanguageInfo>,
+}
+
+/// Server version and capabilities response
+#[derive(Debug, Serialize)]
+pub struct ServerInfoResponse {
+    /// Version of the server
+    pub version: String,
+
+    /// JSON-RPC methods the server handles
+    pub methods: Vec<String>,
+
+    /// Optional sandbox configuration the server can apply
+    pub capabilities: Vec<String>,
+}
+
+/// Filesystem snapshot marker response
+#[derive(Debug, Serialize)]
+pub struct SandboxFsSnapshotResponse {
+    /// ID of the recorded snapshot marker
+    pub snapshot_id: String,
+}
+
+/// Sandbox snapshot response
+#[derive(Debug, Serialize)]
+pub struct SandboxSnapshotResponse {
+    /// Name of the snapshot
+    pub name: String,
+
+    /// Total size of the files in the snapshot
+    pub size_bytes: u64,
+
+    /// When the snapshot was taken, in RFC 3339 format
+    pub created_at: String,
+}
+
+/// Filesystem diff response
+#[derive(Debug, Serialize)]
+pub struct SandboxFsDiffResponse {
+    /// Paths changed since the snapshot marker, ordered by path
+    pub changes: Vec<FsChange>,
+}
+
+/// Captured output response
+#[derive(Debug, Serialize)]
+pub struct SandboxLogsResponse {
+    /// Base64-encoded output
+    pub content: String,
+
+    /// Offset in the log stream just past the returned output, to continue reading from
+    pub next_offset: u64,
+
+    /// Whether output in the requested range is missing because it was rotated out of the log
+    /// or didn't fit in the response
+    pub truncated: bool,
+}
+
+/// Sandbox health response
+#[derive(Debug, Serialize)]
+pub struct SandboxHealthResponse {
+    /// Recorded status of the sandbox, or `None` if it has never been started
+    pub status: Option<String>,
+
+    /// Seconds the MicroVM has been running, if it still is
+    pub uptime_secs: Option<f64>,
+
+    /// PID of the sandbox's last supervisor process
+    pub supervisor_pid: Option<u32>,
+
+    /// PID of the sandbox's last MicroVM process
+    pub microvm_pid: Option<u32>,
+
+    /// Whether the supervisor process is alive
+    pub supervisor_alive: bool,
+}
+
+/// Sandbox resource use response
+#[derive(Debug, Serialize)]
+pub struct SandboxStatsResponse {
+    /// Whether the figures are of a MicroVM that is still running, rather than of its last run
+    pub live: bool,
+
+    /// Peak resident set size of the MicroVM in its current or last run, in bytes
+    pub peak_rss: Option<u64>,
+
+    /// CPU time the MicroVM used in its current or last run, in seconds
+    pub cpu_seconds: Option<f64>,
+
+    /// How many times the OOM killer has killed the sandbox's MicroVM, over all its runs
+    pub oom_kills: u32,
+}
+
+/// A sandbox defined on the server, as listed by `sandbox.list`
+#[derive(Debug, Serialize)]
+pub struct SandboxDescriptor {
+    /// Sandbox name
+    pub name: String,
+
+    /// Namespace of the sandbox
+    pub namespace: String,
+
+    /// Recorded status of the sandbox, or `None` if it has never been started
+    pub status: Option<String>,
+
+    /// Path of the configuration file that defines the sandbox
+    pub config_file: String,
+
+    /// Seconds the MicroVM has been running, if it still is
+    pub uptime_secs: Option<f64>,
+}
+
+/// Sandbox list response
+#[derive(Debug, Serialize)]
+pub struct SandboxListResponse {
+    /// The sandboxes, ordered by namespace then name
+    pub sandboxes: Vec<SandboxDescriptor>,
+}
+
+/// Namespace list response
+#[derive(Debug, Serialize)]
+pub struct ServerNamespacesResponse {
+    /// Names of the namespaces, in order
+    pub namespaces: Vec<String>,
+}
+
+/// Image pull response
+#[derive(Debug, Serialize)]
+pub struct ImagePullResponse {
+    /// Full reference of the image, with its registry and tag
+    pub reference: String,
+
+    /// Whether the image was already pulled, so nothing was downloaded
+    pub already_present: bool,
+}
+
 /// Sandbox configuration response
 #[derive(Debug, Serialize)]
 pub struct SandboxConfigResponse {}
@@ -359,6 +828,12 @@ pub struct SandboxStatus {
     /// Whether the sandbox is running
     pub running: bool,
 
+    /// Whether the running sandbox is paused
+    pub paused: bool,
+
+    /// Whether the sandbox's MicroVM last exited abnormally without being stopped
+    pub crashed: bool,
+
     /// CPU usage percentage
     pub cpu_usage: Option<f32>,
 
@@ -367,6 +842,12 @@ pub struct SandboxStatus {
 
     /// Disk usage of the RW layer in bytes
     pub disk_usage: Option<u64>,
+
+    /// Whether the sandbox's database state may be out of date
+    pub db_stale: bool,
+
+    /// The maximum number of executions the sandbox runs at once, if limited
+    pub max_concurrent_executions: Option<usize>,
 }
 
 //--------------------------------------------------------------------------------------------------
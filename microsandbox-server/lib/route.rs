@@ -27,6 +27,16 @@ pub fn create_router(state: AppState) -> Router {
     // Create REST API routes - only health endpoint remains here
     let rest_api = Router::new().route("/health", get(handler::health));
 
+    // Create the Prometheus metrics route. It covers every namespace, so it needs a token with
+    // access to all of them
+    let metrics_api =
+        Router::new()
+            .route("/", get(handler::metrics))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                app_middleware::auth_middleware,
+            ));
+
     // Create JSON-RPC routes with authentication - a single endpoint that handles all RPC methods
     // This now mirrors the structure used in microsandbox-portal
     let rpc_api = Router::new()
@@ -36,6 +46,11 @@ pub fn create_router(state: AppState) -> Router {
             app_middleware::auth_middleware,
         ));
 
+    // Create the duplex JSON-RPC route, whose body keeps streaming in while the call runs. The
+    // auth middleware would wait for the whole body, so the handler authenticates the request
+    // line itself
+    let duplex_api = Router::new().route("/", post(handler::json_rpc_duplex_handler));
+
     // Create MCP routes - separate endpoint for Model Context Protocol
     // Uses smart auth middleware that handles protocol vs tool methods differently
     let mcp_api =
@@ -50,6 +65,8 @@ pub fn create_router(state: AppState) -> Router {
     Router::new()
         .nest("/api/v1", rest_api)
         .nest("/api/v1/rpc", rpc_api)
+        .nest("/api/v1/rpc/duplex", duplex_api)
+        .nest("/api/v1/metrics", metrics_api)
         .nest("/mcp", mcp_api)
         .layer(middleware::from_fn(app_middleware::logging_middleware))
         .with_state(state)
@@ -10,10 +10,14 @@
 //! - State initialization and access methods
 //! - Configuration state management
 
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+use tokio::sync::{Mutex, OwnedMutexGuard, OwnedSemaphorePermit, RwLock, Semaphore};
 
 use getset::Getters;
+use microsandbox_core::management::fsdiff::FsManifest;
 
 use crate::{
     config::Config,
@@ -21,6 +25,13 @@ use crate::{
     ServerError, ServerResult,
 };
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum number of filesystem snapshot markers kept; the oldest are dropped first
+const MAX_FS_SNAPSHOTS: usize = 1024;
+
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
@@ -34,6 +45,42 @@ pub struct AppState {
 
     /// The port manager for handling sandbox port assignments
     port_manager: Arc<RwLock<PortManager>>,
+
+    /// Filesystem snapshot markers, kept in memory until the server restarts
+    #[getset(skip)]
+    fs_snapshots: Arc<RwLock<FsSnapshots>>,
+
+    /// Limits on concurrent executions, by `<namespace>/<sandbox>`
+    #[getset(skip)]
+    execution_limits: Arc<RwLock<HashMap<String, ExecutionLimit>>>,
+
+    /// Locks held while a sandbox starts, by `<namespace>/<sandbox>`, each guarding the
+    /// idempotency key of the sandbox's last successful start
+    #[getset(skip)]
+    start_locks: Arc<Mutex<HashMap<String, StartLock>>>,
+}
+
+/// Lock held while a sandbox starts, guarding the idempotency key of its last successful start
+type StartLock = Arc<Mutex<Option<String>>>;
+
+/// A limit on how many executions a sandbox runs at once
+struct ExecutionLimit {
+    max: usize,
+    permits: Arc<Semaphore>,
+}
+
+/// Filesystem snapshot markers by ID, with their insertion order for eviction
+#[derive(Default)]
+struct FsSnapshots {
+    by_id: HashMap<String, FsSnapshot>,
+    order: VecDeque<String>,
+}
+
+/// A recorded manifest of a sandbox's writable layer
+struct FsSnapshot {
+    namespace: String,
+    sandbox: String,
+    manifest: FsManifest,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -46,6 +93,159 @@ impl AppState {
         Self {
             config,
             port_manager,
+            fs_snapshots: Arc::new(RwLock::new(FsSnapshots::default())),
+            execution_limits: Arc::new(RwLock::new(HashMap::new())),
+            start_locks: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Lock a sandbox for starting, waiting for a start of it already in progress to finish
+    ///
+    /// The guard holds the idempotency key of the sandbox's last successful start, if it had
+    /// one. A start with a key compares it to the guarded key to tell a retry of an earlier
+    /// start, which should get the sandbox back, from a new start, and stores its own key
+    /// once it succeeds. Keys are kept in memory, until the sandbox is stopped or the server
+    /// restarts.
+    pub async fn lock_start(
+        &self,
+        namespace: &str,
+        sandbox_name: &str,
+    ) -> OwnedMutexGuard<Option<String>> {
+        let key = format!("{}/{}", namespace, sandbox_name);
+        let lock = self
+            .start_locks
+            .lock()
+            .await
+            .entry(key)
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+
+    /// Forget a stopped sandbox's start lock, with the idempotency key it guards
+    ///
+    /// A lock that a start holds or waits for is kept, so starts of the sandbox still run one at
+    /// a time.
+    pub async fn remove_start_lock(&self, namespace: &str, sandbox_name: &str) {
+        let key = format!("{}/{}", namespace, sandbox_name);
+        let mut locks = self.start_locks.lock().await;
+        if locks
+            .get(&key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&key);
+        }
+    }
+
+    /// Store a filesystem snapshot marker for a sandbox and return its ID
+    pub async fn save_fs_snapshot(
+        &self,
+        namespace: &str,
+        sandbox_name: &str,
+        manifest: FsManifest,
+    ) -> String {
+        let id = format!("{:032x}", rand::random::<u128>());
+        let mut snapshots = self.fs_snapshots.write().await;
+
+        if snapshots.order.len() >= MAX_FS_SNAPSHOTS {
+            if let Some(oldest) = snapshots.order.pop_front() {
+                snapshots.by_id.remove(&oldest);
+            }
+        }
+
+        snapshots.order.push_back(id.clone());
+        snapshots.by_id.insert(
+            id.clone(),
+            FsSnapshot {
+                namespace: namespace.to_string(),
+                sandbox: sandbox_name.to_string(),
+                manifest,
+            },
+        );
+
+        id
+    }
+
+    /// Get a sandbox's filesystem snapshot marker by ID
+    ///
+    /// Returns an error if the marker doesn't exist or belongs to another sandbox
+    pub async fn get_fs_snapshot(
+        &self,
+        id: &str,
+        namespace: &str,
+        sandbox_name: &str,
+    ) -> ServerResult<FsManifest> {
+        let snapshots = self.fs_snapshots.read().await;
+        match snapshots.by_id.get(id) {
+            Some(snapshot)
+                if snapshot.namespace == namespace && snapshot.sandbox == sandbox_name =>
+            {
+                Ok(snapshot.manifest.clone())
+            }
+            _ => Err(ServerError::ValidationError(
+                crate::error::ValidationError::InvalidInput(format!(
+                    "Unknown snapshot '{}' for sandbox {}/{}",
+                    id, namespace, sandbox_name
+                )),
+            )),
+        }
+    }
+
+    /// Set how many executions a sandbox runs at once, or remove its limit with `None`
+    ///
+    /// Executions already running keep their slots under the old limit.
+    pub async fn set_execution_limit(
+        &self,
+        namespace: &str,
+        sandbox_name: &str,
+        max: Option<usize>,
+    ) {
+        let key = format!("{}/{}", namespace, sandbox_name);
+        let mut limits = self.execution_limits.write().await;
+        match max {
+            Some(max) => {
+                limits.insert(
+                    key,
+                    ExecutionLimit {
+                        max,
+                        permits: Arc::new(Semaphore::new(max)),
+                    },
+                );
+            }
+            None => {
+                limits.remove(&key);
+            }
+        }
+    }
+
+    /// Get how many executions a sandbox runs at once, if it is limited
+    pub async fn get_execution_limit(&self, namespace: &str, sandbox_name: &str) -> Option<usize> {
+        let key = format!("{}/{}", namespace, sandbox_name);
+        self.execution_limits
+            .read()
+            .await
+            .get(&key)
+            .map(|limit| limit.max)
+    }
+
+    /// Take one of a sandbox's execution slots, held until the returned permit is dropped
+    ///
+    /// Returns `Ok(None)` if the sandbox has no limit, and the limit as the error if every slot
+    /// is taken.
+    pub async fn try_acquire_execution(
+        &self,
+        namespace: &str,
+        sandbox_name: &str,
+    ) -> Result<Option<OwnedSemaphorePermit>, usize> {
+        let key = format!("{}/{}", namespace, sandbox_name);
+        match self.execution_limits.read().await.get(&key) {
+            Some(limit) => limit
+                .permits
+                .clone()
+                .try_acquire_owned()
+                .map(Some)
+                .map_err(|_| limit.max),
+            None => Ok(None),
         }
     }
 
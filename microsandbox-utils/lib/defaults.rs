@@ -13,7 +13,7 @@
 //! let memory = DEFAULT_MEMORY_MIB;
 //! ```
 
-use std::{fs, path::PathBuf, sync::LazyLock};
+use std::{fs, path::PathBuf, sync::LazyLock, time::Duration};
 
 use crate::MICROSANDBOX_HOME_DIR;
 
@@ -24,6 +24,13 @@ use crate::MICROSANDBOX_HOME_DIR;
 /// The default maximum log file size (10MB)
 pub const DEFAULT_LOG_MAX_SIZE: u64 = 10 * 1024 * 1024;
 
+/// The default size of the in-memory buffer in front of a log file (1MB)
+pub const DEFAULT_LOG_WRITE_AHEAD_SIZE: usize = 1024 * 1024;
+
+/// How long, by default, restoring the terminal on an abnormal exit waits for queued output
+/// to be written first
+pub const DEFAULT_TERMINAL_RESTORE_GRACE: Duration = Duration::from_millis(100);
+
 /// The default number of vCPUs to use for the MicroVm.
 pub const DEFAULT_NUM_VCPUS: u8 = 1;
 
@@ -2,6 +2,8 @@
 
 use std::path::PathBuf;
 
+use serde_json::Value;
+
 use crate::{DEFAULT_MICROSANDBOX_HOME, DEFAULT_OCI_REGISTRY};
 
 //--------------------------------------------------------------------------------------------------
@@ -20,6 +22,12 @@ pub const MSBRUN_EXE_ENV_VAR: &str = "MSBRUN_EXE";
 /// Environment variable for the msbserver binary path
 pub const MSBSERVER_EXE_ENV_VAR: &str = "MSBSERVER_EXE";
 
+/// Text logged in place of the value of an environment variable
+pub const REDACTED_ENV_VALUE: &str = "[REDACTED]";
+
+/// Names of request fields holding environment variables, whose values are kept out of logs
+const ENV_FIELDS: [&str; 2] = ["env", "envs"];
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -45,3 +53,87 @@ pub fn get_oci_registry() -> String {
         DEFAULT_OCI_REGISTRY.to_string()
     }
 }
+
+/// Returns a copy of a JSON request with the values of its environment variables redacted,
+/// for logging.
+///
+/// Variables are found in `env` and `envs` fields at any depth, either as an object of names
+/// to values or as a list of `NAME=VALUE` strings. Their names are kept, so logs still show
+/// which variables were set.
+pub fn redact_env(value: &Value) -> Value {
+    let mut value = value.clone();
+    redact_env_fields(&mut value);
+    value
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Redacts the environment variables in every `env` or `envs` field of `value`
+fn redact_env_fields(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if ENV_FIELDS.contains(&key.as_str()) {
+                    redact_env_values(value);
+                } else {
+                    redact_env_fields(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_env_fields),
+        _ => {}
+    }
+}
+
+/// Redacts the values of environment variables, keeping their names
+fn redact_env_values(value: &mut Value) {
+    match value {
+        Value::Null => {}
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|value| *value = Value::String(REDACTED_ENV_VALUE.to_string())),
+        Value::Array(items) => {
+            for item in items {
+                *item = match item.as_str().and_then(|s| s.split_once('=')) {
+                    Some((name, _)) => format!("{}={}", name, REDACTED_ENV_VALUE).into(),
+                    None => REDACTED_ENV_VALUE.into(),
+                };
+            }
+        }
+        _ => *value = Value::String(REDACTED_ENV_VALUE.to_string()),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_redact_env_keeps_names_but_not_values() {
+        let request = json!({
+            "method": "sandbox.repl.run",
+            "params": {
+                "code": "print(1)",
+                "env": { "API_TOKEN": "hunter2", "DEBUG": "1" },
+                "config": { "envs": ["HOME=/root", "SECRET"] },
+            },
+        });
+
+        assert_eq!(
+            redact_env(&request)["params"],
+            json!({
+                "code": "print(1)",
+                "env": { "API_TOKEN": "[REDACTED]", "DEBUG": "[REDACTED]" },
+                "config": { "envs": ["HOME=[REDACTED]", "[REDACTED]"] },
+            })
+        );
+    }
+}
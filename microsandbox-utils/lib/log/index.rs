@@ -0,0 +1,403 @@
+//! Segment index for rotated logs.
+//!
+//! A [`RotatingLog`](super::RotatingLog) writes one logical stream of bytes across several
+//! files: the segments rotated out so far and the active log file. The index records the order
+//! of the segments, their sizes and the offset each one starts at in the stream, so a reader
+//! can seek straight to an offset or to the last few bytes without opening every segment.
+//!
+//! The index lives next to the log as a small text file, `app.index` for `app.log`. It is
+//! rewritten atomically on every rotation and rebuilt from the segments on disk when it is
+//! missing or doesn't match them.
+//!
+//! Sizes and offsets always count the uncompressed bytes of the log stream, so a position in
+//! a gzip-compressed segment (one whose name ends in `.gz`) is a position in its decompressed
+//! contents.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// First line of an index file, identifying its format
+const INDEX_HEADER: &str = "msb-log-index v1";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The index of the segments of a rotated log.
+///
+/// # Example
+///
+/// ```no_run
+/// use microsandbox_utils::log::LogIndex;
+///
+/// # async fn example() -> std::io::Result<()> {
+/// let index = LogIndex::load("app.log").await?;
+///
+/// // Find where byte 4096 of the log stream is stored
+/// if let Some((path, position)) = index.locate(4096) {
+///     println!("{} at {}", path.display(), position);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogIndex {
+    /// Path to the active log file
+    log_path: PathBuf,
+
+    /// Rotated segments, oldest first
+    segments: Vec<LogSegment>,
+
+    /// Offset in the log stream at which the active log file starts
+    active_start: u64,
+}
+
+/// A segment rotated out of a log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogSegment {
+    /// File name of the segment, in the same directory as the log
+    pub file_name: String,
+
+    /// Offset in the log stream at which the segment starts
+    pub start: u64,
+
+    /// Size of the segment in bytes, before any compression
+    pub size: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl LogIndex {
+    /// Loads the index of the log at `log_path`.
+    ///
+    /// If the index file is missing, can't be parsed, or lists segments that don't match the
+    /// files on disk, it is rebuilt from the segments that exist and saved again. A rebuilt
+    /// index starts counting offsets from the oldest segment still on disk.
+    pub async fn load(log_path: impl AsRef<Path>) -> io::Result<Self> {
+        let log_path = log_path.as_ref().to_path_buf();
+
+        match fs::read_to_string(index_path(&log_path)).await {
+            Ok(contents) => {
+                if let Some(index) = Self::parse(&log_path, &contents) {
+                    if index.matches_disk().await {
+                        return Ok(index);
+                    }
+                }
+                tracing::warn!(log = %log_path.display(), "log index doesn't match its segments, rebuilding");
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let index = Self::rebuild(log_path).await?;
+        index.save().await?;
+        Ok(index)
+    }
+
+    /// Returns the rotated segments, oldest first.
+    pub fn segments(&self) -> &[LogSegment] {
+        &self.segments
+    }
+
+    /// Returns the offset in the log stream at which the active log file starts.
+    pub fn active_start(&self) -> u64 {
+        self.active_start
+    }
+
+    /// Returns the offset of the oldest byte still on disk.
+    pub fn first_offset(&self) -> u64 {
+        self.segments
+            .first()
+            .map_or(self.active_start, |segment| segment.start)
+    }
+
+    /// Finds the file holding the byte at `offset` in the log stream, and its position in that
+    /// file.
+    ///
+    /// Offsets at or past the start of the active log file resolve to the active file, even
+    /// beyond its current end. Returns `None` for offsets whose segment has been removed.
+    pub fn locate(&self, offset: u64) -> Option<(PathBuf, u64)> {
+        if offset >= self.active_start {
+            return Some((self.log_path.clone(), offset - self.active_start));
+        }
+
+        let i = self
+            .segments
+            .partition_point(|segment| segment.start <= offset)
+            .checked_sub(1)?;
+        let segment = &self.segments[i];
+        (offset < segment.start + segment.size)
+            .then(|| (self.segment_path(segment), offset - segment.start))
+    }
+
+    /// Returns the path to a segment.
+    pub fn segment_path(&self, segment: &LogSegment) -> PathBuf {
+        self.log_path.with_file_name(&segment.file_name)
+    }
+
+    /// Records that the active log file, `size` bytes long, was rotated out as `file_name`.
+    ///
+    /// Segments whose files no longer exist, including one that `file_name` replaced, are
+    /// dropped. The index file is rewritten atomically.
+    pub async fn record_rotation(&mut self, file_name: &str, size: u64) -> io::Result<()> {
+        let mut kept = Vec::with_capacity(self.segments.len() + 1);
+        for segment in self.segments.drain(..) {
+            if segment.file_name != file_name
+                && fs::try_exists(self.log_path.with_file_name(&segment.file_name)).await?
+            {
+                kept.push(segment);
+            }
+        }
+        self.segments = kept;
+
+        self.segments.push(LogSegment {
+            file_name: file_name.to_string(),
+            start: self.active_start,
+            size,
+        });
+        self.active_start += size;
+
+        self.save().await
+    }
+
+    /// Drops all but the newest `count` segments from the index, returning the dropped ones.
+    ///
+    /// The segment files are left on disk for the caller to delete. The index file is
+    /// rewritten atomically if anything was dropped.
+    pub async fn retain_newest(&mut self, count: usize) -> io::Result<Vec<LogSegment>> {
+        let excess = self.segments.len().saturating_sub(count);
+        if excess == 0 {
+            return Ok(Vec::new());
+        }
+
+        let dropped = self.segments.drain(..excess).collect();
+        self.save().await?;
+        Ok(dropped)
+    }
+
+    /// Writes the index next to the log, replacing the old index atomically.
+    async fn save(&self) -> io::Result<()> {
+        let path = index_path(&self.log_path);
+        let tmp_path = path.with_extension("index.tmp");
+
+        let mut contents = format!("{}\n{}\n", INDEX_HEADER, self.active_start);
+        for segment in &self.segments {
+            contents.push_str(&format!(
+                "{} {} {}\n",
+                segment.start, segment.size, segment.file_name
+            ));
+        }
+
+        fs::write(&tmp_path, contents).await?;
+        fs::rename(&tmp_path, &path).await
+    }
+
+    /// Parses the contents of an index file, returning `None` if they are malformed
+    fn parse(log_path: &Path, contents: &str) -> Option<Self> {
+        let mut lines = contents.lines();
+        if lines.next()? != INDEX_HEADER {
+            return None;
+        }
+
+        let active_start = lines.next()?.parse().ok()?;
+        let mut segments = Vec::new();
+        for line in lines {
+            let mut fields = line.splitn(3, ' ');
+            let start = fields.next()?.parse().ok()?;
+            let size = fields.next()?.parse().ok()?;
+            let file_name = fields.next()?.to_string();
+            segments.push(LogSegment {
+                file_name,
+                start,
+                size,
+            });
+        }
+
+        // Segments must be contiguous and end where the active file starts
+        let mut expected = segments.first().map_or(active_start, |s| s.start);
+        for segment in &segments {
+            if segment.start != expected {
+                return None;
+            }
+            expected += segment.size;
+        }
+        if expected != active_start {
+            return None;
+        }
+
+        Some(Self {
+            log_path: log_path.to_path_buf(),
+            segments,
+            active_start,
+        })
+    }
+
+    /// Checks that every segment in the index exists on disk with the recorded size
+    ///
+    /// Only the existence of compressed segments is checked, since their size on disk differs.
+    async fn matches_disk(&self) -> bool {
+        for segment in &self.segments {
+            match fs::metadata(self.segment_path(segment)).await {
+                Ok(_) if segment.is_compressed() => {}
+                Ok(metadata) if metadata.len() == segment.size => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Builds an index from the segments present on disk
+    ///
+    /// Numbered segments, named after the offset they start at, take precedence over a single
+    /// `.old` segment, which is taken to start the stream.
+    async fn rebuild(log_path: PathBuf) -> io::Result<Self> {
+        let mut index = Self {
+            log_path,
+            segments: Vec::new(),
+            active_start: 0,
+        };
+
+        let stem = index
+            .log_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let legacy_name = format!("{}.old", stem);
+        let numbered_prefix = format!("{}.", stem);
+
+        let dir = match index.log_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(index),
+            Err(e) => return Err(e),
+        };
+
+        let mut numbered = Vec::new();
+        let mut legacy = None;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let name = file_name.strip_suffix(".gz").unwrap_or(&file_name);
+            if name == legacy_name {
+                legacy = Some(file_name);
+            } else if let Some(start) = name
+                .strip_prefix(&numbered_prefix)
+                .and_then(|rest| rest.strip_suffix(".old"))
+                .and_then(|start| start.parse::<u64>().ok())
+            {
+                numbered.push((start, file_name));
+            }
+        }
+
+        numbered.sort();
+        let found = if numbered.is_empty() {
+            legacy.into_iter().map(|name| (0, name)).collect()
+        } else {
+            numbered
+        };
+
+        for (start, file_name) in found {
+            let size = segment_size(&dir.join(&file_name)).await?;
+            index.segments.push(LogSegment {
+                file_name,
+                start,
+                size,
+            });
+            index.active_start = start + size;
+        }
+
+        Ok(index)
+    }
+}
+
+impl LogSegment {
+    /// Returns whether the segment is gzip-compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.file_name.ends_with(".gz")
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the path of the index file for the log at `log_path`.
+pub fn index_path(log_path: &Path) -> PathBuf {
+    log_path.with_extension("index")
+}
+
+/// Returns the uncompressed size of a segment file
+///
+/// For a gzip-compressed segment this is read from the gzip trailer, which holds the size
+/// modulo 4GiB.
+async fn segment_size(path: &Path) -> io::Result<u64> {
+    let metadata = fs::metadata(path).await?;
+    if path.extension().is_none_or(|ext| ext != "gz") || metadata.len() < 4 {
+        return Ok(metadata.len());
+    }
+
+    let mut file = fs::File::open(path).await?;
+    file.seek(io::SeekFrom::End(-4)).await?;
+    let mut trailer = [0; 4];
+    file.read_exact(&mut trailer).await?;
+    Ok(u32::from_le_bytes(trailer) as u64)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_log_index_tracks_rotations_and_recovers() -> io::Result<()> {
+        let dir = tempdir()?;
+        let log_path = dir.path().join("test.log");
+
+        let mut index = LogIndex::load(&log_path).await?;
+        assert_eq!(index.active_start(), 0);
+
+        // Rotate twice; the second rotation replaces the first segment
+        std::fs::write(log_path.with_extension("old"), "0123456789")?;
+        index.record_rotation("test.old", 10).await?;
+        std::fs::write(log_path.with_extension("old"), "abcde")?;
+        index.record_rotation("test.old", 5).await?;
+
+        assert_eq!(index.first_offset(), 10);
+        assert_eq!(index.active_start(), 15);
+        assert_eq!(index.locate(3), None);
+        assert_eq!(index.locate(12), Some((log_path.with_extension("old"), 2)));
+        assert_eq!(index.locate(20), Some((log_path.clone(), 5)));
+
+        // The saved index loads back as is
+        assert_eq!(LogIndex::load(&log_path).await?, index);
+
+        // A corrupt index is rebuilt from the segments on disk
+        std::fs::write(index_path(&log_path), "garbage")?;
+        let rebuilt = LogIndex::load(&log_path).await?;
+        assert_eq!(rebuilt.segments().len(), 1);
+        assert_eq!(rebuilt.first_offset(), 0);
+        assert_eq!(rebuilt.active_start(), 5);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+//! Single-writer locking for log files.
+//!
+//! Two monitors writing the same log, such as an original monitor and one that reattached to
+//! the same sandbox, would interleave their writes and rotate the file out from under each
+//! other. A [`RotatingLog`](super::RotatingLog) opened with a [`LogLockPolicy`] therefore holds
+//! an exclusive `flock` on a lock file next to the log for as long as it is open, `app.lock`
+//! for `app.log`. The lock file also records the writer's process ID, to say who holds it. The
+//! kernel releases the lock when the writer exits, so a crashed writer never leaves a stale
+//! lock behind.
+//!
+//! A writer takes over a log by waiting for the current one to close it, rather than by taking
+//! the lock away. An `flock` can't be taken from a live process, so a forced take-over would
+//! have to either kill the other writer, which is a monitor still supervising its sandbox, or
+//! write without the lock, which brings back the interleaving the lock exists to prevent.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::{Flock, FlockArg},
+};
+use serde::{Deserialize, Serialize};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Time between attempts to take a lock held by another writer
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// What to do when opening a log that another writer already has open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LogLockPolicy {
+    /// Fail right away with an [`io::ErrorKind::WouldBlock`] error naming the other writer.
+    #[default]
+    Refuse,
+
+    /// Wait up to the given time for the other writer to close the log, then take it over.
+    ///
+    /// Fails like [`Refuse`](Self::Refuse) if the log is still held once the time is up.
+    Wait(Duration),
+}
+
+/// An exclusive lock on a log, released when dropped.
+#[derive(Debug)]
+pub(crate) struct LogLock {
+    _lock: Flock<File>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl LogLock {
+    /// Takes the lock for the log at `log_path`, following `policy` if another writer has it.
+    pub(crate) async fn acquire(log_path: &Path, policy: LogLockPolicy) -> io::Result<Self> {
+        let path = lock_path(log_path);
+        let deadline = match policy {
+            LogLockPolicy::Refuse => None,
+            LogLockPolicy::Wait(timeout) => Some(Instant::now() + timeout),
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+
+        loop {
+            match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+                Ok(mut lock) => {
+                    // Record who holds the lock
+                    lock.set_len(0)?;
+                    lock.rewind()?;
+                    write!(lock, "{}", std::process::id())?;
+                    lock.flush()?;
+                    return Ok(Self { _lock: lock });
+                }
+                Err((returned, Errno::EWOULDBLOCK)) => file = returned,
+                Err((_, errno)) => return Err(errno.into()),
+            }
+
+            if !deadline.is_some_and(|deadline| Instant::now() < deadline) {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!(
+                        "log {} is already being written by {}",
+                        log_path.display(),
+                        describe_holder(&mut file)
+                    ),
+                ));
+            }
+
+            tokio::time::sleep(LOCK_RETRY_INTERVAL).await;
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the path of the lock file for the log at `log_path`.
+pub fn lock_path(log_path: &Path) -> PathBuf {
+    log_path.with_extension("lock")
+}
+
+/// Describes the writer holding a lock, from the process ID it recorded
+fn describe_holder(file: &mut File) -> String {
+    let mut contents = String::new();
+    let pid = file
+        .rewind()
+        .and_then(|_| file.read_to_string(&mut contents))
+        .ok()
+        .and_then(|_| contents.trim().parse::<u32>().ok());
+
+    match pid {
+        Some(pid) => format!("process {}", pid),
+        None => "another writer".to_string(),
+    }
+}
@@ -1,9 +1,19 @@
 //! `microsandbox_utils::log` is a module containing logging utilities for the microsandbox project.
 
+mod index;
+mod lock;
+mod ring;
 mod rotating;
+mod sink;
+mod write_ahead;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub use index::*;
+pub use lock::{lock_path, LogLockPolicy};
+pub use ring::*;
 pub use rotating::*;
+pub use sink::*;
+pub use write_ahead::*;
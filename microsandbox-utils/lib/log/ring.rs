@@ -0,0 +1,312 @@
+//! In-memory ring buffer of recent output for the Microsandbox runtime.
+//!
+//! This module provides a bounded buffer that keeps the most recent bytes written to it,
+//! evicting the oldest bytes once it is full. It is meant for fast "show me the tail"
+//! queries that shouldn't have to read log files.
+//!
+//! What "recent" means is set by a [`RingPolicy`], which can bound the buffer by bytes, by
+//! lines and by age at once. Output is evicted as soon as any bound is exceeded.
+
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The retention policy of an [`OutputRing`].
+///
+/// Every bound is checked on each append, and the age bound again on each read, so a read
+/// returns exactly the output that satisfies all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RingPolicy {
+    /// Maximum number of bytes retained
+    pub max_bytes: usize,
+
+    /// Maximum number of lines retained, counting a trailing unterminated line
+    pub max_lines: Option<usize>,
+
+    /// Maximum time output is retained after it was appended
+    pub max_age: Option<Duration>,
+}
+
+/// A bounded in-memory buffer of the most recent output bytes.
+///
+/// Appends and reads take a short-lived lock around a memory copy, so the buffer can be
+/// shared between output tasks without blocking them on I/O.
+///
+/// # Example
+///
+/// ```
+/// use microsandbox_utils::log::OutputRing;
+///
+/// let ring = OutputRing::new(8);
+/// ring.push(b"hello, world");
+/// assert_eq!(ring.tail(5), b"world");
+/// assert_eq!(ring.tail(100), b"o, world");
+/// ```
+#[derive(Debug)]
+pub struct OutputRing {
+    /// What output is retained
+    policy: RingPolicy,
+
+    /// The retained output
+    state: Mutex<RingState>,
+}
+
+/// The retained output of an [`OutputRing`]
+#[derive(Debug, Default)]
+struct RingState {
+    /// The retained bytes, oldest first
+    buf: VecDeque<u8>,
+
+    /// When each retained chunk was appended and its length, oldest first
+    chunks: VecDeque<(Instant, usize)>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RingPolicy {
+    /// Creates a policy that retains at most `max_bytes` bytes, with no line or age bound.
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            max_lines: None,
+            max_age: None,
+        }
+    }
+
+    /// Also retains at most `max_lines` lines.
+    pub fn with_max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = Some(max_lines);
+        self
+    }
+
+    /// Also retains output for at most `max_age` after it was appended.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+}
+
+impl OutputRing {
+    /// Creates a new ring buffer that retains at most `capacity` bytes.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_policy(RingPolicy::new(capacity))
+    }
+
+    /// Creates a new ring buffer that retains output according to `policy`.
+    pub fn with_policy(policy: RingPolicy) -> Self {
+        Self {
+            policy,
+            state: Mutex::new(RingState {
+                buf: VecDeque::with_capacity(policy.max_bytes),
+                chunks: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Returns the maximum number of bytes retained.
+    pub fn capacity(&self) -> usize {
+        self.policy.max_bytes
+    }
+
+    /// Returns the retention policy.
+    pub fn policy(&self) -> RingPolicy {
+        self.policy
+    }
+
+    /// Appends bytes, evicting the oldest output that falls outside the policy.
+    pub fn push(&self, bytes: &[u8]) {
+        self.push_at(bytes, Instant::now());
+    }
+
+    /// Returns up to the last `max_bytes` retained bytes.
+    pub fn tail(&self, max_bytes: usize) -> Vec<u8> {
+        self.tail_at(max_bytes, Instant::now())
+    }
+
+    fn push_at(&self, bytes: &[u8], now: Instant) {
+        // Only the last `max_bytes` bytes of a large chunk can survive anyway
+        let bytes = &bytes[bytes.len().saturating_sub(self.policy.max_bytes)..];
+
+        let mut state = self.state.lock().unwrap();
+        if !bytes.is_empty() {
+            state.buf.extend(bytes);
+            state.chunks.push_back((now, bytes.len()));
+        }
+        state.evict(&self.policy, now);
+    }
+
+    fn tail_at(&self, max_bytes: usize, now: Instant) -> Vec<u8> {
+        let mut state = self.state.lock().unwrap();
+        state.evict(&self.policy, now);
+        let start = state.buf.len().saturating_sub(max_bytes);
+        state.buf.range(start..).copied().collect()
+    }
+}
+
+impl RingState {
+    /// Evicts the oldest output until the retained output satisfies `policy`
+    fn evict(&mut self, policy: &RingPolicy, now: Instant) {
+        let mut evict = self.buf.len().saturating_sub(policy.max_bytes);
+
+        if let Some(max_age) = policy.max_age {
+            let mut expired = 0;
+            for (appended_at, len) in &self.chunks {
+                if now.saturating_duration_since(*appended_at) <= max_age {
+                    break;
+                }
+                expired += len;
+            }
+            evict = evict.max(expired);
+        }
+
+        if let Some(max_lines) = policy.max_lines {
+            evict = evict.max(self.excess_lines_len(evict, max_lines));
+        }
+
+        self.drain_front(evict);
+    }
+
+    /// Returns how many bytes must be evicted from the front, at least `from`, to keep at
+    /// most `max_lines` lines
+    fn excess_lines_len(&self, from: usize, max_lines: usize) -> usize {
+        let mut lines = self.buf.range(from..).filter(|&&b| b == b'\n').count();
+        if self.buf.back().is_some_and(|&b| b != b'\n') && self.buf.len() > from {
+            lines += 1;
+        }
+
+        let mut end = from;
+        for (i, &b) in self.buf.range(from..).enumerate() {
+            if lines <= max_lines {
+                break;
+            }
+            if b == b'\n' {
+                lines -= 1;
+                end = from + i + 1;
+            }
+        }
+
+        // With `max_lines` of zero, not even an unterminated line is kept
+        if lines > max_lines {
+            self.buf.len()
+        } else {
+            end
+        }
+    }
+
+    /// Removes `len` bytes from the front, along with the chunks they belonged to
+    fn drain_front(&mut self, len: usize) {
+        self.buf.drain(..len);
+
+        let mut remaining = len;
+        while remaining > 0 {
+            let Some((_, chunk_len)) = self.chunks.front_mut() else {
+                break;
+            };
+            if *chunk_len > remaining {
+                *chunk_len -= remaining;
+                break;
+            }
+            remaining -= *chunk_len;
+            self.chunks.pop_front();
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_keeps_most_recent_bytes() {
+        let ring = OutputRing::new(4);
+        ring.push(b"ab");
+        ring.push(b"cd");
+        ring.push(b"ef");
+        assert_eq!(ring.tail(10), b"cdef");
+        assert_eq!(ring.tail(3), b"def");
+    }
+
+    #[test]
+    fn test_ring_push_larger_than_capacity() {
+        let ring = OutputRing::new(3);
+        ring.push(b"x");
+        ring.push(b"abcdef");
+        assert_eq!(ring.tail(10), b"def");
+    }
+
+    #[test]
+    fn test_ring_policy_evicts_by_lines() {
+        let ring = OutputRing::with_policy(RingPolicy::new(100).with_max_lines(2));
+        ring.push(b"one\ntwo\n");
+        ring.push(b"three\nfo");
+        assert_eq!(ring.tail(100), b"three\nfo");
+        ring.push(b"ur\n");
+        assert_eq!(ring.tail(100), b"three\nfour\n");
+
+        // The byte bound still applies when fewer lines are retained
+        let ring = OutputRing::with_policy(RingPolicy::new(6).with_max_lines(2));
+        ring.push(b"abcdefgh\n");
+        assert_eq!(ring.tail(100), b"defgh\n");
+    }
+
+    #[test]
+    fn test_ring_policy_evicts_by_age() {
+        let ring =
+            OutputRing::with_policy(RingPolicy::new(100).with_max_age(Duration::from_secs(10)));
+        let start = Instant::now();
+        ring.push_at(b"old ", start);
+        ring.push_at(b"newer ", start + Duration::from_secs(5));
+        assert_eq!(
+            ring.tail_at(100, start + Duration::from_secs(8)),
+            b"old newer "
+        );
+
+        // Expired output is evicted on append and on read
+        ring.push_at(b"newest", start + Duration::from_secs(12));
+        assert_eq!(
+            ring.tail_at(100, start + Duration::from_secs(12)),
+            b"newer newest"
+        );
+        assert_eq!(
+            ring.tail_at(100, start + Duration::from_secs(20)),
+            b"newest"
+        );
+        assert!(ring
+            .tail_at(100, start + Duration::from_secs(30))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_ring_policy_evicts_by_bytes_across_chunks() {
+        let ring =
+            OutputRing::with_policy(RingPolicy::new(5).with_max_age(Duration::from_secs(10)));
+        let start = Instant::now();
+        ring.push_at(b"abc", start);
+        ring.push_at(b"defg", start + Duration::from_secs(5));
+
+        // The partly evicted first chunk still expires at its own time
+        assert_eq!(ring.tail_at(100, start + Duration::from_secs(6)), b"cdefg");
+        assert_eq!(ring.tail_at(100, start + Duration::from_secs(11)), b"defg");
+    }
+
+    #[test]
+    fn test_ring_with_zero_capacity_keeps_nothing() {
+        let ring = OutputRing::new(0);
+        ring.push(b"abc");
+        assert!(ring.tail(10).is_empty());
+    }
+}
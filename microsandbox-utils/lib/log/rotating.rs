@@ -6,10 +6,24 @@
 //! 2. Creating a new empty log file
 //! 3. Continuing writing to the new file
 //!
+//! By default only the last rotated segment is kept. A [`LogRotation`] with more than one
+//! retained file numbers the segments instead, e.g. `app.1048576.old`, after the offset they
+//! start at, and deletes the oldest ones beyond the retention count. Rotated segments can also
+//! be gzip-compressed, gaining a `.gz` suffix.
+//!
+//! Every rotation is also recorded in the log's [`LogIndex`], so readers can find segments
+//! and offsets without scanning the files.
+//!
+//! A log opened with [`RotatingLog::with_lock_policy`] is locked against other writers that
+//! lock it too, so two processes can't rotate the same file out from under each other.
+//!
 //! The implementation is fully asynchronous and implements AsyncWrite.
 
+use flate2::{write::GzEncoder, Compression};
 use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
 use std::{
+    ffi::OsString,
     io::{self, Write},
     path::{Path, PathBuf},
     pin::Pin,
@@ -28,10 +42,37 @@ use tokio::{
 
 use crate::DEFAULT_LOG_MAX_SIZE;
 
+use super::{lock::LogLock, LogIndex, LogLockPolicy};
+
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
 
+/// When and how a [`RotatingLog`] rotates, and how many rotated segments it keeps.
+///
+/// # Example
+///
+/// ```
+/// use microsandbox_utils::log::LogRotation;
+///
+/// // Keep at most 5 compressed segments of 100MB besides the active log
+/// let rotation = LogRotation::new(100 * 1024 * 1024)
+///     .with_max_files(5)
+///     .with_compression(true);
+/// assert_eq!(rotation.max_files(), 5);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRotation {
+    /// Maximum size in bytes of the active log file before rotation
+    max_size: u64,
+
+    /// Number of rotated segments kept
+    max_files: usize,
+
+    /// Whether rotated segments are gzip-compressed
+    compress: bool,
+}
+
 /// A rotating log file that automatically rotates when reaching a maximum size.
 ///
 /// The log rotation process preserves the last full log file with a ".old" extension
@@ -55,8 +96,8 @@ pub struct RotatingLog {
     /// Path to the current log file
     path: PathBuf,
 
-    /// Maximum size in bytes before rotation
-    max_size: u64,
+    /// When to rotate and which rotated segments to keep
+    rotation: LogRotation,
 
     /// Current size of the log file (shared between sync and async paths)
     current_size: Arc<AtomicU64>,
@@ -69,6 +110,9 @@ pub struct RotatingLog {
 
     /// Background task handle
     _background_task: JoinHandle<()>,
+
+    /// Lock keeping other writers off the log, if one was taken
+    _lock: Option<LogLock>,
 }
 
 /// Internal state machine for managing log rotation
@@ -94,6 +138,55 @@ type RotationFuture = BoxFuture<'static, io::Result<(File, PathBuf)>>;
 // Methods
 //--------------------------------------------------------------------------------------------------
 
+impl LogRotation {
+    /// Rotates at `max_size` bytes, keeping one uncompressed rotated segment.
+    pub fn new(max_size: u64) -> Self {
+        Self {
+            max_size,
+            max_files: 1,
+            compress: false,
+        }
+    }
+
+    /// Sets the number of rotated segments to keep; older ones are deleted.
+    ///
+    /// With `0`, every segment is deleted as soon as it is rotated out.
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    /// Sets whether rotated segments are gzip-compressed.
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Returns the maximum size in bytes of the active log file.
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// Returns the number of rotated segments kept.
+    pub fn max_files(&self) -> usize {
+        self.max_files
+    }
+
+    /// Returns whether rotated segments are gzip-compressed.
+    pub fn compress(&self) -> bool {
+        self.compress
+    }
+
+    /// Returns the path a segment starting at `start` in the log stream is rotated to
+    fn segment_path(&self, path: &Path, start: u64) -> PathBuf {
+        if self.max_files <= 1 {
+            path.with_extension("old")
+        } else {
+            path.with_extension(format!("{}.old", start))
+        }
+    }
+}
+
 impl RotatingLog {
     /// Creates a new rotating log file with the default maximum size.
     ///
@@ -121,7 +214,60 @@ impl RotatingLog {
     /// * The file cannot be created or opened
     /// * File metadata cannot be read
     pub async fn with_max_size(path: impl AsRef<Path>, max_size: u64) -> io::Result<Self> {
+        Self::open(
+            path.as_ref().to_path_buf(),
+            LogRotation::new(max_size),
+            None,
+        )
+        .await
+    }
+
+    /// Creates a new rotating log file with the given rotation settings.
+    ///
+    /// With a `lock_policy`, the log is locked against other writers as with
+    /// [`with_lock_policy`](Self::with_lock_policy).
+    ///
+    /// ## Errors
+    ///
+    /// Will return an error if:
+    /// * Another writer holds the lock and `lock_policy` gives up on it
+    /// * The lock file or the log file cannot be created or opened
+    /// * File metadata cannot be read
+    pub async fn with_rotation(
+        path: impl AsRef<Path>,
+        rotation: LogRotation,
+        lock_policy: Option<LogLockPolicy>,
+    ) -> io::Result<Self> {
         let path = path.as_ref().to_path_buf();
+        let lock = match lock_policy {
+            Some(policy) => Some(LogLock::acquire(&path, policy).await?),
+            None => None,
+        };
+        Self::open(path, rotation, lock).await
+    }
+
+    /// Creates a new rotating log file that no other locking writer can open at the same time.
+    ///
+    /// An exclusive lock is held on a lock file next to the log until the log is dropped.
+    /// `policy` decides what happens if another writer already holds it. Logs opened with
+    /// [`new`](Self::new) or [`with_max_size`](Self::with_max_size) don't take the lock, so
+    /// they can be shared.
+    ///
+    /// ## Errors
+    ///
+    /// Will return an error if:
+    /// * Another writer holds the lock and `policy` gives up on it
+    /// * The lock file or the log file cannot be created or opened
+    /// * File metadata cannot be read
+    pub async fn with_lock_policy(
+        path: impl AsRef<Path>,
+        max_size: u64,
+        policy: LogLockPolicy,
+    ) -> io::Result<Self> {
+        Self::with_rotation(path, LogRotation::new(max_size), Some(policy)).await
+    }
+
+    async fn open(path: PathBuf, rotation: LogRotation, lock: Option<LogLock>) -> io::Result<Self> {
         let file = OpenOptions::new()
             .create(true)
             .append(true)
@@ -136,22 +282,22 @@ impl RotatingLog {
         // Create a clone of the file and size counter for the background task
         let bg_file = file.try_clone().await?;
         let bg_path = path.clone();
-        let bg_max_size = max_size;
         let bg_size = Arc::clone(&current_size);
 
         // Spawn background task to handle channel data
         let background_task = tokio::spawn(async move {
-            handle_channel_data(rx, bg_file, bg_path, bg_max_size, bg_size).await
+            handle_channel_data(rx, bg_file, bg_path, rotation, bg_size).await
         });
 
         Ok(Self {
             file,
             path,
-            max_size,
+            rotation,
             current_size,
             state: State::Idle,
             tx,
             _background_task: background_task,
+            _lock: lock,
         })
     }
 
@@ -178,6 +324,7 @@ impl SyncChannelWriter {
 ///
 /// * `file` - The current log file to be rotated
 /// * `path` - Path to the current log file
+/// * `rotation` - Where the rotated segment goes and how many segments are kept
 ///
 /// # Returns
 ///
@@ -192,15 +339,55 @@ impl SyncChannelWriter {
 /// * Old backup file cannot be removed
 /// * File rename operation fails
 /// * New log file cannot be created
-async fn do_rotation(file: File, path: PathBuf) -> io::Result<(File, PathBuf)> {
+async fn do_rotation(
+    file: File,
+    path: PathBuf,
+    rotation: LogRotation,
+) -> io::Result<(File, PathBuf)> {
     file.sync_all().await?;
-    let backup_path = path.with_extension("old");
-    if backup_path.exists() {
-        remove_file(&backup_path).await?;
+    let rotated_size = file.metadata().await?.len();
+
+    // Numbered segments are named after the offset they start at, which the index knows. A
+    // stale index only slows readers down until it is rebuilt, so it doesn't fail the rotation.
+    let mut index = match LogIndex::load(&path).await {
+        Ok(index) => Some(index),
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to load log index");
+            None
+        }
+    };
+    let start = index.as_ref().map_or(0, LogIndex::active_start);
+
+    let backup_path = rotation.segment_path(&path, start);
+    for existing in [backup_path.clone(), compressed_path(&backup_path)] {
+        if existing.exists() {
+            let size = tokio::fs::metadata(&existing).await?.len();
+            tracing::warn!(segment = %existing.display(), bytes = size, "log rotation is replacing a rotated segment, dropping its data");
+            remove_file(&existing).await?;
+        }
     }
 
     rename(&path, &backup_path).await?;
 
+    let backup_path = if rotation.compress {
+        match compress_segment(&backup_path).await {
+            Ok(compressed) => compressed,
+            Err(e) => {
+                tracing::warn!(error = %e, segment = %backup_path.display(), "failed to compress rotated log segment");
+                backup_path
+            }
+        }
+    } else {
+        backup_path
+    };
+
+    if let Some(index) = &mut index {
+        if let Err(e) = record_rotation(index, &backup_path, rotated_size, rotation.max_files).await
+        {
+            tracing::warn!(error = %e, "failed to update log index");
+        }
+    }
+
     let new_file = OpenOptions::new()
         .create(true)
         .append(true)
@@ -210,25 +397,88 @@ async fn do_rotation(file: File, path: PathBuf) -> io::Result<(File, PathBuf)> {
     Ok((new_file, path))
 }
 
+/// Records a rotated segment in the log's index, deleting segments beyond `max_files`
+async fn record_rotation(
+    index: &mut LogIndex,
+    backup_path: &Path,
+    size: u64,
+    max_files: usize,
+) -> io::Result<()> {
+    let file_name = backup_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    index.record_rotation(&file_name, size).await?;
+
+    for segment in index.retain_newest(max_files).await? {
+        let segment_path = index.segment_path(&segment);
+        tracing::warn!(segment = %segment_path.display(), bytes = segment.size, "log rotation dropped a segment beyond the retention count");
+        if let Err(e) = remove_file(&segment_path).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the path of the gzip-compressed version of a segment
+fn compressed_path(path: &Path) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(".gz");
+    PathBuf::from(path)
+}
+
+/// Gzip-compresses a rotated segment, replacing it with a `.gz` file
+async fn compress_segment(path: &Path) -> io::Result<PathBuf> {
+    let source = path.to_path_buf();
+    let target = compressed_path(path);
+
+    tokio::task::spawn_blocking(move || {
+        let compress = || -> io::Result<()> {
+            let mut input = std::fs::File::open(&source)?;
+            let mut encoder =
+                GzEncoder::new(std::fs::File::create(&target)?, Compression::default());
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?.sync_all()
+        };
+
+        match compress() {
+            Ok(()) => {
+                std::fs::remove_file(&source)?;
+                Ok(target)
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&target);
+                Err(e)
+            }
+        }
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
 /// Background task that handles data from the sync channel
 async fn handle_channel_data(
     mut rx: UnboundedReceiver<Vec<u8>>,
     mut file: File,
     path: PathBuf,
-    max_size: u64,
+    rotation: LogRotation,
     current_size: Arc<AtomicU64>,
 ) {
     while let Some(data) = rx.recv().await {
         let data_len = data.len() as u64;
         let size = current_size.fetch_add(data_len, Ordering::Relaxed);
 
-        if size + data_len > max_size {
+        if size + data_len > rotation.max_size {
             // Clone the file handle before rotation
             if let Ok(file_clone) = file.try_clone().await {
-                match do_rotation(file_clone, path.clone()).await {
+                match do_rotation(file_clone, path.clone(), rotation).await {
                     Ok((new_file, _)) => {
                         file = new_file;
-                        current_size.store(0, Ordering::Relaxed);
+                        // The data about to be written is the start of the new file
+                        current_size.store(data_len, Ordering::Relaxed);
                     }
                     Err(e) => {
                         tracing::error!("failed to rotate log file: {}", e);
@@ -253,6 +503,12 @@ async fn handle_channel_data(
 // Trait Implementations
 //--------------------------------------------------------------------------------------------------
 
+impl Default for LogRotation {
+    fn default() -> Self {
+        Self::new(DEFAULT_LOG_MAX_SIZE)
+    }
+}
+
 impl AsyncWrite for RotatingLog {
     fn poll_write(
         mut self: Pin<&mut Self>,
@@ -266,13 +522,13 @@ impl AsyncWrite for RotatingLog {
             match &mut this.state {
                 State::Idle => {
                     let size = this.current_size.fetch_add(buf_len, Ordering::Relaxed);
-                    if size + buf_len > this.max_size {
+                    if size + buf_len > this.rotation.max_size {
                         let old_file = std::mem::replace(
                             &mut this.file,
                             File::from_std(std::fs::File::open("/dev/null").unwrap()),
                         );
                         let old_path = this.path.clone();
-                        let fut = Box::pin(do_rotation(old_file, old_path));
+                        let fut = Box::pin(do_rotation(old_file, old_path, this.rotation));
                         this.state = State::Rotating(fut);
                     } else {
                         this.state = State::Writing;
@@ -290,7 +546,8 @@ impl AsyncWrite for RotatingLog {
                         Poll::Ready(Ok((new_file, new_path))) => {
                             this.file = new_file;
                             this.path = new_path;
-                            this.current_size.store(0, Ordering::Relaxed);
+                            // The buffer about to be written is the start of the new file
+                            this.current_size.store(buf_len, Ordering::Relaxed);
                             this.state = State::Writing;
                         }
                     }
@@ -346,6 +603,7 @@ impl Write for SyncChannelWriter {
 mod tests {
     use super::*;
     use std::fs;
+    use std::time::Duration;
     use tempfile::tempdir;
     use tokio::io::AsyncWriteExt;
 
@@ -356,7 +614,7 @@ mod tests {
 
         let log = RotatingLog::with_max_size(&log_path, 1024).await?;
         assert!(log_path.exists());
-        assert_eq!(log.max_size, 1024);
+        assert_eq!(log.rotation.max_size(), 1024);
         assert_eq!(log.current_size.load(Ordering::Relaxed), 0);
 
         Ok(())
@@ -462,6 +720,90 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_locked_log_rejects_concurrent_writer() -> io::Result<()> {
+        let dir = tempdir()?;
+        let log_path = dir.path().join("test.log");
+
+        let mut first =
+            RotatingLog::with_lock_policy(&log_path, 1024, LogLockPolicy::Refuse).await?;
+        first.write_all(b"first\n").await?;
+
+        // A second writer is refused while the first holds the log
+        let err = RotatingLog::with_lock_policy(&log_path, 1024, LogLockPolicy::Refuse)
+            .await
+            .err()
+            .expect("second writer should be refused");
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert!(err.to_string().contains(&std::process::id().to_string()));
+
+        // A waiting writer takes over once the first closes the log
+        let waiter = tokio::spawn({
+            let log_path = log_path.clone();
+            async move {
+                let policy = LogLockPolicy::Wait(Duration::from_secs(5));
+                let mut log = RotatingLog::with_lock_policy(&log_path, 1024, policy).await?;
+                log.write_all(b"second\n").await?;
+                log.flush().await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!waiter.is_finished());
+        first.flush().await?;
+        drop(first);
+        waiter.await.unwrap()?;
+
+        assert_eq!(fs::read_to_string(&log_path)?, "first\nsecond\n");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rotation_retains_and_compresses_segments() -> io::Result<()> {
+        let dir = tempdir()?;
+        let log_path = dir.path().join("test.log");
+        let rotation = LogRotation::new(20)
+            .with_max_files(2)
+            .with_compression(true);
+
+        let mut log = RotatingLog::with_rotation(&log_path, rotation, None).await?;
+        let entry = b"retention entry 0\n";
+        for _ in 0..4 {
+            log.write_all(entry).await?;
+            log.flush().await?;
+        }
+
+        // Three segments were rotated out; only the newest two are kept, compressed
+        let segment_len = entry.len() as u64;
+        let index = LogIndex::load(&log_path).await?;
+        let names: Vec<_> = index
+            .segments()
+            .iter()
+            .map(|s| s.file_name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                format!("test.{}.old.gz", segment_len),
+                format!("test.{}.old.gz", 2 * segment_len),
+            ]
+        );
+        assert!(!dir.path().join("test.0.old.gz").exists());
+
+        let mut decoded = Vec::new();
+        let segment = fs::File::open(index.segment_path(&index.segments()[0]))?;
+        io::Read::read_to_end(&mut flate2::read::GzDecoder::new(segment), &mut decoded)?;
+        assert_eq!(decoded, entry);
+
+        // The index is rebuilt from the numbered segments on disk
+        fs::remove_file(super::super::index_path(&log_path))?;
+        let rebuilt = LogIndex::load(&log_path).await?;
+        assert_eq!(rebuilt, index);
+        assert_eq!(rebuilt.active_start(), 3 * segment_len);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_multiple_rotations() -> io::Result<()> {
         let dir = tempdir()?;
@@ -484,6 +826,11 @@ mod tests {
         assert!(log_path.exists());
         assert!(log_path.with_extension("old").exists());
 
+        // The index keeps counting offsets across rotations
+        let index = LogIndex::load(&log_path).await?;
+        assert_eq!(index.segments().len(), 1);
+        assert_eq!(index.active_start(), 2 * "rotation test 0\n".len() as u64);
+
         Ok(())
     }
 }
@@ -0,0 +1,215 @@
+//! Destinations for a process's output log.
+//!
+//! A [`LogWriteAhead`](super::LogWriteAhead) writes the output it buffers to a [`LogSink`].
+//! [`RotatingLog`] is the sink for a log file on the local filesystem. [`JsonLinesSink`] wraps
+//! each chunk of output in a JSON record instead, for deployments that ship logs from stdout or
+//! to a remote collector rather than keeping them on local disk.
+
+use std::io;
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use tokio::io::{AsyncWrite, AsyncWriteExt, Stdout};
+
+use super::RotatingLog;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A chunk of output read from one of a process's streams
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogChunk {
+    /// The stream the chunk was read from, e.g. `stdout`
+    pub stream: &'static str,
+
+    /// When the chunk was read
+    pub timestamp: DateTime<Utc>,
+
+    /// The output itself
+    pub data: Vec<u8>,
+}
+
+/// A destination that a process's output log is written to.
+///
+/// Chunks are handed over in batches, in the order they were read, and each batch is followed
+/// by a [`flush`](LogSink::flush).
+#[async_trait]
+pub trait LogSink: Send {
+    /// Writes a batch of chunks, in order
+    async fn write_chunks(&mut self, chunks: &[LogChunk]) -> io::Result<()>;
+
+    /// Flushes the chunks written so far to their destination
+    async fn flush(&mut self) -> io::Result<()>;
+}
+
+/// Creates the log sink for a sandbox, given the sandbox's name.
+///
+/// Called each time the sandbox's process starts.
+pub type LogSinkFactory = Box<dyn Fn(&str) -> io::Result<Box<dyn LogSink>> + Send + Sync>;
+
+/// A log sink writing each chunk of output as a line of JSON.
+///
+/// Every line is an object with the `sandbox_name`, the `stream` the chunk was read from, the
+/// `timestamp` it was read at in RFC 3339 format, and the chunk's `data`, with invalid UTF-8
+/// replaced.
+///
+/// # Example
+///
+/// ```no_run
+/// use microsandbox_utils::log::{JsonLinesSink, LogChunk, LogSink};
+///
+/// # async fn example() -> std::io::Result<()> {
+/// let mut sink = JsonLinesSink::stdout("app");
+/// sink.write_chunks(&[LogChunk {
+///     stream: "stdout",
+///     timestamp: chrono::Utc::now(),
+///     data: b"hello\n".to_vec(),
+/// }])
+/// .await?;
+/// sink.flush().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct JsonLinesSink<W> {
+    /// The name of the sandbox the output comes from
+    sandbox_name: String,
+
+    /// Where the lines are written
+    writer: W,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<W> JsonLinesSink<W>
+where
+    W: AsyncWrite + Send + Unpin,
+{
+    /// Creates a sink writing the output of `sandbox_name` to `writer`
+    pub fn new(sandbox_name: impl Into<String>, writer: W) -> Self {
+        Self {
+            sandbox_name: sandbox_name.into(),
+            writer,
+        }
+    }
+
+    /// Consumes the sink, returning the writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl JsonLinesSink<Stdout> {
+    /// Creates a sink writing the output of `sandbox_name` to the process's stdout
+    pub fn stdout(sandbox_name: impl Into<String>) -> Self {
+        Self::new(sandbox_name, tokio::io::stdout())
+    }
+
+    /// A factory of sinks writing each sandbox's output to the process's stdout
+    pub fn stdout_factory() -> LogSinkFactory {
+        Box::new(|sandbox_name| Ok(Box::new(Self::stdout(sandbox_name))))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+#[async_trait]
+impl LogSink for RotatingLog {
+    async fn write_chunks(&mut self, chunks: &[LogChunk]) -> io::Result<()> {
+        let batch: Vec<u8> = chunks
+            .iter()
+            .flat_map(|chunk| &chunk.data)
+            .copied()
+            .collect();
+        self.write_all(&batch).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        AsyncWriteExt::flush(self).await
+    }
+}
+
+#[async_trait]
+impl<W> LogSink for JsonLinesSink<W>
+where
+    W: AsyncWrite + Send + Unpin,
+{
+    async fn write_chunks(&mut self, chunks: &[LogChunk]) -> io::Result<()> {
+        let mut lines = Vec::new();
+        for chunk in chunks {
+            let record = serde_json::json!({
+                "sandbox_name": self.sandbox_name,
+                "stream": chunk.stream,
+                "timestamp": chunk.timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true),
+                "data": String::from_utf8_lossy(&chunk.data),
+            });
+            serde_json::to_writer(&mut lines, &record)?;
+            lines.push(b'\n');
+        }
+        self.writer.write_all(&lines).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush().await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_json_lines_sink_wraps_each_chunk() -> io::Result<()> {
+        let timestamp = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut sink = JsonLinesSink::new("app", Vec::new());
+        sink.write_chunks(&[
+            LogChunk {
+                stream: "stdout",
+                timestamp,
+                data: b"hello\n".to_vec(),
+            },
+            LogChunk {
+                stream: "stderr",
+                timestamp,
+                data: b"oops \xff".to_vec(),
+            },
+        ])
+        .await?;
+        LogSink::flush(&mut sink).await?;
+
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        let records: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            records,
+            [
+                serde_json::json!({
+                    "sandbox_name": "app",
+                    "stream": "stdout",
+                    "timestamp": "2023-11-14T22:13:20.000000000Z",
+                    "data": "hello\n",
+                }),
+                serde_json::json!({
+                    "sandbox_name": "app",
+                    "stream": "stderr",
+                    "timestamp": "2023-11-14T22:13:20.000000000Z",
+                    "data": "oops \u{fffd}",
+                }),
+            ]
+        );
+
+        Ok(())
+    }
+}
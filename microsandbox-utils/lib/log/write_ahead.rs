@@ -0,0 +1,294 @@
+//! Write-ahead buffering of log output for the Microsandbox runtime.
+//!
+//! Writing to a [`RotatingLog`] stalls while the log rotates, and other [`LogSink`]s can stall
+//! on a slow destination. This module puts a bounded in-memory buffer in front of the sink,
+//! drained by a single writer task, so the tasks reading a process's output can keep going
+//! during a rotation. Once the buffer is full, writers wait for the log to catch up. The writer
+//! task batches the chunks that queue up while it writes, so busy output reaches the disk in a
+//! few large writes.
+
+use std::{io, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use tokio::{
+    sync::{
+        mpsc::{self, error::TryRecvError, UnboundedReceiver, UnboundedSender},
+        OwnedSemaphorePermit, Semaphore, TryAcquireError,
+    },
+    time::Instant,
+};
+
+use super::{LogChunk, LogSink};
+
+#[cfg(doc)]
+use super::RotatingLog;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default number of bytes the writer collects before writing them to the log
+pub const DEFAULT_LOG_MAX_BATCH_SIZE: usize = 64 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A handle for writing to a log through a bounded write-ahead buffer.
+///
+/// Writes return as soon as the data is in the buffer. Clones share the same buffer and log,
+/// and chunks from one handle are written in the order they were sent. The log is closed once
+/// every handle has been dropped and the buffer has drained.
+///
+/// # Example
+///
+/// ```no_run
+/// use microsandbox_utils::log::{LogWriteAhead, RotatingLog};
+///
+/// # async fn example() -> std::io::Result<()> {
+/// let log = RotatingLog::new("app.log").await?;
+/// let (log, writer) = LogWriteAhead::new(Box::new(log), 1024 * 1024);
+/// let writer_task = tokio::spawn(writer.run());
+///
+/// log.write("stdout", b"hello\n").await?;
+///
+/// drop(log);
+/// writer_task.await.ok();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LogWriteAhead {
+    /// Sends buffered chunks to the writer, each holding its share of the buffer
+    tx: UnboundedSender<(LogChunk, OwnedSemaphorePermit)>,
+
+    /// One permit per byte of free buffer space
+    space: Arc<Semaphore>,
+
+    /// Size of the buffer in bytes
+    capacity: u32,
+}
+
+/// The task side of a [`LogWriteAhead`], writing buffered chunks to the log.
+pub struct LogWriteAheadWriter {
+    /// The sink being written to
+    log: Box<dyn LogSink>,
+
+    /// Receives buffered chunks from the handles
+    rx: UnboundedReceiver<(LogChunk, OwnedSemaphorePermit)>,
+
+    /// Number of bytes collected before a batch is written
+    max_batch_size: usize,
+
+    /// How long a batch waits for more chunks before it is written
+    flush_interval: Duration,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl LogWriteAhead {
+    /// Creates a write-ahead buffer of `capacity` bytes in front of the sink `log`.
+    ///
+    /// Returns the handle to write with and the writer, whose [`run`](LogWriteAheadWriter::run)
+    /// future must be spawned for buffered data to reach the log.
+    pub fn new(log: Box<dyn LogSink>, capacity: usize) -> (Self, LogWriteAheadWriter) {
+        let capacity = capacity.clamp(1, u32::MAX as usize) as u32;
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let handle = Self {
+            tx,
+            space: Arc::new(Semaphore::new(capacity as usize)),
+            capacity,
+        };
+
+        let writer = LogWriteAheadWriter {
+            log,
+            rx,
+            max_batch_size: DEFAULT_LOG_MAX_BATCH_SIZE,
+            flush_interval: Duration::ZERO,
+        };
+
+        (handle, writer)
+    }
+
+    /// Adds a chunk read from `stream` to the buffer, waiting for space only if the buffer is
+    /// full.
+    ///
+    /// The chunk is timestamped as it is added. A chunk larger than the whole buffer waits for
+    /// the buffer to be empty.
+    pub async fn write(&self, stream: &'static str, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let permits = (data.len() as u64).min(self.capacity as u64) as u32;
+        let permit = match self.space.clone().try_acquire_many_owned(permits) {
+            Ok(permit) => permit,
+            Err(TryAcquireError::NoPermits) => {
+                tracing::debug!("log write-ahead buffer is full, waiting for the log writer");
+                self.space
+                    .clone()
+                    .acquire_many_owned(permits)
+                    .await
+                    .map_err(|_| writer_gone())?
+            }
+            Err(TryAcquireError::Closed) => return Err(writer_gone()),
+        };
+
+        let chunk = LogChunk {
+            stream,
+            timestamp: Utc::now(),
+            data: data.to_vec(),
+        };
+        self.tx.send((chunk, permit)).map_err(|_| writer_gone())
+    }
+}
+
+impl LogWriteAheadWriter {
+    /// Sets how many bytes the writer collects before writing them to the log.
+    ///
+    /// A single chunk larger than this is written on its own. Defaults to
+    /// [`DEFAULT_LOG_MAX_BATCH_SIZE`].
+    pub fn with_max_batch_size(mut self, size: usize) -> Self {
+        self.max_batch_size = size.max(1);
+        self
+    }
+
+    /// Sets how long a batch waits for more chunks before it is written.
+    ///
+    /// Waiting makes for fewer, larger writes when output trickles in, at the cost of that
+    /// much latency before it shows up in the log. Defaults to zero: a batch holds the chunks
+    /// that queued up while the previous one was written, and is written right away.
+    pub fn with_flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Writes buffered chunks to the log until every handle has been dropped.
+    ///
+    /// Chunks are written in the order they were sent, in batches that are each flushed once.
+    /// The buffer space of a batch's chunks is released once the batch is in the log, and the
+    /// last batch is written and flushed before this returns.
+    pub async fn run(mut self) {
+        let mut batch = Vec::new();
+        let mut batch_size = 0;
+        let mut permits = Vec::new();
+
+        while let Some((chunk, permit)) = self.rx.recv().await {
+            batch_size += chunk.data.len();
+            batch.push(chunk);
+            permits.push(permit);
+
+            let deadline = Instant::now() + self.flush_interval;
+            while batch_size < self.max_batch_size {
+                let next = match self.rx.try_recv() {
+                    Ok(chunk) => Some(chunk),
+                    Err(TryRecvError::Empty) if !self.flush_interval.is_zero() => {
+                        tokio::time::timeout_at(deadline, self.rx.recv())
+                            .await
+                            .ok()
+                            .flatten()
+                    }
+                    Err(_) => None,
+                };
+                let Some((chunk, permit)) = next else {
+                    break;
+                };
+                batch_size += chunk.data.len();
+                batch.push(chunk);
+                permits.push(permit);
+            }
+
+            if let Err(e) = self.log.write_chunks(&batch).await {
+                tracing::error!(error = %e, "failed to write buffered output to log");
+            }
+            if let Err(e) = self.log.flush().await {
+                tracing::error!(error = %e, "failed to flush buffered output to log");
+            }
+            batch.clear();
+            batch_size = 0;
+            permits.clear();
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// The error returned once the writer has stopped
+fn writer_gone() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "log writer has stopped")
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::log::RotatingLog;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_ahead_preserves_order_when_full() -> io::Result<()> {
+        let dir = tempdir()?;
+        let log_path = dir.path().join("test.log");
+
+        let log = RotatingLog::new(&log_path).await?;
+        let (log, writer) = LogWriteAhead::new(Box::new(log), 4);
+        let writer_task = tokio::spawn(writer.run());
+
+        // Chunks bigger than the buffer wait for room instead of failing
+        log.write("stdout", b"first\n").await?;
+        log.write("stdout", b"second\n").await?;
+        log.write("stdout", b"3\n").await?;
+
+        drop(log);
+        writer_task.await.unwrap();
+
+        let content = std::fs::read_to_string(&log_path)?;
+        assert_eq!(content, "first\nsecond\n3\n");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_ahead_batches_queued_chunks_in_order() -> io::Result<()> {
+        let dir = tempdir()?;
+        let log_path = dir.path().join("test.log");
+
+        let log = RotatingLog::new(&log_path).await?;
+        let (log, writer) = LogWriteAhead::new(Box::new(log), 1024);
+        let writer = writer
+            .with_max_batch_size(8)
+            .with_flush_interval(Duration::from_millis(20));
+
+        // Chunks queued before the writer starts are written in batches of at least 8 bytes
+        let out = log.clone();
+        for i in 0..10 {
+            out.write("stdout", format!("out {}\n", i).as_bytes())
+                .await?;
+        }
+        let writer_task = tokio::spawn(writer.run());
+
+        // Another handle's chunk is written after the ones sent before it
+        log.write("stdout", b"err 0\n").await?;
+
+        // The last batch is flushed once every handle is gone
+        drop(out);
+        drop(log);
+        writer_task.await.unwrap();
+
+        let content = std::fs::read_to_string(&log_path)?;
+        let expected: String = (0..10).map(|i| format!("out {}\n", i)).collect();
+        assert_eq!(content, expected + "err 0\n");
+
+        Ok(())
+    }
+}
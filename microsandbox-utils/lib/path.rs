@@ -29,6 +29,11 @@ pub const RW_SUBDIR: &str = "rw";
 /// Example: <PROJECT_ROOT>/<MICROSANDBOX_ENV_DIR>/<PATCH_SUBDIR>
 pub const PATCH_SUBDIR: &str = "patch";
 
+/// The directory where snapshots of project read-write layers are stored
+///
+/// Example: <PROJECT_ROOT>/<MICROSANDBOX_ENV_DIR>/<SNAPSHOTS_SUBDIR>
+pub const SNAPSHOTS_SUBDIR: &str = "snapshots";
+
 /// The directory where project logs are stored
 ///
 /// Example: <PROJECT_ROOT>/<MICROSANDBOX_ENV_DIR>/<LOG_SUBDIR>
@@ -1,6 +1,8 @@
 //! `microsandbox_utils::runtime` is a module containing runtime utilities for the microsandbox project.
 
 mod monitor;
+mod shutdown;
+mod stdin;
 mod supervisor;
 
 //--------------------------------------------------------------------------------------------------
@@ -8,4 +10,6 @@ mod supervisor;
 //--------------------------------------------------------------------------------------------------
 
 pub use monitor::*;
+pub use shutdown::*;
+pub use stdin::*;
 pub use supervisor::*;
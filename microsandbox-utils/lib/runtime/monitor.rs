@@ -1,4 +1,7 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use tokio::{
     fs::File,
     io::unix::AsyncFd,
@@ -35,6 +38,41 @@ pub enum ChildIo {
     },
 }
 
+/// How a supervised child process exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ExitStatus {
+    /// The exit code, if the process exited normally.
+    pub code: Option<i32>,
+
+    /// The signal that terminated the process, if any.
+    pub signal: Option<i32>,
+
+    /// Whether the process was killed by the kernel's OOM killer.
+    pub oom: bool,
+
+    /// How long the process ran for.
+    pub duration: Duration,
+
+    /// The largest resident set size the process reached, in bytes, if known.
+    #[serde(default)]
+    pub peak_rss_bytes: Option<u64>,
+
+    /// The user and system CPU time the process used, if known.
+    #[serde(default)]
+    pub cpu_time: Option<Duration>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ExitStatus {
+    /// Returns true if the process exited normally with code 0.
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Traits
 //--------------------------------------------------------------------------------------------------
@@ -47,4 +85,26 @@ pub trait ProcessMonitor {
 
     /// Stop monitoring
     async fn stop(&mut self) -> MicrosandboxUtilsResult<()>;
+
+    /// Note that the process has exited and been reaped, so its PID may have been reused.
+    ///
+    /// Called by the supervisor before [`stop`](Self::stop) when the process exited on its
+    /// own, so stopping doesn't signal whatever process holds the PID now. The default
+    /// implementation does nothing.
+    fn on_reaped(&mut self) {}
+
+    /// Wait for any buffered output to be drained to its destination.
+    ///
+    /// The default implementation returns immediately.
+    async fn drain(&mut self) -> MicrosandboxUtilsResult<()> {
+        Ok(())
+    }
+
+    /// Record how the monitored process exited.
+    ///
+    /// Called by the supervisor once the process has been reaped. The default implementation
+    /// does nothing.
+    async fn on_exit(&mut self, _status: &ExitStatus) -> MicrosandboxUtilsResult<()> {
+        Ok(())
+    }
 }
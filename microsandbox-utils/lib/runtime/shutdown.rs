@@ -0,0 +1,173 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Mutex,
+    task::JoinHandle,
+};
+
+use crate::{MicrosandboxUtilsResult, ProcessMonitor};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A process monitor that can be shared with a [`ShutdownHandler`].
+pub type SharedMonitor = Arc<Mutex<dyn ProcessMonitor + Send>>;
+
+/// An opt-in shutdown handler for supervisors hosting one or more process monitors.
+///
+/// When installed, the handler waits for SIGTERM or SIGINT and then, for every registered
+/// monitor, calls [`ProcessMonitor::stop`] followed by [`ProcessMonitor::drain`], bounded by a
+/// grace window. Awaiting the returned task before exiting the process prevents losing
+/// buffered log data on orchestrator-initiated shutdowns.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use microsandbox_utils::runtime::ShutdownHandler;
+///
+/// # async fn example(monitor: microsandbox_utils::runtime::SharedMonitor) -> std::io::Result<()> {
+/// let mut handler = ShutdownHandler::new(Duration::from_secs(5));
+/// handler.register(monitor);
+///
+/// let shutdown = handler.install()?;
+/// shutdown.await.ok();
+/// std::process::exit(0);
+/// # }
+/// ```
+pub struct ShutdownHandler {
+    /// The monitors to stop on shutdown
+    monitors: Vec<SharedMonitor>,
+
+    /// How long each monitor gets to stop and drain its output
+    grace: Duration,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ShutdownHandler {
+    /// Creates a new shutdown handler with the given per-monitor grace window.
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            monitors: Vec::new(),
+            grace,
+        }
+    }
+
+    /// Registers a monitor to be stopped and drained on shutdown.
+    pub fn register(&mut self, monitor: SharedMonitor) {
+        self.monitors.push(monitor);
+    }
+
+    /// Installs the SIGTERM/SIGINT handlers and returns a task that completes once every
+    /// registered monitor has been stopped and drained.
+    ///
+    /// The signal handlers are registered before this returns, so signals received after
+    /// the call are not missed.
+    pub fn install(self) -> std::io::Result<JoinHandle<()>> {
+        let mut sigterm = signal(SignalKind::terminate())?;
+        let mut sigint = signal(SignalKind::interrupt())?;
+
+        Ok(tokio::spawn(async move {
+            tokio::select! {
+                _ = sigterm.recv() => tracing::info!("received SIGTERM signal, shutting down monitors"),
+                _ = sigint.recv() => tracing::info!("received SIGINT signal, shutting down monitors"),
+            }
+
+            self.shutdown().await;
+        }))
+    }
+
+    /// Stops and drains every registered monitor, each bounded by the grace window.
+    pub async fn shutdown(&self) {
+        let tasks = self.monitors.iter().cloned().map(|monitor| {
+            let grace = self.grace;
+            async move {
+                let result = tokio::time::timeout(grace, stop_and_drain(monitor)).await;
+                match result {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => tracing::error!(error = %e, "failed to stop monitor"),
+                    Err(_) => tracing::warn!(
+                        grace_ms = grace.as_millis() as u64,
+                        "monitor did not drain within the grace window"
+                    ),
+                }
+            }
+        });
+
+        futures::future::join_all(tasks).await;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+async fn stop_and_drain(monitor: SharedMonitor) -> MicrosandboxUtilsResult<()> {
+    let mut monitor = monitor.lock().await;
+    monitor.stop().await?;
+    monitor.drain().await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::ChildIo;
+
+    struct CountingMonitor {
+        stops: Arc<AtomicUsize>,
+        drains: Arc<AtomicUsize>,
+        drain_delay: Duration,
+    }
+
+    #[async_trait]
+    impl ProcessMonitor for CountingMonitor {
+        async fn start(&mut self, _pid: u32, _child_io: ChildIo) -> MicrosandboxUtilsResult<()> {
+            Ok(())
+        }
+
+        async fn stop(&mut self) -> MicrosandboxUtilsResult<()> {
+            self.stops.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn drain(&mut self) -> MicrosandboxUtilsResult<()> {
+            tokio::time::sleep(self.drain_delay).await;
+            self.drains.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_and_drains_all_monitors() {
+        let stops = Arc::new(AtomicUsize::new(0));
+        let drains = Arc::new(AtomicUsize::new(0));
+
+        let mut handler = ShutdownHandler::new(Duration::from_millis(200));
+        for delay in [0, 10, 500] {
+            handler.register(Arc::new(Mutex::new(CountingMonitor {
+                stops: stops.clone(),
+                drains: drains.clone(),
+                drain_delay: Duration::from_millis(delay),
+            })));
+        }
+
+        handler.shutdown().await;
+
+        // Every monitor is stopped, but the slow one is cut off by the grace window
+        assert_eq!(stops.load(Ordering::SeqCst), 3);
+        assert_eq!(drains.load(Ordering::SeqCst), 2);
+    }
+}
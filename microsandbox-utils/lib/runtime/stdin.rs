@@ -0,0 +1,200 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::Mutex,
+    task::JoinHandle,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Size of the chunks read from the stdin source
+const STDIN_CHUNK_SIZE: usize = 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A writable stdin target, such as a TTY master or a child's stdin pipe.
+type StdinTarget = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Routes a single stdin source to one of several registered targets.
+///
+/// A host supervising several interactive sandboxes can only give the real stdin to one of
+/// them at a time. Each monitor registers its writable end under a name, and the host picks
+/// which one is active, like switching panes in tmux. Targets that aren't active get no input.
+///
+/// The first target registered becomes active, so with a single sandbox the router behaves
+/// like copying stdin straight to it. Clones share the same routing state.
+///
+/// # Example
+///
+/// ```no_run
+/// use microsandbox_utils::runtime::StdinRouter;
+///
+/// # async fn example(master_a: tokio::fs::File, master_b: tokio::fs::File) {
+/// let router = StdinRouter::new();
+/// router.register("a", master_a).await;
+/// router.register("b", master_b).await;
+/// router.spawn(tokio::io::stdin());
+///
+/// // Keystrokes now go to "a" until the host switches focus
+/// router.select("b").await;
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct StdinRouter {
+    state: Arc<Mutex<RouterState>>,
+}
+
+/// The registered targets and which one is active
+#[derive(Default)]
+struct RouterState {
+    /// Writable targets keyed by name
+    targets: HashMap<String, StdinTarget>,
+
+    /// The name of the target that receives input
+    active: Option<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl StdinRouter {
+    /// Creates a router with no targets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a target under `name`, replacing any target already registered with that name.
+    ///
+    /// The target becomes active if no other target is.
+    pub async fn register(
+        &self,
+        name: impl Into<String>,
+        target: impl AsyncWrite + Send + Unpin + 'static,
+    ) {
+        let name = name.into();
+        let mut state = self.state.lock().await;
+        if state.active.is_none() {
+            state.active = Some(name.clone());
+        }
+        state.targets.insert(name, Box::new(target));
+    }
+
+    /// Removes the target registered under `name`.
+    ///
+    /// If it was active, no target receives input until another one is selected.
+    pub async fn unregister(&self, name: &str) {
+        let mut state = self.state.lock().await;
+        state.targets.remove(name);
+        if state.active.as_deref() == Some(name) {
+            state.active = None;
+        }
+    }
+
+    /// Makes the target registered under `name` the one that receives input.
+    ///
+    /// Returns `false`, leaving the active target unchanged, if no such target is registered.
+    pub async fn select(&self, name: &str) -> bool {
+        let mut state = self.state.lock().await;
+        if !state.targets.contains_key(name) {
+            return false;
+        }
+        state.active = Some(name.to_string());
+        true
+    }
+
+    /// Returns the name of the active target, if any.
+    pub async fn active(&self) -> Option<String> {
+        self.state.lock().await.active.clone()
+    }
+
+    /// Spawns a task copying `source` to whichever target is active as each chunk arrives.
+    ///
+    /// Input that arrives while no target is active is discarded. A target that fails to
+    /// accept input is unregistered. The task ends when `source` reaches EOF.
+    pub fn spawn(&self, mut source: impl AsyncRead + Send + Unpin + 'static) -> JoinHandle<()> {
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; STDIN_CHUNK_SIZE];
+            loop {
+                let n = match source.read(&mut buf).await {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "failed to read stdin for routing");
+                        break;
+                    }
+                };
+
+                let mut state = state.lock().await;
+                let Some(active) = state.active.clone() else {
+                    continue;
+                };
+                let Some(target) = state.targets.get_mut(&active) else {
+                    continue;
+                };
+
+                let result = match target.write_all(&buf[..n]).await {
+                    Ok(()) => target.flush().await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    tracing::warn!(target_name = %active, error = %e, "failed to route stdin, removing target");
+                    state.targets.remove(&active);
+                    state.active = None;
+                }
+            }
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{duplex, DuplexStream};
+
+    use super::*;
+
+    async fn read_chunk(reader: &mut DuplexStream) -> Option<Vec<u8>> {
+        let mut buf = [0u8; 64];
+        let n = tokio::time::timeout(Duration::from_millis(100), reader.read(&mut buf))
+            .await
+            .ok()?
+            .ok()?;
+        Some(buf[..n].to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_stdin_router_only_feeds_active_target() {
+        let router = StdinRouter::new();
+        let (a_write, mut a_read) = duplex(64);
+        let (b_write, mut b_read) = duplex(64);
+        router.register("a", a_write).await;
+        router.register("b", b_write).await;
+
+        let (mut source, source_read) = duplex(64);
+        router.spawn(source_read);
+
+        // The first target registered is active
+        assert_eq!(router.active().await.as_deref(), Some("a"));
+        source.write_all(b"ls\n").await.unwrap();
+        assert_eq!(read_chunk(&mut a_read).await, Some(b"ls\n".to_vec()));
+        assert_eq!(read_chunk(&mut b_read).await, None);
+
+        assert!(router.select("b").await);
+        assert!(!router.select("missing").await);
+        source.write_all(b"pwd\n").await.unwrap();
+        assert_eq!(read_chunk(&mut b_read).await, Some(b"pwd\n".to_vec()));
+        assert_eq!(read_chunk(&mut a_read).await, None);
+    }
+}
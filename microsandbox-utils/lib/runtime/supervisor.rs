@@ -4,26 +4,42 @@ use nix::{
     unistd::Pid,
 };
 use std::{
-    os::unix::io::{AsRawFd, FromRawFd, IntoRawFd},
+    future::Future,
+    os::unix::{
+        io::{AsRawFd, FromRawFd, IntoRawFd},
+        process::ExitStatusExt,
+    },
     path::PathBuf,
     process::Stdio,
+    time::{Duration, Instant},
 };
 use tokio::{
     fs::{create_dir_all, File},
     io::unix::AsyncFd,
     process::Command,
     signal::unix::{signal, SignalKind},
+    sync::watch,
 };
 
 use crate::{
-    path::SUPERVISOR_LOG_FILENAME, term, ChildIo, MicrosandboxUtilsResult, ProcessMonitor,
-    RotatingLog,
+    path::SUPERVISOR_LOG_FILENAME, term, ChildIo, ExitStatus, MicrosandboxUtilsResult,
+    ProcessMonitor, RotatingLog,
 };
 
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
 
+/// Resource usage of the supervisor's reaped children, from `getrusage`.
+#[derive(Debug, Clone, Copy)]
+struct ChildrenUsage {
+    /// User and system CPU time of all children.
+    cpu_time: Duration,
+
+    /// Peak resident set size of the largest child, in bytes.
+    peak_rss_bytes: u64,
+}
+
 /// A supervisor that manages a child process and its logging.
 pub struct Supervisor<M>
 where
@@ -46,6 +62,9 @@ where
 
     /// The metrics monitor
     process_monitor: M,
+
+    /// Publishes the child's exit status once it has been reaped
+    exit_tx: watch::Sender<Option<ExitStatus>>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -82,6 +101,20 @@ where
             child_pid: None,
             log_dir: log_dir.into(),
             process_monitor,
+            exit_tx: watch::channel(None).0,
+        }
+    }
+
+    /// Returns a future that resolves with the child's exit status once it has exited.
+    ///
+    /// The future doesn't borrow the supervisor, so it can be awaited alongside
+    /// [`start`](Self::start). It resolves to `None` if the supervisor is dropped before the
+    /// child exits.
+    pub fn exited(&self) -> impl Future<Output = Option<ExitStatus>> + Send + 'static {
+        let mut exit_rx = self.exit_tx.subscribe();
+        async move {
+            let status = exit_rx.wait_for(Option::is_some).await.ok()?;
+            *status
         }
     }
 
@@ -184,6 +217,9 @@ where
 
         let child_pid = child.id().expect("failed to get child process id");
         self.child_pid = Some(child_pid);
+        let started_at = Instant::now();
+        let oom_kills_before = read_oom_kill_count().await;
+        let usage_before = children_usage();
 
         // Start monitoring
         self.process_monitor.start(child_pid, child_io).await?;
@@ -193,9 +229,10 @@ where
         let mut sigint = signal(SignalKind::interrupt())?;
 
         // Wait for either child process to exit or signal to be received
-        tokio::select! {
+        let wait_status = tokio::select! {
             status = child.wait() => {
-                // Stop process monitoring
+                // Stop process monitoring, without signalling the reaped child's PID
+                self.process_monitor.on_reaped();
                 self.process_monitor.stop().await?;
 
                 tracing::info!("child process {} exited", child_pid);
@@ -222,6 +259,8 @@ where
                         status
                     );
                 }
+
+                status.ok()
             }
             _ = sigterm.recv() => {
                 // Stop process monitoring
@@ -240,11 +279,15 @@ where
                 }
 
                 // Wait for child to exit after sending signal
-                if let Err(e) = child.wait().await {
-                    tracing::error!(
-                        "error waiting for child after SIGTERM: {}",
-                        e
-                    );
+                match child.wait().await {
+                    Ok(status) => Some(status),
+                    Err(e) => {
+                        tracing::error!(
+                            "error waiting for child after SIGTERM: {}",
+                            e
+                        );
+                        None
+                    }
                 }
             }
             _ = sigint.recv() => {
@@ -264,17 +307,139 @@ where
                 }
 
                 // Wait for child to exit after sending signal
-                if let Err(e) = child.wait().await {
-                    tracing::error!(
-                        "error waiting for child after SIGINT: {}",
-                        e
-                    );
+                match child.wait().await {
+                    Ok(status) => Some(status),
+                    Err(e) => {
+                        tracing::error!(
+                            "error waiting for child after SIGINT: {}",
+                            e
+                        );
+                        None
+                    }
                 }
             }
-        }
+        };
 
         self.child_pid = None;
 
+        // Publish how the child exited
+        let signal = wait_status.and_then(|status| status.signal());
+        let oom = match (oom_kills_before, read_oom_kill_count().await) {
+            (Some(before), Some(after)) => signal == Some(libc::SIGKILL) && after > before,
+            _ => false,
+        };
+        let usage = children_usage();
+        let exit_status = ExitStatus {
+            code: wait_status.and_then(|status| status.code()),
+            signal,
+            oom,
+            duration: started_at.elapsed(),
+            peak_rss_bytes: usage.map(|usage| usage.peak_rss_bytes),
+            cpu_time: usage
+                .zip(usage_before)
+                .map(|(after, before)| after.cpu_time.saturating_sub(before.cpu_time)),
+        };
+        if exit_status.oom {
+            tracing::warn!("child process {} was killed by the OOM killer", child_pid);
+        }
+
+        self.process_monitor.on_exit(&exit_status).await?;
+        self.exit_tx.send_replace(Some(exit_status));
+
         Ok(())
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Reads the resource usage of the supervisor's reaped children.
+///
+/// CPU time adds up over all children, so the child's own is the difference from a reading
+/// taken before it was spawned. The peak resident set size is that of the largest child,
+/// which is the supervised process as it is the only child.
+fn children_usage() -> Option<ChildrenUsage> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage only writes to the struct it is given
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: getrusage succeeded, so it filled in the struct
+    let usage = unsafe { usage.assume_init() };
+
+    let timeval = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+
+    // Linux reports the peak resident set size in KiB, macOS in bytes
+    let max_rss = usage.ru_maxrss as u64;
+    let peak_rss_bytes = if cfg!(target_os = "macos") {
+        max_rss
+    } else {
+        max_rss * 1024
+    };
+
+    Some(ChildrenUsage {
+        cpu_time: timeval(usage.ru_utime) + timeval(usage.ru_stime),
+        peak_rss_bytes,
+    })
+}
+
+/// Reads how many times the OOM killer has fired in the supervisor's cgroup.
+///
+/// The child inherits the supervisor's cgroup, so a rise in this count over the child's lifetime
+/// means the child was OOM-killed. Returns `None` where cgroup v2 isn't available.
+async fn read_oom_kill_count() -> Option<u64> {
+    let cgroups = tokio::fs::read_to_string("/proc/self/cgroup").await.ok()?;
+    let cgroup = parse_cgroup_v2_path(&cgroups)?;
+    let events = tokio::fs::read_to_string(format!("/sys/fs/cgroup{}/memory.events", cgroup))
+        .await
+        .ok()?;
+    parse_oom_kill_count(&events)
+}
+
+/// Extracts the cgroup v2 path from the contents of `/proc/<pid>/cgroup`
+fn parse_cgroup_v2_path(cgroups: &str) -> Option<&str> {
+    cgroups
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| path.trim_end_matches('/'))
+}
+
+/// Extracts the `oom_kill` counter from the contents of a cgroup's `memory.events`
+fn parse_oom_kill_count(events: &str) -> Option<u64> {
+    events.lines().find_map(|line| {
+        let (key, value) = line.split_once(' ')?;
+        (key == "oom_kill").then(|| value.trim().parse().ok())?
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cgroup_v2_path() {
+        assert_eq!(
+            parse_cgroup_v2_path("0::/user.slice/session-1.scope\n"),
+            Some("/user.slice/session-1.scope")
+        );
+        assert_eq!(
+            parse_cgroup_v2_path("12:memory:/docker/abc\n0::/\n"),
+            Some("")
+        );
+        assert_eq!(parse_cgroup_v2_path("4:cpu:/docker/abc\n"), None);
+    }
+
+    #[test]
+    fn test_parse_oom_kill_count() {
+        let events = "low 0\nhigh 0\nmax 3\noom 2\noom_kill 1\noom_group_kill 0\n";
+        assert_eq!(parse_oom_kill_count(events), Some(1));
+        assert_eq!(parse_oom_kill_count("low 0\n"), None);
+    }
+}
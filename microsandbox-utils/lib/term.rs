@@ -1,7 +1,19 @@
 //! Module containing terminal utilities
 
 use indicatif::{MultiProgress, MultiProgressAlignment, ProgressBar, ProgressStyle};
-use std::sync::{Arc, LazyLock};
+use nix::sys::{
+    signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal},
+    termios::Termios,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, LazyLock, Once, OnceLock,
+    },
+    time::Duration,
+};
+
+use crate::DEFAULT_TERMINAL_RESTORE_GRACE;
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -29,6 +41,31 @@ pub static TICK_STRINGS: LazyLock<[&str; 11]> =
 /// The error tick strings for CLI visualizations
 pub static ERROR_TICK_STRINGS: LazyLock<[&str; 2]> = LazyLock::new(|| ["⠏", &ERROR_MARK]);
 
+/// Catchable signals that end the process without running its normal cleanup.
+///
+/// SIGINT and SIGTERM aren't included since the supervisor already handles them and restores
+/// the terminal on the way out.
+const TERMINAL_RESTORE_SIGNALS: [Signal; 3] = [Signal::SIGHUP, Signal::SIGQUIT, Signal::SIGABRT];
+
+/// The stdin terminal settings to restore if the process exits abnormally
+static SAVED_TERMIOS: OnceLock<libc::termios> = OnceLock::new();
+
+/// Whether the saved terminal settings still need to be restored
+static TERMIOS_RESTORE_ARMED: AtomicBool = AtomicBool::new(false);
+
+/// The actions [`TERMINAL_RESTORE_SIGNALS`] had before the restore handlers replaced them
+static PREVIOUS_SIGNAL_ACTIONS: OnceLock<[Option<SigAction>; 3]> = OnceLock::new();
+
+/// Guards the one-time installation of the exit hook and signal handlers
+static INSTALL_TERMINAL_RESTORE: Once = Once::new();
+
+/// How long, in milliseconds, the abnormal exit restore waits for queued output to drain
+static TERMINAL_RESTORE_GRACE_MS: AtomicU64 =
+    AtomicU64::new(DEFAULT_TERMINAL_RESTORE_GRACE.as_millis() as u64);
+
+/// How often queued terminal output is checked during the restore grace, in milliseconds
+const TERMINAL_DRAIN_POLL_MS: u64 = 10;
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -119,3 +156,188 @@ pub fn finish_with_error(pb: &ProgressBar) {
     pb.set_style(style);
     pb.finish();
 }
+
+/// Registers stdin's original terminal settings to be restored if the process exits abnormally.
+///
+/// Call this before putting the terminal in raw mode. Until [`disarm_terminal_restore`] is
+/// called, the settings are restored by an `atexit` hook (which also covers
+/// `std::process::exit`, where destructors don't run) and by handlers for SIGHUP, SIGQUIT and
+/// SIGABRT. These then put back the action the signal had before and re-raise it, so a
+/// handler installed earlier still runs, and the default action still ends the process. A
+/// signal the process ignores is left ignored.
+///
+/// Before restoring, output still queued to the terminal is given up to the
+/// [grace](set_terminal_restore_grace) to be written, so the last output isn't cut off or
+/// shown with the restored settings.
+///
+/// This is best effort: SIGKILL can't be caught, so a process killed with it still leaves the
+/// terminal in raw mode, and `reset` has to be run by hand.
+///
+/// Only the first settings registered in a process are kept, as they are the ones the user's
+/// shell handed over.
+pub fn arm_terminal_restore(original: &Termios) {
+    let _ = SAVED_TERMIOS.set(libc::termios::from(original.clone()));
+    TERMIOS_RESTORE_ARMED.store(true, Ordering::SeqCst);
+
+    INSTALL_TERMINAL_RESTORE.call_once(|| {
+        if unsafe { libc::atexit(restore_terminal_at_exit) } != 0 {
+            tracing::warn!("failed to register terminal restore exit hook");
+        }
+
+        let action = SigAction::new(
+            SigHandler::Handler(restore_terminal_on_signal),
+            SaFlags::SA_RESETHAND,
+            SigSet::empty(),
+        );
+        let mut previous = [None; TERMINAL_RESTORE_SIGNALS.len()];
+        for (signal, previous) in TERMINAL_RESTORE_SIGNALS.into_iter().zip(&mut previous) {
+            match unsafe { sigaction(signal, &action) } {
+                // An ignored signal doesn't end the process, so there is nothing to restore
+                Ok(old) if old.handler() == SigHandler::SigIgn => {
+                    let _ = unsafe { sigaction(signal, &old) };
+                }
+                Ok(old) => *previous = Some(old),
+                Err(e) => {
+                    tracing::warn!(%signal, error = %e, "failed to install terminal restore handler")
+                }
+            }
+        }
+        let _ = PREVIOUS_SIGNAL_ACTIONS.set(previous);
+    });
+}
+
+/// Marks the terminal as restored, so the abnormal exit hooks leave it alone.
+pub fn disarm_terminal_restore() {
+    TERMIOS_RESTORE_ARMED.store(false, Ordering::SeqCst);
+}
+
+/// Sets how long restoring the terminal on an abnormal exit waits for output queued to the
+/// terminal to be written first.
+///
+/// The restore goes ahead as soon as nothing is queued, so the grace only delays an exit
+/// whose output is stuck, e.g. behind a suspended terminal. Zero restores right away.
+/// Defaults to [`DEFAULT_TERMINAL_RESTORE_GRACE`].
+pub fn set_terminal_restore_grace(grace: Duration) {
+    TERMINAL_RESTORE_GRACE_MS.store(grace.as_millis() as u64, Ordering::SeqCst);
+}
+
+/// Restores the saved terminal settings if they are still armed.
+///
+/// Only uses async-signal-safe operations, so it can run inside a signal handler.
+fn restore_saved_terminal() {
+    if !TERMIOS_RESTORE_ARMED.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    wait_for_terminal_drain();
+    if let Some(termios) = SAVED_TERMIOS.get() {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) };
+    }
+}
+
+/// Waits up to the restore grace for output queued to the terminal on stdout to be written.
+///
+/// Only uses async-signal-safe operations, so it can run inside a signal handler.
+fn wait_for_terminal_drain() {
+    let poll = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: (TERMINAL_DRAIN_POLL_MS * 1_000_000) as libc::c_long,
+    };
+
+    let grace_ms = TERMINAL_RESTORE_GRACE_MS.load(Ordering::SeqCst);
+    let mut waited_ms = 0;
+    while waited_ms < grace_ms {
+        // Fails for a stdout that isn't a terminal, which has nothing to drain
+        let mut queued: libc::c_int = 0;
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCOUTQ, &mut queued) } != 0
+            || queued == 0
+        {
+            return;
+        }
+
+        unsafe { libc::nanosleep(&poll, std::ptr::null_mut()) };
+        waited_ms += TERMINAL_DRAIN_POLL_MS;
+    }
+}
+
+extern "C" fn restore_terminal_at_exit() {
+    restore_saved_terminal();
+}
+
+extern "C" fn restore_terminal_on_signal(signal: libc::c_int) {
+    restore_saved_terminal();
+
+    // Put back the signal's previous action, so the re-raised signal runs it once this handler
+    // returns. Without one, SA_RESETHAND has already put back the default action
+    let previous = TERMINAL_RESTORE_SIGNALS
+        .iter()
+        .zip(PREVIOUS_SIGNAL_ACTIONS.get().into_iter().flatten())
+        .find(|(restore_signal, _)| **restore_signal as libc::c_int == signal);
+    if let Some((restore_signal, Some(previous))) = previous {
+        let _ = unsafe { sigaction(*restore_signal, previous) };
+    }
+
+    unsafe { libc::raise(signal) };
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use nix::sys::termios;
+
+    use super::*;
+
+    static HANGUP_HANDLED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn handle_hangup(_: libc::c_int) {
+        HANGUP_HANDLED.store(true, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_restore_saved_terminal_only_runs_while_armed() {
+        // A handler the process had before arming
+        let hangup = SigAction::new(
+            SigHandler::Handler(handle_hangup),
+            SaFlags::empty(),
+            SigSet::empty(),
+        );
+        unsafe { sigaction(Signal::SIGHUP, &hangup) }.unwrap();
+
+        // Restoring stdin's current settings, when it is a terminal, leaves it as it was
+        let original = termios::tcgetattr(std::io::stdin())
+            .unwrap_or_else(|_| Termios::from(unsafe { std::mem::zeroed::<libc::termios>() }));
+        arm_terminal_restore(&original);
+        assert!(TERMIOS_RESTORE_ARMED.load(Ordering::SeqCst));
+
+        // The signal restores the terminal, then still reaches the earlier handler
+        unsafe { libc::raise(libc::SIGHUP) };
+        assert!(!TERMIOS_RESTORE_ARMED.load(Ordering::SeqCst));
+        assert!(HANGUP_HANDLED.load(Ordering::SeqCst));
+
+        arm_terminal_restore(&original);
+        assert!(TERMIOS_RESTORE_ARMED.load(Ordering::SeqCst));
+
+        // A restore disarms, so the exit hook doesn't restore a second time
+        restore_saved_terminal();
+        assert!(!TERMIOS_RESTORE_ARMED.load(Ordering::SeqCst));
+
+        arm_terminal_restore(&original);
+        disarm_terminal_restore();
+        assert!(!TERMIOS_RESTORE_ARMED.load(Ordering::SeqCst));
+        restore_saved_terminal();
+        assert!(!TERMIOS_RESTORE_ARMED.load(Ordering::SeqCst));
+
+        // With nothing queued to the terminal, the grace doesn't hold up the restore
+        set_terminal_restore_grace(Duration::from_secs(10));
+        arm_terminal_restore(&original);
+        let started = Instant::now();
+        restore_saved_terminal();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        set_terminal_restore_grace(DEFAULT_TERMINAL_RESTORE_GRACE);
+    }
+}
@@ -157,12 +157,23 @@ export abstract class BaseSandbox {
         );
       }
 
-      // Check the result message - it might indicate the sandbox is still initializing
+      // Check the result for warnings - the sandbox might still be initializing
       const result = responseData.result;
-      if (typeof result === "string" && result.includes("timed out waiting")) {
-        // Server timed out but still started the sandbox
-        // We'll log a warning but still consider it started
-        console.warn(`Sandbox start warning: ${result}`);
+      let startWarnings: string[] = [];
+      if (result && typeof result === "object") {
+        startWarnings = (result.warnings ?? []).map(
+          (warning: { message: string }) => warning.message
+        );
+      } else if (
+        typeof result === "string" &&
+        result.includes("timed out waiting")
+      ) {
+        // Older servers only report the warning in the message
+        startWarnings = [result];
+      }
+      // The server still started the sandbox, so we consider it started
+      for (const warning of startWarnings) {
+        console.warn(`Sandbox start warning: ${warning}`);
       }
 
       this._isStarted = true;
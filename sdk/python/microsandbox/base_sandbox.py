@@ -180,14 +180,21 @@ class BaseSandbox(ABC):
                         f"Failed to start sandbox: {response_data['error']['message']}"
                     )
 
-                # Check the result message - it might indicate the sandbox is still initializing
+                # Check the result for warnings - the sandbox might still be initializing
                 result = response_data.get("result", "")
-                if isinstance(result, str) and "timed out waiting" in result:
-                    # Server timed out but still started the sandbox
-                    # We'll raise a warning but still consider it started
+                if isinstance(result, dict):
+                    start_warnings = [w["message"] for w in result.get("warnings", [])]
+                elif isinstance(result, str) and "timed out waiting" in result:
+                    # Older servers only report the warning in the message
+                    start_warnings = [result]
+                else:
+                    start_warnings = []
+                if start_warnings:
+                    # The server still started the sandbox, so we consider it started
                     import warnings
 
-                    warnings.warn(f"Sandbox start warning: {result}")
+                    for warning in start_warnings:
+                        warnings.warn(f"Sandbox start warning: {warning}")
 
                 self._is_started = True
         except aiohttp.ClientError as e:
@@ -0,0 +1,164 @@
+//! Authentication schemes for requests to the Microsandbox server
+
+use std::fmt;
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use sha2::Sha256;
+
+use crate::SandboxError;
+
+/// A function that adds custom authentication headers to a request
+pub type AuthFn = Arc<dyn Fn(&mut HeaderMap) + Send + Sync>;
+
+/// How requests to the Microsandbox server are authenticated
+///
+/// Set it in [`SandboxOptions`](crate::SandboxOptions) when the server sits behind a gateway
+/// that expects something other than a Bearer token. An API key is the same as
+/// `Auth::Bearer`.
+#[derive(Clone, Default)]
+pub enum Auth {
+    /// Send no authentication headers
+    #[default]
+    None,
+
+    /// Send `Authorization: Bearer <token>`
+    Bearer(String),
+
+    /// Sign each request body with HMAC-SHA256, sending the hex-encoded signature in `header`
+    Hmac {
+        /// Shared secret used to sign requests
+        key: String,
+
+        /// Name of the header carrying the signature
+        header: String,
+    },
+
+    /// Add headers with a custom function, called once per request
+    Custom(AuthFn),
+}
+
+impl Auth {
+    /// Short name of the scheme, safe to log
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Auth::None => "none",
+            Auth::Bearer(_) => "bearer",
+            Auth::Hmac { .. } => "hmac",
+            Auth::Custom(_) => "custom",
+        }
+    }
+
+    /// The secret the scheme sends or signs with, if it has one
+    pub(crate) fn secret(&self) -> Option<&str> {
+        match self {
+            Auth::Bearer(token) => Some(token),
+            Auth::Hmac { key, .. } => Some(key),
+            Auth::None | Auth::Custom(_) => None,
+        }
+    }
+
+    /// Check that the scheme can produce valid headers
+    pub(crate) fn validate(&self) -> Result<(), SandboxError> {
+        match self {
+            Auth::Bearer(token) => {
+                if token.is_empty() {
+                    return Err(SandboxError::InvalidInput(
+                        "bearer token must not be empty".to_string(),
+                    ));
+                }
+                HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|_| {
+                    SandboxError::InvalidInput(
+                        "bearer token contains characters not allowed in a header".to_string(),
+                    )
+                })?;
+            }
+            Auth::Hmac { key, header } => {
+                if key.is_empty() {
+                    return Err(SandboxError::InvalidInput(
+                        "HMAC key must not be empty".to_string(),
+                    ));
+                }
+                HeaderName::from_bytes(header.as_bytes()).map_err(|_| {
+                    SandboxError::InvalidInput(format!(
+                        "'{}' is not a valid header name for the HMAC signature",
+                        header
+                    ))
+                })?;
+            }
+            Auth::None | Auth::Custom(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Build the headers for a JSON-RPC request to the server with the given body
+    ///
+    /// This is the one place authentication is applied to requests.
+    pub(crate) fn request_headers(&self, body: &[u8]) -> Result<HeaderMap, SandboxError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        self.apply(&mut headers, body)?;
+        Ok(headers)
+    }
+
+    /// Add the scheme's headers for a request with the given body
+    fn apply(&self, headers: &mut HeaderMap, body: &[u8]) -> Result<(), SandboxError> {
+        match self {
+            Auth::None => {}
+            Auth::Bearer(token) => {
+                let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                    .map_err(|e| SandboxError::InvalidInput(e.to_string()))?;
+                headers.insert(AUTHORIZATION, value);
+            }
+            Auth::Hmac { key, header } => {
+                let name = HeaderName::from_bytes(header.as_bytes())
+                    .map_err(|e| SandboxError::InvalidInput(e.to_string()))?;
+                let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+                    .map_err(|e| SandboxError::InvalidInput(e.to_string()))?;
+                mac.update(body);
+                let signature = hex::encode(mac.finalize().into_bytes());
+                headers.insert(name, HeaderValue::from_str(&signature).unwrap());
+            }
+            Auth::Custom(add_headers) => add_headers(headers),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auth::None => write!(f, "None"),
+            Auth::Bearer(_) => f.debug_tuple("Bearer").field(&"<redacted>").finish(),
+            Auth::Hmac { header, .. } => f
+                .debug_struct("Hmac")
+                .field("key", &"<redacted>")
+                .field("header", header)
+                .finish(),
+            Auth::Custom(_) => f.debug_tuple("Custom").field(&"<fn>").finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_signs_request_body() {
+        let auth = Auth::Hmac {
+            key: "key".to_string(),
+            header: "X-Signature".to_string(),
+        };
+        let headers = auth
+            .request_headers(b"The quick brown fox jumps over the lazy dog")
+            .unwrap();
+
+        assert_eq!(
+            headers["x-signature"],
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+        assert!(!headers.contains_key(AUTHORIZATION));
+    }
+}
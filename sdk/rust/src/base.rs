@@ -1,15 +1,36 @@
 use std::collections::HashMap;
 use std::env;
 use std::error::Error;
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 
 use dotenv::dotenv;
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::header::HeaderMap;
 use serde::Deserialize;
 use serde_json::{json, Value};
 use uuid::Uuid;
 
-use crate::{Execution, SandboxError, SandboxOptions};
+use crate::files::validate_inline_files;
+use crate::hostname::validate_hostname;
+use crate::retry::{default_retry_predicate, RetryClassifier};
+use crate::tls;
+use crate::{
+    Auth, Execution, ExecutionResult, InlineFile, Language, LanguageInfo, ProbeSpec,
+    RequestLogging, RetryBudget, RetryPolicy, RpcError, SandboxError, SandboxGuard, SandboxOptions,
+    SandboxState, SandboxWarning, SecurityProfile, StartOutcome, StartPhase, Ulimit,
+    EXECUTION_LIMIT_CODE, SANDBOX_NOT_FOUND_CODE, WARNING_CPUS_ROUNDED,
+};
+
+/// Default maximum size of a serialized request body, matching the server's body limit
+const DEFAULT_MAX_REQUEST_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+/// How much longer the client waits than the server-side execution timeout
+const EXECUTION_TIMEOUT_MARGIN: Duration = Duration::from_secs(30);
+
+/// Capability a server would report in `server.info` if it could apply fractional CPU counts
+///
+/// No microsandbox server reports it: the server takes `cpus` as a whole number.
+const FRACTIONAL_CPUS_CAPABILITY: &str = "fractional_cpus";
 
 /// Base implementation for sandbox types
 pub struct SandboxBase {
@@ -22,14 +43,90 @@ pub struct SandboxBase {
     /// Name of the sandbox
     pub(crate) name: String,
 
-    /// API key for Microsandbox server authentication
-    pub(crate) api_key: Option<String>,
+    /// Authentication scheme for the Microsandbox server
+    pub(crate) auth: Auth,
+
+    /// Idempotency key sent with `sandbox.start`
+    pub(crate) idempotency_key: Option<String>,
+
+    /// Client-side cap on running sandboxes in the namespace
+    pub(crate) max_sandboxes_per_namespace: Option<usize>,
+
+    /// Resource limits applied in the guest on start
+    pub(crate) ulimits: Vec<Ulimit>,
+
+    /// Maximum size in bytes of a serialized request body
+    pub(crate) max_request_body_size: usize,
+
+    /// Code run once right after the sandbox starts
+    pub(crate) init_code: Option<(Language, String)>,
+
+    /// Whether the init code has run since the sandbox was started
+    pub(crate) init_ran: bool,
+
+    /// Hostname of the guest
+    pub(crate) hostname: Option<String>,
+
+    /// OOM score adjustment of the sandbox process
+    pub(crate) oom_score_adj: Option<i32>,
+
+    /// Maximum number of executions the server runs in the sandbox at once
+    pub(crate) max_concurrent_executions: Option<usize>,
+
+    /// Probe that tells when the application in the sandbox is ready
+    pub(crate) readiness_probe: Option<ProbeSpec>,
+
+    /// Request budget shared with other sandboxes
+    pub(crate) retry_budget: Option<Arc<RetryBudget>>,
+
+    /// How idempotent requests are retried after transient failures
+    pub(crate) retry_policy: Option<RetryPolicy>,
+
+    /// Decides which failures of idempotent requests are retried
+    pub(crate) retry_predicate: Option<RetryClassifier>,
 
-    /// HTTP client for API requests
-    pub(crate) client: reqwest::Client,
+    /// Default timeout for executions that don't set their own
+    pub(crate) execution_timeout: Option<Duration>,
+
+    /// Whether to return the output produced before a timeout instead of failing
+    pub(crate) partial_output_on_timeout: bool,
+
+    /// Time limit for pulling the image during start
+    pub(crate) pull_timeout: Option<Duration>,
+
+    /// Time limit for booting the sandbox during start
+    pub(crate) boot_timeout: Option<Duration>,
+
+    /// Time limit for the sandbox to become ready during start
+    pub(crate) ready_timeout: Option<Duration>,
+
+    /// Debug logging of request and response bodies
+    pub(crate) request_logging: Option<RequestLogging>,
+
+    /// Capabilities dropped and seccomp profile applied in the guest on start
+    pub(crate) security: Option<SecurityProfile>,
+
+    /// Files written into the guest right after start
+    pub(crate) files: Vec<InlineFile>,
+
+    /// Languages reported by the server, fetched on first use
+    pub(crate) supported_languages: OnceLock<Vec<LanguageInfo>>,
+
+    /// HTTP client for API requests, whose pooled connections every request reuses, or why
+    /// it couldn't be configured with the TLS options
+    pub(crate) client: Result<reqwest::Client, String>,
 
     /// Whether the sandbox has been started
     pub(crate) is_started: bool,
+
+    /// Whether the sandbox is paused, as the server last reported on a start, pause or resume
+    pub(crate) is_paused: bool,
+
+    /// Outcome of the most recent successful start
+    pub(crate) start_outcome: Option<StartOutcome>,
+
+    /// Whether a started sandbox is stopped when this handle is dropped
+    pub(crate) stop_on_drop: bool,
 }
 
 impl SandboxBase {
@@ -48,20 +145,31 @@ impl SandboxBase {
             .or_else(|| env::var("MSB_SERVER_URL").ok())
             .unwrap_or_else(|| "http://127.0.0.1:5555".to_string());
 
-        // Get API key from options or environment
-        let api_key = options
-            .api_key
-            .clone()
-            .or_else(|| env::var("MSB_API_KEY").ok());
-
-        // Generate a random name if not provided
-        let name = options.name.clone().unwrap_or_else(|| {
-            format!(
-                "sandbox-{}",
-                Uuid::new_v4().to_string().split('-').next().unwrap()
-            )
+        // Use the auth scheme from options, or fall back to an API key from options or environment
+        let auth = options.auth.clone().unwrap_or_else(|| {
+            options
+                .api_key
+                .clone()
+                .or_else(|| env::var("MSB_API_KEY").ok())
+                .map_or(Auth::None, Auth::Bearer)
         });
 
+        // Derive the name from the idempotency key, keeping the whole UUID so distinct keys can't
+        // share a sandbox, or generate a random one if neither is provided
+        let name = options
+            .name
+            .clone()
+            .unwrap_or_else(|| match &options.idempotency_key {
+                Some(key) => format!(
+                    "sandbox-{}",
+                    Uuid::new_v5(&Uuid::NAMESPACE_OID, key.as_bytes()).simple()
+                ),
+                None => format!(
+                    "sandbox-{}",
+                    Uuid::new_v4().to_string().split('-').next().unwrap()
+                ),
+            });
+
         Self {
             server_url,
             namespace: options
@@ -69,164 +177,640 @@ impl SandboxBase {
                 .clone()
                 .unwrap_or_else(|| "default".to_string()),
             name,
-            api_key,
-            client: reqwest::Client::new(),
+            auth,
+            idempotency_key: options.idempotency_key.clone(),
+            max_sandboxes_per_namespace: options.max_sandboxes_per_namespace,
+            ulimits: options.ulimits.clone(),
+            max_request_body_size: options
+                .max_request_body_size
+                .unwrap_or(DEFAULT_MAX_REQUEST_BODY_SIZE),
+            init_code: options.init_code.clone(),
+            init_ran: false,
+            hostname: options.hostname.clone(),
+            oom_score_adj: options.oom_score_adj,
+            max_concurrent_executions: options.max_concurrent_executions,
+            readiness_probe: options.readiness_probe.clone(),
+            retry_budget: options.retry_budget.clone(),
+            retry_policy: options.retry_policy.clone(),
+            retry_predicate: options.retry_predicate.clone(),
+            execution_timeout: options.execution_timeout,
+            partial_output_on_timeout: options.partial_output_on_timeout,
+            pull_timeout: options.pull_timeout,
+            boot_timeout: options.boot_timeout,
+            ready_timeout: options.ready_timeout,
+            request_logging: options.request_logging.clone(),
+            security: options.security.clone(),
+            files: options.files.clone(),
+            supported_languages: OnceLock::new(),
+            client: build_client(options).map_err(|e| e.to_string()),
             is_started: false,
+            is_paused: false,
+            start_outcome: None,
+            stop_on_drop: options.stop_on_drop,
         }
     }
 
-    /// Make a JSON-RPC request to the Microsandbox server
-    pub(crate) async fn make_request<T: for<'de> Deserialize<'de>>(
+    /// Get the HTTP client for requests to the server
+    ///
+    /// Fails with [`SandboxError::InvalidInput`] if the client certificate or CA bundle in the
+    /// options couldn't be loaded.
+    pub(crate) fn http_client(&self) -> Result<&reqwest::Client, Box<dyn Error + Send + Sync>> {
+        self.client
+            .as_ref()
+            .map_err(|e| Box::new(SandboxError::InvalidInput(e.clone())) as _)
+    }
+
+    /// Send a JSON-RPC request to the Microsandbox server, returning the unread response
+    ///
+    /// Fails if the server responds with a non-success status.
+    pub(crate) async fn send_request(
         &self,
         method: &str,
         params: Value,
-    ) -> Result<T, Box<dyn Error + Send + Sync>> {
-        // Create headers
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-
-        if let Some(api_key) = &self.api_key {
-            headers.insert(
-                AUTHORIZATION,
-                HeaderValue::from_str(&format!("Bearer {}", api_key))?,
-            );
-        }
+    ) -> Result<reqwest::Response, Box<dyn Error + Send + Sync>> {
+        self.send_request_with_timeout(method, params, None).await
+    }
 
-        // Create request body
-        let request_data = json!({
-            "jsonrpc": "2.0",
-            "method": method,
-            "params": params,
-            "id": Uuid::new_v4().to_string(),
-        });
+    /// Send a JSON-RPC request that fails with [`SandboxError::Timeout`] if it isn't answered
+    /// within `timeout`
+    pub(crate) async fn send_request_with_timeout(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response, Box<dyn Error + Send + Sync>> {
+        self.send_attempt(method, params, timeout)
+            .await
+            .map_err(|failed| failed.error)
+    }
+
+    /// Send a JSON-RPC request once, telling whether a failure may be retried
+    async fn send_attempt(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response, FailedAttempt> {
+        let body = self
+            .encode_request(method, params)
+            .map_err(FailedAttempt::fatal)?;
+        self.send_body(method, body, timeout).await
+    }
+
+    /// Send an encoded JSON-RPC request body once, `method` naming it in logs and errors
+    pub(crate) async fn send_body(
+        &self,
+        method: &str,
+        body: Vec<u8>,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response, FailedAttempt> {
+        // Create request headers
+        let headers = self
+            .auth
+            .request_headers(&body)
+            .map_err(|e| FailedAttempt::fatal(e.into()))?;
+        self.log_request(method, &body, &headers);
 
         // Send request
-        let response = self
-            .client
+        let client = self.http_client().map_err(FailedAttempt::fatal)?;
+        self.acquire_budget().await;
+        let mut request = client
             .post(&format!("{}/api/v1/rpc", self.server_url))
             .headers(headers)
-            .json(&request_data)
-            .send()
-            .await?;
+            .body(body);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) if e.is_timeout() => {
+                return Err(FailedAttempt::fatal(Box::new(SandboxError::Timeout(
+                    format!(
+                        "{} request timed out after {:?}",
+                        method,
+                        timeout.unwrap_or_default()
+                    ),
+                ))))
+            }
+            Err(e) => {
+                return Err(FailedAttempt::sent(Box::new(SandboxError::HttpError(
+                    e.to_string(),
+                ))))
+            }
+        };
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(Box::new(SandboxError::RequestFailed(error_text)));
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.map_err(|e| {
+                FailedAttempt::sent(Box::new(SandboxError::HttpError(e.to_string())))
+            })?;
+            return Err(FailedAttempt::sent(Box::new(SandboxError::HttpStatus {
+                status: status.as_u16(),
+                message,
+            })));
         }
 
+        Ok(response)
+    }
+
+    /// Make a JSON-RPC request once, returning its result
+    async fn call_attempt(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Option<Duration>,
+    ) -> Result<Value, FailedAttempt> {
+        let Some(logging) = &self.request_logging else {
+            return self.exchange(method, params, timeout).await;
+        };
+
+        let started = std::time::Instant::now();
+        let result = self.exchange(method, params.clone(), timeout).await;
+        let error = result.as_ref().err().map(|failed| failed.error.as_ref());
+        logging.log_finished(method, params, started.elapsed(), error);
+        result
+    }
+
+    /// Send a JSON-RPC request once and read its result
+    async fn exchange(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Option<Duration>,
+    ) -> Result<Value, FailedAttempt> {
+        let response = self.send_attempt(method, params, timeout).await?;
+
         // Parse response
-        let response_data: Value = response.json().await?;
+        let response_data = read_response_json(response)
+            .await
+            .map_err(FailedAttempt::sent)?;
+        self.log_response(method, &response_data);
 
         if let Some(error) = response_data.get("error") {
-            let error_msg = error
-                .get("message")
-                .and_then(|m| m.as_str())
-                .unwrap_or("Unknown error")
-                .to_string();
-            return Err(Box::new(SandboxError::ServerError(error_msg)));
+            return Err(FailedAttempt::sent(Box::new(rpc_error(error))));
+        }
+
+        Ok(response_data.get("result").cloned().unwrap_or(Value::Null))
+    }
+
+    /// Make a JSON-RPC request to the Microsandbox server
+    pub(crate) async fn make_request<T: for<'de> Deserialize<'de>>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        self.make_request_with_timeout(method, params, None).await
+    }
+
+    /// Make a JSON-RPC request that fails with [`SandboxError::Timeout`] if it isn't answered
+    /// within `timeout`
+    pub(crate) async fn make_request_with_timeout<T: for<'de> Deserialize<'de>>(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Option<Duration>,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let result = self.call_with_retries(method, params, timeout).await?;
+
+        // Deserialize result
+        Ok(serde_json::from_value(result)?)
+    }
+
+    /// Log a request about to be sent, if request logging is enabled
+    pub(crate) fn log_request(&self, method: &str, body: &[u8], headers: &HeaderMap) {
+        if let Some(logging) = &self.request_logging {
+            logging.log_request(method, body, headers);
+        }
+    }
+
+    /// Log the response to a request, if request logging is enabled
+    pub(crate) fn log_response(&self, method: &str, response: &Value) {
+        if let Some(logging) = &self.request_logging {
+            logging.log_response(method, response);
+        }
+    }
+
+    /// Make a JSON-RPC request, retrying failures under the retry policy
+    ///
+    /// Only idempotent methods are retried, only for failures the retry predicate accepts,
+    /// and never past the request's timeout or the policy's deadline. A request that still
+    /// fails after being retried is reported as [`SandboxError::RetriesExhausted`].
+    async fn call_with_retries(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Option<Duration>,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let policy = match &self.retry_policy {
+            Some(policy) if policy.applies_to(method) => policy,
+            _ => {
+                return self
+                    .call_attempt(method, params, timeout)
+                    .await
+                    .map_err(|failed| failed.error)
+            }
+        };
+
+        let started = tokio::time::Instant::now();
+        let deadline = match (timeout, policy.deadline) {
+            (Some(timeout), Some(deadline)) => Some(timeout.min(deadline)),
+            (timeout, deadline) => timeout.or(deadline),
+        };
+
+        let mut retries = 0;
+        loop {
+            let remaining = deadline.map(|deadline| deadline.saturating_sub(started.elapsed()));
+            let failed = match self.call_attempt(method, params.clone(), remaining).await {
+                Ok(result) => return Ok(result),
+                Err(failed) => failed,
+            };
+
+            let backoff = policy.backoff(retries);
+            let out_of_time =
+                deadline.is_some_and(|deadline| started.elapsed() + backoff >= deadline);
+            if !self.is_retryable(&failed) || retries >= policy.max_retries || out_of_time {
+                if retries == 0 {
+                    return Err(failed.error);
+                }
+                return Err(Box::new(SandboxError::RetriesExhausted {
+                    retries,
+                    source: failed.error,
+                }));
+            }
+
+            tokio::time::sleep(backoff).await;
+            retries += 1;
+        }
+    }
+
+    /// Check whether a failed attempt may be retried, according to the retry predicate
+    fn is_retryable(&self, failed: &FailedAttempt) -> bool {
+        let Some(error) = failed.error.downcast_ref::<SandboxError>() else {
+            return false;
+        };
+        failed.sent && self.retry_predicate_accepts(error)
+    }
+
+    /// Check whether the retry predicate, or the default one if none is set, accepts an error
+    pub(crate) fn retry_predicate_accepts(&self, error: &SandboxError) -> bool {
+        match &self.retry_predicate {
+            Some(RetryClassifier(predicate)) => predicate(error),
+            None => default_retry_predicate(error),
+        }
+    }
+
+    /// Wait for a token from the shared request budget, if one is configured
+    pub(crate) async fn acquire_budget(&self) {
+        if let Some(budget) = &self.retry_budget {
+            budget.acquire().await;
         }
+    }
+
+    /// Serialize a JSON-RPC request, refusing bodies over the configured size limit
+    pub(crate) fn encode_request(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let body = serde_json::to_vec(&json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": Uuid::new_v4().to_string(),
+        }))?;
 
-        // Extract and deserialize result
-        let result =
-            serde_json::from_value(response_data.get("result").cloned().unwrap_or(Value::Null))?;
+        if body.len() > self.max_request_body_size {
+            return Err(Box::new(SandboxError::InvalidInput(format!(
+                "{} request body is {} bytes, which exceeds the {} byte limit",
+                method,
+                body.len(),
+                self.max_request_body_size
+            ))));
+        }
 
-        Ok(result)
+        Ok(body)
     }
 
     /// Start the sandbox container
+    ///
+    /// Returns the start outcome, including any warnings reported by the server. If the
+    /// sandbox is already started, the outcome of the previous start is returned.
+    ///
+    /// With a [readiness probe](crate::SandboxOptionsBuilder::readiness_probe) set, this also
+    /// waits until the probe passes. If it doesn't pass before its retries run out, this
+    /// returns [`SandboxError::NotReady`] and leaves the sandbox running, so it can be
+    /// inspected and then stopped.
     pub async fn start_sandbox(
         &mut self,
         image: Option<String>,
         memory: u32,
         cpus: f32,
         timeout: f32,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    ) -> Result<StartOutcome, Box<dyn Error + Send + Sync>> {
         if self.is_started {
-            return Ok(());
+            return Ok(self.start_outcome.clone().unwrap_or_default());
         }
 
-        let params = json!({
+        for ulimit in &self.ulimits {
+            ulimit.validate()?;
+        }
+
+        if let Some(hostname) = &self.hostname {
+            validate_hostname(hostname)?;
+        }
+
+        validate_inline_files(&self.files)?;
+
+        if let Some(oom_score_adj) = self.oom_score_adj {
+            if !(-1000..=1000).contains(&oom_score_adj) {
+                return Err(Box::new(SandboxError::InvalidInput(format!(
+                    "oom_score_adj must be between -1000 and 1000, got {}",
+                    oom_score_adj
+                ))));
+            }
+        }
+
+        if self.max_concurrent_executions == Some(0) {
+            return Err(Box::new(SandboxError::InvalidInput(
+                "max_concurrent_executions must be at least 1".to_string(),
+            )));
+        }
+
+        // Never start without the profile on a server that would ignore it
+        if let Some(security) = &self.security {
+            security.validate()?;
+            let capabilities = self.capabilities().await?;
+            if !capabilities
+                .iter()
+                .any(|c| c == crate::security::SECURITY_PROFILE_CAPABILITY)
+            {
+                return Err(Box::new(SandboxError::Unsupported(
+                    "the server's sandbox backend can't apply security profiles".to_string(),
+                )));
+            }
+        }
+
+        // Refuse to start when the namespace is already at the configured cap
+        if let Some(max) = self.max_sandboxes_per_namespace {
+            let running = self.count_running_sandboxes().await?;
+            if running >= max {
+                return Err(Box::new(SandboxError::ResourceExhausted(format!(
+                    "namespace '{}' already has {} running sandboxes (max {})",
+                    self.namespace, running, max
+                ))));
+            }
+        }
+
+        let (cpus, cpus_warning) = self.cpus_for_server(cpus).await?;
+        let mut params = json!({
             "namespace": self.namespace,
             "sandbox": self.name,
-            "config": {
-                "image": image,
-                "memory": memory,
-                "cpus": cpus.round() as i32,
-            }
+            "config": self.start_config(image, memory, cpus),
         });
 
-        // Set client timeout to be slightly longer than the server timeout
-        let client_timeout = Duration::from_secs_f32(timeout + 30.0);
-        let client = reqwest::Client::builder().timeout(client_timeout).build()?;
+        // Let the server return the existing sandbox when this start is repeated. Without a
+        // key in the options, one is made up for this call, so its retries share it
+        let idempotency_key = self
+            .idempotency_key
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        params["idempotency_key"] = json!(idempotency_key);
+
+        // Give each phase that has a limit its own time limit on the server
+        let phases = [
+            (StartPhase::Pull, self.pull_timeout),
+            (StartPhase::Boot, self.boot_timeout),
+            (StartPhase::Ready, self.ready_timeout),
+        ];
+        let phase_total: Duration = phases.iter().filter_map(|(_, limit)| *limit).sum();
+        if phase_total > Duration::ZERO {
+            let timeouts: serde_json::Map<String, Value> = phases
+                .iter()
+                .filter_map(|(phase, limit)| {
+                    limit.map(|limit| (phase.as_str().to_string(), json!(limit.as_secs_f64())))
+                })
+                .collect();
+            params["timeouts"] = Value::Object(timeouts);
+        }
 
-        let request_data = json!({
-            "jsonrpc": "2.0",
-            "method": "sandbox.start",
-            "params": params,
-            "id": Uuid::new_v4().to_string(),
+        // Set the request timeout to be slightly longer than the server timeout
+        let client_timeout =
+            Duration::from_secs_f32(timeout).max(phase_total) + Duration::from_secs(30);
+
+        let response_data = self
+            .send_start_with_retries(params, client_timeout, timeout)
+            .await?;
+
+        // Collect warnings reported in the result, after the one about rounding the CPUs
+        let mut outcome = StartOutcome::from_result(response_data.get("result"));
+        if let Some(warning) = cpus_warning {
+            outcome.warnings.insert(0, warning);
+        }
+
+        self.is_started = true;
+        self.start_outcome = Some(outcome.clone());
+
+        // A repeated start can find the sandbox running but paused. It was provisioned by the
+        // start that created it, and can't run anything until it is resumed
+        self.is_paused = response_data["result"]["paused"].as_bool().unwrap_or(false);
+        if self.is_paused {
+            return Ok(outcome);
+        }
+
+        // Write the inline files and run the init code before handing the sandbox to the caller
+        let provisioned = match self.write_inline_files().await {
+            Ok(()) => self.run_init_code().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = provisioned {
+            let _ = self.stop_sandbox().await;
+            return Err(e);
+        }
+
+        // Wait for the application to come up, leaving the sandbox running if it doesn't
+        if self.readiness_probe.is_some() {
+            self.wait_until_ready().await?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// The sandbox configuration sent with a `sandbox.start` request, with `cpus` as returned
+    /// by [`cpus_for_server`](Self::cpus_for_server)
+    pub(crate) fn start_config(&self, image: Option<String>, memory: u32, cpus: Value) -> Value {
+        let mut config = json!({
+            "image": image,
+            "memory": memory,
+            "cpus": cpus,
+            "ulimits": self.ulimits,
+            "hostname": self.hostname,
+            "oom_score_adj": self.oom_score_adj,
         });
+        if let Some(security) = &self.security {
+            config["security"] = json!(security);
+        }
+        if let Some(max) = self.max_concurrent_executions {
+            config["max_concurrent_executions"] = json!(max);
+        }
+        config
+    }
 
-        // Create headers
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    /// The `cpus` to send for a requested CPU count, with a warning if it had to be rounded
+    ///
+    /// The server only applies whole CPUs: it reads `cpus` as a whole number, and rejects a
+    /// start with a fraction. So whole counts are sent as integers, and fractions are rounded
+    /// to the nearest whole CPU, but at least one. Fractions are only sent as they are to a
+    /// server reporting the `fractional_cpus` capability, which no microsandbox server does.
+    pub(crate) async fn cpus_for_server(
+        &self,
+        cpus: f32,
+    ) -> Result<(Value, Option<SandboxWarning>), Box<dyn Error + Send + Sync>> {
+        if !cpus.is_finite() || cpus <= 0.0 {
+            return Err(Box::new(SandboxError::InvalidInput(format!(
+                "cpus must be a positive number, got {}",
+                cpus
+            ))));
+        }
 
-        if let Some(api_key) = &self.api_key {
-            headers.insert(
-                AUTHORIZATION,
-                HeaderValue::from_str(&format!("Bearer {}", api_key))?,
-            );
+        if cpus.fract() == 0.0 {
+            return Ok((json!(cpus as u32), None));
         }
 
-        // Send request
-        let response = match client
-            .post(&format!("{}/api/v1/rpc", self.server_url))
-            .headers(headers)
-            .json(&request_data)
-            .send()
+        let capabilities = self.capabilities().await?;
+        if capabilities.iter().any(|c| c == FRACTIONAL_CPUS_CAPABILITY) {
+            return Ok((json!(cpus), None));
+        }
+
+        let rounded = cpus.round().max(1.0) as u32;
+        let warning = SandboxWarning {
+            code: WARNING_CPUS_ROUNDED.to_string(),
+            message: format!(
+                "The server only applies whole CPUs, so {} CPUs were rounded to {}",
+                cpus, rounded
+            ),
+            fatal: false,
+        };
+        Ok((json!(rounded), Some(warning)))
+    }
+
+    /// Send a `sandbox.start` request, retrying failures under the retry policy
+    ///
+    /// A start carries an idempotency key, so the server returns the sandbox started by an
+    /// earlier attempt instead of starting another one, which makes a start safe to retry even
+    /// after it timed out on the client, when the server may still have started the sandbox.
+    /// Timeouts are retried on top of the failures the retry predicate accepts.
+    async fn send_start_with_retries(
+        &self,
+        params: Value,
+        client_timeout: Duration,
+        timeout: f32,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let mut retries = 0;
+        loop {
+            let error = match self.send_start(&params, client_timeout, timeout).await {
+                Ok(response_data) => return Ok(response_data),
+                Err(error) => error,
+            };
+
+            let retryable = error.downcast_ref::<SandboxError>().is_some_and(|error| {
+                matches!(error, SandboxError::Timeout(_)) || self.retry_predicate_accepts(error)
+            });
+            let policy = match &self.retry_policy {
+                Some(policy) if retryable && retries < policy.max_retries => policy,
+                _ if retries == 0 => return Err(error),
+                _ => {
+                    return Err(Box::new(SandboxError::RetriesExhausted {
+                        retries,
+                        source: error,
+                    }))
+                }
+            };
+
+            tokio::time::sleep(policy.backoff(retries)).await;
+            retries += 1;
+        }
+    }
+
+    /// Send a single `sandbox.start` request, returning the JSON-RPC response
+    async fn send_start(
+        &self,
+        params: &Value,
+        client_timeout: Duration,
+        timeout: f32,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let body = self.encode_request("sandbox.start", params.clone())?;
+        let response = self
+            .send_body("sandbox.start", body, Some(client_timeout))
             .await
-        {
-            Ok(resp) => resp,
-            Err(e) => {
-                if e.is_timeout() {
-                    return Err(Box::new(SandboxError::Timeout(format!(
+            .map_err(|failed| {
+                if is_timeout(failed.error.as_ref()) {
+                    return Box::new(SandboxError::Timeout(format!(
                         "Timed out waiting for sandbox to start after {} seconds",
                         timeout
-                    ))));
+                    ))) as _;
                 }
-                return Err(Box::new(SandboxError::HttpError(e.to_string())));
-            }
-        };
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(Box::new(SandboxError::RequestFailed(error_text)));
-        }
+                failed.error
+            })?;
 
         // Parse response
-        let response_data: Value = response.json().await?;
+        let response_data = read_response_json(response).await?;
+        self.log_response("sandbox.start", &response_data);
 
         if let Some(error) = response_data.get("error") {
-            let error_msg = error
-                .get("message")
-                .and_then(|m| m.as_str())
-                .unwrap_or("Unknown error")
-                .to_string();
-            return Err(Box::new(SandboxError::ServerError(error_msg)));
-        }
-
-        // Check for warning in result
-        if let Some(result) = response_data.get("result") {
-            if let Some(result_str) = result.as_str() {
-                if result_str.contains("timed out waiting") {
-                    eprintln!("Sandbox start warning: {}", result_str);
-                }
-            }
+            return Err(Box::new(rpc_error(error)));
         }
 
-        self.is_started = true;
+        Ok(response_data)
+    }
+
+    /// Check if the init code has run since the sandbox was started
+    pub fn init_ran(&self) -> bool {
+        self.init_ran
+    }
+
+    /// Run the configured init code once, failing if the execution reports an error
+    async fn run_init_code(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some((language, code)) = &self.init_code else {
+            return Ok(());
+        };
+
+        if self.init_ran {
+            return Ok(());
+        }
+
+        let execution = self.run_code(language.as_str(), code).await?;
+        if execution.has_error() {
+            return Err(Box::new(SandboxError::General(format!(
+                "Init code failed: {}",
+                execution.error().await?
+            ))));
+        }
+
+        self.init_ran = true;
         Ok(())
     }
 
+    /// Get the outcome of the most recent successful start
+    pub fn start_outcome(&self) -> Option<&StartOutcome> {
+        self.start_outcome.as_ref()
+    }
+
+    /// Count the running sandboxes in this sandbox's namespace, excluding this sandbox
+    ///
+    /// Paused sandboxes count as running, since they still hold their resources. Only this
+    /// namespace is listed, which a token scoped to it is allowed to do; a namespace that
+    /// doesn't exist yet counts as empty.
+    async fn count_running_sandboxes(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let sandboxes = self.list_sandboxes(Some(&self.namespace)).await?;
+
+        Ok(sandboxes
+            .iter()
+            .filter(|s| matches!(s.state, SandboxState::Running | SandboxState::Paused))
+            .filter(|s| s.name != self.name)
+            .count())
+    }
+
     /// Stop the sandbox container
     pub async fn stop_sandbox(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
         if !self.is_started {
@@ -240,28 +824,2014 @@ impl SandboxBase {
 
         let _result: Value = self.make_request("sandbox.stop", params).await?;
         self.is_started = false;
+        self.is_paused = false;
+        self.init_ran = false;
 
         Ok(())
     }
 
-    /// Execute code in the sandbox
-    pub async fn run_code(
-        &self,
-        language: &str,
-        code: &str,
-    ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+    /// Stop the sandbox and consume the handle
+    ///
+    /// This is the counterpart of [`stop_on_drop`](crate::SandboxOptions::stop_on_drop) for
+    /// async scopes: the stop is awaited and its error returned instead of being left to a
+    /// background task. The handle isn't stopped again on drop if the stop fails.
+    pub async fn close(mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.stop_on_drop = false;
+        self.stop_sandbox().await
+    }
+
+    /// Wrap the handle in a guard that stops the sandbox when it goes out of scope
+    ///
+    /// See [`SandboxGuard`].
+    pub fn guard(self) -> SandboxGuard {
+        SandboxGuard::new(self)
+    }
+
+    /// Pause the sandbox
+    ///
+    /// The sandbox's VM is suspended so it consumes no CPU while keeping its state. Code and
+    /// commands can't be run until the sandbox is resumed. The request is always sent, since
+    /// another client may have resumed the sandbox; pausing a paused sandbox succeeds.
+    pub async fn pause(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
         if !self.is_started {
             return Err(Box::new(SandboxError::NotStarted));
         }
 
         let params = json!({
-            "sandbox": self.name,
             "namespace": self.namespace,
-            "language": language,
-            "code": code,
+            "sandbox": self.name,
         });
 
-        let result: HashMap<String, Value> = self.make_request("sandbox.repl.run", params).await?;
-        Ok(Execution::new(result))
+        let _result: Value = self.make_request("sandbox.pause", params).await?;
+        self.is_paused = true;
+
+        Ok(())
+    }
+
+    /// Resume a paused sandbox
+    ///
+    /// The request is always sent, since another client may have paused the sandbox; resuming
+    /// a sandbox that isn't paused succeeds.
+    pub async fn resume(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        let params = json!({
+            "namespace": self.namespace,
+            "sandbox": self.name,
+        });
+
+        let _result: Value = self.make_request("sandbox.resume", params).await?;
+        self.is_paused = false;
+
+        Ok(())
+    }
+
+    /// Clone the sandbox into a new, running sandbox named `new_name`
+    ///
+    /// The server copies this sandbox's configuration and writable filesystem layer to the
+    /// clone, which then starts on top of the same image layers. Packages installed and
+    /// files written in this sandbox are there in the clone without running the setup again.
+    /// A running sandbox is paused while its layer is copied, so the clone sees a consistent
+    /// filesystem, and resumed afterwards. Only the filesystem is cloned: REPL state such as
+    /// variables defined by the init code starts fresh.
+    ///
+    /// The returned handle uses the same server, namespace, credentials and options as this
+    /// one.
+    pub async fn clone_sandbox(
+        &self,
+        new_name: &str,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        let params = json!({
+            "namespace": self.namespace,
+            "sandbox": self.name,
+            "new_name": new_name,
+        });
+
+        let _result: Value = self.make_request("sandbox.clone", params).await?;
+
+        Ok(self.started_handle(new_name))
+    }
+
+    /// Create a handle to the started sandbox `name`, with this handle's server, namespace,
+    /// credentials and options
+    pub(crate) fn started_handle(&self, name: &str) -> Self {
+        Self {
+            server_url: self.server_url.clone(),
+            namespace: self.namespace.clone(),
+            name: name.to_string(),
+            auth: self.auth.clone(),
+            idempotency_key: None,
+            max_sandboxes_per_namespace: self.max_sandboxes_per_namespace,
+            ulimits: self.ulimits.clone(),
+            max_request_body_size: self.max_request_body_size,
+            init_code: self.init_code.clone(),
+            init_ran: false,
+            hostname: self.hostname.clone(),
+            oom_score_adj: self.oom_score_adj,
+            max_concurrent_executions: self.max_concurrent_executions,
+            readiness_probe: self.readiness_probe.clone(),
+            retry_budget: self.retry_budget.clone(),
+            retry_policy: self.retry_policy.clone(),
+            retry_predicate: self.retry_predicate.clone(),
+            execution_timeout: self.execution_timeout,
+            partial_output_on_timeout: self.partial_output_on_timeout,
+            pull_timeout: self.pull_timeout,
+            boot_timeout: self.boot_timeout,
+            ready_timeout: self.ready_timeout,
+            request_logging: self.request_logging.clone(),
+            security: self.security.clone(),
+            files: self.files.clone(),
+            supported_languages: self.supported_languages.clone(),
+            client: self.client.clone(),
+            is_started: true,
+            is_paused: false,
+            start_outcome: None,
+            stop_on_drop: self.stop_on_drop,
+        }
+    }
+
+    /// Set the default timeout applied to every execution
+    ///
+    /// Replaces the timeout set with the `execution_timeout` option, if any. A timeout given for
+    /// a single execution takes precedence over the default; without either, executions run
+    /// until they finish.
+    pub fn set_default_execution_timeout(&mut self, timeout: Duration) {
+        self.execution_timeout = Some(timeout);
+    }
+
+    /// Remove the default execution timeout
+    pub fn clear_default_execution_timeout(&mut self) {
+        self.execution_timeout = None;
+    }
+
+    /// Get the default timeout applied to executions, if one is set
+    pub fn default_execution_timeout(&self) -> Option<Duration> {
+        self.execution_timeout
+    }
+
+    /// Get how many executions the server runs in the sandbox at once
+    ///
+    /// Returns `None` if the sandbox has no limit, or the server is too old to report one.
+    /// Executions over the limit fail with [`SandboxError::ResourceExhausted`] rather than
+    /// waiting, so callers running code concurrently should keep at most this many in flight.
+    pub async fn max_concurrent_executions(
+        &self,
+    ) -> Result<Option<usize>, Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        let params = json!({
+            "namespace": self.namespace,
+            "sandbox": self.name,
+        });
+        let response: Value = self.make_request("sandbox.metrics.get", params).await?;
+
+        Ok(response["sandboxes"]
+            .as_array()
+            .and_then(|sandboxes| sandboxes.iter().find(|s| s["name"] == self.name.as_str()))
+            .and_then(|sandbox| sandbox["max_concurrent_executions"].as_u64())
+            .map(|max| max as usize))
+    }
+
+    /// Execute code in the sandbox
+    ///
+    /// Uses the default execution timeout, if one is set.
+    pub async fn run_code(
+        &self,
+        language: &str,
+        code: &str,
+    ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+        self.execute_code(
+            language,
+            code,
+            &HashMap::new(),
+            None,
+            self.execution_timeout,
+            self.partial_output_on_timeout,
+        )
+        .await
+    }
+
+    /// Execute code in the sandbox with extra environment variables, in a working directory
+    ///
+    /// `env` is set on top of the REPL's environment, and `cwd` replaces its working
+    /// directory, only for this execution; later executions see the REPL's own again. The
+    /// server rejects variable names that are empty or contain `=`, and a `cwd` that isn't
+    /// an existing directory in the sandbox. Uses the default execution timeout, if one is
+    /// set.
+    ///
+    /// The values of `env` are redacted from request logs, see
+    /// [`RequestLogging`](crate::RequestLogging).
+    pub async fn run_code_with_env(
+        &self,
+        language: &str,
+        code: &str,
+        env: &HashMap<String, String>,
+        cwd: Option<&str>,
+    ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+        self.execute_code(
+            language,
+            code,
+            env,
+            cwd,
+            self.execution_timeout,
+            self.partial_output_on_timeout,
+        )
+        .await
+    }
+
+    /// Execute code in the sandbox, stopping it once it has run for `timeout`
+    ///
+    /// The server interrupts code that runs past the timeout and reports the execution as
+    /// [timed out](Execution::timed_out), with whatever it wrote to stdout and stderr before
+    /// the interrupt. The timeout is rounded down to whole seconds, with a minimum of one.
+    /// If the server doesn't answer within a margin past the timeout, the partial output is
+    /// read back from the sandbox instead.
+    pub async fn run_code_with_timeout(
+        &self,
+        language: &str,
+        code: &str,
+        timeout: Duration,
+    ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+        self.execute_code(language, code, &HashMap::new(), None, Some(timeout), true)
+            .await
+    }
+
+    /// Run a command in the sandbox as its own process, outside of any language REPL
+    ///
+    /// `argv[0]` is the program and the rest are its arguments, passed to it as they are:
+    /// nothing is interpreted by a shell, so arguments need no quoting. The returned
+    /// execution has the command's [exit code](Execution::exit_code) and its stdout and
+    /// stderr, and [has an error](Execution::has_error) if the command exited with a non-zero
+    /// code. Uses the default execution timeout, if one is set.
+    pub async fn run_command(
+        &self,
+        argv: &[String],
+    ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+        let Some((command, args)) = argv.split_first() else {
+            return Err(Box::new(SandboxError::InvalidInput(
+                "run_command needs at least the program to run".to_string(),
+            )));
+        };
+
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        if self.is_paused {
+            return Err(Box::new(SandboxError::Paused));
+        }
+
+        let mut params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "command": command,
+            "args": args,
+        });
+
+        let request_timeout = self.execution_timeout.map(|timeout| {
+            params["timeout"] = json!(timeout.as_secs().max(1));
+            timeout + EXECUTION_TIMEOUT_MARGIN
+        });
+
+        let result = self
+            .make_request_with_timeout("sandbox.command.run", params, request_timeout)
+            .await?;
+        Ok(Execution::from_command(result))
+    }
+
+    /// Execute code in the sandbox with the given environment and timeout, or none
+    ///
+    /// An empty `env` and no `cwd` leave the REPL's own in place. Callers resolve the timeout: a
+    /// per-call timeout, else the default, else none. With `partial_output`, a request that
+    /// times out on the client returns the output produced so far instead of failing.
+    async fn execute_code(
+        &self,
+        language: &str,
+        code: &str,
+        env: &HashMap<String, String>,
+        cwd: Option<&str>,
+        timeout: Option<Duration>,
+        partial_output: bool,
+    ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        if self.is_paused {
+            return Err(Box::new(SandboxError::Paused));
+        }
+
+        let mut params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "language": language,
+            "code": code,
+        });
+        if !env.is_empty() {
+            params["env"] = json!(env);
+        }
+        if let Some(cwd) = cwd {
+            params["cwd"] = json!(cwd);
+        }
+
+        // Let the server cancel the execution, and give it time to report back before giving up
+        let request_timeout = timeout.map(|timeout| {
+            params["timeout"] = json!(timeout.as_secs().max(1));
+            timeout + EXECUTION_TIMEOUT_MARGIN
+        });
+
+        match self
+            .make_request_with_timeout("sandbox.repl.run", params, request_timeout)
+            .await
+        {
+            Ok(result) => Ok(Execution::new(result)),
+            Err(e) if partial_output && is_timeout(e.as_ref()) => {
+                // Best effort: keep the timeout error if the output can't be read back
+                self.fetch_partial_output(language).await.map_err(|_| e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read back the output the REPL has produced so far for the most recent execution
+    async fn fetch_partial_output(
+        &self,
+        language: &str,
+    ) -> Result<Execution, Box<dyn Error + Send + Sync>> {
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "language": language,
+        });
+
+        let result: ExecutionResult = self.make_request("sandbox.repl.partial", params).await?;
+        Ok(Execution::new_timed_out(result))
+    }
+
+    /// Ask the REPL to emit output it is holding back for the running execution
+    ///
+    /// The guest reads REPL output line by line, so text without a trailing newline (such
+    /// as a prompt written with `print(..., end="")`) is held until its line completes or
+    /// the execution ends. Flushing emits that text as its own output line right away, so a
+    /// UI consuming the execution's output can show it promptly. Call it from another task
+    /// while `run_code` is in flight on the same `SandboxBase`; it does nothing when no
+    /// code is running.
+    ///
+    /// The Python REPL already runs unbuffered. Output that a program inside the guest
+    /// buffers itself, e.g. a subprocess whose stdout is block-buffered because it is a
+    /// pipe, is only seen once that program flushes it.
+    pub async fn flush_repl(&self, language: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "language": language,
+        });
+
+        let _result: Value = self.make_request("sandbox.repl.flush", params).await?;
+        Ok(())
+    }
+}
+
+impl Drop for SandboxBase {
+    fn drop(&mut self) {
+        if !self.is_started || !self.stop_on_drop {
+            return;
+        }
+
+        // Drop can't await the request, so it's sent from a task on the current runtime
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!(
+                sandbox = %self.name,
+                "sandbox handle dropped outside a Tokio runtime, leaving the sandbox running"
+            );
+            return;
+        };
+
+        let mut handle = self.started_handle(&self.name);
+        handle.stop_on_drop = false;
+        runtime.spawn(async move {
+            if let Err(e) = handle.stop_sandbox().await {
+                tracing::warn!(sandbox = %handle.name, "failed to stop dropped sandbox: {}", e);
+            }
+        });
+    }
+}
+
+/// Build the HTTP client for requests to the server, with the connection pool and TLS
+/// settings from `options`
+fn build_client(options: &SandboxOptions) -> Result<reqwest::Client, SandboxError> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(max) = options.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if let Some(timeout) = options.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(timeout);
+    }
+    if let Some(interval) = options.tcp_keepalive {
+        builder = builder.tcp_keepalive(interval);
+    }
+
+    tls::configure(builder, options)?.build().map_err(|e| {
+        SandboxError::InvalidInput(format!("failed to configure the HTTP client: {}", e))
+    })
+}
+
+/// Convert the `error` member of a JSON-RPC response into the matching [`SandboxError`]
+pub(crate) fn rpc_error(error: &Value) -> SandboxError {
+    let error_msg = error
+        .get("message")
+        .and_then(|m| m.as_str())
+        .unwrap_or("Unknown error")
+        .to_string();
+    let code = error.get("code").and_then(Value::as_i64).unwrap_or(0);
+    let data = error.get("data").filter(|data| !data.is_null()).cloned();
+
+    // An execution over the sandbox's limit names the limit in the error data
+    if code == EXECUTION_LIMIT_CODE || error.pointer("/data/max_concurrent_executions").is_some() {
+        return SandboxError::ResourceExhausted(error_msg);
+    }
+    if code == SANDBOX_NOT_FOUND_CODE {
+        return SandboxError::NotFound(error_msg);
+    }
+    // A start phase that exceeded its limit is named in the error data
+    if let Some(phase) = error
+        .pointer("/data/phase")
+        .and_then(|p| p.as_str())
+        .and_then(StartPhase::parse)
+    {
+        return SandboxError::PhaseTimeout {
+            phase,
+            message: error_msg,
+        };
+    }
+
+    SandboxError::Rpc(RpcError {
+        code,
+        message: error_msg,
+        data,
+    })
+}
+
+/// Check whether an error is a [`SandboxError::Timeout`]
+fn is_timeout(error: &(dyn Error + Send + Sync + 'static)) -> bool {
+    matches!(
+        error.downcast_ref::<SandboxError>(),
+        Some(SandboxError::Timeout(_))
+    )
+}
+
+/// A failed attempt at sending a request
+pub(crate) struct FailedAttempt {
+    /// Whether the request was sent, so the failure may be retried if the predicate agrees
+    sent: bool,
+
+    /// Why the attempt failed
+    pub(crate) error: Box<dyn Error + Send + Sync>,
+}
+
+impl FailedAttempt {
+    /// A failure before the request was sent, which sending it again won't fix
+    fn fatal(error: Box<dyn Error + Send + Sync>) -> Self {
+        Self { sent: false, error }
+    }
+
+    /// A failure of a request that was sent
+    fn sent(error: Box<dyn Error + Send + Sync>) -> Self {
+        Self { sent: true, error }
+    }
+}
+
+/// Read a JSON-RPC response body, reporting truncated or invalid bodies as malformed
+pub(crate) async fn read_response_json(
+    mut response: reqwest::Response,
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    let mut body = Vec::new();
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            Ok(None) => break,
+            Err(e) => {
+                return Err(Box::new(SandboxError::MalformedResponse {
+                    received: body.len(),
+                    message: e.to_string(),
+                }))
+            }
+        }
+    }
+
+    serde_json::from_slice(&body).map_err(|e| {
+        let message = if e.is_eof() {
+            format!("response ended unexpectedly: {}", e)
+        } else {
+            e.to_string()
+        };
+        Box::new(SandboxError::MalformedResponse {
+            received: body.len(),
+            message,
+        }) as _
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ConfigDiagnostic, ImagePull, RetryPredicate, RpcCall, SandboxState, SandboxStats, Snapshot,
+        StartOptions, CHECK_IMAGE, CHECK_RESOURCES, INVALID_PARAMS_CODE,
+    };
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Answer one JSON-RPC request with `response`, returning the request's params
+    async fn serve_once(listener: TcpListener, response: Value) -> Value {
+        serve_with_status(&listener, "200 OK", &response).await
+    }
+
+    /// Answer one JSON-RPC request with an HTTP status and body, closing the connection
+    async fn serve_with_status(listener: &TcpListener, status: &str, response: &Value) -> Value {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        let params = read_request(&mut stream).await;
+
+        let response = response.to_string();
+        stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        params
+    }
+
+    /// Read one JSON-RPC request from a connection, returning its params
+    async fn read_request(stream: &mut BufReader<tokio::net::TcpStream>) -> Value {
+        let mut content_length = 0;
+        let mut line = String::new();
+        while stream.read_line(&mut line).await.unwrap() > 2 {
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                content_length = value.trim().parse().unwrap();
+            }
+            line.clear();
+        }
+        let mut body = vec![0; content_length];
+        stream.read_exact(&mut body).await.unwrap();
+
+        serde_json::from_slice::<Value>(&body).unwrap()["params"].clone()
+    }
+
+    #[tokio::test]
+    async fn test_start_and_stop_reuse_one_pooled_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .build();
+
+        // Answer both requests on the first connection, keeping it open
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut methods = Vec::new();
+            for _ in 0..2 {
+                let mut content_length = 0;
+                let mut line = String::new();
+                while stream.read_line(&mut line).await.unwrap() > 2 {
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                    line.clear();
+                }
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).await.unwrap();
+                methods.push(serde_json::from_slice::<Value>(&body).unwrap()["method"].clone());
+
+                let response = json!({ "jsonrpc": "2.0", "id": "1", "result": {} }).to_string();
+                stream
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                            response.len(),
+                            response
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            methods
+        });
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.start_sandbox(None, 512, 1.0, 180.0).await.unwrap();
+        sandbox.stop_sandbox().await.unwrap();
+
+        let methods = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("stop_sandbox opened a new connection")
+            .unwrap();
+        assert_eq!(methods, ["sandbox.start", "sandbox.stop"]);
+    }
+
+    #[tokio::test]
+    async fn test_pool_without_idle_connections_opens_one_per_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .pool_max_idle_per_host(0)
+            .tcp_keepalive(Duration::from_secs(30))
+            .build();
+
+        // Answer each request on a new connection, leaving it open for reuse
+        let server = tokio::spawn(async move {
+            let mut connections = Vec::new();
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+                let mut content_length = 0;
+                let mut line = String::new();
+                while stream.read_line(&mut line).await.unwrap() > 2 {
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                    line.clear();
+                }
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).await.unwrap();
+
+                let response = json!({ "jsonrpc": "2.0", "id": "1", "result": {} }).to_string();
+                stream
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                            response.len(),
+                            response
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+                connections.push(stream);
+            }
+        });
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.start_sandbox(None, 512, 1.0, 180.0).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), sandbox.stop_sandbox())
+            .await
+            .expect("stop_sandbox reused an idle connection")
+            .unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_start_waits_for_the_readiness_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .readiness_probe(
+                ProbeSpec::new("pg_isready", ["-q"])
+                    .expected_exit_code(2)
+                    .interval(Duration::from_millis(10))
+                    .retries(1),
+            )
+            .build();
+        let server = tokio::spawn(async move {
+            let start = json!({ "jsonrpc": "2.0", "id": "1", "result": {} });
+            serve_with_status(&listener, "200 OK", &start).await;
+            let mut probes = Vec::new();
+            for exit_code in [1, 2] {
+                let run =
+                    json!({ "jsonrpc": "2.0", "id": "1", "result": { "exit_code": exit_code } });
+                probes.push(serve_with_status(&listener, "200 OK", &run).await);
+            }
+            probes
+        });
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.start_sandbox(None, 512, 1.0, 180.0).await.unwrap();
+
+        let probes = server.await.unwrap();
+        assert_eq!(probes[0]["command"], "pg_isready");
+        assert_eq!(probes[1]["args"], json!(["-q"]));
+    }
+
+    #[tokio::test]
+    async fn test_start_leaves_the_sandbox_running_when_the_probe_fails() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .readiness_probe(
+                ProbeSpec::tcp(8080)
+                    .interval(Duration::from_millis(10))
+                    .retries(1),
+            )
+            .build();
+        let server = tokio::spawn(async move {
+            let start = json!({ "jsonrpc": "2.0", "id": "1", "result": {} });
+            serve_with_status(&listener, "200 OK", &start).await;
+            let run = json!({ "jsonrpc": "2.0", "id": "1", "result": { "exit_code": 1 } });
+            let probe = serve_with_status(&listener, "200 OK", &run).await;
+            serve_with_status(&listener, "200 OK", &run).await;
+            probe
+        });
+
+        let mut sandbox = SandboxBase::new(&options);
+        let err = sandbox
+            .start_sandbox(None, 512, 1.0, 180.0)
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<SandboxError>().unwrap();
+        assert!(
+            matches!(err, SandboxError::NotReady { attempts: 2, message } if message.contains("port 8080")),
+            "{:?}",
+            err
+        );
+        assert!(!err.is_retryable());
+        assert!(sandbox.is_started);
+
+        // The port is polled from inside the guest
+        let probe = server.await.unwrap();
+        assert_eq!(probe["command"], "sh");
+        assert!(probe["args"][1]
+            .as_str()
+            .unwrap()
+            .contains("127.0.0.1 8080"));
+    }
+
+    #[tokio::test]
+    async fn test_start_reports_the_phase_that_timed_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .pull_timeout(Duration::from_secs(120))
+            .boot_timeout(Duration::from_millis(1500))
+            .build();
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "error": {
+                    "code": -32001,
+                    "message": "Sandbox start timed out in the boot phase: gave up booting",
+                    "data": { "phase": "boot" },
+                },
+            }),
+        ));
+
+        let mut sandbox = SandboxBase::new(&options);
+        let err = sandbox
+            .start_sandbox(None, 512, 1.0, 180.0)
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<SandboxError>().unwrap();
+        assert_eq!(err.timed_out_phase(), Some(StartPhase::Boot));
+        assert!(err.is_retryable());
+
+        let params = server.await.unwrap();
+        assert_eq!(params["timeouts"], json!({ "pull": 120.0, "boot": 1.5 }));
+    }
+
+    #[tokio::test]
+    async fn test_guard_stops_the_sandbox_when_dropped() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({ "jsonrpc": "2.0", "id": "1", "result": {} }),
+        ));
+
+        // A released handle leaves its sandbox running
+        let options = SandboxOptions::builder()
+            .server_url(&server_url)
+            .name("kept")
+            .build();
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        drop(sandbox.guard().into_inner());
+
+        let options = SandboxOptions::builder()
+            .server_url(&server_url)
+            .name("scoped")
+            .build();
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        drop(sandbox.guard());
+
+        let params = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("dropping the guard didn't stop the sandbox")
+            .unwrap();
+        assert_eq!(params["sandbox"], "scoped");
+    }
+
+    #[tokio::test]
+    async fn test_batch_correlates_responses_and_isolates_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("batched")
+            .build();
+
+        // Responses come back out of order, one call fails and one is left unanswered
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!([
+                { "jsonrpc": "2.0", "id": "2", "result": { "output": "third" } },
+                {
+                    "jsonrpc": "2.0",
+                    "id": "1",
+                    "error": { "code": -32003, "message": "Sandbox 'gone' not found" },
+                },
+                { "jsonrpc": "2.0", "id": "0", "result": { "output": "first" } },
+            ]),
+        ));
+
+        let sandbox = SandboxBase::new(&options);
+        let results = sandbox
+            .batch(vec![
+                RpcCall::run_code("python", "print('first')"),
+                RpcCall::new("sandbox.stats", json!({ "sandbox": "gone" })),
+                RpcCall::run_code("python", "print('third')"),
+                RpcCall::new("server.info", Value::Null),
+            ])
+            .await
+            .unwrap();
+        server.await.unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap()["output"], "first");
+        assert!(matches!(&results[1], Err(SandboxError::NotFound(msg)) if msg.contains("gone")));
+        assert_eq!(results[2].as_ref().unwrap()["output"], "third");
+        assert!(matches!(&results[3], Err(SandboxError::ServerError(_))));
+    }
+
+    #[test]
+    fn test_rpc_error_keeps_the_code_and_data_of_unnamed_errors() {
+        let error = rpc_error(&json!({
+            "code": -32602,
+            "message": "Invalid params",
+            "data": { "field": "memory" },
+        }));
+        assert_eq!(
+            error.rpc_error(),
+            Some(&RpcError {
+                code: INVALID_PARAMS_CODE,
+                message: "Invalid params".to_string(),
+                data: Some(json!({ "field": "memory" })),
+            })
+        );
+        assert_eq!(error.to_string(), "Server error -32602: Invalid params");
+
+        // Well-known codes map to their own variants
+        assert!(matches!(
+            rpc_error(&json!({ "code": -32003, "message": "gone" })),
+            SandboxError::NotFound(_)
+        ));
+        assert!(matches!(
+            rpc_error(&json!({ "code": -32002, "message": "full" })),
+            SandboxError::ResourceExhausted(_)
+        ));
+        assert!(matches!(
+            rpc_error(&json!({
+                "code": -32001,
+                "message": "too slow",
+                "data": { "phase": "boot" },
+            })),
+            SandboxError::PhaseTimeout { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_request_observer_sees_redacted_params_and_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("observed")
+            .request_logging(RequestLogging::new().redact("sandbox").observe(Arc::new(
+                move |event| recorded.lock().unwrap().push(event.clone()),
+            )))
+            .build();
+
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "error": { "code": -32003, "message": "Sandbox 'observed' not found" },
+            }),
+        ));
+
+        let sandbox = SandboxBase::new(&options);
+        assert!(sandbox.stats().await.is_err());
+        server.await.unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].method, "sandbox.stats");
+        assert_eq!(
+            events[0].params,
+            json!({ "sandbox": "[REDACTED]", "namespace": "default" })
+        );
+        assert!(events[0].error.as_deref().unwrap().contains("not found"));
+    }
+
+    #[tokio::test]
+    async fn test_clone_sandbox_returns_a_started_handle_to_the_clone() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("warm")
+            .namespace("fanout")
+            .build();
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({ "jsonrpc": "2.0", "id": "1", "result": "cloned" }),
+        ));
+
+        let mut sandbox = SandboxBase::new(&options);
+        assert!(sandbox.clone_sandbox("warm-1").await.is_err());
+
+        sandbox.is_started = true;
+        let clone = sandbox.clone_sandbox("warm-1").await.unwrap();
+        assert_eq!(
+            (clone.name.as_str(), clone.namespace.as_str()),
+            ("warm-1", "fanout")
+        );
+        assert!(clone.is_started && !clone.is_paused);
+
+        let params = server.await.unwrap();
+        assert_eq!(
+            params,
+            json!({ "namespace": "fanout", "sandbox": "warm", "new_name": "warm-1" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_code_with_timeout_returns_partial_output() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let options = SandboxOptions::builder()
+            .server_url(url)
+            .name("web")
+            .build();
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "status": "error",
+                    "language": "python",
+                    "output": [
+                        { "stream": "stdout", "text": "step 1" },
+                        { "stream": "stderr", "text": "Error: Execution timed out after 2 seconds" },
+                    ],
+                    "timed_out": true,
+                },
+            }),
+        ));
+
+        let execution = sandbox
+            .run_code_with_timeout("python", "work()", Duration::from_millis(2500))
+            .await
+            .unwrap();
+        assert!(execution.timed_out() && execution.has_error());
+        assert_eq!(execution.output().await.unwrap(), "step 1");
+
+        let params = server.await.unwrap();
+        assert_eq!(params["timeout"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_command_sends_argv_and_reads_exit_code() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let options = SandboxOptions::builder()
+            .server_url(url)
+            .name("web")
+            .build();
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        assert!(sandbox.run_command(&[]).await.is_err());
+
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "command": "git",
+                    "args": ["commit", "-m", "it's done; rm -rf /"],
+                    "exit_code": 0,
+                    "success": true,
+                    "output": [
+                        { "stream": "stdout", "text": "[main 1a2b3c4] it's done; rm -rf /" },
+                        { "stream": "stderr", "text": "hint: using default identity" },
+                    ],
+                },
+            }),
+        ));
+
+        let argv = ["git", "commit", "-m", "it's done; rm -rf /"].map(String::from);
+        let execution = sandbox.run_command(&argv).await.unwrap();
+        assert_eq!(execution.exit_code(), Some(0));
+        assert_eq!(execution.stderr(), "hint: using default identity");
+        assert!(execution.success() && !execution.has_error());
+        assert_eq!(execution.status(), "success");
+
+        let params = server.await.unwrap();
+        assert_eq!(params["command"], "git");
+        assert_eq!(
+            params["args"],
+            json!(["commit", "-m", "it's done; rm -rf /"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_code_with_env_sends_env_and_cwd() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let options = SandboxOptions::builder()
+            .server_url(url)
+            .name("web")
+            .build();
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "status": "success",
+                    "language": "python",
+                    "output": [{ "stream": "stdout", "text": "/srv/app" }],
+                },
+            }),
+        ));
+
+        let env = HashMap::from([("API_TOKEN".to_string(), "secret".to_string())]);
+        let execution = sandbox
+            .run_code_with_env(
+                "python",
+                "import os; print(os.getcwd())",
+                &env,
+                Some("/srv/app"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(execution.output().await.unwrap(), "/srv/app");
+
+        let params = server.await.unwrap();
+        assert_eq!(params["env"], json!({ "API_TOKEN": "secret" }));
+        assert_eq!(params["cwd"], "/srv/app");
+    }
+
+    #[tokio::test]
+    async fn test_read_file_in_chunks_and_list_dir() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let options = SandboxOptions::builder()
+            .server_url(url)
+            .name("web")
+            .build();
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+
+        let server = tokio::spawn(async move {
+            let mut params = Vec::new();
+            for (content, size) in [("aGVsbG8g", 11), ("d29ybGQ=", 11)] {
+                let chunk = json!({
+                    "jsonrpc": "2.0",
+                    "id": "1",
+                    "result": { "path": "/data/greeting", "content": content, "size": size },
+                });
+                params.push(serve_with_status(&listener, "200 OK", &chunk).await);
+            }
+            let listing = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "path": "/data",
+                    "entries": [
+                        { "name": "greeting", "size": 11, "mode": 0o644, "is_dir": false },
+                        { "name": "logs", "size": 4096, "mode": 0o755, "is_dir": true },
+                    ],
+                },
+            });
+            serve_with_status(&listener, "200 OK", &listing).await;
+            params
+        });
+
+        let contents = sandbox.read_file("/data/greeting").await.unwrap();
+        assert_eq!(contents, b"hello world");
+
+        let entries = sandbox.list_dir("/data").await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].mode, 0o644);
+        assert!(entries[1].is_dir);
+
+        let params = server.await.unwrap();
+        assert_eq!(params[0]["offset"], 0);
+        assert_eq!(params[1]["offset"], 6);
+
+        // Paths that escape the sandbox root never reach the server
+        let err = sandbox
+            .read_file("/data/../../etc/shadow")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::InvalidInput(_))
+        ));
+        let err = sandbox.write_file("relative.txt", b"").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_sandboxes_orders_by_namespace_then_name() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let options = SandboxOptions::builder().server_url(url).build();
+        let sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(async move {
+            let descriptor = |namespace: &str, name: &str, status: Value| {
+                json!({
+                    "name": name,
+                    "namespace": namespace,
+                    "status": status,
+                    "config_file": format!("/ns/{}/Sandboxfile", namespace),
+                    "uptime_secs": null,
+                })
+            };
+            let sandboxes = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "sandboxes": [
+                        descriptor("team-b", "api", json!("RUNNING")),
+                        descriptor("team-a", "worker", json!("STOPPED")),
+                        descriptor("team-a", "db", Value::Null),
+                    ],
+                },
+            });
+            let namespaces = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": { "namespaces": ["team-b", "default", "team-a"] },
+            });
+            let params = serve_with_status(&listener, "200 OK", &sandboxes).await;
+            serve_with_status(&listener, "200 OK", &namespaces).await;
+            params
+        });
+
+        let sandboxes = sandbox.list_sandboxes(None).await.unwrap();
+        let names: Vec<_> = sandboxes
+            .iter()
+            .map(|s| (s.namespace.as_str(), s.name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [("team-a", "db"), ("team-a", "worker"), ("team-b", "api")]
+        );
+        assert_eq!(sandboxes[0].state, SandboxState::NotStarted);
+        assert_eq!(sandboxes[2].state, SandboxState::Running);
+        assert_eq!(sandboxes[2].config_file, "/ns/team-b/Sandboxfile");
+
+        let namespaces = sandbox.list_namespaces().await.unwrap();
+        assert_eq!(namespaces, ["default", "team-a", "team-b"]);
+
+        let params = server.await.unwrap();
+        assert_eq!(params, json!({ "namespace": "*" }));
+    }
+
+    #[tokio::test]
+    async fn test_health_works_before_start_and_reports_missing_sandboxes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let options = SandboxOptions::builder()
+            .server_url(url)
+            .name("web")
+            .build();
+        let sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(async move {
+            let running = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "status": "RUNNING",
+                    "uptime_secs": 1.5,
+                    "supervisor_pid": 10,
+                    "microvm_pid": 11,
+                    "supervisor_alive": true,
+                },
+            });
+            let missing = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "error": { "code": -32003, "message": "Sandbox default/web not found" },
+            });
+            let params = serve_with_status(&listener, "200 OK", &running).await;
+            serve_with_status(&listener, "200 OK", &missing).await;
+            params
+        });
+
+        let health = sandbox.health().await.unwrap();
+        assert_eq!(health.state, SandboxState::Running);
+        assert_eq!(health.uptime, Some(Duration::from_millis(1500)));
+        assert_eq!(health.microvm_pid, Some(11));
+        assert!(health.is_healthy());
+
+        let err = sandbox.health().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::NotFound(_))
+        ));
+
+        let params = server.await.unwrap();
+        assert_eq!(params, json!({ "namespace": "default", "sandbox": "web" }));
+
+        // With the server gone, the failure is a transport error instead
+        let err = sandbox.health().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::HttpError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_attach_requires_a_running_sandbox() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("web")
+            .namespace("team-a")
+            .build();
+
+        let server = tokio::spawn(async move {
+            let status = |status: &str| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": "1",
+                    "result": {
+                        "status": status,
+                        "uptime_secs": null,
+                        "supervisor_pid": 10,
+                        "microvm_pid": 11,
+                        "supervisor_alive": status != "STOPPED",
+                    },
+                })
+            };
+            let params = serve_with_status(&listener, "200 OK", &status("PAUSED")).await;
+            serve_with_status(&listener, "200 OK", &status("STOPPED")).await;
+            params
+        });
+
+        let sandbox = SandboxBase::attach_with_options(&options).await.unwrap();
+        assert!(sandbox.is_started && sandbox.is_paused);
+        assert_eq!(sandbox.name, "web");
+
+        let err = SandboxBase::attach_with_options(&options)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::NotRunning(msg)) if msg.contains("team-a/web")
+        ));
+
+        let params = server.await.unwrap();
+        assert_eq!(params, json!({ "namespace": "team-a", "sandbox": "web" }));
+
+        // Without a name there is nothing to attach to
+        let options = SandboxOptions::builder().build();
+        let err = SandboxBase::attach_with_options(&options)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stats_reports_resource_use() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("web")
+            .build();
+        let sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "live": false,
+                    "peak_rss": 268435456u64,
+                    "cpu_seconds": 12.5,
+                    "oom_kills": 1,
+                },
+            }),
+        ));
+
+        let stats = sandbox.stats().await.unwrap();
+        assert_eq!(
+            stats,
+            SandboxStats {
+                live: false,
+                peak_rss: Some(256 << 20),
+                cpu_seconds: Some(12.5),
+                oom_kills: 1,
+            }
+        );
+        let params = server.await.unwrap();
+        assert_eq!(params, json!({ "namespace": "default", "sandbox": "web" }));
+    }
+
+    #[tokio::test]
+    async fn test_validate_config_reports_each_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("web")
+            .build();
+        let sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "valid": false,
+                    "diagnostics": [
+                        { "check": "image", "ok": true, "message": "Image found locally" },
+                        { "check": "resources", "ok": false, "message": "Too many CPUs" },
+                    ],
+                },
+            }),
+        ));
+
+        let validation = sandbox
+            .validate_config(&StartOptions {
+                image: Some("python".to_string()),
+                cpus: 64.0,
+                ..StartOptions::default()
+            })
+            .await
+            .unwrap();
+        assert!(!validation.valid);
+        assert_eq!(validation.diagnostics[0].check, CHECK_IMAGE);
+        assert_eq!(
+            validation.failures().collect::<Vec<_>>(),
+            [&ConfigDiagnostic {
+                check: CHECK_RESOURCES.to_string(),
+                ok: false,
+                message: "Too many CPUs".to_string(),
+            }]
+        );
+
+        let params = server.await.unwrap();
+        assert_eq!(params["sandbox"], "web");
+        assert_eq!(params["config"]["image"], "python");
+        assert_eq!(params["config"]["cpus"], 64);
+    }
+
+    #[tokio::test]
+    async fn test_pull_image_reports_already_present_images() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .build();
+        let sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "reference": "docker.io/microsandbox/python:latest",
+                    "already_present": true,
+                },
+            }),
+        ));
+
+        let pull = sandbox.pull_image("microsandbox/python").await.unwrap();
+        assert_eq!(
+            pull,
+            ImagePull {
+                reference: "docker.io/microsandbox/python:latest".to_string(),
+                already_present: true,
+            }
+        );
+        let params = server.await.unwrap();
+        assert_eq!(params, json!({ "reference": "microsandbox/python" }));
+
+        let err = sandbox.pull_image(" ").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_restore_reports_the_restored_snapshot() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("web")
+            .build();
+        let sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "name": "prepared",
+                    "size_bytes": 4096,
+                    "created_at": "2025-06-15T12:00:00+00:00",
+                },
+            }),
+        ));
+
+        // Restoring works on a stopped sandbox, so the handle doesn't have to be started
+        let restored = sandbox.restore("prepared").await.unwrap();
+        assert_eq!(
+            restored,
+            Snapshot {
+                name: "prepared".to_string(),
+                size_bytes: 4096,
+                created_at: "2025-06-15T12:00:00+00:00".to_string(),
+            }
+        );
+        let params = server.await.unwrap();
+        assert_eq!(
+            params,
+            json!({ "namespace": "default", "sandbox": "web", "snapshot": "prepared" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_with_security_profile_requires_server_support() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .security(SecurityProfile::new().drop_capability("NET_RAW"))
+            .build();
+        let server = tokio::spawn(serve_once(
+            listener,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "version": "0.2.6",
+                    "methods": ["sandbox.start"],
+                    "capabilities": ["ulimits", "hostname"],
+                },
+            }),
+        ));
+
+        let mut sandbox = SandboxBase::new(&options);
+        let err = sandbox
+            .start_sandbox(None, 512, 1.0, 180.0)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::Unsupported(_))
+        ));
+        assert!(!sandbox.is_started);
+        server.await.unwrap();
+
+        // Invalid profiles are rejected before anything is sent
+        sandbox.security = Some(SecurityProfile::new().drop_capability("CAP_FLY"));
+        let err = sandbox
+            .start_sandbox(None, 512, 1.0, 180.0)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_start_refuses_when_the_namespace_is_at_its_cap() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .namespace("team")
+            .name("web")
+            .max_sandboxes_per_namespace(2)
+            .build();
+        let sandbox = |name: &str, status: &str| {
+            json!({
+                "name": name,
+                "namespace": "team",
+                "status": status,
+                "config_file": "/ns/team/Sandboxfile",
+                "uptime_secs": null,
+            })
+        };
+        let listing = json!({
+            "jsonrpc": "2.0",
+            "id": "1",
+            "result": {
+                "sandboxes": [
+                    sandbox("api", "RUNNING"),
+                    sandbox("db", "PAUSED"),
+                    sandbox("old", "STOPPED"),
+                    sandbox("web", "RUNNING"),
+                ],
+            },
+        });
+        let server = tokio::spawn(serve_once(listener, listing));
+
+        // Only the other running and paused sandboxes count against the cap
+        let mut sandbox = SandboxBase::new(&options);
+        let err = sandbox
+            .start_sandbox(None, 512, 1.0, 180.0)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::ResourceExhausted(_))
+        ));
+        assert!(!sandbox.is_started);
+
+        // Only the sandbox's own namespace is listed
+        assert_eq!(server.await.unwrap(), json!({ "namespace": "team" }));
+    }
+
+    #[tokio::test]
+    async fn test_execution_over_the_limit_is_resource_exhausted() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("busy")
+            .build();
+        let server = tokio::spawn(async move {
+            let refused = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "error": {
+                    "code": -32002,
+                    "message": "Sandbox default/busy is already running its maximum of 2 concurrent executions",
+                    "data": { "max_concurrent_executions": 2 },
+                },
+            });
+            serve_with_status(&listener, "200 OK", &refused).await;
+
+            let status = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "sandboxes": [
+                        { "name": "busy", "running": true, "max_concurrent_executions": 2 },
+                    ],
+                },
+            });
+            serve_with_status(&listener, "200 OK", &status).await
+        });
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        let err = sandbox.run_code("python", "print(1)").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::ResourceExhausted(_))
+        ));
+
+        assert_eq!(sandbox.max_concurrent_executions().await.unwrap(), Some(2));
+        assert_eq!(
+            server.await.unwrap(),
+            json!({ "namespace": "default", "sandbox": "busy" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_retries_reuse_the_idempotency_key() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let policy = RetryPolicy::new(1)
+            .initial_backoff(Duration::from_millis(10))
+            .jitter(false);
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("web")
+            .retry_policy(policy)
+            .build();
+        let mut sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(async move {
+            // The first attempt is read but never answered, as if the server went away
+            // after starting the sandbox
+            let (stream, _) = listener.accept().await.unwrap();
+            let first = read_request(&mut BufReader::new(stream)).await;
+
+            let ok =
+                json!({ "jsonrpc": "2.0", "id": "1", "result": "Sandbox web is already running" });
+            let second = serve_with_status(&listener, "200 OK", &ok).await;
+            (first, second)
+        });
+
+        sandbox.start_sandbox(None, 512, 1.0, 180.0).await.unwrap();
+
+        let (first, second) = server.await.unwrap();
+        let key = first["idempotency_key"].as_str().unwrap();
+        assert!(!key.is_empty());
+        assert_eq!(second["idempotency_key"], key);
+    }
+
+    #[tokio::test]
+    async fn test_start_retries_5xx_responses() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let policy = RetryPolicy::new(1)
+            .initial_backoff(Duration::from_millis(10))
+            .jitter(false);
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("web")
+            .retry_policy(policy)
+            .build();
+        let mut sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(async move {
+            let unavailable = json!({ "message": "unavailable" });
+            serve_with_status(&listener, "503 Service Unavailable", &unavailable).await;
+            let ok = json!({ "jsonrpc": "2.0", "id": "1", "result": "Sandbox web started" });
+            serve_with_status(&listener, "200 OK", &ok).await;
+        });
+
+        sandbox.start_sandbox(None, 512, 1.0, 180.0).await.unwrap();
+        assert!(sandbox.is_started);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_always_ask_the_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("web")
+            .build();
+
+        let server = tokio::spawn(async move {
+            let ok = json!({ "jsonrpc": "2.0", "id": "1", "result": "done" });
+            for _ in 0..3 {
+                serve_with_status(&listener, "200 OK", &ok).await;
+            }
+        });
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        sandbox.pause().await.unwrap();
+        assert!(sandbox.is_paused);
+
+        // Nothing is sent while the sandbox is paused
+        let err = sandbox.run_code("python", "print(1)").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::Paused)
+        ));
+        let err = sandbox.run_command(&["ls".to_string()]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::Paused)
+        ));
+
+        // Pausing again still reaches the server, which may have had it resumed since
+        sandbox.pause().await.unwrap();
+        sandbox.resume().await.unwrap();
+        assert!(!sandbox.is_paused);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_start_reads_whether_the_sandbox_is_paused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("web")
+            .idempotency_key("deploy-42")
+            .build();
+
+        let server = tokio::spawn(async move {
+            let paused = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": {
+                    "message": "Sandbox web is already running",
+                    "warnings": [],
+                    "paused": true,
+                },
+            });
+            serve_with_status(&listener, "200 OK", &paused).await;
+        });
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.start_sandbox(None, 512, 1.0, 180.0).await.unwrap();
+        server.await.unwrap();
+        assert!(sandbox.is_started && sandbox.is_paused);
+
+        let err = sandbox.run_code("python", "print(1)").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::Paused)
+        ));
+    }
+
+    #[test]
+    fn test_names_derived_from_idempotency_keys_keep_the_whole_uuid() {
+        let name_for = |key: &str| {
+            SandboxBase::new(&SandboxOptions::builder().idempotency_key(key).build())
+                .name
+                .clone()
+        };
+
+        let name = name_for("deploy-42");
+        assert_eq!(name, name_for("deploy-42"));
+        assert_ne!(name, name_for("deploy-43"));
+        assert_eq!(
+            name,
+            format!(
+                "sandbox-{}",
+                Uuid::new_v5(&Uuid::NAMESPACE_OID, b"deploy-42").simple()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fractional_cpus_are_rounded_for_servers_without_fractions() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("web")
+            .build();
+        let mut sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(async move {
+            let info = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": { "version": "0.2.6", "methods": [], "capabilities": ["ulimits"] },
+            });
+            serve_with_status(&listener, "200 OK", &info).await;
+
+            let ok = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": { "message": "Sandbox web started successfully", "warnings": [] },
+            });
+            serve_with_status(&listener, "200 OK", &ok).await
+        });
+
+        let outcome = sandbox.start_sandbox(None, 512, 0.4, 180.0).await.unwrap();
+
+        let params = server.await.unwrap();
+        assert_eq!(params["config"]["cpus"], 1);
+        assert_eq!(outcome.warnings.len(), 1);
+        assert_eq!(outcome.warnings[0].code, WARNING_CPUS_ROUNDED);
+        assert!(!outcome.has_fatal_warning());
+    }
+
+    #[tokio::test]
+    async fn test_fractional_cpus_are_sent_to_servers_that_apply_them() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .build();
+        let sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(async move {
+            let info = json!({
+                "jsonrpc": "2.0",
+                "id": "1",
+                "result": { "version": "0.3.0", "methods": [], "capabilities": ["fractional_cpus"] },
+            });
+            serve_with_status(&listener, "200 OK", &info).await;
+        });
+
+        let (cpus, warning) = sandbox.cpus_for_server(0.5).await.unwrap();
+        server.await.unwrap();
+        assert_eq!(cpus, json!(0.5));
+        assert!(warning.is_none());
+
+        // Whole counts don't need the server's capabilities
+        assert_eq!(
+            sandbox.cpus_for_server(2.0).await.unwrap(),
+            (json!(2), None)
+        );
+        assert!(sandbox.cpus_for_server(0.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_requests_are_retried_on_server_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let policy = RetryPolicy::new(2)
+            .initial_backoff(Duration::from_millis(10))
+            .jitter(false);
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .retry_policy(policy)
+            .build();
+        let sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(async move {
+            let ok = json!({ "jsonrpc": "2.0", "id": "1", "result": [] });
+            let unavailable = json!({ "message": "unavailable" });
+            let error = json!({ "jsonrpc": "2.0", "id": "1", "error": { "message": "nope" } });
+
+            // Recovers after two 5xx responses
+            serve_with_status(&listener, "503 Service Unavailable", &unavailable).await;
+            serve_with_status(&listener, "502 Bad Gateway", &unavailable).await;
+            serve_with_status(&listener, "200 OK", &ok).await;
+
+            // Gives up after the configured retries
+            for _ in 0..3 {
+                serve_with_status(&listener, "503 Service Unavailable", &unavailable).await;
+            }
+
+            // JSON-RPC errors and non-idempotent methods are sent once
+            serve_with_status(&listener, "200 OK", &error).await;
+            serve_with_status(&listener, "503 Service Unavailable", &unavailable).await;
+        });
+
+        let languages: Value = sandbox
+            .make_request("server.languages", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(languages, json!([]));
+
+        let err = sandbox
+            .make_request::<Value>("server.languages", json!({}))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<SandboxError>().unwrap();
+        assert_eq!(err.retries(), 2);
+        assert!(err.to_string().contains("after 2 retries"));
+
+        let err = sandbox
+            .make_request::<Value>("server.languages", json!({}))
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<SandboxError>().unwrap().retries(), 0);
+
+        let err = sandbox
+            .make_request::<Value>("sandbox.repl.run", json!({}))
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<SandboxError>().unwrap().retries(), 0);
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_truncated_responses_are_malformed_and_retried() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let policy = RetryPolicy::new(1)
+            .initial_backoff(Duration::from_millis(10))
+            .jitter(false);
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .retry_policy(policy)
+            .build();
+        let sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(async move {
+            // Each body promises more bytes than it sends before the connection closes
+            let truncated = |body: &str| {
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len() + 10,
+                    body
+                )
+            };
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+                read_request(&mut stream).await;
+                stream
+                    .write_all(truncated(r#"{"jsonrpc":"2.0","id":"1","#).as_bytes())
+                    .await
+                    .unwrap();
+            }
+
+            let ok = json!({ "jsonrpc": "2.0", "id": "1", "result": [] });
+            serve_with_status(&listener, "200 OK", &ok).await;
+        });
+
+        // The first request gives up after its one retry, reporting the body as malformed
+        let err = sandbox
+            .make_request::<Value>("server.languages", json!({}))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<SandboxError>().unwrap();
+        assert_eq!(err.retries(), 1);
+        assert!(
+            matches!(
+                err,
+                SandboxError::RetriesExhausted { source, .. }
+                    if matches!(
+                        source.downcast_ref::<SandboxError>(),
+                        Some(SandboxError::MalformedResponse { .. })
+                    )
+            ),
+            "{:?}",
+            err
+        );
+
+        let languages: Value = sandbox
+            .make_request("server.languages", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(languages, json!([]));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retry_predicate_overrides_the_default_classification() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let predicate: RetryPredicate = Arc::new(|error| match error {
+            SandboxError::Rpc(error) => error.message.contains("busy"),
+            SandboxError::HttpStatus { status, .. } => *status == 429,
+            _ => false,
+        });
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .retry_policy(
+                RetryPolicy::new(2)
+                    .initial_backoff(Duration::from_millis(10))
+                    .jitter(false),
+            )
+            .retry_predicate(predicate)
+            .build();
+        let sandbox = SandboxBase::new(&options);
+
+        let server = tokio::spawn(async move {
+            let ok = json!({ "jsonrpc": "2.0", "id": "1", "result": [] });
+            let busy = json!({ "jsonrpc": "2.0", "id": "1", "error": { "message": "busy" } });
+            let unavailable = json!({ "message": "unavailable" });
+
+            // A JSON-RPC error and a 429 the predicate accepts are retried
+            serve_with_status(&listener, "200 OK", &busy).await;
+            serve_with_status(&listener, "429 Too Many Requests", &unavailable).await;
+            serve_with_status(&listener, "200 OK", &ok).await;
+
+            // A 5xx the predicate rejects is not
+            serve_with_status(&listener, "503 Service Unavailable", &unavailable).await;
+        });
+
+        let languages: Value = sandbox
+            .make_request("server.languages", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(languages, json!([]));
+
+        let err = sandbox
+            .make_request::<Value>("server.languages", json!({}))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<SandboxError>().unwrap();
+        assert_eq!(err.retries(), 0);
+        assert!(matches!(err, SandboxError::HttpStatus { status: 503, .. }));
+
+        server.await.unwrap();
     }
 }
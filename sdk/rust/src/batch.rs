@@ -0,0 +1,133 @@
+//! Sending several JSON-RPC calls to the server in one request
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use serde_json::{json, Value};
+
+use crate::base::{read_response_json, rpc_error};
+use crate::{SandboxBase, SandboxError};
+
+/// A JSON-RPC call sent as part of a batch with [`SandboxBase::batch`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcCall {
+    /// Method of the call, e.g. `sandbox.repl.run`
+    pub(crate) method: String,
+
+    /// Params of the call
+    pub(crate) params: Value,
+}
+
+impl RpcCall {
+    /// Create a call of `method` with `params`
+    ///
+    /// If `params` is an object without a `sandbox` or `namespace` field, the batch fills them
+    /// in with the sandbox and namespace of the handle sending it.
+    pub fn new(method: impl Into<String>, params: Value) -> Self {
+        Self {
+            method: method.into(),
+            params,
+        }
+    }
+
+    /// Create a call running `code` in the sandbox's REPL for `language`
+    ///
+    /// Its result is the same JSON as [`Execution`](crate::Execution) is built from.
+    pub fn run_code(language: &str, code: &str) -> Self {
+        Self::new(
+            "sandbox.repl.run",
+            json!({
+                "language": language,
+                "code": code,
+            }),
+        )
+    }
+}
+
+impl SandboxBase {
+    /// Send several calls to the server in a single HTTP request
+    ///
+    /// The calls go out as one JSON-RPC batch, saving a round trip per call. The server runs
+    /// them one after another in the order given, and the returned results are in the same
+    /// order as `calls`, whatever order the server answers them in. Each call succeeds or fails
+    /// on its own: a call the server rejects gets an error in its place without affecting the
+    /// others. The outer result only fails if the batch as a whole couldn't be sent or its
+    /// response couldn't be read, e.g. when the server doesn't accept batches.
+    ///
+    /// Batches aren't retried, and calls in them don't check that the sandbox was started by
+    /// this handle.
+    pub async fn batch(
+        &self,
+        calls: Vec<RpcCall>,
+    ) -> Result<Vec<Result<Value, SandboxError>>, Box<dyn Error + Send + Sync>> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Ids only have to be unique within the batch, so the call's index is used
+        let requests: Vec<Value> = calls
+            .into_iter()
+            .enumerate()
+            .map(|(index, call)| {
+                let mut params = call.params;
+                if let Some(params) = params.as_object_mut() {
+                    params.entry("sandbox").or_insert_with(|| json!(self.name));
+                    params
+                        .entry("namespace")
+                        .or_insert_with(|| json!(self.namespace));
+                }
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": call.method,
+                    "params": params,
+                    "id": index.to_string(),
+                })
+            })
+            .collect();
+        let count = requests.len();
+
+        let body = serde_json::to_vec(&requests)?;
+        if body.len() > self.max_request_body_size {
+            return Err(Box::new(SandboxError::InvalidInput(format!(
+                "batch request body is {} bytes, which exceeds the {} byte limit",
+                body.len(),
+                self.max_request_body_size
+            ))));
+        }
+
+        let response = self
+            .send_body("batch", body, None)
+            .await
+            .map_err(|failed| failed.error)?;
+        let response_data = read_response_json(response).await?;
+        self.log_response("batch", &response_data);
+
+        let Value::Array(responses) = response_data else {
+            return Err(Box::new(SandboxError::ServerError(
+                "expected an array of responses to the batch".to_string(),
+            )));
+        };
+
+        // Correlate the responses with the calls by id
+        let mut responses: HashMap<String, Value> = responses
+            .into_iter()
+            .filter_map(|response| {
+                let id = response.get("id")?.as_str()?.to_string();
+                Some((id, response))
+            })
+            .collect();
+
+        Ok((0..count)
+            .map(|index| match responses.remove(&index.to_string()) {
+                Some(response) => match response.get("error") {
+                    Some(error) => Err(rpc_error(error)),
+                    None => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+                },
+                None => Err(SandboxError::ServerError(format!(
+                    "no response to call {} of the batch",
+                    index
+                ))),
+            })
+            .collect())
+    }
+}
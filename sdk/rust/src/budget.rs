@@ -0,0 +1,171 @@
+//! Shared request budget for sandboxes
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Source of the current time, injectable so time-based behavior can be tested
+pub trait Clock: Send + Sync {
+    /// Get the current instant
+    fn now(&self) -> Instant;
+}
+
+/// Clock backed by [`Instant::now`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+/// A token bucket that bounds the aggregate request rate of the sandboxes sharing it
+///
+/// Every request made by a sandbox draws one token. The bucket holds at most `capacity`
+/// tokens and refills continuously at `refill_per_second`. When it is empty, requests wait
+/// for the next token instead of hitting the server, so many sandboxes retrying against a
+/// struggling server can't turn into a retry storm.
+///
+/// Share one budget across sandboxes by passing the same `Arc` to each sandbox's options.
+pub struct RetryBudget {
+    capacity: f64,
+    refill_per_second: f64,
+    clock: Arc<dyn Clock>,
+    state: Mutex<BucketState>,
+}
+
+/// Mutable state of a token bucket
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl RetryBudget {
+    /// Create a full budget of `capacity` tokens refilling at `refill_per_second`
+    pub fn new(capacity: u32, refill_per_second: f64) -> Self {
+        Self::with_clock(capacity, refill_per_second, Arc::new(SystemClock))
+    }
+
+    /// Create a budget that reads time from the given clock
+    pub fn with_clock(capacity: u32, refill_per_second: f64, clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        Self {
+            capacity: capacity as f64,
+            refill_per_second: refill_per_second.max(0.0),
+            clock,
+            state: Mutex::new(BucketState {
+                tokens: capacity as f64,
+                last_refill: now,
+            }),
+        }
+    }
+
+    /// Maximum number of tokens the bucket holds
+    pub fn capacity(&self) -> u32 {
+        self.capacity as u32
+    }
+
+    /// Number of whole tokens currently available
+    pub fn available(&self) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state);
+        state.tokens as u32
+    }
+
+    /// Take a token if one is available
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_or_wait().is_ok()
+    }
+
+    /// Take a token, waiting for the bucket to refill if it's empty
+    pub async fn acquire(&self) {
+        while let Err(wait) = self.try_acquire_or_wait() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take a token, or return how long until the next one is available
+    fn try_acquire_or_wait(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state);
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            return Ok(());
+        }
+
+        if self.refill_per_second == 0.0 {
+            // Never refills, so poll occasionally rather than sleeping forever
+            return Err(Duration::from_secs(1));
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - state.tokens) / self.refill_per_second,
+        ))
+    }
+
+    /// Add the tokens accrued since the last refill
+    fn refill(&self, state: &mut BucketState) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(state.last_refill);
+        state.tokens =
+            (state.tokens + elapsed.as_secs_f64() * self.refill_per_second).min(self.capacity);
+        state.last_refill = now;
+    }
+}
+
+impl fmt::Debug for RetryBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryBudget")
+            .field("capacity", &self.capacity)
+            .field("refill_per_second", &self.refill_per_second)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clock that only moves when advanced
+    struct ManualClock(Mutex<Instant>);
+
+    impl ManualClock {
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_budget_drains_and_refills() {
+        let clock = Arc::new(ManualClock(Mutex::new(Instant::now())));
+        let budget = RetryBudget::with_clock(2, 1.0, clock.clone());
+
+        assert!(budget.try_acquire());
+        assert!(budget.try_acquire());
+        assert!(!budget.try_acquire());
+
+        clock.advance(Duration::from_millis(500));
+        assert!(!budget.try_acquire());
+
+        clock.advance(Duration::from_millis(500));
+        assert!(budget.try_acquire());
+        assert!(!budget.try_acquire());
+    }
+
+    #[test]
+    fn test_budget_refill_is_capped_at_capacity() {
+        let clock = Arc::new(ManualClock(Mutex::new(Instant::now())));
+        let budget = RetryBudget::with_clock(3, 10.0, clock.clone());
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(budget.available(), 3);
+    }
+}
@@ -1,5 +1,14 @@
 //! Builder pattern implementation for sandbox options
 
+use std::{sync::Arc, time::Duration};
+
+use crate::retry::RetryClassifier;
+use crate::{tls, Pem};
+use crate::{
+    Auth, InlineFile, Language, ProbeSpec, RequestLogging, RetryBudget, RetryPolicy,
+    RetryPredicate, SandboxError, SecurityProfile, Ulimit,
+};
+
 /// Options for creating a sandbox
 #[derive(Debug, Clone)]
 pub struct SandboxOptions {
@@ -14,6 +23,90 @@ pub struct SandboxOptions {
 
     /// API key for Microsandbox server authentication
     pub(crate) api_key: Option<String>,
+
+    /// Authentication scheme for the Microsandbox server, used instead of an API key
+    pub(crate) auth: Option<Auth>,
+
+    /// Client certificate presented to the server for mutual TLS
+    pub(crate) client_cert: Option<Pem>,
+
+    /// Private key of the client certificate
+    pub(crate) client_key: Option<Pem>,
+
+    /// CA certificates trusted for the server's certificate, instead of the system's roots
+    pub(crate) ca_bundle: Option<Pem>,
+
+    /// Maximum number of idle connections kept open to the server
+    pub(crate) pool_max_idle_per_host: Option<usize>,
+
+    /// How long an idle connection to the server is kept open
+    pub(crate) pool_idle_timeout: Option<Duration>,
+
+    /// Interval of TCP keepalive probes on connections to the server
+    pub(crate) tcp_keepalive: Option<Duration>,
+
+    /// Idempotency key used to derive a deterministic sandbox name
+    pub(crate) idempotency_key: Option<String>,
+
+    /// Maximum number of running sandboxes allowed in the namespace before starting
+    pub(crate) max_sandboxes_per_namespace: Option<usize>,
+
+    /// Resource limits to apply in the guest
+    pub(crate) ulimits: Vec<Ulimit>,
+
+    /// Maximum size in bytes of a serialized request body
+    pub(crate) max_request_body_size: Option<usize>,
+
+    /// Code run once right after the sandbox starts
+    pub(crate) init_code: Option<(Language, String)>,
+
+    /// Hostname of the guest
+    pub(crate) hostname: Option<String>,
+
+    /// OOM score adjustment of the sandbox process
+    pub(crate) oom_score_adj: Option<i32>,
+
+    /// Maximum number of executions the server runs in the sandbox at once
+    pub(crate) max_concurrent_executions: Option<usize>,
+
+    /// Probe that tells when the application in the sandbox is ready
+    pub(crate) readiness_probe: Option<ProbeSpec>,
+
+    /// Request budget shared with other sandboxes
+    pub(crate) retry_budget: Option<Arc<RetryBudget>>,
+
+    /// How idempotent requests are retried after transient failures
+    pub(crate) retry_policy: Option<RetryPolicy>,
+
+    /// Decides which failures of idempotent requests are retried
+    pub(crate) retry_predicate: Option<RetryClassifier>,
+
+    /// Default timeout for executions
+    pub(crate) execution_timeout: Option<Duration>,
+
+    /// Whether to return the output produced before a timeout instead of failing
+    pub(crate) partial_output_on_timeout: bool,
+
+    /// Time limit for pulling the image during start
+    pub(crate) pull_timeout: Option<Duration>,
+
+    /// Time limit for booting the sandbox during start
+    pub(crate) boot_timeout: Option<Duration>,
+
+    /// Time limit for the sandbox to become ready during start
+    pub(crate) ready_timeout: Option<Duration>,
+
+    /// Debug logging of request and response bodies
+    pub(crate) request_logging: Option<RequestLogging>,
+
+    /// Capabilities dropped and seccomp profile applied in the guest
+    pub(crate) security: Option<SecurityProfile>,
+
+    /// Files written into the guest right after start
+    pub(crate) files: Vec<InlineFile>,
+
+    /// Whether a started sandbox is stopped when its handle is dropped
+    pub(crate) stop_on_drop: bool,
 }
 
 /// Builder for sandbox options
@@ -23,6 +116,34 @@ pub struct SandboxOptionsBuilder {
     namespace: Option<String>,
     name: Option<String>,
     api_key: Option<String>,
+    auth: Option<Auth>,
+    client_cert: Option<Pem>,
+    client_key: Option<Pem>,
+    ca_bundle: Option<Pem>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    tcp_keepalive: Option<Duration>,
+    idempotency_key: Option<String>,
+    max_sandboxes_per_namespace: Option<usize>,
+    ulimits: Vec<Ulimit>,
+    max_request_body_size: Option<usize>,
+    init_code: Option<(Language, String)>,
+    hostname: Option<String>,
+    oom_score_adj: Option<i32>,
+    max_concurrent_executions: Option<usize>,
+    readiness_probe: Option<ProbeSpec>,
+    retry_budget: Option<Arc<RetryBudget>>,
+    retry_policy: Option<RetryPolicy>,
+    retry_predicate: Option<RetryClassifier>,
+    execution_timeout: Option<Duration>,
+    partial_output_on_timeout: bool,
+    pull_timeout: Option<Duration>,
+    boot_timeout: Option<Duration>,
+    ready_timeout: Option<Duration>,
+    request_logging: Option<RequestLogging>,
+    security: Option<SecurityProfile>,
+    files: Vec<InlineFile>,
+    stop_on_drop: bool,
 }
 
 impl SandboxOptions {
@@ -30,6 +151,21 @@ impl SandboxOptions {
     pub fn builder() -> SandboxOptionsBuilder {
         SandboxOptionsBuilder::default()
     }
+
+    /// Check that the options don't conflict, before a sandbox is created from them
+    pub(crate) fn validate(&self) -> Result<(), SandboxError> {
+        if let Some(auth) = &self.auth {
+            if self.api_key.is_some() {
+                return Err(SandboxError::InvalidInput(format!(
+                    "an API key and {} authentication can't both be set",
+                    auth.scheme()
+                )));
+            }
+            auth.validate()?;
+        }
+        tls::validate(self)?;
+        Ok(())
+    }
 }
 
 impl SandboxOptionsBuilder {
@@ -57,6 +193,293 @@ impl SandboxOptionsBuilder {
         self
     }
 
+    /// Set how requests to the server are authenticated
+    ///
+    /// Use this instead of [`api_key`](Self::api_key) when the server sits behind a gateway
+    /// that expects an HMAC signature or custom headers. Setting both is rejected with
+    /// [`SandboxError::InvalidInput`] when the sandbox is created. When set, `MSB_API_KEY` is
+    /// ignored.
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Set the client certificate presented to the server for mutual TLS
+    ///
+    /// Must be set along with [`client_key`](Self::client_key). The certificate may be
+    /// followed by the intermediate certificates of its chain.
+    pub fn client_cert(mut self, cert: Pem) -> Self {
+        self.client_cert = Some(cert);
+        self
+    }
+
+    /// Set the private key of the client certificate, in PKCS#8 PEM format
+    pub fn client_key(mut self, key: Pem) -> Self {
+        self.client_key = Some(key);
+        self
+    }
+
+    /// Set the CA certificates the server's certificate is checked against
+    ///
+    /// The bundle replaces the system's root certificates, which are used when it isn't set.
+    /// A bundle or client certificate that can't be read or parsed fails every request with
+    /// [`SandboxError::InvalidInput`].
+    pub fn ca_bundle(mut self, bundle: Pem) -> Self {
+        self.ca_bundle = Some(bundle);
+        self
+    }
+
+    /// Set the maximum number of idle connections kept open to the server
+    ///
+    /// Requests reuse idle connections from the handle's pool instead of opening new ones.
+    /// Lower it when many sandboxes are driven from one process and idle sockets pile up, or
+    /// set it to zero to open a connection per request. Defaults to no limit.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Set how long an idle connection to the server is kept open for reuse
+    ///
+    /// Defaults to 90 seconds.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Send TCP keepalive probes at this interval on connections to the server
+    ///
+    /// Keeps long-running requests, such as executions, from being dropped by proxies or
+    /// load balancers that close silent connections. Defaults to no keepalive probes.
+    pub fn tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Set the idempotency key
+    ///
+    /// When no explicit name is given, the sandbox name is derived deterministically from
+    /// this key, so re-applying the same spec attaches to the existing sandbox instead of
+    /// creating a duplicate. The name is `sandbox-` followed by the key's whole name-based
+    /// UUID; earlier versions used only its first eight digits, so sandboxes they started get
+    /// a new name.
+    ///
+    /// The key is sent with every start. Without one, each start makes up its own key, which
+    /// is enough for the retries of that start, with a [retry policy](Self::retry_policy)
+    /// set, to get back the sandbox an attempt that timed out on the client still started.
+    pub fn idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Set the maximum number of running sandboxes allowed in the namespace
+    ///
+    /// This is a best-effort, client-side guardrail: before each start, the sandboxes in the
+    /// namespace are listed and the start fails with
+    /// [`SandboxError::ResourceExhausted`](crate::SandboxError::ResourceExhausted) if `max`
+    /// of them, other than this one, are running or paused. The server has no such limit, so
+    /// concurrent clients can race past it.
+    pub fn max_sandboxes_per_namespace(mut self, max: usize) -> Self {
+        self.max_sandboxes_per_namespace = Some(max);
+        self
+    }
+
+    /// Add a resource limit to apply in the guest
+    ///
+    /// Names are validated when the sandbox is started; limits the backend can't set are
+    /// rejected with [`SandboxError::Unsupported`](crate::SandboxError::Unsupported).
+    pub fn ulimit(mut self, ulimit: Ulimit) -> Self {
+        self.ulimits.push(ulimit);
+        self
+    }
+
+    /// Set the maximum size in bytes of a serialized request body
+    ///
+    /// Requests over the limit fail with
+    /// [`SandboxError::InvalidInput`](crate::SandboxError::InvalidInput) before anything is
+    /// sent. Defaults to 2 MiB, the server's request body limit.
+    pub fn max_request_body_size(mut self, size: usize) -> Self {
+        self.max_request_body_size = Some(size);
+        self
+    }
+
+    /// Set code to run once right after the sandbox starts
+    ///
+    /// The code runs before `start` returns, so every later execution sees the initialized
+    /// environment. If it fails, the sandbox is stopped and `start` returns the error.
+    pub fn init_code(mut self, language: Language, code: impl Into<String>) -> Self {
+        self.init_code = Some((language, code.into()));
+        self
+    }
+
+    /// Set the hostname of the guest
+    ///
+    /// The hostname must follow RFC 1123; invalid names are rejected with
+    /// [`SandboxError::InvalidInput`](crate::SandboxError::InvalidInput) when the sandbox is
+    /// started. Without it, the guest keeps its default hostname.
+    pub fn hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = Some(hostname.into());
+        self
+    }
+
+    /// Set the OOM score adjustment of the sandbox process
+    ///
+    /// Ranges from `-1000` (never killed under memory pressure) to `1000` (killed first), like
+    /// Linux's `oom_score_adj`. Values outside that range are rejected with
+    /// [`SandboxError::InvalidInput`](crate::SandboxError::InvalidInput) when the sandbox is
+    /// started. Lowering the score needs the server to run with enough privileges; otherwise
+    /// the server logs it and the sandbox keeps the default score.
+    pub fn oom_score_adj(mut self, oom_score_adj: i32) -> Self {
+        self.oom_score_adj = Some(oom_score_adj);
+        self
+    }
+
+    /// Ask the server to run at most this many executions in the sandbox at once
+    ///
+    /// Executions over the limit fail with
+    /// [`SandboxError::ResourceExhausted`](crate::SandboxError::ResourceExhausted) instead of
+    /// queueing on the server. Zero is rejected with
+    /// [`SandboxError::InvalidInput`](crate::SandboxError::InvalidInput) when the sandbox is
+    /// started. Read the limit in effect with
+    /// [`max_concurrent_executions`](crate::SandboxBase::max_concurrent_executions).
+    pub fn max_concurrent_executions(mut self, max: usize) -> Self {
+        self.max_concurrent_executions = Some(max);
+        self
+    }
+
+    /// Set a probe that tells when the application in the sandbox is ready
+    ///
+    /// Starting the sandbox runs the probe in the guest until it passes, and fails with
+    /// [`SandboxError::NotReady`](crate::SandboxError::NotReady) if it never does, leaving the
+    /// sandbox running. [`wait_until_ready`](crate::SandboxBase::wait_until_ready) runs it again
+    /// on demand. Without it, only the generic check that the guest answers requests is used.
+    pub fn readiness_probe(mut self, probe: ProbeSpec) -> Self {
+        self.readiness_probe = Some(probe);
+        self
+    }
+
+    /// Set a request budget that bounds the rate of requests to the server
+    ///
+    /// Pass the same budget to several sandboxes to bound their aggregate request rate.
+    /// Requests wait for a token when the budget is exhausted.
+    pub fn retry_budget(mut self, budget: Arc<RetryBudget>) -> Self {
+        self.retry_budget = Some(budget);
+        self
+    }
+
+    /// Retry idempotent requests that fail to reach the server or get a 5xx response
+    ///
+    /// Defaults to no retries.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Set which failures are retried under the retry policy
+    ///
+    /// The predicate sees every failed attempt, including JSON-RPC errors returned by the
+    /// server, and replaces [`default_retry_predicate`](crate::default_retry_predicate). It
+    /// only applies to the idempotent methods the retry policy covers; other requests are
+    /// still sent once.
+    pub fn retry_predicate(mut self, predicate: RetryPredicate) -> Self {
+        self.retry_predicate = Some(RetryClassifier(predicate));
+        self
+    }
+
+    /// Set the default timeout applied to every execution
+    ///
+    /// The server cancels the execution once the timeout elapses. The client waits a little
+    /// longer for the response before failing with
+    /// [`SandboxError::Timeout`](crate::SandboxError::Timeout). It can be changed later with
+    /// [`set_default_execution_timeout`](crate::SandboxBase::set_default_execution_timeout).
+    /// Defaults to no timeout.
+    pub fn execution_timeout(mut self, timeout: Duration) -> Self {
+        self.execution_timeout = Some(timeout);
+        self
+    }
+
+    /// Set whether to return the output produced before a timeout instead of failing
+    ///
+    /// When `run_code` times out, the output the REPL produced before the deadline is read
+    /// back from the server and returned as an [`Execution`](crate::Execution) with
+    /// [`timed_out`](crate::Execution::timed_out) set. If that output can't be read, the
+    /// timeout error is returned as usual. Defaults to `false`.
+    pub fn partial_output_on_timeout(mut self, enabled: bool) -> Self {
+        self.partial_output_on_timeout = enabled;
+        self
+    }
+
+    /// Set the time limit for pulling the sandbox's image during start
+    ///
+    /// With a limit set, the server pulls the image before booting the sandbox, and a start
+    /// that exceeds it fails with
+    /// [`SandboxError::PhaseTimeout`](crate::SandboxError::PhaseTimeout) for
+    /// [`StartPhase::Pull`](crate::StartPhase::Pull). Defaults to the server's behavior.
+    pub fn pull_timeout(mut self, timeout: Duration) -> Self {
+        self.pull_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the time limit for booting the sandbox during start
+    ///
+    /// Covers launching the sandbox until its microVM is running. Without a limit, the
+    /// server reports a slow boot as a start warning instead of failing. Defaults to the
+    /// server's behavior.
+    pub fn boot_timeout(mut self, timeout: Duration) -> Self {
+        self.boot_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the time limit for the booted sandbox to accept requests during start
+    ///
+    /// Defaults to not waiting: the first request waits for the sandbox instead.
+    pub fn ready_timeout(mut self, timeout: Duration) -> Self {
+        self.ready_timeout = Some(timeout);
+        self
+    }
+
+    /// Log the JSON-RPC bodies exchanged with the server, with secrets redacted
+    ///
+    /// Bodies are logged as `tracing` debug events with the `microsandbox::rpc` target.
+    /// Defaults to no logging.
+    pub fn request_logging(mut self, logging: RequestLogging) -> Self {
+        self.request_logging = Some(logging);
+        self
+    }
+
+    /// Drop capabilities and apply a seccomp profile in the guest
+    ///
+    /// The profile is validated when the sandbox is started, and the start fails with
+    /// [`SandboxError::Unsupported`] if the server can't apply it. Defaults to the
+    /// backend's own capabilities and seccomp policy.
+    pub fn security(mut self, profile: SecurityProfile) -> Self {
+        self.security = Some(profile);
+        self
+    }
+
+    /// Add a file written into the guest right after the sandbox starts
+    ///
+    /// Files are written in the order they were added, before the init code runs. Paths must
+    /// be absolute and the files may total at most 1MiB; otherwise starting the sandbox fails
+    /// with [`SandboxError::InvalidInput`] before anything is sent.
+    pub fn file(mut self, file: InlineFile) -> Self {
+        self.files.push(file);
+        self
+    }
+
+    /// Stop the sandbox when its handle is dropped while it's still started
+    ///
+    /// `Drop` can't wait for a request, so the stop is spawned as a best-effort task on the
+    /// current Tokio runtime and the handle doesn't learn whether it succeeded. No stop is sent
+    /// if the handle is dropped outside a runtime, and the task may not finish if the runtime
+    /// shuts down right after, as at the end of `main`. Call
+    /// [`SandboxBase::close`](crate::SandboxBase::close) where the sandbox is known to be done
+    /// with. Defaults to leaving the sandbox running.
+    pub fn stop_on_drop(mut self, stop_on_drop: bool) -> Self {
+        self.stop_on_drop = stop_on_drop;
+        self
+    }
+
     /// Build the SandboxOptions
     pub fn build(self) -> SandboxOptions {
         SandboxOptions {
@@ -64,6 +487,34 @@ impl SandboxOptionsBuilder {
             namespace: self.namespace,
             name: self.name,
             api_key: self.api_key,
+            auth: self.auth,
+            client_cert: self.client_cert,
+            client_key: self.client_key,
+            ca_bundle: self.ca_bundle,
+            pool_max_idle_per_host: self.pool_max_idle_per_host,
+            pool_idle_timeout: self.pool_idle_timeout,
+            tcp_keepalive: self.tcp_keepalive,
+            idempotency_key: self.idempotency_key,
+            max_sandboxes_per_namespace: self.max_sandboxes_per_namespace,
+            ulimits: self.ulimits,
+            max_request_body_size: self.max_request_body_size,
+            init_code: self.init_code,
+            hostname: self.hostname,
+            oom_score_adj: self.oom_score_adj,
+            max_concurrent_executions: self.max_concurrent_executions,
+            readiness_probe: self.readiness_probe,
+            retry_budget: self.retry_budget,
+            retry_policy: self.retry_policy,
+            retry_predicate: self.retry_predicate,
+            execution_timeout: self.execution_timeout,
+            partial_output_on_timeout: self.partial_output_on_timeout,
+            pull_timeout: self.pull_timeout,
+            boot_timeout: self.boot_timeout,
+            ready_timeout: self.ready_timeout,
+            request_logging: self.request_logging,
+            security: self.security,
+            files: self.files,
+            stop_on_drop: self.stop_on_drop,
         }
     }
 }
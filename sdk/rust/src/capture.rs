@@ -0,0 +1,185 @@
+//! Capturing command output to files on the host
+
+use std::error::Error;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+use crate::{SandboxBase, SandboxError};
+
+/// How to open the host file that captured output is written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteMode {
+    /// Replace any existing contents of the file
+    #[default]
+    Truncate,
+    /// Add to the end of the file, keeping its existing contents
+    Append,
+}
+
+/// A single newline-delimited frame of a `sandbox.command.stream` response
+#[derive(Debug, Deserialize)]
+struct StreamFrame {
+    /// Text of an output line, without its trailing newline
+    text: Option<String>,
+
+    /// Exit code of the command, sent in the final frame
+    exit_code: Option<i32>,
+
+    /// Error that ended the command early
+    error: Option<StreamFrameError>,
+}
+
+/// Error reported in a `sandbox.command.stream` frame
+#[derive(Debug, Deserialize)]
+struct StreamFrameError {
+    message: String,
+}
+
+/// Splits a chunked body into complete newline-delimited frames
+#[derive(Debug, Default)]
+pub(crate) struct FrameDecoder {
+    pending: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// Add a chunk of the body and return the frames it completes
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<Vec<u8>> {
+        self.pending.extend_from_slice(chunk);
+
+        let mut frames = Vec::new();
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let frame: Vec<u8> = self.pending.drain(..=end).collect();
+            let frame = &frame[..frame.len() - 1];
+            if !frame.iter().all(u8::is_ascii_whitespace) {
+                frames.push(frame.to_vec());
+            }
+        }
+        frames
+    }
+
+    /// Number of bytes received that aren't part of a complete frame yet
+    pub(crate) fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl SandboxBase {
+    /// Run a command in the sandbox, writing its output to a file on the host as it arrives
+    ///
+    /// Output is read from the `sandbox.command.stream` endpoint, whose response body is a
+    /// series of newline-delimited JSON frames, and each line is written to `host_path` as
+    /// soon as it is received, so the full output is never held in memory. Lines from stdout
+    /// and stderr are interleaved in the order the server sends them. `mode` controls whether
+    /// an existing file is truncated or appended to.
+    ///
+    /// Returns the command's exit code.
+    pub async fn run_command_to_file(
+        &self,
+        command: &str,
+        args: &[&str],
+        host_path: impl AsRef<Path>,
+        mode: WriteMode,
+    ) -> Result<i32, Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        if self.is_paused {
+            return Err(Box::new(SandboxError::Paused));
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(mode == WriteMode::Append)
+            .truncate(mode == WriteMode::Truncate)
+            .open(host_path.as_ref())
+            .await?;
+        let mut writer = BufWriter::new(file);
+
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "command": command,
+            "args": args,
+        });
+        let mut response = self.send_request("sandbox.command.stream", params).await?;
+
+        let mut decoder = FrameDecoder::default();
+        let mut received = 0;
+        let exit_code = 'read: loop {
+            let chunk = match response.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => {
+                    return Err(Box::new(SandboxError::MalformedResponse {
+                        received,
+                        message: format!(
+                            "output stream ended before the command exited ({} bytes unframed)",
+                            decoder.pending_len()
+                        ),
+                    }))
+                }
+                Err(e) => {
+                    return Err(Box::new(SandboxError::MalformedResponse {
+                        received,
+                        message: e.to_string(),
+                    }))
+                }
+            };
+            received += chunk.len();
+
+            for frame in decoder.push(&chunk) {
+                let frame: StreamFrame = serde_json::from_slice(&frame).map_err(|e| {
+                    Box::new(SandboxError::MalformedResponse {
+                        received,
+                        message: e.to_string(),
+                    })
+                })?;
+
+                if let Some(error) = frame.error {
+                    writer.flush().await?;
+                    return Err(Box::new(SandboxError::ServerError(error.message)));
+                }
+
+                if let Some(text) = frame.text {
+                    writer.write_all(text.as_bytes()).await?;
+                    writer.write_all(b"\n").await?;
+                }
+
+                if let Some(exit_code) = frame.exit_code {
+                    break 'read exit_code;
+                }
+            }
+
+            // Don't leave output sitting in the buffer while waiting on a slow command
+            writer.flush().await?;
+        };
+
+        writer.flush().await?;
+        Ok(exit_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_decoder_joins_split_frames() {
+        let mut decoder = FrameDecoder::default();
+
+        assert!(decoder.push(b"{\"text\":\"he").is_empty());
+        assert_eq!(
+            decoder.push(b"llo\"}\n\n{\"exit_code\":0}\n{\"te"),
+            vec![
+                b"{\"text\":\"hello\"}".to_vec(),
+                b"{\"exit_code\":0}".to_vec()
+            ]
+        );
+        assert_eq!(decoder.pending_len(), 4);
+    }
+}
@@ -0,0 +1,75 @@
+//! Notebook-style execution of code cells
+
+use std::error::Error;
+use std::future::Future;
+
+use futures::stream::{self, Stream};
+
+use crate::{Execution, Language, SandboxBase};
+
+/// Internal state of a running cell stream
+struct CellState<F> {
+    cells: std::vec::IntoIter<String>,
+    index: usize,
+    continue_on_error: bool,
+    run: F,
+    done: bool,
+}
+
+impl SandboxBase {
+    /// Run code cells in order, yielding each cell's result as it completes
+    ///
+    /// Each item is the cell's index in `cells` along with its execution. Cells share the
+    /// sandbox's REPL, so later cells see the state left by earlier ones. The stream stops
+    /// after the first cell that fails or reports an error, unless `continue_on_error` is set.
+    pub fn run_cells<'a>(
+        &'a self,
+        language: Language,
+        cells: Vec<String>,
+        continue_on_error: bool,
+    ) -> impl Stream<Item = Result<(usize, Execution), Box<dyn Error + Send + Sync>>> + 'a {
+        cell_stream(cells, continue_on_error, move |code| async move {
+            self.run_code(language.as_str(), &code).await
+        })
+    }
+}
+
+/// Build a stream that runs each cell with `run`, in order
+pub(crate) fn cell_stream<F, Fut>(
+    cells: Vec<String>,
+    continue_on_error: bool,
+    run: F,
+) -> impl Stream<Item = Result<(usize, Execution), Box<dyn Error + Send + Sync>>>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<Execution, Box<dyn Error + Send + Sync>>>,
+{
+    let state = CellState {
+        cells: cells.into_iter(),
+        index: 0,
+        continue_on_error,
+        run,
+        done: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+
+        let code = state.cells.next()?;
+        let index = state.index;
+        state.index += 1;
+
+        let result = (state.run)(code).await;
+        let failed = match &result {
+            Ok(execution) => execution.has_error(),
+            Err(_) => true,
+        };
+        if failed && !state.continue_on_error {
+            state.done = true;
+        }
+
+        Some((result.map(|execution| (index, execution)), state))
+    })
+}
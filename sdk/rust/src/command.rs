@@ -3,12 +3,14 @@
 use serde_json::Value;
 use std::collections::HashMap;
 use std::error::Error;
+use std::path::Path;
 use std::sync::Arc;
 
 use tokio::sync::Mutex;
 
 use crate::SandboxBase;
 use crate::SandboxError;
+use crate::WriteMode;
 
 /// Result of a command execution in a sandbox
 #[derive(Debug, Clone)]
@@ -179,15 +181,19 @@ impl Command {
         args: Option<Vec<&str>>,
         timeout: Option<i32>,
     ) -> Result<CommandExecution, Box<dyn Error + Send + Sync>> {
-        let is_started = {
+        let (is_started, is_paused) = {
             let base = self.sandbox.lock().await;
-            base.is_started
+            (base.is_started, base.is_paused)
         };
 
         if !is_started {
             return Err(Box::new(SandboxError::NotStarted));
         }
 
+        if is_paused {
+            return Err(Box::new(SandboxError::Paused));
+        }
+
         // Convert args to strings
         let args_vec = args
             .unwrap_or_default()
@@ -221,4 +227,20 @@ impl Command {
 
         Ok(CommandExecution::new(result))
     }
+
+    /// Execute a shell command in the sandbox, writing its output to a file on the host
+    ///
+    /// See [`SandboxBase::run_command_to_file`]. Returns the command's exit code.
+    pub async fn run_to_file(
+        &self,
+        command: &str,
+        args: Option<Vec<&str>>,
+        host_path: impl AsRef<Path>,
+        mode: WriteMode,
+    ) -> Result<i32, Box<dyn Error + Send + Sync>> {
+        let args = args.unwrap_or_default();
+        let base = self.sandbox.lock().await;
+        base.run_command_to_file(command, &args, host_path, mode)
+            .await
+    }
 }
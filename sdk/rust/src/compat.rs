@@ -0,0 +1,306 @@
+//! Compatibility between the SDK and the Microsandbox server
+
+use std::error::Error;
+use std::fmt;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{SandboxBase, SandboxError, METHOD_NOT_FOUND_CODE};
+
+/// Oldest server version this SDK supports, as (major, minor, patch)
+const MIN_SERVER_VERSION: (u64, u64, u64) = (0, 2, 0);
+
+/// First server version this SDK no longer supports, as (major, minor, patch)
+const MAX_SERVER_VERSION: (u64, u64, u64) = (0, 3, 0);
+
+/// SDK features and the server methods they need: feature, method, and whether the SDK is
+/// unusable without it
+const FEATURES: [(&str, &str, bool); 27] = [
+    ("start_sandbox", "sandbox.start", true),
+    ("stop_sandbox", "sandbox.stop", true),
+    ("run_code", "sandbox.repl.run", true),
+    (
+        "partial output while code runs",
+        "sandbox.repl.partial",
+        false,
+    ),
+    ("flush_repl", "sandbox.repl.flush", false),
+    ("run_code_streaming", "sandbox.repl.stream", false),
+    ("command", "sandbox.command.run", false),
+    ("run_command_to_file", "sandbox.command.stream", false),
+    ("spawn", "sandbox.process.spawn", false),
+    (
+        "get_logs, follow_logs and wait_for_log",
+        "sandbox.logs",
+        false,
+    ),
+    ("pause and resume", "sandbox.pause", false),
+    ("clone_sandbox", "sandbox.clone", false),
+    ("metrics", "sandbox.metrics.get", false),
+    ("health", "sandbox.status", false),
+    ("stats", "sandbox.stats", false),
+    ("list_sandboxes", "sandbox.list", false),
+    ("list_namespaces", "server.namespaces", false),
+    ("describe", "sandbox.env", false),
+    ("inline files and write_file", "sandbox.fs.write", false),
+    ("read_file", "sandbox.fs.read", false),
+    ("list_dir", "sandbox.fs.list", false),
+    ("watch_fs", "sandbox.fs.watch", false),
+    ("fs_snapshot", "sandbox.fs.snapshot", false),
+    ("fs_diff", "sandbox.fs.diff", false),
+    ("snapshot", "sandbox.snapshot.create", false),
+    ("restore", "sandbox.snapshot.restore", false),
+    ("supported_languages", "server.languages", false),
+];
+
+/// How well the SDK and the server work together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compatibility {
+    /// Every SDK feature works with the server
+    Compatible,
+
+    /// The SDK works, but some features are unavailable or can't be checked
+    Degraded,
+
+    /// The SDK can't be used with the server
+    Incompatible,
+}
+
+/// The result of [`SandboxBase::check_compatibility`]
+#[derive(Debug, Clone)]
+pub struct CompatibilityReport {
+    /// Overall compatibility
+    pub compatibility: Compatibility,
+
+    /// Version of the SDK
+    pub sdk_version: String,
+
+    /// Version of the server, if it reports one
+    pub server_version: Option<String>,
+
+    /// Why the SDK and the server are or aren't compatible
+    pub explanation: String,
+
+    /// SDK features the server doesn't support
+    pub unavailable_features: Vec<String>,
+}
+
+/// Response of the `server.info` request
+#[derive(Debug, Deserialize)]
+struct ServerInfoResponse {
+    version: String,
+    methods: Vec<String>,
+
+    /// Optional features the server can apply, missing from servers that predate them
+    #[serde(default)]
+    capabilities: Vec<String>,
+}
+
+impl CompatibilityReport {
+    /// Check whether the SDK can be used with the server, possibly without some features
+    pub fn is_usable(&self) -> bool {
+        self.compatibility != Compatibility::Incompatible
+    }
+
+    /// Build the report from what the server said about itself
+    fn from_server_info(info: ServerInfoResponse) -> Self {
+        let sdk_version = env!("CARGO_PKG_VERSION").to_string();
+
+        let supported = match parse_version(&info.version) {
+            Some(version) => (MIN_SERVER_VERSION..MAX_SERVER_VERSION).contains(&version),
+            None => {
+                return Self {
+                    compatibility: Compatibility::Incompatible,
+                    sdk_version,
+                    explanation: format!("server reported an invalid version '{}'", info.version),
+                    server_version: Some(info.version),
+                    unavailable_features: Vec::new(),
+                };
+            }
+        };
+
+        let missing: Vec<_> = FEATURES
+            .iter()
+            .filter(|(_, method, _)| !info.methods.iter().any(|m| m == method))
+            .collect();
+        let unavailable_features = missing
+            .iter()
+            .map(|(feature, _, _)| feature.to_string())
+            .collect();
+        let missing_required: Vec<_> = missing
+            .iter()
+            .filter(|(_, _, required)| *required)
+            .map(|(_, method, _)| *method)
+            .collect();
+
+        let (compatibility, explanation) = if !supported {
+            (
+                Compatibility::Incompatible,
+                format!(
+                    "server version {} is outside the range supported by SDK {} ({} up to {})",
+                    info.version,
+                    sdk_version,
+                    format_version(MIN_SERVER_VERSION),
+                    format_version(MAX_SERVER_VERSION)
+                ),
+            )
+        } else if !missing_required.is_empty() {
+            (
+                Compatibility::Incompatible,
+                format!(
+                    "server {} doesn't handle {}, which the SDK needs",
+                    info.version,
+                    missing_required.join(", ")
+                ),
+            )
+        } else if !missing.is_empty() {
+            (
+                Compatibility::Degraded,
+                format!(
+                    "server {} doesn't support every SDK feature; upgrade the server to use them",
+                    info.version
+                ),
+            )
+        } else {
+            (
+                Compatibility::Compatible,
+                format!(
+                    "server {} supports every feature of SDK {}",
+                    info.version, sdk_version
+                ),
+            )
+        };
+
+        Self {
+            compatibility,
+            sdk_version,
+            server_version: Some(info.version),
+            explanation,
+            unavailable_features,
+        }
+    }
+}
+
+impl fmt::Display for Compatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compatibility::Compatible => write!(f, "compatible"),
+            Compatibility::Degraded => write!(f, "degraded"),
+            Compatibility::Incompatible => write!(f, "incompatible"),
+        }
+    }
+}
+
+impl SandboxBase {
+    /// Check whether this SDK is compatible with the server it connects to
+    ///
+    /// Fetches the server's version and the methods it handles with `server.info`, and
+    /// compares them with the server versions and methods the SDK relies on. Call it before
+    /// starting work to warn users to upgrade, instead of failing with a method-not-found
+    /// error midway. The sandbox doesn't need to be started.
+    ///
+    /// Servers older than `server.info` are reported as [`Compatibility::Degraded`], since
+    /// the features they support can't be checked.
+    pub async fn check_compatibility(
+        &self,
+    ) -> Result<CompatibilityReport, Box<dyn Error + Send + Sync>> {
+        match self
+            .make_request::<ServerInfoResponse>("server.info", json!({}))
+            .await
+        {
+            Ok(info) => Ok(CompatibilityReport::from_server_info(info)),
+            Err(e) if is_method_not_found(e.as_ref()) => Ok(CompatibilityReport {
+                compatibility: Compatibility::Degraded,
+                sdk_version: env!("CARGO_PKG_VERSION").to_string(),
+                server_version: None,
+                explanation: "server doesn't report its version, so it predates `server.info` \
+                              and some SDK features may be unavailable"
+                    .to_string(),
+                unavailable_features: Vec::new(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl SandboxBase {
+    /// Get the optional features the server's sandbox backend can apply, such as
+    /// `security_profile`
+    ///
+    /// Reported by `server.info`; servers that predate `server.info` or don't report any
+    /// capabilities return an empty list. The sandbox doesn't need to be started.
+    pub async fn capabilities(&self) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        match self
+            .make_request::<ServerInfoResponse>("server.info", json!({}))
+            .await
+        {
+            Ok(info) => Ok(info.capabilities),
+            Err(e) if is_method_not_found(e.as_ref()) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Check whether a request failed because the server doesn't know the method
+fn is_method_not_found(error: &(dyn Error + Send + Sync + 'static)) -> bool {
+    match error.downcast_ref::<SandboxError>() {
+        Some(SandboxError::Rpc(error)) => error.code == METHOD_NOT_FOUND_CODE,
+        Some(SandboxError::RequestFailed(msg))
+        | Some(SandboxError::ServerError(msg))
+        | Some(SandboxError::HttpStatus { message: msg, .. }) => msg.contains("Method not found"),
+        _ => false,
+    }
+}
+
+/// Parse a `major.minor.patch` version, ignoring any pre-release or build suffix
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+    let version = (parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(version)
+}
+
+/// Format a version tuple as `major.minor.patch`
+fn format_version((major, minor, patch): (u64, u64, u64)) -> String {
+    format!("{}.{}.{}", major, minor, patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(version: &str, methods: &[&str]) -> ServerInfoResponse {
+        ServerInfoResponse {
+            version: version.to_string(),
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+            capabilities: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_compatibility_from_server_info() {
+        let all: Vec<_> = FEATURES.iter().map(|(_, method, _)| *method).collect();
+        let report = CompatibilityReport::from_server_info(info("0.2.6", &all));
+        assert_eq!(report.compatibility, Compatibility::Compatible);
+        assert!(report.unavailable_features.is_empty());
+
+        let report = CompatibilityReport::from_server_info(info(
+            "0.2.6",
+            &["sandbox.start", "sandbox.stop", "sandbox.repl.run"],
+        ));
+        assert_eq!(report.compatibility, Compatibility::Degraded);
+        assert!(report.unavailable_features.contains(&"fs_diff".to_string()));
+
+        let report = CompatibilityReport::from_server_info(info("0.2.6", &["sandbox.start"]));
+        assert_eq!(report.compatibility, Compatibility::Incompatible);
+        assert!(report.explanation.contains("sandbox.repl.run"));
+
+        let report = CompatibilityReport::from_server_info(info("0.3.0-beta.1", &all));
+        assert_eq!(report.compatibility, Compatibility::Incompatible);
+        assert!(!report.is_usable());
+
+        assert_eq!(parse_version("1.2.3+build"), Some((1, 2, 3)));
+        assert_eq!(parse_version("1.2"), None);
+    }
+}
@@ -0,0 +1,135 @@
+//! Sandbox description for debugging
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{SandboxBase, SandboxError, SandboxWarning};
+
+/// Substrings of environment variable names that are never returned by `describe()`
+const SENSITIVE_ENV_DENYLIST: &[&str] = &[
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "TOKEN",
+    "API_KEY",
+    "PRIVATE_KEY",
+    "CREDENTIAL",
+];
+
+/// Value shown in place of a masked environment variable
+const MASKED_VALUE: &str = "********";
+
+/// Options for describing a sandbox
+#[derive(Debug, Clone, Default)]
+pub struct DescribeOptions {
+    /// Whether to fetch the effective environment variables of the guest
+    pub include_env: bool,
+
+    /// Environment variable names whose values are masked in the description
+    pub mask_keys: Vec<String>,
+}
+
+/// A snapshot of a sandbox's state, for debugging
+#[derive(Debug, Clone)]
+pub struct SandboxDescription {
+    /// Name of the sandbox
+    pub name: String,
+
+    /// Namespace of the sandbox
+    pub namespace: String,
+
+    /// URL of the Microsandbox server
+    pub server_url: String,
+
+    /// Whether the sandbox has been started
+    pub is_started: bool,
+
+    /// Whether the sandbox is paused
+    pub is_paused: bool,
+
+    /// Whether the init code has run since the sandbox was started
+    pub init_ran: bool,
+
+    /// Warnings reported by the server during the last start
+    pub warnings: Vec<SandboxWarning>,
+
+    /// Effective guest environment, if requested
+    ///
+    /// Variables whose names look like secrets are left out, and the values of
+    /// `mask_keys` are masked.
+    pub env: Option<BTreeMap<String, String>>,
+}
+
+/// Response of a `sandbox.env` request
+#[derive(Debug, Deserialize)]
+struct EnvResponse {
+    env: BTreeMap<String, String>,
+}
+
+impl SandboxBase {
+    /// Describe the sandbox's current state
+    pub async fn describe(
+        &self,
+        options: DescribeOptions,
+    ) -> Result<SandboxDescription, Box<dyn Error + Send + Sync>> {
+        let env = if options.include_env {
+            Some(self.get_guest_env(&options.mask_keys).await?)
+        } else {
+            None
+        };
+
+        Ok(SandboxDescription {
+            name: self.name.clone(),
+            namespace: self.namespace.clone(),
+            server_url: self.server_url.clone(),
+            is_started: self.is_started,
+            is_paused: self.is_paused,
+            init_ran: self.init_ran,
+            warnings: self
+                .start_outcome
+                .as_ref()
+                .map(|o| o.warnings.clone())
+                .unwrap_or_default(),
+            env,
+        })
+    }
+
+    /// Fetch the guest environment with sensitive variables removed or masked
+    pub(crate) async fn get_guest_env(
+        &self,
+        mask_keys: &[String],
+    ) -> Result<BTreeMap<String, String>, Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        if self.is_paused {
+            return Err(Box::new(SandboxError::Paused));
+        }
+
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+        });
+
+        let response: EnvResponse = self.make_request("sandbox.env", params).await?;
+        Ok(response
+            .env
+            .into_iter()
+            .filter(|(key, _)| {
+                let key = key.to_uppercase();
+                !SENSITIVE_ENV_DENYLIST.iter().any(|s| key.contains(s))
+            })
+            .map(|(key, value)| {
+                if mask_keys.contains(&key) {
+                    (key, MASKED_VALUE.to_string())
+                } else {
+                    (key, value)
+                }
+            })
+            .collect())
+    }
+}
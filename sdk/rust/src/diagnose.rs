@@ -0,0 +1,287 @@
+//! Connectivity and authentication diagnostics
+
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+use serde_json::{json, Value};
+use tokio::net::{lookup_host, TcpStream};
+
+use crate::SandboxBase;
+
+/// Time a single diagnostic step may take before it counts as failed
+const DIAGNOSE_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A step of the diagnostics, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticStep {
+    /// The server's host name resolves to an address
+    Dns,
+
+    /// A TCP connection to the server can be opened
+    Connect,
+
+    /// The server answers its health endpoint, over TLS for `https` URLs
+    Ping,
+
+    /// The server accepts the sandbox's credentials
+    Auth,
+
+    /// The sandbox's namespace exists on the server
+    Namespace,
+}
+
+/// The outcome of a diagnostic step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// The step succeeded
+    Passed,
+
+    /// The step failed, with the reason
+    Failed(String),
+
+    /// The step wasn't run because an earlier step failed
+    Skipped,
+}
+
+/// The result of one diagnostic step
+#[derive(Debug, Clone)]
+pub struct DiagnosticCheck {
+    /// The step that was checked
+    pub step: DiagnosticStep,
+
+    /// Whether it passed
+    pub status: CheckStatus,
+
+    /// How long the step took
+    pub latency: Duration,
+}
+
+/// The results of [`SandboxBase::diagnose`], one check per step
+#[derive(Debug, Clone)]
+pub struct DiagnosticsReport {
+    /// URL of the server that was checked
+    pub server_url: String,
+
+    /// Namespace that was checked
+    pub namespace: String,
+
+    /// Results of each step, in the order they ran
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+impl DiagnosticStep {
+    /// All steps, in the order they run
+    const ALL: [DiagnosticStep; 5] = [
+        DiagnosticStep::Dns,
+        DiagnosticStep::Connect,
+        DiagnosticStep::Ping,
+        DiagnosticStep::Auth,
+        DiagnosticStep::Namespace,
+    ];
+
+    /// Short name of the step
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticStep::Dns => "dns",
+            DiagnosticStep::Connect => "connect",
+            DiagnosticStep::Ping => "ping",
+            DiagnosticStep::Auth => "auth",
+            DiagnosticStep::Namespace => "namespace",
+        }
+    }
+}
+
+impl DiagnosticsReport {
+    /// Check whether every step passed
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.status == CheckStatus::Passed)
+    }
+
+    /// Get the first step that failed, if any
+    pub fn first_failure(&self) -> Option<&DiagnosticCheck> {
+        self.checks
+            .iter()
+            .find(|check| matches!(check.status, CheckStatus::Failed(_)))
+    }
+}
+
+impl fmt::Display for DiagnosticsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} (namespace {})", self.server_url, self.namespace)?;
+        for check in &self.checks {
+            let status = match &check.status {
+                CheckStatus::Passed => "ok".to_string(),
+                CheckStatus::Failed(reason) => format!("FAILED: {}", reason),
+                CheckStatus::Skipped => "skipped".to_string(),
+            };
+            writeln!(
+                f,
+                "  {:<10} {:>8.1?}  {}",
+                check.step.as_str(),
+                check.latency,
+                status
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl SandboxBase {
+    /// Check each layer between the client and the server, reporting which one fails
+    ///
+    /// Runs these steps in order, timing each one: resolving the server's host name, opening
+    /// a TCP connection, calling the health endpoint (which also negotiates TLS for `https`
+    /// URLs), making an authenticated request, and checking that the sandbox's namespace
+    /// exists. Once a step fails, the remaining steps are skipped. The sandbox doesn't need
+    /// to be started.
+    ///
+    /// A namespace that doesn't exist yet is created when the first sandbox in it starts, so
+    /// a failed namespace step on a fresh server isn't necessarily a problem.
+    pub async fn diagnose(&self) -> DiagnosticsReport {
+        let mut checks = Vec::new();
+        let mut addrs = Vec::new();
+
+        for step in DiagnosticStep::ALL {
+            if checks
+                .iter()
+                .any(|check: &DiagnosticCheck| check.status != CheckStatus::Passed)
+            {
+                checks.push(DiagnosticCheck {
+                    step,
+                    status: CheckStatus::Skipped,
+                    latency: Duration::ZERO,
+                });
+                continue;
+            }
+
+            let started = Instant::now();
+            let result = match step {
+                DiagnosticStep::Dns => timed(self.resolve_server())
+                    .await
+                    .map(|resolved| addrs = resolved),
+                DiagnosticStep::Connect => timed(connect_any(&addrs)).await,
+                DiagnosticStep::Ping => timed(self.ping()).await,
+                DiagnosticStep::Auth => {
+                    timed(async {
+                        let _: Value = self.make_request("server.languages", json!({})).await?;
+                        Ok(())
+                    })
+                    .await
+                }
+                DiagnosticStep::Namespace => {
+                    timed(async {
+                        let params = json!({ "namespace": self.namespace });
+                        let _: Value = self.make_request("sandbox.metrics.get", params).await?;
+                        Ok(())
+                    })
+                    .await
+                }
+            };
+
+            checks.push(DiagnosticCheck {
+                step,
+                status: match result {
+                    Ok(()) => CheckStatus::Passed,
+                    Err(e) => CheckStatus::Failed(e.to_string()),
+                },
+                latency: started.elapsed(),
+            });
+        }
+
+        DiagnosticsReport {
+            server_url: self.server_url.clone(),
+            namespace: self.namespace.clone(),
+            checks,
+        }
+    }
+
+    /// Resolve the server's host name to the addresses to connect to
+    async fn resolve_server(&self) -> Result<Vec<SocketAddr>, Box<dyn Error + Send + Sync>> {
+        let url = Url::parse(&self.server_url)?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| format!("server URL '{}' has no host", self.server_url))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| format!("server URL '{}' has no port", self.server_url))?;
+
+        let addrs: Vec<SocketAddr> = lookup_host((host.trim_matches(['[', ']']), port))
+            .await?
+            .collect();
+        if addrs.is_empty() {
+            return Err(format!("'{}' resolved to no addresses", host).into());
+        }
+        Ok(addrs)
+    }
+
+    /// Call the server's unauthenticated health endpoint
+    async fn ping(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let response = self
+            .http_client()?
+            .get(format!("{}/api/v1/health", self.server_url))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(format!("health check returned {}", response.status()).into());
+        }
+        Ok(())
+    }
+}
+
+/// Open a TCP connection to the first address that accepts one
+async fn connect_any(addrs: &[SocketAddr]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(_) => return Ok(()),
+            Err(e) => last_error = Some(format!("{}: {}", addr, e)),
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| "no addresses to connect to".to_string())
+        .into())
+}
+
+/// Run a diagnostic step, failing it if it takes longer than the step timeout
+async fn timed<T>(
+    step: impl Future<Output = Result<T, Box<dyn Error + Send + Sync>>>,
+) -> Result<T, Box<dyn Error + Send + Sync>> {
+    tokio::time::timeout(DIAGNOSE_STEP_TIMEOUT, step)
+        .await
+        .map_err(|_| format!("timed out after {:?}", DIAGNOSE_STEP_TIMEOUT))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SandboxOptions;
+
+    #[tokio::test]
+    async fn test_diagnose_stops_at_first_failed_step() {
+        // Find a local port with nothing listening on it
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://127.0.0.1:{}", port))
+            .build();
+        let report = SandboxBase::new(&options).diagnose().await;
+
+        let statuses: Vec<_> = report.checks.iter().map(|c| &c.status).collect();
+        assert_eq!(statuses[0], &CheckStatus::Passed);
+        assert!(matches!(statuses[1], CheckStatus::Failed(_)));
+        assert!(statuses[2..].iter().all(|s| **s == CheckStatus::Skipped));
+        assert_eq!(
+            report.first_failure().map(|c| c.step),
+            Some(DiagnosticStep::Connect)
+        );
+        assert!(!report.passed());
+    }
+}
@@ -1,27 +1,136 @@
 use std::error::Error;
 use std::fmt;
 
+use serde_json::Value;
+
+use crate::StartPhase;
+
+/// JSON-RPC error code of a method the server doesn't know
+pub const METHOD_NOT_FOUND_CODE: i64 = -32601;
+
+/// JSON-RPC error code of a request with invalid params
+pub const INVALID_PARAMS_CODE: i64 = -32602;
+
+/// JSON-RPC error code of an internal server error
+pub const INTERNAL_ERROR_CODE: i64 = -32603;
+
+/// JSON-RPC error code of a sandbox start phase that exceeded its time limit
+pub const START_PHASE_TIMEOUT_CODE: i64 = -32001;
+
+/// JSON-RPC error code of an execution refused because the sandbox is running its maximum
+/// number of concurrent executions
+pub const EXECUTION_LIMIT_CODE: i64 = -32002;
+
+/// JSON-RPC error code of a sandbox that isn't defined in its namespace
+pub const SANDBOX_NOT_FOUND_CODE: i64 = -32003;
+
+/// A JSON-RPC error returned by the server
+///
+/// Errors with well-known codes are reported as their own [`SandboxError`] variants, such as
+/// [`NotFound`](SandboxError::NotFound) for [`SANDBOX_NOT_FOUND_CODE`]; the rest are reported
+/// as [`SandboxError::Rpc`] with the code and data the server sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    /// The JSON-RPC error code
+    pub code: i64,
+
+    /// The error message
+    pub message: String,
+
+    /// Additional information about the error, if the server sent any
+    pub data: Option<Value>,
+}
+
 /// Common error types for the Microsandbox SDK
 #[derive(Debug)]
 pub enum SandboxError {
     /// The sandbox has not been started
     NotStarted,
 
+    /// The sandbox is paused
+    Paused,
+
     /// The request to the server failed
     RequestFailed(String),
 
     /// The server returned an error
     ServerError(String),
 
+    /// The server returned a JSON-RPC error without a more specific variant
+    Rpc(RpcError),
+
+    /// The sandbox isn't defined on the server
+    NotFound(String),
+
+    /// The sandbox is defined on the server but isn't running
+    NotRunning(String),
+
+    /// The server responded with a non-success HTTP status
+    HttpStatus {
+        /// The HTTP status code
+        status: u16,
+
+        /// The response body
+        message: String,
+    },
+
     /// The sandbox timed out
     Timeout(String),
 
+    /// A phase of the sandbox start exceeded its time limit
+    PhaseTimeout {
+        /// The phase that timed out
+        phase: StartPhase,
+
+        /// What the server was waiting for
+        message: String,
+    },
+
+    /// The sandbox started, but its readiness probe didn't pass before the retries ran out
+    ///
+    /// The sandbox is left running, so it can be inspected.
+    NotReady {
+        /// Number of probe attempts made
+        attempts: u32,
+
+        /// Why the last attempt failed
+        message: String,
+    },
+
     /// An error occurred with the HTTP client
     HttpError(String),
 
     /// Invalid response received from server
     InvalidResponse(String),
 
+    /// The server's response was cut off or isn't valid JSON
+    MalformedResponse {
+        /// Number of body bytes received before the failure
+        received: usize,
+
+        /// What went wrong while reading or parsing the body
+        message: String,
+    },
+
+    /// The request is invalid and was not sent
+    InvalidInput(String),
+
+    /// A client-side resource limit, or the sandbox's limit on concurrent executions, was
+    /// reached
+    ResourceExhausted(String),
+
+    /// The requested feature is not supported by the sandbox backend
+    Unsupported(String),
+
+    /// A request kept failing after being retried
+    RetriesExhausted {
+        /// Number of retries made after the first attempt
+        retries: u32,
+
+        /// Error of the last attempt
+        source: Box<dyn Error + Send + Sync>,
+    },
+
     /// General error
     General(String),
 }
@@ -30,18 +139,100 @@ impl fmt::Display for SandboxError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             SandboxError::NotStarted => write!(f, "Sandbox is not started. Call start() first."),
+            SandboxError::Paused => write!(f, "Sandbox is paused. Call resume() first."),
             SandboxError::RequestFailed(msg) => {
                 write!(f, "Failed to communicate with Microsandbox server: {}", msg)
             }
             SandboxError::ServerError(msg) => write!(f, "Server error: {}", msg),
+            SandboxError::Rpc(error) => write!(f, "{}", error),
+            SandboxError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            SandboxError::NotRunning(msg) => write!(f, "Not running: {}", msg),
+            SandboxError::HttpStatus { status, message } => write!(
+                f,
+                "Failed to communicate with Microsandbox server: HTTP {}: {}",
+                status, message
+            ),
             SandboxError::Timeout(msg) => write!(f, "Timeout error: {}", msg),
+            SandboxError::PhaseTimeout { phase, message } => {
+                write!(f, "Timeout error in the {} phase: {}", phase, message)
+            }
+            SandboxError::NotReady { attempts, message } => {
+                write!(f, "{} (after {} probe attempts)", message, attempts)
+            }
             SandboxError::HttpError(msg) => write!(f, "HTTP error: {}", msg),
             SandboxError::InvalidResponse(msg) => {
                 write!(f, "Invalid response from server: {}", msg)
             }
+            SandboxError::MalformedResponse { received, message } => write!(
+                f,
+                "Malformed response from server after {} bytes: {}",
+                received, message
+            ),
+            SandboxError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+            SandboxError::ResourceExhausted(msg) => write!(f, "Resource exhausted: {}", msg),
+            SandboxError::Unsupported(msg) => write!(f, "Unsupported: {}", msg),
+            SandboxError::RetriesExhausted { retries, source } => {
+                write!(f, "{} (after {} retries)", source, retries)
+            }
             SandboxError::General(msg) => write!(f, "{}", msg),
         }
     }
 }
 
-impl Error for SandboxError {}
+impl SandboxError {
+    /// Check if the error is transient, so retrying an idempotent request may succeed
+    ///
+    /// This is the classification [`default_retry_predicate`](crate::default_retry_predicate)
+    /// applies: failures to reach the server, 5xx responses, malformed bodies and timeouts.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SandboxError::HttpError(_)
+            | SandboxError::MalformedResponse { .. }
+            | SandboxError::Timeout(_)
+            | SandboxError::PhaseTimeout { .. } => true,
+            SandboxError::HttpStatus { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// Get the number of retries made before the request failed
+    ///
+    /// Zero unless the request was retried under a [`RetryPolicy`](crate::RetryPolicy).
+    pub fn retries(&self) -> u32 {
+        match self {
+            SandboxError::RetriesExhausted { retries, .. } => *retries,
+            _ => 0,
+        }
+    }
+
+    /// Get the JSON-RPC error, if the server returned one without a more specific variant
+    pub fn rpc_error(&self) -> Option<&RpcError> {
+        match self {
+            SandboxError::Rpc(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Get the start phase that timed out, if this is a start phase timeout
+    pub fn timed_out_phase(&self) -> Option<StartPhase> {
+        match self {
+            SandboxError::PhaseTimeout { phase, .. } => Some(*phase),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Server error {}: {}", self.code, self.message)
+    }
+}
+
+impl Error for SandboxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SandboxError::RetriesExhausted { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
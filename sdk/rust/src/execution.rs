@@ -1,8 +1,9 @@
 //! Execution results for code run in sandboxes
 
-use serde_json::Value;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::error::Error;
+use std::time::Duration;
 
 /// Represents a code execution in a sandbox environment
 ///
@@ -10,117 +11,253 @@ use std::error::Error;
 /// that was executed in a sandbox.
 #[derive(Debug, Clone)]
 pub struct Execution {
-    /// Output lines from the execution
-    output_lines: Vec<OutputLine>,
-    /// Status of the execution
-    status: String,
-    /// Language used for the execution
-    language: String,
+    /// Result reported by the server
+    result: ExecutionResult,
+    /// Text written to stdout, one output line per line
+    stdout: String,
+    /// Text written to stderr, one output line per line
+    stderr: String,
     /// Whether the execution encountered an error
     has_error: bool,
+    /// Whether the execution was cut off by a timeout
+    timed_out: bool,
+}
+
+/// The result of a code execution, as reported by the server
+///
+/// Fields the SDK doesn't know about are kept in `raw`, so results from newer servers can
+/// still be read in full.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionResult {
+    /// Status of the execution, e.g. `success` or `error`
+    #[serde(default = "unknown")]
+    pub status: String,
+
+    /// Language the code was run in
+    #[serde(default = "unknown")]
+    pub language: String,
+
+    /// Output lines, in the order they were produced
+    #[serde(default)]
+    pub output: Vec<OutputLine>,
+
+    /// Resources used by the execution, if the server measured them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_usage: Option<ResourceUsage>,
+
+    /// Fields not covered above
+    #[serde(flatten)]
+    pub raw: Map<String, Value>,
+}
+
+/// Resources used by a single code execution, as measured inside the sandbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// CPU time spent running the code, in milliseconds
+    pub cpu_ms: u64,
+
+    /// Peak resident set size of the interpreter while the code ran, in bytes
+    pub peak_rss: u64,
+
+    /// Wall-clock time the execution took, in milliseconds
+    pub wall_ms: u64,
+}
+
+/// Everything about a finished execution in one value
+///
+/// Returned by [`Execution::summary`]. Fields the server didn't report take neutral
+/// defaults: no exit code, duration or resource usage, and not truncated.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExecutionSummary {
+    /// Status of the execution, e.g. `success` or `error`
+    pub status: String,
+
+    /// Language the code was run in
+    pub language: String,
+
+    /// Text written to stdout, one output line per line
+    pub stdout: String,
+
+    /// Text written to stderr, one output line per line
+    pub stderr: String,
+
+    /// Exit code, if the server reported one
+    pub exit_code: Option<i32>,
+
+    /// Signal that ended the execution, if the server reported one
+    pub exit_signal: Option<i32>,
+
+    /// Whether the execution encountered an error
+    pub has_error: bool,
+
+    /// Wall-clock time the execution took, if the server measured it
+    pub duration: Option<Duration>,
+
+    /// Resources the execution used, if the server measured them
+    pub resource_usage: Option<ResourceUsage>,
+
+    /// Whether the server cut off some of the output
+    pub truncated: bool,
+
+    /// Whether the execution was cut off by a timeout
+    pub timed_out: bool,
 }
 
 /// A single line of output from an execution
-#[derive(Debug, Clone)]
-struct OutputLine {
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputLine {
     /// Stream type (stdout or stderr)
-    stream: String,
+    #[serde(default)]
+    pub stream: String,
+
     /// Text content
-    text: String,
+    #[serde(default)]
+    pub text: String,
+}
+
+fn unknown() -> String {
+    "unknown".to_string()
+}
+
+impl ExecutionResult {
+    /// Get the text written to stdout, one output line per line
+    pub fn stdout(&self) -> String {
+        self.stream_text("stdout")
+    }
+
+    /// Get the text written to stderr, one output line per line
+    pub fn stderr(&self) -> String {
+        self.stream_text("stderr")
+    }
+
+    /// Get the wall-clock time the execution took, if the server measured it
+    pub fn duration(&self) -> Option<Duration> {
+        self.resource_usage
+            .map(|usage| Duration::from_millis(usage.wall_ms))
+    }
+
+    /// Get the exit code, if the server reported one in the `exit_code` field
+    pub fn exit_code(&self) -> Option<i32> {
+        self.raw_i32("exit_code")
+    }
+
+    /// Get the signal that ended the execution, if the server reported one in the `signal`
+    /// field
+    pub fn exit_signal(&self) -> Option<i32> {
+        self.raw_i32("signal")
+    }
+
+    /// Read a field not covered by the typed fields as an `i32`
+    fn raw_i32(&self, key: &str) -> Option<i32> {
+        self.raw
+            .get(key)
+            .and_then(Value::as_i64)
+            .and_then(|value| i32::try_from(value).ok())
+    }
+
+    /// Join the text of one stream's output lines
+    fn stream_text(&self, stream: &str) -> String {
+        self.output
+            .iter()
+            .filter(|line| line.stream == stream)
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
 impl Execution {
-    /// Create a new execution instance from output data
-    pub(crate) fn new(output_data: HashMap<String, Value>) -> Self {
-        let mut output_lines = Vec::new();
-        let mut has_error = false;
-        let status = output_data
-            .get("status")
-            .and_then(|v| v.as_str())
-            .unwrap_or("unknown")
-            .to_string();
-        let language = output_data
-            .get("language")
-            .and_then(|v| v.as_str())
-            .unwrap_or("unknown")
-            .to_string();
-
-        // Check if status indicates an error
-        if status == "error" || status == "exception" {
-            has_error = true;
-        }
+    /// Create a new execution instance from the server's result
+    pub(crate) fn new(result: ExecutionResult) -> Self {
+        // Check if status indicates an error, or if anything was written to stderr
+        let has_error = result.status == "error"
+            || result.status == "exception"
+            || result
+                .output
+                .iter()
+                .any(|line| line.stream == "stderr" && !line.text.is_empty());
 
-        // Process output lines
-        if let Some(output) = output_data.get("output") {
-            if let Some(lines) = output.as_array() {
-                for line in lines {
-                    if let Some(line_obj) = line.as_object() {
-                        let stream = line_obj
-                            .get("stream")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("")
-                            .to_string();
-                        let text = line_obj
-                            .get("text")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("")
-                            .to_string();
-
-                        // Check for errors in stderr
-                        if stream == "stderr" && !text.is_empty() {
-                            has_error = true;
-                        }
-
-                        output_lines.push(OutputLine { stream, text });
-                    }
-                }
-            }
-        }
+        // Servers that interrupt code at its timeout flag the result
+        let timed_out = result.raw.get("timed_out") == Some(&Value::Bool(true));
 
         Self {
-            output_lines,
-            status,
-            language,
+            stdout: result.stdout(),
+            stderr: result.stderr(),
+            result,
             has_error,
+            timed_out,
         }
     }
 
-    /// Get the standard output from the execution
-    pub async fn output(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
-        let mut output_text = String::new();
+    /// Create an execution from the result of a command run as its own process
+    ///
+    /// Commands often write diagnostics to stderr when they succeed, so whether a command
+    /// failed is read from its exit code alone. The status is set from the exit code if the
+    /// server didn't report one.
+    pub(crate) fn from_command(mut result: ExecutionResult) -> Self {
+        let failed = result.exit_code() != Some(0);
+        if result.status == "unknown" {
+            result.status = if failed { "error" } else { "success" }.to_string();
+        }
 
-        for line in &self.output_lines {
-            if line.stream == "stdout" {
-                output_text.push_str(&line.text);
-                output_text.push('\n');
-            }
+        Self {
+            has_error: failed,
+            ..Self::new(result)
         }
+    }
 
-        // Remove trailing newline if present
-        if output_text.ends_with('\n') {
-            output_text.pop();
+    /// Create an execution from the output produced before a timeout
+    pub(crate) fn new_timed_out(result: ExecutionResult) -> Self {
+        Self {
+            timed_out: true,
+            ..Self::new(result)
         }
+    }
 
-        Ok(output_text)
+    /// Get the standard output from the execution
+    pub async fn output(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(self.stdout.clone())
     }
 
     /// Get the error output from the execution
     pub async fn error(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
-        let mut error_text = String::new();
+        Ok(self.stderr.clone())
+    }
 
-        for line in &self.output_lines {
-            if line.stream == "stderr" {
-                error_text.push_str(&line.text);
-                error_text.push('\n');
-            }
-        }
+    /// Get the text the execution wrote to stdout, one output line per line
+    pub fn stdout(&self) -> &str {
+        &self.stdout
+    }
 
-        // Remove trailing newline if present
-        if error_text.ends_with('\n') {
-            error_text.pop();
-        }
+    /// Get the text the execution wrote to stderr, one output line per line
+    pub fn stderr(&self) -> &str {
+        &self.stderr
+    }
+
+    /// Get the exit code of the execution, if the server reported one
+    ///
+    /// Code run in a REPL has no exit code of its own, so this is usually `None` unless the
+    /// server runs each execution as a separate process.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.result.exit_code()
+    }
+
+    /// Get the signal that ended the execution, if the server reported one instead of an
+    /// exit code
+    pub fn exit_signal(&self) -> Option<i32> {
+        self.result.exit_signal()
+    }
 
-        Ok(error_text)
+    /// Check if the execution succeeded
+    ///
+    /// An execution succeeds if it has no error, wasn't cut off by a timeout, and neither
+    /// exited with a non-zero code nor was ended by a signal. A missing exit code counts as
+    /// success.
+    pub fn success(&self) -> bool {
+        !self.has_error
+            && !self.timed_out
+            && self.exit_code().unwrap_or(0) == 0
+            && self.exit_signal().is_none()
     }
 
     /// Check if the execution contains an error
@@ -128,13 +265,154 @@ impl Execution {
         self.has_error
     }
 
+    /// Check if the execution was cut off by a timeout
+    ///
+    /// The output of a timed-out execution is whatever was produced before the deadline.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    /// Get the resources the execution used
+    ///
+    /// Returns `None` if the server didn't report them, e.g. because it couldn't measure
+    /// them in the sandbox or predates resource accounting.
+    pub fn resource_usage(&self) -> Option<ResourceUsage> {
+        self.result.resource_usage
+    }
+
     /// Get the status of the execution
     pub fn status(&self) -> &str {
-        &self.status
+        &self.result.status
     }
 
     /// Get the language used for the execution
     pub fn language(&self) -> &str {
-        &self.language
+        &self.result.language
+    }
+
+    /// Get the result as reported by the server, including fields the SDK doesn't know about
+    pub fn result(&self) -> &ExecutionResult {
+        &self.result
+    }
+
+    /// Take the result as reported by the server
+    pub fn into_result(self) -> ExecutionResult {
+        self.result
+    }
+
+    /// Get the output, exit code, timing, resource usage and outcome of the execution at once
+    pub fn summary(&self) -> ExecutionSummary {
+        let result = &self.result;
+        ExecutionSummary {
+            status: result.status.clone(),
+            language: result.language.clone(),
+            stdout: self.stdout.clone(),
+            stderr: self.stderr.clone(),
+            exit_code: result.exit_code(),
+            exit_signal: result.exit_signal(),
+            has_error: self.has_error,
+            duration: result.duration(),
+            resource_usage: result.resource_usage,
+            truncated: result
+                .raw
+                .get("truncated")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            timed_out: self.timed_out,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_execution_result_keeps_unknown_fields() {
+        let result: ExecutionResult = serde_json::from_value(json!({
+            "status": "success",
+            "language": "python",
+            "output": [
+                { "stream": "stdout", "text": "a" },
+                { "stream": "stderr", "text": "warning" },
+                { "stream": "stdout", "text": "b" },
+            ],
+            "resource_usage": { "cpu_ms": 3, "peak_rss": 1024, "wall_ms": 5 },
+            "cell_id": 7,
+        }))
+        .unwrap();
+
+        assert_eq!(result.stdout(), "a\nb");
+        assert_eq!(result.stderr(), "warning");
+        assert_eq!(result.duration(), Some(Duration::from_millis(5)));
+        assert_eq!(result.raw["cell_id"], 7);
+        assert_eq!(serde_json::to_value(&result).unwrap()["cell_id"], 7);
+
+        let execution = Execution::new(result);
+        assert!(execution.has_error());
+        assert_eq!(execution.status(), "success");
+
+        let empty: ExecutionResult = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(empty.status, "unknown");
+        assert!(empty.raw.is_empty());
+    }
+
+    #[test]
+    fn test_summary_combines_output_and_outcome() {
+        let result: ExecutionResult = serde_json::from_value(json!({
+            "status": "error",
+            "language": "python",
+            "output": [
+                { "stream": "stdout", "text": "partial" },
+                { "stream": "stderr", "text": "Traceback" },
+            ],
+            "resource_usage": { "cpu_ms": 3, "peak_rss": 1024, "wall_ms": 5 },
+            "exit_code": 1,
+            "truncated": true,
+        }))
+        .unwrap();
+
+        let summary = Execution::new_timed_out(result).summary();
+        assert_eq!(summary.stdout, "partial");
+        assert_eq!(summary.stderr, "Traceback");
+        assert_eq!(summary.exit_code, Some(1));
+        assert_eq!(summary.duration, Some(Duration::from_millis(5)));
+        assert!(summary.has_error && summary.truncated && summary.timed_out);
+
+        // Absent fields take neutral defaults
+        let summary = Execution::new(serde_json::from_value(json!({})).unwrap()).summary();
+        assert_eq!(summary.exit_code, None);
+        assert_eq!(summary.duration, None);
+        assert!(!summary.has_error && !summary.truncated && !summary.timed_out);
+    }
+
+    #[test]
+    fn test_exit_accessors() {
+        let execution = |result: Value| Execution::new(serde_json::from_value(result).unwrap());
+
+        let ok = execution(json!({
+            "status": "success",
+            "output": [
+                { "stream": "stdout", "text": "a" },
+                { "stream": "stdout", "text": "b" },
+            ],
+            "exit_code": 0,
+        }));
+        assert_eq!((ok.stdout(), ok.stderr()), ("a\nb", ""));
+        assert_eq!((ok.exit_code(), ok.exit_signal()), (Some(0), None));
+        assert!(ok.success());
+
+        // A REPL execution reports no exit code at all
+        assert!(execution(json!({ "status": "success" })).success());
+
+        let failed = execution(json!({ "status": "success", "exit_code": 2 }));
+        assert_eq!(failed.exit_code(), Some(2));
+        assert!(!failed.success());
+
+        let killed = execution(json!({ "status": "success", "signal": 9 }));
+        assert_eq!((killed.exit_code(), killed.exit_signal()), (None, Some(9)));
+        assert!(!killed.success());
+        assert_eq!(killed.summary().exit_signal, Some(9));
     }
 }
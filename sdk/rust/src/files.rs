@@ -0,0 +1,293 @@
+//! Files written into and read from the guest
+
+use std::error::Error;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{SandboxBase, SandboxError};
+
+/// Maximum total size of the inline files of a sandbox (1MiB)
+const MAX_INLINE_FILES_SIZE: usize = 1024 * 1024;
+
+/// Size of the chunks files are uploaded and downloaded in (512KiB)
+///
+/// Base64-encoded, a chunk stays well under the server's request body limit.
+const FILE_CHUNK_SIZE: usize = 512 * 1024;
+
+/// A file written into the guest right after the sandbox starts
+///
+/// Inline files are written before the init code runs, so config files and other small
+/// bootstrap files are in place before any code sees the sandbox.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlineFile {
+    /// Absolute path of the file in the guest
+    pub guest_path: String,
+
+    /// Contents of the file
+    pub contents: Vec<u8>,
+
+    /// Permission bits of the file, e.g. `0o644`
+    pub mode: u32,
+}
+
+impl InlineFile {
+    /// Create a new inline file
+    pub fn new(guest_path: impl Into<String>, contents: impl Into<Vec<u8>>, mode: u32) -> Self {
+        Self {
+            guest_path: guest_path.into(),
+            contents: contents.into(),
+            mode,
+        }
+    }
+
+    /// Check that the path is absolute and that the mode is a valid set of permission bits
+    pub(crate) fn validate(&self) -> Result<(), SandboxError> {
+        validate_guest_path("inline file", &self.guest_path)?;
+
+        if self.mode > 0o7777 {
+            return Err(SandboxError::InvalidInput(format!(
+                "inline file mode {:o} of '{}' is not a valid set of permission bits",
+                self.mode, self.guest_path
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// An entry of a directory in the guest, as returned by [`SandboxBase::list_dir`]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DirEntry {
+    /// Name of the entry within its directory
+    pub name: String,
+
+    /// Size of the entry in bytes
+    pub size: u64,
+
+    /// Permission bits of the entry, e.g. `0o644`
+    pub mode: u32,
+
+    /// Whether the entry is a directory
+    pub is_dir: bool,
+}
+
+/// A chunk of a file read from the guest
+#[derive(Debug, Deserialize)]
+struct ReadChunk {
+    /// Base64-encoded bytes of the chunk
+    content: String,
+
+    /// Total size of the file
+    size: u64,
+}
+
+/// The entries of a directory listed in the guest
+#[derive(Debug, Deserialize)]
+struct DirListing {
+    entries: Vec<DirEntry>,
+}
+
+/// Check that a guest path is absolute and can't climb out of the sandbox root with `..`
+///
+/// `kind` names the path in errors, e.g. "inline file".
+pub(crate) fn validate_guest_path(kind: &str, path: &str) -> Result<(), SandboxError> {
+    if !path.starts_with('/') {
+        return Err(SandboxError::InvalidInput(format!(
+            "{} path '{}' must be absolute",
+            kind, path
+        )));
+    }
+
+    if path.split('/').any(|component| component == "..") {
+        return Err(SandboxError::InvalidInput(format!(
+            "{} path '{}' must not contain '..'",
+            kind, path
+        )));
+    }
+
+    if path.contains('\0') {
+        return Err(SandboxError::InvalidInput(format!(
+            "{} path '{}' must not contain NUL bytes",
+            kind, path
+        )));
+    }
+
+    Ok(())
+}
+
+/// Check every inline file, and that together they stay within the size limit
+pub(crate) fn validate_inline_files(files: &[InlineFile]) -> Result<(), SandboxError> {
+    for file in files {
+        file.validate()?;
+    }
+
+    let total: usize = files.iter().map(|file| file.contents.len()).sum();
+    if total > MAX_INLINE_FILES_SIZE {
+        return Err(SandboxError::InvalidInput(format!(
+            "inline files total {} bytes, more than the limit of {} bytes; upload large files \
+             after the sandbox starts instead",
+            total, MAX_INLINE_FILES_SIZE
+        )));
+    }
+
+    Ok(())
+}
+
+impl SandboxBase {
+    /// Write a file into the guest, replacing any existing file and creating its parent
+    /// directories
+    ///
+    /// Large files are uploaded in chunks, so there is no limit on their size beyond the
+    /// space in the guest.
+    pub async fn write_file(
+        &self,
+        path: &str,
+        contents: impl AsRef<[u8]>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        validate_guest_path("file", path)?;
+        self.write_guest_file(path, contents.as_ref(), None).await
+    }
+
+    /// Read a file from the guest
+    ///
+    /// Large files are downloaded in chunks.
+    pub async fn read_file(&self, path: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        validate_guest_path("file", path)?;
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        let mut contents = Vec::new();
+        loop {
+            let params = json!({
+                "sandbox": self.name,
+                "namespace": self.namespace,
+                "path": path,
+                "offset": contents.len(),
+                "length": FILE_CHUNK_SIZE,
+            });
+            let chunk: ReadChunk = self.make_request("sandbox.fs.read", params).await?;
+            let bytes = BASE64.decode(&chunk.content).map_err(|e| {
+                SandboxError::InvalidResponse(format!("Invalid file content: {}", e))
+            })?;
+
+            contents.extend_from_slice(&bytes);
+
+            // An empty chunk ends the read even if the file shrank while it was being read
+            if bytes.is_empty() || contents.len() as u64 >= chunk.size {
+                return Ok(contents);
+            }
+        }
+    }
+
+    /// List the entries of a directory in the guest, sorted by name
+    pub async fn list_dir(
+        &self,
+        path: &str,
+    ) -> Result<Vec<DirEntry>, Box<dyn Error + Send + Sync>> {
+        validate_guest_path("directory", path)?;
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "path": path,
+        });
+        let listing: DirListing = self.make_request("sandbox.fs.list", params).await?;
+        Ok(listing.entries)
+    }
+
+    /// Write a file into the guest in chunks, creating its parent directories
+    ///
+    /// The first chunk replaces any existing file and sets its mode; later chunks are appended.
+    pub(crate) async fn write_guest_file(
+        &self,
+        guest_path: &str,
+        contents: &[u8],
+        mode: Option<u32>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        let mut chunks = contents.chunks(FILE_CHUNK_SIZE);
+        let first = chunks.next().unwrap_or_default();
+        self.write_chunk(guest_path, first, mode, false).await?;
+        for chunk in chunks {
+            self.write_chunk(guest_path, chunk, None, true).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write or append one chunk of a file in the guest
+    async fn write_chunk(
+        &self,
+        guest_path: &str,
+        chunk: &[u8],
+        mode: Option<u32>,
+        append: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "path": guest_path,
+            "content": BASE64.encode(chunk),
+            "mode": mode,
+            "append": append,
+        });
+
+        let _result: Value = self.make_request("sandbox.fs.write", params).await?;
+        Ok(())
+    }
+
+    /// Write the configured inline files into the guest, in order
+    pub(crate) async fn write_inline_files(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for file in &self.files {
+            self.write_guest_file(&file.guest_path, &file.contents, Some(file.mode))
+                .await
+                .map_err(|e| {
+                    Box::new(SandboxError::General(format!(
+                        "Failed to write inline file '{}': {}",
+                        file.guest_path, e
+                    )))
+                })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_files_validation() {
+        let config = InlineFile::new("/etc/app/config.toml", "port = 8080\n", 0o600);
+        assert!(validate_inline_files(std::slice::from_ref(&config)).is_ok());
+
+        for invalid in [
+            InlineFile::new("etc/app.toml", "", 0o644),
+            InlineFile::new("/etc/../root/.ssh/keys", "", 0o644),
+            InlineFile::new("/etc/app.toml", "", 0o10000),
+        ] {
+            assert!(matches!(
+                validate_inline_files(&[invalid]),
+                Err(SandboxError::InvalidInput(_))
+            ));
+        }
+
+        for invalid in ["data/file", "/data/../../etc/passwd", "/data/\0"] {
+            assert!(validate_guest_path("file", invalid).is_err());
+        }
+        assert!(validate_guest_path("file", "/data/..hidden").is_ok());
+
+        let large = InlineFile::new("/data/blob", vec![0; MAX_INLINE_FILES_SIZE], 0o644);
+        assert!(validate_inline_files(&[large, config]).is_err());
+    }
+}
@@ -0,0 +1,520 @@
+//! Filesystem interface for sandboxes
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::time::Duration;
+
+use futures::stream::{self, Stream};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{RetryPolicy, SandboxBase, SandboxError};
+
+/// Number of consecutive failed polls after which a watch gives up
+const MAX_WATCH_FAILURES: u32 = 3;
+
+/// How long the server holds a watch poll open when there are no events (seconds)
+const WATCH_POLL_TIMEOUT: u64 = 30;
+
+/// Kind of change reported by a filesystem watch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsEventKind {
+    /// A file or directory was created
+    Create,
+    /// A file was modified
+    Modify,
+    /// A file or directory was deleted
+    Delete,
+}
+
+/// A filesystem change event inside the sandbox
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FsEvent {
+    /// Path of the changed file, as seen by the guest
+    pub path: String,
+
+    /// Kind of change
+    pub kind: FsEventKind,
+}
+
+/// Kind of change reported by a filesystem diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsChangeKind {
+    /// The path was created
+    Added,
+    /// The file's contents or metadata changed
+    Modified,
+    /// The path was removed
+    Deleted,
+}
+
+/// A path that changed inside the sandbox since a snapshot marker
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FsChange {
+    /// Path of the changed file, as seen by the guest
+    pub path: String,
+
+    /// Kind of change
+    pub kind: FsChangeKind,
+}
+
+/// A marker recording the state of a sandbox's filesystem at a point in time
+///
+/// Markers are held by the server in memory and are only valid for the sandbox that
+/// created them, until the server restarts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotRef {
+    id: String,
+}
+
+/// Response to a `sandbox.fs.snapshot` request
+#[derive(Debug, Deserialize)]
+struct SnapshotResponse {
+    snapshot_id: String,
+}
+
+/// Response to a `sandbox.fs.diff` request
+#[derive(Debug, Deserialize)]
+struct DiffResponse {
+    #[serde(default)]
+    changes: Vec<FsChange>,
+}
+
+/// A batch of events returned by a single `sandbox.fs.watch` poll
+#[derive(Debug, Deserialize)]
+struct WatchResponse {
+    #[serde(default)]
+    events: Vec<FsEvent>,
+    cursor: Option<String>,
+}
+
+/// Internal state of a running watch
+struct WatchState<'a> {
+    base: &'a SandboxBase,
+    path: String,
+    patterns: Vec<String>,
+    debounce: Option<Duration>,
+    cursor: Option<String>,
+    pending: VecDeque<FsEvent>,
+    failures: u32,
+    done: bool,
+}
+
+impl SnapshotRef {
+    /// Get the server-assigned ID of the marker
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl SandboxBase {
+    /// Record a marker of the sandbox's current filesystem state
+    ///
+    /// Pass the marker to [`fs_diff`](Self::fs_diff) later to find out what changed.
+    pub async fn fs_snapshot(&self) -> Result<SnapshotRef, Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+        });
+
+        let response: SnapshotResponse = self.make_request("sandbox.fs.snapshot", params).await?;
+        Ok(SnapshotRef {
+            id: response.snapshot_id,
+        })
+    }
+
+    /// List the paths added, modified or deleted since a snapshot marker, ordered by path
+    ///
+    /// The server computes the diff from the sandbox's writable overlay layer, so only
+    /// changes made on the root filesystem are reported, not those in mounted volumes.
+    pub async fn fs_diff(
+        &self,
+        since: &SnapshotRef,
+    ) -> Result<Vec<FsChange>, Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "since": since.id,
+        });
+
+        let response: DiffResponse = self.make_request("sandbox.fs.diff", params).await?;
+        Ok(response.changes)
+    }
+
+    /// Watch a path inside the sandbox for filesystem changes
+    ///
+    /// Events are backed by a `sandbox.fs.watch` RPC (inotify in the guest) that is
+    /// long-polled with a cursor, so no events are lost between polls. `patterns` are glob
+    /// patterns applied by the guest to the changed path relative to `path` and to its file
+    /// name; an empty slice matches everything. When `debounce` is set, events for the same
+    /// path that arrive within the window are coalesced into the most recent one.
+    ///
+    /// A poll that fails with an error the [retry predicate](crate::RetryPredicate) accepts,
+    /// such as a failure to reach the server, is sent again after the retry policy's backoff.
+    /// The stream ends with the error if the sandbox is not started, if a poll fails with any
+    /// other error, such as a JSON-RPC error, or if polls fail several times in a row.
+    pub fn watch_fs<'a>(
+        &'a self,
+        path: &str,
+        patterns: &[&str],
+        debounce: Option<Duration>,
+    ) -> impl Stream<Item = Result<FsEvent, Box<dyn Error + Send + Sync>>> + 'a {
+        let state = WatchState {
+            base: self,
+            path: path.to_string(),
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            debounce,
+            cursor: None,
+            pending: VecDeque::new(),
+            failures: 0,
+            done: false,
+        };
+
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if state.done {
+                    return None;
+                }
+
+                if let Some(event) = state.pending.pop_front() {
+                    return Some((Ok(event), state));
+                }
+
+                if !state.base.is_started {
+                    state.done = true;
+                    return Some((Err(Box::new(SandboxError::NotStarted) as _), state));
+                }
+
+                // Give the guest time to batch up bursts of changes
+                if let Some(debounce) = state.debounce {
+                    if state.cursor.is_some() {
+                        tokio::time::sleep(debounce).await;
+                    }
+                }
+
+                let params = json!({
+                    "sandbox": state.base.name,
+                    "namespace": state.base.namespace,
+                    "path": state.path,
+                    "patterns": state.patterns,
+                    "cursor": state.cursor,
+                    "timeout": WATCH_POLL_TIMEOUT,
+                });
+
+                match state
+                    .base
+                    .make_request::<WatchResponse>("sandbox.fs.watch", params)
+                    .await
+                {
+                    Ok(response) => {
+                        state.failures = 0;
+                        if response.cursor.is_some() {
+                            state.cursor = response.cursor;
+                        }
+
+                        let events = if state.debounce.is_some() {
+                            coalesce_events(response.events)
+                        } else {
+                            response.events
+                        };
+                        state.pending.extend(events);
+                    }
+                    Err(e) => {
+                        let retryable = e
+                            .downcast_ref::<SandboxError>()
+                            .is_some_and(|error| state.base.retry_predicate_accepts(error));
+                        if !retryable {
+                            state.done = true;
+                            return Some((Err(e), state));
+                        }
+
+                        state.failures += 1;
+                        if state.failures >= MAX_WATCH_FAILURES {
+                            state.done = true;
+                            return Some((
+                                Err(Box::new(SandboxError::RetriesExhausted {
+                                    retries: state.failures - 1,
+                                    source: e,
+                                }) as _),
+                                state,
+                            ));
+                        }
+
+                        let policy = state
+                            .base
+                            .retry_policy
+                            .clone()
+                            .unwrap_or_else(|| RetryPolicy::new(MAX_WATCH_FAILURES));
+                        tokio::time::sleep(policy.backoff(state.failures - 1)).await;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Collapse multiple events for the same path into the latest one, keeping first-seen order
+fn coalesce_events(events: Vec<FsEvent>) -> Vec<FsEvent> {
+    let mut coalesced: Vec<FsEvent> = Vec::with_capacity(events.len());
+    for event in events {
+        match coalesced.iter_mut().find(|e| e.path == event.path) {
+            Some(existing) => existing.kind = event.kind,
+            None => coalesced.push(event),
+        }
+    }
+    coalesced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SandboxOptions;
+    use futures::StreamExt;
+    use serde_json::Value;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Answer one request per connection with each HTTP status and body in turn, returning
+    /// the params of every request
+    async fn serve_responses(listener: TcpListener, responses: Vec<(&str, Value)>) -> Vec<Value> {
+        let mut requests = Vec::new();
+        for (status, response) in responses {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+
+            let mut content_length = 0;
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 2 {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; content_length];
+            stream.read_exact(&mut body).await.unwrap();
+            requests.push(serde_json::from_slice::<Value>(&body).unwrap()["params"].clone());
+
+            let response = response.to_string();
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 {}\r\ncontent-type: application/json\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                        status,
+                        response.len(),
+                        response
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+        }
+        requests
+    }
+
+    /// A successful JSON-RPC response carrying `result`
+    fn ok(result: Value) -> (&'static str, Value) {
+        (
+            "200 OK",
+            json!({ "jsonrpc": "2.0", "id": "1", "result": result }),
+        )
+    }
+
+    /// A started sandbox talking to the server on `listener`, retrying without delay
+    fn started_sandbox(listener: &TcpListener) -> SandboxBase {
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .name("web")
+            .retry_policy(
+                RetryPolicy::new(1)
+                    .initial_backoff(Duration::from_millis(1))
+                    .jitter(false),
+            )
+            .build();
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        sandbox
+    }
+
+    #[tokio::test]
+    async fn test_fs_diff_lists_changes_since_a_marker() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sandbox = started_sandbox(&listener);
+        let server = tokio::spawn(serve_responses(
+            listener,
+            vec![
+                ok(json!({ "snapshot_id": "marker-1" })),
+                ok(json!({
+                    "changes": [
+                        { "path": "/app/main.py", "kind": "modified" },
+                        { "path": "/tmp/out", "kind": "added" },
+                    ],
+                })),
+            ],
+        ));
+
+        let marker = sandbox.fs_snapshot().await.unwrap();
+        assert_eq!(marker.id(), "marker-1");
+        assert_eq!(
+            sandbox.fs_diff(&marker).await.unwrap(),
+            [
+                FsChange {
+                    path: "/app/main.py".to_string(),
+                    kind: FsChangeKind::Modified,
+                },
+                FsChange {
+                    path: "/tmp/out".to_string(),
+                    kind: FsChangeKind::Added,
+                },
+            ]
+        );
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests[1]["since"], "marker-1");
+        assert_eq!(requests[1]["sandbox"], "web");
+    }
+
+    #[tokio::test]
+    async fn test_watch_fs_retries_transport_failures_and_resumes_from_the_cursor() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sandbox = started_sandbox(&listener);
+        let unavailable = (
+            "503 Service Unavailable",
+            json!({ "message": "unavailable" }),
+        );
+        let server = tokio::spawn(serve_responses(
+            listener,
+            vec![
+                ok(json!({
+                    "events": [{ "path": "/app/a", "kind": "create" }],
+                    "cursor": "c1",
+                })),
+                unavailable,
+                ok(json!({
+                    "events": [{ "path": "/app/b", "kind": "delete" }],
+                    "cursor": "c2",
+                })),
+            ],
+        ));
+
+        let paths: Vec<_> = sandbox
+            .watch_fs("/app", &["*.py"], None)
+            .take(2)
+            .map(|event| event.unwrap().path)
+            .collect()
+            .await;
+        assert_eq!(paths, ["/app/a", "/app/b"]);
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests[0]["cursor"], Value::Null);
+        assert_eq!(requests[0]["patterns"], json!(["*.py"]));
+        assert_eq!(requests[1]["cursor"], "c1");
+        assert_eq!(requests[2]["cursor"], "c1");
+    }
+
+    #[tokio::test]
+    async fn test_watch_fs_surfaces_rpc_errors_and_gives_up_on_repeated_failures() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sandbox = started_sandbox(&listener);
+        let unavailable = (
+            "503 Service Unavailable",
+            json!({ "message": "unavailable" }),
+        );
+        let server = tokio::spawn(serve_responses(
+            listener,
+            vec![
+                (
+                    "200 OK",
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": "1",
+                        "error": { "code": -32602, "message": "no such path" },
+                    }),
+                ),
+                unavailable.clone(),
+                unavailable.clone(),
+                unavailable,
+            ],
+        ));
+
+        // A JSON-RPC error is returned as is, without a retry
+        let errors: Vec<_> = sandbox.watch_fs("/missing", &[], None).collect().await;
+        assert_eq!(errors.len(), 1);
+        let err = errors[0].as_ref().unwrap_err();
+        let rpc = err
+            .downcast_ref::<SandboxError>()
+            .and_then(SandboxError::rpc_error)
+            .unwrap();
+        assert_eq!(rpc.message, "no such path");
+
+        // Transport failures end the watch once they repeat
+        let errors: Vec<_> = sandbox.watch_fs("/app", &[], None).collect().await;
+        assert_eq!(errors.len(), 1);
+        let err = errors[0].as_ref().unwrap_err();
+        let err = err.downcast_ref::<SandboxError>().unwrap();
+        assert_eq!(err.retries(), MAX_WATCH_FAILURES - 1);
+        assert!(
+            matches!(
+                err,
+                SandboxError::RetriesExhausted { source, .. }
+                    if matches!(
+                        source.downcast_ref::<SandboxError>(),
+                        Some(SandboxError::HttpStatus { status: 503, .. })
+                    )
+            ),
+            "{:?}",
+            err
+        );
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watch_fs_coalesces_events_within_the_debounce_window() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sandbox = started_sandbox(&listener);
+        let server = tokio::spawn(serve_responses(
+            listener,
+            vec![ok(json!({
+                "events": [
+                    { "path": "/app/a", "kind": "create" },
+                    { "path": "/app/b", "kind": "create" },
+                    { "path": "/app/a", "kind": "modify" },
+                    { "path": "/app/a", "kind": "delete" },
+                ],
+                "cursor": "c1",
+            }))],
+        ));
+
+        let events: Vec<_> = sandbox
+            .watch_fs("/app", &[], Some(Duration::from_millis(1)))
+            .take(2)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(
+            events,
+            [
+                FsEvent {
+                    path: "/app/a".to_string(),
+                    kind: FsEventKind::Delete,
+                },
+                FsEvent {
+                    path: "/app/b".to_string(),
+                    kind: FsEventKind::Create,
+                },
+            ]
+        );
+
+        server.await.unwrap();
+    }
+}
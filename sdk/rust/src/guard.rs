@@ -0,0 +1,61 @@
+//! Scoped handle that stops its sandbox when it goes out of scope
+
+use std::error::Error;
+use std::ops::{Deref, DerefMut};
+
+use crate::SandboxBase;
+
+/// Sandbox handle that stops the sandbox when the guard is dropped
+///
+/// The guard dereferences to the [`SandboxBase`] it wraps, so it can be used wherever the
+/// handle is. Dropping it while the sandbox is started spawns a best-effort stop on the
+/// current Tokio runtime, with the limitations described at
+/// [`SandboxOptions::stop_on_drop`](crate::SandboxOptions::stop_on_drop). Call
+/// [`close`](Self::close) at the end of an async scope to wait for the stop and see its
+/// error, and [`into_inner`](Self::into_inner) to keep the sandbox running.
+pub struct SandboxGuard {
+    /// The guarded handle, set to stop on drop
+    sandbox: SandboxBase,
+
+    /// Whether the handle stopped on drop before it was guarded
+    stop_on_drop: bool,
+}
+
+impl SandboxGuard {
+    /// Guard a sandbox handle
+    pub(crate) fn new(mut sandbox: SandboxBase) -> Self {
+        let stop_on_drop = std::mem::replace(&mut sandbox.stop_on_drop, true);
+        Self {
+            sandbox,
+            stop_on_drop,
+        }
+    }
+
+    /// Stop the sandbox, waiting for the server to confirm
+    pub async fn close(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.sandbox.close().await
+    }
+
+    /// Release the handle without stopping the sandbox
+    ///
+    /// The handle stops on drop again only if its options asked for it.
+    pub fn into_inner(self) -> SandboxBase {
+        let mut sandbox = self.sandbox;
+        sandbox.stop_on_drop = self.stop_on_drop;
+        sandbox
+    }
+}
+
+impl Deref for SandboxGuard {
+    type Target = SandboxBase;
+
+    fn deref(&self) -> &SandboxBase {
+        &self.sandbox
+    }
+}
+
+impl DerefMut for SandboxGuard {
+    fn deref_mut(&mut self) -> &mut SandboxBase {
+        &mut self.sandbox
+    }
+}
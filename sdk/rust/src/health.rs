@@ -0,0 +1,163 @@
+//! Checking the health of a sandbox
+
+use std::error::Error;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{SandboxBase, SandboxError, SandboxOptions};
+
+/// State of a sandbox, as recorded by the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SandboxState {
+    /// The sandbox has never been started
+    NotStarted,
+
+    /// The sandbox's MicroVM is running
+    Running,
+
+    /// The sandbox's MicroVM is suspended
+    Paused,
+
+    /// The sandbox has stopped
+    Stopped,
+
+    /// The sandbox's MicroVM exited abnormally without being stopped
+    Crashed,
+
+    /// A state this SDK doesn't know about
+    Other(String),
+}
+
+/// Health of a sandbox, returned by [`SandboxBase::health`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxHealth {
+    /// Recorded state of the sandbox
+    pub state: SandboxState,
+
+    /// How long the MicroVM has been running, if it still is
+    pub uptime: Option<Duration>,
+
+    /// PID of the sandbox's last MicroVM process
+    pub microvm_pid: Option<u32>,
+
+    /// PID of the sandbox's last supervisor process
+    pub supervisor_pid: Option<u32>,
+
+    /// Whether the supervisor process is alive
+    pub supervisor_alive: bool,
+}
+
+/// The result of a `sandbox.status` request
+#[derive(Debug, Deserialize)]
+struct HealthResponse {
+    status: Option<String>,
+    uptime_secs: Option<f64>,
+    supervisor_pid: Option<u32>,
+    microvm_pid: Option<u32>,
+    supervisor_alive: bool,
+}
+
+impl SandboxState {
+    /// Map a status recorded by the server to a state, `None` meaning never started
+    pub(crate) fn from_status(status: Option<&str>) -> Self {
+        match status {
+            None => SandboxState::NotStarted,
+            Some("RUNNING") => SandboxState::Running,
+            Some("PAUSED") => SandboxState::Paused,
+            Some("STOPPED") => SandboxState::Stopped,
+            Some("CRASHED") => SandboxState::Crashed,
+            Some(other) => SandboxState::Other(other.to_string()),
+        }
+    }
+}
+
+impl SandboxHealth {
+    /// Check whether the sandbox is running and its supervisor is alive
+    ///
+    /// A sandbox recorded as running whose supervisor has died is not healthy: nothing is
+    /// left to manage its MicroVM or update its state.
+    pub fn is_healthy(&self) -> bool {
+        self.state == SandboxState::Running && self.supervisor_alive
+    }
+}
+
+impl SandboxBase {
+    /// Check the health of the sandbox on the server
+    ///
+    /// Calls the `sandbox.status` endpoint, which works whether or not this handle has started
+    /// the sandbox, so it can be polled while waiting for a sandbox to come up. A sandbox that
+    /// isn't defined on the server fails with
+    /// [`SandboxError::NotFound`](crate::SandboxError::NotFound), while a server that can't be
+    /// reached fails with [`SandboxError::HttpError`](crate::SandboxError::HttpError).
+    pub async fn health(&self) -> Result<SandboxHealth, Box<dyn Error + Send + Sync>> {
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+        });
+        let response: HealthResponse = self.make_request("sandbox.status", params).await?;
+
+        Ok(SandboxHealth {
+            state: SandboxState::from_status(response.status.as_deref()),
+            uptime: response
+                .uptime_secs
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok()),
+            microvm_pid: response.microvm_pid,
+            supervisor_pid: response.supervisor_pid,
+            supervisor_alive: response.supervisor_alive,
+        })
+    }
+
+    /// Attach to a sandbox that is already running on the server
+    ///
+    /// Lets a process that restarted pick up a sandbox it started before, for example to run
+    /// more code in it or stop it. Uses the server URL and credentials from the environment;
+    /// see [`attach_with_options`](Self::attach_with_options) to set them.
+    pub async fn attach(name: &str, namespace: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let options = SandboxOptions::builder()
+            .name(name)
+            .namespace(namespace)
+            .build();
+        Self::attach_with_options(&options).await
+    }
+
+    /// Attach to a sandbox that is already running on the server, using `options` for the
+    /// server connection and the defaults of later requests
+    ///
+    /// The sandbox is identified by the name and namespace in `options`, and is not started
+    /// or reconfigured: options that only apply on start have no effect. Fails with
+    /// [`SandboxError::NotRunning`] unless the sandbox is running or paused with a live
+    /// supervisor, and with [`SandboxError::NotFound`] if the server doesn't know it.
+    pub async fn attach_with_options(
+        options: &SandboxOptions,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        options.validate()?;
+        if options.name.is_none() && options.idempotency_key.is_none() {
+            return Err(Box::new(SandboxError::InvalidInput(
+                "a sandbox name is required to attach to a sandbox".to_string(),
+            )));
+        }
+
+        let mut base = Self::new(options);
+        let health = base.health().await?;
+        let reason = match &health.state {
+            SandboxState::Running | SandboxState::Paused if health.supervisor_alive => None,
+            SandboxState::Running | SandboxState::Paused => Some("its supervisor has died"),
+            SandboxState::NotStarted => Some("it has never been started"),
+            SandboxState::Stopped => Some("it is stopped"),
+            SandboxState::Crashed => Some("it crashed"),
+            SandboxState::Other(status) => Some(status.as_str()),
+        };
+        if let Some(reason) = reason {
+            return Err(Box::new(SandboxError::NotRunning(format!(
+                "sandbox {}/{} can't be attached to: {}",
+                base.namespace, base.name, reason
+            ))));
+        }
+
+        base.is_started = true;
+        base.is_paused = health.state == SandboxState::Paused;
+        Ok(base)
+    }
+}
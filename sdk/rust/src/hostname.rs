@@ -0,0 +1,95 @@
+//! Guest hostname validation
+
+use crate::SandboxError;
+
+/// Maximum length of a full hostname
+const MAX_HOSTNAME_LEN: usize = 253;
+
+/// Maximum length of a single dot-separated label
+const MAX_LABEL_LEN: usize = 63;
+
+/// Check that a hostname follows RFC 1123
+///
+/// The hostname is made of dot-separated labels of 1 to 63 alphanumeric characters or
+/// hyphens, not starting or ending with a hyphen, and is at most 253 characters long.
+pub(crate) fn validate_hostname(hostname: &str) -> Result<(), SandboxError> {
+    let invalid = |reason: &str| {
+        SandboxError::InvalidInput(format!("invalid hostname '{}': {}", hostname, reason))
+    };
+
+    if hostname.is_empty() || hostname.len() > MAX_HOSTNAME_LEN {
+        return Err(invalid("must be between 1 and 253 characters"));
+    }
+
+    for label in hostname.split('.') {
+        if label.is_empty() || label.len() > MAX_LABEL_LEN {
+            return Err(invalid("each label must be between 1 and 63 characters"));
+        }
+
+        if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(invalid(
+                "labels can only contain alphanumeric characters or hyphens",
+            ));
+        }
+
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(invalid("labels cannot start or end with a hyphen"));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_hostname() {
+        let label = |len: usize| "a".repeat(len);
+        // Three labels of 63 characters, one of 61 and the dots between them make 253
+        let longest = [label(63), label(63), label(63), label(61)].join(".");
+        assert_eq!(longest.len(), MAX_HOSTNAME_LEN);
+
+        let cases = [
+            // Length of the whole hostname
+            ("", Some("between 1 and 253 characters")),
+            ("a", None),
+            (longest.as_str(), None),
+            (
+                &format!("{}a", longest),
+                Some("between 1 and 253 characters"),
+            ),
+            // Length of each label
+            (&label(MAX_LABEL_LEN), None),
+            (
+                &label(MAX_LABEL_LEN + 1),
+                Some("label must be between 1 and 63"),
+            ),
+            ("web..internal", Some("label must be between 1 and 63")),
+            ("web.", Some("label must be between 1 and 63")),
+            (".web", Some("label must be between 1 and 63")),
+            // Characters allowed in a label
+            ("web-1.Internal", None),
+            ("web_1", Some("alphanumeric characters or hyphens")),
+            ("web 1", Some("alphanumeric characters or hyphens")),
+            ("wéb", Some("alphanumeric characters or hyphens")),
+            // Hyphens at either end of a label
+            ("-web", Some("start or end with a hyphen")),
+            ("web-", Some("start or end with a hyphen")),
+            ("db.-web", Some("start or end with a hyphen")),
+            ("web-.db", Some("start or end with a hyphen")),
+            ("-", Some("start or end with a hyphen")),
+        ];
+
+        for (hostname, expected) in cases {
+            match (validate_hostname(hostname), expected) {
+                (Ok(()), None) => {}
+                (Err(SandboxError::InvalidInput(message)), Some(reason)) => {
+                    assert!(message.contains(reason), "{}: {}", hostname, message)
+                }
+                (result, _) => panic!("{}: unexpected {:?}", hostname, result),
+            }
+        }
+    }
+}
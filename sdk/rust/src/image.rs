@@ -0,0 +1,41 @@
+//! Pulling images ahead of sandbox starts
+
+use std::error::Error;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{SandboxBase, SandboxError};
+
+/// Outcome of an image pull, returned by [`SandboxBase::pull_image`]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ImagePull {
+    /// Full reference of the image, with its registry and tag
+    pub reference: String,
+
+    /// Whether the image was already on the server, so nothing was downloaded
+    pub already_present: bool,
+}
+
+impl SandboxBase {
+    /// Pull an image onto the server, so that starting a sandbox with it doesn't pull
+    ///
+    /// Completes once the image is stored on the server. An image that is already there is
+    /// left alone and reported as [`already_present`](ImagePull::already_present). With a
+    /// pull timeout set in the sandbox's options, a pull that takes longer fails with
+    /// [`SandboxError::Timeout`]; the server may still finish it.
+    pub async fn pull_image(
+        &self,
+        reference: &str,
+    ) -> Result<ImagePull, Box<dyn Error + Send + Sync>> {
+        if reference.trim().is_empty() {
+            return Err(Box::new(SandboxError::InvalidInput(
+                "image reference cannot be empty".to_string(),
+            )));
+        }
+
+        let params = json!({ "reference": reference });
+        self.make_request_with_timeout("image.pull", params, self.pull_timeout)
+            .await
+    }
+}
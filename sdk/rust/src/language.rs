@@ -0,0 +1,71 @@
+//! Languages supported by sandbox REPLs
+
+use std::error::Error;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::SandboxBase;
+
+/// A language that code can be run in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    /// Python
+    Python,
+
+    /// JavaScript (Node.js)
+    JavaScript,
+}
+
+impl Language {
+    /// Get the language name used by the server
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Language::Python => "python",
+            Language::JavaScript => "javascript",
+        }
+    }
+}
+
+/// A language the server can run code in, as reported by the server
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LanguageInfo {
+    /// Language name, as passed to `run_code`
+    pub name: String,
+
+    /// Image providing the language's REPL
+    pub image: String,
+
+    /// Version of the language, if the server has pulled the image
+    pub version: Option<String>,
+
+    /// Package manager available in the sandbox, e.g. `pip`
+    pub package_manager: String,
+}
+
+/// Response of the `server.languages` request
+#[derive(Debug, Deserialize)]
+struct LanguagesResponse {
+    languages: Vec<LanguageInfo>,
+}
+
+impl SandboxBase {
+    /// Get the languages the server can run code in
+    ///
+    /// The list comes from the server, so it stays in sync with what the server actually
+    /// supports. It is fetched once and cached on this handle; the sandbox doesn't need to be
+    /// started.
+    pub async fn supported_languages(
+        &self,
+    ) -> Result<Vec<LanguageInfo>, Box<dyn Error + Send + Sync>> {
+        if let Some(languages) = self.supported_languages.get() {
+            return Ok(languages.clone());
+        }
+
+        let response: LanguagesResponse = self.make_request("server.languages", json!({})).await?;
+        Ok(self
+            .supported_languages
+            .get_or_init(|| response.languages)
+            .clone())
+    }
+}
@@ -7,25 +7,92 @@
 use async_trait::async_trait;
 
 // Re-export common types
+pub use auth::{Auth, AuthFn};
 pub use base::SandboxBase;
+pub use batch::RpcCall;
+pub use budget::{Clock, RetryBudget, SystemClock};
 pub use builder::SandboxOptions;
+pub use capture::WriteMode;
 pub use command::Command;
-pub use error::SandboxError;
-pub use execution::Execution;
+pub use compat::{Compatibility, CompatibilityReport};
+pub use describe::{DescribeOptions, SandboxDescription};
+pub use diagnose::{CheckStatus, DiagnosticCheck, DiagnosticStep, DiagnosticsReport};
+pub use error::{
+    RpcError, SandboxError, EXECUTION_LIMIT_CODE, INTERNAL_ERROR_CODE, INVALID_PARAMS_CODE,
+    METHOD_NOT_FOUND_CODE, SANDBOX_NOT_FOUND_CODE, START_PHASE_TIMEOUT_CODE,
+};
+pub use execution::{Execution, ExecutionResult, ExecutionSummary, OutputLine, ResourceUsage};
+pub use files::{DirEntry, InlineFile};
+pub use fs::{FsChange, FsChangeKind, FsEvent, FsEventKind, SnapshotRef};
+pub use guard::SandboxGuard;
+pub use health::{SandboxHealth, SandboxState};
+pub use image::ImagePull;
+pub use language::{Language, LanguageInfo};
+pub use logging::{RequestEvent, RequestLogging, RequestObserver};
+pub use logs::{LogQuery, LogStream, SandboxLogs};
 pub use metrics::Metrics;
 pub use node::NodeSandbox;
+pub use probe::{ProbeCheck, ProbeSpec};
+pub use process::{ExitFuture, InputSink, OutputStream};
 pub use python::PythonSandbox;
+pub use retry::{default_retry_predicate, RetryPolicy, RetryPredicate};
+pub use sandboxes::SandboxInfo;
+pub use security::{SeccompProfile, SecurityProfile};
+pub use snapshot::Snapshot;
 pub use start_options::StartOptions;
+#[allow(deprecated)]
+pub use start_outcome::Warning;
+pub use start_outcome::{
+    SandboxWarning, StartOutcome, StartPhase, WARNING_CPUS_ROUNDED, WARNING_START_TIMEOUT,
+    WARNING_START_UNVERIFIED, WARNING_UNKNOWN,
+};
+pub use stats::SandboxStats;
+pub use streaming::{OutputChunk, StreamKind};
+pub use tls::Pem;
+pub use ulimit::Ulimit;
+pub use validate::{
+    ConfigDiagnostic, ConfigValidation, CHECK_CONFIG, CHECK_IMAGE, CHECK_NAME, CHECK_RESOURCES,
+};
 
+mod auth;
 mod base;
+mod batch;
+mod budget;
 mod builder;
+mod capture;
+mod cells;
 mod command;
+mod compat;
+mod describe;
+mod diagnose;
 mod error;
 mod execution;
+mod files;
+mod fs;
+mod guard;
+mod health;
+mod hostname;
+mod image;
+mod language;
+mod logging;
+mod logs;
 mod metrics;
 mod node;
+mod probe;
+mod process;
 mod python;
+mod retry;
+mod sandboxes;
+mod security;
+mod snapshot;
 mod start_options;
+mod start_outcome;
+mod stats;
+mod streaming;
+mod support;
+mod tls;
+mod ulimit;
+mod validate;
 
 /// Base trait for sandbox implementations
 #[async_trait]
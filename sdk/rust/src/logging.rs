@@ -0,0 +1,277 @@
+//! Debug logging of the JSON-RPC requests and responses exchanged with the server
+
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use serde_json::Value;
+
+/// `tracing` target of request and response events
+const LOG_TARGET: &str = "microsandbox::rpc";
+
+/// Text logged in place of a redacted value
+const REDACTED: &str = "[REDACTED]";
+
+/// Fields redacted unless allowed with [`RequestLogging::allow`]
+const DEFAULT_REDACTED_FIELDS: [&str; 7] = [
+    "api_key", "token", "secret", "password", "auth", "env", "envs",
+];
+
+/// Logging of the full JSON-RPC bodies sent to and received from the server
+///
+/// Set it in [`SandboxOptions`](crate::SandboxOptions) for wire-level debugging. Every request
+/// and response is logged as a `tracing` debug event with the `microsandbox::rpc` target, so
+/// it shows up only when that target is enabled.
+///
+/// Bodies can carry secrets, so the values of redacted fields are replaced with
+/// `[REDACTED]` wherever the field appears in a body. Field names match without regard to
+/// case. Environment variables given as `KEY=VALUE` strings keep their names and lose only
+/// their values. By default `api_key`, `token`, `secret`, `password`, `auth`, `env` and
+/// `envs` are redacted. Header values are never logged, except `content-type`, so neither
+/// the `Authorization` header nor a request signature ends up in the logs.
+///
+/// Once each request completes, a debug event with its method, elapsed time and error, if
+/// any, is logged as well, and passed to the observer set with
+/// [`observe`](RequestLogging::observe).
+#[derive(Debug, Clone)]
+pub struct RequestLogging {
+    redacted_fields: Vec<String>,
+    observer: Option<ObserverFn>,
+}
+
+/// Called with a summary of each request once it completes
+///
+/// Set it with [`RequestLogging::observe`].
+pub type RequestObserver = Arc<dyn Fn(&RequestEvent) + Send + Sync>;
+
+/// Summary of a completed request, passed to a [`RequestObserver`]
+///
+/// Requests retried under the retry policy produce one event per attempt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestEvent {
+    /// JSON-RPC method of the request
+    pub method: String,
+
+    /// Params of the request, with the redacted fields' values replaced
+    pub params: Value,
+
+    /// Time from sending the request to reading its response
+    pub elapsed: Duration,
+
+    /// Why the request failed, or `None` if the server returned a result
+    pub error: Option<String>,
+}
+
+/// A request observer that can live in the options, which are `Debug`
+#[derive(Clone)]
+struct ObserverFn(RequestObserver);
+
+impl RequestLogging {
+    /// Log bodies with the default fields redacted
+    pub fn new() -> Self {
+        Self {
+            redacted_fields: DEFAULT_REDACTED_FIELDS
+                .iter()
+                .map(|field| field.to_string())
+                .collect(),
+            observer: None,
+        }
+    }
+
+    /// Call `observer` with a summary of each request once it completes
+    ///
+    /// The observer sees the same redacted params that are logged, and runs on the task that
+    /// made the request, so it should return quickly.
+    pub fn observe(mut self, observer: RequestObserver) -> Self {
+        self.observer = Some(ObserverFn(observer));
+        self
+    }
+
+    /// Also redact the values of `field`, such as `code` to keep executed code out of logs
+    pub fn redact(mut self, field: impl Into<String>) -> Self {
+        let field = field.into().to_lowercase();
+        if !self.redacted_fields.contains(&field) {
+            self.redacted_fields.push(field);
+        }
+        self
+    }
+
+    /// Log the values of `field` as they are, even if it is redacted by default
+    pub fn allow(mut self, field: &str) -> Self {
+        let field = field.to_lowercase();
+        self.redacted_fields.retain(|f| *f != field);
+        self
+    }
+
+    /// Get the fields whose values are redacted
+    pub fn redacted_fields(&self) -> &[String] {
+        &self.redacted_fields
+    }
+
+    /// Log a serialized request about to be sent
+    pub(crate) fn log_request(&self, method: &str, body: &[u8], headers: &HeaderMap) {
+        let body = match serde_json::from_slice::<Value>(body) {
+            Ok(mut body) => {
+                self.redact_value(&mut body);
+                body.to_string()
+            }
+            Err(_) => format!("<{} bytes of non-JSON body>", body.len()),
+        };
+
+        tracing::debug!(
+            target: LOG_TARGET,
+            method,
+            headers = %describe_headers(headers),
+            body = %body,
+            "sending request"
+        );
+    }
+
+    /// Log a response received for a request
+    pub(crate) fn log_response(&self, method: &str, response: &Value) {
+        let mut response = response.clone();
+        self.redact_value(&mut response);
+
+        tracing::debug!(
+            target: LOG_TARGET,
+            method,
+            body = %response,
+            "received response"
+        );
+    }
+
+    /// Log a completed request and pass it to the observer, if any
+    pub(crate) fn log_finished(
+        &self,
+        method: &str,
+        mut params: Value,
+        elapsed: Duration,
+        error: Option<&(dyn Error + Send + Sync)>,
+    ) {
+        self.redact_value(&mut params);
+        let error = error.map(|e| e.to_string());
+
+        tracing::debug!(
+            target: LOG_TARGET,
+            method,
+            elapsed_ms = elapsed.as_millis() as u64,
+            error = error.as_deref(),
+            "request finished"
+        );
+
+        if let Some(ObserverFn(observer)) = &self.observer {
+            observer(&RequestEvent {
+                method: method.to_string(),
+                params,
+                elapsed,
+                error,
+            });
+        }
+    }
+
+    /// Replace the values of redacted fields anywhere in `value`
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self.redacted_fields.contains(&key.to_lowercase()) {
+                        redact_all(value);
+                    } else {
+                        self.redact_value(value);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact_value(item)),
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Debug for ObserverFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ObserverFn(..)")
+    }
+}
+
+impl Default for RequestLogging {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Redact a value entirely, keeping only object keys and the names of `KEY=VALUE` variables
+fn redact_all(value: &mut Value) {
+    match value {
+        Value::Null => {}
+        Value::String(s) => {
+            *s = match s.split_once('=') {
+                Some((name, _)) if is_variable_name(name) => format!("{}={}", name, REDACTED),
+                _ => REDACTED.to_string(),
+            };
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_all),
+        Value::Object(map) => map.values_mut().for_each(redact_all),
+        _ => *value = Value::String(REDACTED.to_string()),
+    }
+}
+
+/// Check whether `name` looks like the name of an environment variable
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Describe request headers without their values, apart from the content type
+fn describe_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            if name == CONTENT_TYPE {
+                format!("{}: {}", name, value.to_str().unwrap_or(REDACTED))
+            } else {
+                format!("{}: {}", name, REDACTED)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderValue, AUTHORIZATION};
+    use serde_json::json;
+
+    #[test]
+    fn test_redaction_hides_secrets_but_keeps_structure() {
+        let logging = RequestLogging::new().redact("Code").allow("token");
+        let mut body = json!({
+            "method": "sandbox.start",
+            "params": {
+                "api_key": "sk-123",
+                "token": "visible",
+                "code": "print(end=secret)",
+                "config": { "envs": ["HOME=/root", "SECRET=hunter2"], "memory": 512 },
+            },
+        });
+        logging.redact_value(&mut body);
+
+        assert_eq!(
+            body["params"],
+            json!({
+                "api_key": "[REDACTED]",
+                "token": "visible",
+                "code": "[REDACTED]",
+                "config": { "envs": ["HOME=[REDACTED]", "SECRET=[REDACTED]"], "memory": 512 },
+            })
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer sk-123"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let described = describe_headers(&headers);
+        assert!(!described.contains("sk-123"));
+        assert!(described.contains("content-type: application/json"));
+    }
+}
@@ -0,0 +1,488 @@
+//! Reading and following the log of a sandbox
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{SandboxBase, SandboxError, SandboxState, StreamKind};
+
+/// Time between `sandbox.logs` polls while a followed log has no new output
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Which part of a sandbox's captured output [`SandboxBase::get_logs`] returns
+///
+/// By default all of the output still in the log is returned, from both streams.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogQuery {
+    tail: Option<usize>,
+    offset: Option<u64>,
+    stream: Option<StreamKind>,
+}
+
+/// Output captured by the server while the sandbox ran
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxLogs {
+    /// The output bytes
+    pub data: Vec<u8>,
+
+    /// Offset just past the returned output; pass it to [`LogQuery::offset`] to read what the
+    /// sandbox writes next
+    pub next_offset: u64,
+
+    /// Whether output in the requested range is missing, because the server rotated it out of
+    /// the log or it didn't fit in one response
+    pub truncated: bool,
+}
+
+/// The result of a `sandbox.logs` request
+#[derive(Debug, Deserialize)]
+struct LogsResponse {
+    content: String,
+    next_offset: u64,
+    truncated: bool,
+}
+
+/// Lines of a sandbox's log, read as the sandbox writes them
+///
+/// Returned by [`SandboxBase::follow_logs`]. The log is polled with `sandbox.logs` requests,
+/// each continuing at the offset where the last one ended; drop the stream to stop following.
+pub struct LogStream {
+    base: SandboxBase,
+    offset: Option<u64>,
+    partial: Vec<u8>,
+    lines: VecDeque<String>,
+    stopped: bool,
+    ended: bool,
+}
+
+impl LogQuery {
+    /// Create a query for all of the captured output
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only return the last `lines` lines
+    pub fn tail(mut self, lines: usize) -> Self {
+        self.tail = Some(lines);
+        self
+    }
+
+    /// Start reading at `offset` bytes into the log, e.g. the
+    /// [`next_offset`](SandboxLogs::next_offset) of an earlier read
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Only return output written to `stream`
+    ///
+    /// The server can only tell the streams apart when the sandbox's log is in the `jsonl`
+    /// format; with a raw log, the request fails.
+    pub fn stream(mut self, stream: StreamKind) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+}
+
+impl SandboxLogs {
+    /// Get the output as text, if it is valid UTF-8
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+}
+
+impl std::fmt::Debug for LogStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogStream")
+            .field("sandbox", &self.base.name)
+            .field("namespace", &self.base.namespace)
+            .field("offset", &self.offset)
+            .field("ended", &self.ended)
+            .finish()
+    }
+}
+
+impl LogStream {
+    /// Get the next line of the log, or `None` once the sandbox has stopped and its last
+    /// line has been read
+    ///
+    /// While the sandbox runs without writing anything, this waits for its next line.
+    pub async fn next(&mut self) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+        loop {
+            if let Some(line) = self.lines.pop_front() {
+                return Ok(Some(line));
+            }
+
+            if self.ended {
+                return Ok(None);
+            }
+
+            let mut query = LogQuery::new();
+            if let Some(offset) = self.offset {
+                query = query.offset(offset);
+            }
+            let logs = self.base.get_logs(query).await?;
+            self.offset = Some(logs.next_offset);
+
+            if !logs.data.is_empty() {
+                self.push(&logs.data);
+                continue;
+            }
+
+            if self.stopped {
+                // Nothing was written between the sandbox stopping and this read
+                self.ended = true;
+                if !self.partial.is_empty() {
+                    let line = std::mem::take(&mut self.partial);
+                    self.lines
+                        .push_back(String::from_utf8_lossy(&line).into_owned());
+                }
+                continue;
+            }
+
+            // Read once more after the sandbox stops, for output written just before it did
+            let state = self.base.health().await?.state;
+            if matches!(state, SandboxState::Running | SandboxState::Paused) {
+                tokio::time::sleep(LOG_POLL_INTERVAL).await;
+            } else {
+                self.stopped = true;
+            }
+        }
+    }
+
+    /// Split newly read output into lines, keeping an unfinished last line for later
+    fn push(&mut self, data: &[u8]) {
+        self.partial.extend_from_slice(data);
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            let line = line[..end].strip_suffix(b"\r").unwrap_or(&line[..end]);
+            self.lines
+                .push_back(String::from_utf8_lossy(line).into_owned());
+        }
+    }
+
+    /// Read lines until one matches `pattern`, and return that line
+    ///
+    /// Fails with [`SandboxError::Timeout`] if no line matches within `timeout`, and with
+    /// [`SandboxError::General`] if the stream ends first. Lines read before the match are
+    /// consumed.
+    pub async fn wait_for(
+        &mut self,
+        pattern: &Regex,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let matched = tokio::time::timeout(timeout, async {
+            while let Some(line) = self.next().await? {
+                if pattern.is_match(&line) {
+                    return Ok(line);
+                }
+            }
+            Err(Box::new(SandboxError::General(format!(
+                "log ended before a line matched '{}'",
+                pattern
+            ))) as Box<dyn Error + Send + Sync>)
+        })
+        .await;
+
+        matched.unwrap_or_else(|_| {
+            Err(Box::new(SandboxError::Timeout(format!(
+                "no log line matched '{}' within {:?}",
+                pattern, timeout
+            ))))
+        })
+    }
+}
+
+impl SandboxBase {
+    /// Follow the sandbox's log, starting with the lines written so far
+    ///
+    /// The returned stream polls the `sandbox.logs` endpoint, continuing each read at the
+    /// [`next_offset`](SandboxLogs::next_offset) of the last, so it sees new lines within a
+    /// fraction of a second of the sandbox writing them. It ends once the sandbox has stopped
+    /// and the rest of its log has been read.
+    pub async fn follow_logs(&self) -> Result<LogStream, Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        // The stream has its own handle, which must not stop the sandbox when dropped
+        let mut base = self.started_handle(&self.name);
+        base.stop_on_drop = false;
+
+        Ok(LogStream {
+            base,
+            offset: None,
+            partial: Vec::new(),
+            lines: VecDeque::new(),
+            stopped: false,
+            ended: false,
+        })
+    }
+
+    /// Get the output the server captured from the sandbox
+    ///
+    /// This works after the sandbox has stopped, so it can show why a sandbox exited early.
+    /// Output is read from the `sandbox.logs`
+    /// endpoint, which returns at most 1MiB per call; read the rest by passing
+    /// [`next_offset`](SandboxLogs::next_offset) back as the query's offset.
+    pub async fn get_logs(
+        &self,
+        query: LogQuery,
+    ) -> Result<SandboxLogs, Box<dyn Error + Send + Sync>> {
+        let mut params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+        });
+        if let Some(tail) = query.tail {
+            params["tail"] = json!(tail);
+        }
+        if let Some(offset) = query.offset {
+            params["offset"] = json!(offset);
+        }
+        if let Some(stream) = query.stream {
+            params["stream"] = json!(stream.as_str());
+        }
+
+        let response: LogsResponse = self.make_request("sandbox.logs", params).await?;
+        let data =
+            BASE64
+                .decode(&response.content)
+                .map_err(|e| SandboxError::MalformedResponse {
+                    received: response.content.len(),
+                    message: format!("invalid log content: {}", e),
+                })?;
+
+        Ok(SandboxLogs {
+            data,
+            next_offset: response.next_offset,
+            truncated: response.truncated,
+        })
+    }
+
+    /// Wait until the sandbox logs a line matching `pattern`, and return that line
+    ///
+    /// For services that report readiness only in their log, such as
+    /// `Server started on port 8080`. Lines already in the log count, so a line written
+    /// before the call is found too. Fails with [`SandboxError::Timeout`] if no line matches
+    /// within `timeout`.
+    ///
+    /// Dropping the returned future cancels the wait and stops polling the log, so it can be
+    /// raced against other futures with `tokio::select!`.
+    pub async fn wait_for_log(
+        &self,
+        pattern: Regex,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let started = tokio::time::Instant::now();
+        let mut logs = tokio::time::timeout(timeout, self.follow_logs())
+            .await
+            .map_err(|_| {
+                Box::new(SandboxError::Timeout(format!(
+                    "no log line matched '{}' within {:?}",
+                    pattern, timeout
+                )))
+            })??;
+
+        logs.wait_for(&pattern, timeout.saturating_sub(started.elapsed()))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SandboxOptions;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Answer JSON-RPC requests, one per connection, with the result `respond` gives for
+    /// each method and params
+    async fn serve_rpc(
+        listener: TcpListener,
+        mut respond: impl FnMut(&str, &serde_json::Value) -> serde_json::Value,
+    ) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+
+            let mut content_length = 0;
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 2 {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; content_length];
+            tokio::io::AsyncReadExt::read_exact(&mut stream, &mut body)
+                .await
+                .unwrap();
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let result = respond(request["method"].as_str().unwrap(), &request["params"]);
+
+            let response = json!({ "jsonrpc": "2.0", "result": result, "id": 1 }).to_string();
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                        response.len(),
+                        response
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Result of a `sandbox.logs` request returning `content`
+    fn logs_result(content: &str, next_offset: u64) -> serde_json::Value {
+        json!({
+            "content": BASE64.encode(content),
+            "next_offset": next_offset,
+            "truncated": false,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_get_logs_sends_query_and_decodes_output() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .build();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+
+            let mut content_length = 0;
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 2 {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; content_length];
+            tokio::io::AsyncReadExt::read_exact(&mut stream, &mut body)
+                .await
+                .unwrap();
+
+            let response = json!({
+                "jsonrpc": "2.0",
+                "result": {
+                    "content": BASE64.encode("Traceback\nValueError\n"),
+                    "next_offset": 4096,
+                    "truncated": true,
+                },
+                "id": 1,
+            })
+            .to_string();
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+                        response.len(),
+                        response
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        });
+
+        let sandbox = SandboxBase::new(&options);
+        let logs = sandbox
+            .get_logs(LogQuery::new().tail(2).stream(StreamKind::Stderr))
+            .await
+            .unwrap();
+        assert_eq!(logs.text(), Some("Traceback\nValueError\n"));
+        assert_eq!(logs.next_offset, 4096);
+        assert!(logs.truncated);
+
+        let request = server.await.unwrap();
+        assert_eq!(request["method"], "sandbox.logs");
+        assert_eq!(request["params"]["tail"], 2);
+        assert_eq!(request["params"]["stream"], "stderr");
+        assert!(request["params"].get("offset").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_follow_logs_polls_from_offsets_until_the_sandbox_stops() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .build();
+
+        // A line split across two reads, an idle read while running, and a last line without
+        // a newline written just before the sandbox stops
+        let mut results = vec![
+            logs_result("booting\nServer started on port 80", 32),
+            logs_result("80\nready\n", 41),
+            logs_result("", 41),
+            json!({ "status": "RUNNING", "supervisor_alive": true }),
+            logs_result("bye", 44),
+            logs_result("", 44),
+            json!({ "status": "STOPPED", "supervisor_alive": false }),
+            logs_result("", 44),
+        ]
+        .into_iter();
+        let offsets = Arc::new(Mutex::new(Vec::new()));
+        let seen = offsets.clone();
+        tokio::spawn(serve_rpc(listener, move |method, params| {
+            if method == "sandbox.logs" {
+                seen.lock().unwrap().push(params["offset"].as_u64());
+            }
+            results.next().unwrap()
+        }));
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        let mut logs = sandbox.follow_logs().await.unwrap();
+
+        let pattern = Regex::new(r"started on port (\d+)").unwrap();
+        let line = logs
+            .wait_for(&pattern, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(line, "Server started on port 8080");
+        assert_eq!(logs.next().await.unwrap().as_deref(), Some("ready"));
+        assert_eq!(logs.next().await.unwrap().as_deref(), Some("bye"));
+        assert_eq!(logs.next().await.unwrap(), None);
+        assert_eq!(
+            *offsets.lock().unwrap(),
+            [None, Some(32), Some(41), Some(41), Some(44), Some(44)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_log_times_out_while_the_sandbox_runs() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .build();
+        tokio::spawn(serve_rpc(listener, |method, params| match method {
+            "sandbox.logs" if params["offset"].is_null() => logs_result("booting\n", 8),
+            "sandbox.logs" => logs_result("", 8),
+            _ => json!({ "status": "RUNNING", "supervisor_alive": true }),
+        }));
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        let err = sandbox
+            .wait_for_log(Regex::new("ready").unwrap(), Duration::from_millis(600))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SandboxError>(),
+            Some(SandboxError::Timeout(_))
+        ));
+    }
+}
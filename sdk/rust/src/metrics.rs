@@ -32,13 +32,16 @@ impl Metrics {
         }
 
         // Extract sandbox details
-        let (server_url, namespace, sandbox_name, api_key) = {
+        let (client, server_url, namespace, sandbox_name, auth, retry_budget, request_logging) = {
             let base = self.base.lock().await;
             (
+                base.http_client()?.clone(),
                 base.server_url.clone(),
                 base.namespace.clone(),
                 base.name.clone(),
-                base.api_key.clone(),
+                base.auth.clone(),
+                base.retry_budget.clone(),
+                base.request_logging.clone(),
             )
         };
 
@@ -54,19 +57,21 @@ impl Metrics {
             "id": request_id,
         });
 
-        // Create HTTP client
-        let client = reqwest::Client::new();
-        let mut req_builder = client
-            .post(&format!("{}/api/v1/rpc", server_url))
-            .json(&payload)
-            .header("Content-Type", "application/json");
-
-        // Add API key if present
-        if let Some(key) = api_key {
-            req_builder = req_builder.header("Authorization", format!("Bearer {}", key));
+        // Encode request, sent on the sandbox's pooled client
+        let body = serde_json::to_vec(&payload)?;
+        let headers = auth.request_headers(&body)?;
+        if let Some(logging) = &request_logging {
+            logging.log_request("sandbox.metrics.get", &body, &headers);
         }
+        let req_builder = client
+            .post(&format!("{}/api/v1/rpc", server_url))
+            .headers(headers)
+            .body(body);
 
         // Send request
+        if let Some(budget) = retry_budget {
+            budget.acquire().await;
+        }
         let response = req_builder
             .send()
             .await
@@ -90,6 +95,9 @@ impl Metrics {
             .json()
             .await
             .map_err(|e| Box::new(crate::SandboxError::InvalidResponse(e.to_string())))?;
+        if let Some(logging) = &request_logging {
+            logging.log_response("sandbox.metrics.get", &response_data);
+        }
 
         // Check for errors in response
         if let Some(error) = response_data.get("error") {
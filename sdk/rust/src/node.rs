@@ -2,12 +2,20 @@
 
 use std::error::Error;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use futures::stream::Stream;
+use regex::Regex;
 use tokio::sync::Mutex;
 
+use crate::cells::cell_stream;
 use crate::command::Command;
-use crate::{BaseSandbox, Execution, Metrics, SandboxBase, SandboxOptions, StartOptions};
+use crate::{
+    BaseSandbox, CompatibilityReport, DescribeOptions, DiagnosticsReport, Execution, ExitFuture,
+    InputSink, LanguageInfo, LogStream, Metrics, OutputChunk, OutputStream, SandboxBase,
+    SandboxDescription, SandboxError, SandboxOptions, StartOptions, StartOutcome,
+};
 
 /// Node.js-specific sandbox for executing JavaScript code
 pub struct NodeSandbox {
@@ -26,6 +34,7 @@ impl NodeSandbox {
     pub async fn create_with_options(
         options: SandboxOptions,
     ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        options.validate()?;
         let base = SandboxBase::new(&options);
 
         // Create sandbox
@@ -36,11 +45,190 @@ impl NodeSandbox {
         Ok(sandbox)
     }
 
+    /// Attach to a Node.js sandbox that is already running on the server
+    ///
+    /// See [`SandboxBase::attach`].
+    pub async fn attach(name: &str, namespace: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let options = SandboxOptions::builder()
+            .name(name)
+            .namespace(namespace)
+            .build();
+        Self::attach_with_options(options).await
+    }
+
+    /// Attach to a Node.js sandbox that is already running on the server, with options
+    ///
+    /// See [`SandboxBase::attach_with_options`].
+    pub async fn attach_with_options(
+        options: SandboxOptions,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let base = SandboxBase::attach_with_options(&options).await?;
+        Ok(Self {
+            base: Arc::new(Mutex::new(base)),
+        })
+    }
+
     /// Get the command interface for executing shell commands
     pub async fn command(&self) -> Result<Command, Box<dyn Error + Send + Sync>> {
         Ok(Command::new(self.base.clone()))
     }
 
+    /// Get the outcome of the most recent start, including any server warnings
+    pub async fn start_outcome(&self) -> Option<StartOutcome> {
+        let base = self.base.lock().await;
+        base.start_outcome().cloned()
+    }
+
+    /// Check if the init code has run since the sandbox was started
+    pub async fn init_ran(&self) -> bool {
+        let base = self.base.lock().await;
+        base.init_ran()
+    }
+
+    /// Describe the sandbox's current state, optionally including the guest environment
+    pub async fn describe(
+        &self,
+        options: DescribeOptions,
+    ) -> Result<SandboxDescription, Box<dyn Error + Send + Sync>> {
+        let base = self.base.lock().await;
+        base.describe(options).await
+    }
+
+    /// Run code cells in order, yielding each cell's result as it completes
+    ///
+    /// Each item is the cell's index in `cells` along with its execution. The stream stops
+    /// after the first cell that fails or reports an error, unless `continue_on_error` is set.
+    pub fn run_cells(
+        &self,
+        cells: Vec<String>,
+        continue_on_error: bool,
+    ) -> impl Stream<Item = Result<(usize, Execution), Box<dyn Error + Send + Sync>>> + '_ {
+        cell_stream(cells, continue_on_error, move |code| async move {
+            let base = self.base.lock().await;
+            base.run_code("javascript", &code).await
+        })
+    }
+
+    /// Check connectivity, authentication and the namespace, reporting which step fails
+    pub async fn diagnose(&self) -> DiagnosticsReport {
+        let base = self.base.lock().await;
+        base.diagnose().await
+    }
+
+    /// Start a long-running process in the sandbox, with a duplex connection to its streams
+    pub async fn spawn(
+        &self,
+        command: &str,
+        args: &[&str],
+    ) -> Result<(InputSink, OutputStream, ExitFuture), Box<dyn Error + Send + Sync>> {
+        let base = self.base.lock().await;
+        base.spawn(command, args).await
+    }
+
+    /// Check whether this SDK is compatible with the server it connects to
+    pub async fn check_compatibility(
+        &self,
+    ) -> Result<CompatibilityReport, Box<dyn Error + Send + Sync>> {
+        let base = self.base.lock().await;
+        base.check_compatibility().await
+    }
+
+    /// Get the optional features the server's sandbox backend can apply
+    pub async fn capabilities(&self) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let base = self.base.lock().await;
+        base.capabilities().await
+    }
+
+    /// Wait until the sandbox is ready, using its readiness probe if one is configured
+    pub async fn wait_until_ready(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let base = self.base.lock().await;
+        base.wait_until_ready().await
+    }
+
+    /// Get the languages the server can run code in
+    pub async fn supported_languages(
+        &self,
+    ) -> Result<Vec<LanguageInfo>, Box<dyn Error + Send + Sync>> {
+        let base = self.base.lock().await;
+        base.supported_languages().await
+    }
+
+    /// Collect diagnostics for the sandbox into a zip archive for bug reports
+    pub async fn support_bundle(&self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let base = self.base.lock().await;
+        base.support_bundle().await
+    }
+
+    /// Pause the sandbox, suspending its VM until it is resumed
+    pub async fn pause(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut base = self.base.lock().await;
+        base.pause().await
+    }
+
+    /// Resume a paused sandbox
+    pub async fn resume(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut base = self.base.lock().await;
+        base.resume().await
+    }
+
+    /// Execute code in the sandbox, streaming its output as it is produced
+    ///
+    /// Other calls on the sandbox aren't held up while the output is read.
+    pub async fn run_code_streaming(
+        &self,
+        code: &str,
+    ) -> Result<
+        impl Stream<Item = Result<OutputChunk, SandboxError>> + Send + 'static,
+        Box<dyn Error + Send + Sync>,
+    > {
+        let base = self.base.lock().await;
+        base.run_code_streaming("javascript", code).await
+    }
+
+    /// Follow the sandbox's log, starting with the lines written so far
+    pub async fn follow_logs(&self) -> Result<LogStream, Box<dyn Error + Send + Sync>> {
+        let base = self.base.lock().await;
+        base.follow_logs().await
+    }
+
+    /// Wait until the sandbox logs a line matching `pattern`, and return that line
+    ///
+    /// Other calls on the sandbox aren't held up while waiting.
+    pub async fn wait_for_log(
+        &self,
+        pattern: Regex,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let started = tokio::time::Instant::now();
+        let mut logs = self.follow_logs().await?;
+        logs.wait_for(&pattern, timeout.saturating_sub(started.elapsed()))
+            .await
+    }
+
+    /// Clone the sandbox into a new, running sandbox sharing its filesystem state
+    pub async fn clone_sandbox(
+        &self,
+        new_name: &str,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let base = self.base.lock().await;
+        let clone = base.clone_sandbox(new_name).await?;
+        Ok(Self {
+            base: Arc::new(Mutex::new(clone)),
+        })
+    }
+
+    /// Set the default timeout applied to every execution
+    pub async fn set_default_execution_timeout(&self, timeout: Duration) {
+        let mut base = self.base.lock().await;
+        base.set_default_execution_timeout(timeout);
+    }
+
+    /// Remove the default execution timeout
+    pub async fn clear_default_execution_timeout(&self) {
+        let mut base = self.base.lock().await;
+        base.clear_default_execution_timeout();
+    }
+
     /// Get the metrics interface for retrieving sandbox metrics
     pub async fn metrics(&self) -> Result<Metrics, Box<dyn Error + Send + Sync>> {
         Ok(Metrics::new(self.base.clone()))
@@ -86,7 +274,8 @@ impl BaseSandbox for NodeSandbox {
 
         let mut base = self.base.lock().await;
         base.start_sandbox(image, opts.memory, opts.cpus, opts.timeout)
-            .await
+            .await?;
+        Ok(())
     }
 
     async fn stop(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
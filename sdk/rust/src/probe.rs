@@ -0,0 +1,228 @@
+//! Readiness probes for applications inside sandboxes
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::{SandboxBase, SandboxError};
+
+/// Default time between probe attempts
+const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default time a single probe attempt may take
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default number of retries after the first failed attempt
+const DEFAULT_PROBE_RETRIES: u32 = 30;
+
+/// A check run inside the sandbox to tell whether its application is ready
+///
+/// Like a Kubernetes readiness probe, the sandbox counts as ready once the check passes.
+/// Failed attempts are retried every `interval`, up to `retries` times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeSpec {
+    /// What the probe checks
+    pub check: ProbeCheck,
+
+    /// Time to wait between attempts
+    pub interval: Duration,
+
+    /// Time a single attempt may take before it counts as failed
+    pub timeout: Duration,
+
+    /// Number of retries after the first failed attempt
+    pub retries: u32,
+}
+
+/// What a readiness probe checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeCheck {
+    /// Run a command in the guest, passing once it exits with the expected status
+    Command {
+        /// Command to run, e.g. `curl`
+        command: String,
+
+        /// Arguments passed to the command
+        args: Vec<String>,
+
+        /// Exit status that counts as ready
+        expected_exit_code: i64,
+    },
+
+    /// Connect to a TCP port on the guest's loopback interface, passing once a connection is
+    /// accepted
+    ///
+    /// The connection is attempted from inside the guest, with whichever of `nc`, `bash`,
+    /// `python3` or `node` the image has.
+    TcpPort(u16),
+}
+
+impl ProbeSpec {
+    /// Create a probe running `command` with `args` until it exits with status 0, using the
+    /// default interval, timeout and retries
+    pub fn new(
+        command: impl Into<String>,
+        args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self::with_check(ProbeCheck::Command {
+            command: command.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            expected_exit_code: 0,
+        })
+    }
+
+    /// Create a probe polling TCP `port` in the guest until it accepts a connection, using the
+    /// default interval, timeout and retries
+    pub fn tcp(port: u16) -> Self {
+        Self::with_check(ProbeCheck::TcpPort(port))
+    }
+
+    /// Create a probe running `check`, using the default interval, timeout and retries
+    fn with_check(check: ProbeCheck) -> Self {
+        Self {
+            check,
+            interval: DEFAULT_PROBE_INTERVAL,
+            timeout: DEFAULT_PROBE_TIMEOUT,
+            retries: DEFAULT_PROBE_RETRIES,
+        }
+    }
+
+    /// Set the exit status a command probe expects; ignored by TCP probes
+    pub fn expected_exit_code(mut self, code: i64) -> Self {
+        if let ProbeCheck::Command {
+            expected_exit_code, ..
+        } = &mut self.check
+        {
+            *expected_exit_code = code;
+        }
+        self
+    }
+
+    /// Set the time to wait between attempts
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the time a single attempt may take
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the number of retries after the first failed attempt
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+}
+
+impl ProbeCheck {
+    /// Get the command, arguments and expected exit status that run the check in the guest
+    fn command(&self) -> (String, Vec<String>, i64) {
+        match self {
+            ProbeCheck::Command {
+                command,
+                args,
+                expected_exit_code,
+            } => (command.clone(), args.clone(), *expected_exit_code),
+            ProbeCheck::TcpPort(port) => {
+                let script = format!(
+                    "nc -z 127.0.0.1 {port} 2>/dev/null \
+                     || bash -c 'exec 3<>/dev/tcp/127.0.0.1/{port}' 2>/dev/null \
+                     || python3 -c 'import socket; socket.create_connection((\"127.0.0.1\", {port}), 1)' 2>/dev/null \
+                     || node -e 'require(\"net\").connect({port}, \"127.0.0.1\").on(\"connect\", () => process.exit(0)).on(\"error\", () => process.exit(1))' 2>/dev/null",
+                    port = port
+                );
+                ("sh".to_string(), vec!["-c".to_string(), script], 0)
+            }
+        }
+    }
+
+    /// Describe the check, for failure messages and support bundles
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            ProbeCheck::Command { command, .. } => format!("probe '{}'", command),
+            ProbeCheck::TcpPort(port) => format!("TCP probe of port {}", port),
+        }
+    }
+}
+
+impl SandboxBase {
+    /// Wait until the sandbox is ready to use
+    ///
+    /// With a readiness probe set in the sandbox's options, the probe is run in the guest until
+    /// it passes. Otherwise this falls back to the generic check, which waits until the guest
+    /// answers requests. [`start_sandbox`](SandboxBase::start_sandbox) already waits for a
+    /// configured probe, so this is only needed to check again later.
+    ///
+    /// Returns [`SandboxError::NotReady`] with the last failure if the sandbox isn't ready once
+    /// the retries run out. The sandbox is left running.
+    pub async fn wait_until_ready(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        let (interval, timeout, retries) = match &self.readiness_probe {
+            Some(probe) => (probe.interval, probe.timeout, probe.retries),
+            None => (
+                DEFAULT_PROBE_INTERVAL,
+                DEFAULT_PROBE_TIMEOUT,
+                DEFAULT_PROBE_RETRIES,
+            ),
+        };
+
+        let mut last_failure = String::new();
+        for attempt in 0..=retries {
+            if attempt > 0 {
+                tokio::time::sleep(interval).await;
+            }
+
+            match tokio::time::timeout(timeout, self.probe_once()).await {
+                Ok(Ok(None)) => return Ok(()),
+                Ok(Ok(Some(failure))) => last_failure = failure,
+                Ok(Err(e)) => last_failure = e.to_string(),
+                Err(_) => last_failure = format!("attempt timed out after {:?}", timeout),
+            }
+        }
+
+        Err(Box::new(SandboxError::NotReady {
+            attempts: retries.saturating_add(1),
+            message: format!("sandbox '{}' is not ready: {}", self.name, last_failure),
+        }))
+    }
+
+    /// Run one readiness check, returning why the sandbox isn't ready yet, if it isn't
+    async fn probe_once(&self) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+        let Some(probe) = &self.readiness_probe else {
+            self.get_guest_env(&[]).await?;
+            return Ok(None);
+        };
+
+        let (command, args, expected_exit_code) = probe.check.command();
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "command": command,
+            "args": args,
+            "timeout": probe.timeout.as_secs().max(1),
+        });
+
+        let result: HashMap<String, Value> =
+            self.make_request("sandbox.command.run", params).await?;
+        match result.get("exit_code").and_then(|v| v.as_i64()) {
+            Some(code) if code == expected_exit_code => Ok(None),
+            Some(code) => Ok(Some(format!(
+                "{} exited with status {}",
+                probe.check.describe(),
+                code
+            ))),
+            None => Ok(Some(format!(
+                "{} did not report an exit status",
+                probe.check.describe()
+            ))),
+        }
+    }
+}
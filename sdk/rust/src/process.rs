@@ -0,0 +1,325 @@
+//! Long-running processes with a duplex connection to their standard streams
+
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::capture::FrameDecoder;
+use crate::{OutputLine, SandboxBase, SandboxError};
+
+/// Number of input or output frames buffered between the caller and the connection
+const PROCESS_CHANNEL_CAPACITY: usize = 64;
+
+/// Writes to the stdin of a process started with [`SandboxBase::spawn`]
+///
+/// Dropping the sink, or calling [`close`](Self::close), closes the process's stdin, so a
+/// process reading its input to the end can finish cleanly.
+#[derive(Debug)]
+pub struct InputSink {
+    tx: mpsc::Sender<Vec<u8>>,
+}
+
+/// Reads the output of a process started with [`SandboxBase::spawn`]
+///
+/// Lines from stdout and stderr arrive in the order the server sends them. The stream ends
+/// when the process exits or the connection fails; the outcome is reported by the process's
+/// [`ExitFuture`].
+#[derive(Debug)]
+pub struct OutputStream {
+    rx: mpsc::Receiver<OutputLine>,
+}
+
+/// Resolves to the exit code of a process started with [`SandboxBase::spawn`]
+///
+/// The process keeps running and its output keeps being read even if the [`OutputStream`]
+/// is dropped, so the exit code is always reported.
+#[derive(Debug)]
+pub struct ExitFuture {
+    rx: oneshot::Receiver<Result<i32, SandboxError>>,
+}
+
+/// A single newline-delimited frame of a `sandbox.process.spawn` response
+#[derive(Debug, Deserialize)]
+struct ProcessFrame {
+    /// Stream an output line came from
+    stream: Option<String>,
+
+    /// Text of an output line, without its trailing newline
+    text: Option<String>,
+
+    /// Exit code of the process, sent in the final frame
+    exit_code: Option<i32>,
+
+    /// Error that ended the process early
+    error: Option<ProcessFrameError>,
+}
+
+/// Error reported in a `sandbox.process.spawn` frame
+#[derive(Debug, Deserialize)]
+struct ProcessFrameError {
+    message: String,
+}
+
+impl InputSink {
+    /// Write text to the process's stdin
+    ///
+    /// Fails once the process has exited or the connection is gone.
+    pub async fn write(&self, data: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut frame = serde_json::to_vec(&json!({ "stdin": data }))?;
+        frame.push(b'\n');
+        self.tx.send(frame).await.map_err(|_| {
+            Box::new(SandboxError::RequestFailed(
+                "process input connection is closed".to_string(),
+            )) as Box<dyn Error + Send + Sync>
+        })
+    }
+
+    /// Close the process's stdin
+    pub fn close(self) {}
+}
+
+impl OutputStream {
+    /// Get the next line of output, or `None` once the process has exited
+    pub async fn next(&mut self) -> Option<OutputLine> {
+        self.rx.recv().await
+    }
+}
+
+impl Future for ExitFuture {
+    type Output = Result<i32, Box<dyn Error + Send + Sync>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx).poll(cx).map(|result| match result {
+            Ok(Ok(exit_code)) => Ok(exit_code),
+            Ok(Err(e)) => Err(Box::new(e) as Box<dyn Error + Send + Sync>),
+            Err(_) => Err(Box::new(SandboxError::General(
+                "process connection task ended without an exit code".to_string(),
+            )) as Box<dyn Error + Send + Sync>),
+        })
+    }
+}
+
+impl SandboxBase {
+    /// Start a long-running process in the sandbox, with a duplex connection to its streams
+    ///
+    /// Unlike [`command`](crate::BaseSandbox::command), which waits for the command to finish,
+    /// this returns as soon as the process has started, for servers, interactive CLIs and
+    /// other processes driven programmatically. Write to its stdin with the [`InputSink`],
+    /// read its output with the [`OutputStream`], and await its exit code with the
+    /// [`ExitFuture`]. This is separate from the REPL and doesn't use a TTY.
+    ///
+    /// The connection is a single streaming HTTP request to the server's duplex endpoint: its
+    /// body starts with the `sandbox.process.spawn` JSON-RPC request and continues with
+    /// newline-delimited stdin frames, while the response streams newline-delimited output
+    /// frames back. With HMAC authentication, the JSON-RPC request line is what is signed.
+    pub async fn spawn(
+        &self,
+        command: &str,
+        args: &[&str],
+    ) -> Result<(InputSink, OutputStream, ExitFuture), Box<dyn Error + Send + Sync>> {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        if self.is_paused {
+            return Err(Box::new(SandboxError::Paused));
+        }
+
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "command": command,
+            "args": args,
+        });
+        let mut request_line = self.encode_request("sandbox.process.spawn", params)?;
+        let headers = self.auth.request_headers(&request_line)?;
+        self.log_request("sandbox.process.spawn", &request_line, &headers);
+        request_line.push(b'\n');
+
+        // The body ends with an EOF frame once every input sink is gone
+        let (input_tx, input_rx) = mpsc::channel::<Vec<u8>>(PROCESS_CHANNEL_CAPACITY);
+        let body = futures::stream::once(async move { request_line })
+            .chain(input_frames(input_rx))
+            .chain(futures::stream::once(async {
+                b"{\"eof\":true}\n".to_vec()
+            }))
+            .map(Ok::<_, std::io::Error>);
+
+        let client = self.http_client()?;
+        self.acquire_budget().await;
+        let response = client
+            .post(format!("{}/api/v1/rpc/duplex", self.server_url))
+            .headers(headers)
+            .body(reqwest::Body::wrap_stream(body))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(Box::new(SandboxError::RequestFailed(error_text)));
+        }
+
+        let (output_tx, output_rx) = mpsc::channel(PROCESS_CHANNEL_CAPACITY);
+        let (exit_tx, exit_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = exit_tx.send(read_process_output(response, output_tx).await);
+        });
+
+        Ok((
+            InputSink { tx: input_tx },
+            OutputStream { rx: output_rx },
+            ExitFuture { rx: exit_rx },
+        ))
+    }
+}
+
+/// Turn the receiving end of the input channel into a stream of body chunks
+fn input_frames(mut rx: mpsc::Receiver<Vec<u8>>) -> impl futures::Stream<Item = Vec<u8>> + Send {
+    futures::stream::poll_fn(move |cx| rx.poll_recv(cx))
+}
+
+/// Read output frames until the process exits, forwarding output lines to `output`
+async fn read_process_output(
+    mut response: reqwest::Response,
+    output: mpsc::Sender<OutputLine>,
+) -> Result<i32, SandboxError> {
+    let mut decoder = FrameDecoder::default();
+    let mut received = 0;
+
+    loop {
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => {
+                return Err(SandboxError::MalformedResponse {
+                    received,
+                    message: format!(
+                        "output stream ended before the process exited ({} bytes unframed)",
+                        decoder.pending_len()
+                    ),
+                })
+            }
+            Err(e) => {
+                return Err(SandboxError::MalformedResponse {
+                    received,
+                    message: e.to_string(),
+                })
+            }
+        };
+        received += chunk.len();
+
+        for frame in decoder.push(&chunk) {
+            let frame: ProcessFrame =
+                serde_json::from_slice(&frame).map_err(|e| SandboxError::MalformedResponse {
+                    received,
+                    message: e.to_string(),
+                })?;
+
+            if let Some(error) = frame.error {
+                return Err(SandboxError::ServerError(error.message));
+            }
+
+            if let Some(text) = frame.text {
+                let line = OutputLine {
+                    stream: frame.stream.unwrap_or_else(|| "stdout".to_string()),
+                    text,
+                };
+                // Keep reading for the exit code even if nobody reads the output
+                let _ = output.send(line).await;
+            }
+
+            if let Some(exit_code) = frame.exit_code {
+                return Ok(exit_code);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SandboxOptions;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Serve one duplex request, echoing each stdin frame back as stdout until EOF
+    async fn serve_echo(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        // Skip the request headers; the streamed body is chunk-encoded
+        let mut line = String::new();
+        while reader.read_line(&mut line).await.unwrap() > 2 {
+            line.clear();
+        }
+        writer
+            .write_all(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut decoder = FrameDecoder::default();
+        let mut method = String::new();
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size).await.unwrap();
+            let size = usize::from_str_radix(size.trim(), 16).unwrap();
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).await.unwrap();
+
+            for frame in decoder.push(&chunk[..size]) {
+                let frame: serde_json::Value = serde_json::from_slice(&frame).unwrap();
+                let reply = if let Some(m) = frame["method"].as_str() {
+                    method = m.to_string();
+                    continue;
+                } else if let Some(stdin) = frame["stdin"].as_str() {
+                    json!({ "stream": "stdout", "text": stdin.trim_end() })
+                } else {
+                    json!({ "exit_code": 3 })
+                };
+
+                let reply = format!("{}\n", reply);
+                let chunk = format!("{:x}\r\n{}\r\n", reply.len(), reply);
+                writer.write_all(chunk.as_bytes()).await.unwrap();
+                if frame["eof"] == true {
+                    writer.write_all(b"0\r\n\r\n").await.unwrap();
+                    return method;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spawn_streams_both_ways_and_closes_stdin_on_drop() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .build();
+        let server = tokio::spawn(serve_echo(listener));
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        let (input, mut output, exit) = sandbox.spawn("cat", &[]).await.unwrap();
+
+        input.write("hello\n").await.unwrap();
+        let line = output.next().await.unwrap();
+        assert_eq!(
+            (line.stream.as_str(), line.text.as_str()),
+            ("stdout", "hello")
+        );
+
+        input.write("again\n").await.unwrap();
+        assert_eq!(output.next().await.unwrap().text, "again");
+
+        // Dropping the sink ends stdin, after which the process exits
+        drop(input);
+        assert_eq!(exit.await.unwrap(), 3);
+        assert!(output.next().await.is_none());
+        assert_eq!(server.await.unwrap(), "sandbox.process.spawn");
+    }
+}
@@ -0,0 +1,187 @@
+//! Automatic retries of requests that failed for transient reasons
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::SandboxError;
+
+/// JSON-RPC methods that are safe to send again, because they only read state
+const IDEMPOTENT_METHODS: [&str; 12] = [
+    "sandbox.metrics.get",
+    "sandbox.status",
+    "sandbox.stats",
+    "sandbox.list",
+    "sandbox.env",
+    "sandbox.fs.diff",
+    "sandbox.fs.read",
+    "sandbox.fs.list",
+    "sandbox.repl.partial",
+    "server.languages",
+    "server.namespaces",
+    "server.info",
+];
+
+/// Decides whether a failed request is worth retrying
+///
+/// Set it in [`SandboxOptions`](crate::SandboxOptions) to override
+/// [`default_retry_predicate`].
+pub type RetryPredicate = Arc<dyn Fn(&SandboxError) -> bool + Send + Sync>;
+
+/// How requests are retried when the server is briefly unavailable
+///
+/// Set it in [`SandboxOptions`](crate::SandboxOptions) to retry idempotent requests that
+/// fail to reach the server, get a 5xx response or get a truncated body. JSON-RPC errors
+/// returned by the server are not retried, unless a retry predicate says otherwise. Requests
+/// that change state, such as running code, are never retried, since the server may have
+/// acted on them already.
+///
+/// The wait before retry `n` is `initial_backoff * 2^n`, capped at `max_backoff`. With
+/// jitter, each wait is instead picked at random between half and all of that, so clients
+/// that failed together don't retry together. Retries stop early rather than wait past the
+/// deadline, which is the request's own timeout or the policy's
+/// [`deadline`](Self::deadline), whichever is shorter. Every attempt draws from the
+/// [`RetryBudget`](crate::RetryBudget), if one is set.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub(crate) max_retries: u32,
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    pub(crate) jitter: bool,
+    pub(crate) deadline: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Retry up to `max_retries` times, starting at 100ms between attempts with jitter
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+            deadline: None,
+        }
+    }
+
+    /// Set the wait before the first retry
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the longest wait between two attempts
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set whether waits are randomized
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the total time a request may take across all its attempts
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Check whether requests of `method` may be retried
+    pub(crate) fn applies_to(&self, method: &str) -> bool {
+        self.max_retries > 0 && IDEMPOTENT_METHODS.contains(&method)
+    }
+
+    /// Get the wait before retry number `retry`, counting from zero
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff);
+
+        if self.jitter {
+            backoff.mul_f64(0.5 + random_fraction() / 2.0)
+        } else {
+            backoff
+        }
+    }
+}
+
+/// Retry the errors [`SandboxError::is_retryable`] reports as transient
+///
+/// This is what decides retries unless the options set another predicate, which can fall back
+/// to this one for the errors it has no opinion about.
+pub fn default_retry_predicate(error: &SandboxError) -> bool {
+    error.is_retryable()
+}
+
+/// A retry predicate that can live in the options, which are `Debug`
+#[derive(Clone)]
+pub(crate) struct RetryClassifier(pub(crate) RetryPredicate);
+
+impl fmt::Debug for RetryClassifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RetryClassifier(..)")
+    }
+}
+
+/// Get a random number in `[0, 1)`
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RpcError, INTERNAL_ERROR_CODE};
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::new(5)
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(350))
+            .jitter(false);
+        let waits: Vec<_> = (0..4).map(|retry| policy.backoff(retry)).collect();
+        assert_eq!(
+            waits,
+            [100, 200, 350, 350].map(Duration::from_millis).to_vec()
+        );
+
+        let policy = policy.jitter(true);
+        for retry in 0..4 {
+            let wait = policy.backoff(retry);
+            assert!(wait >= waits[retry as usize] / 2 && wait <= waits[retry as usize]);
+        }
+
+        assert!(policy.applies_to("sandbox.metrics.get"));
+        assert!(!policy.applies_to("sandbox.repl.run"));
+    }
+
+    #[test]
+    fn test_default_predicate_retries_transport_failures_5xx_and_malformed_bodies() {
+        let status = |status| SandboxError::HttpStatus {
+            status,
+            message: String::new(),
+        };
+        assert!(default_retry_predicate(&SandboxError::HttpError(
+            "connection refused".to_string()
+        )));
+        assert!(default_retry_predicate(&status(503)));
+        assert!(default_retry_predicate(&SandboxError::MalformedResponse {
+            received: 12,
+            message: "response ended unexpectedly".to_string(),
+        }));
+        assert!(!default_retry_predicate(&status(429)));
+        assert!(!default_retry_predicate(&SandboxError::ServerError(
+            "nope".to_string()
+        )));
+        assert!(!default_retry_predicate(&SandboxError::Rpc(RpcError {
+            code: INTERNAL_ERROR_CODE,
+            message: "nope".to_string(),
+            data: None,
+        })));
+    }
+}
@@ -0,0 +1,101 @@
+//! Discovering the sandboxes and namespaces on a server
+
+use std::error::Error;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{SandboxBase, SandboxState};
+
+/// A sandbox defined on the server, returned by [`SandboxBase::list_sandboxes`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxInfo {
+    /// Name of the sandbox
+    pub name: String,
+
+    /// Namespace of the sandbox
+    pub namespace: String,
+
+    /// Recorded state of the sandbox
+    pub state: SandboxState,
+
+    /// Path on the server of the configuration file that defines the sandbox
+    pub config_file: String,
+
+    /// How long the MicroVM has been running, if it still is
+    pub uptime: Option<Duration>,
+}
+
+/// A sandbox in the result of a `sandbox.list` request
+#[derive(Debug, Deserialize)]
+struct SandboxDescriptor {
+    name: String,
+    namespace: String,
+    status: Option<String>,
+    config_file: String,
+    uptime_secs: Option<f64>,
+}
+
+/// The result of a `sandbox.list` request
+#[derive(Debug, Deserialize)]
+struct SandboxListResponse {
+    sandboxes: Vec<SandboxDescriptor>,
+}
+
+/// The result of a `server.namespaces` request
+#[derive(Debug, Deserialize)]
+struct NamespacesResponse {
+    namespaces: Vec<String>,
+}
+
+impl SandboxBase {
+    /// List the sandboxes defined on the server, in one namespace or all of them
+    ///
+    /// Sandboxes are listed whether or not they are running, ordered by namespace then
+    /// name, so cleanup scripts can find sandboxes left behind and dashboards can show
+    /// everything the server knows about. This works whether or not this handle has
+    /// started its own sandbox.
+    ///
+    /// Listing every namespace, with `None`, needs a token for all namespaces (`*`); a
+    /// namespace-scoped token can only list its own namespace.
+    pub async fn list_sandboxes(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<SandboxInfo>, Box<dyn Error + Send + Sync>> {
+        let params = json!({ "namespace": namespace.unwrap_or("*") });
+        let response: SandboxListResponse = self.make_request("sandbox.list", params).await?;
+
+        let mut sandboxes: Vec<SandboxInfo> = response
+            .sandboxes
+            .into_iter()
+            .map(|sandbox| SandboxInfo {
+                state: SandboxState::from_status(sandbox.status.as_deref()),
+                uptime: sandbox
+                    .uptime_secs
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok()),
+                name: sandbox.name,
+                namespace: sandbox.namespace,
+                config_file: sandbox.config_file,
+            })
+            .collect();
+
+        // The server orders them already; don't rely on it
+        sandboxes.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+        Ok(sandboxes)
+    }
+
+    /// List the namespaces on the server, in order
+    ///
+    /// Needs a token for all namespaces (`*`). The server refuses a namespace-scoped token,
+    /// which would only be told about its own namespace.
+    pub async fn list_namespaces(&self) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let response: NamespacesResponse = self
+            .make_request("server.namespaces", json!({ "namespace": "*" }))
+            .await?;
+
+        let mut namespaces = response.namespaces;
+        namespaces.sort();
+        Ok(namespaces)
+    }
+}
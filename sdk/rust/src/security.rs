@@ -0,0 +1,199 @@
+//! Guest security profiles
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::SandboxError;
+
+/// Capability the server reports in `server.info` when it can apply security profiles
+pub(crate) const SECURITY_PROFILE_CAPABILITY: &str = "security_profile";
+
+/// Linux capabilities, without their `CAP_` prefix
+const LINUX_CAPABILITIES: [&str; 41] = [
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "FSETID",
+    "KILL",
+    "SETGID",
+    "SETUID",
+    "SETPCAP",
+    "LINUX_IMMUTABLE",
+    "NET_BIND_SERVICE",
+    "NET_BROADCAST",
+    "NET_ADMIN",
+    "NET_RAW",
+    "IPC_LOCK",
+    "IPC_OWNER",
+    "SYS_MODULE",
+    "SYS_RAWIO",
+    "SYS_CHROOT",
+    "SYS_PTRACE",
+    "SYS_PACCT",
+    "SYS_ADMIN",
+    "SYS_BOOT",
+    "SYS_NICE",
+    "SYS_RESOURCE",
+    "SYS_TIME",
+    "SYS_TTY_CONFIG",
+    "MKNOD",
+    "LEASE",
+    "AUDIT_WRITE",
+    "AUDIT_CONTROL",
+    "SETFCAP",
+    "MAC_OVERRIDE",
+    "MAC_ADMIN",
+    "SYSLOG",
+    "WAKE_ALARM",
+    "BLOCK_SUSPEND",
+    "AUDIT_READ",
+    "PERFMON",
+    "BPF",
+    "CHECKPOINT_RESTORE",
+];
+
+/// A seccomp profile applied to processes in the guest
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeccompProfile {
+    /// A profile the server knows by name, such as `default`
+    Named(String),
+
+    /// A profile in the OCI seccomp JSON format, with at least a `defaultAction`
+    Inline(Value),
+}
+
+/// Capabilities dropped from, and the seccomp profile applied to, processes in the guest
+///
+/// Set it in [`SandboxOptions`](crate::SandboxOptions) to harden sandboxes that run untrusted
+/// code. Starting a sandbox with a profile fails with
+/// [`SandboxError::Unsupported`] unless the server reports the `security_profile` capability,
+/// so a server that can't apply the profile never starts the sandbox without it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SecurityProfile {
+    /// Capabilities to drop, as `CAP_`-prefixed upper-case names
+    pub(crate) drop_capabilities: Vec<String>,
+
+    /// Seccomp profile to apply
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) seccomp: Option<SeccompProfile>,
+}
+
+impl SecurityProfile {
+    /// Create a profile that drops nothing and applies no seccomp profile
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop a capability, e.g. `NET_RAW` or `CAP_SYS_ADMIN`, or `ALL` to drop every one
+    pub fn drop_capability(mut self, capability: impl AsRef<str>) -> Self {
+        let capability = capability.as_ref().to_uppercase();
+        let capability = match capability.strip_prefix("CAP_") {
+            Some(_) => capability,
+            None if capability == "ALL" => capability,
+            None => format!("CAP_{}", capability),
+        };
+        if !self.drop_capabilities.contains(&capability) {
+            self.drop_capabilities.push(capability);
+        }
+        self
+    }
+
+    /// Apply a seccomp profile, replacing any set before
+    pub fn seccomp(mut self, profile: SeccompProfile) -> Self {
+        self.seccomp = Some(profile);
+        self
+    }
+
+    /// Get the capabilities the profile drops
+    pub fn dropped_capabilities(&self) -> &[String] {
+        &self.drop_capabilities
+    }
+
+    /// Check that the capabilities exist and that the seccomp profile is well formed
+    pub(crate) fn validate(&self) -> Result<(), SandboxError> {
+        for capability in &self.drop_capabilities {
+            let known = capability == "ALL"
+                || capability
+                    .strip_prefix("CAP_")
+                    .is_some_and(|name| LINUX_CAPABILITIES.contains(&name));
+            if !known {
+                return Err(SandboxError::InvalidInput(format!(
+                    "unknown capability '{}'",
+                    capability
+                )));
+            }
+        }
+
+        match &self.seccomp {
+            Some(SeccompProfile::Named(name)) => {
+                let valid = !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+                if !valid {
+                    return Err(SandboxError::InvalidInput(format!(
+                        "invalid seccomp profile name '{}'",
+                        name
+                    )));
+                }
+            }
+            Some(SeccompProfile::Inline(profile)) => {
+                if !profile.get("defaultAction").is_some_and(Value::is_string) {
+                    return Err(SandboxError::InvalidInput(
+                        "inline seccomp profile must be an object with a string 'defaultAction'"
+                            .to_string(),
+                    ));
+                }
+                if profile.get("syscalls").is_some_and(|s| !s.is_array()) {
+                    return Err(SandboxError::InvalidInput(
+                        "'syscalls' of an inline seccomp profile must be an array".to_string(),
+                    ));
+                }
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_security_profile_validation() {
+        let profile = SecurityProfile::new()
+            .drop_capability("net_raw")
+            .drop_capability("CAP_NET_RAW")
+            .drop_capability("sys_admin")
+            .seccomp(SeccompProfile::Inline(json!({
+                "defaultAction": "SCMP_ACT_ERRNO",
+                "syscalls": [{ "names": ["read"], "action": "SCMP_ACT_ALLOW" }],
+            })));
+        assert_eq!(
+            profile.dropped_capabilities(),
+            ["CAP_NET_RAW", "CAP_SYS_ADMIN"]
+        );
+        assert!(profile.validate().is_ok());
+        assert_eq!(
+            serde_json::to_value(&profile).unwrap()["seccomp"]["inline"]["defaultAction"],
+            "SCMP_ACT_ERRNO"
+        );
+
+        let unknown = SecurityProfile::new().drop_capability("fly");
+        assert!(matches!(
+            unknown.validate(),
+            Err(SandboxError::InvalidInput(_))
+        ));
+
+        let inline = SecurityProfile::new().seccomp(SeccompProfile::Inline(json!(["read"])));
+        assert!(inline.validate().is_err());
+
+        let named = SecurityProfile::new().seccomp(SeccompProfile::Named("../etc".to_string()));
+        assert!(named.validate().is_err());
+    }
+}
@@ -0,0 +1,60 @@
+//! Snapshots of a sandbox's filesystem state
+
+use std::error::Error;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::SandboxBase;
+
+/// A saved copy of a sandbox's writable layer, kept by the server
+///
+/// Unlike the markers returned by [`fs_snapshot`](SandboxBase::fs_snapshot), snapshots hold
+/// the files themselves and survive server restarts, so the sandbox can be restored to them.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Snapshot {
+    /// Name of the snapshot, unique among the sandbox's snapshots
+    pub name: String,
+
+    /// Total size of the files in the snapshot
+    pub size_bytes: u64,
+
+    /// When the snapshot was taken, in RFC 3339 format
+    pub created_at: String,
+}
+
+impl SandboxBase {
+    /// Save the sandbox's filesystem state as a snapshot named `name`
+    ///
+    /// The sandbox must have been started at least once. A running sandbox is paused while
+    /// the server copies its writable layer and resumed afterwards, so code running in it
+    /// sees a short stall; a stopped sandbox can be snapshotted as it is.
+    pub async fn snapshot(&self, name: &str) -> Result<Snapshot, Box<dyn Error + Send + Sync>> {
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "name": name,
+        });
+
+        self.make_request("sandbox.snapshot.create", params).await
+    }
+
+    /// Restore the sandbox's filesystem to the snapshot named `snapshot_name`
+    ///
+    /// Changes made since the snapshot was taken are discarded. The sandbox must be stopped,
+    /// and the restored state is used the next time it starts. The snapshot is kept, so a
+    /// prepared environment can be reset to it any number of times by stopping the sandbox,
+    /// restoring it and starting it again.
+    pub async fn restore(
+        &self,
+        snapshot_name: &str,
+    ) -> Result<Snapshot, Box<dyn Error + Send + Sync>> {
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "snapshot": snapshot_name,
+        });
+
+        self.make_request("sandbox.snapshot.restore", params).await
+    }
+}
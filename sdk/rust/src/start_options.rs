@@ -7,7 +7,11 @@ pub struct StartOptions {
     /// Memory limit in MB
     pub memory: u32,
 
-    /// CPU limit
+    /// Number of CPUs
+    ///
+    /// The server only applies whole CPUs, so a fraction is rounded to the nearest whole CPU,
+    /// but at least one, and the start reports a
+    /// [`WARNING_CPUS_ROUNDED`](crate::WARNING_CPUS_ROUNDED) warning.
     pub cpus: f32,
 
     /// Maximum time in seconds to wait for the sandbox to start
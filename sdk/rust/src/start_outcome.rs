@@ -0,0 +1,222 @@
+//! Outcome of starting a sandbox
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Warning code reported when the server timed out waiting for the sandbox to be running
+pub const WARNING_START_TIMEOUT: &str = "start_timeout";
+
+/// Warning code reported when the server started the sandbox but couldn't verify it's running
+pub const WARNING_START_UNVERIFIED: &str = "start_unverified";
+
+/// Warning code reported when a fractional CPU count was rounded to whole CPUs, because the
+/// server can't apply fractions
+pub const WARNING_CPUS_ROUNDED: &str = "cpus_rounded";
+
+/// Warning code given to a warning the server sent in a shape the SDK couldn't read, whose
+/// message is the raw JSON the server sent
+pub const WARNING_UNKNOWN: &str = "unknown";
+
+/// A phase of a sandbox start, each with its own optional time limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartPhase {
+    /// Pulling the sandbox's image
+    Pull,
+
+    /// Booting the sandbox's microVM until it is running
+    Boot,
+
+    /// Waiting for the sandbox to accept requests
+    Ready,
+}
+
+/// A warning reported by the server while starting a sandbox
+///
+/// The sandbox started despite the warning, unless it is [fatal](Self::fatal). Match on
+/// [`code`](Self::code) to react to a kind of warning, e.g. [`WARNING_START_TIMEOUT`] for a
+/// sandbox that may still be initializing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SandboxWarning {
+    /// Machine-readable warning code (e.g. `start_timeout`)
+    pub code: String,
+
+    /// Human-readable warning message
+    pub message: String,
+
+    /// Whether the server considers the sandbox unusable because of this warning
+    #[serde(default)]
+    pub fatal: bool,
+}
+
+/// The former name of [`SandboxWarning`]
+#[deprecated(note = "renamed to `SandboxWarning`")]
+pub type Warning = SandboxWarning;
+
+/// The result of a successful sandbox start
+#[derive(Debug, Clone, Default)]
+pub struct StartOutcome {
+    /// Message the server described the start with, if it sent one
+    pub message: Option<String>,
+
+    /// Warnings reported by the server during start
+    pub warnings: Vec<SandboxWarning>,
+}
+
+impl StartPhase {
+    /// Name of the phase used by the server
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StartPhase::Pull => "pull",
+            StartPhase::Boot => "boot",
+            StartPhase::Ready => "ready",
+        }
+    }
+
+    /// Parse the name of a phase reported by the server
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        match name {
+            "pull" => Some(StartPhase::Pull),
+            "boot" => Some(StartPhase::Boot),
+            "ready" => Some(StartPhase::Ready),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for StartPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl SandboxWarning {
+    /// Read a warning from the server, keeping one that can't be read as a
+    /// [`WARNING_UNKNOWN`] warning
+    fn from_value(value: &Value) -> Self {
+        serde_json::from_value(value.clone()).unwrap_or_else(|_| Self::unknown(value))
+    }
+
+    /// A [`WARNING_UNKNOWN`] warning carrying the raw JSON the server sent
+    fn unknown(value: &Value) -> Self {
+        Self {
+            code: WARNING_UNKNOWN.to_string(),
+            message: value.to_string(),
+            fatal: false,
+        }
+    }
+}
+
+impl StartOutcome {
+    /// Build a start outcome from the `result` field of a `sandbox.start` response
+    ///
+    /// Servers return an object with the `message` and a structured `warnings` array. Older
+    /// servers return the message alone as a string, in which case known warning phrases are
+    /// detected as a fallback.
+    ///
+    /// A warning that can't be read is kept as a [`WARNING_UNKNOWN`] warning rather than
+    /// dropped, so a malformed warning can't hide the others or make a start look clean.
+    pub(crate) fn from_result(result: Option<&Value>) -> Self {
+        let Some(result) = result else {
+            return Self::default();
+        };
+
+        if let Some(warnings) = result.get("warnings") {
+            let warnings = match warnings.as_array() {
+                Some(warnings) => warnings.iter().map(SandboxWarning::from_value).collect(),
+                None => vec![SandboxWarning::unknown(warnings)],
+            };
+            return Self {
+                message: result["message"].as_str().map(str::to_string),
+                warnings,
+            };
+        }
+
+        let mut warnings = Vec::new();
+        if let Some(message) = result.as_str() {
+            if message.contains("timed out waiting") {
+                warnings.push(SandboxWarning {
+                    code: WARNING_START_TIMEOUT.to_string(),
+                    message: message.to_string(),
+                    fatal: false,
+                });
+            } else if message.contains("couldn't verify") {
+                warnings.push(SandboxWarning {
+                    code: WARNING_START_UNVERIFIED.to_string(),
+                    message: message.to_string(),
+                    fatal: false,
+                });
+            }
+        }
+
+        Self {
+            message: result.as_str().map(str::to_string),
+            warnings,
+        }
+    }
+
+    /// Check if any of the warnings is fatal
+    pub fn has_fatal_warning(&self) -> bool {
+        self.warnings.iter().any(|w| w.fatal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_from_result_reads_structured_warnings() {
+        let outcome = StartOutcome::from_result(Some(&json!({
+            "message": "Sandbox web was started, but timed out waiting for it",
+            "warnings": [{
+                "code": "start_timeout",
+                "message": "Sandbox web was started, but timed out waiting for it",
+                "fatal": false,
+            }],
+        })));
+
+        assert_eq!(
+            outcome.message.as_deref(),
+            Some("Sandbox web was started, but timed out waiting for it")
+        );
+        assert_eq!(outcome.warnings.len(), 1);
+        assert_eq!(outcome.warnings[0].code, WARNING_START_TIMEOUT);
+        assert!(!outcome.has_fatal_warning());
+    }
+
+    #[test]
+    fn test_from_result_keeps_unreadable_warnings() {
+        let outcome = StartOutcome::from_result(Some(&json!({
+            "message": "Sandbox web was started",
+            "warnings": [
+                {"code": "start_timeout", "message": "timed out", "fatal": false},
+                {"code": "start_unverified", "fatal": "yes"},
+            ],
+        })));
+
+        assert_eq!(outcome.warnings.len(), 2);
+        assert_eq!(outcome.warnings[0].code, WARNING_START_TIMEOUT);
+        assert_eq!(outcome.warnings[1].code, WARNING_UNKNOWN);
+        assert_eq!(
+            serde_json::from_str::<Value>(&outcome.warnings[1].message).unwrap(),
+            json!({"code": "start_unverified", "fatal": "yes"})
+        );
+
+        let outcome = StartOutcome::from_result(Some(&json!({"warnings": "gone"})));
+        assert_eq!(outcome.warnings.len(), 1);
+        assert_eq!(outcome.warnings[0].message, "\"gone\"");
+    }
+
+    #[test]
+    fn test_from_result_falls_back_to_the_message_of_older_servers() {
+        let outcome = StartOutcome::from_result(Some(&json!(
+            "Sandbox web was started, but couldn't verify it's running: gone"
+        )));
+        assert_eq!(outcome.warnings[0].code, WARNING_START_UNVERIFIED);
+
+        let outcome = StartOutcome::from_result(Some(&json!("Sandbox web started successfully")));
+        assert!(outcome.warnings.is_empty());
+    }
+}
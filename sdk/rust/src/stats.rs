@@ -0,0 +1,43 @@
+//! Resource use of a sandbox's MicroVM
+
+use std::error::Error;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::SandboxBase;
+
+/// Resource use of a sandbox's MicroVM, returned by [`SandboxBase::stats`]
+///
+/// Compare it with the `memory` and `cpus` the sandbox was started with to tell whether its
+/// limits are too tight or too loose.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SandboxStats {
+    /// Whether the figures are of a MicroVM that is still running, rather than of its last run
+    pub live: bool,
+
+    /// Peak resident set size of the MicroVM in its current or last run, in bytes
+    pub peak_rss: Option<u64>,
+
+    /// CPU time the MicroVM used in its current or last run, in seconds
+    pub cpu_seconds: Option<f64>,
+
+    /// How many times the OOM killer has killed the sandbox's MicroVM, over all its runs
+    pub oom_kills: u32,
+}
+
+impl SandboxBase {
+    /// Get the resource use of the sandbox's MicroVM
+    ///
+    /// Works whether or not this handle has started the sandbox. While the MicroVM runs, the
+    /// figures are its use so far; once it has stopped, they cover its whole last run. A
+    /// sandbox that has never been started reports no figures. A sandbox that isn't defined
+    /// on the server fails with [`SandboxError::NotFound`](crate::SandboxError::NotFound).
+    pub async fn stats(&self) -> Result<SandboxStats, Box<dyn Error + Send + Sync>> {
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+        });
+        self.make_request("sandbox.stats", params).await
+    }
+}
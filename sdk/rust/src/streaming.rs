@@ -0,0 +1,351 @@
+//! Streaming the output of code as it runs
+
+use std::collections::VecDeque;
+use std::error::Error;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::stream::{self, Stream};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::capture::FrameDecoder;
+use crate::{SandboxBase, SandboxError};
+
+/// Output stream a chunk was written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamKind {
+    /// Standard output
+    Stdout,
+
+    /// Standard error
+    Stderr,
+}
+
+/// A piece of output from code run with [`SandboxBase::run_code_streaming`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputChunk {
+    /// Stream the output was written to
+    pub stream: StreamKind,
+
+    /// Output bytes, in the order they were written
+    pub data: Vec<u8>,
+}
+
+/// A single newline-delimited frame of a `sandbox.repl.stream` response
+#[derive(Debug, Deserialize)]
+struct ReplStreamFrame {
+    /// Stream an output chunk came from
+    stream: Option<String>,
+
+    /// Base64-encoded bytes of an output chunk
+    data: Option<String>,
+
+    /// Set in the final frame, once the code has finished
+    #[serde(default)]
+    done: bool,
+
+    /// Error that ended the execution early
+    error: Option<ReplStreamFrameError>,
+}
+
+/// Error reported in a `sandbox.repl.stream` frame
+#[derive(Debug, Deserialize)]
+struct ReplStreamFrameError {
+    message: String,
+}
+
+/// Holds back a trailing, incomplete UTF-8 sequence until the rest of it arrives
+#[derive(Debug, Default)]
+struct Utf8Boundary {
+    pending: Vec<u8>,
+}
+
+/// Internal state of a streaming execution
+struct ReplStreamState {
+    response: reqwest::Response,
+    decoder: FrameDecoder,
+    chunks: VecDeque<OutputChunk>,
+    stdout: Utf8Boundary,
+    stderr: Utf8Boundary,
+    received: usize,
+    done: bool,
+}
+
+impl StreamKind {
+    /// Get the stream's name, `stdout` or `stderr`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StreamKind::Stdout => "stdout",
+            StreamKind::Stderr => "stderr",
+        }
+    }
+}
+
+impl OutputChunk {
+    /// Get the chunk as text, if it is valid UTF-8
+    ///
+    /// Chunks never end partway through a UTF-8 character, so output that is valid UTF-8 as
+    /// a whole is valid in every chunk too.
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+}
+
+impl Utf8Boundary {
+    /// Add bytes and return everything up to the last complete character
+    ///
+    /// Invalid bytes are passed through as they are; only a sequence that may still be
+    /// completed by the next bytes is held back.
+    fn push(&mut self, data: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(data);
+        let end = complete_utf8_len(&self.pending);
+        self.pending.drain(..end).collect()
+    }
+
+    /// Return whatever is still held back, once no more bytes will arrive
+    fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+impl ReplStreamState {
+    /// Read frames until at least one chunk is ready or the execution ends
+    async fn fill(&mut self) -> Result<(), SandboxError> {
+        while self.chunks.is_empty() && !self.done {
+            let chunk = match self.response.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => {
+                    return Err(SandboxError::MalformedResponse {
+                        received: self.received,
+                        message: format!(
+                            "output stream ended before the code finished ({} bytes unframed)",
+                            self.decoder.pending_len()
+                        ),
+                    })
+                }
+                Err(e) => {
+                    return Err(SandboxError::MalformedResponse {
+                        received: self.received,
+                        message: e.to_string(),
+                    })
+                }
+            };
+            self.received += chunk.len();
+
+            for frame in self.decoder.push(&chunk) {
+                self.handle_frame(&frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue the output carried by a frame
+    fn handle_frame(&mut self, frame: &[u8]) -> Result<(), SandboxError> {
+        let malformed = |message: String| SandboxError::MalformedResponse {
+            received: self.received,
+            message,
+        };
+
+        let frame: ReplStreamFrame =
+            serde_json::from_slice(frame).map_err(|e| malformed(e.to_string()))?;
+
+        if let Some(error) = frame.error {
+            return Err(SandboxError::ServerError(error.message));
+        }
+
+        if let Some(data) = frame.data {
+            let data = BASE64
+                .decode(data)
+                .map_err(|e| malformed(format!("invalid output chunk: {}", e)))?;
+            let stream = match frame.stream.as_deref() {
+                Some("stderr") => StreamKind::Stderr,
+                _ => StreamKind::Stdout,
+            };
+            let data = self.boundary(stream).push(&data);
+            self.queue(stream, data);
+        }
+
+        if frame.done {
+            self.done = true;
+            for stream in [StreamKind::Stdout, StreamKind::Stderr] {
+                let data = self.boundary(stream).finish();
+                self.queue(stream, data);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the UTF-8 boundary tracker of a stream
+    fn boundary(&mut self, stream: StreamKind) -> &mut Utf8Boundary {
+        match stream {
+            StreamKind::Stdout => &mut self.stdout,
+            StreamKind::Stderr => &mut self.stderr,
+        }
+    }
+
+    /// Queue a chunk of output, skipping empty ones
+    fn queue(&mut self, stream: StreamKind, data: Vec<u8>) {
+        if !data.is_empty() {
+            self.chunks.push_back(OutputChunk { stream, data });
+        }
+    }
+}
+
+impl SandboxBase {
+    /// Execute code in the sandbox, streaming its output as it is produced
+    ///
+    /// Unlike [`run_code`](Self::run_code), which returns once the code has finished, this
+    /// returns as soon as the server accepts the request. The stream yields stdout and stderr
+    /// chunks in the order they were written and ends once the code finishes. The default
+    /// execution timeout applies, if one is set.
+    ///
+    /// Output is read from the `sandbox.repl.stream` endpoint, whose response body is a series
+    /// of newline-delimited JSON frames carrying base64-encoded output. A UTF-8 character
+    /// split across two frames is held back until it is complete, so no chunk ends partway
+    /// through one and no bytes are ever replaced.
+    pub async fn run_code_streaming(
+        &self,
+        language: &str,
+        code: &str,
+    ) -> Result<
+        impl Stream<Item = Result<OutputChunk, SandboxError>> + Send + 'static,
+        Box<dyn Error + Send + Sync>,
+    > {
+        if !self.is_started {
+            return Err(Box::new(SandboxError::NotStarted));
+        }
+
+        if self.is_paused {
+            return Err(Box::new(SandboxError::Paused));
+        }
+
+        let mut params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "language": language,
+            "code": code,
+        });
+        if let Some(timeout) = self.execution_timeout {
+            params["timeout"] = json!(timeout.as_secs().max(1));
+        }
+
+        let response = self.send_request("sandbox.repl.stream", params).await?;
+        let state = ReplStreamState {
+            response,
+            decoder: FrameDecoder::default(),
+            chunks: VecDeque::new(),
+            stdout: Utf8Boundary::default(),
+            stderr: Utf8Boundary::default(),
+            received: 0,
+            done: false,
+        };
+
+        Ok(stream::unfold(Some(state), |state| async move {
+            let mut state = state?;
+            match state.fill().await {
+                Ok(()) => {
+                    let chunk = state.chunks.pop_front()?;
+                    Some((Ok(chunk), Some(state)))
+                }
+                // End the stream after reporting the error
+                Err(e) => Some((Err(e), None)),
+            }
+        }))
+    }
+}
+
+/// Get the length of the longest prefix of `bytes` that doesn't end inside a UTF-8 sequence
+fn complete_utf8_len(bytes: &[u8]) -> usize {
+    let mut start = 0;
+    loop {
+        match std::str::from_utf8(&bytes[start..]) {
+            Ok(_) => return bytes.len(),
+            Err(e) => match e.error_len() {
+                Some(invalid) => start += e.valid_up_to() + invalid,
+                None => return start + e.valid_up_to(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SandboxOptions;
+    use futures::StreamExt;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Serve one streaming request, sending `frames` one chunk at a time
+    async fn serve_frames(listener: TcpListener, frames: Vec<serde_json::Value>) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+
+        let mut line = String::new();
+        while stream.read_line(&mut line).await.unwrap() > 2 {
+            line.clear();
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n")
+            .await
+            .unwrap();
+
+        for frame in frames {
+            let frame = format!("{}\n", frame);
+            let chunk = format!("{:x}\r\n{}\r\n", frame.len(), frame);
+            stream.write_all(chunk.as_bytes()).await.unwrap();
+            stream.flush().await.unwrap();
+        }
+        stream.write_all(b"0\r\n\r\n").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_code_streaming_keeps_split_characters_whole() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .build();
+
+        // "é" is split across two stdout frames, with a stderr frame in between
+        let text = "caf\u{e9}\n".as_bytes();
+        let frames = vec![
+            json!({ "stream": "stdout", "data": BASE64.encode(&text[..4]) }),
+            json!({ "stream": "stderr", "data": BASE64.encode(b"warn\xff") }),
+            json!({ "stream": "stdout", "data": BASE64.encode(&text[4..]) }),
+            json!({ "done": true }),
+        ];
+        tokio::spawn(serve_frames(listener, frames));
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        let chunks: Vec<_> = sandbox
+            .run_code_streaming("python", "print('café')")
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(
+            chunks,
+            [
+                OutputChunk {
+                    stream: StreamKind::Stdout,
+                    data: b"caf".to_vec(),
+                },
+                OutputChunk {
+                    stream: StreamKind::Stderr,
+                    data: b"warn\xff".to_vec(),
+                },
+                OutputChunk {
+                    stream: StreamKind::Stdout,
+                    data: "\u{e9}\n".as_bytes().to_vec(),
+                },
+            ]
+        );
+        assert_eq!(chunks[2].text(), Some("é\n"));
+        assert_eq!(chunks[1].text(), None);
+    }
+}
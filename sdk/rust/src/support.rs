@@ -0,0 +1,239 @@
+//! Support bundles for bug reports
+
+use std::error::Error;
+use std::io::{Cursor, Write};
+
+use serde_json::{json, Value};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::{DescribeOptions, SandboxBase};
+
+/// Placeholder written in place of redacted secrets
+const REDACTED: &str = "[REDACTED]";
+
+impl SandboxBase {
+    /// Collect diagnostics for the sandbox into a zip archive
+    ///
+    /// The bundle contains the client configuration, the sandbox status, server metrics, the
+    /// guest process list and the guest environment. Sections that can't be collected (for
+    /// example because the sandbox is not running) are listed in `errors.txt` instead of
+    /// failing the whole bundle. The API key and secret-looking environment variables are
+    /// redacted.
+    pub async fn support_bundle(&self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let mut files: Vec<(&str, String)> = Vec::new();
+        let mut errors: Vec<String> = Vec::new();
+
+        files.push(("config.json", to_pretty_json(&self.bundle_config())?));
+
+        let description = self.describe(DescribeOptions::default()).await?;
+        files.push((
+            "status.json",
+            to_pretty_json(&json!({
+                "is_started": description.is_started,
+                "is_paused": description.is_paused,
+                "init_ran": description.init_ran,
+                "warnings": description.warnings,
+            }))?,
+        ));
+
+        let params = json!({
+            "namespace": self.namespace,
+            "sandbox": self.name,
+        });
+        match self
+            .make_request::<Value>("sandbox.metrics.get", params)
+            .await
+        {
+            Ok(metrics) => files.push(("metrics.json", to_pretty_json(&metrics)?)),
+            Err(e) => errors.push(format!("metrics: {}", e)),
+        }
+
+        match self.bundle_process_list().await {
+            Ok(processes) => files.push(("processes.txt", processes)),
+            Err(e) => errors.push(format!("processes: {}", e)),
+        }
+
+        match self.get_guest_env(&[]).await {
+            Ok(env) => files.push(("env.json", to_pretty_json(&env)?)),
+            Err(e) => errors.push(format!("env: {}", e)),
+        }
+
+        if !errors.is_empty() {
+            files.push(("errors.txt", errors.join("\n")));
+        }
+
+        // Package everything, scrubbing the API key from every file
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, contents) in files {
+            zip.start_file(name, options)?;
+            zip.write_all(self.redact(contents).as_bytes())?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    /// Client-side configuration of the sandbox, without secrets
+    fn bundle_config(&self) -> Value {
+        json!({
+            "server_url": self.server_url,
+            "namespace": self.namespace,
+            "name": self.name,
+            "auth": self.auth.scheme(),
+            "idempotency_key": self.idempotency_key,
+            "max_sandboxes_per_namespace": self.max_sandboxes_per_namespace,
+            "max_request_body_size": self.max_request_body_size,
+            "ulimits": self.ulimits,
+            "hostname": self.hostname,
+            "oom_score_adj": self.oom_score_adj,
+            "security": self.security,
+            "files": self.files.iter().map(|file| &file.guest_path).collect::<Vec<_>>(),
+            "readiness_probe": self.readiness_probe.as_ref().map(|probe| probe.check.describe()),
+            "execution_timeout_secs": self.execution_timeout.map(|timeout| timeout.as_secs_f64()),
+            "partial_output_on_timeout": self.partial_output_on_timeout,
+            "init_code_language": self.init_code.as_ref().map(|(language, _)| language.as_str()),
+        })
+    }
+
+    /// List the processes running in the guest
+    async fn bundle_process_list(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let params = json!({
+            "sandbox": self.name,
+            "namespace": self.namespace,
+            "command": "ps",
+            "args": ["aux"],
+        });
+
+        let result: Value = self.make_request("sandbox.command.run", params).await?;
+        let lines = result
+            .get("output")
+            .and_then(|o| o.as_array())
+            .map(|lines| {
+                lines
+                    .iter()
+                    .filter_map(|line| line.get("text").and_then(|t| t.as_str()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+
+        Ok(lines)
+    }
+
+    /// Replace any occurrence of the auth secret with a placeholder
+    fn redact(&self, contents: String) -> String {
+        match self.auth.secret() {
+            Some(key) if !key.is_empty() => contents.replace(key, REDACTED),
+            _ => contents,
+        }
+    }
+}
+
+/// Serialize a value as pretty-printed JSON
+fn to_pretty_json<T: serde::Serialize>(value: &T) -> Result<String, Box<dyn Error + Send + Sync>> {
+    Ok(serde_json::to_string_pretty(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SandboxOptions;
+    use std::io::Read;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use zip::ZipArchive;
+
+    /// Answer JSON-RPC requests, one per connection, with the result `respond` gives for
+    /// each method
+    async fn serve_rpc(listener: TcpListener, respond: impl Fn(&str) -> Value) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+
+            let mut content_length = 0;
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 2 {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; content_length];
+            stream.read_exact(&mut body).await.unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let result = respond(request["method"].as_str().unwrap());
+
+            let response = json!({ "jsonrpc": "2.0", "result": result, "id": 1 }).to_string();
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                        response.len(),
+                        response
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_support_bundle_leaves_out_secrets() {
+        let api_key = "msb-key-0123456789";
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SandboxOptions::builder()
+            .server_url(format!("http://{}", listener.local_addr().unwrap()))
+            .api_key(api_key)
+            .name("web")
+            .build();
+
+        // The server echoes the API key back in places a bundle copies verbatim
+        let server = tokio::spawn(serve_rpc(listener, move |method| match method {
+            "sandbox.metrics.get" => json!({ "authorization": format!("Bearer {}", api_key) }),
+            "sandbox.command.run" => json!({
+                "output": [{ "stream": "stdout", "text": format!("app --key={}", api_key) }],
+            }),
+            "sandbox.env" => json!({
+                "env": {
+                    "PATH": "/usr/bin",
+                    "AWS_SECRET_ACCESS_KEY": "aws-secret-value",
+                    "GITHUB_TOKEN": "github-token-value",
+                    "DB_PASSWORD": "db-password-value",
+                },
+            }),
+            _ => Value::Null,
+        }));
+
+        let mut sandbox = SandboxBase::new(&options);
+        sandbox.is_started = true;
+        let bundle = sandbox.support_bundle().await.unwrap();
+        server.abort();
+
+        let mut archive = ZipArchive::new(Cursor::new(bundle)).unwrap();
+        let mut contents = String::new();
+        for index in 0..archive.len() {
+            archive
+                .by_index(index)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+        }
+
+        for secret in [
+            api_key,
+            "aws-secret-value",
+            "github-token-value",
+            "db-password-value",
+        ] {
+            assert!(
+                !contents.contains(secret),
+                "{} leaked into the bundle",
+                secret
+            );
+        }
+        assert!(contents.contains(REDACTED));
+        assert!(contents.contains("/usr/bin"));
+    }
+}
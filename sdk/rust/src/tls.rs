@@ -0,0 +1,145 @@
+//! TLS settings for connections to the Microsandbox server
+
+use std::fmt;
+use std::path::PathBuf;
+
+use reqwest::{Certificate, ClientBuilder, Identity};
+
+use crate::{SandboxError, SandboxOptions};
+
+/// PEM-encoded data for a TLS setting, read from a file or given inline
+#[derive(Clone)]
+pub enum Pem {
+    /// Read from the file at this path when the sandbox handle is created
+    File(PathBuf),
+
+    /// PEM-encoded bytes
+    Bytes(Vec<u8>),
+}
+
+impl Pem {
+    /// Read the PEM data, `what` naming it in errors
+    fn load(&self, what: &str) -> Result<Vec<u8>, SandboxError> {
+        match self {
+            Pem::File(path) => std::fs::read(path).map_err(|e| {
+                SandboxError::InvalidInput(format!(
+                    "failed to read the {} from {}: {}",
+                    what,
+                    path.display(),
+                    e
+                ))
+            }),
+            Pem::Bytes(bytes) => Ok(bytes.clone()),
+        }
+    }
+}
+
+impl fmt::Debug for Pem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Inline data may be a private key, so only its size is shown
+        match self {
+            Pem::File(path) => f.debug_tuple("File").field(path).finish(),
+            Pem::Bytes(bytes) => write!(f, "Bytes(<{} bytes>)", bytes.len()),
+        }
+    }
+}
+
+/// Check that a client certificate and key are either both set or both unset
+pub(crate) fn validate(options: &SandboxOptions) -> Result<(), SandboxError> {
+    match (&options.client_cert, &options.client_key) {
+        (Some(_), None) => Err(SandboxError::InvalidInput(
+            "a client certificate was set without its private key".to_string(),
+        )),
+        (None, Some(_)) => Err(SandboxError::InvalidInput(
+            "a client private key was set without its certificate".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Configure the HTTP client for requests to the server to present the client certificate and
+/// trust the CA bundle from `options`, if they are set
+///
+/// Without a CA bundle, the server's certificate is checked against the system's roots.
+pub(crate) fn configure(
+    mut builder: ClientBuilder,
+    options: &SandboxOptions,
+) -> Result<ClientBuilder, SandboxError> {
+    if let (Some(cert), Some(key)) = (&options.client_cert, &options.client_key) {
+        let (cert, key) = (cert.load("client certificate")?, key.load("client key")?);
+        let identity = Identity::from_pkcs8_pem(&cert, &key).map_err(|e| {
+            SandboxError::InvalidInput(format!("invalid client certificate or private key: {}", e))
+        })?;
+        builder = builder.identity(identity);
+    }
+
+    if let Some(ca_bundle) = &options.ca_bundle {
+        let certs = Certificate::from_pem_bundle(&ca_bundle.load("CA bundle")?)
+            .map_err(|e| SandboxError::InvalidInput(format!("invalid CA bundle: {}", e)))?;
+        if certs.is_empty() {
+            return Err(SandboxError::InvalidInput(
+                "the CA bundle contains no certificates".to_string(),
+            ));
+        }
+
+        builder = builder.tls_built_in_root_certs(false);
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_certificate_needs_its_key() {
+        let options = SandboxOptions::builder()
+            .client_cert(Pem::Bytes(b"cert".to_vec()))
+            .build();
+        assert!(matches!(
+            options.validate(),
+            Err(SandboxError::InvalidInput(msg)) if msg.contains("without its private key")
+        ));
+
+        // Without any TLS settings the default client is built
+        let options = SandboxOptions::builder().build();
+        assert!(options.validate().is_ok());
+        assert!(configure(reqwest::Client::builder(), &options).is_ok());
+    }
+
+    #[test]
+    fn test_configure_reports_unusable_pem() {
+        let missing = std::env::temp_dir().join("microsandbox-missing-ca.pem");
+        let options = SandboxOptions::builder()
+            .ca_bundle(Pem::File(missing.clone()))
+            .build();
+        assert!(matches!(
+            configure(reqwest::Client::builder(), &options),
+            Err(SandboxError::InvalidInput(msg)) if msg.contains(&missing.display().to_string())
+        ));
+
+        let options = SandboxOptions::builder()
+            .ca_bundle(Pem::Bytes(b"not a certificate".to_vec()))
+            .build();
+        assert!(matches!(
+            configure(reqwest::Client::builder(), &options),
+            Err(SandboxError::InvalidInput(msg)) if msg.contains("no certificates")
+        ));
+
+        let options = SandboxOptions::builder()
+            .client_cert(Pem::Bytes(b"not a certificate".to_vec()))
+            .client_key(Pem::Bytes(b"not a key".to_vec()))
+            .build();
+        assert!(matches!(
+            configure(reqwest::Client::builder(), &options),
+            Err(SandboxError::InvalidInput(msg)) if msg.contains("client certificate")
+        ));
+
+        // Inline key material isn't shown when options are logged
+        assert!(!format!("{:?}", options).contains("not a key"));
+    }
+}
@@ -0,0 +1,112 @@
+//! Guest resource limits
+
+use serde::Serialize;
+
+use crate::SandboxError;
+
+/// Resource limit names the sandbox backend knows how to apply in the guest
+const SUPPORTED_ULIMITS: &[&str] = &[
+    "cpu",
+    "fsize",
+    "data",
+    "stack",
+    "core",
+    "rss",
+    "nproc",
+    "nofile",
+    "memlock",
+    "as",
+    "locks",
+    "sigpending",
+    "msgqueue",
+    "nice",
+    "rtprio",
+    "rttime",
+];
+
+/// A resource limit applied to processes inside the sandbox
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Ulimit {
+    /// Limit name, e.g. `nofile` or `stack`
+    pub name: String,
+
+    /// Soft limit enforced by the guest kernel
+    pub soft: u64,
+
+    /// Hard limit, the ceiling for the soft limit
+    pub hard: u64,
+}
+
+impl Ulimit {
+    /// Create a new ulimit
+    pub fn new(name: impl Into<String>, soft: u64, hard: u64) -> Self {
+        Self {
+            name: name.into(),
+            soft,
+            hard,
+        }
+    }
+
+    /// Check that the limit is one the backend can set and that its values are consistent
+    pub(crate) fn validate(&self) -> Result<(), SandboxError> {
+        let name = self.name.to_lowercase();
+        let name = name.strip_prefix("rlimit_").unwrap_or(&name);
+        if !SUPPORTED_ULIMITS.contains(&name) {
+            return Err(SandboxError::Unsupported(format!(
+                "ulimit '{}' cannot be set in the sandbox",
+                self.name
+            )));
+        }
+
+        if self.soft > self.hard {
+            return Err(SandboxError::General(format!(
+                "soft limit for ulimit '{}' exceeds its hard limit",
+                self.name
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_ulimit() {
+        let cases = [
+            // Names the backend can set, in any case and with or without the RLIMIT_ prefix
+            (Ulimit::new("nofile", 1024, 4096), None),
+            (Ulimit::new("NOFILE", 1024, 4096), None),
+            (Ulimit::new("RLIMIT_STACK", 8192, 8192), None),
+            (Ulimit::new("rlimit_core", 0, 0), None),
+            // Names it can't
+            (Ulimit::new("files", 1024, 4096), Some("cannot be set")),
+            (Ulimit::new("", 1024, 4096), Some("cannot be set")),
+            (Ulimit::new("rlimit_", 1024, 4096), Some("cannot be set")),
+            // Soft limits up to the hard limit
+            (Ulimit::new("nproc", 0, u64::MAX), None),
+            (Ulimit::new("nproc", u64::MAX, u64::MAX), None),
+            (
+                Ulimit::new("nproc", 4097, 4096),
+                Some("exceeds its hard limit"),
+            ),
+            (
+                Ulimit::new("nproc", u64::MAX, 0),
+                Some("exceeds its hard limit"),
+            ),
+        ];
+
+        for (ulimit, expected) in cases {
+            match (ulimit.validate(), expected) {
+                (Ok(()), None) => {}
+                (Err(SandboxError::Unsupported(message)), Some(reason))
+                | (Err(SandboxError::General(message)), Some(reason)) => {
+                    assert!(message.contains(reason), "{:?}: {}", ulimit, message)
+                }
+                (result, _) => panic!("{:?}: unexpected {:?}", ulimit, result),
+            }
+        }
+    }
+}
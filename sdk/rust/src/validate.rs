@@ -0,0 +1,84 @@
+//! Dry runs of a sandbox start
+
+use std::error::Error;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{SandboxBase, StartOptions};
+
+/// Check of the sandbox and namespace names
+pub const CHECK_NAME: &str = "name";
+
+/// Check that the sandbox configuration parses and is complete
+pub const CHECK_CONFIG: &str = "config";
+
+/// Check that the sandbox image can be found
+pub const CHECK_IMAGE: &str = "image";
+
+/// Check that the sandbox's memory and CPUs fit the server's host
+pub const CHECK_RESOURCES: &str = "resources";
+
+/// Outcome of a sandbox configuration dry run, returned by [`SandboxBase::validate_config`]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ConfigValidation {
+    /// Whether the sandbox would pass every check at start
+    pub valid: bool,
+
+    /// Outcome of each check, in the order they ran
+    pub diagnostics: Vec<ConfigDiagnostic>,
+}
+
+/// Outcome of one sandbox configuration check
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ConfigDiagnostic {
+    /// Check that ran, one of the `CHECK_*` constants
+    pub check: String,
+
+    /// Whether the check passed
+    pub ok: bool,
+
+    /// Human-readable description of the outcome
+    pub message: String,
+}
+
+impl ConfigValidation {
+    /// The checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &ConfigDiagnostic> {
+        self.diagnostics.iter().filter(|d| !d.ok)
+    }
+}
+
+impl SandboxBase {
+    /// Check whether the sandbox would start with `options`, without starting it
+    ///
+    /// The server runs the checks of a start — the names, the configuration, whether the image
+    /// can be found and whether the memory and CPUs fit its host — and reports each outcome
+    /// instead of stopping at the first failure. Nothing is written on the server, so this
+    /// works whether or not the sandbox exists or runs. A configuration that fails a check
+    /// is reported in the result, not as an error.
+    pub async fn validate_config(
+        &self,
+        options: &StartOptions,
+    ) -> Result<ConfigValidation, Box<dyn Error + Send + Sync>> {
+        let (cpus, cpus_warning) = self.cpus_for_server(options.cpus).await?;
+        let params = json!({
+            "namespace": self.namespace,
+            "sandbox": self.name,
+            "config": self.start_config(options.image.clone(), options.memory, cpus),
+        });
+        let mut validation: ConfigValidation =
+            self.make_request("sandbox.validate", params).await?;
+
+        // A start would round the CPUs the same way, which doesn't stop it
+        if let Some(warning) = cpus_warning {
+            validation.diagnostics.push(ConfigDiagnostic {
+                check: CHECK_RESOURCES.to_string(),
+                ok: true,
+                message: warning.message,
+            });
+        }
+
+        Ok(validation)
+    }
+}